@@ -0,0 +1,87 @@
+"""Build a signed dataset pack ("fleet starter pack") for install_dataset_pack.
+
+Run at headquarters only; the private key never ships with the app, which
+verifies packs against the public key embedded in dataset_pack.rs.
+
+    python build_dataset_pack.py <source_dir> <destination.zip> <name> <version>
+        --key signing-key.pem [--description TEXT]
+
+<source_dir> holds any of officers.json, ranks.json, units.json and
+positions.json, plus the officer photos officers.json references.
+Requires the `cryptography` package.
+"""
+import argparse
+import hashlib
+import json
+import os
+import sys
+import zipfile
+from datetime import datetime, timezone
+
+from cryptography.hazmat.primitives import serialization
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+
+DATA_FILES = ["officers.json", "ranks.json", "units.json", "positions.json"]
+
+
+def valid_entry_name(name):
+    parts = name.split('/')
+    return bool(name) and '\\' not in name and not name.startswith('/') \
+        and all(part not in ('', '.', '..') for part in parts)
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Build a signed dataset pack")
+    parser.add_argument("source_dir")
+    parser.add_argument("destination")
+    parser.add_argument("name")
+    parser.add_argument("version")
+    parser.add_argument("--key", required=True, help="Ed25519 private key (PEM)")
+    parser.add_argument("--description")
+    args = parser.parse_args()
+
+    with open(args.key, 'rb') as f:
+        key = serialization.load_pem_private_key(f.read(), password=None)
+    if not isinstance(key, Ed25519PrivateKey):
+        sys.exit("The signing key must be an Ed25519 key")
+
+    entries = [name for name in DATA_FILES if os.path.isfile(os.path.join(args.source_dir, name))]
+    if not entries:
+        sys.exit("No pack data files found; expected any of " + ", ".join(DATA_FILES))
+    if "officers.json" in entries:
+        with open(os.path.join(args.source_dir, "officers.json"), encoding='utf-8') as f:
+            for officer in json.load(f):
+                photo = officer.get("photo")
+                if photo and photo not in entries:
+                    entries.append(photo)
+
+    contents = {}
+    for name in entries:
+        if not valid_entry_name(name):
+            sys.exit("Invalid file name in pack: " + name)
+        with open(os.path.join(args.source_dir, name), 'rb') as f:
+            contents[name] = f.read()
+
+    manifest = {
+        "name": args.name,
+        "version": args.version,
+        "description": args.description,
+        "created_at": datetime.now(timezone.utc).isoformat(),
+        "files": {name: hashlib.sha256(data).hexdigest() for name, data in sorted(contents.items())},
+    }
+    # The signature covers these exact bytes
+    manifest_json = json.dumps(manifest, ensure_ascii=False, indent=2).encode('utf-8')
+    signature = key.sign(manifest_json).hex()
+
+    with zipfile.ZipFile(args.destination, 'w', zipfile.ZIP_DEFLATED) as pack:
+        pack.writestr("pack.json", manifest_json)
+        pack.writestr("pack.sig", signature)
+        for name, data in contents.items():
+            pack.writestr(name, data)
+
+    public_key = key.public_key().public_bytes(serialization.Encoding.Raw, serialization.PublicFormat.Raw)
+    print(f"Wrote {args.destination} ({len(contents)} files), signed by key {public_key.hex()}")
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,130 @@
+//! Activity log stored in the main database
+//!
+//! Keeps a compact trail of security-relevant events (logins, admin actions,
+//! maintenance runs) so it travels with hybrid backups.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::database::get_connection_safe;
+use crate::logger;
+
+pub const EVENT_LOGIN: &str = "login";
+pub const EVENT_LEGACY_MIGRATION: &str = "legacy_migration";
+pub const EVENT_USERS_ARCHIVED: &str = "users_archived";
+pub const EVENT_MAINTENANCE: &str = "maintenance";
+pub const EVENT_ADMIN_PASSWORD_ROTATED: &str = "admin_password_rotated";
+pub const EVENT_ADMIN_PASSWORD_ROTATION_FAILED: &str = "admin_password_rotation_failed";
+/// Outcome of a backup or restore command, see `backup_notify`
+pub const EVENT_BACKUP_RESULT: &str = "backup_result";
+pub const EVENT_BACKUP_SUMMARY_SENT: &str = "backup_summary_sent";
+pub const EVENT_PASSWORDS_RESET: &str = "passwords_reset";
+/// A user replaced their own password, see `password_reset::change_own_password`
+pub const EVENT_PASSWORD_CHANGED: &str = "password_changed";
+/// Sensitive command and its outcome, see `admin_audit`
+pub const EVENT_ADMIN_ACTION: &str = "admin_action";
+/// Recorded by `avatar_audit` when auditing of photo reads is enabled
+pub const EVENT_AVATAR_ACCESS: &str = "avatar_access";
+/// Media crossed a warning level of its size budget, see `media_budget`
+pub const EVENT_MEDIA_BUDGET: &str = "media_budget";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActivityEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+pub fn init_activity_log_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS activity_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_type TEXT NOT NULL,
+            user_id INTEGER,
+            username TEXT,
+            details TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create activity_log table: {}", e))?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_activity_log_type_time ON activity_log(event_type, created_at)",
+        [],
+    )
+    .map_err(|e| format!("Failed to create activity_log index: {}", e))?;
+
+    Ok(())
+}
+
+pub fn record_event_with_conn(
+    conn: &Connection,
+    event_type: &str,
+    user_id: Option<i32>,
+    username: Option<&str>,
+    details: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO activity_log (event_type, user_id, username, details) VALUES (?, ?, ?, ?)",
+        params![event_type, user_id, username, details],
+    )
+    .map_err(|e| format!("Failed to record activity event: {}", e))?;
+    Ok(())
+}
+
+/// Record an event on a fresh connection
+/// Logging is best-effort: failures are reported but never fail the caller
+pub fn record_event(
+    event_type: &str,
+    user_id: Option<i32>,
+    username: Option<&str>,
+    details: Option<&str>,
+) {
+    let result = get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))
+        .and_then(|conn| record_event_with_conn(&conn, event_type, user_id, username, details));
+
+    if let Err(e) = result {
+        logger::warn(format!(
+            "Failed to record activity event '{}': {}",
+            event_type, e
+        ));
+    }
+}
+
+/// Most recent events first, optionally filtered by event type
+pub fn get_recent_events_with_conn(
+    conn: &Connection,
+    event_type: Option<&str>,
+    limit: u32,
+) -> Result<Vec<ActivityEvent>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, event_type, user_id, username, details, created_at FROM activity_log
+             WHERE (?1 IS NULL OR event_type = ?1)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ?2",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let events = stmt
+        .query_map(params![event_type, limit], |row| {
+            Ok(ActivityEvent {
+                id: row.get(0)?,
+                event_type: row.get(1)?,
+                user_id: row.get(2)?,
+                username: row.get(3)?,
+                details: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query activity log: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read activity event: {}", e))?;
+
+    Ok(events)
+}
@@ -0,0 +1,263 @@
+//! Audit trail of administrative commands
+//!
+//! Restores, imports, user deletion, role changes and password resets are
+//! written to the activity log as `admin_action` events holding the command,
+//! a short summary of its parameters (ids and file names, never passwords or
+//! file contents), the session the call was made with and whether it
+//! succeeded. Each command resolves that session from its own
+//! `sessionToken` (see `acting_session`), so concurrent windows and logins
+//! never credit an action to someone else.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::activity_log::{self, EVENT_ADMIN_ACTION};
+use crate::database::get_connection_safe;
+use crate::logger;
+use crate::sessions;
+
+const DEFAULT_QUERY_LIMIT: u32 = 200;
+/// Longer error messages are cut; the full text is in the app log
+const MAX_ERROR_LENGTH: usize = 500;
+
+pub const OUTCOME_SUCCESS: &str = "success";
+pub const OUTCOME_FAILURE: &str = "failure";
+
+/// Who an administrative call was made by
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ActingSession {
+    /// `sessions::session_id` of the call's token
+    pub session_id: String,
+    pub user_id: Option<i32>,
+    pub username: String,
+}
+
+/// Stored as the details of an `admin_action` event
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AdminActionDetails {
+    pub command: String,
+    pub params: serde_json::Value,
+    pub session_id: Option<String>,
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminAction {
+    pub id: i64,
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub command: String,
+    pub params: serde_json::Value,
+    pub session_id: Option<String>,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AdminActionFilter {
+    pub command: Option<String>,
+    pub user_id: Option<i32>,
+    /// "success" or "failure"
+    pub outcome: Option<String>,
+    /// Only actions at or after this time (SQLite "YYYY-MM-DD HH:MM:SS")
+    pub since: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Owner of `session_token`; None without a token or once its session
+/// expired or ended
+pub fn acting_session(session_token: Option<&str>) -> Result<Option<ActingSession>, String> {
+    let Some(token) = session_token.filter(|token| !token.is_empty()) else {
+        return Ok(None);
+    };
+    Ok(
+        sessions::validate_session(token)?.map(|user| ActingSession {
+            session_id: sessions::session_id(token),
+            user_id: user.id,
+            username: user.username,
+        }),
+    )
+}
+
+pub fn record_action_with_conn(
+    conn: &Connection,
+    session: Option<&ActingSession>,
+    command: &str,
+    params: serde_json::Value,
+    error: Option<&str>,
+) -> Result<(), String> {
+    let details = AdminActionDetails {
+        command: command.to_string(),
+        params,
+        session_id: session.map(|s| s.session_id.clone()),
+        outcome: if error.is_some() {
+            OUTCOME_FAILURE
+        } else {
+            OUTCOME_SUCCESS
+        }
+        .to_string(),
+        error: error.map(|e| e.chars().take(MAX_ERROR_LENGTH).collect()),
+    };
+    let details = serde_json::to_string(&details)
+        .map_err(|e| format!("Failed to serialize admin action: {}", e))?;
+
+    activity_log::record_event_with_conn(
+        conn,
+        EVENT_ADMIN_ACTION,
+        session.and_then(|s| s.user_id),
+        session.map(|s| s.username.as_str()),
+        Some(&details),
+    )
+}
+
+/// Record `command`, run by `session`, with its outcome and hand the result
+/// back unchanged; auditing failures are logged, never returned
+pub fn audited<T>(
+    command: &str,
+    session: Option<&ActingSession>,
+    params: serde_json::Value,
+    result: Result<T, String>,
+) -> Result<T, String> {
+    let error = result.as_ref().err().map(String::as_str);
+    let recorded = get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))
+        .and_then(|conn| record_action_with_conn(&conn, session, command, params, error));
+    if let Err(e) = recorded {
+        logger::warn(format!("Failed to audit {}: {}", command, e));
+    }
+    result
+}
+
+/// Most recent actions first
+pub fn query_admin_actions_with_conn(
+    conn: &Connection,
+    filter: &AdminActionFilter,
+) -> Result<Vec<AdminAction>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, user_id, username, details, created_at FROM activity_log
+             WHERE event_type = ?1
+               AND (?2 IS NULL OR json_extract(details, '$.command') = ?2)
+               AND (?3 IS NULL OR user_id = ?3)
+               AND (?4 IS NULL OR json_extract(details, '$.outcome') = ?4)
+               AND (?5 IS NULL OR created_at >= ?5)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ?6",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let rows = stmt
+        .query_map(
+            params![
+                EVENT_ADMIN_ACTION,
+                filter.command,
+                filter.user_id,
+                filter.outcome,
+                filter.since,
+                filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT)
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i32>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            },
+        )
+        .map_err(|e| format!("Failed to query admin action log: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read admin action: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, user_id, username, details, created_at)| {
+            let details: AdminActionDetails = serde_json::from_str(&details?).ok()?;
+            Some(AdminAction {
+                id,
+                user_id,
+                username,
+                command: details.command,
+                params: details.params,
+                session_id: details.session_id,
+                outcome: details.outcome,
+                error: details.error,
+                created_at,
+            })
+        })
+        .collect())
+}
+
+pub fn query_admin_actions(filter: &AdminActionFilter) -> Result<Vec<AdminAction>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    query_admin_actions_with_conn(&conn, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_actions_record_session_and_outcome() {
+        let conn = create_app_db();
+        let session = ActingSession {
+            session_id: "session-1".to_string(),
+            user_id: Some(1),
+            username: "admin".to_string(),
+        };
+
+        record_action_with_conn(
+            &conn,
+            Some(&session),
+            "delete_user",
+            json!({ "id": 5 }),
+            None,
+        )
+        .expect("record should succeed");
+        record_action_with_conn(
+            &conn,
+            None,
+            "import_hybrid_backup",
+            json!({ "zip_path": "backup.zip" }),
+            Some("Backup file does not exist"),
+        )
+        .expect("record should succeed");
+
+        let all = query_admin_actions_with_conn(&conn, &AdminActionFilter::default())
+            .expect("query should succeed");
+        assert_eq!(all.len(), 2);
+
+        let failures = query_admin_actions_with_conn(
+            &conn,
+            &AdminActionFilter {
+                outcome: Some(OUTCOME_FAILURE.to_string()),
+                ..AdminActionFilter::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].command, "import_hybrid_backup");
+        assert_eq!(failures[0].session_id, None);
+
+        let by_admin = query_admin_actions_with_conn(
+            &conn,
+            &AdminActionFilter {
+                user_id: Some(1),
+                command: Some("delete_user".to_string()),
+                ..AdminActionFilter::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(by_admin.len(), 1);
+        assert_eq!(by_admin[0].username.as_deref(), Some("admin"));
+        assert_eq!(by_admin[0].session_id.as_deref(), Some("session-1"));
+        assert_eq!(by_admin[0].params, json!({ "id": 5 }));
+    }
+}
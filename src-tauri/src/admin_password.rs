@@ -0,0 +1,195 @@
+//! Rotation of the seeded admin account's password
+//!
+//! Every install starts with the same admin password, so the app warns at
+//! startup (via `security://default-admin-password`) until it is changed, and
+//! `rotate_admin_password` enforces a password policy and writes each attempt
+//! to the activity log.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::thread;
+use tauri::{AppHandle, Manager};
+
+use crate::activity_log;
+use crate::auth;
+use crate::database::{self, DEFAULT_ADMIN_PASSWORD, DEFAULT_ADMIN_USERNAME};
+use crate::logger;
+
+pub const DEFAULT_ADMIN_PASSWORD_EVENT: &str = "security://default-admin-password";
+
+const MIN_PASSWORD_LENGTH: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DefaultAdminPasswordPayload {
+    pub username: String,
+    pub message: String,
+}
+
+/// All policy violations at once, so the UI can list them together
+pub fn check_password_policy(password: &str, username: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        problems.push(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    if !password.chars().any(|c| c.is_uppercase()) {
+        problems.push("Password must contain an uppercase letter".to_string());
+    }
+    if !password.chars().any(|c| c.is_lowercase()) {
+        problems.push("Password must contain a lowercase letter".to_string());
+    }
+    if !password.chars().any(|c| c.is_ascii_digit()) {
+        problems.push("Password must contain a digit".to_string());
+    }
+    if password.chars().all(|c| c.is_alphanumeric()) {
+        problems.push("Password must contain a symbol".to_string());
+    }
+    if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+        problems.push("Password must not contain the username".to_string());
+    }
+    if password == DEFAULT_ADMIN_PASSWORD {
+        problems.push("Password must differ from the default password".to_string());
+    }
+
+    problems
+}
+
+fn admin_account_with_conn(conn: &Connection) -> Result<(i32, String), String> {
+    conn.query_row(
+        "SELECT id, password_hash FROM users WHERE username = ? AND role = 'admin'",
+        params![DEFAULT_ADMIN_USERNAME],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| format!("Failed to find admin account: {}", e))
+}
+
+pub fn is_default_admin_password_in_use_with_conn(conn: &Connection) -> Result<bool, String> {
+    let (_, password_hash) = admin_account_with_conn(conn)?;
+    auth::verify_password(DEFAULT_ADMIN_PASSWORD, &password_hash)
+}
+
+pub fn rotate_admin_password_with_conn(
+    conn: &Connection,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), String> {
+    let (admin_id, password_hash) = admin_account_with_conn(conn)?;
+
+    let current_ok = auth::verify_password(current_password, &password_hash)?;
+    if !current_ok {
+        activity_log::record_event_with_conn(
+            conn,
+            activity_log::EVENT_ADMIN_PASSWORD_ROTATION_FAILED,
+            Some(admin_id),
+            Some(DEFAULT_ADMIN_USERNAME),
+            Some("Current password did not match"),
+        )?;
+        return Err("Current password is incorrect".to_string());
+    }
+
+    if new_password == current_password {
+        return Err("New password must differ from the current password".to_string());
+    }
+    let problems = check_password_policy(new_password, DEFAULT_ADMIN_USERNAME);
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+
+    let new_hash = auth::hash_password(new_password)?;
+    conn.execute(
+        "UPDATE users SET row_version = row_version + 1, must_change_password = 0, password_hash = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        params![new_hash, admin_id],
+    )
+    .map_err(|e| format!("Failed to update admin password: {}", e))?;
+
+    activity_log::record_event_with_conn(
+        conn,
+        activity_log::EVENT_ADMIN_PASSWORD_ROTATED,
+        Some(admin_id),
+        Some(DEFAULT_ADMIN_USERNAME),
+        None,
+    )?;
+    logger::info("Admin password rotated");
+    Ok(())
+}
+
+pub fn rotate_admin_password(current_password: &str, new_password: &str) -> Result<(), String> {
+    let conn = database::get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))?;
+    rotate_admin_password_with_conn(&conn, current_password, new_password)
+}
+
+/// Check in the background (hashing is slow) and emit a warning event if the
+/// seeded admin password is still in use
+pub fn warn_if_default_admin_password(app: AppHandle) {
+    thread::spawn(move || {
+        if !database::check_database_exists_and_valid().unwrap_or(false) {
+            return;
+        }
+
+        let in_use = database::get_connection_safe()
+            .map_err(|e| format!("Failed to connect to database: {}", e))
+            .and_then(|conn| is_default_admin_password_in_use_with_conn(&conn));
+
+        match in_use {
+            Ok(true) => {
+                logger::warn("The default admin password is still in use");
+                let payload = DefaultAdminPasswordPayload {
+                    username: DEFAULT_ADMIN_USERNAME.to_string(),
+                    message: "The default admin password is still in use. Change it now."
+                        .to_string(),
+                };
+                if let Err(e) = app.emit_all(DEFAULT_ADMIN_PASSWORD_EVENT, payload) {
+                    logger::warn(format!("Failed to emit admin password warning: {}", e));
+                }
+            }
+            Ok(false) => {}
+            Err(e) => logger::warn(format!("Failed to check admin password: {}", e)),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password_hashing;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn seed_admin(conn: &Connection, password: &str) {
+        let hash = password_hashing::hash_password(password).unwrap();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, role) VALUES (?, 'admin@test.com', ?, 'Admin', 'admin')",
+            params![DEFAULT_ADMIN_USERNAME, hash],
+        )
+        .expect("admin insert should succeed");
+    }
+
+    #[test]
+    fn test_password_policy() {
+        assert!(check_password_policy("Str0ng!Passw0rd", "admin").is_empty());
+        assert_eq!(check_password_policy("short", "admin").len(), 4);
+        assert!(!check_password_policy("Admin!Passw0rd", "admin").is_empty());
+        assert!(!check_password_policy(DEFAULT_ADMIN_PASSWORD, "x").is_empty());
+    }
+
+    #[test]
+    fn test_rotate_admin_password() {
+        let conn = create_app_db();
+        seed_admin(&conn, DEFAULT_ADMIN_PASSWORD);
+        assert!(is_default_admin_password_in_use_with_conn(&conn).unwrap());
+
+        assert!(rotate_admin_password_with_conn(&conn, "wrong", "Str0ng!Passw0rd").is_err());
+        assert!(rotate_admin_password_with_conn(&conn, DEFAULT_ADMIN_PASSWORD, "weak").is_err());
+        rotate_admin_password_with_conn(&conn, DEFAULT_ADMIN_PASSWORD, "Str0ng!Passw0rd")
+            .expect("rotation should succeed");
+
+        assert!(!is_default_admin_password_in_use_with_conn(&conn).unwrap());
+        let events = activity_log::get_recent_events_with_conn(&conn, None, 10).unwrap();
+        let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+        assert!(types.contains(&activity_log::EVENT_ADMIN_PASSWORD_ROTATED));
+        assert!(types.contains(&activity_log::EVENT_ADMIN_PASSWORD_ROTATION_FAILED));
+    }
+}
@@ -0,0 +1,426 @@
+//! Password hash schemes
+//!
+//! New hashes use the scheme chosen in settings, Argon2id unless an
+//! administrator switched back to bcrypt, at the configured cost (bcrypt cost
+//! from `password_hashing`, Argon2id memory/iterations/lanes from settings).
+//! Verification looks at the stored hash instead, so `$2b$` hashes from
+//! earlier releases and hashes made at an older cost keep working; after a
+//! successful login a hash in another scheme or weaker than the configured
+//! target is replaced (see `database::authenticate_user`). That moves
+//! accounts over one login at a time without a forced password reset.
+//!
+//! A hash cannot be strengthened without the password it was made from, so
+//! `rehash_all_passwords` sets a new target and then checks every account,
+//! reporting which ones will be upgraded at their next login.
+//! `calibrate_hash_target` times the configured scheme on this machine: the
+//! bcrypt cost for bcrypt, the number of passes at the configured memory for
+//! Argon2id.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::database::get_connection_safe;
+use crate::password_hashing;
+use crate::progress::{ProgressReporter, ROW_REPORT_INTERVAL};
+use crate::settings::{self, Argon2Settings, HashScheme};
+
+const ARGON2ID_PREFIX: &str = "$argon2id$";
+const SALT_BYTES: usize = 16;
+/// Above this an Argon2id login needs more memory than an office PC should spare
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+/// OWASP's minimum at the default 19 MiB
+const MIN_ARGON2_ITERATIONS: u32 = 2;
+/// Passes cost time linearly; past this a login takes seconds even on fast PCs
+const MAX_ARGON2_ITERATIONS: u32 = 16;
+
+/// Scheme and cost new hashes are made with
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct HashTarget {
+    pub scheme: HashScheme,
+    pub bcrypt_cost: u32,
+    pub argon2: Argon2Settings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HashCalibration {
+    /// The configured target with the calibrated cost filled in
+    pub target: HashTarget,
+    /// Time one hash took at `target` on this machine
+    pub measured_ms: u64,
+    pub target_ms: u64,
+    /// Whether the target was saved to settings
+    pub applied: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RehashReport {
+    pub total_users: usize,
+    /// Hashes already at the target
+    pub up_to_date: usize,
+    /// Hashes replaced at the user's next successful login
+    pub rehash_on_login: usize,
+    /// Hashes in no known format; these users cannot sign in
+    pub unrecognized: usize,
+}
+
+/// Scheme `hash` was made with; None for anything unrecognised
+pub fn scheme_of_hash(hash: &str) -> Option<HashScheme> {
+    if hash.starts_with(ARGON2ID_PREFIX) {
+        Some(HashScheme::Argon2id)
+    } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+    {
+        Some(HashScheme::Bcrypt)
+    } else {
+        None
+    }
+}
+
+pub fn configured_scheme() -> HashScheme {
+    settings::load_settings()
+        .map(|s| s.password_hash_scheme)
+        .unwrap_or_default()
+}
+
+pub fn set_scheme(scheme: HashScheme) -> Result<(), String> {
+    settings::update_settings(|s| s.password_hash_scheme = scheme)?;
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn configured_argon2() -> Argon2Settings {
+    settings::load_settings()
+        .ok()
+        .and_then(|s| s.argon2_params)
+        .unwrap_or_default()
+}
+
+// Full memory cost makes the test suite crawl
+#[cfg(test)]
+fn configured_argon2() -> Argon2Settings {
+    Argon2Settings {
+        memory_kib: Params::MIN_M_COST,
+        iterations: 1,
+        parallelism: 1,
+    }
+}
+
+pub fn configured_target() -> HashTarget {
+    HashTarget {
+        scheme: configured_scheme(),
+        bcrypt_cost: password_hashing::configured_cost(),
+        argon2: configured_argon2(),
+    }
+}
+
+fn argon2_params(settings: &Argon2Settings) -> Result<Params, String> {
+    Params::new(
+        settings.memory_kib,
+        settings.iterations,
+        settings.parallelism,
+        None,
+    )
+    .map_err(|e| format!("Invalid Argon2 parameters: {}", e))
+}
+
+pub fn validate_target(target: &HashTarget) -> Result<(), String> {
+    password_hashing::validate_cost(target.bcrypt_cost)?;
+    argon2_params(&target.argon2)?;
+    if target.argon2.memory_kib > MAX_ARGON2_MEMORY_KIB {
+        return Err(format!(
+            "Argon2 memory must be at most {} KiB",
+            MAX_ARGON2_MEMORY_KIB
+        ));
+    }
+    if target.argon2.iterations > MAX_ARGON2_ITERATIONS {
+        return Err(format!(
+            "Argon2 iterations must be at most {}",
+            MAX_ARGON2_ITERATIONS
+        ));
+    }
+    Ok(())
+}
+
+fn hash_argon2id(password: &str, settings: &Argon2Settings) -> Result<String, String> {
+    let mut salt = [0u8; SALT_BYTES];
+    getrandom::getrandom(&mut salt).map_err(|e| format!("Failed to read random bytes: {}", e))?;
+    let salt =
+        SaltString::encode_b64(&salt).map_err(|e| format!("Failed to encode salt: {}", e))?;
+
+    Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        argon2_params(settings)?,
+    )
+    .hash_password(password.as_bytes(), &salt)
+    .map(|hash| hash.to_string())
+    .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+pub fn hash_password_with(target: &HashTarget, password: &str) -> Result<String, String> {
+    match target.scheme {
+        HashScheme::Bcrypt => bcrypt::hash(password, target.bcrypt_cost)
+            .map_err(|e| format!("Failed to hash password: {}", e)),
+        HashScheme::Argon2id => hash_argon2id(password, &target.argon2),
+    }
+}
+
+/// Most passes whose estimated time stays within `target`, given one pass
+/// took `one_pass`; kept between `MIN_ARGON2_ITERATIONS` and
+/// `MAX_ARGON2_ITERATIONS`
+pub fn pick_argon2_iterations(one_pass: Duration, target: Duration) -> u32 {
+    let passes = target.as_nanos() / one_pass.as_nanos().max(1);
+    (passes.min(MAX_ARGON2_ITERATIONS as u128) as u32).max(MIN_ARGON2_ITERATIONS)
+}
+
+fn time_argon2(settings: &Argon2Settings) -> Result<Duration, String> {
+    let started = Instant::now();
+    hash_argon2id("calibration-password", settings)?;
+    Ok(started.elapsed())
+}
+
+/// Measure this machine with the configured scheme and pick its cost for
+/// `target_ms`; saved when `apply` is set
+pub fn calibrate_hash_target(target_ms: u64, apply: bool) -> Result<HashCalibration, String> {
+    if target_ms == 0 {
+        return Err("Calibration target must be above 0 ms".to_string());
+    }
+    let mut target = configured_target();
+    let measured_ms = match target.scheme {
+        HashScheme::Bcrypt => {
+            let calibration = password_hashing::calibrate_cost(target_ms, apply)?;
+            target.bcrypt_cost = calibration.cost;
+            calibration.measured_ms
+        }
+        HashScheme::Argon2id => {
+            // Memory stays as configured; passes are what scales with time
+            let one_pass = time_argon2(&Argon2Settings {
+                iterations: 1,
+                ..target.argon2
+            })?;
+            target.argon2.iterations =
+                pick_argon2_iterations(one_pass, Duration::from_millis(target_ms));
+            let measured = time_argon2(&target.argon2)?;
+            if apply {
+                settings::update_settings(|s| s.argon2_params = Some(target.argon2))?;
+            }
+            measured.as_millis() as u64
+        }
+    };
+
+    Ok(HashCalibration {
+        target,
+        measured_ms,
+        target_ms,
+        applied: apply,
+    })
+}
+
+/// Hash for storing a new password, at the configured target
+pub fn hash_password(password: &str) -> Result<String, String> {
+    hash_password_with(&configured_target(), password)
+}
+
+/// Check `password` against a stored hash of either scheme
+pub fn verify_password(password: &str, hash: &str) -> Result<bool, String> {
+    match scheme_of_hash(hash) {
+        Some(HashScheme::Argon2id) => {
+            let parsed = PasswordHash::new(hash)
+                .map_err(|e| format!("Password verification failed: {}", e))?;
+            // Parameters come from the hash itself
+            match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+                Ok(()) => Ok(true),
+                Err(argon2::password_hash::Error::Password) => Ok(false),
+                Err(e) => Err(format!("Password verification failed: {}", e)),
+            }
+        }
+        Some(HashScheme::Bcrypt) => bcrypt::verify(password, hash)
+            .map_err(|e| format!("Password verification failed: {}", e)),
+        None => Err("Password verification failed: unknown hash format".to_string()),
+    }
+}
+
+/// Cost of a `$2b$12$...` hash
+fn bcrypt_cost_of(hash: &str) -> Option<u32> {
+    hash.split('$').nth(2)?.parse().ok()
+}
+
+fn argon2_settings_of(hash: &str) -> Option<Argon2Settings> {
+    let parsed = PasswordHash::new(hash).ok()?;
+    let params = Params::try_from(&parsed).ok()?;
+    Some(Argon2Settings {
+        memory_kib: params.m_cost(),
+        iterations: params.t_cost(),
+        parallelism: params.p_cost(),
+    })
+}
+
+/// Whether a verified hash should be replaced: another scheme than the
+/// target's, or weaker in any parameter. Stronger hashes are kept.
+pub fn needs_rehash(hash: &str, target: &HashTarget) -> bool {
+    if scheme_of_hash(hash) != Some(target.scheme) {
+        return true;
+    }
+    match target.scheme {
+        HashScheme::Bcrypt => bcrypt_cost_of(hash).is_none_or(|cost| cost < target.bcrypt_cost),
+        HashScheme::Argon2id => argon2_settings_of(hash).is_none_or(|params| {
+            params.memory_kib < target.argon2.memory_kib
+                || params.iterations < target.argon2.iterations
+                || params.parallelism < target.argon2.parallelism
+        }),
+    }
+}
+
+/// Classify every stored hash against `target`
+pub fn check_password_hashes_with_conn(
+    conn: &Connection,
+    target: &HashTarget,
+    progress: &ProgressReporter,
+) -> Result<RehashReport, String> {
+    let mut stmt = conn
+        .prepare("SELECT password_hash FROM users")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let hashes = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to query password hashes: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read password hash: {}", e))?;
+
+    let total = hashes.len() as u64;
+    let mut report = RehashReport {
+        total_users: hashes.len(),
+        ..RehashReport::default()
+    };
+    for (index, hash) in hashes.iter().enumerate() {
+        let processed = index as u64 + 1;
+        if processed.is_multiple_of(ROW_REPORT_INTERVAL) {
+            progress.check_cancelled()?;
+            progress.report(Some("users"), processed, Some(total));
+        }
+
+        if scheme_of_hash(hash).is_none() {
+            report.unrecognized += 1;
+        } else if needs_rehash(hash, target) {
+            report.rehash_on_login += 1;
+        } else {
+            report.up_to_date += 1;
+        }
+    }
+
+    progress.finish(total);
+    Ok(report)
+}
+
+/// Make `target` the configured one and report how many accounts it upgrades
+pub fn rehash_all_passwords(
+    target: HashTarget,
+    progress: &ProgressReporter,
+) -> Result<RehashReport, String> {
+    validate_target(&target)?;
+    settings::update_settings(|s| {
+        s.password_hash_scheme = target.scheme;
+        s.password_hash_cost = Some(target.bcrypt_cost);
+        s.argon2_params = Some(target.argon2);
+    })?;
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    check_password_hashes_with_conn(&conn, &target, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn target(scheme: HashScheme, bcrypt_cost: u32, memory_kib: u32) -> HashTarget {
+        HashTarget {
+            scheme,
+            bcrypt_cost,
+            argon2: Argon2Settings {
+                memory_kib,
+                iterations: 1,
+                parallelism: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_both_schemes_verify() {
+        let argon = hash_password_with(&target(HashScheme::Argon2id, 4, 8), "secret").unwrap();
+        let bcrypt = hash_password_with(&target(HashScheme::Bcrypt, 4, 8), "secret").unwrap();
+        assert!(argon.starts_with(ARGON2ID_PREFIX));
+        assert_eq!(scheme_of_hash(&bcrypt), Some(HashScheme::Bcrypt));
+
+        assert!(verify_password("secret", &argon).unwrap());
+        assert!(!verify_password("wrong", &argon).unwrap());
+        assert!(verify_password("secret", &bcrypt).unwrap());
+        assert!(!verify_password("wrong", &bcrypt).unwrap());
+        assert!(verify_password("secret", "plain-text").is_err());
+
+        assert!(needs_rehash(&bcrypt, &target(HashScheme::Argon2id, 4, 8)));
+        assert!(!needs_rehash(&argon, &target(HashScheme::Argon2id, 4, 8)));
+        assert!(needs_rehash(&argon, &target(HashScheme::Bcrypt, 4, 8)));
+    }
+
+    #[test]
+    fn test_pick_argon2_iterations() {
+        let ms = Duration::from_millis;
+        assert_eq!(pick_argon2_iterations(ms(60), ms(250)), 4);
+        assert_eq!(
+            pick_argon2_iterations(ms(300), ms(250)),
+            MIN_ARGON2_ITERATIONS
+        );
+        assert_eq!(
+            pick_argon2_iterations(Duration::ZERO, ms(250)),
+            MAX_ARGON2_ITERATIONS
+        );
+
+        let mut too_slow = target(HashScheme::Argon2id, password_hashing::MIN_COST, 16);
+        assert!(validate_target(&too_slow).is_ok());
+        too_slow.argon2.iterations = MAX_ARGON2_ITERATIONS + 1;
+        assert!(validate_target(&too_slow).is_err());
+    }
+
+    #[test]
+    fn test_weaker_hashes_are_flagged() {
+        let bcrypt = hash_password_with(&target(HashScheme::Bcrypt, 5, 8), "secret").unwrap();
+        assert_eq!(bcrypt_cost_of(&bcrypt), Some(5));
+        assert!(needs_rehash(&bcrypt, &target(HashScheme::Bcrypt, 6, 8)));
+        assert!(!needs_rehash(&bcrypt, &target(HashScheme::Bcrypt, 4, 8)));
+
+        let argon = hash_password_with(&target(HashScheme::Argon2id, 4, 16), "secret").unwrap();
+        assert!(needs_rehash(&argon, &target(HashScheme::Argon2id, 4, 32)));
+        assert!(!needs_rehash(&argon, &target(HashScheme::Argon2id, 4, 16)));
+
+        let conn = create_app_db();
+        for (id, hash) in [(1, &bcrypt), (2, &argon), (3, &"plain".to_string())] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (?1, ?1, ?1, ?2, 'X')",
+                rusqlite::params![id, hash],
+            )
+            .unwrap();
+        }
+        let report = check_password_hashes_with_conn(
+            &conn,
+            &target(HashScheme::Argon2id, 4, 16),
+            &ProgressReporter::noop(),
+        )
+        .unwrap();
+        assert_eq!(
+            report,
+            RehashReport {
+                total_users: 3,
+                up_to_date: 1,
+                rehash_on_login: 1,
+                unrecognized: 1,
+            }
+        );
+
+        assert!(validate_target(&target(HashScheme::Argon2id, 3, 16)).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+//! Review of newly uploaded photos
+//!
+//! Users and officers carry an `avatar_status`. A photo uploaded or adopted by
+//! anyone but an admin starts out pending and stays off the officer board and
+//! the contact sheets until an admin approves it; a rejected photo stays off
+//! as well until it is replaced. Photos from before the review existed have no
+//! status and count as approved, as do photos uploaded by an admin. Clearing
+//! or transferring a photo takes its status along.
+//!
+//! Every path that hands out a photo (data URL commands, `avatar://` and its
+//! URLs, zip exports, board and contact sheets) goes through the checks
+//! here: the reviewing admins and the user the photo belongs to still see it,
+//! everyone else gets no photo.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::database::get_connection_safe;
+use crate::media_maintenance::{normalize_media_path, owner_table, OWNER_OFFICER, OWNER_USER};
+use crate::permissions::{Caller, Role};
+
+/// SQL condition for rows whose photo may be shown outside the review queue
+pub const VISIBLE_AVATAR_CONDITION: &str = "(avatar_status IS NULL OR avatar_status = 'approved')";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AvatarStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl AvatarStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AvatarStatus::Pending => "pending",
+            AvatarStatus::Approved => "approved",
+            AvatarStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(status: &str) -> Result<AvatarStatus, String> {
+        match status.trim().to_lowercase().as_str() {
+            "pending" => Ok(AvatarStatus::Pending),
+            "approved" => Ok(AvatarStatus::Approved),
+            "rejected" => Ok(AvatarStatus::Rejected),
+            _ => Err(format!("Unknown avatar status: {}", status)),
+        }
+    }
+}
+
+/// A photo in the review queue
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvatarReviewItem {
+    pub owner_type: String,
+    pub owner_id: i32,
+    /// Full name of a user, Thai name of an officer
+    pub name: String,
+    pub avatar_path: String,
+    pub avatar_updated_at: Option<String>,
+    pub avatar_status: AvatarStatus,
+}
+
+/// `status` shows outside the review queue
+pub fn is_visible_status(status: Option<&str>) -> bool {
+    status.is_none_or(|status| status == AvatarStatus::Approved.as_str())
+}
+
+/// A photo with `status` of `owner_type` `owner_id` may be shown to `viewer`
+pub fn is_visible_to(
+    status: Option<&str>,
+    owner_type: &str,
+    owner_id: i32,
+    viewer: Option<Caller>,
+) -> bool {
+    is_visible_status(status)
+        || viewer.is_some_and(|viewer| {
+            viewer.role == Role::Admin
+                || (owner_type == OWNER_USER && viewer.user_id == Some(owner_id))
+        })
+}
+
+/// The photo of `owner_type` `owner_id` may be shown to `viewer`; owners
+/// that do not exist have nothing to hide
+pub fn owner_visible_to_with_conn(
+    conn: &Connection,
+    owner_type: &str,
+    owner_id: i32,
+    viewer: Option<Caller>,
+) -> Result<bool, String> {
+    let status: Option<Option<String>> = conn
+        .query_row(
+            &format!(
+                "SELECT avatar_status FROM {} WHERE id = ?",
+                owner_table(owner_type)?
+            ),
+            params![owner_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read avatar status: {}", e))?;
+    Ok(status.is_none_or(|status| is_visible_to(status.as_deref(), owner_type, owner_id, viewer)))
+}
+
+/// The media file at `path` may be shown to `viewer`: no user or officer
+/// whose photo is hidden from them refers to it
+pub fn path_visible_to_with_conn(
+    conn: &Connection,
+    path: &str,
+    viewer: Option<Caller>,
+) -> Result<bool, String> {
+    let path = normalize_media_path(path);
+    for owner_type in [OWNER_USER, OWNER_OFFICER] {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, avatar_status FROM {} WHERE REPLACE(avatar_path, '\\', '/') = ? AND NOT {}",
+                owner_table(owner_type)?,
+                VISIBLE_AVATAR_CONDITION
+            ))
+            .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+        let hidden = stmt
+            .query_map(params![path], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .map_err(|e| format!("Failed to look up avatar owner: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read avatar owner: {}", e))?;
+        if hidden
+            .iter()
+            .any(|(id, status)| !is_visible_to(status.as_deref(), owner_type, *id, viewer))
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+pub fn owner_visible_to(
+    owner_type: &str,
+    owner_id: i32,
+    viewer: Option<Caller>,
+) -> Result<bool, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    owner_visible_to_with_conn(&conn, owner_type, owner_id, viewer)
+}
+
+pub fn path_visible_to(path: &str, viewer: Option<Caller>) -> Result<bool, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    path_visible_to_with_conn(&conn, path, viewer)
+}
+
+/// Status a photo gets when `role` uploads it
+pub fn status_for_upload(role: Option<Role>) -> AvatarStatus {
+    if role == Some(Role::Admin) {
+        AvatarStatus::Approved
+    } else {
+        AvatarStatus::Pending
+    }
+}
+
+/// Put a photo just stored for `owner_type` `owner_id` up for review, unless
+/// `uploaded_by` is an admin
+pub fn mark_uploaded_with_conn(
+    conn: &Connection,
+    owner_type: &str,
+    owner_id: i32,
+    uploaded_by: Option<Caller>,
+) -> Result<(), String> {
+    let role = uploaded_by.map(|caller| caller.role);
+    conn.execute(
+        &format!(
+            "UPDATE {} SET avatar_status = ? WHERE id = ?",
+            owner_table(owner_type)?
+        ),
+        params![status_for_upload(role).as_str(), owner_id],
+    )
+    .map_err(|e| format!("Failed to record avatar status: {}", e))?;
+    Ok(())
+}
+
+/// Photos with `status`, oldest upload first
+pub fn list_review_queue_with_conn(
+    conn: &Connection,
+    status: AvatarStatus,
+) -> Result<Vec<AvatarReviewItem>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT ?2, id, full_name, avatar_path, avatar_updated_at FROM users
+             WHERE avatar_status = ?1 AND avatar_path IS NOT NULL AND avatar_path != ''
+             UNION ALL
+             SELECT ?3, id, thai_name, avatar_path, avatar_updated_at FROM high_ranking_officers
+             WHERE avatar_status = ?1 AND avatar_path IS NOT NULL AND avatar_path != ''
+             ORDER BY 5, 1, 2",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let items = stmt
+        .query_map(params![status.as_str(), OWNER_USER, OWNER_OFFICER], |row| {
+            Ok(AvatarReviewItem {
+                owner_type: row.get(0)?,
+                owner_id: row.get(1)?,
+                name: row.get(2)?,
+                avatar_path: row.get(3)?,
+                avatar_updated_at: row.get(4)?,
+                avatar_status: status,
+            })
+        })
+        .map_err(|e| format!("Failed to query avatar review queue: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read avatar review item: {}", e))?;
+    Ok(items)
+}
+
+/// Approve, reject or re-queue the current photo of `owner_type` `owner_id`
+pub fn set_avatar_status_with_conn(
+    conn: &Connection,
+    owner_type: &str,
+    owner_id: i32,
+    status: AvatarStatus,
+) -> Result<(), String> {
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE {} SET avatar_status = ? WHERE id = ? AND avatar_path IS NOT NULL AND avatar_path != ''",
+                owner_table(owner_type)?
+            ),
+            params![status.as_str(), owner_id],
+        )
+        .map_err(|e| format!("Failed to update avatar status: {}", e))?;
+
+    if updated == 0 {
+        return Err(format!("No {} photo found for ID {}", owner_type, owner_id));
+    }
+    Ok(())
+}
+
+pub fn list_review_queue(status: AvatarStatus) -> Result<Vec<AvatarReviewItem>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    list_review_queue_with_conn(&conn, status)
+}
+
+pub fn set_avatar_status(
+    owner_type: &str,
+    owner_id: i32,
+    status: AvatarStatus,
+) -> Result<(), String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    set_avatar_status_with_conn(&conn, owner_type, owner_id, status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_review_queue() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path, avatar_updated_at, avatar_status)
+                 VALUES (1, 'a', 'a@test.com', 'h', 'A', 'avatars/a.jpg', '2024-01-02', 'pending'),
+                        (2, 'b', 'b@test.com', 'h', 'B', 'avatars/b.jpg', '2024-01-01', NULL),
+                        (3, 'c', 'c@test.com', 'h', 'C', NULL, NULL, NULL);
+             INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, avatar_path, avatar_updated_at, avatar_status)
+                 VALUES (1, 'O', 'P', 'P', 'high_ranks/o.jpg', '2024-01-01', 'pending');",
+        )
+        .expect("rows should insert");
+
+        let queue = list_review_queue_with_conn(&conn, AvatarStatus::Pending).unwrap();
+        let owners: Vec<_> = queue
+            .iter()
+            .map(|item| (item.owner_type.as_str(), item.owner_id))
+            .collect();
+        assert_eq!(owners, vec![(OWNER_OFFICER, 1), (OWNER_USER, 1)]);
+
+        set_avatar_status_with_conn(&conn, OWNER_USER, 1, AvatarStatus::Rejected).unwrap();
+        assert_eq!(
+            list_review_queue_with_conn(&conn, AvatarStatus::Rejected)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(set_avatar_status_with_conn(&conn, OWNER_USER, 3, AvatarStatus::Approved).is_err());
+
+        let visible: i64 = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM users WHERE {}",
+                    VISIBLE_AVATAR_CONDITION
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(visible, 2);
+
+        assert_eq!(status_for_upload(Some(Role::Admin)), AvatarStatus::Approved);
+        assert_eq!(status_for_upload(Some(Role::Editor)), AvatarStatus::Pending);
+        assert_eq!(status_for_upload(None), AvatarStatus::Pending);
+    }
+
+    #[test]
+    fn test_upload_status_follows_the_uploader() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path)
+                 VALUES (1, 'a', 'a@test.com', 'h', 'A', 'avatars/a.jpg');",
+        )
+        .expect("rows should insert");
+        let status = || -> Option<String> {
+            conn.query_row("SELECT avatar_status FROM users WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let uploader = |role| {
+            Some(Caller {
+                user_id: Some(1),
+                role,
+                must_change_password: false,
+            })
+        };
+
+        mark_uploaded_with_conn(&conn, OWNER_USER, 1, uploader(Role::Admin)).unwrap();
+        assert_eq!(status().as_deref(), Some("approved"));
+        mark_uploaded_with_conn(&conn, OWNER_USER, 1, uploader(Role::Visitor)).unwrap();
+        assert_eq!(status().as_deref(), Some("pending"));
+        mark_uploaded_with_conn(&conn, OWNER_USER, 1, None).unwrap();
+        assert_eq!(status().as_deref(), Some("pending"));
+    }
+
+    #[test]
+    fn test_pending_photos_show_only_to_admins_and_their_owner() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path, avatar_status)
+                 VALUES (1, 'a', 'a@test.com', 'h', 'A', 'avatars\\a.jpg', 'pending'),
+                        (2, 'b', 'b@test.com', 'h', 'B', 'avatars/b.jpg', 'approved');",
+        )
+        .expect("rows should insert");
+
+        let viewer = |user_id, role| {
+            Some(Caller {
+                user_id: Some(user_id),
+                role,
+                must_change_password: false,
+            })
+        };
+        let editor = viewer(2, Role::Editor);
+        assert!(!owner_visible_to_with_conn(&conn, OWNER_USER, 1, editor).unwrap());
+        assert!(!owner_visible_to_with_conn(&conn, OWNER_USER, 1, None).unwrap());
+        assert!(
+            owner_visible_to_with_conn(&conn, OWNER_USER, 1, viewer(1, Role::Visitor)).unwrap()
+        );
+        assert!(owner_visible_to_with_conn(&conn, OWNER_USER, 1, viewer(3, Role::Admin)).unwrap());
+        assert!(owner_visible_to_with_conn(&conn, OWNER_USER, 2, None).unwrap());
+        assert!(owner_visible_to_with_conn(&conn, OWNER_USER, 99, None).unwrap());
+
+        assert!(!path_visible_to_with_conn(&conn, "avatars/a.jpg", editor).unwrap());
+        assert!(
+            path_visible_to_with_conn(&conn, "avatars/a.jpg", viewer(1, Role::Visitor)).unwrap()
+        );
+        assert!(path_visible_to_with_conn(&conn, "avatars/b.jpg", None).unwrap());
+        assert!(path_visible_to_with_conn(&conn, "avatars/unknown.jpg", None).unwrap());
+    }
+}
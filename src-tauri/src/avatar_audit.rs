@@ -0,0 +1,348 @@
+//! Audit trail of who viewed which personnel photo
+//!
+//! Off by default; deployments with privacy requirements switch it on in
+//! settings. Each read of an avatar file is written to the activity log as an
+//! `avatar_access` event whose details hold the photo's owner. Lists render
+//! the same photo many times in a row, so repeated reads of one photo by one
+//! requester are recorded once per `REPEAT_WINDOW_SECS`.
+
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::activity_log::{self, EVENT_AVATAR_ACCESS};
+use crate::database::get_connection_safe;
+use crate::logger;
+use crate::media_maintenance::{normalize_media_path, OWNER_OFFICER, OWNER_USER};
+use crate::settings;
+
+const REPEAT_WINDOW_SECS: u64 = 5 * 60;
+const DEFAULT_QUERY_LIMIT: u32 = 200;
+/// Remembered (requester, photo) pairs before the repeat map is reset
+const MAX_REMEMBERED_READS: usize = 1000;
+
+lazy_static! {
+    static ref AUDIT_ENABLED: RwLock<Option<bool>> = RwLock::new(None);
+    static ref LAST_RECORDED: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+/// The photo that was read; path-only reads get their owner looked up
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvatarAccess {
+    pub owner_type: Option<String>,
+    pub owner_id: Option<i32>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AvatarAccessEvent {
+    pub id: i64,
+    pub requested_by: Option<i32>,
+    pub requested_by_username: Option<String>,
+    pub access: AvatarAccess,
+    pub accessed_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AvatarAccessFilter {
+    pub owner_type: Option<String>,
+    pub owner_id: Option<i32>,
+    pub requested_by: Option<i32>,
+    /// Only events at or after this time (SQLite "YYYY-MM-DD HH:MM:SS")
+    pub since: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl AvatarAccess {
+    pub fn user(user_id: i32) -> Self {
+        AvatarAccess {
+            owner_type: Some(OWNER_USER.to_string()),
+            owner_id: Some(user_id),
+            path: None,
+        }
+    }
+
+    pub fn officer(officer_id: i32) -> Self {
+        AvatarAccess {
+            owner_type: Some(OWNER_OFFICER.to_string()),
+            owner_id: Some(officer_id),
+            path: None,
+        }
+    }
+
+    pub fn path(path: &str) -> Self {
+        AvatarAccess {
+            owner_type: None,
+            owner_id: None,
+            path: Some(normalize_media_path(path)),
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn is_enabled() -> bool {
+    if let Some(enabled) = AUDIT_ENABLED.read().ok().and_then(|cached| *cached) {
+        return enabled;
+    }
+    let enabled = settings::load_settings()
+        .map(|s| s.audit_avatar_access)
+        .unwrap_or(false);
+    if let Ok(mut cached) = AUDIT_ENABLED.write() {
+        *cached = Some(enabled);
+    }
+    enabled
+}
+
+pub fn set_enabled(enabled: bool) -> Result<(), String> {
+    settings::update_settings(|s| s.audit_avatar_access = enabled)?;
+    if let Ok(mut cached) = AUDIT_ENABLED.write() {
+        *cached = Some(enabled);
+    }
+    Ok(())
+}
+
+/// Whether a read last recorded at `last` should be recorded again at `now`
+pub fn is_repeat(last: Option<u64>, now: u64) -> bool {
+    last.is_some_and(|last| now.saturating_sub(last) < REPEAT_WINDOW_SECS)
+}
+
+/// Owner of the user or officer whose avatar is stored at `path`
+fn owner_for_path_with_conn(
+    conn: &Connection,
+    path: &str,
+) -> Result<Option<(String, i32)>, String> {
+    for (owner_type, table) in [
+        (OWNER_USER, "users"),
+        (OWNER_OFFICER, "high_ranking_officers"),
+    ] {
+        let owner_id: Option<i32> = conn
+            .query_row(
+                &format!(
+                    "SELECT id FROM {} WHERE REPLACE(avatar_path, '\\', '/') = ? LIMIT 1",
+                    table
+                ),
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to look up avatar owner: {}", e))?;
+        if let Some(owner_id) = owner_id {
+            return Ok(Some((owner_type.to_string(), owner_id)));
+        }
+    }
+    Ok(None)
+}
+
+pub fn record_access_with_conn(
+    conn: &Connection,
+    requested_by: Option<i32>,
+    access: &AvatarAccess,
+) -> Result<(), String> {
+    let mut access = access.clone();
+    if access.owner_id.is_none() {
+        if let Some(path) = access.path.clone() {
+            if let Some((owner_type, owner_id)) = owner_for_path_with_conn(conn, &path)? {
+                access.owner_type = Some(owner_type);
+                access.owner_id = Some(owner_id);
+            }
+        }
+    }
+
+    let username: Option<String> = match requested_by {
+        Some(id) => conn
+            .query_row(
+                "SELECT username FROM users WHERE id = ?",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to look up requester: {}", e))?,
+        None => None,
+    };
+    let details = serde_json::to_string(&access)
+        .map_err(|e| format!("Failed to serialize avatar access: {}", e))?;
+
+    activity_log::record_event_with_conn(
+        conn,
+        EVENT_AVATAR_ACCESS,
+        requested_by,
+        username.as_deref(),
+        Some(&details),
+    )
+}
+
+/// Record a read when auditing is on; never fails the read itself
+pub fn record_avatar_access(requested_by: Option<i32>, access: AvatarAccess) {
+    if !is_enabled() {
+        return;
+    }
+
+    let key = format!(
+        "{:?}|{:?}|{:?}|{:?}",
+        requested_by, access.owner_type, access.owner_id, access.path
+    );
+    let now = now_secs();
+    if let Ok(mut last_recorded) = LAST_RECORDED.lock() {
+        if is_repeat(last_recorded.get(&key).copied(), now) {
+            return;
+        }
+        if last_recorded.len() >= MAX_REMEMBERED_READS {
+            last_recorded.clear();
+        }
+        last_recorded.insert(key, now);
+    }
+
+    let result = get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))
+        .and_then(|conn| record_access_with_conn(&conn, requested_by, &access));
+    if let Err(e) = result {
+        logger::warn(format!("Failed to record avatar access: {}", e));
+    }
+}
+
+/// Most recent reads first
+pub fn query_avatar_access_with_conn(
+    conn: &Connection,
+    filter: &AvatarAccessFilter,
+) -> Result<Vec<AvatarAccessEvent>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, user_id, username, details, created_at FROM activity_log
+             WHERE event_type = ?1
+               AND (?2 IS NULL OR json_extract(details, '$.owner_type') = ?2)
+               AND (?3 IS NULL OR json_extract(details, '$.owner_id') = ?3)
+               AND (?4 IS NULL OR user_id = ?4)
+               AND (?5 IS NULL OR created_at >= ?5)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ?6",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let rows = stmt
+        .query_map(
+            params![
+                EVENT_AVATAR_ACCESS,
+                filter.owner_type,
+                filter.owner_id,
+                filter.requested_by,
+                filter.since,
+                filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT)
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i32>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            },
+        )
+        .map_err(|e| format!("Failed to query avatar access log: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read avatar access event: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, requested_by, requested_by_username, details, accessed_at)| {
+                let access = details
+                    .and_then(|d| serde_json::from_str(&d).ok())
+                    .unwrap_or(AvatarAccess {
+                        owner_type: None,
+                        owner_id: None,
+                        path: None,
+                    });
+                AvatarAccessEvent {
+                    id,
+                    requested_by,
+                    requested_by_username,
+                    access,
+                    accessed_at,
+                }
+            },
+        )
+        .collect())
+}
+
+pub fn query_avatar_access(filter: &AvatarAccessFilter) -> Result<Vec<AvatarAccessEvent>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    query_avatar_access_with_conn(&conn, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn setup() -> Connection {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path) VALUES
+                (1, 'viewer', 'v@test.com', 'h', 'Viewer', NULL),
+                (2, 'subject', 's@test.com', 'h', 'Subject', 'avatars\\avatar_2.png');
+             INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english)
+                VALUES (7, 'a', 'b', 'c');",
+        )
+        .expect("seed data should insert");
+        conn
+    }
+
+    #[test]
+    fn test_path_reads_resolve_owner_and_filter() {
+        let conn = setup();
+        record_access_with_conn(&conn, Some(1), &AvatarAccess::path("avatars/avatar_2.png"))
+            .expect("record should succeed");
+        record_access_with_conn(&conn, None, &AvatarAccess::officer(7))
+            .expect("record should succeed");
+
+        let for_subject = query_avatar_access_with_conn(
+            &conn,
+            &AvatarAccessFilter {
+                owner_type: Some(OWNER_USER.to_string()),
+                owner_id: Some(2),
+                ..AvatarAccessFilter::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(for_subject.len(), 1);
+        assert_eq!(
+            for_subject[0].requested_by_username.as_deref(),
+            Some("viewer")
+        );
+
+        let by_viewer = query_avatar_access_with_conn(
+            &conn,
+            &AvatarAccessFilter {
+                requested_by: Some(1),
+                ..AvatarAccessFilter::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(by_viewer.len(), 1);
+        assert_eq!(
+            query_avatar_access_with_conn(&conn, &AvatarAccessFilter::default())
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_repeat_window() {
+        assert!(!is_repeat(None, 1_000));
+        assert!(is_repeat(Some(1_000), 1_000 + REPEAT_WINDOW_SECS - 1));
+        assert!(!is_repeat(Some(1_000), 1_000 + REPEAT_WINDOW_SECS));
+    }
+}
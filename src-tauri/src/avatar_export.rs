@@ -0,0 +1,289 @@
+//! Bulk export of selected users' avatar files as a zip
+//!
+//! Files are renamed to `<service_number>_<full_name>.<ext>` so photos can
+//! be handed to printing services without a lookup table.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::avatar_approval;
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+use crate::long_path;
+use crate::safe_path::MediaRoot;
+use crate::validation;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SkippedAvatar {
+    pub user_id: i32,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AvatarExportReport {
+    pub destination: String,
+    pub exported: usize,
+    pub skipped: Vec<SkippedAvatar>,
+}
+
+/// Characters Windows forbids in filenames become '_', whitespace runs become '_'
+pub fn sanitize_filename_part(value: &str) -> String {
+    let replaced: String = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() || c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect();
+
+    let mut result = String::with_capacity(replaced.len());
+    for c in replaced.chars() {
+        if !(c == '_' && result.ends_with('_')) {
+            result.push(c);
+        }
+    }
+    result.trim_matches('_').to_string()
+}
+
+/// Source file and zip entry name of an exported avatar
+type AvatarEntry = (PathBuf, String);
+
+/// Source file and zip entry name for every exportable user, plus the users skipped
+pub fn collect_avatar_entries_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    user_ids: &[i32],
+) -> Result<(Vec<AvatarEntry>, Vec<SkippedAvatar>), String> {
+    let media_root = MediaRoot::new(media_dir)?;
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    let mut used_names = HashSet::new();
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT full_name, service_number, avatar_path, avatar_status FROM users WHERE id = ?",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    type Row = (String, Option<String>, Option<String>, Option<String>);
+    for &user_id in user_ids {
+        let row: Option<Row> = match stmt.query_row([user_id], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+        }) {
+            Ok(row) => Some(row),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(format!("Failed to query user {}: {}", user_id, e)),
+        };
+
+        let (full_name, service_number, avatar_path, avatar_status) = match row {
+            Some(row) => row,
+            None => {
+                skipped.push(SkippedAvatar {
+                    user_id,
+                    reason: "User not found".to_string(),
+                });
+                continue;
+            }
+        };
+        // Printed photos must have passed review
+        if !avatar_approval::is_visible_status(avatar_status.as_deref()) {
+            skipped.push(SkippedAvatar {
+                user_id,
+                reason: "Photo awaiting review".to_string(),
+            });
+            continue;
+        }
+
+        let source = match avatar_path.filter(|p| !p.is_empty()) {
+            Some(p) => match media_root.resolve(&p) {
+                Ok(source) => source.into_path_buf(),
+                Err(e) => {
+                    skipped.push(SkippedAvatar { user_id, reason: e });
+                    continue;
+                }
+            },
+            None => {
+                skipped.push(SkippedAvatar {
+                    user_id,
+                    reason: "No avatar".to_string(),
+                });
+                continue;
+            }
+        };
+        if !source.is_file() {
+            skipped.push(SkippedAvatar {
+                user_id,
+                reason: "Avatar file missing".to_string(),
+            });
+            continue;
+        }
+
+        let prefix = service_number
+            .map(|s| sanitize_filename_part(&s))
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| format!("user_{}", user_id));
+        let extension = source
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("jpg")
+            .to_lowercase();
+        let base = format!("{}_{}", prefix, sanitize_filename_part(&full_name));
+
+        // Two people can share a name and lack a service number - keep both files
+        let mut name = format!("{}.{}", base, extension);
+        let mut counter = 2;
+        while !used_names.insert(name.clone()) {
+            name = format!("{}_{}.{}", base, counter, extension);
+            counter += 1;
+        }
+
+        entries.push((source, name));
+    }
+
+    Ok((entries, skipped))
+}
+
+pub fn write_avatar_zip(entries: &[(PathBuf, String)], destination: &Path) -> Result<(), String> {
+    let destination = long_path::for_io(destination);
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create destination directory: {}", e))?;
+    }
+
+    let file =
+        fs::File::create(&destination).map_err(|e| format!("Failed to create zip file: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    // Images are already compressed
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    for (source, name) in entries {
+        let data = fs::read(long_path::for_io(source))
+            .map_err(|e| format!("Failed to read avatar file: {}", e))?;
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| format!("Failed to add {} to zip: {}", name, e))?;
+        zip.write_all(&data)
+            .map_err(|e| format!("Failed to write {} to zip: {}", name, e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize zip file: {}", e))?;
+    Ok(())
+}
+
+pub fn export_avatars_zip(
+    user_ids: &[i32],
+    destination: &str,
+) -> Result<AvatarExportReport, String> {
+    if user_ids.is_empty() {
+        return Err("No users selected".to_string());
+    }
+    let destination_path = validation::absolute_path("destination", destination)?;
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+
+    let (entries, skipped) =
+        collect_avatar_entries_with_conn(&conn, file_manager.get_media_directory(), user_ids)?;
+    if entries.is_empty() {
+        return Err("None of the selected users has an avatar file".to_string());
+    }
+
+    write_avatar_zip(&entries, &destination_path)?;
+
+    Ok(AvatarExportReport {
+        destination: destination.to_string(),
+        exported: entries.len(),
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sanitize_filename_part() {
+        assert_eq!(sanitize_filename_part("สมชาย  ใจดี"), "สมชาย_ใจดี");
+        assert_eq!(sanitize_filename_part(" a/b:c* "), "a_b_c");
+        assert_eq!(sanitize_filename_part("12-345"), "12-345");
+    }
+
+    #[test]
+    fn test_collect_entries_names_and_skips() {
+        let conn = create_app_db();
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("avatars")).unwrap();
+        fs::write(media.path().join("avatars").join("a1.png"), b"png").unwrap();
+        fs::write(media.path().join("avatars").join("a2.jpg"), b"jpg").unwrap();
+        fs::write(media.path().join("avatars").join("a6.jpg"), b"jpg").unwrap();
+
+        for (id, name, sn, avatar, status) in [
+            (1, "John Doe", Some("1234"), Some("avatars\\a1.png"), None),
+            (
+                2,
+                "Jane Roe",
+                None,
+                Some("avatars/a2.jpg"),
+                Some("approved"),
+            ),
+            (3, "No Photo", Some("999"), None, None),
+            (5, "Escape", Some("555"), Some("../database.db"), None),
+            (
+                6,
+                "Pending",
+                Some("666"),
+                Some("avatars/a6.jpg"),
+                Some("pending"),
+            ),
+        ] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name, service_number, avatar_path, avatar_status) VALUES (?, ?, ?, 'h', ?, ?, ?, ?)",
+                rusqlite::params![id, format!("u{}", id), format!("u{}@test.com", id), name, sn, avatar, status],
+            )
+            .expect("user insert should succeed");
+        }
+
+        let (entries, skipped) =
+            collect_avatar_entries_with_conn(&conn, media.path(), &[1, 2, 3, 4, 5, 6])
+                .expect("collect should succeed");
+
+        let names: Vec<&str> = entries.iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(names, vec!["1234_John_Doe.png", "user_2_Jane_Roe.jpg"]);
+        assert_eq!(skipped.len(), 4);
+        assert!(skipped[2].reason.contains("Invalid media path"));
+        assert_eq!(skipped[3].reason, "Photo awaiting review");
+
+        let zip_path = media.path().join("out").join("avatars.zip");
+        write_avatar_zip(&entries, &zip_path).expect("zip should be written");
+        let archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 2);
+    }
+
+    #[test]
+    fn test_zip_written_into_thai_directory() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let photo = dir.path().join("ภาพถ่าย.png");
+        fs::write(&photo, b"png").unwrap();
+        let entries = vec![(photo, "1234_สมชาย_ใจดี.png".to_string())];
+
+        let zip_path = dir
+            .path()
+            .join("งานพิมพ์บัตร")
+            .join("กองทัพเรือ")
+            .join("ภาพกำลังพล.zip");
+        write_avatar_zip(&entries, &zip_path).expect("zip should be written");
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "1234_สมชาย_ใจดี.png");
+    }
+}
@@ -0,0 +1,406 @@
+//! Upload rules shared by the user and high-rank avatar managers
+//!
+//! Size limit, accepted formats, maximum dimensions and re-encode quality come
+//! from `AppSettings::avatar_policy`. Images larger than the maximum dimension
+//! are scaled down and re-encoded (PNG stays PNG, everything else becomes
+//! JPEG); animated GIFs cannot be scaled and are rejected instead.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::logger;
+use crate::settings::{self, AvatarPolicy};
+
+/// Formats the image decoder is built with; policies may only narrow this
+pub const SUPPORTED_MIME_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp", "image/gif"];
+
+/// Format and size persisted with every avatar (`avatar_width`, ...)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageMetadata {
+    /// "jpeg", "png", "webp" or "gif"
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedAvatar {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    pub report: AvatarValidationReport,
+}
+
+/// What was actually stored, returned to the UI with the saved avatar
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvatarValidationReport {
+    /// Format sniffed from the bytes, not the type the client declared
+    pub detected_mime: String,
+    pub original_width: u32,
+    pub original_height: u32,
+    pub original_size: u64,
+    pub stored_mime: String,
+    pub stored_width: u32,
+    pub stored_height: u32,
+    pub stored_size: u64,
+    pub reencoded: bool,
+    pub downscaled: bool,
+}
+
+/// "image/jpg" is common in the wild but not a registered type
+pub fn normalize_mime(mime_type: &str) -> String {
+    match mime_type.trim().to_lowercase().as_str() {
+        "image/jpg" => "image/jpeg".to_string(),
+        other => other.to_string(),
+    }
+}
+
+pub fn extension_for_mime(mime_type: &str) -> &'static str {
+    match normalize_mime(mime_type).as_str() {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "jpg",
+    }
+}
+
+/// Reject policies that could never accept an upload
+pub fn validate_policy(policy: &AvatarPolicy) -> Result<(), String> {
+    if policy.max_upload_bytes == 0 {
+        return Err("Maximum upload size must be greater than zero".to_string());
+    }
+    if policy.max_dimension == 0 {
+        return Err("Maximum dimension must be greater than zero".to_string());
+    }
+    if !(1..=100).contains(&policy.reencode_quality) {
+        return Err("Re-encode quality must be between 1 and 100".to_string());
+    }
+    if policy.accepted_mime_types.is_empty() {
+        return Err("At least one image format must be accepted".to_string());
+    }
+    for mime_type in &policy.accepted_mime_types {
+        if !SUPPORTED_MIME_TYPES.contains(&normalize_mime(mime_type).as_str()) {
+            return Err(format!("Unsupported image type: {}", mime_type));
+        }
+    }
+    Ok(())
+}
+
+/// Policy from settings; an unreadable settings file falls back to defaults
+pub fn current_policy() -> AvatarPolicy {
+    match settings::load_settings() {
+        Ok(settings) => settings.avatar_policy,
+        Err(e) => {
+            logger::warn(format!("Using default avatar policy: {}", e));
+            AvatarPolicy::default()
+        }
+    }
+}
+
+pub fn save_avatar_policy(policy: AvatarPolicy) -> Result<(), String> {
+    validate_policy(&policy)?;
+    settings::update_settings(|settings| settings.avatar_policy = policy)?;
+    Ok(())
+}
+
+/// Size and format checks that need no decoding (also used before streaming)
+pub fn check_upload(policy: &AvatarPolicy, size: u64, mime_type: &str) -> Result<(), String> {
+    if size == 0 {
+        return Err("Avatar data is empty".to_string());
+    }
+    if size > policy.max_upload_bytes {
+        return Err(format!(
+            "Avatar data too large: {} bytes (max: {} bytes)",
+            size, policy.max_upload_bytes
+        ));
+    }
+
+    check_mime(policy, mime_type)
+}
+
+pub fn check_mime(policy: &AvatarPolicy, mime_type: &str) -> Result<(), String> {
+    let mime_type = normalize_mime(mime_type);
+    let accepted = policy
+        .accepted_mime_types
+        .iter()
+        .any(|accepted| normalize_mime(accepted) == mime_type);
+    if !accepted {
+        return Err(format!("Unsupported image type: {}", mime_type));
+    }
+    Ok(())
+}
+
+impl ImageMetadata {
+    /// From the avatar_format/avatar_width/avatar_height columns; None unless all are set
+    pub fn from_columns(
+        format: Option<String>,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Option<Self> {
+        Some(ImageMetadata {
+            format: format?,
+            width: width?,
+            height: height?,
+        })
+    }
+}
+
+impl AvatarValidationReport {
+    pub fn stored_metadata(&self) -> ImageMetadata {
+        ImageMetadata {
+            format: format_name(&self.stored_mime),
+            width: self.stored_width,
+            height: self.stored_height,
+        }
+    }
+}
+
+/// "image/jpeg" -> "jpeg"
+fn format_name(mime_type: &str) -> String {
+    normalize_mime(mime_type)
+        .trim_start_matches("image/")
+        .to_string()
+}
+
+/// Format and dimensions from the file header only; the pixels are not decoded
+pub fn read_image_metadata(path: &Path) -> Result<ImageMetadata, String> {
+    let reader = image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("Failed to open image: {}", e))?;
+    let mime_type = reader
+        .format()
+        .and_then(mime_for_format)
+        .ok_or_else(|| "Unrecognized image format".to_string())?;
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| format!("Failed to read image header: {}", e))?;
+
+    Ok(ImageMetadata {
+        format: format_name(mime_type),
+        width,
+        height,
+    })
+}
+
+fn mime_for_format(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::WebP => Some("image/webp"),
+        ImageFormat::Gif => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// Apply the whole policy; returns the bytes and MIME type to store
+pub fn prepare_avatar(
+    policy: &AvatarPolicy,
+    data: &[u8],
+    mime_type: &str,
+) -> Result<PreparedAvatar, String> {
+    check_upload(policy, data.len() as u64, mime_type)?;
+
+    // Trust the bytes over the declared type; a renamed file is stored as what it is
+    let format = image::guess_format(data)
+        .ok()
+        .filter(|format| mime_for_format(*format).is_some())
+        .ok_or_else(|| "Invalid image data: unrecognized format".to_string())?;
+    let mime_type = mime_for_format(format).unwrap_or_default().to_string();
+    check_mime(policy, &mime_type)?;
+    let image = image::load_from_memory_with_format(data, format)
+        .map_err(|e| format!("Invalid image data: {}", e))?;
+
+    let mut report = AvatarValidationReport {
+        detected_mime: mime_type.clone(),
+        original_width: image.width(),
+        original_height: image.height(),
+        original_size: data.len() as u64,
+        stored_mime: mime_type.clone(),
+        stored_width: image.width(),
+        stored_height: image.height(),
+        stored_size: data.len() as u64,
+        reencoded: false,
+        downscaled: false,
+    };
+
+    if image.width() <= policy.max_dimension && image.height() <= policy.max_dimension {
+        return Ok(PreparedAvatar {
+            data: data.to_vec(),
+            mime_type,
+            report,
+        });
+    }
+
+    if format == ImageFormat::Gif {
+        return Err(format!(
+            "GIF avatars must be at most {}x{} pixels",
+            policy.max_dimension, policy.max_dimension
+        ));
+    }
+
+    // resize() keeps the aspect ratio within the bounding box
+    let resized = image.resize(
+        policy.max_dimension,
+        policy.max_dimension,
+        FilterType::Lanczos3,
+    );
+
+    let mut output = Vec::new();
+    let output_mime = if format == ImageFormat::Png {
+        resized
+            .write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Png)
+            .map_err(|e| format!("Failed to encode avatar: {}", e))?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut output, policy.reencode_quality)
+            .encode_image(&resized.to_rgb8())
+            .map_err(|e| format!("Failed to encode avatar: {}", e))?;
+        "image/jpeg"
+    };
+
+    logger::debug(format!(
+        "Avatar scaled from {}x{} to {}x{} ({} -> {} bytes)",
+        image.width(),
+        image.height(),
+        resized.width(),
+        resized.height(),
+        data.len(),
+        output.len()
+    ));
+
+    report.stored_mime = output_mime.to_string();
+    report.stored_width = resized.width();
+    report.stored_height = resized.height();
+    report.stored_size = output.len() as u64;
+    report.reencoded = true;
+    report.downscaled = true;
+
+    Ok(PreparedAvatar {
+        data: output,
+        mime_type: output_mime.to_string(),
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn encoded(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(width, height));
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), format)
+            .expect("test image should encode");
+        data
+    }
+
+    fn small_policy() -> AvatarPolicy {
+        AvatarPolicy {
+            max_dimension: 64,
+            ..AvatarPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_check_upload_limits() {
+        let policy = AvatarPolicy {
+            max_upload_bytes: 100,
+            accepted_mime_types: vec!["image/jpeg".to_string()],
+            ..AvatarPolicy::default()
+        };
+
+        assert!(check_upload(&policy, 50, "image/jpg").is_ok());
+        assert!(check_upload(&policy, 0, "image/jpeg").is_err());
+        assert!(check_upload(&policy, 101, "image/jpeg").is_err());
+        assert!(check_upload(&policy, 50, "image/png").is_err());
+    }
+
+    #[test]
+    fn test_small_image_is_stored_unchanged() {
+        let data = encoded(32, 16, ImageOutputFormat::Png);
+
+        let prepared =
+            prepare_avatar(&small_policy(), &data, "image/png").expect("avatar should pass");
+
+        assert!(!prepared.report.downscaled);
+        assert_eq!(prepared.data, data);
+        assert_eq!(
+            (prepared.report.stored_width, prepared.report.stored_size),
+            (32, data.len() as u64)
+        );
+    }
+
+    #[test]
+    fn test_large_image_is_scaled_down() {
+        let data = encoded(256, 128, ImageOutputFormat::Jpeg(90));
+
+        let prepared =
+            prepare_avatar(&small_policy(), &data, "image/jpeg").expect("avatar should pass");
+
+        assert!(prepared.report.downscaled && prepared.report.reencoded);
+        assert_eq!(prepared.mime_type, "image/jpeg");
+        assert_eq!(
+            (prepared.report.original_width, prepared.report.stored_width),
+            (256, 64)
+        );
+        assert_eq!(prepared.report.stored_size, prepared.data.len() as u64);
+        let image = image::load_from_memory(&prepared.data).expect("output should decode");
+        assert_eq!((image.width(), image.height()), (64, 32));
+    }
+
+    #[test]
+    fn test_format_detected_from_bytes() {
+        let data = encoded(16, 16, ImageOutputFormat::Png);
+
+        let prepared =
+            prepare_avatar(&small_policy(), &data, "image/jpeg").expect("avatar should pass");
+
+        assert_eq!(prepared.report.detected_mime, "image/png");
+        assert_eq!(prepared.mime_type, "image/png");
+    }
+
+    #[test]
+    fn test_metadata_read_from_header() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let data = encoded(48, 24, ImageOutputFormat::Png);
+        let prepared =
+            prepare_avatar(&small_policy(), &data, "image/png").expect("avatar should pass");
+        let path = dir.path().join("avatar.jpg");
+        std::fs::write(&path, &prepared.data).unwrap();
+
+        let metadata = read_image_metadata(&path).expect("header should parse");
+        assert_eq!(metadata, prepared.report.stored_metadata());
+        assert_eq!(metadata.format, "png");
+        assert_eq!((metadata.width, metadata.height), (48, 24));
+
+        std::fs::write(&path, b"not an image").unwrap();
+        assert!(read_image_metadata(&path).is_err());
+    }
+
+    #[test]
+    fn test_oversized_gif_and_garbage_are_rejected() {
+        let gif = encoded(128, 128, ImageOutputFormat::Gif);
+        assert!(prepare_avatar(&small_policy(), &gif, "image/gif").is_err());
+        assert!(prepare_avatar(&small_policy(), b"not an image", "image/png").is_err());
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        assert!(validate_policy(&AvatarPolicy::default()).is_ok());
+        let bad_quality = AvatarPolicy {
+            reencode_quality: 0,
+            ..AvatarPolicy::default()
+        };
+        assert!(validate_policy(&bad_quality).is_err());
+        let bad_format = AvatarPolicy {
+            accepted_mime_types: vec!["image/bmp".to_string()],
+            ..AvatarPolicy::default()
+        };
+        assert!(validate_policy(&bad_format).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+//! `avatar://` protocol serving photos from the media directory
+//!
+//! `avatar://localhost/avatars/12_1700000000.jpg` (on Windows
+//! `https://avatar.localhost/...`) returns the media file with an ETag and a
+//! Last-Modified header derived from its size and mtime. `Cache-Control:
+//! no-cache` makes the webview keep the photo across sessions but revalidate
+//! it, so an unchanged file costs a 304 and a new upload is fetched at once.
+//! Only `avatars/` and `high_ranks/` are served; other media such as
+//! signature scans answer 404. Webview requests carry no session token, so
+//! the handler serves only photos everyone may see; reads go to the avatar
+//! audit trail without a user.
+//!
+//! `get_avatar_urls` hands the UI these URLs in place of base64 data URLs,
+//! with the owner's `avatar_updated_at` in the query so a replaced photo is
+//! never served from a stale cache entry even before revalidation. Photos
+//! under review that the caller may still see come as data URLs instead.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use tauri::http::{Request, Response, ResponseBuilder};
+
+use crate::avatar_approval;
+use crate::avatar_audit::{self, AvatarAccess};
+use crate::database::get_connection_safe;
+use crate::file_manager::{AVATARS_SUBDIR, HIGH_RANKS_SUBDIR};
+use crate::hybrid_avatar::placeholder_avatar_data_url;
+use crate::media_maintenance::{normalize_media_path, owner_table};
+use crate::permissions::Caller;
+use crate::safe_path::MediaRoot;
+use crate::storage_paths;
+
+pub const SCHEME: &str = "avatar";
+
+// WebView2 only hands custom schemes over as https://<scheme>.localhost
+#[cfg(windows)]
+const URL_BASE: &str = "https://avatar.localhost/";
+#[cfg(not(windows))]
+const URL_BASE: &str = "avatar://localhost/";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AvatarUrl {
+    pub owner_id: i32,
+    /// None for owners without a photo
+    pub url: Option<String>,
+}
+
+fn percent_encode(value: &str, keep_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// URL the webview loads `relative_path` from; `version` is the owner's
+/// `avatar_updated_at`
+pub fn avatar_url(relative_path: &str, version: Option<&str>) -> String {
+    let path = percent_encode(&normalize_media_path(relative_path), true);
+    match version {
+        Some(version) => format!("{}{}?v={}", URL_BASE, path, percent_encode(version, false)),
+        None => format!("{}{}", URL_BASE, path),
+    }
+}
+
+/// The photo at `relative_path` as a data URL, for photos `avatar://` does
+/// not serve; the placeholder when the file cannot be read
+fn private_data_url(relative_path: &str) -> String {
+    use base64::{engine::general_purpose, Engine as _};
+    let data = storage_paths::get_media_dir()
+        .and_then(|dir| MediaRoot::new(&dir))
+        .and_then(|root| root.resolve(relative_path))
+        .ok()
+        .filter(|path| is_served_path(path.relative()))
+        .and_then(|path| fs::read(path.full_path()).ok());
+    match data {
+        Some(data) => format!(
+            "data:{};base64,{}",
+            mime_type(relative_path),
+            general_purpose::STANDARD.encode(data)
+        ),
+        None => placeholder_avatar_data_url(),
+    }
+}
+
+/// URLs for `ids` of `owner_type`, in the order asked; unknown ids and
+/// photos hidden from `viewer` get None
+pub fn get_avatar_urls_with_conn(
+    conn: &Connection,
+    owner_type: &str,
+    ids: &[i32],
+    viewer: Option<Caller>,
+) -> Result<Vec<AvatarUrl>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT avatar_path, avatar_updated_at, avatar_status FROM {} WHERE id = ? AND avatar_path IS NOT NULL AND avatar_path != ''",
+            owner_table(owner_type)?
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    ids.iter()
+        .map(|&owner_id| {
+            let row = stmt.query_row(params![owner_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            });
+            let url = match row {
+                Ok((path, updated_at, status))
+                    if avatar_approval::is_visible_status(status.as_deref()) =>
+                {
+                    Some(avatar_url(&path, updated_at.as_deref()))
+                }
+                Ok((path, _, status))
+                    if avatar_approval::is_visible_to(
+                        status.as_deref(),
+                        owner_type,
+                        owner_id,
+                        viewer,
+                    ) =>
+                {
+                    avatar_audit::record_avatar_access(
+                        viewer.and_then(|viewer| viewer.user_id),
+                        AvatarAccess::path(&path),
+                    );
+                    Some(private_data_url(&path))
+                }
+                Ok(_) => None,
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(e) => return Err(format!("Failed to read avatar path: {}", e)),
+            };
+            Ok(AvatarUrl { owner_id, url })
+        })
+        .collect()
+}
+
+pub fn get_avatar_urls(
+    owner_type: &str,
+    ids: &[i32],
+    viewer: Option<Caller>,
+) -> Result<Vec<AvatarUrl>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_avatar_urls_with_conn(&conn, owner_type, ids, viewer)
+}
+
+/// Media-relative path from an `avatar://localhost/...` or
+/// `https://avatar.localhost/...` URI, percent-decoded, without query
+fn relative_path_from_uri(uri: &str) -> Result<String, String> {
+    let without_scheme = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let path = without_scheme
+        .split_once('/')
+        .map(|(_, path)| path)
+        .unwrap_or_default();
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    percent_decode(path)
+}
+
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("Invalid avatar URL: {}", value))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| format!("Invalid avatar URL: {}", value))
+}
+
+/// Changes whenever the file is replaced or rewritten
+fn etag(modified: SystemTime, size: u64) -> String {
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "\"{:x}-{:x}-{:x}\"",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos(),
+        size
+    )
+}
+
+/// IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// The webview's cached copy is still current. If-None-Match wins over
+/// If-Modified-Since when both are sent.
+fn is_not_modified(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: &str,
+    modified: SystemTime,
+) -> bool {
+    if let Some(tags) = if_none_match {
+        return tags
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+    let Some(since) = if_modified_since.and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+    else {
+        return false;
+    };
+    // HTTP dates have whole seconds
+    DateTime::<Utc>::from(modified).timestamp() <= since.timestamp()
+}
+
+fn mime_type(path: &str) -> &'static str {
+    match path.rsplit('.').next().map(str::to_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Only photo directories are served; signatures and anything else under the
+/// media root stay behind their commands' role checks
+fn is_served_path(relative: &str) -> bool {
+    matches!(
+        relative.split_once('/'),
+        Some((dir, name)) if (dir == AVATARS_SUBDIR || dir == HIGH_RANKS_SUBDIR) && !name.is_empty()
+    )
+}
+
+fn status_response(status: u16) -> Result<Response, Box<dyn Error>> {
+    ResponseBuilder::new().status(status).body(Vec::new())
+}
+
+/// Handler registered for the `avatar` scheme
+pub fn handle_request(request: &Request) -> Result<Response, Box<dyn Error>> {
+    let relative = match relative_path_from_uri(request.uri()) {
+        Ok(relative) => relative,
+        Err(_) => return status_response(400),
+    };
+    // Not created until the first upload
+    let Ok(media_root) = MediaRoot::new(&storage_paths::get_media_dir()?) else {
+        return status_response(404);
+    };
+    let path = match media_root.resolve(&relative) {
+        Ok(path) => path,
+        Err(_) => return status_response(400),
+    };
+    if !is_served_path(path.relative()) {
+        return status_response(404);
+    }
+    // Photos under review go to their admins and owner as data URLs only
+    if !avatar_approval::path_visible_to(path.relative(), None)? {
+        return status_response(404);
+    }
+    let metadata = match fs::metadata(path.full_path()) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return status_response(404),
+    };
+
+    avatar_audit::record_avatar_access(None, AvatarAccess::path(path.relative()));
+
+    let modified = metadata.modified()?;
+    let etag = etag(modified, metadata.len());
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let builder = ResponseBuilder::new()
+        .header("ETag", &etag)
+        .header("Last-Modified", http_date(modified))
+        .header("Cache-Control", "no-cache");
+
+    if is_not_modified(
+        header("if-none-match"),
+        header("if-modified-since"),
+        &etag,
+        modified,
+    ) {
+        return builder.status(304).body(Vec::new());
+    }
+    builder
+        .status(200)
+        .mimetype(mime_type(path.relative()))
+        .body(fs::read(path.full_path())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::Role;
+    use crate::test_helpers::helpers::create_app_db;
+    use std::time::Duration;
+
+    #[test]
+    fn test_relative_path_from_uri() {
+        assert_eq!(
+            relative_path_from_uri("avatar://localhost/avatars/12_a.jpg?v=3"),
+            Ok("avatars/12_a.jpg".to_string())
+        );
+        assert_eq!(
+            relative_path_from_uri("https://avatar.localhost/avatars/%E0%B8%81%20b.png"),
+            Ok("avatars/ก b.png".to_string())
+        );
+        assert!(relative_path_from_uri("avatar://localhost/avatars/%zz.png").is_err());
+    }
+
+    #[test]
+    fn test_only_photo_directories_are_served() {
+        assert!(is_served_path("avatars/12_a.jpg"));
+        assert!(is_served_path("high_ranks/3_b.png"));
+        assert!(!is_served_path("signatures/4_c.png"));
+        assert!(!is_served_path("avatars"));
+        assert!(!is_served_path("hybrid_backups/x.zip"));
+        assert!(!is_served_path("avatars_old/a.jpg"));
+    }
+
+    #[test]
+    fn test_avatar_urls_round_trip() {
+        let url = avatar_url("avatars\\ก b.png", Some("2024-01-01T10:00:00+07:00"));
+        assert!(url.ends_with("avatars/%E0%B8%81%20b.png?v=2024-01-01T10%3A00%3A00%2B07%3A00"));
+        assert_eq!(
+            relative_path_from_uri(&url),
+            Ok("avatars/ก b.png".to_string())
+        );
+
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path, avatar_updated_at)
+                 VALUES (1, 'a', 'a@test.com', 'h', 'A', 'avatars/a.jpg', '5'),
+                        (2, 'b', 'b@test.com', 'h', 'B', NULL, NULL);
+             INSERT INTO users (id, username, email, password_hash, full_name, avatar_path, avatar_status)
+                 VALUES (3, 'c', 'c@test.com', 'h', 'C', 'avatars/c.jpg', 'pending');",
+        )
+        .expect("rows should insert");
+        let urls = get_avatar_urls_with_conn(&conn, "user", &[2, 1, 9, 3], None).unwrap();
+        assert_eq!(urls[0].url, None);
+        assert_eq!(urls[1].url, Some(format!("{}avatars/a.jpg?v=5", URL_BASE)));
+        assert_eq!(urls[2].url, None);
+        assert_eq!(urls[3].url, None);
+        let owner = Caller {
+            user_id: Some(3),
+            role: Role::Visitor,
+            must_change_password: false,
+        };
+        // avatar:// cannot tell the owner apart, so a pending photo comes inline
+        let urls = get_avatar_urls_with_conn(&conn, "user", &[3], Some(owner)).unwrap();
+        assert!(urls[0]
+            .url
+            .as_deref()
+            .is_some_and(|url| url.starts_with("data:")));
+        assert!(get_avatar_urls_with_conn(&conn, "ship", &[1], None).is_err());
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let modified = UNIX_EPOCH + Duration::new(1_700_000_000, 500);
+        let tag = etag(modified, 2048);
+        assert_ne!(tag, etag(modified, 2049));
+        assert_eq!(http_date(modified), "Tue, 14 Nov 2023 22:13:20 GMT");
+
+        assert!(is_not_modified(Some(&tag), None, &tag, modified));
+        assert!(is_not_modified(
+            Some(&format!("\"other\", W/{}", tag)),
+            None,
+            &tag,
+            modified
+        ));
+        // A stale ETag is not rescued by a matching date
+        assert!(!is_not_modified(
+            Some("\"old\""),
+            Some(&http_date(modified)),
+            &tag,
+            modified
+        ));
+        assert!(is_not_modified(
+            None,
+            Some(&http_date(modified)),
+            &tag,
+            modified
+        ));
+        assert!(!is_not_modified(
+            None,
+            Some("Mon, 13 Nov 2023 00:00:00 GMT"),
+            &tag,
+            modified
+        ));
+        assert!(!is_not_modified(None, None, &tag, modified));
+    }
+}
@@ -0,0 +1,349 @@
+//! Catalog of the files in the backup directory
+//!
+//! `backup_catalog` remembers every backup the app has seen: kind, time,
+//! size and, for hybrid backups, what the manifest says. Files copied in or
+//! moved away by hand only show up there after `rescan_backup_directory`,
+//! which walks the directory, adds untracked backups, refreshes known ones
+//! and flags entries whose file is gone instead of forgetting them, so the
+//! listing can still say "missing since" for a backup someone moved to a USB
+//! stick. Split hybrid backups are one entry named after their first volume.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::backup_results::BackupKind;
+use crate::backup_volumes;
+use crate::database::get_connection_safe;
+use crate::hybrid_backup::{is_hybrid_backup_filename, read_backup_manifest};
+use crate::storage_paths;
+
+/// Filename prefix and extension of each non-hybrid kind
+const PLAIN_BACKUP_PATTERNS: &[(BackupKind, &str, &str)] = &[
+    (BackupKind::Json, "database_backup_", ".json"),
+    (BackupKind::Sqlite, "database_universal_", ".db"),
+    (BackupKind::SqlDump, "database_standard_", ".sql"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub filename: String,
+    pub kind: BackupKind,
+    /// Creation time in seconds, from the manifest or the file name
+    pub timestamp: u64,
+    pub size_bytes: u64,
+    /// Files a split hybrid backup consists of; 1 otherwise
+    pub volumes: usize,
+    pub schema_version: Option<i32>,
+    pub note: Option<String>,
+    pub checksum: Option<String>,
+    /// The file was not found by the last rescan
+    pub missing: bool,
+    pub last_seen_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CatalogRescan {
+    /// Backups not in the catalog before
+    pub added: Vec<String>,
+    /// Known backups found again
+    pub refreshed: usize,
+    /// Entries whose file disappeared since the previous rescan
+    pub newly_missing: Vec<String>,
+    /// Hybrid backups whose manifest could not be read, e.g. a split set
+    /// with a volume not copied along; cataloged without manifest details
+    pub unreadable: Vec<String>,
+}
+
+pub fn init_backup_catalog_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS backup_catalog (
+            filename TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            volumes INTEGER NOT NULL DEFAULT 1,
+            schema_version INTEGER,
+            note TEXT,
+            checksum TEXT,
+            missing INTEGER NOT NULL DEFAULT 0,
+            last_seen_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create backup_catalog table: {}", e))?;
+    Ok(())
+}
+
+fn kind_name(kind: BackupKind) -> String {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn parse_kind(name: &str) -> BackupKind {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).unwrap_or(BackupKind::Json)
+}
+
+/// Kind and name timestamp of a non-hybrid backup file
+fn plain_backup_kind(filename: &str) -> Option<(BackupKind, Option<u64>)> {
+    PLAIN_BACKUP_PATTERNS
+        .iter()
+        .find(|(_, prefix, extension)| {
+            filename.starts_with(prefix) && filename.ends_with(extension)
+        })
+        .map(|(kind, prefix, extension)| {
+            let timestamp = filename[prefix.len()..filename.len() - extension.len()]
+                .parse()
+                .ok();
+            (*kind, timestamp)
+        })
+}
+
+/// Whether `filename` is named like a backup the app writes; for a split
+/// hybrid backup only the first volume counts
+pub fn is_backup_filename(filename: &str) -> bool {
+    is_hybrid_backup_filename(filename) || plain_backup_kind(filename).is_some()
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Catalog entry for one file of the backup directory; None for files that
+/// are not backups. The bool is true for a hybrid backup without a readable manifest.
+fn describe_backup(path: &Path, seen_at: &str) -> Option<(CatalogEntry, bool)> {
+    let filename = path.file_name()?.to_str()?.to_string();
+    let mut entry = CatalogEntry {
+        filename: filename.clone(),
+        kind: BackupKind::Hybrid,
+        timestamp: modified_secs(path),
+        size_bytes: 0,
+        volumes: 1,
+        schema_version: None,
+        note: None,
+        checksum: None,
+        missing: false,
+        last_seen_at: seen_at.to_string(),
+    };
+
+    if is_hybrid_backup_filename(&filename) {
+        let volumes = backup_volumes::volume_set(path).unwrap_or_else(|_| vec![path.into()]);
+        entry.volumes = volumes.len();
+        entry.size_bytes = backup_volumes::set_size(&volumes);
+        let Ok(manifest) = read_backup_manifest(path) else {
+            return Some((entry, true));
+        };
+        entry.timestamp = manifest.timestamp;
+        entry.schema_version = manifest.schema_version;
+        entry.note = manifest.note;
+        entry.checksum = Some(manifest.checksum);
+        return Some((entry, false));
+    }
+
+    let (kind, timestamp) = plain_backup_kind(&filename)?;
+    entry.kind = kind;
+    entry.size_bytes = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    if let Some(timestamp) = timestamp {
+        entry.timestamp = timestamp;
+    }
+    Some((entry, false))
+}
+
+/// (filename, missing) of every catalog entry
+fn cataloged_filenames(conn: &Connection) -> Result<Vec<(String, bool)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT filename, missing FROM backup_catalog")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| format!("Failed to query backup catalog: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read backup catalog: {}", e))?;
+    Ok(rows)
+}
+
+/// Bring the catalog in line with `backup_dir`
+pub fn rescan_backup_directory_with_conn(
+    conn: &Connection,
+    backup_dir: &Path,
+) -> Result<CatalogRescan, String> {
+    let seen_at = chrono::Utc::now().to_rfc3339();
+    let mut found = Vec::new();
+    let mut report = CatalogRescan::default();
+    if backup_dir.exists() {
+        let entries = fs::read_dir(backup_dir)
+            .map_err(|e| format!("Failed to read backup directory: {}", e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read directory entry: {}", e))?
+                .path();
+            if !path.is_file() {
+                continue;
+            }
+            if let Some((entry, unreadable)) = describe_backup(&path, &seen_at) {
+                if unreadable {
+                    report.unreadable.push(entry.filename.clone());
+                }
+                found.push(entry);
+            }
+        }
+    }
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let known = cataloged_filenames(&tx)?;
+    let known_names: HashSet<&str> = known.iter().map(|(name, _)| name.as_str()).collect();
+    let found_names: HashSet<&str> = found.iter().map(|entry| entry.filename.as_str()).collect();
+
+    for entry in &found {
+        if known_names.contains(entry.filename.as_str()) {
+            report.refreshed += 1;
+        } else {
+            report.added.push(entry.filename.clone());
+        }
+        tx.execute(
+            "INSERT INTO backup_catalog (filename, kind, timestamp, size_bytes, volumes, schema_version, note, checksum, missing, last_seen_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, ?9)
+             ON CONFLICT(filename) DO UPDATE SET kind = ?2, timestamp = ?3, size_bytes = ?4, volumes = ?5,
+                 schema_version = ?6, note = ?7, checksum = ?8, missing = 0, last_seen_at = ?9",
+            params![
+                entry.filename,
+                kind_name(entry.kind),
+                entry.timestamp as i64,
+                entry.size_bytes as i64,
+                entry.volumes as i64,
+                entry.schema_version,
+                entry.note,
+                entry.checksum,
+                entry.last_seen_at
+            ],
+        )
+        .map_err(|e| format!("Failed to catalog backup {}: {}", entry.filename, e))?;
+    }
+
+    for (filename, was_missing) in &known {
+        if found_names.contains(filename.as_str()) || *was_missing {
+            continue;
+        }
+        tx.execute(
+            "UPDATE backup_catalog SET missing = 1 WHERE filename = ?",
+            params![filename],
+        )
+        .map_err(|e| format!("Failed to flag missing backup {}: {}", filename, e))?;
+        report.newly_missing.push(filename.clone());
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to commit backup catalog: {}", e))?;
+
+    report.added.sort();
+    report.newly_missing.sort();
+    report.unreadable.sort();
+    Ok(report)
+}
+
+/// Newest first, missing entries included
+pub fn list_backup_catalog_with_conn(conn: &Connection) -> Result<Vec<CatalogEntry>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT filename, kind, timestamp, size_bytes, volumes, schema_version, note, checksum, missing, last_seen_at
+             FROM backup_catalog ORDER BY timestamp DESC, filename",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(CatalogEntry {
+                filename: row.get(0)?,
+                kind: parse_kind(&row.get::<_, String>(1)?),
+                timestamp: row.get::<_, i64>(2)? as u64,
+                size_bytes: row.get::<_, i64>(3)? as u64,
+                volumes: row.get::<_, i64>(4)? as usize,
+                schema_version: row.get(5)?,
+                note: row.get(6)?,
+                checksum: row.get(7)?,
+                missing: row.get(8)?,
+                last_seen_at: row.get(9)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query backup catalog: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read backup catalog: {}", e))?;
+    Ok(entries)
+}
+
+pub fn rescan_backup_directory() -> Result<CatalogRescan, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    rescan_backup_directory_with_conn(&conn, &storage_paths::get_backup_dir()?)
+}
+
+pub fn list_backup_catalog() -> Result<Vec<CatalogEntry>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    list_backup_catalog_with_conn(&conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rescan_adds_and_flags_missing() {
+        let conn = create_app_db();
+        let dir = TempDir::new().expect("temp dir should be created");
+        fs::write(dir.path().join("database_backup_1700000000.json"), b"{}").unwrap();
+        fs::write(dir.path().join("database_universal_1700000100.db"), b"db").unwrap();
+        fs::write(
+            dir.path().join("hybrid_backup_1700000200.zip"),
+            b"not a zip",
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.txt"), b"x").unwrap();
+
+        let report = rescan_backup_directory_with_conn(&conn, dir.path()).unwrap();
+        assert_eq!(report.added.len(), 3);
+        assert_eq!(
+            report.unreadable,
+            vec!["hybrid_backup_1700000200.zip".to_string()]
+        );
+
+        fs::remove_file(dir.path().join("database_backup_1700000000.json")).unwrap();
+        let report = rescan_backup_directory_with_conn(&conn, dir.path()).unwrap();
+        assert!(report.added.is_empty());
+        assert_eq!(report.refreshed, 2);
+        assert_eq!(
+            report.newly_missing,
+            vec!["database_backup_1700000000.json".to_string()]
+        );
+        // Still missing, but no longer news
+        let report = rescan_backup_directory_with_conn(&conn, dir.path()).unwrap();
+        assert!(report.newly_missing.is_empty());
+
+        // The unreadable hybrid backup falls back to its file time, i.e. now
+        let catalog = list_backup_catalog_with_conn(&conn).unwrap();
+        let entries: Vec<_> = catalog
+            .iter()
+            .map(|entry| (entry.kind, entry.missing))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (BackupKind::Hybrid, false),
+                (BackupKind::Sqlite, false),
+                (BackupKind::Json, true),
+            ]
+        );
+        assert_eq!(catalog[1].timestamp, 1_700_000_100);
+    }
+}
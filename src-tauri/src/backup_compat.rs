@@ -0,0 +1,342 @@
+//! Pre-restore compatibility check for backup files
+//!
+//! Looks at the backup format version, the main database schema version and
+//! the tables present, and tells the UI whether the backup restores as-is,
+//! restores but gets upgraded by `apply_schema` afterwards, or cannot be
+//! restored by this build at all.
+
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::backup_manager;
+use crate::database::SCHEMA_VERSION;
+use crate::database_backup::DatabaseBackup;
+use crate::hybrid_backup;
+use crate::validation;
+
+/// Highest backup format major version this build can read
+const SUPPORTED_FORMAT_MAJOR: u32 = 1;
+
+/// Tables a restore cannot do without
+const REQUIRED_TABLES: &[&str] = &["users", "high_ranking_officers"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatibilityStatus {
+    Compatible,
+    NeedsMigration,
+    Unsupported,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupCompatibility {
+    pub filename: String,
+    /// "hybrid", "json" or "database"
+    pub backup_kind: String,
+    pub format_version: Option<String>,
+    pub schema_version: Option<i32>,
+    pub current_schema_version: i32,
+    pub missing_tables: Vec<String>,
+    pub status: CompatibilityStatus,
+    pub messages: Vec<String>,
+}
+
+/// Decide compatibility from what was read out of the backup
+/// `schema_version` is None when the backup predates schema versioning
+pub fn evaluate_compatibility(
+    format_version: Option<&str>,
+    schema_version: Option<i32>,
+    tables: &[String],
+) -> (CompatibilityStatus, Vec<String>, Vec<String>) {
+    let mut messages = Vec::new();
+    let mut unsupported = false;
+    let mut needs_migration = false;
+
+    if let Some(version) = format_version {
+        match version
+            .split('.')
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+        {
+            Some(major) if major <= SUPPORTED_FORMAT_MAJOR => {}
+            Some(_) => {
+                unsupported = true;
+                messages.push(format!(
+                    "Backup format {} is newer than this version of the application supports",
+                    version
+                ));
+            }
+            None => {
+                unsupported = true;
+                messages.push(format!("Unrecognized backup format version '{}'", version));
+            }
+        }
+    }
+
+    match schema_version {
+        Some(v) if v > SCHEMA_VERSION => {
+            unsupported = true;
+            messages.push(format!(
+                "Database schema {} is newer than the current schema {}",
+                v, SCHEMA_VERSION
+            ));
+        }
+        Some(v) if v < SCHEMA_VERSION => {
+            needs_migration = true;
+            messages.push(format!(
+                "Database schema {} will be upgraded to {} after restore",
+                v, SCHEMA_VERSION
+            ));
+        }
+        Some(_) => {}
+        None => {
+            needs_migration = true;
+            messages.push(
+                "Backup does not record a schema version; it will be upgraded after restore"
+                    .to_string(),
+            );
+        }
+    }
+
+    let missing_tables: Vec<String> = REQUIRED_TABLES
+        .iter()
+        .filter(|required| !tables.iter().any(|t| t == *required))
+        .map(|t| t.to_string())
+        .collect();
+    if !missing_tables.is_empty() {
+        unsupported = true;
+        messages.push(format!(
+            "Backup is missing required tables: {}",
+            missing_tables.join(", ")
+        ));
+    }
+
+    let status = if unsupported {
+        CompatibilityStatus::Unsupported
+    } else if needs_migration {
+        CompatibilityStatus::NeedsMigration
+    } else {
+        CompatibilityStatus::Compatible
+    };
+
+    (status, missing_tables, messages)
+}
+
+/// Schema version and table names of a database connection
+/// user_version 0 means the database was never stamped
+pub fn inspect_database_with_conn(conn: &Connection) -> Result<(Option<i32>, Vec<String>), String> {
+    let user_version: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to list tables: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read table name: {}", e))?;
+
+    let schema_version = if user_version > 0 {
+        Some(user_version)
+    } else {
+        None
+    };
+    Ok((schema_version, tables))
+}
+
+fn inspect_database_file(path: &Path) -> Result<(Option<i32>, Vec<String>), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open backup database: {}", e))?;
+    inspect_database_with_conn(&conn)
+}
+
+/// Format version, schema version and table names found in a backup
+type BackupContents = (Option<String>, Option<i32>, Vec<String>);
+
+/// Format version, schema version and tables of a hybrid zip backup
+fn inspect_hybrid_backup(path: &Path) -> Result<BackupContents, String> {
+    let manifest = hybrid_backup::read_backup_manifest(path)?;
+
+    // SQLite needs a real file, so stage the backed-up database next to the backups
+    let staged_db = path.with_extension("compat_check.db");
+    let inspected = hybrid_backup::extract_backup_database(path, &staged_db)
+        .and_then(|_| inspect_database_file(&staged_db));
+    let _ = fs::remove_file(&staged_db);
+    let (db_schema_version, tables) = inspected?;
+
+    // The stamped database is authoritative; the manifest covers unstamped copies
+    Ok((
+        Some(manifest.version),
+        db_schema_version.or(manifest.schema_version),
+        tables,
+    ))
+}
+
+fn inspect_json_backup(path: &Path) -> Result<BackupContents, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read backup file: {}", e))?;
+    let backup: DatabaseBackup = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse backup file: {}", e))?;
+
+    let tables = backup.tables.into_iter().map(|t| t.name).collect();
+    // JSON backups carry no schema stamp
+    Ok((Some(backup.version), None, tables))
+}
+
+pub fn check_backup_compatibility_at(path: &Path) -> Result<BackupCompatibility, String> {
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let extension = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(str::to_ascii_lowercase);
+    let (backup_kind, inspected) = match extension.as_deref() {
+        Some("zip") => ("hybrid", inspect_hybrid_backup(path)),
+        Some("json") => ("json", inspect_json_backup(path)),
+        Some("db") => (
+            "database",
+            inspect_database_file(path).map(|(schema, tables)| (None, schema, tables)),
+        ),
+        _ => {
+            return Ok(BackupCompatibility {
+                filename,
+                backup_kind: "unknown".to_string(),
+                format_version: None,
+                schema_version: None,
+                current_schema_version: SCHEMA_VERSION,
+                missing_tables: Vec::new(),
+                status: CompatibilityStatus::Unsupported,
+                messages: vec!["Unrecognized backup file type".to_string()],
+            })
+        }
+    };
+
+    // A backup that cannot be read is unsupported rather than a command error
+    let (format_version, schema_version, tables) = match inspected {
+        Ok(result) => result,
+        Err(e) => {
+            return Ok(BackupCompatibility {
+                filename,
+                backup_kind: backup_kind.to_string(),
+                format_version: None,
+                schema_version: None,
+                current_schema_version: SCHEMA_VERSION,
+                missing_tables: Vec::new(),
+                status: CompatibilityStatus::Unsupported,
+                messages: vec![e],
+            })
+        }
+    };
+
+    let (status, missing_tables, messages) =
+        evaluate_compatibility(format_version.as_deref(), schema_version, &tables);
+
+    Ok(BackupCompatibility {
+        filename,
+        backup_kind: backup_kind.to_string(),
+        format_version,
+        schema_version,
+        current_schema_version: SCHEMA_VERSION,
+        missing_tables,
+        status,
+        messages,
+    })
+}
+
+pub fn check_backup_compatibility(filename: &str) -> Result<BackupCompatibility, String> {
+    validation::file_name("filename", filename)?;
+
+    let backup_path = backup_manager::get_backup_directory()?.join(filename);
+    if !backup_path.exists() {
+        return Err(format!("Backup file not found: {}", filename));
+    }
+
+    check_backup_compatibility_at(&backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use tempfile::TempDir;
+
+    fn tables(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_current_backup_is_compatible() {
+        let (status, missing, _) = evaluate_compatibility(
+            Some("1.0"),
+            Some(SCHEMA_VERSION),
+            &tables(&["users", "high_ranking_officers", "activity_log"]),
+        );
+        assert_eq!(status, CompatibilityStatus::Compatible);
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn test_old_or_unstamped_schema_needs_migration() {
+        let all = tables(&["users", "high_ranking_officers"]);
+        assert_eq!(
+            evaluate_compatibility(Some("1.0"), Some(1), &all).0,
+            CompatibilityStatus::NeedsMigration
+        );
+        assert_eq!(
+            evaluate_compatibility(Some("1.0"), None, &all).0,
+            CompatibilityStatus::NeedsMigration
+        );
+    }
+
+    #[test]
+    fn test_unsupported_cases() {
+        let all = tables(&["users", "high_ranking_officers"]);
+        assert_eq!(
+            evaluate_compatibility(Some("2.0"), Some(SCHEMA_VERSION), &all).0,
+            CompatibilityStatus::Unsupported
+        );
+        assert_eq!(
+            evaluate_compatibility(Some("1.0"), Some(SCHEMA_VERSION + 1), &all).0,
+            CompatibilityStatus::Unsupported
+        );
+
+        let (status, missing, _) =
+            evaluate_compatibility(Some("1.0"), Some(SCHEMA_VERSION), &tables(&["users"]));
+        assert_eq!(status, CompatibilityStatus::Unsupported);
+        assert_eq!(missing, vec!["high_ranking_officers"]);
+    }
+
+    #[test]
+    fn test_database_file_is_inspected() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join("backup.db");
+        let conn = Connection::open(&path).expect("db should open");
+        apply_schema(&conn).expect("schema should apply");
+        drop(conn);
+
+        let report = check_backup_compatibility_at(&path).expect("check should succeed");
+
+        assert_eq!(report.backup_kind, "database");
+        assert_eq!(report.schema_version, Some(SCHEMA_VERSION));
+        assert_eq!(report.status, CompatibilityStatus::Compatible);
+    }
+
+    #[test]
+    fn test_unreadable_backup_is_unsupported() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join("broken.json");
+        fs::write(&path, b"not json").unwrap();
+
+        let report = check_backup_compatibility_at(&path).expect("check should succeed");
+
+        assert_eq!(report.status, CompatibilityStatus::Unsupported);
+        assert_eq!(report.messages.len(), 1);
+    }
+}
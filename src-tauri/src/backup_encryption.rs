@@ -0,0 +1,559 @@
+//! Password-protected hybrid backups
+//!
+//! An encrypted backup is a zip with two entries. `manifest.json` is the
+//! manifest of the backup inside, with `encryption` filled in, so listings
+//! and the catalog read it like any other backup. `payload.enc` is the
+//! complete hybrid backup zip encrypted with AES-256-GCM under a key derived
+//! from the password with Argon2id; salt and cost are stored in the
+//! manifest. The payload is sealed in `CHUNK_BYTES` pieces whose nonces are
+//! a random prefix, the piece number and a last-piece flag, so a payload
+//! that was cut short or reordered does not decrypt. The plain zip only
+//! exists inside a per-operation temp directory.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::backup_results::{BackupCreated, BackupKind, BackupRestored};
+use crate::disk_space;
+use crate::error_codes::{self, BACKUP_PASSWORD_REQUIRED};
+use crate::export_encryption::MIN_PASSPHRASE_LENGTH;
+use crate::hybrid_backup::{self, BackupManifest};
+use crate::logger;
+use crate::progress::ProgressReporter;
+use crate::storage_paths;
+use crate::temp_space::{self, TempSpace};
+
+pub const CIPHER: &str = "aes-256-gcm";
+pub const KDF: &str = "argon2id";
+
+const CHUNK_BYTES: u64 = 1024 * 1024;
+const TAG_BYTES: usize = 16;
+const KEY_BYTES: usize = 32;
+const SALT_BYTES: usize = 16;
+const NONCE_PREFIX_BYTES: usize = 7;
+/// Refuse manifests asking for more key derivation memory than this (KiB)
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+/// Refuse manifests asking for more key derivation passes than this
+const MAX_ITERATIONS: u32 = 64;
+
+const PAYLOAD_ENTRY: &str = "payload.enc";
+const PLAIN_BACKUP_NAME: &str = "hybrid_backup.zip";
+
+/// How the payload of an encrypted backup was sealed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupEncryption {
+    /// CIPHER
+    pub cipher: String,
+    /// KDF
+    pub kdf: String,
+    /// Hex
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Hex; each chunk's nonce adds its number and the last-chunk flag
+    pub nonce_prefix: String,
+    pub chunk_bytes: u64,
+    /// Size of the plain backup zip
+    pub plaintext_bytes: u64,
+}
+
+/// Key derivation cost for new backups: (memory KiB, iterations, lanes)
+#[cfg(not(test))]
+fn kdf_cost() -> (u32, u32, u32) {
+    (64 * 1024, 3, 1)
+}
+
+#[cfg(test)]
+fn kdf_cost() -> (u32, u32, u32) {
+    (Params::MIN_M_COST, Params::MIN_T_COST, 1)
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(format!(
+            "Backup password must be at least {} characters",
+            MIN_PASSPHRASE_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Error for an encrypted backup reached without (the right) password
+pub fn password_required(message: &str) -> String {
+    error_codes::with_code(BACKUP_PASSWORD_REQUIRED, message)
+}
+
+/// Refuse encrypted backups in code paths that read the zip directly
+pub fn ensure_not_encrypted(manifest: &BackupManifest) -> Result<(), String> {
+    if manifest.encryption.is_some() {
+        return Err(password_required(
+            "This backup is encrypted; restore it with its password",
+        ));
+    }
+    Ok(())
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to read random bytes: {}", e))?;
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str, expected_len: usize) -> Result<Vec<u8>, String> {
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>();
+    match bytes {
+        Some(bytes) if bytes.len() == expected_len => Ok(bytes),
+        _ => Err("Backup manifest has invalid encryption settings".to_string()),
+    }
+}
+
+fn new_encryption(plaintext_bytes: u64) -> Result<BackupEncryption, String> {
+    let (memory_kib, iterations, parallelism) = kdf_cost();
+    Ok(BackupEncryption {
+        cipher: CIPHER.to_string(),
+        kdf: KDF.to_string(),
+        salt: to_hex(&random_bytes(SALT_BYTES)?),
+        memory_kib,
+        iterations,
+        parallelism,
+        nonce_prefix: to_hex(&random_bytes(NONCE_PREFIX_BYTES)?),
+        chunk_bytes: CHUNK_BYTES,
+        plaintext_bytes,
+    })
+}
+
+fn cipher_for(password: &str, encryption: &BackupEncryption) -> Result<Aes256Gcm, String> {
+    if encryption.cipher != CIPHER || encryption.kdf != KDF {
+        return Err(format!(
+            "Unsupported backup encryption: {} with {}",
+            encryption.cipher, encryption.kdf
+        ));
+    }
+    if encryption.memory_kib > MAX_MEMORY_KIB
+        || encryption.iterations > MAX_ITERATIONS
+        || encryption.chunk_bytes == 0
+        || encryption.chunk_bytes > 64 * CHUNK_BYTES
+    {
+        return Err("Backup manifest has invalid encryption settings".to_string());
+    }
+    let params = Params::new(
+        encryption.memory_kib,
+        encryption.iterations,
+        encryption.parallelism,
+        Some(KEY_BYTES),
+    )
+    .map_err(|e| format!("Invalid key derivation settings: {}", e))?;
+    let salt = from_hex(&encryption.salt, SALT_BYTES)?;
+
+    let mut key = [0u8; KEY_BYTES];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Failed to derive backup key: {}", e))?;
+    Aes256Gcm::new_from_slice(&key).map_err(|e| format!("Invalid backup key: {}", e))
+}
+
+fn chunk_count(encryption: &BackupEncryption) -> Result<u64, String> {
+    let chunks = encryption
+        .plaintext_bytes
+        .div_ceil(encryption.chunk_bytes)
+        .max(1);
+    if chunks > u32::MAX as u64 {
+        return Err("Backup is too large to encrypt".to_string());
+    }
+    Ok(chunks)
+}
+
+/// Length of chunk `index` of `chunks`
+fn chunk_len(encryption: &BackupEncryption, index: u64, chunks: u64) -> usize {
+    if index + 1 == chunks {
+        (encryption.plaintext_bytes - index * encryption.chunk_bytes) as usize
+    } else {
+        encryption.chunk_bytes as usize
+    }
+}
+
+fn chunk_nonce(prefix: &[u8], index: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_BYTES].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_BYTES..11].copy_from_slice(&(index as u32).to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn encrypt_payload(
+    plain_path: &Path,
+    encryption: &BackupEncryption,
+    password: &str,
+    out: &mut impl Write,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let cipher = cipher_for(password, encryption)?;
+    let prefix = from_hex(&encryption.nonce_prefix, NONCE_PREFIX_BYTES)?;
+    let chunks = chunk_count(encryption)?;
+    let mut file =
+        fs::File::open(plain_path).map_err(|e| format!("Failed to open backup file: {}", e))?;
+    let mut buffer = vec![0u8; encryption.chunk_bytes as usize];
+
+    for index in 0..chunks {
+        progress.check_cancelled()?;
+        let len = chunk_len(encryption, index, chunks);
+        file.read_exact(&mut buffer[..len])
+            .map_err(|e| format!("Failed to read backup file: {}", e))?;
+        let nonce = chunk_nonce(&prefix, index, index + 1 == chunks);
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&nonce), &buffer[..len])
+            .map_err(|_| "Failed to encrypt backup".to_string())?;
+        out.write_all(&sealed)
+            .map_err(|e| format!("Failed to write encrypted backup: {}", e))?;
+        progress.report_bytes(
+            Some("encrypt"),
+            index + 1,
+            Some(chunks),
+            index * encryption.chunk_bytes + len as u64,
+            Some(encryption.plaintext_bytes),
+        );
+    }
+    Ok(())
+}
+
+fn decrypt_payload(
+    payload: &mut impl Read,
+    encryption: &BackupEncryption,
+    password: &str,
+    out: &mut impl Write,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let cipher = cipher_for(password, encryption)?;
+    let prefix = from_hex(&encryption.nonce_prefix, NONCE_PREFIX_BYTES)?;
+    let chunks = chunk_count(encryption)?;
+    let mut buffer = vec![0u8; encryption.chunk_bytes as usize + TAG_BYTES];
+
+    for index in 0..chunks {
+        progress.check_cancelled()?;
+        let len = chunk_len(encryption, index, chunks) + TAG_BYTES;
+        payload
+            .read_exact(&mut buffer[..len])
+            .map_err(|_| "Encrypted backup is incomplete".to_string())?;
+        let nonce = chunk_nonce(&prefix, index, index + 1 == chunks);
+        let plain = cipher
+            .decrypt(Nonce::from_slice(&nonce), &buffer[..len])
+            .map_err(|_| password_required("Wrong password or damaged backup"))?;
+        out.write_all(&plain)
+            .map_err(|e| format!("Failed to write decrypted backup: {}", e))?;
+        progress.report_bytes(
+            Some("decrypt"),
+            index + 1,
+            Some(chunks),
+            index * encryption.chunk_bytes + plain.len() as u64,
+            Some(encryption.plaintext_bytes),
+        );
+    }
+    if payload.read(&mut buffer[..1]).unwrap_or(0) != 0 {
+        return Err("Encrypted backup has unexpected trailing data".to_string());
+    }
+    Ok(())
+}
+
+fn write_encrypted_zip(
+    plain_path: &Path,
+    dest: &Path,
+    manifest: &BackupManifest,
+    encryption: &BackupEncryption,
+    password: &str,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let file =
+        fs::File::create(dest).map_err(|e| format!("Failed to create encrypted backup: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+
+    let manifest_json = serde_json::to_string_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    zip.start_file("manifest.json", FileOptions::default())
+        .map_err(|e| format!("Failed to start manifest file in zip: {}", e))?;
+    zip.write_all(manifest_json.as_bytes())
+        .map_err(|e| format!("Failed to write manifest to zip: {}", e))?;
+
+    // Ciphertext does not compress
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(encryption.plaintext_bytes > u32::MAX as u64 / 2);
+    zip.start_file(PAYLOAD_ENTRY, options)
+        .map_err(|e| format!("Failed to start payload in zip: {}", e))?;
+    encrypt_payload(plain_path, encryption, password, &mut zip, progress)?;
+    zip.finish()
+        .map_err(|e| format!("Failed to finish zip file: {}", e))?;
+    Ok(())
+}
+
+/// Write `plain_path`, a hybrid backup zip, to `dest` as an encrypted backup
+pub fn write_encrypted_backup(
+    plain_path: &Path,
+    dest: &Path,
+    password: &str,
+    progress: &ProgressReporter,
+) -> Result<BackupManifest, String> {
+    validate_password(password)?;
+    let mut manifest = hybrid_backup::read_backup_manifest(plain_path)?;
+    ensure_not_encrypted(&manifest)?;
+    let plaintext_bytes = fs::metadata(plain_path)
+        .map_err(|e| format!("Failed to get backup file size: {}", e))?
+        .len();
+    let encryption = new_encryption(plaintext_bytes)?;
+    manifest.encryption = Some(encryption.clone());
+
+    let written = write_encrypted_zip(plain_path, dest, &manifest, &encryption, password, progress);
+    if let Err(e) = written {
+        let _ = fs::remove_file(dest);
+        return Err(e);
+    }
+    Ok(manifest)
+}
+
+/// Decrypt the payload of the encrypted backup at `zip_path` into `dest`
+pub fn decrypt_backup_to(
+    zip_path: &Path,
+    password: &str,
+    dest: &Path,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let manifest = hybrid_backup::read_backup_manifest(zip_path)?;
+    let encryption = manifest
+        .encryption
+        .ok_or_else(|| "This backup is not encrypted".to_string())?;
+
+    let file =
+        fs::File::open(zip_path).map_err(|e| format!("Failed to open backup file: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+    let mut payload = archive
+        .by_name(PAYLOAD_ENTRY)
+        .map_err(|_| "Encrypted payload not found in backup".to_string())?;
+    let mut out =
+        fs::File::create(dest).map_err(|e| format!("Failed to create output file: {}", e))?;
+    decrypt_payload(&mut payload, &encryption, password, &mut out, progress)
+}
+
+/// Create a hybrid backup and store it only in encrypted form
+pub fn create_encrypted_backup_with_progress(
+    password: &str,
+    progress: &ProgressReporter,
+) -> Result<BackupCreated, String> {
+    validate_password(password)?;
+    // Removed with the plain zip in it when this function returns
+    let temp = TempSpace::create("encrypted-backup")?;
+    let plain = hybrid_backup::create_hybrid_backup_in(temp.path(), progress, None)?;
+    let plain_path = PathBuf::from(&plain.path);
+
+    let timestamp = hybrid_backup::read_backup_manifest(&plain_path)?.timestamp;
+    let backup_path =
+        storage_paths::get_backup_dir()?.join(format!("hybrid_backup_{}_encrypted.zip", timestamp));
+    disk_space::ensure_free_space(&backup_path, plain.size_bytes)?;
+
+    write_encrypted_backup(&plain_path, &backup_path, password, progress)?;
+    logger::info(format!(
+        "Encrypted hybrid backup created: {}",
+        backup_path.display()
+    ));
+
+    let mut created = BackupCreated::for_file(BackupKind::Hybrid, &backup_path)?;
+    created.database_bytes = plain.database_bytes;
+    created.media_bytes = plain.media_bytes;
+    created.file_count = plain.file_count;
+    created.warnings = plain.warnings;
+    Ok(created)
+}
+
+/// Decrypt the backup at `zip_path` to a temp directory and restore it
+pub fn restore_encrypted_backup_with_progress(
+    zip_path: &str,
+    password: &str,
+    progress: &ProgressReporter,
+) -> Result<BackupRestored, String> {
+    let path = Path::new(zip_path);
+    if !path.exists() {
+        return Err("Backup file does not exist".to_string());
+    }
+    let manifest = hybrid_backup::read_backup_manifest(path)?;
+    let plaintext_bytes = manifest
+        .encryption
+        .as_ref()
+        .map(|e| e.plaintext_bytes)
+        .ok_or_else(|| "This backup is not encrypted".to_string())?;
+    disk_space::ensure_free_space(&temp_space::get_temp_root()?, plaintext_bytes)?;
+
+    let temp = TempSpace::create("decrypt")?;
+    let plain_path = temp.path().join(PLAIN_BACKUP_NAME);
+    decrypt_backup_to(path, password, &plain_path, progress)?;
+
+    let mut restored =
+        hybrid_backup::import_backup_with_progress(&plain_path.to_string_lossy(), progress)?;
+    restored.source = zip_path.to_string();
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn plain_backup(dir: &Path) -> PathBuf {
+        let path = dir.join("hybrid_backup_1.zip");
+        let mut zip = ZipWriter::new(fs::File::create(&path).unwrap());
+        let manifest = BackupManifest {
+            version: "1.0".to_string(),
+            timestamp: 1,
+            database_size: 5,
+            media_size: 0,
+            total_files: 1,
+            backup_type: "hybrid".to_string(),
+            checksum: String::new(),
+            schema_version: None,
+            note: None,
+            encryption: None,
+        };
+        zip.start_file("manifest.json", FileOptions::default())
+            .unwrap();
+        zip.write_all(serde_json::to_string(&manifest).unwrap().as_bytes())
+            .unwrap();
+        zip.start_file("database.db", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"sqlite").unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    fn small_chunks(encryption: &mut BackupEncryption) {
+        encryption.chunk_bytes = 64;
+    }
+
+    #[test]
+    fn test_encrypted_backup_round_trip() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let plain = plain_backup(dir.path());
+        let encrypted = dir.path().join("hybrid_backup_1_encrypted.zip");
+
+        let manifest = write_encrypted_backup(
+            &plain,
+            &encrypted,
+            "correct horse",
+            &ProgressReporter::noop(),
+        )
+        .expect("backup should be encrypted");
+        assert_eq!(manifest.encryption.as_ref().unwrap().cipher, CIPHER);
+
+        // Listings still read the manifest, but the data is not readable
+        let listed = hybrid_backup::read_backup_manifest(&encrypted).unwrap();
+        assert!(listed.encryption.is_some());
+        assert_eq!(
+            error_codes::find_code(&ensure_not_encrypted(&listed).unwrap_err()),
+            Some(BACKUP_PASSWORD_REQUIRED)
+        );
+        let raw = fs::read(&encrypted).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"sqlite"));
+
+        let wrong = decrypt_backup_to(
+            &encrypted,
+            "wrong password",
+            &dir.path().join("wrong.zip"),
+            &ProgressReporter::noop(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error_codes::find_code(&wrong),
+            Some(BACKUP_PASSWORD_REQUIRED)
+        );
+
+        let decrypted = dir.path().join("decrypted.zip");
+        decrypt_backup_to(
+            &encrypted,
+            "correct horse",
+            &decrypted,
+            &ProgressReporter::noop(),
+        )
+        .expect("backup should decrypt");
+        assert_eq!(fs::read(&decrypted).unwrap(), fs::read(&plain).unwrap());
+
+        assert!(validate_password("short").is_err());
+    }
+
+    #[test]
+    fn test_costly_manifest_settings_are_refused() {
+        let mut encryption = new_encryption(0).unwrap();
+        encryption.iterations = MAX_ITERATIONS + 1;
+        assert!(cipher_for("password1", &encryption).is_err());
+
+        let mut encryption = new_encryption(0).unwrap();
+        encryption.memory_kib = MAX_MEMORY_KIB + 1;
+        assert!(cipher_for("password1", &encryption).is_err());
+    }
+
+    #[test]
+    fn test_truncated_or_reordered_payload_is_rejected() {
+        let data: Vec<u8> = (0..200u8).collect();
+        let mut encryption = new_encryption(data.len() as u64).unwrap();
+        small_chunks(&mut encryption);
+        let dir = TempDir::new().expect("temp dir should be created");
+        let plain = dir.path().join("plain.bin");
+        fs::write(&plain, &data).unwrap();
+
+        let mut sealed = Vec::new();
+        encrypt_payload(
+            &plain,
+            &encryption,
+            "password1",
+            &mut sealed,
+            &ProgressReporter::noop(),
+        )
+        .unwrap();
+        let decrypt = |payload: &[u8]| {
+            let mut out = Vec::new();
+            decrypt_payload(
+                &mut &payload[..],
+                &encryption,
+                "password1",
+                &mut out,
+                &ProgressReporter::noop(),
+            )
+            .map(|_| out)
+        };
+        assert_eq!(decrypt(&sealed).unwrap(), data);
+
+        // Dropping the last chunk: the new last chunk is not marked last
+        let chunk = 64 + TAG_BYTES;
+        let mut shortened = encryption.clone();
+        shortened.plaintext_bytes = 192;
+        let mut out = Vec::new();
+        assert!(decrypt_payload(
+            &mut &sealed[..3 * chunk],
+            &shortened,
+            "password1",
+            &mut out,
+            &ProgressReporter::noop(),
+        )
+        .is_err());
+
+        let mut swapped = sealed.clone();
+        swapped[..chunk].copy_from_slice(&sealed[chunk..2 * chunk]);
+        swapped[chunk..2 * chunk].copy_from_slice(&sealed[..chunk]);
+        assert!(decrypt(&swapped).is_err());
+        assert!(decrypt(&sealed[..sealed.len() - 1]).is_err());
+    }
+}
@@ -1,46 +1,188 @@
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
-use tauri::api::path::app_data_dir;
-use tauri::Config;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-// Copy backup files to custom location
+use crate::backup_results::BackupCopied;
+use crate::backup_volumes;
+use crate::long_path;
+use crate::validation;
+
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path)
+        .map_err(|e| format!("Failed to open {} for checksum: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let bytes_read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {} for checksum: {}", path.display(), e))?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Target file for `destination`: an existing directory (or a path ending in
+/// a separator) gets the backup's own name, numbered when already taken
+pub fn resolve_copy_destination(destination: &str, filename: &str) -> PathBuf {
+    let dest_path = PathBuf::from(destination);
+    if !(dest_path.is_dir() || destination.ends_with(['/', '\\'])) {
+        return dest_path;
+    }
+
+    let candidate = dest_path.join(filename);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let name = Path::new(filename);
+    let stem = name
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(filename);
+    let extension = name.extension().and_then(|e| e.to_str());
+    (2..)
+        .map(|n| match extension {
+            Some(ext) => dest_path.join(format!("{}_{}.{}", stem, n, ext)),
+            None => dest_path.join(format!("{}_{}", stem, n)),
+        })
+        .find(|path| !path.exists())
+        .unwrap_or(candidate)
+}
+
+fn copy_and_check(
+    source: &Path,
+    partial_path: &Path,
+    dest_path: &Path,
+) -> Result<(u64, String), String> {
+    fs::copy(source, partial_path).map_err(|e| format!("Failed to copy backup file: {}", e))?;
+    let source_size = fs::metadata(source)
+        .map_err(|e| format!("Failed to get backup file size: {}", e))?
+        .len();
+    let copy_size = fs::metadata(partial_path)
+        .map_err(|e| format!("Failed to get copied file size: {}", e))?
+        .len();
+    if source_size != copy_size {
+        return Err(format!(
+            "Copied file is {} bytes but the backup is {} bytes",
+            copy_size, source_size
+        ));
+    }
+    let source_hash = sha256_file(source)?;
+    let copy_hash = sha256_file(partial_path)?;
+    if source_hash != copy_hash {
+        return Err("Copied file does not match the backup (SHA-256 differs)".to_string());
+    }
+    fs::rename(partial_path, dest_path)
+        .map_err(|e| format!("Failed to move verified copy into place: {}", e))?;
+    Ok((copy_size, copy_hash))
+}
+
+/// Copy `source` to `dest_path` and confirm size and SHA-256 before the copy
+/// takes its final name; a failed check leaves nothing behind
+pub fn copy_verified(source: &Path, dest_path: &Path) -> Result<BackupCopied, String> {
+    let source_io = long_path::for_io(source);
+    let dest_io = long_path::for_io(dest_path);
+    if let Some(parent) = dest_io.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create destination directory: {}", e))?;
+    }
+
+    let mut partial_name = dest_io.as_os_str().to_os_string();
+    partial_name.push(".partial");
+    let partial_path = PathBuf::from(partial_name);
+
+    let (size_bytes, sha256) = match copy_and_check(&source_io, &partial_path, &dest_io) {
+        Ok(checked) => checked,
+        Err(e) => {
+            let _ = fs::remove_file(&partial_path);
+            return Err(e);
+        }
+    };
+
+    Ok(BackupCopied {
+        filename: dest_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        destination: dest_path.to_string_lossy().to_string(),
+        size_bytes,
+        sha256,
+    })
+}
+
+/// Copy a backup to a file or directory of the user's choosing, verified
 pub fn copy_backup_to_location(
     backup_filename: &str,
     destination_path: &str,
-) -> Result<String, String> {
-    let source_path = get_backup_directory()?.join(backup_filename);
+) -> Result<BackupCopied, String> {
+    let backup_filename = validation::file_name("backup_filename", backup_filename)?;
+    let source_path = get_backup_directory()?.join(&backup_filename);
 
     if !source_path.exists() {
         return Err(format!("Backup file not found: {}", backup_filename));
     }
 
-    let dest_path = PathBuf::from(destination_path);
-
-    // Create destination directory if it doesn't exist
-    if let Some(parent) = dest_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create destination directory: {}", e))?;
+    let volumes = backup_volumes::volume_set(&source_path)?;
+    let dest_path = resolve_copy_destination(destination_path, &backup_filename);
+    if dest_path == source_path {
+        return Err("Destination is the backup file itself".to_string());
     }
+    if backup_volumes::is_first_volume(&source_path) {
+        return copy_volume_set(&volumes, &dest_path);
+    }
+    copy_verified(&source_path, &dest_path)
+}
 
-    // Copy file
-    fs::copy(&source_path, &dest_path).map_err(|e| format!("Failed to copy backup file: {}", e))?;
-
-    Ok(format!("Backup copied to: {}", dest_path.display()))
+/// Copy every volume of a split backup, numbered after `dest_first` (the
+/// copy of the first volume); a failed volume removes the ones copied before
+pub fn copy_volume_set(volumes: &[PathBuf], dest_first: &Path) -> Result<BackupCopied, String> {
+    let dest_base =
+        backup_volumes::set_base(dest_first).unwrap_or_else(|| dest_first.to_path_buf());
+    let mut copies: Vec<BackupCopied> = Vec::new();
+    for (index, volume) in volumes.iter().enumerate() {
+        let dest = backup_volumes::volume_path(&dest_base, index + 1);
+        match copy_verified(volume, &dest) {
+            Ok(copy) => copies.push(copy),
+            Err(e) => {
+                for copy in &copies {
+                    let _ = fs::remove_file(long_path::for_io(Path::new(&copy.destination)));
+                }
+                return Err(e);
+            }
+        }
+    }
+    if copies.is_empty() {
+        return Err("Backup volume set is empty".to_string());
+    }
+    let mut first = copies.remove(0);
+    first.size_bytes += copies.iter().map(|copy| copy.size_bytes).sum::<u64>();
+    Ok(first)
 }
 
 // Get backup directory path
 pub fn get_backup_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    let backup_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
+    crate::storage_paths::get_backup_dir()
+}
 
-    if !backup_dir.exists() {
-        fs::create_dir_all(&backup_dir)
-            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+/// Name of `source` in the backup directory, copied in (verified, numbered
+/// when the name is taken) unless it is there already
+pub fn copy_into_backup_directory(source: &Path) -> Result<String, String> {
+    let backup_dir = get_backup_directory()?;
+    let filename = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid backup path: {}", source.display()))?;
+    if source.parent() == Some(backup_dir.as_path()) {
+        return Ok(filename.to_string());
     }
 
-    Ok(backup_dir)
+    fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+    let dest_path = resolve_copy_destination(&backup_dir.to_string_lossy(), filename);
+    Ok(copy_verified(source, &dest_path)?.filename)
 }
 
 // List all backup files with full paths
@@ -103,3 +245,81 @@ pub fn get_backup_file_info(backup_filename: &str) -> Result<(String, u64, Strin
         created_date,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_to_directory_is_verified_and_numbered() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let source = dir.path().join("hybrid_backup_1.zip");
+        fs::write(&source, b"backup-bytes").unwrap();
+        let usb = dir.path().join("usb");
+        fs::create_dir_all(&usb).unwrap();
+
+        let first = copy_verified(
+            &source,
+            &resolve_copy_destination(usb.to_str().unwrap(), "hybrid_backup_1.zip"),
+        )
+        .expect("copy should succeed");
+        assert_eq!(first.filename, "hybrid_backup_1.zip");
+        assert_eq!(first.size_bytes, 12);
+        assert_eq!(first.sha256, sha256_file(&source).unwrap());
+
+        let second = resolve_copy_destination(usb.to_str().unwrap(), "hybrid_backup_1.zip");
+        assert_eq!(second, usb.join("hybrid_backup_1_2.zip"));
+        // A plain file path is used as given
+        let named = usb.join("latest.zip");
+        assert_eq!(
+            resolve_copy_destination(named.to_str().unwrap(), "x.zip"),
+            named
+        );
+        assert!(!usb.join("hybrid_backup_1.zip.partial").exists());
+    }
+
+    #[test]
+    fn test_copy_into_thai_directories() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let source = dir.path().join("hybrid_backup_1.zip");
+        fs::write(&source, b"backup-bytes").unwrap();
+        let mut usb = dir.path().join("แฟลชไดรฟ์");
+        for _ in 0..6 {
+            usb = usb.join("สำรองข้อมูลกำลังพล");
+        }
+
+        let dest = resolve_copy_destination(usb.to_str().unwrap(), "สำรอง_มกราคม.zip");
+        let copied = copy_verified(&source, &dest).expect("copy should succeed");
+        assert_eq!(copied.filename, "สำรอง_มกราคม.zip");
+        assert_eq!(copied.destination, dest.to_string_lossy());
+        assert_eq!(fs::read(&dest).unwrap(), b"backup-bytes");
+    }
+
+    #[test]
+    fn test_split_backup_is_copied_volume_by_volume() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let base = dir.path().join("hybrid_backup_1.zip");
+        for (index, part) in [b"one", b"two"].iter().enumerate() {
+            fs::write(backup_volumes::volume_path(&base, index + 1), part).unwrap();
+        }
+        let volumes = backup_volumes::volume_set(&backup_volumes::volume_path(&base, 1)).unwrap();
+        let usb = dir.path().join("usb");
+        fs::create_dir_all(&usb).unwrap();
+
+        let dest = resolve_copy_destination(usb.to_str().unwrap(), "hybrid_backup_1.zip.001");
+        let copied = copy_volume_set(&volumes, &dest).expect("set should be copied");
+        assert_eq!(copied.filename, "hybrid_backup_1.zip.001");
+        assert_eq!(copied.size_bytes, 6);
+        assert_eq!(
+            fs::read(usb.join("hybrid_backup_1.zip.002")).unwrap(),
+            b"two"
+        );
+
+        // A named file becomes the base of the copied set
+        let named = usb.join("latest.zip");
+        copy_volume_set(&volumes, &named).expect("set should be copied");
+        assert_eq!(fs::read(usb.join("latest.zip.001")).unwrap(), b"one");
+        assert_eq!(fs::read(usb.join("latest.zip.002")).unwrap(), b"two");
+    }
+}
@@ -0,0 +1,592 @@
+//! Daily backup summary by e-mail (SMTP) or LINE (Messaging API push)
+//!
+//! Backup and restore commands record their outcome in the activity log.
+//! Once a day, after the configured hour, a background thread sums up the
+//! last 24 hours and sends it to the configured channel, so a failing backup
+//! is noticed before anyone needs it. A day without any backup counts as a
+//! problem too.
+//!
+//! Sending goes through the system `curl`, which speaks both SMTP with TLS
+//! and HTTPS. The password or channel access token comes from the keyring
+//! and is handed to curl on stdin, never on its command line.
+
+use chrono::{Local, Timelike};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::activity_log::{self, EVENT_BACKUP_RESULT, EVENT_BACKUP_SUMMARY_SENT};
+use crate::backup_results::{BackupCreated, BackupKind, BackupRestored};
+use crate::database::get_connection_safe;
+use crate::logger;
+use crate::settings::{
+    self, LineSettings, NotificationChannel, NotificationSettings, SmtpSettings,
+    LINE_CHANNEL_TOKEN_SECRET_KEY,
+};
+use crate::temp_space::TempSpace;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const SUMMARY_WINDOW_HOURS: i64 = 24;
+const LINE_PUSH_URL: &str = "https://api.line.me/v2/bot/message/push";
+/// Longest text message the Messaging API accepts, in characters
+const LINE_MAX_TEXT_CHARS: usize = 5000;
+const SEND_TIMEOUT_SECS: u32 = 30;
+const SUBJECT: &str = "PQS RTN backup summary";
+
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupOperation {
+    Backup,
+    Restore,
+}
+
+/// Details of an `EVENT_BACKUP_RESULT` activity log entry
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupResultEvent {
+    pub operation: BackupOperation,
+    pub kind: Option<BackupKind>,
+    /// Backup file written or restored from; empty when a backup failed early
+    pub target: String,
+    pub size_bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BackupSummary {
+    /// Start of the summarized window (UTC, as stored in the activity log)
+    pub since: String,
+    pub backups_succeeded: usize,
+    pub backups_failed: usize,
+    pub restores_succeeded: usize,
+    pub restores_failed: usize,
+    /// One line per failed run
+    pub failures: Vec<String>,
+    pub latest_backup: Option<String>,
+}
+
+impl BackupSummary {
+    /// Something failed, or nothing was backed up at all
+    pub fn needs_attention(&self) -> bool {
+        self.backups_failed > 0 || self.restores_failed > 0 || self.backups_succeeded == 0
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{} since {} UTC\nBackups: {} succeeded, {} failed\nRestores: {} succeeded, {} failed\n",
+            SUBJECT,
+            self.since,
+            self.backups_succeeded,
+            self.backups_failed,
+            self.restores_succeeded,
+            self.restores_failed
+        );
+        match &self.latest_backup {
+            Some(latest) => text.push_str(&format!("Latest backup: {}\n", latest)),
+            None => text.push_str("No backup was made in this period\n"),
+        }
+        if !self.failures.is_empty() {
+            text.push_str("Failures:\n");
+            for failure in &self.failures {
+                text.push_str(&format!("- {}\n", failure));
+            }
+        }
+        text
+    }
+}
+
+fn record(event: &BackupResultEvent) {
+    let details = serde_json::to_string(event).unwrap_or_default();
+    activity_log::record_event(EVENT_BACKUP_RESULT, None, None, Some(&details));
+}
+
+/// Record a backup command's outcome for the daily summary and pass it on
+pub fn record_backup(
+    kind: BackupKind,
+    result: Result<BackupCreated, String>,
+) -> Result<BackupCreated, String> {
+    record(&match &result {
+        Ok(created) => BackupResultEvent {
+            operation: BackupOperation::Backup,
+            kind: Some(created.kind),
+            target: created.filename.clone(),
+            size_bytes: Some(created.size_bytes),
+            error: None,
+        },
+        Err(e) => BackupResultEvent {
+            operation: BackupOperation::Backup,
+            kind: Some(kind),
+            target: String::new(),
+            size_bytes: None,
+            error: Some(e.clone()),
+        },
+    });
+    result
+}
+
+/// Record a restore command's outcome for the daily summary and pass it on
+pub fn record_restore(
+    source: &str,
+    result: Result<BackupRestored, String>,
+) -> Result<BackupRestored, String> {
+    record(&match &result {
+        Ok(restored) => BackupResultEvent {
+            operation: BackupOperation::Restore,
+            kind: Some(restored.kind),
+            target: restored.source.clone(),
+            size_bytes: None,
+            error: None,
+        },
+        Err(e) => BackupResultEvent {
+            operation: BackupOperation::Restore,
+            kind: None,
+            target: source.to_string(),
+            size_bytes: None,
+            error: Some(e.clone()),
+        },
+    });
+    result
+}
+
+/// Summary of the results recorded at or after `since` ("YYYY-MM-DD HH:MM:SS", UTC)
+pub fn summarize_since_with_conn(conn: &Connection, since: &str) -> Result<BackupSummary, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT details, created_at FROM activity_log
+             WHERE event_type = ?1 AND created_at >= ?2
+             ORDER BY created_at, id",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let rows = stmt
+        .query_map(params![EVENT_BACKUP_RESULT, since], |row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| format!("Failed to query backup results: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read backup result: {}", e))?;
+
+    let mut summary = BackupSummary {
+        since: since.to_string(),
+        ..Default::default()
+    };
+    for (details, created_at) in rows {
+        let Some(event) = details.and_then(|d| serde_json::from_str::<BackupResultEvent>(&d).ok())
+        else {
+            continue;
+        };
+        match (event.operation, &event.error) {
+            (BackupOperation::Backup, None) => {
+                summary.backups_succeeded += 1;
+                summary.latest_backup = Some(format!("{} ({} UTC)", event.target, created_at));
+            }
+            (BackupOperation::Restore, None) => summary.restores_succeeded += 1,
+            (operation, Some(error)) => {
+                if operation == BackupOperation::Backup {
+                    summary.backups_failed += 1;
+                } else {
+                    summary.restores_failed += 1;
+                }
+                let what = match (operation, event.kind) {
+                    (BackupOperation::Backup, Some(kind)) => format!("{:?} backup", kind),
+                    (BackupOperation::Backup, None) => "backup".to_string(),
+                    (BackupOperation::Restore, _) => format!("restore of {}", event.target),
+                };
+                summary
+                    .failures
+                    .push(format!("{} UTC {}: {}", created_at, what, error));
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Summary of the last 24 hours
+pub fn summarize_last_day() -> Result<BackupSummary, String> {
+    let since = (chrono::Utc::now() - chrono::Duration::hours(SUMMARY_WINDOW_HOURS))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    summarize_since_with_conn(&conn, &since)
+}
+
+/// Quoted value for a curl config file, with the escapes curl understands
+pub fn curl_config_value(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// curl config for pushing `message` to `line.to` with the Messaging API
+pub fn line_config(token: &str, line: &LineSettings, message: &str) -> String {
+    let text: String = message.chars().take(LINE_MAX_TEXT_CHARS).collect();
+    let body = serde_json::json!({
+        "to": line.to,
+        "messages": [{ "type": "text", "text": text }],
+    });
+    format!(
+        "url = {}\nheader = {}\nheader = {}\ndata-binary = {}\nmax-time = {}\n",
+        curl_config_value(LINE_PUSH_URL),
+        curl_config_value(&format!("Authorization: Bearer {}", token)),
+        curl_config_value("Content-Type: application/json"),
+        curl_config_value(&body.to_string()),
+        SEND_TIMEOUT_SECS
+    )
+}
+
+/// curl config for sending the message file at `message_path` over SMTP
+pub fn smtp_config(smtp: &SmtpSettings, password: Option<&str>, message_path: &Path) -> String {
+    let scheme = if smtp.port == 465 { "smtps" } else { "smtp" };
+    let mut config = format!(
+        "url = {}\nssl-reqd\nmail-from = {}\n",
+        curl_config_value(&format!("{}://{}:{}", scheme, smtp.host, smtp.port)),
+        curl_config_value(&smtp.from)
+    );
+    for recipient in &smtp.recipients {
+        config.push_str(&format!("mail-rcpt = {}\n", curl_config_value(recipient)));
+    }
+    if !smtp.username.is_empty() {
+        config.push_str(&format!(
+            "user = {}\n",
+            curl_config_value(&format!("{}:{}", smtp.username, password.unwrap_or("")))
+        ));
+    }
+    config.push_str(&format!(
+        "upload-file = {}\nmax-time = {}\n",
+        curl_config_value(&message_path.to_string_lossy()),
+        SEND_TIMEOUT_SECS
+    ));
+    config
+}
+
+/// Plain-text UTF-8 e-mail with CRLF line endings
+pub fn email_message(smtp: &SmtpSettings, subject: &str, body: &str) -> String {
+    let body = body.replace("\r\n", "\n").replace('\n', "\r\n");
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+        smtp.from,
+        smtp.recipients.join(", "),
+        subject,
+        Local::now().to_rfc2822(),
+        body
+    )
+}
+
+/// Run curl with `config` on stdin
+fn run_curl(config: &str) -> Result<(), String> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                "curl is not installed; notifications cannot be sent".to_string()
+            }
+            _ => format!("Failed to run curl: {}", e),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(config.as_bytes())
+            .map_err(|e| format!("Failed to pass settings to curl: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for curl: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Sending notification failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn send_notification(
+    notifications: &NotificationSettings,
+    subject: &str,
+    body: &str,
+) -> Result<(), String> {
+    match notifications.channel {
+        NotificationChannel::Line => {
+            let line = notifications
+                .line
+                .as_ref()
+                .ok_or("LINE destination is not configured")?;
+            let token = settings::load_secret(LINE_CHANNEL_TOKEN_SECRET_KEY)?
+                .ok_or("No LINE channel access token stored in keyring")?;
+            run_curl(&line_config(
+                &token,
+                line,
+                &format!("{}\n{}", subject, body),
+            ))
+        }
+        NotificationChannel::Smtp => {
+            let smtp = notifications
+                .smtp
+                .as_ref()
+                .ok_or("SMTP server is not configured")?;
+            let password = if smtp.username.is_empty() {
+                None
+            } else {
+                settings::load_secret(&smtp.secret_key())?
+            };
+            let temp = TempSpace::create("notify")?;
+            let message_path = temp.path().join("message.eml");
+            fs::write(&message_path, email_message(smtp, subject, body))
+                .map_err(|e| format!("Failed to write e-mail message: {}", e))?;
+            run_curl(&smtp_config(smtp, password.as_deref(), &message_path))
+        }
+    }
+}
+
+fn load_notification_settings() -> Result<NotificationSettings, String> {
+    settings::load_settings()?
+        .notifications
+        .ok_or_else(|| "Backup notifications are not configured".to_string())
+}
+
+pub fn get_notification_settings() -> Result<Option<NotificationSettings>, String> {
+    Ok(settings::load_settings()?.notifications)
+}
+
+/// Save notification settings (None turns them off); the secret is the SMTP
+/// password or LINE channel access token and goes to the keyring
+pub fn save_notification_settings(
+    notifications: Option<NotificationSettings>,
+    secret: Option<String>,
+) -> Result<(), String> {
+    if let Some(n) = &notifications {
+        if n.send_hour > 23 {
+            return Err("Send hour must be between 0 and 23".to_string());
+        }
+        let secret = secret.filter(|s| !s.is_empty());
+        match n.channel {
+            NotificationChannel::Smtp => {
+                let smtp = n.smtp.as_ref().ok_or("SMTP server is not configured")?;
+                if smtp.host.trim().is_empty()
+                    || smtp.from.trim().is_empty()
+                    || smtp.recipients.iter().all(|r| r.trim().is_empty())
+                {
+                    return Err(
+                        "SMTP host, sender and at least one recipient are required".to_string()
+                    );
+                }
+                if let Some(secret) = secret {
+                    settings::store_secret(&smtp.secret_key(), &secret)?;
+                }
+            }
+            NotificationChannel::Line => {
+                let line = n
+                    .line
+                    .as_ref()
+                    .ok_or("LINE destination is not configured")?;
+                if line.to.trim().is_empty() {
+                    return Err("A LINE user, group or room id is required".to_string());
+                }
+                if let Some(secret) = secret {
+                    settings::store_secret(LINE_CHANNEL_TOKEN_SECRET_KEY, &secret)?;
+                }
+            }
+        }
+    }
+
+    settings::update_settings(|s| s.notifications = notifications)?;
+    Ok(())
+}
+
+pub fn send_test_notification() -> Result<String, String> {
+    let notifications = load_notification_settings()?;
+    send_notification(
+        &notifications,
+        SUBJECT,
+        "Test message: backup notifications are working.",
+    )?;
+    Ok(match notifications.channel {
+        NotificationChannel::Line => "Test message sent to LINE".to_string(),
+        NotificationChannel::Smtp => "Test e-mail sent".to_string(),
+    })
+}
+
+/// Send the last 24 hours' summary now, whatever `only_on_failure` says
+pub fn send_backup_summary() -> Result<BackupSummary, String> {
+    let notifications = load_notification_settings()?;
+    let summary = summarize_last_day()?;
+    send_notification(&notifications, SUBJECT, &summary.to_text())?;
+    Ok(summary)
+}
+
+/// Whether today's summary is due at `hour` (local) given the day it was last sent
+pub fn is_summary_due(send_hour: u8, hour: u32, last_sent: Option<&str>, today: &str) -> bool {
+    hour >= send_hour as u32 && last_sent != Some(today)
+}
+
+fn last_summary_date_with_conn(conn: &Connection) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT details FROM activity_log WHERE event_type = ?1 ORDER BY id DESC LIMIT 1",
+        params![EVENT_BACKUP_SUMMARY_SENT],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(|date| date.flatten())
+    .map_err(|e| format!("Failed to read last backup summary: {}", e))
+}
+
+fn send_scheduled_summary() -> Result<(), String> {
+    let Some(notifications) = settings::load_settings()?.notifications else {
+        return Ok(());
+    };
+    let now = Local::now();
+    let today = now.format("%Y-%m-%d").to_string();
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let last_sent = last_summary_date_with_conn(&conn)?;
+    if !is_summary_due(
+        notifications.send_hour,
+        now.hour(),
+        last_sent.as_deref(),
+        &today,
+    ) {
+        return Ok(());
+    }
+
+    let summary = summarize_last_day()?;
+    if !notifications.only_on_failure || summary.needs_attention() {
+        send_notification(&notifications, SUBJECT, &summary.to_text())?;
+        logger::info("Backup summary notification sent");
+    }
+    // Marked even when skipped so the day is only evaluated once
+    activity_log::record_event_with_conn(&conn, EVENT_BACKUP_SUMMARY_SENT, None, None, Some(&today))
+}
+
+/// Start the daily summary thread; later calls are no-ops
+pub fn start_notification_scheduler() {
+    if SCHEDULER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    thread::spawn(|| loop {
+        thread::sleep(CHECK_INTERVAL);
+        if let Err(e) = send_scheduled_summary() {
+            logger::warn(format!("Backup summary notification failed: {}", e));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn insert_result(conn: &Connection, event: &BackupResultEvent, at: &str) {
+        conn.execute(
+            "INSERT INTO activity_log (event_type, details, created_at) VALUES (?, ?, ?)",
+            params![
+                EVENT_BACKUP_RESULT,
+                serde_json::to_string(event).unwrap(),
+                at
+            ],
+        )
+        .expect("event insert should succeed");
+    }
+
+    #[test]
+    fn test_summary_counts_results_in_window() {
+        let conn = create_app_db();
+
+        let ok = BackupResultEvent {
+            operation: BackupOperation::Backup,
+            kind: Some(BackupKind::Hybrid),
+            target: "hybrid_backup_1.zip".to_string(),
+            size_bytes: Some(10),
+            error: None,
+        };
+        insert_result(&conn, &ok, "2026-01-01 01:00:00");
+        insert_result(&conn, &ok, "2026-01-02 01:00:00");
+        insert_result(
+            &conn,
+            &BackupResultEvent {
+                kind: Some(BackupKind::Json),
+                target: String::new(),
+                size_bytes: None,
+                error: Some("disk full".to_string()),
+                ..ok.clone()
+            },
+            "2026-01-02 02:00:00",
+        );
+
+        let summary = summarize_since_with_conn(&conn, "2026-01-02 00:00:00")
+            .expect("summary should succeed");
+        assert_eq!(summary.backups_succeeded, 1);
+        assert_eq!(summary.backups_failed, 1);
+        assert_eq!(
+            summary.failures,
+            vec!["2026-01-02 02:00:00 UTC Json backup: disk full".to_string()]
+        );
+        assert!(summary.needs_attention());
+        assert!(summary
+            .to_text()
+            .contains("Latest backup: hybrid_backup_1.zip"));
+
+        let empty = summarize_since_with_conn(&conn, "2026-02-01 00:00:00").unwrap();
+        assert!(empty.needs_attention());
+        assert!(empty.to_text().contains("No backup was made"));
+    }
+
+    #[test]
+    fn test_curl_config_escapes_values() {
+        assert_eq!(curl_config_value("a\"b\\c\nd"), r#""a\"b\\c\nd""#);
+
+        let smtp = SmtpSettings {
+            host: "mail.local".to_string(),
+            port: 465,
+            username: "pqs".to_string(),
+            from: "pqs@mail.local".to_string(),
+            recipients: vec!["a@mail.local".to_string(), "b@mail.local".to_string()],
+        };
+        let config = smtp_config(&smtp, Some("p\"w"), Path::new("/tmp/m.eml"));
+        assert!(config.contains("url = \"smtps://mail.local:465\"\n"));
+        assert!(config.contains("user = \"pqs:p\\\"w\"\n"));
+        assert_eq!(config.matches("mail-rcpt").count(), 2);
+
+        let line = LineSettings {
+            to: "U1234".to_string(),
+        };
+        let config = line_config("tok", &line, "ผ่าน \"ok\"");
+        assert!(config.contains("url = \"https://api.line.me/v2/bot/message/push\"\n"));
+        assert!(config.contains("Authorization: Bearer tok"));
+        assert!(config.contains(r#"\"to\":\"U1234\""#));
+        let long = line_config("tok", &line, &"x".repeat(LINE_MAX_TEXT_CHARS + 10));
+        assert!(long.contains(&"x".repeat(LINE_MAX_TEXT_CHARS)));
+        assert!(!long.contains(&"x".repeat(LINE_MAX_TEXT_CHARS + 1)));
+        assert!(email_message(&smtp, "S", "one\ntwo").contains("one\r\ntwo"));
+    }
+
+    #[test]
+    fn test_summary_due_once_a_day_after_hour() {
+        assert!(!is_summary_due(7, 6, None, "2026-01-02"));
+        assert!(is_summary_due(7, 7, Some("2026-01-01"), "2026-01-02"));
+        assert!(!is_summary_due(7, 9, Some("2026-01-02"), "2026-01-02"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Structured results of the backup, restore and delete commands
+//!
+//! These used to be English sentences ("✅ Database restored… Found 3
+//! users"). The UI now builds its own (localized) message from the fields
+//! and can act on them, e.g. reload after a restore or list warnings.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// `database_backup_<ts>.json`, every table as JSON rows
+    Json,
+    /// `database_universal_<ts>.db`, a copy of the database file
+    Sqlite,
+    /// `database_standard_<ts>.sql`
+    SqlDump,
+    /// `hybrid_backup_<ts>.zip`, database plus media
+    Hybrid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupCreated {
+    pub kind: BackupKind,
+    pub filename: String,
+    pub path: String,
+    /// Size of the backup file itself
+    pub size_bytes: u64,
+    pub database_bytes: Option<u64>,
+    pub media_bytes: Option<u64>,
+    /// Files stored in a hybrid backup (database + media)
+    pub file_count: Option<u64>,
+    pub user_count: Option<usize>,
+    pub warnings: Vec<String>,
+    /// Volume filenames of a split backup, first to last; empty otherwise
+    pub volumes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupRestored {
+    pub kind: BackupKind,
+    /// Backup filename or path the data came from
+    pub source: String,
+    pub user_count: i64,
+    pub officer_count: i64,
+    pub media_files: Option<u64>,
+    /// Copy of the replaced database kept next to it
+    pub previous_database: Option<String>,
+    /// The UI must reload; cached data refers to the replaced database
+    pub requires_reload: bool,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupDeleted {
+    pub filename: String,
+    pub freed_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupCopied {
+    pub filename: String,
+    /// Full path of the verified copy; the first volume of a split backup
+    pub destination: String,
+    /// Of all volumes together for a split backup
+    pub size_bytes: u64,
+    /// SHA-256 of the copy, equal to the source's; every volume of a split
+    /// backup is checked, this is the first one's
+    pub sha256: String,
+}
+
+impl BackupCreated {
+    pub fn for_file(kind: BackupKind, path: &Path) -> Result<Self, String> {
+        let size_bytes = path
+            .metadata()
+            .map_err(|e| format!("Failed to get backup file size: {}", e))?
+            .len();
+        Ok(BackupCreated {
+            kind,
+            filename: file_name(path),
+            path: path.to_string_lossy().to_string(),
+            size_bytes,
+            database_bytes: None,
+            media_bytes: None,
+            file_count: None,
+            user_count: None,
+            warnings: Vec::new(),
+            volumes: Vec::new(),
+        })
+    }
+}
+
+impl BackupRestored {
+    /// Result for a restored database, with its user and officer counts
+    pub fn from_database(
+        kind: BackupKind,
+        source: &str,
+        conn: &Connection,
+    ) -> Result<Self, String> {
+        let count = |table: &str| -> Result<i64, String> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to verify restored database: {}", e))
+        };
+        Ok(BackupRestored {
+            kind,
+            source: source.to_string(),
+            user_count: count("users")?,
+            officer_count: count("high_ranking_officers").unwrap_or(0),
+            media_files: None,
+            previous_database: None,
+            requires_reload: true,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl BackupDeleted {
+    /// Remove `path`, remembering its size
+    pub fn delete(path: &Path) -> Result<Self, String> {
+        let freed_bytes = path.metadata().map(|m| m.len()).unwrap_or(0);
+        std::fs::remove_file(path).map_err(|e| format!("Failed to delete backup file: {}", e))?;
+        Ok(BackupDeleted {
+            filename: file_name(path),
+            freed_bytes,
+        })
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_results_serialize_for_the_ui() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join("database_universal_1.db");
+        std::fs::write(&path, b"12345").unwrap();
+
+        let created =
+            BackupCreated::for_file(BackupKind::Sqlite, &path).expect("result should build");
+        let json = serde_json::to_value(&created).unwrap();
+        assert_eq!(json["kind"], "sqlite");
+        assert_eq!(json["filename"], "database_universal_1.db");
+        assert_eq!(json["size_bytes"], 5);
+
+        let deleted = BackupDeleted::delete(&path).expect("delete should succeed");
+        assert_eq!(deleted.freed_bytes, 5);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_restored_counts_rows() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('u', 'u@test.com', 'h', 'U')",
+            [],
+        )
+        .unwrap();
+
+        let restored = BackupRestored::from_database(BackupKind::Json, "backup.json", &conn)
+            .expect("result should build");
+        assert_eq!((restored.user_count, restored.officer_count), (1, 0));
+        assert!(restored.requires_reload);
+    }
+}
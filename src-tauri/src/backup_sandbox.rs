@@ -0,0 +1,522 @@
+//! Open a backup in an isolated sandbox to look inside it
+//!
+//! `open_backup_sandbox` extracts a hybrid zip (database plus media) or a raw
+//! database backup into its own `TempSpace` under `<workspace>/temp`. The
+//! sandbox database is only ever opened read-only, and the query commands
+//! take a sandbox id, so nothing here can reach the live database or media.
+//! A sandbox directory is removed once `close_backup_sandbox` dropped it and
+//! no query holds it any more; if the app exits without closing them, at the
+//! next startup.
+
+use base64::{engine::general_purpose, Engine as _};
+use lazy_static::lazy_static;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use walkdir::WalkDir;
+
+use crate::backup_manager;
+use crate::backup_volumes;
+use crate::hybrid_backup;
+use crate::logger;
+use crate::media_maintenance::normalize_media_path;
+use crate::safe_path;
+use crate::temp_space::{self, TempSpace};
+use crate::validation;
+
+/// `TempSpace` kind, and so the name prefix, of sandbox directories
+const SANDBOX_KIND: &str = "backup-sandbox";
+/// Sandbox root under the OS temp folder used by earlier versions
+const LEGACY_SANDBOX_DIR_NAME: &str = "pqs-rtn-backup-sandbox";
+const SANDBOX_DB_FILENAME: &str = "database.db";
+const SANDBOX_MEDIA_DIR: &str = "media";
+/// Rows returned per query page at most
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+lazy_static! {
+    static ref SANDBOXES: Mutex<HashMap<String, Sandbox>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    pub id: String,
+    pub filename: String,
+    pub dir: PathBuf,
+    pub opened_at: String,
+    /// Removes `dir` when the last clone goes
+    _space: Arc<TempSpace>,
+}
+
+impl Sandbox {
+    fn db_path(&self) -> PathBuf {
+        self.dir.join(SANDBOX_DB_FILENAME)
+    }
+
+    /// Only exists when the backup carried media files
+    pub fn media_dir(&self) -> PathBuf {
+        self.dir.join(SANDBOX_MEDIA_DIR)
+    }
+
+    pub fn connection(&self) -> Result<Connection, String> {
+        Connection::open_with_flags(self.db_path(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Failed to open sandbox database: {}", e))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SandboxTable {
+    pub name: String,
+    pub row_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SandboxInfo {
+    pub sandbox_id: String,
+    pub filename: String,
+    pub opened_at: String,
+    /// PRAGMA user_version of the backed-up database; None when never stamped
+    pub schema_version: Option<i32>,
+    pub tables: Vec<SandboxTable>,
+    pub media_files: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SandboxRows {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    pub total_rows: i64,
+}
+
+fn extract_hybrid_backup(zip_path: &Path, dir: &Path) -> Result<(), String> {
+    let mut archive = hybrid_backup::open_backup_archive(zip_path)?;
+
+    let mut found_database = false;
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read zip entry: {}", e))?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = normalize_media_path(entry.name());
+        let target = if name == SANDBOX_DB_FILENAME {
+            found_database = true;
+            dir.join(SANDBOX_DB_FILENAME)
+        } else if let Some(media_path) = name.strip_prefix("media/") {
+            match safe_path::normalize_relative(media_path) {
+                Ok(relative) => dir.join(SANDBOX_MEDIA_DIR).join(relative),
+                Err(_) => {
+                    logger::warn(format!("Skipping unsafe backup entry: {}", entry.name()));
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create sandbox directory: {}", e))?;
+        }
+        let mut out = fs::File::create(&target)
+            .map_err(|e| format!("Failed to create sandbox file: {}", e))?;
+        std::io::copy(&mut entry, &mut out)
+            .map_err(|e| format!("Failed to extract {}: {}", name, e))?;
+    }
+
+    if !found_database {
+        return Err("Database file not found in backup".to_string());
+    }
+    Ok(())
+}
+
+/// Extract `backup_path` into a fresh sandbox directory under `root`
+pub fn create_sandbox_in(root: &Path, backup_path: &Path) -> Result<Sandbox, String> {
+    let filename = backup_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let space = TempSpace::create_in(root, SANDBOX_KIND)?;
+    let dir = space.path().to_path_buf();
+    let id = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let extension = if backup_volumes::is_first_volume(backup_path) {
+        Some("zip")
+    } else {
+        backup_path.extension().and_then(|s| s.to_str())
+    };
+    let extracted = match extension {
+        Some("zip") => extract_hybrid_backup(backup_path, &dir),
+        Some("db") => fs::copy(backup_path, dir.join(SANDBOX_DB_FILENAME))
+            .map(|_| ())
+            .map_err(|e| format!("Failed to copy backup database: {}", e)),
+        _ => Err("Only hybrid (.zip) and database (.db) backups can be opened".to_string()),
+    };
+    extracted?;
+
+    Ok(Sandbox {
+        id,
+        filename,
+        dir,
+        opened_at: chrono::Utc::now().to_rfc3339(),
+        _space: Arc::new(space),
+    })
+}
+
+/// Every table of `conn` by name with its row count
+pub fn table_row_counts(conn: &Connection) -> Result<Vec<SandboxTable>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to list tables: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read table name: {}", e))?;
+
+    let mut tables = Vec::with_capacity(names.len());
+    for name in names {
+        let row_count = conn
+            .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to count rows in {}: {}", name, e))?;
+        tables.push(SandboxTable { name, row_count });
+    }
+    Ok(tables)
+}
+
+pub fn describe_sandbox(sandbox: &Sandbox) -> Result<SandboxInfo, String> {
+    let conn = sandbox.connection()?;
+
+    let user_version: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+    let tables = table_row_counts(&conn)?;
+
+    let media_files = WalkDir::new(sandbox.media_dir())
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .count();
+
+    Ok(SandboxInfo {
+        sandbox_id: sandbox.id.clone(),
+        filename: sandbox.filename.clone(),
+        opened_at: sandbox.opened_at.clone(),
+        schema_version: (user_version > 0).then_some(user_version),
+        tables,
+        media_files,
+    })
+}
+
+fn value_to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(v) => Value::from(v),
+        ValueRef::Real(v) => Value::from(v),
+        ValueRef::Text(v) => Value::from(String::from_utf8_lossy(v).to_string()),
+        // Leftover BLOB avatars are not worth shipping to the UI
+        ValueRef::Blob(v) => Value::from(format!("<{} bytes>", v.len())),
+    }
+}
+
+/// One page of a table; the name must be a table of the sandbox database
+pub fn query_table_with_conn(
+    conn: &Connection,
+    table: &str,
+    limit: u32,
+    offset: u32,
+) -> Result<SandboxRows, String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [table],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to look up table: {}", e))?;
+    if !exists {
+        return Err(format!("Table not found in backup: {}", table));
+    }
+
+    let total_rows = conn
+        .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Failed to count rows: {}", e))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT * FROM \"{}\" ORDER BY rowid LIMIT ? OFFSET ?",
+            table
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|n| n.to_string()).collect();
+    let column_count = columns.len();
+
+    let rows = stmt
+        .query_map([limit.min(MAX_PAGE_SIZE), offset], |row| {
+            (0..column_count)
+                .map(|i| row.get_ref(i).map(value_to_json))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .map_err(|e| format!("Failed to query {}: {}", table, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read row: {}", e))?;
+
+    Ok(SandboxRows {
+        table: table.to_string(),
+        columns,
+        rows,
+        total_rows,
+    })
+}
+
+/// Media file of the sandbox as a data URL
+pub fn read_media_data_url(media_dir: &Path, relative_path: &str) -> Result<String, String> {
+    let path = media_dir.join(safe_path::normalize_relative(relative_path)?);
+
+    let mut data = Vec::new();
+    fs::File::open(&path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|_| format!("Media file not found in backup: {}", relative_path))?;
+
+    let mime_type = match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    };
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        general_purpose::STANDARD.encode(&data)
+    ))
+}
+
+/// Sandboxes sit with the other temp dirs, on the workspace volume
+pub fn sandbox_root() -> Result<PathBuf, String> {
+    temp_space::get_temp_root()
+}
+
+fn get_sandbox(sandbox_id: &str) -> Result<Sandbox, String> {
+    SANDBOXES
+        .lock()
+        .map_err(|e| format!("Failed to acquire sandbox lock: {}", e))?
+        .get(sandbox_id)
+        .cloned()
+        .ok_or_else(|| format!("Sandbox not found: {}", sandbox_id))
+}
+
+pub fn open_backup_sandbox(filename: &str) -> Result<SandboxInfo, String> {
+    validation::file_name("filename", filename)?;
+
+    let backup_path = backup_manager::get_backup_directory()?.join(filename);
+    if !backup_path.exists() {
+        return Err(format!("Backup file not found: {}", filename));
+    }
+
+    let sandbox = create_sandbox_in(&sandbox_root()?, &backup_path)?;
+    let info = describe_sandbox(&sandbox)?;
+
+    logger::info(format!(
+        "Opened backup {} in sandbox {}",
+        filename, sandbox.id
+    ));
+    SANDBOXES
+        .lock()
+        .map_err(|e| format!("Failed to acquire sandbox lock: {}", e))?
+        .insert(sandbox.id.clone(), sandbox);
+
+    Ok(info)
+}
+
+pub fn list_backup_sandboxes() -> Result<Vec<SandboxInfo>, String> {
+    let sandboxes: Vec<Sandbox> = SANDBOXES
+        .lock()
+        .map_err(|e| format!("Failed to acquire sandbox lock: {}", e))?
+        .values()
+        .cloned()
+        .collect();
+
+    let mut infos = sandboxes
+        .iter()
+        .map(describe_sandbox)
+        .collect::<Result<Vec<_>, _>>()?;
+    infos.sort_by(|a, b| a.opened_at.cmp(&b.opened_at));
+    Ok(infos)
+}
+
+pub fn sandbox_query_table(
+    sandbox_id: &str,
+    table: &str,
+    limit: u32,
+    offset: u32,
+) -> Result<SandboxRows, String> {
+    let conn = get_sandbox(sandbox_id)?.connection()?;
+    query_table_with_conn(&conn, table, limit, offset)
+}
+
+pub fn sandbox_read_media(sandbox_id: &str, relative_path: &str) -> Result<String, String> {
+    read_media_data_url(&get_sandbox(sandbox_id)?.media_dir(), relative_path)
+}
+
+/// Returns false when no sandbox with this id is open
+pub fn close_backup_sandbox(sandbox_id: &str) -> Result<bool, String> {
+    let removed = SANDBOXES
+        .lock()
+        .map_err(|e| format!("Failed to acquire sandbox lock: {}", e))?
+        .remove(sandbox_id);
+    Ok(removed.is_some())
+}
+
+/// Remove the sandboxes under `root` and the legacy root; only for startup,
+/// before any sandbox of this run is open
+pub fn cleanup_stale_sandboxes_in(root: &Path, legacy_root: &Path) -> usize {
+    let mut removed = 0;
+    let stale = fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(SANDBOX_KIND))
+        })
+        .chain(legacy_root.exists().then(|| legacy_root.to_path_buf()));
+    for path in stale {
+        match fs::remove_dir_all(&path) {
+            Ok(()) => removed += 1,
+            Err(e) => logger::warn(format!(
+                "Failed to clean backup sandbox {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+    removed
+}
+
+/// Remove sandboxes left behind by a previous run
+pub fn cleanup_stale_sandboxes() {
+    match sandbox_root() {
+        Ok(root) => {
+            cleanup_stale_sandboxes_in(&root, &std::env::temp_dir().join(LEGACY_SANDBOX_DIR_NAME));
+        }
+        Err(e) => logger::warn(format!("Failed to clean backup sandboxes: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+
+    fn write_backup_zip(dir: &Path) -> PathBuf {
+        let db_path = dir.join("source.db");
+        let conn = Connection::open(&db_path).expect("db should open");
+        apply_schema(&conn).expect("schema should apply");
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, avatar_path) VALUES ('old', 'old@test.com', 'h', 'Old User', 'avatars\\old.png')",
+            [],
+        )
+        .expect("user insert should succeed");
+        drop(conn);
+
+        let zip_path = dir.join("hybrid_backup_1.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = FileOptions::default();
+        zip.start_file("database.db", options).unwrap();
+        zip.write_all(&fs::read(&db_path).unwrap()).unwrap();
+        zip.start_file("media/avatars\\old.png", options).unwrap();
+        zip.write_all(b"png").unwrap();
+        zip.start_file("media/../escape.txt", options).unwrap();
+        zip.write_all(b"nope").unwrap();
+        zip.finish().unwrap();
+        zip_path
+    }
+
+    #[test]
+    fn test_sandbox_exposes_backup_contents() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = write_backup_zip(dir.path());
+        let root = dir.path().join("sandboxes");
+
+        let sandbox = create_sandbox_in(&root, &zip_path).expect("sandbox should open");
+        let info = describe_sandbox(&sandbox).expect("describe should work");
+
+        assert_eq!(info.media_files, 1);
+        assert!(!root.join("escape.txt").exists() && !dir.path().join("escape.txt").exists());
+        let users = info.tables.iter().find(|t| t.name == "users").unwrap();
+        assert_eq!(users.row_count, 1);
+
+        let conn = sandbox.connection().unwrap();
+        let page = query_table_with_conn(&conn, "users", 10, 0).expect("query should work");
+        let username = page.columns.iter().position(|c| c == "username").unwrap();
+        assert_eq!(page.rows[0][username], Value::from("old"));
+        // Read-only: the sandbox can never be modified through its connection
+        assert!(conn.execute("DELETE FROM users", []).is_err());
+
+        let photo = read_media_data_url(&sandbox.media_dir(), "avatars\\old.png")
+            .expect("media should be readable");
+        assert!(photo.starts_with("data:image/png;base64,"));
+    }
+
+    #[test]
+    fn test_sandbox_rejects_unknown_tables_and_paths() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = write_backup_zip(dir.path());
+        let sandbox = create_sandbox_in(&dir.path().join("sandboxes"), &zip_path).expect("sandbox");
+        let conn = sandbox.connection().unwrap();
+
+        assert!(query_table_with_conn(&conn, "users; DROP TABLE users", 10, 0).is_err());
+        assert!(read_media_data_url(&sandbox.media_dir(), "../database.db").is_err());
+    }
+
+    #[test]
+    fn test_sandbox_directory_goes_with_the_last_handle() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = write_backup_zip(dir.path());
+        let root = dir.path().join("temp");
+
+        let sandbox = create_sandbox_in(&root, &zip_path).expect("sandbox should open");
+        assert!(sandbox.id.starts_with(SANDBOX_KIND));
+        let query_handle = sandbox.clone();
+        drop(sandbox);
+        assert!(query_handle.dir.exists());
+        drop(query_handle);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+
+        // Leftovers of a crashed run, next to unrelated temp dirs
+        fs::create_dir_all(root.join("backup-sandbox-1-2-3/media")).unwrap();
+        fs::create_dir_all(root.join("import-1-2-3")).unwrap();
+        let legacy = dir.path().join(LEGACY_SANDBOX_DIR_NAME);
+        fs::create_dir_all(legacy.join("sandbox-1-0")).unwrap();
+        assert_eq!(cleanup_stale_sandboxes_in(&root, &legacy), 2);
+        assert!(root.join("import-1-2-3").exists());
+        assert!(!legacy.exists());
+    }
+}
@@ -0,0 +1,362 @@
+//! Hybrid backups split into fixed-size volumes
+//!
+//! FAT32 USB sticks cannot hold a file of 4 GB or more, so a large hybrid
+//! backup can be cut into `hybrid_backup_<ts>.zip.001`, `.002`, ... of at most
+//! the chosen size each. The volumes are plain byte ranges of the zip; they
+//! only need to sit next to each other again. `VolumeReader` presents a set
+//! (or an ordinary single-file backup) as one seekable stream, so manifests
+//! are read and restores extracted without joining the volumes on disk first.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::disk_space;
+
+pub const FIRST_VOLUME_SUFFIX: &str = ".001";
+/// Largest file FAT32 can store
+pub const FAT32_MAX_FILE_SIZE: u64 = 4 * 1024 * 1024 * 1024 - 1;
+pub const MIN_VOLUME_SIZE: u64 = 1024 * 1024;
+/// Three-digit suffixes
+const MAX_VOLUMES: usize = 999;
+
+/// `<base>.001`, `<base>.002`, ... for 1-based `index`
+pub fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut name = base.as_os_str().to_os_string();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+pub fn is_first_volume(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(FIRST_VOLUME_SUFFIX)
+}
+
+/// `<base>` of a first volume `<base>.001`
+pub fn set_base(path: &Path) -> Option<PathBuf> {
+    let full = path.to_string_lossy();
+    full.strip_suffix(FIRST_VOLUME_SUFFIX).map(PathBuf::from)
+}
+
+/// Highest volume number next to the set `<base>.NNN`, whether or not the
+/// volumes before it are there
+fn last_volume_index(base: &Path) -> usize {
+    let (Some(dir), Some(name)) = (base.parent(), base.file_name()) else {
+        return 0;
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let suffix = name.strip_prefix(&prefix)?;
+            if suffix.len() != 3 {
+                return None;
+            }
+            suffix.parse::<usize>().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Files making up the backup at `path`: the whole set when `path` is a first
+/// volume, otherwise just `path`
+pub fn volume_set(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let Some(base) = set_base(path) else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+
+    let volumes: Vec<PathBuf> = (1..=MAX_VOLUMES)
+        .map(|index| volume_path(&base, index))
+        .take_while(|volume| volume.is_file())
+        .collect();
+    if volumes.is_empty() {
+        return Err("Backup file does not exist".to_string());
+    }
+    // A gap means a volume was not copied along with the others
+    if last_volume_index(&base) > volumes.len() {
+        return Err(format!(
+            "Backup volume {:03} is missing; copy all volumes into one folder",
+            volumes.len() + 1
+        ));
+    }
+    Ok(volumes)
+}
+
+pub fn validate_volume_size(max_volume_size: u64) -> Result<(), String> {
+    if !(MIN_VOLUME_SIZE..=FAT32_MAX_FILE_SIZE).contains(&max_volume_size) {
+        return Err(format!(
+            "Volume size must be between {} MB and {} MB",
+            MIN_VOLUME_SIZE / 1024 / 1024,
+            FAT32_MAX_FILE_SIZE / 1024 / 1024
+        ));
+    }
+    Ok(())
+}
+
+/// Cut `zip_path` into volumes of at most `max_volume_size` bytes and remove
+/// it. A file that already fits is left as it is.
+pub fn split_into_volumes(zip_path: &Path, max_volume_size: u64) -> Result<Vec<PathBuf>, String> {
+    validate_volume_size(max_volume_size)?;
+    let total = zip_path
+        .metadata()
+        .map_err(|e| format!("Failed to get backup file size: {}", e))?
+        .len();
+    if total <= max_volume_size {
+        return Ok(vec![zip_path.to_path_buf()]);
+    }
+    let count = total.div_ceil(max_volume_size) as usize;
+    if count > MAX_VOLUMES {
+        return Err(format!(
+            "A volume size of {} bytes would need more than {} volumes",
+            max_volume_size, MAX_VOLUMES
+        ));
+    }
+    disk_space::ensure_free_space(zip_path, total)?;
+
+    let mut written = Vec::new();
+    if let Err(e) = write_volumes(zip_path, max_volume_size, count, &mut written) {
+        for volume in &written {
+            let _ = fs::remove_file(volume);
+        }
+        return Err(e);
+    }
+    fs::remove_file(zip_path).map_err(|e| format!("Failed to remove unsplit backup: {}", e))?;
+    Ok(written)
+}
+
+fn write_volumes(
+    zip_path: &Path,
+    max_volume_size: u64,
+    count: usize,
+    written: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let mut source =
+        fs::File::open(zip_path).map_err(|e| format!("Failed to open backup file: {}", e))?;
+    for index in 1..=count {
+        let volume = volume_path(zip_path, index);
+        let mut out = fs::File::create(&volume)
+            .map_err(|e| format!("Failed to create backup volume: {}", e))?;
+        written.push(volume);
+        io::copy(&mut (&mut source).take(max_volume_size), &mut out)
+            .map_err(|e| format!("Failed to write backup volume: {}", e))?;
+        out.sync_all()
+            .map_err(|e| format!("Failed to write backup volume: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Put the rewritten backup `zip_path` in place of the set `volumes`, cut to
+/// the size of the set's first volume; old volumes beyond the new count go
+pub fn replace_volume_set(zip_path: &Path, volumes: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let first = volumes.first().ok_or("Backup volume set is empty")?;
+    let base = set_base(first)
+        .ok_or_else(|| format!("{} is not the first volume of a set", first.display()))?;
+    let volume_size = disk_space::file_size(first).max(MIN_VOLUME_SIZE);
+
+    let written = split_into_volumes(zip_path, volume_size)?;
+    let mut replaced = Vec::new();
+    for (index, volume) in written.iter().enumerate() {
+        let target = volume_path(&base, index + 1);
+        fs::rename(volume, &target)
+            .map_err(|e| format!("Failed to replace backup volume: {}", e))?;
+        replaced.push(target);
+    }
+    for old in volumes.iter().skip(replaced.len()) {
+        fs::remove_file(old).map_err(|e| format!("Failed to remove old backup volume: {}", e))?;
+    }
+    Ok(replaced)
+}
+
+/// Total size of the files in a set
+pub fn set_size(volumes: &[PathBuf]) -> u64 {
+    volumes
+        .iter()
+        .map(|volume| disk_space::file_size(volume))
+        .sum()
+}
+
+/// A volume set read as one continuous file
+pub struct VolumeReader {
+    /// Path, offset of its first byte and length of each volume
+    volumes: Vec<(PathBuf, u64, u64)>,
+    total: u64,
+    position: u64,
+    /// Index and handle of the volume last read, positioned after that read
+    current: Option<(usize, fs::File)>,
+    current_position: u64,
+}
+
+impl VolumeReader {
+    /// `path` may be a single backup file or the first volume of a set
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut volumes = Vec::new();
+        let mut total = 0;
+        for volume in volume_set(path)? {
+            let len = volume
+                .metadata()
+                .map_err(|e| format!("Failed to open backup file: {}", e))?
+                .len();
+            volumes.push((volume, total, len));
+            total += len;
+        }
+        Ok(VolumeReader {
+            volumes,
+            total,
+            position: 0,
+            current: None,
+            current_position: 0,
+        })
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.total || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self
+            .volumes
+            .iter()
+            .position(|(_, start, len)| self.position < start + len)
+            .unwrap_or(self.volumes.len() - 1);
+        let (path, start, len) = &self.volumes[index];
+        let offset = self.position - start;
+
+        let reuse = matches!(&self.current, Some((open, _)) if *open == index)
+            && self.current_position == self.position;
+        if !reuse {
+            let mut file = fs::File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            self.current = Some((index, file));
+        }
+        let Some((_, file)) = self.current.as_mut() else {
+            return Ok(0);
+        };
+
+        let wanted = (len - offset).min(buf.len() as u64) as usize;
+        let read = file.read(&mut buf[..wanted])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Backup volume {} is shorter than expected", path.display()),
+            ));
+        }
+        self.position += read as u64;
+        self.current_position = self.position;
+        Ok(read)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.total.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        let Some(target) = target else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the backup",
+            ));
+        };
+        self.position = target;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_volumes_read_back_as_one_file() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = dir.path().join("hybrid_backup_1.zip");
+        let data: Vec<u8> = (0..(MIN_VOLUME_SIZE * 2 + 100))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&zip_path, &data).unwrap();
+
+        let volumes = split_into_volumes(&zip_path, MIN_VOLUME_SIZE).expect("split should work");
+        assert_eq!(volumes.len(), 3);
+        assert!(!zip_path.exists());
+        assert_eq!(disk_space::file_size(&volumes[2]), 100);
+        assert_eq!(set_size(&volumes), data.len() as u64);
+
+        let mut reader = VolumeReader::open(&volumes[0]).expect("set should open");
+        assert_eq!(reader.volumes.len(), 3);
+        let mut joined = Vec::new();
+        reader.read_to_end(&mut joined).unwrap();
+        assert_eq!(joined, data);
+
+        // Reads across a volume boundary after seeking
+        let boundary = MIN_VOLUME_SIZE - 2;
+        reader.seek(SeekFrom::Start(boundary)).unwrap();
+        let mut window = [0u8; 4];
+        reader.read_exact(&mut window).unwrap();
+        assert_eq!(&window[..], &data[boundary as usize..boundary as usize + 4]);
+        assert_eq!(
+            reader.seek(SeekFrom::End(-1)).unwrap(),
+            data.len() as u64 - 1
+        );
+
+        fs::remove_file(&volumes[1]).unwrap();
+        assert!(VolumeReader::open(&volumes[0]).is_err());
+    }
+
+    #[test]
+    fn test_small_backup_is_not_split() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = dir.path().join("hybrid_backup_1.zip");
+        fs::write(&zip_path, b"small").unwrap();
+
+        let volumes = split_into_volumes(&zip_path, MIN_VOLUME_SIZE).unwrap();
+        assert_eq!(volumes, vec![zip_path.clone()]);
+        assert!(split_into_volumes(&zip_path, 10).is_err());
+        assert_eq!(volume_set(&zip_path).unwrap(), vec![zip_path]);
+    }
+
+    #[test]
+    fn test_any_missing_volume_is_reported() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let base = dir.path().join("hybrid_backup_1.zip");
+        for index in [1, 2, 5] {
+            fs::write(volume_path(&base, index), b"part").unwrap();
+        }
+        let error = volume_set(&volume_path(&base, 1)).expect_err("gap should be found");
+        assert!(error.contains("003"));
+
+        fs::write(volume_path(&base, 3), b"part").unwrap();
+        fs::write(volume_path(&base, 4), b"part").unwrap();
+        assert_eq!(volume_set(&volume_path(&base, 1)).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_replaced_set_keeps_its_volume_size() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = dir.path().join("hybrid_backup_1.zip");
+        fs::write(&zip_path, vec![1u8; (MIN_VOLUME_SIZE * 2 + 10) as usize]).unwrap();
+        let volumes = split_into_volumes(&zip_path, MIN_VOLUME_SIZE).unwrap();
+        assert_eq!(volumes.len(), 3);
+
+        let rewritten = dir.path().join("hybrid_backup_1.zip.tmp");
+        let data: Vec<u8> = (0..MIN_VOLUME_SIZE + 5).map(|i| (i % 7) as u8).collect();
+        fs::write(&rewritten, &data).unwrap();
+        let replaced = replace_volume_set(&rewritten, &volumes).expect("set should be replaced");
+
+        assert_eq!(replaced, volumes[..2].to_vec());
+        assert!(!volumes[2].exists());
+        assert!(!rewritten.exists());
+        let mut joined = Vec::new();
+        VolumeReader::open(&volumes[0])
+            .unwrap()
+            .read_to_end(&mut joined)
+            .unwrap();
+        assert_eq!(joined, data);
+    }
+}
@@ -0,0 +1,700 @@
+//! Row-level change events for incremental sync between installations
+//!
+//! Triggers on the synced tables append (op, table, pk, time) to
+//! `change_log`. `export_changes_since` collapses the events after a point in
+//! time to the latest state of each row and writes them as NDJSON: a header
+//! line with the time range, then one `upsert` (with the full row) or
+//! `delete` line per changed row, in the order the rows last changed.
+//! `apply_changeset` replays such a file on another installation, using
+//! `updated_at` to detect rows that changed on both sides.
+
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::database::get_connection_safe;
+use crate::logger;
+use crate::storage_paths;
+
+/// Format version written in the changeset header
+pub const CHANGESET_VERSION: u32 = 1;
+
+/// (table, primary key column) pairs recorded in the change log
+const TRACKED_TABLES: &[(&str, &str)] = &[
+    ("users", "id"),
+    ("high_ranking_officers", "id"),
+    ("user_preferences", "user_id"),
+    ("saved_views", "id"),
+];
+
+/// Current time in unix milliseconds, in SQL
+const NOW_MS_SQL: &str = "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChangesetInfo {
+    pub filename: String,
+    pub path: String,
+    /// Unix milliseconds; changes after `since` up to and including `until`
+    pub since: i64,
+    pub until: i64,
+    pub changes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ChangeEvent {
+    op: String,
+    table: String,
+    pk: i64,
+    changed_at: i64,
+}
+
+pub fn init_change_log_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS change_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            op TEXT NOT NULL,
+            table_name TEXT NOT NULL,
+            pk INTEGER NOT NULL,
+            changed_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create change_log table: {}", e))?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_change_log_changed_at ON change_log(changed_at)",
+        [],
+    )
+    .map_err(|e| format!("Failed to create change_log index: {}", e))?;
+
+    for (table, pk) in TRACKED_TABLES {
+        for (op, event, row) in [
+            ("insert", "INSERT", "NEW"),
+            ("update", "UPDATE", "NEW"),
+            ("delete", "DELETE", "OLD"),
+        ] {
+            conn.execute_batch(&format!(
+                "CREATE TRIGGER IF NOT EXISTS trg_{table}_{op}_change_log AFTER {event} ON {table}
+                 BEGIN
+                    INSERT INTO change_log (op, table_name, pk, changed_at)
+                    VALUES ('{op}', '{table}', {row}.{pk}, {NOW_MS_SQL});
+                 END;"
+            ))
+            .map_err(|e| format!("Failed to create change trigger on {}: {}", table, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A column value as JSON; blobs as base64
+pub fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(t) => Value::String(String::from_utf8_lossy(t).to_string()),
+        ValueRef::Blob(b) => Value::String(general_purpose::STANDARD.encode(b)),
+    }
+}
+
+/// Whether changes to `table` are recorded in change_log
+pub fn is_tracked_table(table: &str) -> bool {
+    pk_column(table).is_ok()
+}
+
+fn pk_column(table: &str) -> Result<&'static str, String> {
+    TRACKED_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, pk)| *pk)
+        .ok_or_else(|| format!("Table is not tracked: {}", table))
+}
+
+/// Current row as a JSON object; None when it no longer exists
+pub fn current_row(conn: &Connection, table: &str, pk: i64) -> Result<Option<Value>, String> {
+    let pk_column = pk_column(table)?;
+    let mut stmt = conn
+        .prepare(&format!("SELECT * FROM {} WHERE {} = ?", table, pk_column))
+        .map_err(|e| format!("Failed to prepare row query: {}", e))?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+    let mut rows = stmt
+        .query(params![pk])
+        .map_err(|e| format!("Failed to query {}: {}", table, e))?;
+    let row = match rows
+        .next()
+        .map_err(|e| format!("Failed to read {}: {}", table, e))?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let mut object = serde_json::Map::new();
+    for (i, name) in column_names.iter().enumerate() {
+        let value = row
+            .get_ref(i)
+            .map_err(|e| format!("Failed to read column {}: {}", name, e))?;
+        object.insert(name.clone(), json_value(value));
+    }
+    Ok(Some(Value::Object(object)))
+}
+
+/// Latest event per row after `since`, ordered by when each row last changed
+fn collapsed_changes_since(conn: &Connection, since: i64) -> Result<Vec<ChangeEvent>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT op, table_name, pk, changed_at FROM change_log WHERE changed_at > ? ORDER BY id",
+        )
+        .map_err(|e| format!("Failed to prepare change query: {}", e))?;
+    let events = stmt
+        .query_map(params![since], |row| {
+            Ok(ChangeEvent {
+                op: row.get(0)?,
+                table: row.get(1)?,
+                pk: row.get(2)?,
+                changed_at: row.get(3)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query changes: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read changes: {}", e))?;
+
+    let mut last_index: HashMap<(String, i64), usize> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        last_index.insert((event.table.clone(), event.pk), index);
+    }
+
+    Ok(events
+        .into_iter()
+        .enumerate()
+        .filter(|(index, event)| last_index.get(&(event.table.clone(), event.pk)) == Some(index))
+        .map(|(_, event)| event)
+        .collect())
+}
+
+/// NDJSON changeset and the number of row changes in it
+pub fn build_changeset_with_conn(
+    conn: &Connection,
+    since: i64,
+    until: i64,
+) -> Result<(String, usize), String> {
+    let events = collapsed_changes_since(conn, since)?;
+
+    let mut lines = vec![json!({
+        "type": "header",
+        "changeset_version": CHANGESET_VERSION,
+        "since": since,
+        "until": until,
+    })
+    .to_string()];
+
+    for event in &events {
+        let row = if event.op == "delete" {
+            None
+        } else {
+            current_row(conn, &event.table, event.pk)?
+        };
+        // An insert/update whose row has since vanished without a logged delete
+        // (e.g. the table was rebuilt) is sent as a delete
+        let op = if row.is_some() { "upsert" } else { "delete" };
+        lines.push(
+            json!({
+                "type": "change",
+                "op": op,
+                "table": event.table,
+                "pk": event.pk,
+                "changed_at": event.changed_at,
+                "row": row,
+            })
+            .to_string(),
+        );
+    }
+
+    Ok((lines.join("\n") + "\n", events.len()))
+}
+
+/// Write the changes after `since` (unix milliseconds) to the export directory
+pub fn export_changes_since(since: i64) -> Result<ChangesetInfo, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+
+    let until: i64 = conn
+        .query_row(
+            &format!(
+                "SELECT MAX(COALESCE((SELECT MAX(changed_at) FROM change_log), 0), {})",
+                NOW_MS_SQL
+            ),
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to read change log time: {}", e))?;
+    let (content, changes) = build_changeset_with_conn(&conn, since, until)?;
+
+    let export_dir = storage_paths::get_export_dir()?;
+    fs::create_dir_all(&export_dir)
+        .map_err(|e| format!("Failed to create export directory: {}", e))?;
+    let filename = format!("changes_{}_{}.ndjson", since, until);
+    let path = export_dir.join(&filename);
+    fs::write(&path, content).map_err(|e| format!("Failed to write changeset: {}", e))?;
+
+    logger::info(format!(
+        "Changeset exported: {} ({} changes)",
+        filename, changes
+    ));
+
+    Ok(ChangesetInfo {
+        filename,
+        path: path.to_string_lossy().to_string(),
+        since,
+        until,
+        changes,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Leave rows that changed locally more recently untouched
+    KeepLocal,
+    /// Overwrite local rows even when they are newer
+    TakeIncoming,
+    /// Roll back the whole changeset on the first conflict
+    Abort,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChangeConflict {
+    pub table: String,
+    pub pk: i64,
+    pub reason: String,
+    /// Whether the incoming change was applied anyway (TakeIncoming)
+    pub applied: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ChangesetApplyReport {
+    pub applied: usize,
+    /// Already in sync (same updated_at) or deleting a row that is not here
+    pub skipped: usize,
+    pub conflicts: Vec<ChangeConflict>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeLine {
+    #[serde(rename = "type")]
+    line_type: String,
+    #[serde(default)]
+    changeset_version: Option<u32>,
+    #[serde(default)]
+    op: Option<String>,
+    #[serde(default)]
+    table: Option<String>,
+    #[serde(default)]
+    pk: Option<i64>,
+    #[serde(default)]
+    changed_at: Option<i64>,
+    #[serde(default)]
+    row: Option<serde_json::Map<String, Value>>,
+}
+
+/// updated_at is written both as SQLite CURRENT_TIMESTAMP (UTC, no zone) and
+/// as RFC 3339; compare them as unix milliseconds
+fn parse_timestamp_ms(value: &str) -> Option<i64> {
+    if let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(parsed.timestamp_millis());
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|naive| naive.and_utc().timestamp_millis())
+}
+
+fn local_updated_at(
+    conn: &Connection,
+    table: &str,
+    pk: i64,
+) -> Result<Option<Option<i64>>, String> {
+    let pk_column = pk_column(table)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT updated_at FROM {} WHERE {} = ?",
+            table, pk_column
+        ))
+        .map_err(|e| format!("Failed to prepare row query: {}", e))?;
+    let mut rows = stmt
+        .query(params![pk])
+        .map_err(|e| format!("Failed to query {}: {}", table, e))?;
+    match rows
+        .next()
+        .map_err(|e| format!("Failed to read {}: {}", table, e))?
+    {
+        Some(row) => {
+            let updated_at: Option<String> = row
+                .get(0)
+                .map_err(|e| format!("Failed to read updated_at: {}", e))?;
+            Ok(Some(updated_at.as_deref().and_then(parse_timestamp_ms)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn local_columns(conn: &Connection, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    Ok(columns)
+}
+
+fn sql_value(value: &Value) -> rusqlite::types::Value {
+    use rusqlite::types::Value as SqlValue;
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// Insert or update one row; columns unknown to this installation are ignored
+fn upsert_row(
+    conn: &Connection,
+    table: &str,
+    row: &serde_json::Map<String, Value>,
+) -> Result<(), String> {
+    let pk_column = pk_column(table)?;
+    let known = local_columns(conn, table)?;
+    let columns: Vec<&String> = row.keys().filter(|c| known.contains(c)).collect();
+    if !columns.iter().any(|c| c.as_str() == pk_column) {
+        return Err(format!("Change for {} has no {} value", table, pk_column));
+    }
+
+    let column_list: Vec<&str> = columns.iter().map(|c| c.as_str()).collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    let updates: Vec<String> = column_list
+        .iter()
+        .filter(|c| **c != pk_column)
+        .map(|c| format!("{c} = excluded.{c}"))
+        .collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT({}) DO UPDATE SET {}",
+        table,
+        column_list.join(", "),
+        placeholders.join(", "),
+        pk_column,
+        updates.join(", ")
+    );
+
+    let values: Vec<rusqlite::types::Value> = columns.iter().map(|c| sql_value(&row[*c])).collect();
+    conn.execute(&sql, rusqlite::params_from_iter(values))
+        .map_err(|e| format!("Failed to apply change to {}: {}", table, e))?;
+    Ok(())
+}
+
+fn delete_row(conn: &Connection, table: &str, pk: i64) -> Result<(), String> {
+    conn.execute(
+        &format!("DELETE FROM {} WHERE {} = ?", table, pk_column(table)?),
+        params![pk],
+    )
+    .map_err(|e| format!("Failed to apply delete to {}: {}", table, e))?;
+    Ok(())
+}
+
+/// Decide and apply one change line, recording the outcome in `report`
+fn apply_change(
+    conn: &Connection,
+    change: &ChangeLine,
+    strategy: ConflictStrategy,
+    report: &mut ChangesetApplyReport,
+) -> Result<(), String> {
+    let table = change.table.as_deref().ok_or("Change line without table")?;
+    let pk = change.pk.ok_or("Change line without pk")?;
+    let op = change.op.as_deref().unwrap_or_default();
+    pk_column(table)?;
+
+    let local = local_updated_at(conn, table, pk)?;
+    // The time the incoming change was made: the row's updated_at for upserts,
+    // the logged change time for deletes
+    let incoming_at = match op {
+        "delete" => change.changed_at,
+        _ => change
+            .row
+            .as_ref()
+            .and_then(|row| row.get("updated_at"))
+            .and_then(Value::as_str)
+            .and_then(parse_timestamp_ms),
+    };
+
+    let conflict_reason = match (local, incoming_at) {
+        (None, _) if op == "delete" => {
+            report.skipped += 1;
+            return Ok(());
+        }
+        (Some(Some(local_at)), Some(incoming_at)) if local_at == incoming_at && op != "delete" => {
+            report.skipped += 1;
+            return Ok(());
+        }
+        (Some(Some(local_at)), Some(incoming_at)) if local_at > incoming_at => {
+            Some("Local row was changed more recently".to_string())
+        }
+        (Some(Some(_)), None) => Some("Incoming change has no timestamp".to_string()),
+        _ => None,
+    };
+
+    let apply = |conn: &Connection| match op {
+        "delete" => delete_row(conn, table, pk),
+        "upsert" => match change.row {
+            Some(ref row) => upsert_row(conn, table, row),
+            None => Err(format!("Upsert for {} {} has no row", table, pk)),
+        },
+        other => Err(format!("Unknown change operation: {}", other)),
+    };
+
+    match conflict_reason {
+        None => match apply(conn) {
+            Ok(()) => report.applied += 1,
+            // Constraint failures (e.g. a username taken by another local row)
+            Err(e) => {
+                if strategy == ConflictStrategy::Abort {
+                    return Err(e);
+                }
+                report.conflicts.push(ChangeConflict {
+                    table: table.to_string(),
+                    pk,
+                    reason: e,
+                    applied: false,
+                });
+            }
+        },
+        Some(reason) => {
+            let applied = match strategy {
+                ConflictStrategy::Abort => {
+                    return Err(format!("Conflict on {} {}: {}", table, pk, reason))
+                }
+                ConflictStrategy::KeepLocal => false,
+                ConflictStrategy::TakeIncoming => {
+                    apply(conn)?;
+                    report.applied += 1;
+                    true
+                }
+            };
+            report.conflicts.push(ChangeConflict {
+                table: table.to_string(),
+                pk,
+                reason,
+                applied,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Replay a changeset produced by `export_changes_since` inside one transaction
+pub fn apply_changeset_with_conn(
+    conn: &mut Connection,
+    content: &str,
+    strategy: ConflictStrategy,
+) -> Result<ChangesetApplyReport, String> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+
+    let header: ChangeLine = serde_json::from_str(lines.next().ok_or("Changeset is empty")?)
+        .map_err(|e| format!("Failed to parse changeset header: {}", e))?;
+    if header.line_type != "header" {
+        return Err("Changeset does not start with a header line".to_string());
+    }
+    match header.changeset_version {
+        Some(version) if version <= CHANGESET_VERSION => {}
+        other => {
+            return Err(format!(
+                "Unsupported changeset version: {:?} (supported: {})",
+                other, CHANGESET_VERSION
+            ))
+        }
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let mut report = ChangesetApplyReport::default();
+
+    for (index, line) in lines.enumerate() {
+        let change: ChangeLine = serde_json::from_str(line)
+            .map_err(|e| format!("Failed to parse change on line {}: {}", index + 2, e))?;
+        if change.line_type != "change" {
+            continue;
+        }
+        // Returning early drops the transaction, which rolls everything back
+        apply_change(&tx, &change, strategy, &mut report)?;
+    }
+
+    tx.commit()
+        .map_err(|e| format!("Failed to commit changeset: {}", e))?;
+    Ok(report)
+}
+
+pub fn apply_changeset(
+    path: &Path,
+    strategy: ConflictStrategy,
+) -> Result<ChangesetApplyReport, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read changeset: {}", e))?;
+    let mut conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+
+    let report = apply_changeset_with_conn(&mut conn, &content, strategy)?;
+    logger::info(format!(
+        "Changeset applied: {} applied, {} skipped, {} conflicts",
+        report.applied,
+        report.skipped,
+        report.conflicts.len()
+    ));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn parse(content: &str) -> Vec<Value> {
+        content
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("line should be JSON"))
+            .collect()
+    }
+
+    #[test]
+    fn test_triggers_record_changes() {
+        let conn = create_app_db();
+
+        conn.execute_batch(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('u', 'u@test.com', 'h', 'U');
+             UPDATE users SET full_name = 'User' WHERE username = 'u';
+             DELETE FROM users WHERE username = 'u';",
+        )
+        .expect("changes should apply");
+
+        let ops: Vec<String> = conn
+            .prepare("SELECT op FROM change_log WHERE table_name = 'users' ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ops, vec!["insert", "update", "delete"]);
+    }
+
+    #[test]
+    fn test_changeset_collapses_to_latest_state() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('a', 'a@test.com', 'h', 'A');
+             INSERT INTO users (username, email, password_hash, full_name) VALUES ('b', 'b@test.com', 'h', 'B');
+             UPDATE users SET full_name = 'Alpha' WHERE username = 'a';
+             DELETE FROM users WHERE username = 'b';",
+        )
+        .expect("changes should apply");
+
+        let (content, changes) =
+            build_changeset_with_conn(&conn, 0, i64::MAX).expect("changeset should build");
+        let lines = parse(&content);
+
+        assert_eq!(changes, 2);
+        assert_eq!(lines[0]["type"], "header");
+        assert_eq!(lines[1]["op"], "upsert");
+        assert_eq!(lines[1]["row"]["full_name"], "Alpha");
+        assert_eq!(lines[2]["op"], "delete");
+        assert!(lines[2]["row"].is_null());
+    }
+
+    #[test]
+    fn test_changes_before_since_are_skipped() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO change_log (op, table_name, pk, changed_at) VALUES ('delete', 'users', 1, 100)",
+            [],
+        )
+        .unwrap();
+
+        let (_, changes) = build_changeset_with_conn(&conn, 100, 200).unwrap();
+        assert_eq!(changes, 0);
+    }
+
+    #[test]
+    fn test_changeset_replays_on_another_database() {
+        let source = create_app_db();
+        source
+            .execute_batch(
+                "INSERT INTO users (id, username, email, password_hash, full_name, updated_at) VALUES (1, 'a', 'a@test.com', 'h', 'A', '2026-01-01 10:00:00');
+                 INSERT INTO users (id, username, email, password_hash, full_name, updated_at) VALUES (2, 'b', 'b@test.com', 'h', 'B', '2026-01-01 10:00:00');",
+            )
+            .unwrap();
+        let mut target = create_app_db();
+        target
+            .execute_batch(
+                "INSERT INTO users (id, username, email, password_hash, full_name, updated_at) VALUES (2, 'b', 'b@test.com', 'h', 'B', '2026-01-01 10:00:00');",
+            )
+            .unwrap();
+
+        let (content, _) = build_changeset_with_conn(&source, 0, i64::MAX).unwrap();
+        let report = apply_changeset_with_conn(&mut target, &content, ConflictStrategy::Abort)
+            .expect("changeset should apply");
+
+        assert_eq!(report.applied, 1);
+        assert_eq!(report.skipped, 1);
+        assert!(report.conflicts.is_empty());
+        let name: String = target
+            .query_row("SELECT full_name FROM users WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(name, "A");
+    }
+
+    #[test]
+    fn test_newer_local_row_is_a_conflict() {
+        let source = create_app_db();
+        source
+            .execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name, updated_at) VALUES (1, 'a', 'a@test.com', 'h', 'Old', '2026-01-01 10:00:00')",
+                [],
+            )
+            .unwrap();
+        let (content, _) = build_changeset_with_conn(&source, 0, i64::MAX).unwrap();
+
+        let mut target = create_app_db();
+        target
+            .execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name, updated_at) VALUES (1, 'a', 'a@test.com', 'h', 'New', '2026-02-01T10:00:00+00:00')",
+                [],
+            )
+            .unwrap();
+
+        let report =
+            apply_changeset_with_conn(&mut target, &content, ConflictStrategy::KeepLocal).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert!(!report.conflicts[0].applied);
+
+        assert!(apply_changeset_with_conn(&mut target, &content, ConflictStrategy::Abort).is_err());
+
+        let report =
+            apply_changeset_with_conn(&mut target, &content, ConflictStrategy::TakeIncoming)
+                .unwrap();
+        assert!(report.conflicts[0].applied);
+        let name: String = target
+            .query_row("SELECT full_name FROM users WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(name, "Old");
+    }
+}
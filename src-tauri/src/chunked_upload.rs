@@ -0,0 +1,520 @@
+//! Avatar uploads sent in pieces
+//!
+//! For files too large for one command payload (see `ipc_guard`). The UI
+//! calls `begin_upload` with the final size, sends the bytes with
+//! `append_chunk` in order, each at most `MAX_CHUNK_BYTES`, and calls
+//! `finish_upload`, which checks the file against the avatar policy and saves
+//! it through the avatar managers exactly like a one-piece upload. A chunk
+//! resent after a lost reply is accepted again without being written twice.
+//!
+//! Each upload keeps its state on disk, in `<workspace>/uploads/<id>`: the
+//! announced owner, type, size and optional SHA-256 in `session.json` next
+//! to the bytes received so far. The length of the partial file is the
+//! received count, so an upload interrupted by closing the app carries on
+//! after a restart: `get_upload_status` tells the UI where to continue, and
+//! `begin_upload` with the same hash picks the old session up again.
+//! Sessions without a chunk for `STALE_UPLOAD_AGE` are removed at startup
+//! and by the retention job.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::avatar_policy;
+use crate::backup_manager::sha256_file;
+use crate::hybrid_avatar::HybridAvatarManager;
+use crate::hybrid_high_rank_avatar::HybridHighRankAvatarManager;
+use crate::logger;
+use crate::media_maintenance::{owner_table, OWNER_USER};
+use crate::permissions::Caller;
+use crate::storage_paths;
+use crate::validation;
+
+/// Well below the payload limit even as a JSON number array
+pub const MAX_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+pub const UPLOADS_DIR_NAME: &str = "uploads";
+
+/// Time since the last chunk after which an upload is given up
+pub const STALE_UPLOAD_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+const SESSION_FILE_NAME: &str = "session.json";
+const UPLOAD_FILE_NAME: &str = "upload.part";
+
+/// What `begin_upload` was told, as stored in `session.json`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct UploadSession {
+    owner_type: String,
+    owner_id: i32,
+    mime_type: String,
+    total_bytes: u64,
+    /// Lowercase hex SHA-256 of the whole file, checked by `finish_upload`
+    sha256: Option<String>,
+}
+
+lazy_static! {
+    // One upload call at a time, so two chunks never append to the same file at once
+    static ref UPLOAD_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UploadStatus {
+    pub upload_id: String,
+    pub received_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Where a finished upload was stored
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UploadedAvatar {
+    pub owner_type: String,
+    pub owner_id: i32,
+    pub avatar_path: Option<String>,
+}
+
+fn new_upload_id() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to read random bytes: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn lock_uploads() -> Result<std::sync::MutexGuard<'static, ()>, String> {
+    UPLOAD_LOCK
+        .lock()
+        .map_err(|_| "Upload registry is unavailable".to_string())
+}
+
+/// `<workspace>/uploads`, next to the media the uploads end up in
+pub fn get_uploads_root() -> Result<PathBuf, String> {
+    let dir = storage_paths::get_workspace_dir()?.join(UPLOADS_DIR_NAME);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create uploads directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Directory of `upload_id`; ids come from the page, so anything but the
+/// hex ids `new_upload_id` hands out is refused before it reaches a path
+fn session_dir(upload_id: &str) -> Result<PathBuf, String> {
+    if upload_id.len() != 32 || !upload_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Unknown upload: {}", upload_id));
+    }
+    Ok(get_uploads_root()?.join(upload_id))
+}
+
+fn read_session(dir: &Path) -> Result<UploadSession, String> {
+    let json = fs::read_to_string(dir.join(SESSION_FILE_NAME))
+        .map_err(|e| format!("Failed to read upload session: {}", e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse upload session: {}", e))
+}
+
+/// Session and bytes received so far of `upload_id`
+fn load_session(upload_id: &str) -> Result<(PathBuf, UploadSession, u64), String> {
+    let dir = session_dir(upload_id)?;
+    if !dir.exists() {
+        return Err(format!("Unknown upload: {}", upload_id));
+    }
+    let session = read_session(&dir)?;
+    let received_bytes = fs::metadata(dir.join(UPLOAD_FILE_NAME))
+        .map(|metadata| metadata.len())
+        .map_err(|e| format!("Failed to read upload file: {}", e))?;
+    Ok((dir, session, received_bytes))
+}
+
+fn status_of(upload_id: &str, session: &UploadSession, received_bytes: u64) -> UploadStatus {
+    UploadStatus {
+        upload_id: upload_id.to_string(),
+        received_bytes,
+        total_bytes: session.total_bytes,
+    }
+}
+
+/// An unfinished upload of the same file, left by an earlier run
+fn find_resumable(session: &UploadSession) -> Option<UploadStatus> {
+    // Without a hash there is no telling two files of the same size apart
+    session.sha256.as_ref()?;
+    let entries = fs::read_dir(get_uploads_root().ok()?).ok()?;
+    entries.flatten().find_map(|entry| {
+        let upload_id = entry.file_name().to_string_lossy().to_string();
+        match load_session(&upload_id) {
+            Ok((_, existing, received)) if existing == *session => {
+                Some(status_of(&upload_id, &existing, received))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Start an upload of `total_bytes` for `owner_type` `owner_id`; size and
+/// type are checked against the avatar policy before any data is sent.
+/// With `sha256`, an unfinished upload of the same file is continued: the
+/// returned status says how many bytes it already has
+pub fn begin_upload(
+    owner_type: &str,
+    owner_id: i32,
+    mime_type: &str,
+    total_bytes: u64,
+    sha256: Option<&str>,
+) -> Result<UploadStatus, String> {
+    owner_table(owner_type)?;
+    let mime_type = avatar_policy::normalize_mime(mime_type);
+    avatar_policy::check_upload(&avatar_policy::current_policy(), total_bytes, &mime_type)?;
+    let sha256 = match sha256.map(|hash| hash.trim().to_ascii_lowercase()) {
+        Some(hash) if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            return Err("sha256 must be 64 hexadecimal characters".to_string());
+        }
+        hash => hash,
+    };
+
+    let session = UploadSession {
+        owner_type: owner_type.to_string(),
+        owner_id,
+        mime_type,
+        total_bytes,
+        sha256,
+    };
+    let _lock = lock_uploads()?;
+    if let Some(status) = find_resumable(&session) {
+        logger::info(format!(
+            "Resuming upload {} at byte {} of {}",
+            status.upload_id, status.received_bytes, status.total_bytes
+        ));
+        return Ok(status);
+    }
+
+    let upload_id = new_upload_id()?;
+    let dir = get_uploads_root()?.join(&upload_id);
+    let created = fs::create_dir_all(&dir)
+        .and_then(|_| fs::File::create(dir.join(UPLOAD_FILE_NAME)))
+        .map_err(|e| format!("Failed to create upload file: {}", e))
+        .and_then(|_| {
+            let json = serde_json::to_string(&session)
+                .map_err(|e| format!("Failed to serialize upload session: {}", e))?;
+            fs::write(dir.join(SESSION_FILE_NAME), json)
+                .map_err(|e| format!("Failed to write upload session: {}", e))
+        });
+    if let Err(e) = created {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(status_of(&upload_id, &session, 0))
+}
+
+/// Bytes received so far, to continue an upload after a restart
+pub fn get_upload_status(upload_id: &str) -> Result<UploadStatus, String> {
+    let _lock = lock_uploads()?;
+    let (_, session, received_bytes) = load_session(upload_id)?;
+    Ok(status_of(upload_id, &session, received_bytes))
+}
+
+/// Append `data` at `offset`, which must not be past where the previous
+/// chunk ended; bytes that already arrived are skipped
+pub fn append_chunk(upload_id: &str, offset: u64, data: &[u8]) -> Result<UploadStatus, String> {
+    if data.len() > MAX_CHUNK_BYTES {
+        return Err(format!(
+            "Upload chunks must be at most {} bytes",
+            MAX_CHUNK_BYTES
+        ));
+    }
+    let _lock = lock_uploads()?;
+    let (dir, session, received_bytes) = load_session(upload_id)?;
+
+    let end = offset.saturating_add(data.len() as u64);
+    // A resend of a chunk that already arrived
+    if end <= received_bytes {
+        return Ok(status_of(upload_id, &session, received_bytes));
+    }
+    if offset > received_bytes {
+        return Err(format!(
+            "Upload chunk starts at byte {} but {} bytes were received",
+            offset, received_bytes
+        ));
+    }
+    if end > session.total_bytes {
+        return Err(format!(
+            "Upload is larger than the announced {} bytes",
+            session.total_bytes
+        ));
+    }
+
+    // Part of the chunk may have been written before the app was closed
+    let new_data = &data[(received_bytes - offset) as usize..];
+    OpenOptions::new()
+        .append(true)
+        .open(dir.join(UPLOAD_FILE_NAME))
+        .and_then(|mut file| file.write_all(new_data))
+        .map_err(|e| format!("Failed to write upload chunk: {}", e))?;
+    Ok(status_of(upload_id, &session, end))
+}
+
+fn save_avatar(
+    session: &UploadSession,
+    data: &[u8],
+    uploaded_by: Option<Caller>,
+) -> Result<Option<String>, String> {
+    let image = validation::ImagePayload::parse(
+        "avatar_data",
+        data,
+        &session.mime_type,
+        &avatar_policy::current_policy(),
+    )?;
+    if session.owner_type == OWNER_USER {
+        let info = HybridAvatarManager::new()?.save_avatar(
+            session.owner_id,
+            image.data,
+            &image.mime_type,
+            uploaded_by,
+        )?;
+        Ok(info.avatar_path)
+    } else {
+        let info = HybridHighRankAvatarManager::new()?.save_avatar(
+            session.owner_id,
+            image.data,
+            &image.mime_type,
+            uploaded_by,
+        )?;
+        Ok(info.avatar_path)
+    }
+}
+
+/// Store the complete file as the owner's avatar, reviewed as an upload by
+/// `uploaded_by`, and end the upload
+pub fn finish_upload(
+    upload_id: &str,
+    uploaded_by: Option<Caller>,
+) -> Result<UploadedAvatar, String> {
+    let _lock = lock_uploads()?;
+    let (dir, session, received_bytes) = load_session(upload_id)?;
+    // The session stays so the missing chunks can still be sent
+    if received_bytes != session.total_bytes {
+        return Err(format!(
+            "Upload is missing its last {} bytes",
+            session.total_bytes - received_bytes
+        ));
+    }
+
+    let file_path = dir.join(UPLOAD_FILE_NAME);
+    if let Some(expected) = &session.sha256 {
+        if sha256_file(&file_path)? != *expected {
+            // Not worth resuming: the bytes on disk are not the announced file
+            let _ = fs::remove_dir_all(&dir);
+            return Err("Upload does not match its SHA-256; send the file again".to_string());
+        }
+    }
+    let data = fs::read(&file_path).map_err(|e| format!("Failed to read upload file: {}", e))?;
+    let avatar_path = save_avatar(&session, &data, uploaded_by)?;
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        logger::warn(format!("Failed to remove finished upload: {}", e));
+    }
+    Ok(UploadedAvatar {
+        owner_type: session.owner_type,
+        owner_id: session.owner_id,
+        avatar_path,
+    })
+}
+
+/// Drop an upload and its partial file; false if it did not exist
+pub fn cancel_upload(upload_id: &str) -> Result<bool, String> {
+    let _lock = lock_uploads()?;
+    let dir = session_dir(upload_id)?;
+    if !dir.exists() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove upload: {}", e))?;
+    Ok(true)
+}
+
+/// Remove uploads under `root` whose last chunk arrived more than `max_age`
+/// before `now`, and any without a readable session
+pub fn cleanup_stale_uploads_in(
+    root: &Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> Result<usize, String> {
+    if !root.exists() {
+        return Ok(0);
+    }
+
+    let entries =
+        fs::read_dir(root).map_err(|e| format!("Failed to read uploads directory: {}", e))?;
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let last_chunk = fs::metadata(path.join(UPLOAD_FILE_NAME)).and_then(|m| m.modified());
+        let stale = match last_chunk {
+            Ok(modified) if read_session(&path).is_ok() => {
+                now.duration_since(modified).unwrap_or_default() >= max_age
+            }
+            _ => true,
+        };
+        if !stale {
+            continue;
+        }
+
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => removed += 1,
+            Err(e) => logger::warn(format!(
+                "Failed to remove stale upload {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+    Ok(removed)
+}
+
+/// Uploads nobody came back to finish
+pub fn cleanup_stale_uploads() -> Result<usize, String> {
+    let _lock = lock_uploads()?;
+    let removed =
+        cleanup_stale_uploads_in(&get_uploads_root()?, STALE_UPLOAD_AGE, SystemTime::now())?;
+    if removed > 0 {
+        logger::info(format!("Removed {} abandoned uploads", removed));
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestEnvironment;
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        png
+    }
+
+    #[test]
+    fn test_upload_in_chunks() {
+        let env = TestEnvironment::with_temp_dir();
+        let user_id = env
+            .create_user("chunked_user")
+            .id
+            .expect("user should have an id");
+        let png = png_bytes();
+        let (first, second) = png.split_at(png.len() / 2);
+
+        let status =
+            begin_upload(OWNER_USER, user_id, "image/png", png.len() as u64, None).unwrap();
+        let id = status.upload_id;
+        append_chunk(&id, 0, first).unwrap();
+        // Resent after a lost reply
+        append_chunk(&id, 0, first).unwrap();
+        assert!(append_chunk(&id, first.len() as u64 + 1, second).is_err());
+        assert!(finish_upload(&id, None).is_err());
+
+        let status = append_chunk(&id, first.len() as u64, second).unwrap();
+        assert_eq!(status.received_bytes, png.len() as u64);
+        let uploaded = finish_upload(&id, None).expect("upload should be saved");
+        assert!(uploaded.avatar_path.is_some());
+        assert!(!cancel_upload(&id).unwrap());
+
+        assert!(begin_upload("ship", 1, "image/png", 10, None).is_err());
+        assert!(append_chunk("../../database.db", 0, b"x").is_err());
+    }
+
+    #[test]
+    fn test_upload_resumes_from_disk() {
+        let env = TestEnvironment::with_temp_dir();
+        let user_id = env
+            .create_user("resumed_user")
+            .id
+            .expect("user should have an id");
+        let png = png_bytes();
+        let hash = format!("{:x}", <sha2::Sha256 as sha2::Digest>::digest(&png));
+        let (first, second) = png.split_at(png.len() / 2);
+
+        let id = begin_upload(
+            OWNER_USER,
+            user_id,
+            "image/png",
+            png.len() as u64,
+            Some(&hash),
+        )
+        .unwrap()
+        .upload_id;
+        append_chunk(&id, 0, first).unwrap();
+
+        // After a restart: the same file announced again continues the session
+        let resumed = begin_upload(
+            OWNER_USER,
+            user_id,
+            "image/png",
+            png.len() as u64,
+            Some(&hash),
+        )
+        .unwrap();
+        assert_eq!(resumed.upload_id, id);
+        assert_eq!(resumed.received_bytes, first.len() as u64);
+        assert_eq!(get_upload_status(&id).unwrap(), resumed);
+
+        // A chunk that was half written when the app closed
+        let overlap = first.len() - 3;
+        append_chunk(&id, overlap as u64, &png[overlap..]).unwrap();
+        assert!(finish_upload(&id, None).unwrap().avatar_path.is_some());
+        assert!(get_upload_status(&id).is_err());
+
+        let wrong_hash = "0".repeat(64);
+        let id = begin_upload(
+            OWNER_USER,
+            user_id,
+            "image/png",
+            png.len() as u64,
+            Some(&wrong_hash),
+        )
+        .unwrap()
+        .upload_id;
+        append_chunk(&id, 0, first).unwrap();
+        append_chunk(&id, first.len() as u64, second).unwrap();
+        assert!(finish_upload(&id, None).is_err());
+        assert!(!cancel_upload(&id).unwrap());
+    }
+
+    #[test]
+    fn test_cleanup_removes_abandoned_uploads() {
+        let root = tempfile::TempDir::new().expect("temp dir should be created");
+        let session = UploadSession {
+            owner_type: OWNER_USER.to_string(),
+            owner_id: 1,
+            mime_type: "image/png".to_string(),
+            total_bytes: 10,
+            sha256: None,
+        };
+        let active = root.path().join("a".repeat(32));
+        fs::create_dir_all(&active).unwrap();
+        fs::write(active.join(UPLOAD_FILE_NAME), b"12345").unwrap();
+        fs::write(
+            active.join(SESSION_FILE_NAME),
+            serde_json::to_string(&session).unwrap(),
+        )
+        .unwrap();
+        // Crashed between creating the directory and writing the session
+        fs::create_dir_all(root.path().join("b".repeat(32))).unwrap();
+
+        let now = SystemTime::now();
+        assert_eq!(
+            cleanup_stale_uploads_in(root.path(), STALE_UPLOAD_AGE, now).unwrap(),
+            1
+        );
+        assert!(active.exists());
+        let later = now + STALE_UPLOAD_AGE + Duration::from_secs(1);
+        assert_eq!(
+            cleanup_stale_uploads_in(root.path(), STALE_UPLOAD_AGE, later).unwrap(),
+            1
+        );
+        assert!(!active.exists());
+    }
+}
@@ -0,0 +1,523 @@
+//! Printable contact sheet of selected avatars
+//!
+//! `generate_contact_sheet` lays the photos of the chosen users (or officers)
+//! out in a grid with the name and rank (or position) under each one, and
+//! writes it as a single SVG or PDF file in the export directory. Photos are
+//! scaled down and embedded, so the file opens and prints on its own. In the
+//! SVG, text is left to the viewer's fonts, which is what makes Thai names
+//! render correctly without shipping a font; the PDF embeds an installed
+//! Thai-capable font the way signature stamps do (see `signature_pdf`).
+
+use image::imageops::FilterType;
+use image::ImageOutputFormat;
+use lopdf::{dictionary, Dictionary, Document, Object, Stream};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose, Engine as _};
+
+use crate::avatar_approval::VISIBLE_AVATAR_CONDITION;
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+use crate::logger;
+use crate::media_maintenance::{OWNER_OFFICER, OWNER_USER};
+use crate::officer_board::escape_html;
+use crate::safe_path::MediaRoot;
+use crate::signature_pdf::{self, TitleFont, TITLE_FONT_CANDIDATES};
+use crate::storage_paths;
+
+pub const MAX_COLUMNS: u32 = 12;
+pub const MIN_PHOTO_SIZE: u32 = 48;
+pub const MAX_PHOTO_SIZE: u32 = 600;
+
+const MARGIN: u32 = 24;
+const GAP: u32 = 16;
+/// Room under each photo for the two caption lines
+const CAPTION_HEIGHT: u32 = 44;
+/// Caption sizes and baselines below the photo, as in the SVG style
+const NAME_FONT_SIZE: f32 = 14.0;
+const SUBTITLE_FONT_SIZE: f32 = 12.0;
+const NAME_BASELINE: u32 = 18;
+const SUBTITLE_BASELINE: u32 = 36;
+const PDF_FONT_NAME: &str = "PqsSheetFont";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SheetFormat {
+    /// Opens in any browser, text set in the viewer's fonts
+    #[default]
+    Svg,
+    /// Prints the same everywhere; needs a Thai font installed
+    Pdf,
+}
+
+impl SheetFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            SheetFormat::Svg => "svg",
+            SheetFormat::Pdf => "pdf",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SheetEntry {
+    pub id: i32,
+    pub name: String,
+    /// Rank for users, position for officers
+    pub subtitle: String,
+    #[serde(skip)]
+    pub photo_source: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContactSheetReport {
+    pub filename: String,
+    pub path: String,
+    pub people: usize,
+    pub photos: usize,
+    /// Ids that do not exist
+    pub not_found: Vec<i32>,
+    /// Names shown with an empty frame: no photo, or the file is unreadable
+    pub missing_photos: Vec<String>,
+}
+
+pub fn validate_layout(columns: u32, size: u32) -> Result<(), String> {
+    if !(1..=MAX_COLUMNS).contains(&columns) {
+        return Err(format!("Columns must be between 1 and {}", MAX_COLUMNS));
+    }
+    if !(MIN_PHOTO_SIZE..=MAX_PHOTO_SIZE).contains(&size) {
+        return Err(format!(
+            "Photo size must be between {} and {} pixels",
+            MIN_PHOTO_SIZE, MAX_PHOTO_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Entries in the order of `ids`, plus the ids that were not found
+pub fn collect_sheet_entries_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    owner_type: &str,
+    ids: &[i32],
+) -> Result<(Vec<SheetEntry>, Vec<i32>), String> {
+    let media_root = MediaRoot::new(media_dir)?;
+    // Photos waiting for review get an empty frame
+    let sql = match owner_type {
+        OWNER_USER => format!(
+            "SELECT full_name, COALESCE(rank, ''), CASE WHEN {} THEN avatar_path END FROM users WHERE id = ?",
+            VISIBLE_AVATAR_CONDITION
+        ),
+        OWNER_OFFICER => format!(
+            "SELECT thai_name, position_thai, CASE WHEN avatar_visible = 1 AND {} THEN avatar_path END FROM high_ranking_officers WHERE id = ?",
+            VISIBLE_AVATAR_CONDITION
+        ),
+        _ => return Err(format!("Unknown media owner type: {}", owner_type)),
+    };
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let mut entries = Vec::new();
+    let mut not_found = Vec::new();
+    for &id in ids {
+        type Row = (String, String, Option<String>);
+        let row: Option<Row> = stmt
+            .query_row([id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .optional()
+            .map_err(|e| format!("Failed to query {} {}: {}", owner_type, id, e))?;
+
+        let Some((name, subtitle, avatar_path)) = row else {
+            not_found.push(id);
+            continue;
+        };
+        let photo_source = avatar_path
+            .filter(|p| !p.is_empty())
+            .and_then(|p| media_root.resolve(&p).ok())
+            .map(|p| p.into_path_buf())
+            .filter(|p| p.is_file());
+        entries.push(SheetEntry {
+            id,
+            name,
+            subtitle,
+            photo_source,
+        });
+    }
+
+    Ok((entries, not_found))
+}
+
+/// Square JPEG thumbnail of `source`, cropped to fill the frame
+fn thumbnail_jpeg(source: &Path, size: u32) -> Result<Vec<u8>, String> {
+    let image = image::open(source).map_err(|e| format!("Failed to read image: {}", e))?;
+    let thumbnail = image.resize_to_fill(size, size, FilterType::Lanczos3);
+
+    let mut output = Vec::new();
+    image::DynamicImage::ImageRgb8(thumbnail.to_rgb8())
+        .write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Jpeg(85))
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(output)
+}
+
+/// Thumbnail of `entry`, None (logged) when it has no readable photo
+fn entry_thumbnail(entry: &SheetEntry, size: u32) -> Option<Vec<u8>> {
+    let source = entry.photo_source.as_deref()?;
+    match thumbnail_jpeg(source, size) {
+        Ok(jpeg) => Some(jpeg),
+        Err(e) => {
+            logger::warn(format!(
+                "Contact sheet skipped photo of {}: {}",
+                entry.name, e
+            ));
+            None
+        }
+    }
+}
+
+/// Every name and subtitle, for picking and embedding the PDF font
+fn caption_lines(entries: &[SheetEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .flat_map(|entry| [entry.name.clone(), entry.subtitle.clone()])
+        .collect()
+}
+
+/// (columns used, width, height) of a sheet of `count` entries
+fn sheet_layout(count: usize, columns: u32, size: u32) -> (u32, u32, u32) {
+    let columns = columns.min(count.max(1) as u32);
+    let rows = (count as u32).div_ceil(columns).max(1);
+    let width = MARGIN * 2 + columns * size + (columns - 1) * GAP;
+    let height = MARGIN * 2 + rows * (size + CAPTION_HEIGHT) + (rows - 1) * GAP;
+    (columns, width, height)
+}
+
+/// Top left corner of the photo frame of entry `index`
+fn cell_origin(index: usize, columns: u32, size: u32) -> (u32, u32) {
+    (
+        MARGIN + (index as u32 % columns) * (size + GAP),
+        MARGIN + (index as u32 / columns) * (size + CAPTION_HEIGHT + GAP),
+    )
+}
+
+/// The sheet as SVG; entries whose photo cannot be read get an empty frame
+/// and are returned by name
+pub fn render_contact_sheet(
+    entries: &[SheetEntry],
+    columns: u32,
+    size: u32,
+) -> (String, Vec<String>) {
+    let (columns, width, height) = sheet_layout(entries.len(), columns, size);
+
+    let mut missing = Vec::new();
+    let mut cells = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let (x, y) = cell_origin(index, columns, size);
+
+        match entry_thumbnail(entry, size) {
+            Some(jpeg) => cells.push_str(&format!(
+                r#"  <image x="{}" y="{}" width="{}" height="{}" href="data:image/jpeg;base64,{}"/>
+"#,
+                x,
+                y,
+                size,
+                size,
+                general_purpose::STANDARD.encode(&jpeg)
+            )),
+            None => {
+                missing.push(entry.name.clone());
+                cells.push_str(&format!(
+                    r##"  <rect x="{}" y="{}" width="{}" height="{}" fill="#e5e7eb" stroke="#9ca3af"/>
+"##,
+                    x, y, size, size
+                ));
+            }
+        }
+
+        let center = x + size / 2;
+        cells.push_str(&format!(
+            r#"  <text x="{}" y="{}" class="name">{}</text>
+  <text x="{}" y="{}" class="subtitle">{}</text>
+"#,
+            center,
+            y + size + NAME_BASELINE,
+            escape_html(&entry.name),
+            center,
+            y + size + SUBTITLE_BASELINE,
+            escape_html(&entry.subtitle)
+        ));
+    }
+
+    let svg = format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">
+  <style>
+    text {{ font-family: "Sarabun", "Tahoma", sans-serif; text-anchor: middle; fill: #1f2937; }}
+    .name {{ font-size: 14px; font-weight: bold; }}
+    .subtitle {{ font-size: 12px; fill: #4b5563; }}
+  </style>
+  <rect width="100%" height="100%" fill="#ffffff"/>
+{cells}</svg>
+"##,
+        w = width,
+        h = height,
+        cells = cells
+    );
+
+    (svg, missing)
+}
+
+/// The sheet as a one-page PDF laid out like the SVG, one point per pixel.
+/// Captions are set in `font`; without one only the photos are drawn.
+pub fn render_contact_sheet_pdf(
+    entries: &[SheetEntry],
+    columns: u32,
+    size: u32,
+    font: Option<&TitleFont>,
+) -> Result<(Vec<u8>, Vec<String>), String> {
+    let (columns, width, height) = sheet_layout(entries.len(), columns, size);
+    let top = height as f32;
+    let size_pt = size as f32;
+
+    let mut doc = Document::with_version("1.5");
+    let mut xobjects = Dictionary::new();
+    let mut missing = Vec::new();
+    let mut content = format!("1 1 1 rg 0 0 {} {} re f\n", width, height);
+
+    let face = font.map(TitleFont::face).transpose()?;
+    let mut fonts = Dictionary::new();
+    if let Some(font) = font {
+        let font_id = signature_pdf::add_font(&mut doc, font, &caption_lines(entries))?;
+        fonts.set(PDF_FONT_NAME, font_id);
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        let (x, y) = cell_origin(index, columns, size);
+        // PDF measures from the bottom of the page
+        let (left, photo_bottom) = (x as f32, top - (y + size) as f32);
+
+        match entry_thumbnail(entry, size) {
+            Some(jpeg) => {
+                let (image_id, _, _) = signature_pdf::add_image(&mut doc, &jpeg)?;
+                let name = format!("Photo{}", index);
+                xobjects.set(name.as_str(), image_id);
+                content.push_str(&format!(
+                    "q {} 0 0 {} {} {} cm /{} Do Q\n",
+                    size_pt, size_pt, left, photo_bottom, name
+                ));
+            }
+            None => {
+                missing.push(entry.name.clone());
+                content.push_str(&format!(
+                    "0.898 0.906 0.922 rg 0.612 0.639 0.686 RG {} {} {} {} re B\n",
+                    left, photo_bottom, size_pt, size_pt
+                ));
+            }
+        }
+
+        let Some(face) = face.as_ref() else {
+            continue;
+        };
+        let center = left + size_pt / 2.0;
+        for (text, font_size, baseline, color) in [
+            (
+                &entry.name,
+                NAME_FONT_SIZE,
+                NAME_BASELINE,
+                "0.122 0.161 0.216",
+            ),
+            (
+                &entry.subtitle,
+                SUBTITLE_FONT_SIZE,
+                SUBTITLE_BASELINE,
+                "0.294 0.333 0.388",
+            ),
+        ] {
+            if text.is_empty() {
+                continue;
+            }
+            let (hex, text_width) = signature_pdf::encode_line(face, text, font_size);
+            content.push_str(&format!(
+                "BT /{} {} Tf {} rg {:.2} {:.2} Td <{}> Tj ET\n",
+                PDF_FONT_NAME,
+                font_size,
+                color,
+                center - text_width / 2.0,
+                top - (y + size + baseline) as f32,
+                hex
+            ));
+        }
+    }
+
+    let pages_id = doc.new_object_id();
+    let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![
+            Object::Integer(0),
+            Object::Integer(0),
+            Object::Integer(i64::from(width)),
+            Object::Integer(i64::from(height)),
+        ],
+        "Resources" => dictionary! { "XObject" => xobjects, "Font" => fonts },
+        "Contents" => content_id,
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![Object::Reference(page_id)],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    doc.compress();
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output)
+        .map_err(|e| format!("Failed to write contact sheet PDF: {}", e))?;
+    Ok((output, missing))
+}
+
+pub fn generate_contact_sheet(
+    owner_type: &str,
+    ids: &[i32],
+    columns: u32,
+    size: u32,
+    format: SheetFormat,
+) -> Result<ContactSheetReport, String> {
+    validate_layout(columns, size)?;
+    if ids.is_empty() {
+        return Err("Select at least one person for the contact sheet".to_string());
+    }
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    let (entries, not_found) = collect_sheet_entries_with_conn(
+        &conn,
+        file_manager.get_media_directory(),
+        owner_type,
+        ids,
+    )?;
+    if entries.is_empty() {
+        return Err("None of the selected people were found".to_string());
+    }
+
+    let (content, missing_photos) = match format {
+        SheetFormat::Svg => {
+            let (svg, missing) = render_contact_sheet(&entries, columns, size);
+            (svg.into_bytes(), missing)
+        }
+        SheetFormat::Pdf => {
+            let lines = caption_lines(&entries);
+            let font = signature_pdf::find_title_font(TITLE_FONT_CANDIDATES, &lines).ok_or(
+                "No installed font can print the names; install Tahoma or a Thai font such as Noto Sans Thai",
+            )?;
+            render_contact_sheet_pdf(&entries, columns, size, Some(&font))?
+        }
+    };
+
+    let export_dir = storage_paths::get_export_dir()?;
+    fs::create_dir_all(&export_dir)
+        .map_err(|e| format!("Failed to create export directory: {}", e))?;
+    let filename = format!(
+        "contact_sheet_{}_{}.{}",
+        owner_type,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+        format.extension()
+    );
+    let path = export_dir.join(&filename);
+    fs::write(&path, content).map_err(|e| format!("Failed to write contact sheet: {}", e))?;
+
+    logger::info(format!(
+        "Contact sheet generated: {} ({} people)",
+        filename,
+        entries.len()
+    ));
+
+    Ok(ContactSheetReport {
+        filename,
+        path: path.to_string_lossy().to_string(),
+        people: entries.len(),
+        photos: entries.len() - missing_photos.len(),
+        not_found,
+        missing_photos,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use image::{DynamicImage, RgbImage};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sheet_lists_people_in_order() {
+        let conn = create_app_db();
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("avatars")).unwrap();
+        DynamicImage::ImageRgb8(RgbImage::new(40, 20))
+            .save(media.path().join("avatars").join("a.png"))
+            .unwrap();
+        fs::write(media.path().join("avatars").join("broken.png"), b"not png").unwrap();
+
+        for (id, name, rank, avatar) in [
+            (1, "สมชาย <ใจดี>", Some("น.ท."), Some("avatars\\a.png")),
+            (2, "สมหญิง", None, Some("avatars/broken.png")),
+            (3, "มานะ", Some("น.ต."), None),
+        ] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name, rank, avatar_path) VALUES (?, ?, ?, 'h', ?, ?, ?)",
+                rusqlite::params![id, format!("u{}", id), format!("u{}@test.com", id), name, rank, avatar],
+            )
+            .expect("user insert should succeed");
+        }
+
+        let (entries, not_found) =
+            collect_sheet_entries_with_conn(&conn, media.path(), OWNER_USER, &[3, 1, 9, 2])
+                .expect("collect should succeed");
+        assert_eq!(
+            entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![3, 1, 2]
+        );
+        assert_eq!(not_found, vec![9]);
+
+        let (svg, missing) = render_contact_sheet(&entries, 2, 64);
+        assert_eq!(missing, vec!["มานะ".to_string(), "สมหญิง".to_string()]);
+        assert_eq!(svg.matches("data:image/jpeg;base64,").count(), 1);
+        assert!(svg.contains("สมชาย &lt;ใจดี&gt;"));
+        assert!(svg.contains("น.ต."));
+        // 2 columns x 2 rows of 64px photos
+        assert!(svg.contains(r#"width="192" height="280""#));
+
+        assert!(validate_layout(0, 64).is_err());
+        assert!(validate_layout(2, MAX_PHOTO_SIZE + 1).is_err());
+        assert!(collect_sheet_entries_with_conn(&conn, media.path(), "pet", &[1]).is_err());
+
+        let (pdf, missing) =
+            render_contact_sheet_pdf(&entries, 2, 64, None).expect("PDF should render");
+        assert_eq!(missing.len(), 2);
+        let doc = Document::load_mem(&pdf).expect("contact sheet PDF should load");
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let page = doc.get_dictionary(page_id).unwrap();
+        let media_box: Vec<i64> = page
+            .get(b"MediaBox")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_i64().unwrap())
+            .collect();
+        assert_eq!(media_box, vec![0, 0, 192, 280]);
+        let content = String::from_utf8(doc.get_page_content(page_id).unwrap()).unwrap();
+        assert_eq!(content.matches(" Do Q").count(), 1);
+        assert_eq!(content.matches(" re B").count(), 2);
+    }
+}
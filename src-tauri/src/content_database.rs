@@ -84,6 +84,7 @@ pub use media::{
     resolve_image_path, upload_question_image,
 };
 pub mod answers;
+pub mod previews;
 pub use answers::{get_trainee_answers, save_qualifier_assessment, save_trainee_answer};
 pub mod helpers;
 pub use helpers::{
@@ -120,6 +121,12 @@ pub fn get_section_dev_metrics(
     scoring::get_section_dev_metrics(document_id, section_id)
 }
 
+/// First-page PNG thumbnail of a reference file as a data URI
+#[tauri::command]
+pub fn get_attachment_preview(attachment_id: i64) -> Result<Option<String>, String> {
+    previews::get_attachment_preview(attachment_id)
+}
+
 #[tauri::command]
 pub fn clear_all_trainee_answers() -> Result<(), String> {
     answers::clear_all_trainee_answers_inner()
@@ -1,15 +1,10 @@
 use rusqlite::{Connection, Result as SqlResult};
 use std::path::PathBuf;
-use tauri::api::path::app_data_dir;
-use tauri::Config;
 
 /// Get path to the content database file
 pub fn get_content_database_path() -> Result<PathBuf, String> {
-    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
-
-    let db_dir = app_data.join("pqs-rtn-hybrid-storage");
-    std::fs::create_dir_all(&db_dir)
-        .map_err(|e| format!("Failed to create database directory: {}", e))?;
+    // Shared by all workspaces, so it lives in the app root
+    let db_dir = crate::storage_paths::get_app_root()?;
 
     // Using 'content.db' as requested by user
     Ok(db_dir.join("content.db"))
@@ -20,10 +15,7 @@ pub fn get_portable_data_dir() -> Result<std::path::PathBuf, String> {
     // Release Mode: Use exe_dir/data to keep it PORTABLE on USB
 
     if cfg!(debug_assertions) {
-        let app_data =
-            app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
-
-        let dev_storage = app_data.join("pqs-rtn-hybrid-storage").join("data");
+        let dev_storage = crate::storage_paths::get_app_root()?.join("data");
 
         if !dev_storage.exists() {
             std::fs::create_dir_all(&dev_storage).map_err(|e| e.to_string())?;
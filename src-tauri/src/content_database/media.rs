@@ -1,3 +1,4 @@
+use crate::file_transaction;
 use crate::logger;
 use base64::{engine::general_purpose, Engine as _};
 use rusqlite::{params, Connection};
@@ -148,8 +149,14 @@ pub fn upload_question_image(
         target_path.display()
     ));
 
-    std::fs::copy(&source_path, &target_path)
-        .map_err(|e| format!("Failed to copy image: {}", e))?;
+    let data = std::fs::read(&source_path).map_err(|e| format!("Failed to read image: {}", e))?;
+    let mut conn = get_content_connection().map_err(|e| format!("Failed to connect: {}", e))?;
+    // Staged so a failed copy never leaves a partial image behind
+    file_transaction::with_file_and_db_in(
+        &data_dir.join(file_transaction::STAGING_DIR_NAME),
+        &mut conn,
+        |files, _tx| files.write(&target_path, &data),
+    )?;
 
     // Return relative path: data/{document_id}/question-images/{filename}
     // Note: We return the relative path from "data" root or just the portable path string?
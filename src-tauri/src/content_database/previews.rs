@@ -0,0 +1,289 @@
+use crate::logger;
+use base64::{engine::general_purpose, Engine as _};
+use image::ImageOutputFormat;
+use rusqlite::{params, Connection};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::*;
+
+// ============================================================
+// Attachment Previews
+// ============================================================
+//
+// Reference files (PDF, Office documents, images) get a small first-page PNG
+// stored next to them as `<file>.preview.png`, so the UI can show thumbnails
+// without reading whole documents over IPC. PDFs are rendered with Poppler's
+// `pdftoppm` and Office files with LibreOffice; when neither is installed the
+// reference simply has no preview.
+
+/// Longest side of a stored preview, in pixels
+pub const PREVIEW_MAX_DIMENSION: u32 = 320;
+
+const PREVIEW_SUFFIX: &str = ".preview.png";
+
+const OFFICE_EXTENSIONS: &[&str] = &[
+    "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf",
+];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+#[cfg(target_os = "windows")]
+const SOFFICE_CANDIDATES: &[&str] = &[
+    "soffice",
+    r"C:\Program Files\LibreOffice\program\soffice.exe",
+    r"C:\Program Files (x86)\LibreOffice\program\soffice.exe",
+];
+#[cfg(not(target_os = "windows"))]
+const SOFFICE_CANDIDATES: &[&str] = &["soffice", "libreoffice"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PreviewSource {
+    Pdf,
+    Office,
+    Image,
+}
+
+pub(crate) fn preview_source(file: &Path) -> Option<PreviewSource> {
+    let extension = file.extension()?.to_str()?.to_lowercase();
+    if extension == "pdf" {
+        Some(PreviewSource::Pdf)
+    } else if OFFICE_EXTENSIONS.contains(&extension.as_str()) {
+        Some(PreviewSource::Office)
+    } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        Some(PreviewSource::Image)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn preview_path_for(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(PREVIEW_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Run an external renderer; Ok(false) when it is not installed
+fn run_renderer(program: &str, args: &[&std::ffi::OsStr]) -> Result<bool, String> {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => Ok(true),
+        Ok(output) => Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("Failed to run {}: {}", program, e)),
+    }
+}
+
+/// First page of `file` as an image file in `work_dir`, or None without a renderer
+fn render_first_page(
+    file: &Path,
+    source: PreviewSource,
+    work_dir: &Path,
+) -> Result<Option<PathBuf>, String> {
+    match source {
+        PreviewSource::Image => Ok(Some(file.to_path_buf())),
+        PreviewSource::Pdf => {
+            let prefix = work_dir.join("page");
+            let rendered = run_renderer(
+                "pdftoppm",
+                &[
+                    "-png".as_ref(),
+                    "-f".as_ref(),
+                    "1".as_ref(),
+                    "-l".as_ref(),
+                    "1".as_ref(),
+                    "-singlefile".as_ref(),
+                    "-scale-to".as_ref(),
+                    PREVIEW_MAX_DIMENSION.to_string().as_ref(),
+                    file.as_os_str(),
+                    prefix.as_os_str(),
+                ],
+            )?;
+            Ok(rendered.then(|| prefix.with_extension("png")))
+        }
+        PreviewSource::Office => {
+            for program in SOFFICE_CANDIDATES {
+                // LibreOffice's PNG export renders the first page only
+                let rendered = run_renderer(
+                    program,
+                    &[
+                        "--headless".as_ref(),
+                        "--convert-to".as_ref(),
+                        "png".as_ref(),
+                        "--outdir".as_ref(),
+                        work_dir.as_os_str(),
+                        file.as_os_str(),
+                    ],
+                )?;
+                if rendered {
+                    let stem = file
+                        .file_stem()
+                        .ok_or_else(|| "Invalid file name".to_string())?;
+                    return Ok(Some(work_dir.join(stem).with_extension("png")));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn write_preview(
+    file: &Path,
+    source: PreviewSource,
+    work_dir: &Path,
+) -> Result<Option<PathBuf>, String> {
+    let Some(page) = render_first_page(file, source, work_dir)? else {
+        return Ok(None);
+    };
+    let image = image::open(&page).map_err(|e| format!("Failed to read rendered page: {}", e))?;
+    // thumbnail() keeps the aspect ratio within the bounding box
+    let thumbnail = image.thumbnail(PREVIEW_MAX_DIMENSION, PREVIEW_MAX_DIMENSION);
+
+    let mut output = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut output), ImageOutputFormat::Png)
+        .map_err(|e| format!("Failed to encode preview: {}", e))?;
+    let preview_path = preview_path_for(file);
+    std::fs::write(&preview_path, output).map_err(|e| format!("Failed to write preview: {}", e))?;
+    Ok(Some(preview_path))
+}
+
+/// Write the preview of `file`; Ok(None) when the type cannot be previewed
+/// or no renderer for it is installed
+pub(crate) fn generate_preview(file: &Path) -> Result<Option<PathBuf>, String> {
+    let Some(source) = preview_source(file) else {
+        return Ok(None);
+    };
+    if !file.is_file() {
+        return Err(format!("Attachment file not found: {}", file.display()));
+    }
+
+    let work_dir = std::env::temp_dir().join(format!("pqs-preview-{}", generate_uuid()));
+    std::fs::create_dir_all(&work_dir)
+        .map_err(|e| format!("Failed to create preview work directory: {}", e))?;
+    let result = write_preview(file, source, &work_dir);
+    let _ = std::fs::remove_dir_all(&work_dir);
+    result
+}
+
+/// Absolute path of a bundled reference file (`data/...`), None for links
+pub(crate) fn attachment_file_in_dir(data_dir: &Path, file_path: &str) -> Option<PathBuf> {
+    let relative = file_path
+        .strip_prefix("data/")
+        .or_else(|| file_path.strip_prefix("data\\"))?;
+    Some(data_dir.join(relative))
+}
+
+/// Build the preview in the background so saving a reference stays fast
+pub(crate) fn generate_preview_in_background(file_path: Option<&str>) {
+    let Some(file_path) = file_path else {
+        return;
+    };
+    let Ok(data_dir) = get_portable_data_dir() else {
+        return;
+    };
+    let Some(file) = attachment_file_in_dir(&data_dir, file_path) else {
+        return;
+    };
+    if preview_source(&file).is_none() {
+        return;
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = generate_preview(&file) {
+            logger::warn(format!(
+                "Failed to generate preview for {}: {}",
+                file.display(),
+                e
+            ));
+        }
+    });
+}
+
+/// Remove the preview stored for a reference file, if any
+pub(crate) fn remove_preview(file: &Path) {
+    let preview = preview_path_for(file);
+    if preview.exists() {
+        if let Err(e) = std::fs::remove_file(&preview) {
+            logger::warn(format!(
+                "Failed to delete attachment preview {}: {}",
+                preview.display(),
+                e
+            ));
+        }
+    }
+}
+
+/// Bundled reference files that could have a preview but have none yet
+pub(crate) fn files_missing_preview_in_dir(
+    conn: &Connection,
+    data_dir: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let mut stmt = conn
+        .prepare("SELECT file_path FROM DocumentReferences WHERE file_path IS NOT NULL")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let paths = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to query references: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read reference: {}", e))?;
+
+    Ok(paths
+        .iter()
+        .filter_map(|path| attachment_file_in_dir(data_dir, path))
+        .filter(|file| preview_source(file).is_some())
+        .filter(|file| file.is_file() && !preview_path_for(file).is_file())
+        .collect())
+}
+
+pub(crate) fn files_missing_preview() -> Result<Vec<PathBuf>, String> {
+    let conn = get_content_connection().map_err(|e| format!("Failed to connect: {}", e))?;
+    let data_dir = get_portable_data_dir()?;
+    files_missing_preview_in_dir(&conn, &data_dir)
+}
+
+pub(crate) fn get_attachment_preview_in_dir(
+    conn: &Connection,
+    data_dir: &Path,
+    attachment_id: i64,
+) -> Result<Option<String>, String> {
+    let file_path: Option<String> = conn
+        .query_row(
+            "SELECT file_path FROM DocumentReferences WHERE id = ?1",
+            params![attachment_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Reference with ID {} not found: {}", attachment_id, e))?;
+
+    let Some(file) = file_path
+        .as_deref()
+        .and_then(|path| attachment_file_in_dir(data_dir, path))
+    else {
+        return Ok(None);
+    };
+
+    let preview = preview_path_for(&file);
+    // Files bundled before previews existed (or saved while a renderer was
+    // missing) get one on first request
+    if !preview.is_file() && file.is_file() && generate_preview(&file)?.is_none() {
+        return Ok(None);
+    }
+    if !preview.is_file() {
+        return Ok(None);
+    }
+
+    let data = std::fs::read(&preview).map_err(|e| format!("Failed to read preview: {}", e))?;
+    Ok(Some(format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(&data)
+    )))
+}
+
+/// First-page PNG preview of a reference's file as a data URI; None when it has none
+pub fn get_attachment_preview(attachment_id: i64) -> Result<Option<String>, String> {
+    let conn = get_content_connection().map_err(|e| format!("Failed to connect: {}", e))?;
+    let data_dir = get_portable_data_dir()?;
+    get_attachment_preview_in_dir(&conn, &data_dir, attachment_id)
+}
@@ -88,6 +88,7 @@ pub fn create_reference(request: CreateReferenceRequest) -> Result<DocumentRefer
     .map_err(|e| e.to_string())?;
 
     let id = conn.last_insert_rowid();
+    previews::generate_preview_in_background(final_file_path.as_deref());
 
     // Return created reference
     get_reference_by_id(&conn, id)
@@ -201,6 +202,7 @@ pub fn delete_reference(id: i64) -> Result<(), String> {
                             e
                         ));
                     }
+                    previews::remove_preview(&full_path);
 
                     // Optional: Try to remove parent directory if empty (cleanup)
                     if let Some(parent) = full_path.parent() {
@@ -501,6 +503,7 @@ pub fn update_reference(args: UpdateReferenceArgs) -> Result<(), String> {
             final_file_path,            args.id
         ]
     ).map_err(|e| format!("Failed to update reference: {}", e))?;
+    previews::generate_preview_in_background(final_file_path.as_deref());
 
     // 2. Cleanup old file if path changed
     if let Some(old_path) = old_file_path {
@@ -530,6 +533,7 @@ pub fn update_reference(args: UpdateReferenceArgs) -> Result<(), String> {
                             e
                         ));
                     }
+                    previews::remove_preview(&full_path);
 
                     // Optional: Try to remove parent directory if empty
                     if let Some(parent) = full_path.parent() {
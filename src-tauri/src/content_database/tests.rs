@@ -7,6 +7,9 @@ mod tests {
     use crate::content_database::media::{
         bundle_reference_file_in_dir, delete_question_image_in_dir, resolve_image_path_in_dir,
     };
+    use crate::content_database::previews::{
+        get_attachment_preview_in_dir, preview_path_for, PREVIEW_MAX_DIMENSION,
+    };
     use crate::content_database::*;
     use crate::test_helpers::helpers::*;
     use rusqlite::params;
@@ -1445,6 +1448,59 @@ mod tests {
         // When _temp_dir is dropped, file will be cleaned up automatically
     }
 
+    #[test]
+    fn test_attachment_preview_is_generated_and_cached() {
+        let conn = create_test_db();
+        conn.execute(
+            "CREATE TABLE DocumentReferences (id INTEGER PRIMARY KEY, code TEXT, title TEXT, file_path TEXT)",
+            [],
+        )
+        .expect("Failed to create DocumentReferences");
+        let temp_dir = create_temp_dir();
+        let dir = temp_dir
+            .path()
+            .join("COMMON")
+            .join("references")
+            .join("DIAGRAM");
+        std::fs::create_dir_all(&dir).expect("Failed to create reference dir");
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(1280, 640))
+            .save(dir.join("DG-0001_layout.png"))
+            .expect("Failed to write image");
+        std::fs::write(dir.join("DG-0002_notes.txt"), b"notes").expect("Failed to write notes");
+
+        for (id, path) in [
+            (1, "data/COMMON/references/DIAGRAM/DG-0001_layout.png"),
+            (2, "data/COMMON/references/DIAGRAM/DG-0002_notes.txt"),
+            (3, "https://example.com/manual.pdf"),
+        ] {
+            conn.execute(
+                "INSERT INTO DocumentReferences (id, code, title, file_path) VALUES (?1, ?2, 'Ref', ?3)",
+                params![id, format!("R{}", id), path],
+            )
+            .expect("Failed to insert reference");
+        }
+
+        let preview = get_attachment_preview_in_dir(&conn, temp_dir.path(), 1)
+            .expect("Preview lookup should succeed")
+            .expect("Image reference should have a preview");
+        assert!(preview.starts_with("data:image/png;base64,"));
+        let stored = image::open(preview_path_for(&dir.join("DG-0001_layout.png")))
+            .expect("Preview should be stored next to the file");
+        assert_eq!(
+            (stored.width(), stored.height()),
+            (PREVIEW_MAX_DIMENSION, PREVIEW_MAX_DIMENSION / 2)
+        );
+
+        for id in [2, 3] {
+            assert_eq!(
+                get_attachment_preview_in_dir(&conn, temp_dir.path(), id)
+                    .expect("Preview lookup should succeed"),
+                None
+            );
+        }
+        assert!(get_attachment_preview_in_dir(&conn, temp_dir.path(), 99).is_err());
+    }
+
     #[test]
     fn test_bundle_reference_file_in_dir_copies_into_expected_portable_location() {
         let temp_dir = create_temp_dir();
@@ -0,0 +1,284 @@
+//! Aggregated statistics for the home screen
+//!
+//! Collects everything the dashboard needs in a single IPC call instead of
+//! one round-trip per widget.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::activity_log::{self, ActivityEvent};
+use crate::backup_catalog;
+use crate::backup_manager;
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+
+const RECENT_LOGIN_LIMIT: u32 = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleCount {
+    pub role: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MediaUsage {
+    pub avatar_files: u64,
+    pub avatar_bytes: u64,
+    pub high_rank_files: u64,
+    pub high_rank_bytes: u64,
+    pub total_files: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DashboardStats {
+    pub total_users: i64,
+    pub active_users: i64,
+    pub inactive_users: i64,
+    pub users_by_role: Vec<RoleCount>,
+    pub officer_count: i64,
+    pub media_usage: MediaUsage,
+    pub last_backup_at: Option<String>,
+    pub last_backup_filename: Option<String>,
+    pub recent_logins: Vec<ActivityEvent>,
+}
+
+pub fn get_dashboard_stats() -> Result<DashboardStats, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    let backup_dir = backup_manager::get_backup_directory()?;
+
+    build_dashboard_stats(&conn, file_manager.get_media_directory(), &backup_dir)
+}
+
+pub fn build_dashboard_stats(
+    conn: &Connection,
+    media_dir: &Path,
+    backup_dir: &Path,
+) -> Result<DashboardStats, String> {
+    let (active_users, inactive_users) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(CASE WHEN is_active = 1 THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN is_active = 1 THEN 0 ELSE 1 END), 0)
+             FROM users",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )
+        .map_err(|e| format!("Failed to count users: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT role, COUNT(*) FROM users GROUP BY role ORDER BY role")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let users_by_role = stmt
+        .query_map([], |row| {
+            Ok(RoleCount {
+                role: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })
+        .map_err(|e| format!("Failed to count users by role: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read role count: {}", e))?;
+
+    let officer_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM high_ranking_officers", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Failed to count officers: {}", e))?;
+
+    let recent_logins = activity_log::get_recent_events_with_conn(
+        conn,
+        Some(activity_log::EVENT_LOGIN),
+        RECENT_LOGIN_LIMIT,
+    )?;
+
+    let (last_backup_filename, last_backup_at) = match find_latest_backup(backup_dir) {
+        Some((name, at)) => (Some(name), Some(at)),
+        None => (None, None),
+    };
+
+    Ok(DashboardStats {
+        total_users: active_users + inactive_users,
+        active_users,
+        inactive_users,
+        users_by_role,
+        officer_count,
+        media_usage: calculate_media_usage(media_dir),
+        last_backup_at,
+        last_backup_filename,
+        recent_logins,
+    })
+}
+
+/// Walk the media directory and tally files per subfolder
+pub fn calculate_media_usage(media_dir: &Path) -> MediaUsage {
+    let mut usage = MediaUsage::default();
+
+    if !media_dir.exists() {
+        return usage;
+    }
+
+    for entry in WalkDir::new(media_dir).into_iter().flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        usage.total_files += 1;
+        usage.total_bytes += size;
+
+        let subfolder = entry
+            .path()
+            .strip_prefix(media_dir)
+            .ok()
+            .and_then(|rel| rel.components().next())
+            .and_then(|c| c.as_os_str().to_str().map(|s| s.to_string()));
+
+        match subfolder.as_deref() {
+            Some("avatars") => {
+                usage.avatar_files += 1;
+                usage.avatar_bytes += size;
+            }
+            Some("high_ranks") => {
+                usage.high_rank_files += 1;
+                usage.high_rank_bytes += size;
+            }
+            _ => {}
+        }
+    }
+
+    usage
+}
+
+/// Newest backup in the backup directory as (filename, RFC 3339 modified
+/// time); notes, partial downloads and other files there are skipped
+pub fn find_latest_backup(backup_dir: &Path) -> Option<(String, String)> {
+    let entries = fs::read_dir(backup_dir).ok()?;
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if !backup_catalog::is_backup_filename(&filename) {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let modified = metadata.modified().ok()?;
+            Some((filename, modified))
+        })
+        .max_by_key(|(_, modified)| *modified)
+        .map(|(name, modified)| {
+            let modified: chrono::DateTime<chrono::Utc> = modified.into();
+            (name, modified.to_rfc3339())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use rusqlite::params;
+    use tempfile::TempDir;
+
+    fn insert_user(conn: &Connection, username: &str, role: &str, is_active: bool) -> i32 {
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, role, is_active) VALUES (?, ?, 'hash', ?, ?, ?)",
+            params![username, format!("{}@test.com", username), username, role, is_active],
+        )
+        .expect("user insert should succeed");
+        conn.last_insert_rowid() as i32
+    }
+
+    #[test]
+    fn test_dashboard_counts_users_and_officers() {
+        let conn = create_app_db();
+        insert_user(&conn, "admin", "admin", true);
+        insert_user(&conn, "editor1", "editor", true);
+        insert_user(&conn, "visitor1", "visitor", false);
+        conn.execute(
+            "INSERT INTO high_ranking_officers (thai_name, position_thai, position_english) VALUES ('a', 'b', 'c')",
+            [],
+        )
+        .expect("officer insert should succeed");
+
+        let media = TempDir::new().expect("temp dir should be created");
+        let backups = TempDir::new().expect("temp dir should be created");
+        let stats =
+            build_dashboard_stats(&conn, media.path(), backups.path()).expect("stats should build");
+
+        assert_eq!(stats.total_users, 3);
+        assert_eq!(stats.active_users, 2);
+        assert_eq!(stats.inactive_users, 1);
+        assert_eq!(stats.users_by_role.len(), 3);
+        assert_eq!(stats.officer_count, 1);
+        assert!(stats.last_backup_at.is_none());
+    }
+
+    #[test]
+    fn test_dashboard_reports_media_backup_and_logins() {
+        let conn = create_app_db();
+        let admin_id = insert_user(&conn, "admin", "admin", true);
+        activity_log::record_event_with_conn(
+            &conn,
+            activity_log::EVENT_LOGIN,
+            Some(admin_id),
+            Some("admin"),
+            None,
+        )
+        .expect("event should record");
+
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("avatars")).unwrap();
+        fs::create_dir_all(media.path().join("high_ranks")).unwrap();
+        fs::write(media.path().join("avatars").join("a.png"), [0u8; 10]).unwrap();
+        fs::write(media.path().join("high_ranks").join("b.png"), [0u8; 5]).unwrap();
+
+        let backups = TempDir::new().expect("temp dir should be created");
+        fs::write(backups.path().join("hybrid_backup_1.zip"), b"zip").unwrap();
+
+        let stats =
+            build_dashboard_stats(&conn, media.path(), backups.path()).expect("stats should build");
+
+        assert_eq!(stats.media_usage.avatar_files, 1);
+        assert_eq!(stats.media_usage.avatar_bytes, 10);
+        assert_eq!(stats.media_usage.high_rank_bytes, 5);
+        assert_eq!(stats.media_usage.total_bytes, 15);
+        assert_eq!(
+            stats.last_backup_filename.as_deref(),
+            Some("hybrid_backup_1.zip")
+        );
+        assert_eq!(stats.recent_logins.len(), 1);
+        assert_eq!(stats.recent_logins[0].username.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn test_latest_backup_skips_newer_non_backup_files() {
+        let backups = TempDir::new().expect("temp dir should be created");
+        let backup = backups.path().join("hybrid_backup_1.zip");
+        fs::write(&backup, b"zip").unwrap();
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&backup)
+            .and_then(|file| file.set_modified(old))
+            .expect("backup time should be set");
+        for other in [
+            "notes.txt",
+            "hybrid_backup_2.zip.download",
+            "hybrid_backup_1.user_restore.db",
+            "hybrid_backup_3.zip.002",
+        ] {
+            fs::write(backups.path().join(other), b"x").unwrap();
+        }
+
+        let (filename, _) = find_latest_backup(backups.path()).expect("backup should be found");
+        assert_eq!(filename, "hybrid_backup_1.zip");
+    }
+}
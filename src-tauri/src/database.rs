@@ -1,9 +1,12 @@
+use crate::activity_log;
+use crate::error_codes;
 use crate::logger;
-use rusqlite::{params, Connection, Result as SqlResult};
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tauri::api::path::app_data_dir;
-use tauri::Config;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 // use crate::database_logger::{DB_LOGGER, DatabaseOperation}; // DISABLED - logging removed
 
 // Global flag to prevent multiple database initialization
@@ -26,17 +29,143 @@ pub struct User {
     pub avatar_size: Option<i32>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
+    #[serde(default)]
+    pub service_number: Option<String>,
+    /// Bumped on every edit; send it back with an update to detect conflicts
+    #[serde(default)]
+    pub row_version: i64,
+    /// Set by a password reset; cleared once the user picks a new password
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// Name in Latin script; `full_name` is usually Thai
+    #[serde(default)]
+    pub full_name_en: Option<String>,
 }
 
-// SQLite database operations
-pub fn get_database_path() -> Result<PathBuf, String> {
-    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
+/// Main database schema version, stored in PRAGMA user_version by apply_schema
+/// 1: users + high_ranking_officers, 2: activity_log + users.service_number,
+/// 3: user_preferences, 4: change_log + change triggers, 5: saved_views,
+/// 6: avatar_format/avatar_width/avatar_height on users and officers,
+/// 7: ranks + position_templates (dataset packs),
+/// 8: row_version on users and officers (optimistic locking),
+/// 9: users.must_change_password (bulk password reset),
+/// 10: sessions (login tokens),
+/// 11: avatar_status on users and officers (photo review),
+/// 12: officer_signatures (signature blocks),
+/// 13: operations (recent activity panel),
+/// 14: backup_catalog (backup directory listing),
+/// 15: user_drafts (auto-saved profile edits),
+/// 16: display_on_board/avatar_visible on officers (board toggles),
+/// 17: full_name_en on users and officers (English names)
+pub const SCHEMA_VERSION: i32 = 17;
+
+/// How long a statement waits on another connection's lock before SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Account seeded on first run; its password must be rotated (see admin_password)
+pub const DEFAULT_ADMIN_USERNAME: &str = "admin";
+pub const DEFAULT_ADMIN_PASSWORD: &str = "Admin&21";
+
+/// Column list matching `map_user_row` - keep the two in sync
+pub const USER_SELECT_COLUMNS: &str = "id, username, email, password_hash, full_name, rank, role, is_active, avatar_path, avatar_updated_at, avatar_mime, avatar_size, created_at, updated_at, service_number, row_version, must_change_password, full_name_en";
+
+/// Map a row selected with `USER_SELECT_COLUMNS` into a User
+pub fn map_user_row(row: &rusqlite::Row) -> SqlResult<User> {
+    Ok(User {
+        id: Some(row.get(0)?),
+        username: row.get(1)?,
+        email: row.get(2)?,
+        password_hash: row.get(3)?,
+        full_name: row.get(4)?,
+        rank: row.get(5)?,
+        role: row.get(6)?,
+        is_active: row.get(7)?,
+        avatar_path: row.get(8)?,
+        avatar_updated_at: row.get(9)?,
+        avatar_mime: row.get(10)?,
+        avatar_size: row.get(11)?,
+        created_at: row.get(12)?,
+        updated_at: row.get(13)?,
+        service_number: row.get(14)?,
+        row_version: row.get(15)?,
+        must_change_password: row.get(16)?,
+        full_name_en: row.get(17)?,
+    })
+}
+
+/// Source of main database connections
+/// The default (no provider) opens the workspace database file; tests install
+/// an in-memory provider so nothing touches the real app data directory.
+pub trait ConnectionProvider: Send + Sync {
+    fn connect(&self) -> SqlResult<Connection>;
+}
 
-    let db_dir = app_data.join("pqs-rtn-hybrid-storage");
-    std::fs::create_dir_all(&db_dir)
-        .map_err(|e| format!("Failed to create database directory: {}", e))?;
+lazy_static! {
+    static ref CONNECTION_PROVIDER: RwLock<Option<Arc<dyn ConnectionProvider>>> = RwLock::new(None);
+}
+
+/// Install or remove (None) the connection provider
+#[cfg(test)]
+pub fn set_connection_provider(provider: Option<Arc<dyn ConnectionProvider>>) {
+    if let Ok(mut current) = CONNECTION_PROVIDER.write() {
+        *current = provider;
+    }
+    crate::read_cache::invalidate();
+}
 
-    Ok(db_dir.join("database.db"))
+fn provided_connection() -> Option<SqlResult<Connection>> {
+    let provider = CONNECTION_PROVIDER.read().ok()?.clone()?;
+    Some(provider.connect())
+}
+
+pub fn has_connection_provider() -> bool {
+    CONNECTION_PROVIDER
+        .read()
+        .map(|p| p.is_some())
+        .unwrap_or(false)
+}
+
+/// Named shared-cache in-memory database
+/// One connection is held open so the data survives between `connect` calls.
+#[cfg(test)]
+pub struct InMemoryProvider {
+    uri: String,
+    _keep_alive: std::sync::Mutex<Connection>,
+}
+
+#[cfg(test)]
+impl InMemoryProvider {
+    pub fn new(name: &str) -> SqlResult<Self> {
+        let uri = format!("file:{}?mode=memory&cache=shared", name);
+        let keep_alive = Self::open_uri(&uri)?;
+        Ok(InMemoryProvider {
+            uri,
+            _keep_alive: std::sync::Mutex::new(keep_alive),
+        })
+    }
+
+    fn open_uri(uri: &str) -> SqlResult<Connection> {
+        Connection::open_with_flags(
+            uri,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
+                | rusqlite::OpenFlags::SQLITE_OPEN_CREATE
+                | rusqlite::OpenFlags::SQLITE_OPEN_URI,
+        )
+    }
+}
+
+#[cfg(test)]
+impl ConnectionProvider for InMemoryProvider {
+    fn connect(&self) -> SqlResult<Connection> {
+        let conn = Self::open_uri(&self.uri)?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        Ok(conn)
+    }
+}
+
+// SQLite database operations
+pub fn get_database_path() -> Result<PathBuf, String> {
+    crate::storage_paths::get_database_path()
 }
 
 /// Get connection to existing database or create new one
@@ -44,6 +173,10 @@ pub fn get_database_path() -> Result<PathBuf, String> {
 /// Use get_connection_readonly() if you only want to check without creating.
 /// Use get_connection_safe() to prevent accidental database creation.
 pub fn get_connection() -> SqlResult<Connection> {
+    if let Some(conn) = provided_connection() {
+        return conn;
+    }
+
     let db_path = get_database_path().map_err(|e| {
         rusqlite::Error::SqliteFailure(
             rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
@@ -52,6 +185,9 @@ pub fn get_connection() -> SqlResult<Connection> {
     })?;
 
     let conn = Connection::open(db_path)?;
+    crate::db_maintenance::mark_activity();
+    // Wait briefly for short-lived locks instead of failing straight away
+    conn.busy_timeout(BUSY_TIMEOUT)?;
 
     // Enhanced SQLite configuration for desktop performance
     conn.execute("PRAGMA foreign_keys = ON", [])?;
@@ -73,6 +209,10 @@ pub fn get_connection() -> SqlResult<Connection> {
 /// Safe wrapper for get_connection() that checks if database exists first
 /// Returns error if database doesn't exist instead of creating an empty file
 pub fn get_connection_safe() -> SqlResult<Connection> {
+    if let Some(conn) = provided_connection() {
+        return conn;
+    }
+
     let db_path = get_database_path().map_err(|e| {
         rusqlite::Error::SqliteFailure(
             rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
@@ -111,8 +251,10 @@ pub fn get_connection_safe() -> SqlResult<Connection> {
     // Database exists and has content - safe to open
     // Use READWRITE mode (not CREATE) to avoid creating new file if it was deleted
     let conn = Connection::open_with_flags(&db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    crate::db_maintenance::mark_activity();
 
     // Apply same SQLite configuration as get_connection()
+    conn.busy_timeout(BUSY_TIMEOUT)?;
     conn.execute("PRAGMA foreign_keys = ON", [])?;
     conn.execute("PRAGMA synchronous = NORMAL", [])?;
     conn.execute("PRAGMA temp_store = MEMORY", [])?;
@@ -123,6 +265,10 @@ pub fn get_connection_safe() -> SqlResult<Connection> {
 /// Get read-only connection to database WITHOUT creating it if it doesn't exist
 /// Returns error if database doesn't exist
 pub fn get_connection_readonly() -> SqlResult<Connection> {
+    if let Some(conn) = provided_connection() {
+        return conn;
+    }
+
     let db_path = get_database_path().map_err(|e| {
         rusqlite::Error::SqliteFailure(
             rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
@@ -158,24 +304,27 @@ pub fn initialize_database() -> Result<String, String> {
 pub fn check_database_exists_and_valid() -> Result<bool, String> {
     let db_path = get_database_path()?;
 
-    // Check if database file exists FIRST before trying to open it
-    // Important: Connection::open() will CREATE an empty file if it doesn't exist!
-    if !db_path.exists() {
-        return Ok(false);
-    }
+    // A provided (in-memory) database has no file - go straight to the table checks
+    if !has_connection_provider() {
+        // Check if database file exists FIRST before trying to open it
+        // Important: Connection::open() will CREATE an empty file if it doesn't exist!
+        if !db_path.exists() {
+            return Ok(false);
+        }
 
-    // Check if the file has content (not empty)
-    let file_size = std::fs::metadata(&db_path)
-        .map_err(|e| format!("Failed to check database file size: {}", e))?
-        .len();
+        // Check if the file has content (not empty)
+        let file_size = std::fs::metadata(&db_path)
+            .map_err(|e| format!("Failed to check database file size: {}", e))?
+            .len();
 
-    if file_size == 0 {
-        logger::warn("Database file exists but is empty (0 bytes) - removing it");
-        // Delete the empty file so it doesn't interfere with initialization
-        if let Err(e) = std::fs::remove_file(&db_path) {
-            logger::error(format!("Failed to remove empty database file: {}", e));
+        if file_size == 0 {
+            logger::warn("Database file exists but is empty (0 bytes) - removing it");
+            // Delete the empty file so it doesn't interfere with initialization
+            if let Err(e) = std::fs::remove_file(&db_path) {
+                logger::error(format!("Failed to remove empty database file: {}", e));
+            }
+            return Ok(false);
         }
-        return Ok(false);
     }
 
     // Try to connect and check if database is valid
@@ -272,6 +421,43 @@ fn initialize_database_internal() -> Result<String, String> {
         }
     }
 
+    // Create core tables and bring the schema up to date
+    apply_schema(&conn)?;
+
+    // Check if admin user already exists
+    let admin_exists = conn
+        .query_row::<i32, _, _>(
+            "SELECT COUNT(*) FROM users WHERE role = 'admin'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap_or(0)
+        > 0;
+
+    if !admin_exists {
+        // Hash the admin password before storing
+        let admin_password_hash = crate::auth::hash_password(DEFAULT_ADMIN_PASSWORD)
+            .map_err(|e| format!("Failed to hash admin password: {}", e))?;
+
+        // Insert new admin user with hashed password
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, rank, role, is_active) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![DEFAULT_ADMIN_USERNAME, "admin@pqs-rtn.com", admin_password_hash, "System Administrator", "ร.ต.", "admin", true],
+        ).map_err(|e| format!("Failed to insert new admin user: {}", e))?;
+    }
+
+    // Insert default high ranking officers if they don't exist
+    insert_default_high_ranking_officers(&conn)?;
+
+    // Migrate existing plain text passwords to hashed passwords
+    // migrate_plain_text_passwords(&conn)?; // DISABLED - causing issues
+
+    Ok("Database initialized successfully".to_string())
+}
+
+/// Create the core tables (if missing) and apply incremental schema changes
+/// Every step is idempotent so this is safe to run on each startup
+pub fn apply_schema(conn: &Connection) -> Result<(), String> {
     // Create users table with new schema
     // let _ = DB_LOGGER.log_table_change(
     //     DatabaseOperation::CreateTable,
@@ -332,35 +518,104 @@ fn initialize_database_internal() -> Result<String, String> {
     // High ranking avatars table removed - now using file-based storage in media/high_ranks/ folder
     // The high_ranking_officers table has avatar_path field for file-based avatar storage
 
-    // Check if admin user already exists
-    let admin_exists = conn
-        .query_row::<i32, _, _>(
-            "SELECT COUNT(*) FROM users WHERE role = 'admin'",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0)
-        > 0;
+    // Activity log (logins, maintenance runs, admin actions)
+    activity_log::init_activity_log_schema(conn)?;
 
-    if !admin_exists {
-        // Hash the admin password before storing
-        let admin_password_hash = bcrypt::hash("Admin&21", bcrypt::DEFAULT_COST)
-            .map_err(|e| format!("Failed to hash admin password: {}", e))?;
+    // Per-user zoom/theme/language
+    crate::user_preferences::init_user_preferences_schema(conn)?;
 
-        // Insert new admin user with hashed password
+    // Named user list filters, shared by every machine using this database
+    crate::saved_views::init_saved_views_schema(conn)?;
+
+    // Rank and position lookups seeded by dataset packs
+    crate::dataset_pack::init_dataset_pack_schema(conn)?;
+
+    // Login session tokens
+    crate::sessions::init_sessions_schema(conn)?;
+
+    // Signature image and title block per officer
+    crate::officer_signature::init_officer_signatures_schema(conn)?;
+
+    // Backups, restores, imports and cleanups for the recent activity panel
+    crate::operation_history::init_operations_schema(conn)?;
+
+    // Backups seen in the backup directory, including ones moved away since
+    crate::backup_catalog::init_backup_catalog_schema(conn)?;
+
+    // Auto-saved profile edits that survive a crash
+    crate::user_drafts::init_user_drafts_schema(conn)?;
+
+    // Row-level change events for incremental sync (needs the tables above)
+    crate::change_log::init_change_log_schema(conn)?;
+
+    // Columns added after the initial release
+    add_column_if_missing(conn, "users", "service_number", "TEXT")?;
+    add_column_if_missing(
+        conn,
+        "users",
+        "must_change_password",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    for table in ["users", "high_ranking_officers"] {
+        add_column_if_missing(conn, table, "avatar_format", "TEXT")?;
+        add_column_if_missing(conn, table, "avatar_width", "INTEGER")?;
+        add_column_if_missing(conn, table, "avatar_height", "INTEGER")?;
+        add_column_if_missing(conn, table, "row_version", "INTEGER NOT NULL DEFAULT 1")?;
+        add_column_if_missing(conn, table, "avatar_status", "TEXT")?;
+        add_column_if_missing(conn, table, "full_name_en", "TEXT")?;
+    }
+    for column in ["display_on_board", "avatar_visible"] {
+        add_column_if_missing(
+            conn,
+            "high_ranking_officers",
+            column,
+            "INTEGER NOT NULL DEFAULT 1",
+        )?;
+    }
+
+    conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+        .map_err(|e| format!("Failed to record schema version: {}", e))?;
+
+    Ok(())
+}
+
+/// Add a column unless it already exists (SQLite has no ADD COLUMN IF NOT EXISTS)
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| format!("Failed to prepare pragma statement: {}", e))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| format!("Failed to query table info: {}", e))?
+        .flatten()
+        .any(|name| name == column);
+
+    if !exists {
         conn.execute(
-            "INSERT INTO users (username, email, password_hash, full_name, rank, role, is_active) VALUES (?, ?, ?, ?, ?, ?, ?)",
-            params!["admin", "admin@pqs-rtn.com", admin_password_hash, "System Administrator", "ร.ต.", "admin", true],
-        ).map_err(|e| format!("Failed to insert new admin user: {}", e))?;
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )
+        .map_err(|e| format!("Failed to add column {}.{}: {}", table, column, e))?;
     }
 
-    // Insert default high ranking officers if they don't exist
-    insert_default_high_ranking_officers(&conn)?;
+    Ok(())
+}
 
-    // Migrate existing plain text passwords to hashed passwords
-    // migrate_plain_text_passwords(&conn)?; // DISABLED - causing issues
+/// Apply schema changes to an existing database at startup
+/// Does nothing when the database has not been initialized yet
+pub fn migrate_existing_database() -> Result<(), String> {
+    if !check_database_exists_and_valid()? {
+        return Ok(());
+    }
 
-    Ok("Database initialized successfully".to_string())
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    apply_schema(&conn)
 }
 
 // Function to migrate plain text passwords to hashed passwords
@@ -380,24 +635,25 @@ pub fn migrate_plain_text_passwords(conn: &rusqlite::Connection) -> Result<(), S
         return Ok(());
     }
 
-    // Check if there are any users with plain text passwords (not starting with $2b$)
+    // Plain text passwords are the ones no supported hash scheme recognises
     let mut stmt = conn
-        .prepare("SELECT id, password_hash FROM users WHERE password_hash NOT LIKE '$2b$%'")
+        .prepare("SELECT id, password_hash FROM users")
         .map_err(|e| format!("Failed to prepare migration statement: {}", e))?;
 
-    let rows = stmt
+    let plain_rows = stmt
         .query_map([], |row| {
             Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?))
         })
-        .map_err(|e| format!("Failed to query users for migration: {}", e))?;
+        .map_err(|e| format!("Failed to query users for migration: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read user data: {}", e))?
+        .into_iter()
+        .filter(|(_, hash)| crate::auth::scheme_of_hash(hash).is_none());
 
     let mut migrated_count = 0;
-    for row in rows {
-        let (user_id, plain_password) =
-            row.map_err(|e| format!("Failed to read user data: {}", e))?;
-
+    for (user_id, plain_password) in plain_rows {
         // Hash the plain text password
-        let hashed_password = bcrypt::hash(&plain_password, bcrypt::DEFAULT_COST)
+        let hashed_password = crate::auth::hash_password(&plain_password)
             .map_err(|e| format!("Failed to hash password for user {}: {}", user_id, e))?;
 
         // Update the user with hashed password
@@ -423,28 +679,12 @@ pub fn migrate_plain_text_passwords(conn: &rusqlite::Connection) -> Result<(), S
 pub fn get_all_users() -> Result<Vec<User>, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
-    let mut stmt = conn.prepare("SELECT id, username, email, password_hash, full_name, rank, role, is_active, avatar_path, avatar_updated_at, avatar_mime, avatar_size, created_at, updated_at FROM users")
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM users", USER_SELECT_COLUMNS))
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
     let user_iter = stmt
-        .query_map([], |row| {
-            Ok(User {
-                id: Some(row.get(0)?),
-                username: row.get(1)?,
-                email: row.get(2)?,
-                password_hash: row.get(3)?,
-                full_name: row.get(4)?,
-                rank: row.get(5)?,
-                role: row.get(6)?,
-                is_active: row.get(7)?,
-                avatar_path: row.get(8)?,
-                avatar_updated_at: row.get(9)?,
-                avatar_mime: row.get(10)?,
-                avatar_size: row.get(11)?,
-                created_at: row.get(12)?,
-                updated_at: row.get(13)?,
-            })
-        })
+        .query_map([], map_user_row)
         .map_err(|e| format!("Failed to query users: {}", e))?;
 
     let mut users = Vec::new();
@@ -455,30 +695,22 @@ pub fn get_all_users() -> Result<Vec<User>, String> {
     Ok(users)
 }
 
+/// Served from the read cache when looked up within the last few seconds
 pub fn get_user_by_id(id: i32) -> Result<Option<User>, String> {
+    crate::read_cache::user_by_id(id, || load_user_by_id(id))
+}
+
+fn load_user_by_id(id: i32) -> Result<Option<User>, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
-    let mut stmt = conn.prepare("SELECT id, username, email, password_hash, full_name, rank, role, is_active, avatar_path, avatar_updated_at, avatar_mime, avatar_size, created_at, updated_at FROM users WHERE id = ?")
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM users WHERE id = ?",
+            USER_SELECT_COLUMNS
+        ))
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
-    let user = stmt.query_row(params![id], |row| {
-        Ok(User {
-            id: Some(row.get(0)?),
-            username: row.get(1)?,
-            email: row.get(2)?,
-            password_hash: row.get(3)?,
-            full_name: row.get(4)?,
-            rank: row.get(5)?,
-            role: row.get(6)?,
-            is_active: row.get(7)?,
-            avatar_path: row.get(8)?,
-            avatar_updated_at: row.get(9)?,
-            avatar_mime: row.get(10)?,
-            avatar_size: row.get(11)?,
-            created_at: row.get(12)?,
-            updated_at: row.get(13)?,
-        })
-    });
+    let user = stmt.query_row(params![id], map_user_row);
 
     match user {
         Ok(user) => Ok(Some(user)),
@@ -490,27 +722,14 @@ pub fn get_user_by_id(id: i32) -> Result<Option<User>, String> {
 pub fn get_user_by_email(email: &str) -> Result<Option<User>, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
-    let mut stmt = conn.prepare("SELECT id, username, email, password_hash, full_name, rank, role, is_active, avatar_path, avatar_updated_at, avatar_mime, avatar_size, created_at, updated_at FROM users WHERE email = ?")
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM users WHERE email = ? COLLATE NOCASE",
+            USER_SELECT_COLUMNS
+        ))
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
-    let user = stmt.query_row(params![email], |row| {
-        Ok(User {
-            id: Some(row.get(0)?),
-            username: row.get(1)?,
-            email: row.get(2)?,
-            password_hash: row.get(3)?,
-            full_name: row.get(4)?,
-            rank: row.get(5)?,
-            role: row.get(6)?,
-            is_active: row.get(7)?,
-            avatar_path: row.get(8)?,
-            avatar_updated_at: row.get(9)?,
-            avatar_mime: row.get(10)?,
-            avatar_size: row.get(11)?,
-            created_at: row.get(12)?,
-            updated_at: row.get(13)?,
-        })
-    });
+    let user = stmt.query_row(params![email], map_user_row);
 
     match user {
         Ok(user) => Ok(Some(user)),
@@ -519,21 +738,25 @@ pub fn get_user_by_email(email: &str) -> Result<Option<User>, String> {
     }
 }
 
+/// `full_name_en` is optional; blank counts as none
 pub fn create_user(
     username: &str,
     email: &str,
     password_hash: &str,
     full_name: &str,
+    full_name_en: Option<&str>,
     rank: Option<&str>,
     role: &str,
 ) -> Result<User, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
+    let full_name_en = full_name_en.map(str::trim).filter(|s| !s.is_empty());
     conn.execute(
-        "INSERT INTO users (username, email, password_hash, full_name, rank, role, is_active) VALUES (?, ?, ?, ?, ?, ?, ?)",
-        params![username, email, password_hash, full_name, rank, role, true],
-    ).map_err(|e| format!("Failed to create user: {}", e))?;
+        "INSERT INTO users (username, email, password_hash, full_name, full_name_en, rank, role, is_active) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![username, email, password_hash, full_name, full_name_en, rank, role, true],
+    ).map_err(|e| error_codes::describe_sql_error("Failed to create user", &e))?;
+    crate::read_cache::invalidate();
 
     let user_id = conn.last_insert_rowid() as i32;
 
@@ -548,22 +771,44 @@ pub fn create_user(
     get_user_by_id(user_id)?.ok_or_else(|| "Failed to retrieve created user".to_string())
 }
 
+/// Error for an update made against an out-of-date copy of a record; carries
+/// the current record as JSON so the UI can merge
+pub fn row_version_conflict<T: Serialize>(current: &T) -> String {
+    error_codes::with_code(
+        error_codes::ROW_VERSION_CONFLICT,
+        &serde_json::to_string(current).unwrap_or_default(),
+    )
+}
+
+/// `expected_version` is the `row_version` the caller last read; a mismatch
+/// fails with `row_version_conflict` instead of overwriting the other edit.
+/// `full_name_en` None keeps the stored English name, blank clears it.
+#[allow(clippy::too_many_arguments)]
 pub fn update_user(
     id: i32,
     username: &str,
     email: &str,
     password_hash: &str,
     full_name: &str,
+    full_name_en: Option<&str>,
     rank: Option<&str>,
     role: &str,
+    expected_version: Option<i64>,
 ) -> Result<User, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-    conn.execute(
-        "UPDATE users SET username = ?, email = ?, password_hash = ?, full_name = ?, rank = ?, role = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-        params![username, email, password_hash, full_name, rank, role, id],
-    ).map_err(|e| format!("Failed to update user: {}", e))?;
+    let full_name_en = full_name_en.map(str::trim);
+    let updated = conn.execute(
+        "UPDATE users SET username = ?1, email = ?2, password_hash = ?3, full_name = ?4, rank = ?5, role = ?6, full_name_en = CASE WHEN ?9 IS NULL THEN full_name_en ELSE NULLIF(?9, '') END, must_change_password = CASE WHEN password_hash = ?3 THEN must_change_password ELSE 0 END, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?7 AND (?8 IS NULL OR row_version = ?8)",
+        params![username, email, password_hash, full_name, rank, role, id, expected_version, full_name_en],
+    ).map_err(|e| error_codes::describe_sql_error("Failed to update user", &e))?;
+    crate::read_cache::invalidate();
+    if updated == 0 && expected_version.is_some() {
+        if let Some(current) = get_user_by_id(id)? {
+            return Err(row_version_conflict(&current));
+        }
+    }
 
     // Log user update - DISABLED
     // let _ = DB_LOGGER.log_user_operation(
@@ -576,6 +821,58 @@ pub fn update_user(
     get_user_by_id(id)?.ok_or_else(|| "User not found after update".to_string())
 }
 
+pub fn update_user_service_number(
+    id: i32,
+    service_number: Option<&str>,
+    expected_version: Option<i64>,
+) -> Result<User, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+
+    let service_number = service_number.map(str::trim).filter(|s| !s.is_empty());
+    let updated = conn
+        .execute(
+            "UPDATE users SET service_number = ?1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND (?3 IS NULL OR row_version = ?3)",
+            params![service_number, id, expected_version],
+        )
+        .map_err(|e| format!("Failed to update service number: {}", e))?;
+    crate::read_cache::invalidate();
+    if updated == 0 && expected_version.is_some() {
+        if let Some(current) = get_user_by_id(id)? {
+            return Err(row_version_conflict(&current));
+        }
+    }
+
+    get_user_by_id(id)?.ok_or_else(|| "User not found after update".to_string())
+}
+
+/// Set or clear (None or blank) the user's English name; `expected_version`
+/// works as in update_user
+pub fn update_user_full_name_en(
+    id: i32,
+    full_name_en: Option<&str>,
+    expected_version: Option<i64>,
+) -> Result<User, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+
+    let full_name_en = full_name_en.map(str::trim).filter(|s| !s.is_empty());
+    let updated = conn
+        .execute(
+            "UPDATE users SET full_name_en = ?1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND (?3 IS NULL OR row_version = ?3)",
+            params![full_name_en, id, expected_version],
+        )
+        .map_err(|e| format!("Failed to update English name: {}", e))?;
+    crate::read_cache::invalidate();
+    if updated == 0 && expected_version.is_some() {
+        if let Some(current) = get_user_by_id(id)? {
+            return Err(row_version_conflict(&current));
+        }
+    }
+
+    get_user_by_id(id)?.ok_or_else(|| "User not found after update".to_string())
+}
+
 pub fn delete_user(id: i32) -> Result<bool, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
@@ -612,7 +909,8 @@ pub fn delete_user(id: i32) -> Result<bool, String> {
     // Delete user - this should cascade to avatars table
     let rows_affected = conn
         .execute("DELETE FROM users WHERE id = ?", params![id])
-        .map_err(|e| format!("Failed to delete user: {}", e))?;
+        .map_err(|e| error_codes::describe_sql_error("Failed to delete user", &e))?;
+    crate::read_cache::invalidate();
 
     // Avatar cleanup is now handled by file-based storage system
     // No need to manually delete from avatars table since it's removed
@@ -620,44 +918,74 @@ pub fn delete_user(id: i32) -> Result<bool, String> {
     Ok(rows_affected > 0)
 }
 
+/// Move a verified password over to the configured hash scheme and cost; a
+/// failure only means the next login tries again. Skipped in maintenance
+/// mode, which lets sign-ins through but no other changes.
+fn rehash_password_if_needed(conn: &Connection, user: &User, password: &str) {
+    let target = crate::auth::configured_target();
+    if !crate::auth::needs_rehash(&user.password_hash, &target)
+        || crate::maintenance_mode::is_enabled()
+    {
+        return;
+    }
+    // row_version stays: the account did not change for anyone editing it
+    let rehashed = crate::auth::hash_password_with(&target, password).and_then(|hash| {
+        conn.execute(
+            "UPDATE users SET password_hash = ? WHERE id = ? AND password_hash = ?",
+            params![hash, user.id, user.password_hash],
+        )
+        .map_err(|e| format!("Failed to store password hash: {}", e))
+    });
+    match rehashed {
+        Ok(0) => {}
+        Ok(_) => crate::read_cache::invalidate(),
+        Err(e) => logger::warn(format!(
+            "Failed to rehash password of {}: {}",
+            user.username, e
+        )),
+    }
+}
+
+/// Active user signing in as `username_or_email`; emails are stored
+/// lowercased, but accounts from before that may not be, so they match
+/// in any case
+pub fn find_login_user_with_conn(
+    conn: &Connection,
+    username_or_email: &str,
+) -> Result<Option<User>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM users WHERE (email = ?1 COLLATE NOCASE OR username = ?1) AND is_active = 1",
+            USER_SELECT_COLUMNS
+        ),
+        params![username_or_email.trim()],
+        map_user_row,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to query user: {}", e))
+}
+
 pub fn authenticate_user(username_or_email: &str, password: &str) -> Result<Option<User>, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
-    let mut stmt = conn.prepare("SELECT id, username, email, password_hash, full_name, rank, role, is_active, avatar_path, avatar_updated_at, avatar_mime, avatar_size, created_at, updated_at FROM users WHERE (email = ? OR username = ?) AND is_active = 1")
-        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
-
-    let user = stmt.query_row(params![username_or_email, username_or_email], |row| {
-        Ok(User {
-            id: Some(row.get(0)?),
-            username: row.get(1)?,
-            email: row.get(2)?,
-            password_hash: row.get(3)?,
-            full_name: row.get(4)?,
-            rank: row.get(5)?,
-            role: row.get(6)?,
-            is_active: row.get(7)?,
-            avatar_path: row.get(8)?,
-            avatar_updated_at: row.get(9)?,
-            avatar_mime: row.get(10)?,
-            avatar_size: row.get(11)?,
-            created_at: row.get(12)?,
-            updated_at: row.get(13)?,
-        })
-    });
 
-    match user {
-        Ok(user) => {
+    match find_login_user_with_conn(&conn, username_or_email)? {
+        Some(user) => {
             // Verify the provided password against the stored hash
-            if bcrypt::verify(password, &user.password_hash)
-                .map_err(|e| format!("Password verification failed: {}", e))?
-            {
+            if crate::auth::verify_password(password, &user.password_hash)? {
+                rehash_password_if_needed(&conn, &user, password);
+                activity_log::record_event(
+                    activity_log::EVENT_LOGIN,
+                    user.id,
+                    Some(&user.username),
+                    None,
+                );
                 Ok(Some(user))
             } else {
                 Ok(None) // Password does not match
             }
         }
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None), // User not found
-        Err(e) => Err(format!("Failed to query user: {}", e)),
+        None => Ok(None), // User not found
     }
 }
 
@@ -671,6 +999,42 @@ pub struct HighRankingOfficer {
     pub order_index: i32,
     pub created_at: String,
     pub updated_at: String,
+    /// Bumped on every edit; send it back with an update to detect conflicts
+    #[serde(default)]
+    pub row_version: i64,
+    /// Shown on generated boards; off for a position that is vacant for now
+    #[serde(default = "default_true")]
+    pub display_on_board: bool,
+    /// Photo shown on boards and contact sheets; off until one is approved
+    #[serde(default = "default_true")]
+    pub avatar_visible: bool,
+    /// Name in Latin script, shown under the Thai name
+    #[serde(default)]
+    pub full_name_en: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Column list matching `map_officer_row` - keep the two in sync
+pub const OFFICER_SELECT_COLUMNS: &str = "id, thai_name, position_thai, position_english, order_index, created_at, updated_at, row_version, display_on_board, avatar_visible, full_name_en";
+
+/// Map a row selected with `OFFICER_SELECT_COLUMNS` into a HighRankingOfficer
+pub fn map_officer_row(row: &rusqlite::Row) -> SqlResult<HighRankingOfficer> {
+    Ok(HighRankingOfficer {
+        id: Some(row.get(0)?),
+        thai_name: row.get(1)?,
+        position_thai: row.get(2)?,
+        position_english: row.get(3)?,
+        order_index: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        row_version: row.get(7)?,
+        display_on_board: row.get(8)?,
+        avatar_visible: row.get(9)?,
+        full_name_en: row.get(10)?,
+    })
 }
 
 // DEPRECATED: HighRankingAvatar struct removed
@@ -720,26 +1084,24 @@ pub fn insert_default_high_ranking_officers(conn: &rusqlite::Connection) -> Resu
     Ok(())
 }
 
-// Get all high ranking officers
+// Get all high ranking officers (served from the read cache when fresh)
 pub fn get_all_high_ranking_officers() -> Result<Vec<HighRankingOfficer>, String> {
+    crate::read_cache::officers(load_all_high_ranking_officers)
+}
+
+fn load_all_high_ranking_officers() -> Result<Vec<HighRankingOfficer>, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-    let mut stmt = conn.prepare("SELECT id, thai_name, position_thai, position_english, order_index, created_at, updated_at FROM high_ranking_officers ORDER BY order_index")
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM high_ranking_officers ORDER BY order_index",
+            OFFICER_SELECT_COLUMNS
+        ))
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
     let officer_iter = stmt
-        .query_map([], |row| {
-            Ok(HighRankingOfficer {
-                id: Some(row.get(0)?),
-                thai_name: row.get(1)?,
-                position_thai: row.get(2)?,
-                position_english: row.get(3)?,
-                order_index: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
-        })
+        .query_map([], map_officer_row)
         .map_err(|e| format!("Failed to query officers: {}", e))?;
 
     let mut officers = Vec::new();
@@ -750,40 +1112,344 @@ pub fn get_all_high_ranking_officers() -> Result<Vec<HighRankingOfficer>, String
     Ok(officers)
 }
 
-// Update high ranking officer
+// Update high ranking officer; `expected_version` and `full_name_en` work as
+// in update_user
 pub fn update_high_ranking_officer(
     id: i32,
     thai_name: &str,
+    full_name_en: Option<&str>,
     position_thai: &str,
     position_english: &str,
     order_index: i32,
+    expected_version: Option<i64>,
 ) -> Result<HighRankingOfficer, String> {
     let conn =
         get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
     // Update the officer
-    conn.execute(
-        "UPDATE high_ranking_officers SET thai_name = ?, position_thai = ?, position_english = ?, order_index = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-        params![thai_name, position_thai, position_english, order_index, id],
+    let full_name_en = full_name_en.map(str::trim);
+    let updated = conn.execute(
+        "UPDATE high_ranking_officers SET thai_name = ?1, position_thai = ?2, position_english = ?3, order_index = ?4, full_name_en = CASE WHEN ?7 IS NULL THEN full_name_en ELSE NULLIF(?7, '') END, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?5 AND (?6 IS NULL OR row_version = ?6)",
+        params![thai_name, position_thai, position_english, order_index, id, expected_version, full_name_en],
     ).map_err(|e| format!("Failed to update officer: {}", e))?;
+    crate::read_cache::invalidate();
 
-    // Get the updated officer
-    let mut stmt = conn.prepare("SELECT id, thai_name, position_thai, position_english, order_index, created_at, updated_at FROM high_ranking_officers WHERE id = ?")
+    // Get the updated officer (or the current one, on a conflict)
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM high_ranking_officers WHERE id = ?",
+            OFFICER_SELECT_COLUMNS
+        ))
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
     let officer = stmt
-        .query_row(params![id], |row| {
-            Ok(HighRankingOfficer {
-                id: Some(row.get(0)?),
-                thai_name: row.get(1)?,
-                position_thai: row.get(2)?,
-                position_english: row.get(3)?,
-                order_index: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
-        })
+        .query_row(params![id], map_officer_row)
         .map_err(|e| format!("Failed to retrieve updated officer: {}", e))?;
+    if updated == 0 && expected_version.is_some() {
+        return Err(row_version_conflict(&officer));
+    }
+
+    Ok(officer)
+}
+
+/// Set or clear (None or blank) the officer's English name;
+/// `expected_version` works as in update_user
+pub fn update_officer_full_name_en_with_conn(
+    conn: &Connection,
+    id: i32,
+    full_name_en: Option<&str>,
+    expected_version: Option<i64>,
+) -> Result<HighRankingOfficer, String> {
+    let full_name_en = full_name_en.map(str::trim).filter(|s| !s.is_empty());
+    let updated = conn
+        .execute(
+            "UPDATE high_ranking_officers SET full_name_en = ?1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND (?3 IS NULL OR row_version = ?3)",
+            params![full_name_en, id, expected_version],
+        )
+        .map_err(|e| format!("Failed to update English name: {}", e))?;
 
+    let officer = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM high_ranking_officers WHERE id = ?",
+                OFFICER_SELECT_COLUMNS
+            ),
+            params![id],
+            map_officer_row,
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("Officer not found: {}", id),
+            e => format!("Failed to retrieve updated officer: {}", e),
+        })?;
+    if updated == 0 && expected_version.is_some() {
+        return Err(row_version_conflict(&officer));
+    }
     Ok(officer)
 }
+
+pub fn update_officer_full_name_en(
+    id: i32,
+    full_name_en: Option<&str>,
+    expected_version: Option<i64>,
+) -> Result<HighRankingOfficer, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let officer = update_officer_full_name_en_with_conn(&conn, id, full_name_en, expected_version)?;
+    crate::read_cache::invalidate();
+    Ok(officer)
+}
+
+/// Which of an officer's board toggles to change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OfficerBoardFlag {
+    DisplayOnBoard,
+    AvatarVisible,
+}
+
+impl OfficerBoardFlag {
+    fn column(self) -> &'static str {
+        match self {
+            OfficerBoardFlag::DisplayOnBoard => "display_on_board",
+            OfficerBoardFlag::AvatarVisible => "avatar_visible",
+        }
+    }
+}
+
+/// Turn one of the officer's board toggles on or off; the record itself stays
+pub fn set_officer_board_flag_with_conn(
+    conn: &Connection,
+    id: i32,
+    flag: OfficerBoardFlag,
+    enabled: bool,
+) -> Result<HighRankingOfficer, String> {
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE high_ranking_officers SET {} = ?1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                flag.column()
+            ),
+            params![enabled, id],
+        )
+        .map_err(|e| format!("Failed to update officer: {}", e))?;
+    if updated == 0 {
+        return Err(format!("Officer not found: {}", id));
+    }
+
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM high_ranking_officers WHERE id = ?",
+            OFFICER_SELECT_COLUMNS
+        ),
+        params![id],
+        map_officer_row,
+    )
+    .map_err(|e| format!("Failed to retrieve updated officer: {}", e))
+}
+
+pub fn set_officer_board_flag(
+    id: i32,
+    flag: OfficerBoardFlag,
+    enabled: bool,
+) -> Result<HighRankingOfficer, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let officer = set_officer_board_flag_with_conn(&conn, id, flag, enabled)?;
+    crate::read_cache::invalidate();
+    Ok(officer)
+}
+
+/// Give the officers `order_index` 1, 2, 3... in the order of `ids_in_order`,
+/// which must list every officer exactly once. All indexes change in one
+/// transaction, so a failed or concurrent reorder never leaves duplicates.
+pub fn reorder_high_ranking_officers_with_conn(
+    conn: &Connection,
+    ids_in_order: &[i32],
+) -> Result<(), String> {
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    let mut stmt = tx
+        .prepare("SELECT id FROM high_ranking_officers")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let existing = stmt
+        .query_map([], |row| row.get::<_, i32>(0))
+        .map_err(|e| format!("Failed to query officers: {}", e))?
+        .collect::<Result<std::collections::HashSet<_>, _>>()
+        .map_err(|e| format!("Failed to read officer: {}", e))?;
+    drop(stmt);
+
+    let requested: std::collections::HashSet<i32> = ids_in_order.iter().copied().collect();
+    if requested.len() != ids_in_order.len() {
+        return Err("Officer order lists an officer more than once".to_string());
+    }
+    if requested != existing {
+        return Err(format!(
+            "Officer order must list all {} officers exactly once",
+            existing.len()
+        ));
+    }
+
+    for (position, id) in ids_in_order.iter().enumerate() {
+        tx.execute(
+            "UPDATE high_ranking_officers SET order_index = ?1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND order_index != ?1",
+            params![position as i32 + 1, id],
+        )
+        .map_err(|e| format!("Failed to reorder officer {}: {}", id, e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to commit officer order: {}", e))
+}
+
+pub fn reorder_high_ranking_officers(
+    ids_in_order: &[i32],
+) -> Result<Vec<HighRankingOfficer>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    reorder_high_ranking_officers_with_conn(&conn, ids_in_order)?;
+    crate::read_cache::invalidate();
+    drop(conn);
+    get_all_high_ranking_officers()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_reorder_high_ranking_officers() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, order_index)
+                 VALUES (1, 'A', 'P', 'P', 1), (2, 'B', 'P', 'P', 2), (3, 'C', 'P', 'P', 2);",
+        )
+        .expect("rows should insert");
+
+        reorder_high_ranking_officers_with_conn(&conn, &[3, 1, 2]).unwrap();
+        let order: Vec<(i32, i32)> = conn
+            .prepare("SELECT id, order_index FROM high_ranking_officers ORDER BY order_index")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(order, vec![(3, 1), (1, 2), (2, 3)]);
+
+        assert!(reorder_high_ranking_officers_with_conn(&conn, &[1, 2]).is_err());
+        assert!(reorder_high_ranking_officers_with_conn(&conn, &[1, 1, 2, 3]).is_err());
+        assert!(reorder_high_ranking_officers_with_conn(&conn, &[1, 2, 4]).is_err());
+    }
+
+    #[test]
+    fn test_login_matches_email_in_any_case() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES
+                (1, 'somchai', 'somchai@navy.mi.th', 'h', 'A'),
+                (2, 'Legacy', 'Legacy.User@Navy.mi.th', 'h', 'B');",
+        )
+        .expect("users should insert");
+
+        let found = |login: &str| {
+            find_login_user_with_conn(&conn, login)
+                .expect("lookup should succeed")
+                .and_then(|user| user.id)
+        };
+        assert_eq!(found("Somchai@Navy.MI.TH"), Some(1));
+        assert_eq!(found(" legacy.user@navy.mi.th "), Some(2));
+        assert_eq!(found("somchai"), Some(1));
+        assert_eq!(found("SOMCHAI"), None);
+        conn.execute("UPDATE users SET is_active = 0 WHERE id = 1", [])
+            .unwrap();
+        assert_eq!(found("somchai@navy.mi.th"), None);
+    }
+
+    #[test]
+    fn test_sign_in_rehash_waits_for_maintenance_to_end() {
+        let _env = crate::test_support::TestEnvironment::in_memory();
+        let weak = crate::auth::hash_password_with(
+            &crate::auth::HashTarget {
+                scheme: crate::settings::HashScheme::Bcrypt,
+                bcrypt_cost: 4,
+                argon2: crate::settings::Argon2Settings::default(),
+            },
+            "secret-pass",
+        )
+        .unwrap();
+        let user = create_user(
+            "rehash",
+            "rehash@test.local",
+            &weak,
+            "R",
+            None,
+            None,
+            "user",
+        )
+        .expect("user should be created");
+        let stored = || {
+            get_user_by_id(user.id.unwrap())
+                .unwrap()
+                .unwrap()
+                .password_hash
+        };
+
+        crate::maintenance_mode::set_maintenance_mode(true, None).expect("mode should be set");
+        assert!(authenticate_user("rehash", "secret-pass")
+            .unwrap()
+            .is_some());
+        assert_eq!(stored(), weak);
+
+        crate::maintenance_mode::set_maintenance_mode(false, None).expect("mode should clear");
+        assert!(authenticate_user("rehash", "secret-pass")
+            .unwrap()
+            .is_some());
+        assert_ne!(stored(), weak);
+    }
+
+    #[test]
+    fn test_password_migration_leaves_argon2id_and_bcrypt_hashes_alone() {
+        let conn = create_app_db();
+        let target = |scheme| crate::auth::HashTarget {
+            scheme,
+            bcrypt_cost: 4,
+            argon2: crate::settings::Argon2Settings {
+                memory_kib: 8,
+                iterations: 1,
+                parallelism: 1,
+            },
+        };
+        let argon =
+            crate::auth::hash_password_with(&target(crate::settings::HashScheme::Argon2id), "a")
+                .unwrap();
+        let bcrypt =
+            crate::auth::hash_password_with(&target(crate::settings::HashScheme::Bcrypt), "b")
+                .unwrap();
+        let bcrypt_2y = bcrypt.replacen("$2b$", "$2y$", 1);
+        for (id, hash) in [
+            (1, &argon),
+            (2, &bcrypt),
+            (3, &bcrypt_2y),
+            (4, &"plain".to_string()),
+        ] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (?1, ?1, ?1, ?2, 'X')",
+                params![id, hash],
+            )
+            .unwrap();
+        }
+
+        migrate_plain_text_passwords(&conn).expect("migration should run");
+
+        let hash_of = |id: i32| -> String {
+            conn.query_row("SELECT password_hash FROM users WHERE id = ?", [id], |r| {
+                r.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(hash_of(1), argon);
+        assert_eq!(hash_of(2), bcrypt);
+        assert_eq!(hash_of(3), bcrypt_2y);
+        assert!(crate::auth::verify_password("plain", &hash_of(4)).unwrap());
+    }
+}
@@ -1,11 +1,51 @@
 use base64::{engine::general_purpose, Engine as _};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::api::path::app_data_dir;
-use tauri::Config;
+
+use crate::backup_results::{BackupCreated, BackupDeleted, BackupKind, BackupRestored};
+use crate::database_export::{has_updated_at, parse_changed_since, table_columns};
+use crate::hybrid_avatar::HybridAvatarManager;
+use crate::hybrid_high_rank_avatar::HybridHighRankAvatarManager;
+use crate::media_maintenance::{OWNER_OFFICER, OWNER_USER};
+use crate::permissions::Caller;
+use crate::sessions;
+
+/// Per-user tables that are not part of every backup
+const USER_OWNED_TABLES: &[&str] = &["user_drafts", "user_preferences"];
+
+/// Blob-avatar tables of versions before avatars became files, with the
+/// owner their rows belong to and the names the owner id column had. Their
+/// rows are saved as the owner's avatar file instead of restored as a table.
+const LEGACY_AVATAR_TABLES: &[(&str, &str, &[&str])] = &[
+    ("avatars", OWNER_USER, &["user_id"]),
+    (
+        "high_ranking_avatars",
+        OWNER_OFFICER,
+        &["officer_id", "high_ranking_officer_id"],
+    ),
+];
+const LEGACY_AVATAR_DATA_COLUMNS: &[&str] = &["avatar_data", "image_data", "data"];
+const LEGACY_AVATAR_MIME_COLUMNS: &[&str] = &["mime_type", "avatar_mime"];
+
+/// A photo from a legacy blob-avatar table
+struct LegacyAvatar {
+    owner_type: &'static str,
+    owner_id: i32,
+    data: Vec<u8>,
+    mime_type: String,
+}
+
+/// What a JSON restore left to do or could not restore
+#[derive(Default)]
+struct JsonRestore {
+    legacy_avatars: Vec<LegacyAvatar>,
+    warnings: Vec<String>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseBackup {
@@ -13,6 +53,10 @@ pub struct DatabaseBackup {
     pub version: String,
     pub tables: Vec<TableBackup>,
     pub metadata: BackupMetadata,
+    /// Set for an incremental backup: only rows of tables with `updated_at`
+    /// changed at or after this UTC time. Such a backup cannot be restored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_since: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,7 +78,11 @@ pub struct BackupMetadata {
     pub file_size: u64,
 }
 
-pub fn create_backup() -> Result<String, String> {
+/// With `changed_since` (see `database_export::parse_changed_since`) only
+/// rows updated since then are written, for handing data to other systems;
+/// tables without `updated_at` are left out
+pub fn create_backup(changed_since: Option<&str>) -> Result<BackupCreated, String> {
+    let changed_since = changed_since.map(parse_changed_since).transpose()?;
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -44,6 +92,11 @@ pub fn create_backup() -> Result<String, String> {
     let backup_path = get_backup_directory()?.join(&backup_filename);
 
     let db_path = get_database_path()?;
+    // JSON with base64 blobs runs larger than the database file itself
+    crate::disk_space::ensure_free_space(
+        &backup_path,
+        crate::disk_space::file_size(&db_path).saturating_mul(2),
+    )?;
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
 
     let mut backup = DatabaseBackup {
@@ -59,20 +112,30 @@ pub fn create_backup() -> Result<String, String> {
             high_ranking_count: 0,
             file_size: 0,
         },
+        changed_since: changed_since.clone(),
     };
 
     // Get table list
     let tables = get_table_list(&conn)?;
 
     for table_name in tables {
-        let table_backup = backup_table(&conn, &table_name)?;
+        if changed_since.is_some() && !has_updated_at(&conn, &table_name)? {
+            continue;
+        }
+        let table_backup = backup_table(&conn, &table_name, changed_since.as_deref())?;
         backup.metadata.total_rows += table_backup.row_count;
         backup.tables.push(table_backup);
     }
 
     backup.metadata.total_tables = backup.tables.len();
     backup.metadata.user_count = get_table_count(&conn, "users")?;
-    backup.metadata.avatar_count = get_table_count(&conn, "avatars")?;
+    backup.metadata.avatar_count =
+        conn.query_row(
+            "SELECT COUNT(*) FROM users WHERE avatar_path IS NOT NULL",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|e| format!("Failed to count avatars: {}", e))? as usize;
     backup.metadata.high_ranking_count = get_table_count(&conn, "high_ranking_officers")?;
 
     // Write backup to file
@@ -82,15 +145,16 @@ pub fn create_backup() -> Result<String, String> {
     fs::write(&backup_path, backup_json)
         .map_err(|e| format!("Failed to write backup file: {}", e))?;
 
-    backup.metadata.file_size = backup_path
-        .metadata()
-        .map_err(|e| format!("Failed to get file size: {}", e))?
-        .len();
-
-    Ok(format!("Backup created successfully: {}", backup_filename))
+    let mut created = BackupCreated::for_file(BackupKind::Json, &backup_path)?;
+    created.user_count = Some(backup.metadata.user_count);
+    Ok(created)
 }
 
-pub fn restore_backup(backup_filename: &str) -> Result<String, String> {
+/// Photos carried as legacy blobs are stored as uploads by `restored_by`
+pub fn restore_backup(
+    backup_filename: &str,
+    restored_by: Option<Caller>,
+) -> Result<BackupRestored, String> {
     let backup_path = get_backup_directory()?.join(backup_filename);
 
     // Check if backup file exists
@@ -98,57 +162,170 @@ pub fn restore_backup(backup_filename: &str) -> Result<String, String> {
         return Err(format!("Backup file not found: {}", backup_filename));
     }
 
-    // Check file extension to determine restore method
-    if let Some(extension) = backup_path.extension().and_then(|s| s.to_str()) {
+    // Whatever the kind of backup, a failure puts the current database back
+    crate::restore_snapshot::with_restore_snapshot(&get_database_path()?, None, || {
+        restore_backup_at(&backup_path, backup_filename, restored_by)
+    })
+}
+
+fn restore_backup_at(
+    backup_path: &Path,
+    backup_filename: &str,
+    restored_by: Option<Caller>,
+) -> Result<BackupRestored, String> {
+    // Check file extension to determine restore method; files picked on
+    // Windows often come as .DB or .SQL
+    if let Some(extension) = backup_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(str::to_ascii_lowercase)
+    {
         if extension == "db" {
             // Universal SQLite backup - use direct file copy
             return restore_universal_sqlite_backup(backup_filename);
         }
+        if extension == "sql" {
+            // SQL dumps only get their filtered CREATE/INSERT statements run
+            return crate::sql_dump_import::import_sql_dump_at(
+                backup_path,
+                &get_database_path()?,
+                false,
+            )
+            .map(|report| report.restored);
+        }
     }
 
     // JSON backup - use existing method
-    let backup_content = fs::read_to_string(&backup_path)
+    let backup_content = fs::read_to_string(backup_path)
         .map_err(|e| format!("Failed to read backup file: {}", e))?;
 
     // Parse backup
     let backup: DatabaseBackup = serde_json::from_str(&backup_content)
         .map_err(|e| format!("Failed to parse backup file: {}", e))?;
+    if let Some(since) = &backup.changed_since {
+        return Err(format!(
+            "{} only holds rows changed since {} UTC and cannot replace the database",
+            backup_filename, since
+        ));
+    }
 
     // Get database connection
     let db_path = get_database_path()?;
     let mut conn =
         Connection::open(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+    let outcome = restore_json_with_conn(&mut conn, &backup)?;
 
-    // Start transaction
+    // Verify restore by counting users
+    let mut restored = BackupRestored::from_database(BackupKind::Json, backup_filename, &conn)?;
+    restored.warnings = outcome.warnings;
+    for avatar in outcome.legacy_avatars {
+        if let Err(e) = save_legacy_avatar(&avatar, restored_by) {
+            restored.warnings.push(format!(
+                "Photo of {} {} was not restored: {}",
+                avatar.owner_type, avatar.owner_id, e
+            ));
+        }
+    }
+    Ok(restored)
+}
+
+/// Replace the rows of every table the backup and the live database both
+/// have, in one transaction. Tables the live schema no longer has are left
+/// out with a warning, and so are columns; legacy blob avatars are returned
+/// to be saved as files once the owners are back.
+fn restore_json_with_conn(
+    conn: &mut Connection,
+    backup: &DatabaseBackup,
+) -> Result<JsonRestore, String> {
     let tx = conn
         .transaction()
         .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let mut outcome = JsonRestore::default();
+
+    let mut restorable = Vec::new();
+    for table in &backup.tables {
+        if let Some((_, owner_type, id_columns)) = LEGACY_AVATAR_TABLES
+            .iter()
+            .find(|(name, _, _)| *name == table.name)
+        {
+            read_legacy_avatars(table, owner_type, id_columns, &mut outcome)?;
+            continue;
+        }
+        // Sessions of the restored data would sign people in again
+        if table.name == sessions::SESSIONS_TABLE {
+            continue;
+        }
+        let columns = table_columns(&tx, &table.name)?;
+        if columns.is_empty() {
+            outcome.warnings.push(format!(
+                "Table {} no longer exists; its {} rows were not restored",
+                table.name, table.row_count
+            ));
+            continue;
+        }
+        restorable.push((table, columns));
+    }
 
     // Clear existing data
-    tx.execute("DELETE FROM high_ranking_avatars", [])
-        .map_err(|e| format!("Failed to clear high_ranking_avatars: {}", e))?;
-    tx.execute("DELETE FROM high_ranking_officers", [])
-        .map_err(|e| format!("Failed to clear high_ranking_officers: {}", e))?;
-    tx.execute("DELETE FROM avatars", [])
-        .map_err(|e| format!("Failed to clear avatars: {}", e))?;
-    tx.execute("DELETE FROM users", [])
-        .map_err(|e| format!("Failed to clear users: {}", e))?;
+    for (table, _) in &restorable {
+        tx.execute(&format!("DELETE FROM {}", table.name), [])
+            .map_err(|e| format!("Failed to clear {}: {}", table.name, e))?;
+    }
+    // Restored user ids may belong to someone else now; what hangs off the
+    // live users goes unless the backup brings its own
+    if restorable.iter().any(|(table, _)| table.name == "users") {
+        for owned in USER_OWNED_TABLES {
+            let in_backup = restorable.iter().any(|(table, _)| table.name == *owned);
+            if !in_backup && !table_columns(&tx, owned)?.is_empty() {
+                tx.execute(&format!("DELETE FROM {}", owned), [])
+                    .map_err(|e| format!("Failed to clear {}: {}", owned, e))?;
+            }
+        }
+    }
+    if !table_columns(&tx, sessions::SESSIONS_TABLE)?.is_empty() {
+        tx.execute(&format!("DELETE FROM {}", sessions::SESSIONS_TABLE), [])
+            .map_err(|e| format!("Failed to end sessions: {}", e))?;
+    }
 
     // Restore data from backup
-    for table in &backup.tables {
-        restore_table(&tx, table)?;
+    for (table, columns) in &restorable {
+        restore_table(&tx, table, columns, &mut outcome.warnings)?;
     }
 
     // Commit transaction
     tx.commit()
         .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(outcome)
+}
 
-    // Verify restore by counting users
-    let user_count: i64 = conn
-        .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
-        .map_err(|e| format!("Failed to verify restored database: {}", e))?;
+/// Column names in the order the backup wrote its rows, from the table's
+/// schema as it was when the backup was made
+fn backup_columns(table: &TableBackup) -> Result<Vec<String>, String> {
+    let conn = Connection::open_in_memory()
+        .map_err(|e| format!("Failed to open in-memory database: {}", e))?;
+    conn.execute_batch(&table.schema)
+        .map_err(|e| format!("Failed to read schema of {}: {}", table.name, e))?;
+    Ok(table_columns(&conn, &table.name)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
 
-    Ok(format!("✅ JSON backup restored successfully! Found {} users in restored database. Application will refresh automatically.", user_count))
+/// (column, value) pairs of a backup row, written either as an array in
+/// column order or as an object
+fn row_values(row: &Value, columns: &[String]) -> Result<Vec<(String, Value)>, String> {
+    match row {
+        Value::Array(values) => Ok(columns
+            .iter()
+            .cloned()
+            .zip(values.iter().cloned())
+            .collect()),
+        Value::Object(values) => Ok(values
+            .iter()
+            .map(|(column, value)| (column.clone(), value.clone()))
+            .collect()),
+        _ => Err("Invalid row data format".to_string()),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,9 +349,10 @@ pub fn list_backups() -> Result<Vec<BackupInfo>, String> {
 
             if path.is_file() {
                 if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                    if filename.ends_with(".json")
-                        || filename.ends_with(".db")
-                        || filename.ends_with(".sql")
+                    let lowercase = filename.to_ascii_lowercase();
+                    if lowercase.ends_with(".json")
+                        || lowercase.ends_with(".db")
+                        || lowercase.ends_with(".sql")
                     {
                         let metadata = fs::metadata(&path)
                             .map_err(|e| format!("Failed to read file metadata: {}", e))?;
@@ -236,46 +414,36 @@ pub fn list_backups() -> Result<Vec<BackupInfo>, String> {
     Ok(backups)
 }
 
-pub fn delete_backup(backup_filename: &str) -> Result<String, String> {
+pub fn delete_backup(backup_filename: &str) -> Result<BackupDeleted, String> {
     let backup_path = get_backup_directory()?.join(backup_filename);
 
     if !backup_path.exists() {
         return Err(format!("Backup file not found: {}", backup_filename));
     }
 
-    fs::remove_file(&backup_path).map_err(|e| format!("Failed to delete backup file: {}", e))?;
-
-    Ok(format!("Backup deleted successfully: {}", backup_filename))
+    BackupDeleted::delete(&backup_path)
 }
 
 fn get_backup_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    let backup_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
-
-    if !backup_dir.exists() {
-        fs::create_dir_all(&backup_dir)
-            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
-    }
-
-    Ok(backup_dir)
+    crate::storage_paths::get_backup_dir()
 }
 
 fn get_database_path() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    Ok(app_data.join("pqs-rtn-hybrid-storage").join("database.db"))
+    crate::storage_paths::get_database_path()
 }
 
 fn get_table_list(conn: &Connection) -> Result<Vec<String>, String> {
     let mut stmt = conn
-        .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%'")
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' AND name != ?1",
+        )
         .map_err(|e| format!("Failed to prepare table list query: {}", e))?;
 
+    // Session tokens stay with the database they were issued by
     let table_names = stmt
-        .query_map([], |row| row.get::<_, String>(0))
+        .query_map([crate::sessions::SESSIONS_TABLE], |row| {
+            row.get::<_, String>(0)
+        })
         .map_err(|e| format!("Failed to query table names: {}", e))?;
 
     let mut tables = Vec::new();
@@ -286,7 +454,11 @@ fn get_table_list(conn: &Connection) -> Result<Vec<String>, String> {
     Ok(tables)
 }
 
-fn backup_table(conn: &Connection, table_name: &str) -> Result<TableBackup, String> {
+fn backup_table(
+    conn: &Connection,
+    table_name: &str,
+    changed_since: Option<&str>,
+) -> Result<TableBackup, String> {
     // Get table schema
     let schema = conn
         .query_row::<String, _, _>(
@@ -297,12 +469,17 @@ fn backup_table(conn: &Connection, table_name: &str) -> Result<TableBackup, Stri
         .map_err(|e| format!("Failed to get table schema for {}: {}", table_name, e))?;
 
     // Get table data
+    let filter = if changed_since.is_some() {
+        " WHERE updated_at >= ?1"
+    } else {
+        ""
+    };
     let mut stmt = conn
-        .prepare(&format!("SELECT * FROM {}", table_name))
+        .prepare(&format!("SELECT * FROM {}{}", table_name, filter))
         .map_err(|e| format!("Failed to prepare data query for {}: {}", table_name, e))?;
 
     let rows = stmt
-        .query_map([], |row| {
+        .query_map(rusqlite::params_from_iter(changed_since), |row| {
             let mut values = Vec::new();
             let mut i = 0;
             loop {
@@ -355,41 +532,70 @@ fn backup_table(conn: &Connection, table_name: &str) -> Result<TableBackup, Stri
     })
 }
 
-fn restore_table(tx: &rusqlite::Transaction, table: &TableBackup) -> Result<(), String> {
-    // Recreate table schema
-    tx.execute(&table.schema, [])
-        .map_err(|e| format!("Failed to recreate table {}: {}", table.name, e))?;
+/// Insert the backup's rows of `table` into the live table with `columns`;
+/// values of columns the live table does not have are dropped
+fn restore_table(
+    tx: &rusqlite::Transaction,
+    table: &TableBackup,
+    columns: &[(String, String)],
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    let backup_columns = backup_columns(table)?;
+    // Live column name -> whether it holds blobs, which the backup wrote as base64
+    let live: HashMap<&str, bool> = columns
+        .iter()
+        .map(|(name, declared)| (name.as_str(), declared.eq_ignore_ascii_case("BLOB")))
+        .collect();
+    let dropped: Vec<&str> = backup_columns
+        .iter()
+        .map(String::as_str)
+        .filter(|column| !live.contains_key(column))
+        .collect();
+    if !dropped.is_empty() && !table.data.is_empty() {
+        warnings.push(format!(
+            "Columns {} of table {} no longer exist and were not restored",
+            dropped.join(", "),
+            table.name
+        ));
+    }
 
     // Insert data
     for row_value in &table.data {
-        let values = row_value
-            .as_array()
-            .ok_or_else(|| "Invalid row data format".to_string())?;
+        let values: Vec<(String, Value)> = row_values(row_value, &backup_columns)?
+            .into_iter()
+            .filter(|(column, _)| live.contains_key(column.as_str()))
+            .collect();
 
         if values.is_empty() {
             continue;
         }
 
+        let names: Vec<&str> = values.iter().map(|(column, _)| column.as_str()).collect();
         let placeholders = vec!["?"; values.len()].join(", ");
-        let insert_sql = format!("INSERT INTO {} VALUES ({})", table.name, placeholders);
+        let insert_sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table.name,
+            names.join(", "),
+            placeholders
+        );
 
         let mut stmt = tx
-            .prepare(&insert_sql)
+            .prepare_cached(&insert_sql)
             .map_err(|e| format!("Failed to prepare insert statement: {}", e))?;
 
         let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-        for value in values {
+        for (column, value) in &values {
             match value {
                 serde_json::Value::String(s) => {
-                    // Decode base64 for BLOBs (avatars)
-                    if table.name == "avatars" && s.len() > 100 {
-                        if let Ok(decoded_bytes) = general_purpose::STANDARD.decode(s) {
-                            params.push(Box::new(decoded_bytes));
-                        } else {
-                            params.push(Box::new(s.clone()));
-                        }
+                    // Decode base64 for BLOBs
+                    let decoded = if live[column.as_str()] {
+                        general_purpose::STANDARD.decode(s).ok()
                     } else {
-                        params.push(Box::new(s.clone()));
+                        None
+                    };
+                    match decoded {
+                        Some(decoded_bytes) => params.push(Box::new(decoded_bytes)),
+                        None => params.push(Box::new(s.clone())),
                     }
                 }
                 serde_json::Value::Number(n) => {
@@ -415,6 +621,91 @@ fn restore_table(tx: &rusqlite::Transaction, table: &TableBackup) -> Result<(),
     Ok(())
 }
 
+/// Collect the photos of a legacy blob-avatar table; rows without an owner
+/// id or image data are skipped with a warning
+fn read_legacy_avatars(
+    table: &TableBackup,
+    owner_type: &'static str,
+    id_columns: &[&str],
+    outcome: &mut JsonRestore,
+) -> Result<(), String> {
+    let columns = backup_columns(table)?;
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find(|name| columns.iter().any(|column| column == **name))
+            .map(|name| name.to_string())
+    };
+    let (id_column, data_column) = match (find(id_columns), find(LEGACY_AVATAR_DATA_COLUMNS)) {
+        (Some(id_column), Some(data_column)) => (id_column, data_column),
+        _ => {
+            outcome.warnings.push(format!(
+                "Table {} has no owner id or image column; its {} photos were not restored",
+                table.name, table.row_count
+            ));
+            return Ok(());
+        }
+    };
+    let mime_column = find(LEGACY_AVATAR_MIME_COLUMNS);
+
+    let mut skipped = 0;
+    for row in &table.data {
+        let values: HashMap<String, Value> = row_values(row, &columns)?.into_iter().collect();
+        let owner_id = values.get(&id_column).and_then(Value::as_i64);
+        let data = values
+            .get(&data_column)
+            .and_then(Value::as_str)
+            .and_then(|encoded| general_purpose::STANDARD.decode(encoded).ok());
+        let (owner_id, data) = match (owner_id, data) {
+            (Some(owner_id), Some(data)) if !data.is_empty() => (owner_id as i32, data),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        // Old rows did not always record the type; saving goes by the bytes anyway
+        let mime_type = mime_column
+            .as_ref()
+            .and_then(|column| values.get(column))
+            .and_then(Value::as_str)
+            .unwrap_or("image/png")
+            .to_string();
+        outcome.legacy_avatars.push(LegacyAvatar {
+            owner_type,
+            owner_id,
+            data,
+            mime_type,
+        });
+    }
+    if skipped > 0 {
+        outcome.warnings.push(format!(
+            "{} rows of table {} had no owner id or image data",
+            skipped, table.name
+        ));
+    }
+    Ok(())
+}
+
+/// Save a legacy blob avatar as its owner's avatar file
+fn save_legacy_avatar(avatar: &LegacyAvatar, restored_by: Option<Caller>) -> Result<(), String> {
+    if avatar.owner_type == OWNER_USER {
+        HybridAvatarManager::new()?.save_avatar(
+            avatar.owner_id,
+            &avatar.data,
+            &avatar.mime_type,
+            restored_by,
+        )?;
+    } else {
+        HybridHighRankAvatarManager::new()?.save_avatar(
+            avatar.owner_id,
+            &avatar.data,
+            &avatar.mime_type,
+            restored_by,
+        )?;
+    }
+    Ok(())
+}
+
 fn get_table_count(conn: &Connection, table_name: &str) -> Result<usize, String> {
     let count: i64 = conn
         .query_row(&format!("SELECT COUNT(*) FROM {}", table_name), [], |row| {
@@ -425,12 +716,13 @@ fn get_table_count(conn: &Connection, table_name: &str) -> Result<usize, String>
     Ok(count as usize)
 }
 
-fn restore_universal_sqlite_backup(backup_filename: &str) -> Result<String, String> {
+fn restore_universal_sqlite_backup(backup_filename: &str) -> Result<BackupRestored, String> {
     let backup_path = get_backup_directory()?.join(backup_filename);
     let db_path = get_database_path()?;
 
     // Create backup of current database (only if it exists and has content)
     let current_backup_path = db_path.with_extension("backup");
+    let mut previous_database = None;
     if db_path.exists() {
         let metadata = fs::metadata(&db_path)
             .map_err(|e| format!("Failed to get database metadata: {}", e))?;
@@ -439,6 +731,7 @@ fn restore_universal_sqlite_backup(backup_filename: &str) -> Result<String, Stri
         if metadata.len() > 0 {
             fs::copy(&db_path, &current_backup_path)
                 .map_err(|e| format!("Failed to backup current database: {}", e))?;
+            previous_database = Some(current_backup_path.to_string_lossy().to_string());
         }
     }
 
@@ -450,9 +743,108 @@ fn restore_universal_sqlite_backup(backup_filename: &str) -> Result<String, Stri
         .map_err(|e| format!("Failed to open restored database: {}", e))?;
 
     // Test basic functionality
-    let user_count: i64 = conn
-        .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
-        .map_err(|e| format!("Failed to verify restored database: {}", e))?;
+    let mut restored = BackupRestored::from_database(BackupKind::Sqlite, backup_filename, &conn)?;
+    restored.previous_database = previous_database;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use serde_json::json;
+
+    fn table(name: &str, schema: &str, data: Vec<Value>) -> TableBackup {
+        TableBackup {
+            name: name.to_string(),
+            schema: schema.to_string(),
+            row_count: data.len(),
+            data,
+        }
+    }
 
-    Ok(format!("✅ Database restored successfully! Found {} users in restored database. Application will refresh automatically.", user_count))
+    fn backup(tables: Vec<TableBackup>) -> DatabaseBackup {
+        DatabaseBackup {
+            timestamp: 1,
+            version: "1.0".to_string(),
+            tables,
+            metadata: BackupMetadata {
+                created_at: "2024-01-01T00:00:00+00:00".to_string(),
+                total_tables: 0,
+                total_rows: 0,
+                user_count: 0,
+                avatar_count: 0,
+                high_ranking_count: 0,
+                file_size: 0,
+            },
+            changed_since: None,
+        }
+    }
+
+    #[test]
+    fn test_json_restore_follows_the_live_schema() {
+        let mut conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('live', 'live@test.com', 'h', 'Live')",
+            [],
+        )
+        .unwrap();
+
+        let photo = general_purpose::STANDARD.encode(b"\x89PNG photo");
+        let old = backup(vec![
+            table(
+                "users",
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT, email TEXT, password_hash TEXT, full_name TEXT, nickname TEXT)",
+                vec![json!([7, "old", "old@test.com", "h", "Old", "Oldie"])],
+            ),
+            table(
+                "avatars",
+                "CREATE TABLE avatars (id INTEGER PRIMARY KEY, user_id INTEGER, avatar_data BLOB, mime_type TEXT)",
+                vec![json!([1, 7, photo, "image/png"]), json!([2, null, null, null])],
+            ),
+            table(
+                "high_ranking_avatars",
+                "CREATE TABLE high_ranking_avatars (id INTEGER PRIMARY KEY, officer_id INTEGER, avatar_data BLOB)",
+                vec![],
+            ),
+            table("retired", "CREATE TABLE retired (id INTEGER)", vec![json!([1])]),
+        ]);
+
+        conn.execute_batch(
+            "INSERT INTO user_preferences (user_id, theme) VALUES (1, 'dark');
+             INSERT INTO user_drafts (user_id, fields) VALUES (1, '{}');
+             INSERT INTO sessions (token_hash, user_id, expires_at) VALUES ('t', 1, '2999-01-01');",
+        )
+        .unwrap();
+
+        let outcome = restore_json_with_conn(&mut conn, &old).expect("restore should succeed");
+        for live_only in ["user_preferences", "user_drafts", "sessions"] {
+            let rows: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", live_only), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(rows, 0, "{} should be cleared", live_only);
+        }
+
+        let users: Vec<(i64, String, Option<String>)> = conn
+            .prepare("SELECT id, username, avatar_path FROM users")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(users, vec![(7, "old".to_string(), None)]);
+
+        assert_eq!(outcome.legacy_avatars.len(), 1);
+        let avatar = &outcome.legacy_avatars[0];
+        assert_eq!((avatar.owner_type, avatar.owner_id), (OWNER_USER, 7));
+        assert_eq!(avatar.data, b"\x89PNG photo");
+        assert_eq!(avatar.mime_type, "image/png");
+
+        let warnings = outcome.warnings.join("\n");
+        assert!(warnings.contains("nickname"));
+        assert!(warnings.contains("1 rows of table avatars"));
+        assert!(warnings.contains("Table retired no longer exists"));
+    }
 }
@@ -1,10 +1,19 @@
-use rusqlite::Connection;
+use crate::change_log::json_value;
+use crate::export_encryption;
+use crate::logger;
+use crate::long_path;
+use crate::progress::{ProgressReporter, ROW_REPORT_INTERVAL};
+use crate::validation;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{Connection, ToSql};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::api::path::app_data_dir;
-use tauri::Config;
+use zip::write::FileOptions;
+use zip::ZipWriter;
 
 // Export formats
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +31,10 @@ pub struct DatabaseExport {
     pub version: String,
     pub tables: Vec<TableExport>,
     pub metadata: ExportMetadata,
+    /// Set for an incremental export: only rows with `updated_at` at or
+    /// after this UTC time ("YYYY-MM-DD HH:MM:SS"); importing merges them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_since: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +43,52 @@ pub struct TableExport {
     pub data: Vec<serde_json::Value>,
     pub schema: String,
     pub row_count: usize,
+    /// In table order; empty in exports written before columns were recorded
+    #[serde(default)]
+    pub columns: Vec<ExportColumn>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExportColumn {
+    pub name: String,
+    /// As declared in the schema ("INTEGER", "TEXT", "BLOB", ...)
+    pub declared_type: String,
+}
+
+impl TableExport {
+    /// Column names in table order, or the first row's keys for old exports
+    fn column_names(&self) -> Vec<String> {
+        if !self.columns.is_empty() {
+            return self.columns.iter().map(|c| c.name.clone()).collect();
+        }
+        self.data
+            .first()
+            .and_then(|row| row.as_object())
+            .map(|obj| obj.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether `column` holds blobs, which are exported as base64 strings
+    fn is_blob_column(&self, column: &str) -> bool {
+        self.columns
+            .iter()
+            .any(|c| c.name == column && c.declared_type.eq_ignore_ascii_case("BLOB"))
+    }
+}
+
+/// What an import does with rows that are already in the database
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Empty each table, then insert every row
+    #[default]
+    Replace,
+    /// Update rows that match an existing one by natural key (username or
+    /// email, rank abbreviation, ...) and insert the rest; tables without
+    /// one are left as they are
+    Merge,
+    /// Like merge, but rows that match are left as they are
+    SkipExisting,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,12 +122,13 @@ pub fn export_sql_directly(destination_path: &str) -> Result<String, String> {
             file_size: 0,
             format: "Sql".to_string(),
         },
+        changed_since: None,
     };
 
     // Export all tables
     let table_names = vec!["users", "high_ranking_officers"];
     for table_name in table_names {
-        let table_export = export_table(&conn, table_name)?;
+        let table_export = export_table(&conn, table_name, None, &ProgressReporter::noop())?;
         export.tables.push(table_export);
     }
 
@@ -80,7 +140,7 @@ pub fn export_sql_directly(destination_path: &str) -> Result<String, String> {
     let sql_content = export_to_sql(&export)?;
 
     // Write directly to destination
-    fs::write(destination_path, sql_content)
+    fs::write(long_path::for_io(Path::new(destination_path)), sql_content)
         .map_err(|e| format!("Failed to write SQL file: {}", e))?;
 
     Ok(format!(
@@ -89,7 +149,75 @@ pub fn export_sql_directly(destination_path: &str) -> Result<String, String> {
     ))
 }
 
-pub fn export_database(format: ExportFormat) -> Result<String, String> {
+/// UTC time in SQLite's CURRENT_TIMESTAMP form for an incremental export.
+/// Accepts a local date ("2024-05-01", from local midnight), an RFC 3339
+/// time, or "YYYY-MM-DD HH:MM:SS" taken as UTC like the stored values.
+pub fn parse_changed_since(since: &str) -> Result<String, String> {
+    const SQLITE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+    let since = since.trim();
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(since) {
+        return Ok(time
+            .with_timezone(&chrono::Utc)
+            .format(SQLITE_FORMAT)
+            .to_string());
+    }
+    if let Ok(time) = chrono::NaiveDateTime::parse_from_str(since, SQLITE_FORMAT) {
+        return Ok(time.format(SQLITE_FORMAT).to_string());
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        use chrono::TimeZone;
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        if let Some(local) = chrono::Local.from_local_datetime(&midnight).earliest() {
+            return Ok(local
+                .with_timezone(&chrono::Utc)
+                .format(SQLITE_FORMAT)
+                .to_string());
+        }
+    }
+    Err(format!(
+        "Invalid date '{}': use YYYY-MM-DD or YYYY-MM-DD HH:MM:SS",
+        since
+    ))
+}
+
+/// Whether `table` has an `updated_at` column to filter an incremental export on
+/// (name, declared type) of each column of `table`; empty when there is no such table
+pub fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(String, String)>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    let columns = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    Ok(columns)
+}
+
+pub fn has_updated_at(conn: &Connection, table: &str) -> Result<bool, String> {
+    let count: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = 'updated_at'",
+            [table],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    Ok(count > 0)
+}
+
+/// Export with per-table progress reporting; stops early when cancelled
+/// A CSV export is a `.csv.zip` with one file per table (see `export_to_csv_zip`).
+/// With a passphrase the file is written inside an AES-256 zip
+/// (`database_export_<ts>.<ext>.zip`) and never touches the disk in plaintext.
+/// With `changed_since` (see `parse_changed_since`) only rows updated since
+/// then are written, as `database_changes_<ts>.<ext>`.
+pub fn export_database_with_progress(
+    format: ExportFormat,
+    passphrase: Option<&str>,
+    changed_since: Option<&str>,
+    progress: &ProgressReporter,
+) -> Result<String, String> {
+    let changed_since = changed_since.map(parse_changed_since).transpose()?;
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -97,11 +225,20 @@ pub fn export_database(format: ExportFormat) -> Result<String, String> {
 
     let extension = match format {
         ExportFormat::Json => "json",
-        ExportFormat::Csv => "csv",
+        ExportFormat::Csv => "csv.zip",
         ExportFormat::Sql => "sql",
     };
 
-    let export_filename = format!("database_export_{}.{}", timestamp, extension);
+    let prefix = if changed_since.is_some() {
+        "database_changes"
+    } else {
+        "database_export"
+    };
+    let content_filename = format!("{}_{}.{}", prefix, timestamp, extension);
+    let export_filename = match passphrase {
+        Some(_) => format!("{}.zip", content_filename),
+        None => content_filename.clone(),
+    };
     let export_path = get_export_directory()?.join(&export_filename);
 
     // Get database connection
@@ -121,12 +258,13 @@ pub fn export_database(format: ExportFormat) -> Result<String, String> {
             file_size: 0,
             format: format!("{:?}", format),
         },
+        changed_since: changed_since.clone(),
     };
 
     // Export all tables
     let table_names = vec!["users", "high_ranking_officers"];
     for table_name in table_names {
-        let table_export = export_table(&conn, table_name)?;
+        let table_export = export_table(&conn, table_name, changed_since.as_deref(), progress)?;
         export.tables.push(table_export);
     }
 
@@ -134,24 +272,27 @@ pub fn export_database(format: ExportFormat) -> Result<String, String> {
     export.metadata.total_tables = export.tables.len();
     export.metadata.total_rows = export.tables.iter().map(|t| t.row_count).sum();
 
-    // Write export file based on format
-    match format {
-        ExportFormat::Json => {
-            let json_content = serde_json::to_string_pretty(&export)
-                .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
-            fs::write(&export_path, json_content)
-                .map_err(|e| format!("Failed to write JSON file: {}", e))?;
-        }
-        ExportFormat::Csv => {
-            let csv_content = export_to_csv(&export)?;
-            fs::write(&export_path, csv_content)
-                .map_err(|e| format!("Failed to write CSV file: {}", e))?;
-        }
-        ExportFormat::Sql => {
-            let sql_content = export_to_sql(&export)?;
-            fs::write(&export_path, sql_content)
-                .map_err(|e| format!("Failed to write SQL file: {}", e))?;
-        }
+    // Last chance to cancel before anything is written to disk
+    progress.check_cancelled()?;
+
+    // Render export content based on format
+    let content = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&export)
+            .map_err(|e| format!("Failed to serialize JSON: {}", e))?
+            .into_bytes(),
+        ExportFormat::Csv => export_to_csv_zip(&export)?,
+        ExportFormat::Sql => export_to_sql(&export)?.into_bytes(),
+    };
+
+    match passphrase {
+        Some(passphrase) => export_encryption::write_encrypted_zip(
+            &export_path,
+            &content_filename,
+            &content,
+            passphrase,
+        )?,
+        None => fs::write(&export_path, content)
+            .map_err(|e| format!("Failed to write {} file: {}", extension, e))?,
     }
 
     // Update file size
@@ -159,10 +300,34 @@ pub fn export_database(format: ExportFormat) -> Result<String, String> {
         .map_err(|e| format!("Failed to get file size: {}", e))?
         .len();
 
+    progress.finish(export.metadata.total_rows as u64);
+
     Ok(format!("Export created successfully: {}", export_filename))
 }
 
-pub fn import_database(import_filename: &str) -> Result<String, String> {
+/// Row counts of one table before and after a rehearsed import
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableImportStats {
+    pub table: String,
+    pub rows_before: i64,
+    pub rows_after: i64,
+}
+
+/// Outcome of an import that ran to the end and was then rolled back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRehearsal {
+    pub import_filename: String,
+    pub would_succeed: bool,
+    /// First error the real import would stop at (e.g. a constraint violation)
+    pub error: Option<String>,
+    pub tables: Vec<TableImportStats>,
+    /// Rows left pointing at missing parents, from PRAGMA foreign_key_check
+    pub foreign_key_violations: Vec<String>,
+    pub duration_ms: u64,
+}
+
+/// Format (from the extension) and content of a file in the export directory
+fn read_import_file(import_filename: &str) -> Result<(ExportFormat, Vec<u8>), String> {
     let import_path = get_export_directory()?.join(import_filename);
 
     // Check if import file exists
@@ -171,19 +336,68 @@ pub fn import_database(import_filename: &str) -> Result<String, String> {
     }
 
     // Determine format from file extension
-    let format = match import_path.extension().and_then(|s| s.to_str()) {
-        Some("json") => ExportFormat::Json,
-        Some("csv") => ExportFormat::Csv,
-        Some("sql") => ExportFormat::Sql,
-        _ => return Err("Unsupported file format".to_string()),
-    };
+    let format =
+        match import_path.extension().and_then(|s| s.to_str()) {
+            Some("json") => ExportFormat::Json,
+            Some("zip") if import_filename.to_ascii_lowercase().ends_with(".csv.zip") => {
+                ExportFormat::Csv
+            }
+            Some("csv") => return Err(
+                "Single-file CSV exports cannot be imported; export again as CSV to get a .csv.zip"
+                    .to_string(),
+            ),
+            Some("sql") => ExportFormat::Sql,
+            _ => return Err("Unsupported file format".to_string()),
+        };
 
     // Read import file
-    let import_content = fs::read_to_string(&import_path)
-        .map_err(|e| format!("Failed to read import file: {}", e))?;
+    let import_content =
+        fs::read(&import_path).map_err(|e| format!("Failed to read import file: {}", e))?;
+
+    Ok((format, import_content))
+}
+
+fn run_import(
+    tx: &rusqlite::Transaction,
+    format: &ExportFormat,
+    import_content: &[u8],
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    match format {
+        ExportFormat::Json => {
+            let export: DatabaseExport = serde_json::from_slice(import_content)
+                .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+            import_from_json(tx, &export, mode, progress)
+        }
+        ExportFormat::Csv => import_from_csv(tx, import_content, mode, progress),
+        ExportFormat::Sql if mode != ImportMode::Replace => {
+            Err("SQL files can only be imported in replace mode".to_string())
+        }
+        ExportFormat::Sql => {
+            let sql = std::str::from_utf8(import_content)
+                .map_err(|e| format!("SQL file is not valid UTF-8: {}", e))?;
+            import_from_sql(tx, sql, progress)
+        }
+    }
+}
+
+/// Import with progress reporting; a cancelled import rolls back the transaction
+/// `mode` decides whether existing rows are replaced, merged or kept
+pub fn import_database_with_progress(
+    import_filename: &str,
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<String, String> {
+    let (format, import_content) = read_import_file(import_filename)?;
 
     // Get database connection
     let db_path = get_database_path()?;
+    // New rows plus the rollback journal for the rows they replace
+    crate::disk_space::ensure_free_space(
+        &db_path,
+        (import_content.len() as u64).saturating_mul(2),
+    )?;
     let mut conn =
         Connection::open(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
 
@@ -193,30 +407,149 @@ pub fn import_database(import_filename: &str) -> Result<String, String> {
         .map_err(|e| format!("Failed to start transaction: {}", e))?;
 
     // Import based on format
-    match format {
-        ExportFormat::Json => {
-            let export: DatabaseExport = serde_json::from_str(&import_content)
-                .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-            import_from_json(&tx, &export)?;
-        }
-        ExportFormat::Csv => {
-            import_from_csv(&tx, &import_content)?;
-        }
-        ExportFormat::Sql => {
-            import_from_sql(&tx, &import_content)?;
-        }
-    }
+    run_import(&tx, &format, &import_content, mode, progress)?;
+
+    // Dropping the transaction without commit rolls everything back
+    progress.check_cancelled()?;
 
     // Commit transaction
     tx.commit()
         .map_err(|e| format!("Failed to commit transaction: {}", e))?;
 
+    progress.finish(0);
+
     Ok(format!(
         "Database imported successfully from: {}",
         import_filename
     ))
 }
 
+fn table_row_counts(conn: &Connection) -> Result<Vec<(String, i64)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .map_err(|e| format!("Failed to list tables: {}", e))?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to list tables: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to list tables: {}", e))?;
+
+    tables
+        .into_iter()
+        .map(|table| {
+            let count: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+                    row.get(0)
+                })
+                .map_err(|e| format!("Failed to count rows in {}: {}", table, e))?;
+            Ok((table, count))
+        })
+        .collect()
+}
+
+fn foreign_key_violations(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("PRAGMA foreign_key_check")
+        .map_err(|e| format!("Failed to check foreign keys: {}", e))?;
+    let violations = stmt
+        .query_map([], |row| {
+            let table: String = row.get(0)?;
+            let rowid: Option<i64> = row.get(1)?;
+            let parent: String = row.get(2)?;
+            Ok(format!(
+                "{} row {} references a missing {} row",
+                table,
+                rowid.map(|id| id.to_string()).unwrap_or_default(),
+                parent
+            ))
+        })
+        .map_err(|e| format!("Failed to check foreign keys: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read foreign key check: {}", e))?;
+    Ok(violations)
+}
+
+/// Run the whole import inside a transaction, collect statistics and roll
+/// back; import errors are reported rather than returned
+pub fn rehearse_import_with_conn(
+    conn: &mut Connection,
+    import_filename: &str,
+    format: &ExportFormat,
+    import_content: &[u8],
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<ImportRehearsal, String> {
+    let started = std::time::Instant::now();
+    let before = table_row_counts(conn)?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    let error = run_import(&tx, format, import_content, mode, progress).err();
+    // Cancelling the rehearsal itself is not a finding about the file
+    progress.check_cancelled()?;
+
+    let after = table_row_counts(&tx)?;
+    let foreign_key_violations = foreign_key_violations(&tx)?;
+
+    tx.rollback()
+        .map_err(|e| format!("Failed to roll back rehearsal: {}", e))?;
+
+    let tables = after
+        .into_iter()
+        .map(|(table, rows_after)| {
+            let rows_before = before
+                .iter()
+                .find(|(name, _)| *name == table)
+                .map(|(_, count)| *count)
+                .unwrap_or(0);
+            TableImportStats {
+                table,
+                rows_before,
+                rows_after,
+            }
+        })
+        .collect();
+
+    progress.finish(0);
+
+    Ok(ImportRehearsal {
+        import_filename: import_filename.to_string(),
+        would_succeed: error.is_none() && foreign_key_violations.is_empty(),
+        error,
+        tables,
+        foreign_key_violations,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Dry run of `import_database_with_progress`; the database is left unchanged
+pub fn rehearse_import_with_progress(
+    import_filename: &str,
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<ImportRehearsal, String> {
+    let (format, import_content) = read_import_file(import_filename)?;
+
+    let db_path = get_database_path()?;
+    let mut conn =
+        Connection::open(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON")
+        .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+
+    rehearse_import_with_conn(
+        &mut conn,
+        import_filename,
+        &format,
+        &import_content,
+        mode,
+        progress,
+    )
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportFileInfo {
     pub filename: String,
@@ -278,33 +611,57 @@ pub fn delete_export(export_filename: &str) -> Result<String, String> {
     Ok(format!("Export deleted successfully: {}", export_filename))
 }
 
-// Helper functions
-fn get_export_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
+/// Full path of an existing export; the filename must be a single path component
+pub fn export_file_path(export_filename: &str) -> Result<PathBuf, String> {
+    validation::file_name("filename", export_filename)?;
 
-    let export_dir = app_data.join("pqs-rtn-hybrid-storage").join("exports");
-
-    if !export_dir.exists() {
-        fs::create_dir_all(&export_dir)
-            .map_err(|e| format!("Failed to create export directory: {}", e))?;
+    let export_path = get_export_directory()?.join(export_filename);
+    if !export_path.is_file() {
+        return Err(format!("Export file not found: {}", export_filename));
     }
+    Ok(export_path)
+}
 
-    Ok(export_dir)
+// Helper functions
+fn get_export_directory() -> Result<PathBuf, String> {
+    crate::storage_paths::get_export_dir()
 }
 
 fn get_database_path() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
+    crate::storage_paths::get_database_path()
+}
 
-    let db_dir = app_data.join("pqs-rtn-hybrid-storage");
-    std::fs::create_dir_all(&db_dir)
-        .map_err(|e| format!("Failed to create database directory: {}", e))?;
+/// Rows of `table_name`; with `changed_since` only those updated since then
+/// (tables without `updated_at` are exported whole)
+fn export_table(
+    conn: &Connection,
+    table_name: &str,
+    changed_since: Option<&str>,
+    progress: &ProgressReporter,
+) -> Result<TableExport, String> {
+    progress.check_cancelled()?;
+
+    let changed_since = match changed_since {
+        Some(since) if has_updated_at(conn, table_name)? => Some(since),
+        _ => None,
+    };
+    let filter = if changed_since.is_some() {
+        " WHERE updated_at >= ?1"
+    } else {
+        ""
+    };
+    let filter_params: Vec<&str> = changed_since.into_iter().collect();
 
-    Ok(db_dir.join("database.db"))
-}
+    let total_rows: u64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM {}{}", table_name, filter),
+            rusqlite::params_from_iter(&filter_params),
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count as u64)
+        .map_err(|e| format!("Failed to count rows in {}: {}", table_name, e))?;
+    progress.report(Some(table_name), 0, Some(total_rows));
 
-fn export_table(conn: &Connection, table_name: &str) -> Result<TableExport, String> {
     // Get table schema
     let schema = conn
         .prepare(&format!(
@@ -317,7 +674,7 @@ fn export_table(conn: &Connection, table_name: &str) -> Result<TableExport, Stri
 
     // Get table data with proper column names
     let mut stmt = conn
-        .prepare(&format!("SELECT * FROM {}", table_name))
+        .prepare(&format!("SELECT * FROM {}{}", table_name, filter))
         .map_err(|e| format!("Failed to prepare data query: {}", e))?;
 
     // Get column names
@@ -326,26 +683,20 @@ fn export_table(conn: &Connection, table_name: &str) -> Result<TableExport, Stri
         .iter()
         .map(|name| name.to_string())
         .collect();
+    let columns = table_columns(conn, table_name)?
+        .into_iter()
+        .map(|(name, declared_type)| ExportColumn {
+            name,
+            declared_type,
+        })
+        .collect();
 
     let rows = stmt
-        .query_map([], |row| {
+        .query_map(rusqlite::params_from_iter(&filter_params), |row| {
             let mut map = serde_json::Map::new();
             for (i, col_name) in column_names.iter().enumerate() {
-                // Try to get value as different types
-                let value = if let Ok(val) = row.get::<_, i64>(i) {
-                    serde_json::Value::Number(serde_json::Number::from(val))
-                } else if let Ok(val) = row.get::<_, f64>(i) {
-                    serde_json::Number::from_f64(val)
-                        .map(serde_json::Value::Number)
-                        .unwrap_or(serde_json::Value::Null)
-                } else if let Ok(val) = row.get::<_, String>(i) {
-                    serde_json::Value::String(val)
-                } else if let Ok(val) = row.get::<_, bool>(i) {
-                    serde_json::Value::Bool(val)
-                } else {
-                    serde_json::Value::Null
-                };
-                map.insert(col_name.clone(), value);
+                // The value as stored: NULL, integer, real, text or blob
+                map.insert(col_name.clone(), json_value(row.get_ref(i)?));
             }
             Ok(serde_json::Value::Object(map))
         })
@@ -355,62 +706,149 @@ fn export_table(conn: &Connection, table_name: &str) -> Result<TableExport, Stri
     for row in rows {
         let row = row.map_err(|e| format!("Failed to process row: {}", e))?;
         data.push(row);
+
+        let processed = data.len() as u64;
+        if processed.is_multiple_of(ROW_REPORT_INTERVAL) {
+            progress.check_cancelled()?;
+            progress.report(Some(table_name), processed, Some(total_rows));
+        }
     }
 
+    progress.report(Some(table_name), data.len() as u64, Some(total_rows));
+
     let row_count = data.len();
     Ok(TableExport {
         name: table_name.to_string(),
         data,
         schema,
         row_count,
+        columns,
     })
 }
 
-fn export_to_csv(export: &DatabaseExport) -> Result<String, String> {
-    let mut csv_content = String::new();
-
-    for table in &export.tables {
-        csv_content.push_str(&format!("# Table: {}\n", table.name));
-        csv_content.push_str(&format!("# Schema: {}\n", table.schema));
-        csv_content.push_str(&format!("# Rows: {}\n", table.row_count));
-
-        if !table.data.is_empty() {
-            // Get column names from first row
-            if let Some(first_row) = table.data.first() {
-                if let Some(obj) = first_row.as_object() {
-                    let columns: Vec<&String> = obj.keys().collect();
-                    csv_content.push_str(
-                        &columns
-                            .iter()
-                            .map(|s| s.as_str())
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    );
-                    csv_content.push('\n');
-
-                    // Add data rows
-                    for row in &table.data {
-                        if let Some(obj) = row.as_object() {
-                            let values: Vec<String> = columns
-                                .iter()
-                                .map(|col| {
-                                    obj.get(*col)
-                                        .map(|v| v.to_string())
-                                        .unwrap_or_else(|| "".to_string())
-                                })
-                                .collect();
-                            csv_content.push_str(&values.join(","));
-                            csv_content.push('\n');
-                        }
-                    }
-                }
+/// `value` of `column` as an SQL literal; blobs come back from base64 as X'..'
+fn sql_literal(table: &TableExport, column: &str, value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => {
+            let blob = if table.is_blob_column(column) {
+                general_purpose::STANDARD.decode(s).ok()
+            } else {
+                None
+            };
+            match blob {
+                Some(bytes) => format!(
+                    "X'{}'",
+                    bytes
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<String>()
+                ),
+                None => format!("'{}'", s.replace("'", "''")),
             }
         }
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+        _ => "NULL".to_string(),
+    }
+}
+
+/// Manifest entry of a CSV export: the export itself with the rows left out
+const CSV_MANIFEST_ENTRY: &str = "export.json";
+/// NULL in a CSV cell, as in MySQL and PostgreSQL dumps; an empty cell is ''
+const CSV_NULL: &str = "\\N";
+/// Written first so spreadsheet programs read the Thai text as UTF-8
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn csv_entry_name(table: &str) -> String {
+    format!("{}.csv", table)
+}
+
+fn csv_cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => CSV_NULL.to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// One table as RFC 4180 CSV with a header row, columns in table order
+fn table_to_csv(table: &TableExport) -> Result<Vec<u8>, String> {
+    let columns = table.column_names();
+    let mut writer = csv::Writer::from_writer(UTF8_BOM.to_vec());
+    if !columns.is_empty() {
+        writer
+            .write_record(&columns)
+            .map_err(|e| format!("Failed to write CSV header for {}: {}", table.name, e))?;
+    }
+    for row in &table.data {
+        let record: Vec<String> = columns
+            .iter()
+            .map(|column| csv_cell(row.get(column).unwrap_or(&serde_json::Value::Null)))
+            .collect();
+        writer
+            .write_record(&record)
+            .map_err(|e| format!("Failed to write CSV row for {}: {}", table.name, e))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| format!("Failed to finish CSV for {}: {}", table.name, e))
+}
 
-        csv_content.push('\n');
+/// Rows of a `<table>.csv` entry as JSON objects; values stay text and the
+/// column affinity converts them on insert
+fn csv_to_rows(table_name: &str, content: &[u8]) -> Result<Vec<serde_json::Value>, String> {
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+    let mut reader = csv::Reader::from_reader(content);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Failed to read CSV header for {}: {}", table_name, e))?
+        .clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record =
+            record.map_err(|e| format!("Failed to read CSV row for {}: {}", table_name, e))?;
+        let row: serde_json::Map<String, serde_json::Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(column, cell)| {
+                let value = match cell {
+                    CSV_NULL => serde_json::Value::Null,
+                    cell => serde_json::Value::String(cell.to_string()),
+                };
+                (column.to_string(), value)
+            })
+            .collect();
+        rows.push(serde_json::Value::Object(row));
     }
+    Ok(rows)
+}
 
-    Ok(csv_content)
+/// Zip with `export.json` (tables, schemas, columns) and one `<table>.csv` each
+fn export_to_csv_zip(export: &DatabaseExport) -> Result<Vec<u8>, String> {
+    let mut manifest = export.clone();
+    for table in &mut manifest.tables {
+        table.data.clear();
+    }
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(CSV_MANIFEST_ENTRY, FileOptions::default())
+        .map_err(|e| format!("Failed to start manifest file in zip: {}", e))?;
+    zip.write_all(manifest_json.as_bytes())
+        .map_err(|e| format!("Failed to write manifest to zip: {}", e))?;
+    for table in &export.tables {
+        zip.start_file(csv_entry_name(&table.name), FileOptions::default())
+            .map_err(|e| format!("Failed to start {} in zip: {}", table.name, e))?;
+        zip.write_all(&table_to_csv(table)?)
+            .map_err(|e| format!("Failed to write {} to zip: {}", table.name, e))?;
+    }
+    let cursor = zip
+        .finish()
+        .map_err(|e| format!("Failed to finish zip file: {}", e))?;
+    Ok(cursor.into_inner())
 }
 
 fn export_to_sql(export: &DatabaseExport) -> Result<String, String> {
@@ -445,44 +883,25 @@ fn export_to_sql(export: &DatabaseExport) -> Result<String, String> {
         if !table.data.is_empty() {
             sql_content.push_str(&format!("-- Insert data for table: {}\n", table.name));
 
-            // Get column names from first row
-            if let Some(first_row) = table.data.first() {
-                if let Some(obj) = first_row.as_object() {
-                    let columns: Vec<&String> = obj.keys().collect();
-
-                    // Add INSERT statements
-                    for row in &table.data {
-                        if let Some(obj) = row.as_object() {
-                            let values: Vec<String> = columns
-                                .iter()
-                                .map(|col| {
-                                    let value = obj.get(*col).unwrap_or(&serde_json::Value::Null);
-                                    match value {
-                                        serde_json::Value::String(s) => {
-                                            format!("'{}'", s.replace("'", "''"))
-                                        }
-                                        serde_json::Value::Number(n) => n.to_string(),
-                                        serde_json::Value::Bool(b) => {
-                                            if *b { "1" } else { "0" }.to_string()
-                                        }
-                                        serde_json::Value::Null => "NULL".to_string(),
-                                        _ => "NULL".to_string(),
-                                    }
-                                })
-                                .collect();
-
-                            sql_content.push_str(&format!(
-                                "INSERT INTO {} ({}) VALUES ({});\n",
-                                table.name,
-                                columns
-                                    .iter()
-                                    .map(|s| s.as_str())
-                                    .collect::<Vec<_>>()
-                                    .join(", "),
-                                values.join(", ")
-                            ));
-                        }
-                    }
+            let columns = table.column_names();
+
+            // Add INSERT statements
+            for row in &table.data {
+                if let Some(obj) = row.as_object() {
+                    let values: Vec<String> = columns
+                        .iter()
+                        .map(|col| {
+                            let value = obj.get(col).unwrap_or(&serde_json::Value::Null);
+                            sql_literal(table, col, value)
+                        })
+                        .collect();
+
+                    sql_content.push_str(&format!(
+                        "INSERT INTO {} ({}) VALUES ({});\n",
+                        table.name,
+                        columns.join(", "),
+                        values.join(", ")
+                    ));
                 }
             }
         }
@@ -497,122 +916,416 @@ fn export_to_sql(export: &DatabaseExport) -> Result<String, String> {
     Ok(sql_content)
 }
 
-fn import_from_json(tx: &rusqlite::Transaction, export: &DatabaseExport) -> Result<(), String> {
-    for table in &export.tables {
+/// Columns that identify a row across installations, for merge and
+/// skip_existing. Ids only match within one installation, so tables without
+/// such a key are left as they are by those modes
+fn match_columns(table: &str) -> Option<&'static [&'static str]> {
+    match table {
+        "users" => Some(&["username", "email"]),
+        "ranks" => Some(&["abbreviation"]),
+        "position_templates" => Some(&["title_thai"]),
+        "saved_views" => Some(&["name"]),
+        "user_preferences" | "user_drafts" => Some(&["user_id"]),
+        _ => None,
+    }
+}
+
+/// Column of `table` holding a users.id, and whether a row whose user was
+/// not imported is dropped (otherwise the column is cleared)
+fn user_reference(table: &str) -> Option<(&'static str, bool)> {
+    match table {
+        "user_preferences" | "user_drafts" => Some(("user_id", true)),
+        "saved_views" => Some(("created_by", false)),
+        _ => None,
+    }
+}
+
+/// Point `row`'s user column at the id its user has here; false when the
+/// row has to be dropped
+fn remap_user_reference(
+    table: &str,
+    row: &mut serde_json::Map<String, serde_json::Value>,
+    user_ids: &HashMap<i64, i64>,
+) -> bool {
+    let Some((column, required)) = user_reference(table) else {
+        return true;
+    };
+    let Some(old_id) = row.get(column).and_then(|v| v.as_i64()) else {
+        return !required;
+    };
+    match user_ids.get(&old_id) {
+        Some(new_id) => {
+            row.insert(column.to_string(), (*new_id).into());
+            true
+        }
+        None if required => false,
+        None => {
+            row.insert(column.to_string(), serde_json::Value::Null);
+            true
+        }
+    }
+}
+
+/// `value` bound for `column`; base64 strings go into blob columns as bytes
+fn json_param(table: &TableExport, column: &str, value: &serde_json::Value) -> Box<dyn ToSql> {
+    match value {
+        serde_json::Value::String(s) if table.is_blob_column(column) => {
+            match general_purpose::STANDARD.decode(s) {
+                Ok(bytes) => Box::new(bytes),
+                Err(_) => Box::new(s.clone()),
+            }
+        }
+        serde_json::Value::String(s) => Box::new(s.clone()),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Box::new(i)
+            } else if let Some(f) = n.as_f64() {
+                Box::new(f)
+            } else {
+                Box::new(n.to_string())
+            }
+        }
+        serde_json::Value::Bool(b) => Box::new(*b),
+        serde_json::Value::Null => Box::new(None::<String>),
+        _ => Box::new(value.to_string()),
+    }
+}
+
+fn execute_with(
+    tx: &rusqlite::Transaction,
+    query: &str,
+    params: &[Box<dyn ToSql>],
+    action: &str,
+) -> Result<usize, String> {
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    tx.execute(query, param_refs.as_slice())
+        .map_err(|e| format!("Failed to execute {}: {}", action, e))
+}
+
+fn insert_row(
+    tx: &rusqlite::Transaction,
+    table: &TableExport,
+    row: &serde_json::Map<String, serde_json::Value>,
+    insert: &str,
+    keep_id: bool,
+) -> Result<(), String> {
+    let columns: Vec<&String> = row.keys().filter(|c| keep_id || *c != "id").collect();
+    let query = format!(
+        "{} INTO {} ({}) VALUES ({})",
+        insert,
+        table.name,
+        columns
+            .iter()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+    let params: Vec<Box<dyn ToSql>> = columns
+        .iter()
+        .map(|c| json_param(table, c, &row[c.as_str()]))
+        .collect();
+    execute_with(tx, &query, &params, "insert")?;
+    Ok(())
+}
+
+/// Overwrite the row with rowid `id` with the incoming values; the id stays
+/// and `row_version` is bumped so open edit forms see the change
+fn update_row(
+    tx: &rusqlite::Transaction,
+    table: &TableExport,
+    row: &serde_json::Map<String, serde_json::Value>,
+    id: i64,
+) -> Result<(), String> {
+    let mut assignments = Vec::new();
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+    for (column, value) in row {
+        if column == "id" || column == "row_version" {
+            continue;
+        }
+        params.push(json_param(table, column, value));
+        assignments.push(format!("{} = ?{}", column, params.len()));
+    }
+    if row.contains_key("row_version") {
+        assignments.push("row_version = row_version + 1".to_string());
+    }
+    if assignments.is_empty() {
+        return Ok(());
+    }
+    params.push(Box::new(id));
+    let query = format!(
+        "UPDATE {} SET {} WHERE rowid = ?{}",
+        table.name,
+        assignments.join(", "),
+        params.len()
+    );
+    execute_with(tx, &query, &params, "update")?;
+    Ok(())
+}
+
+/// Rowid of the existing row `row` matches on any of `columns`; a row
+/// matching two different rows (username of one, email of another) is an
+/// error
+fn existing_row_id(
+    tx: &rusqlite::Transaction,
+    table: &TableExport,
+    columns: &[&str],
+    row: &serde_json::Map<String, serde_json::Value>,
+) -> Result<Option<i64>, String> {
+    let mut conditions = Vec::new();
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+    for column in columns {
+        if let Some(value) = row.get(*column).filter(|v| !v.is_null()) {
+            params.push(json_param(table, column, value));
+            conditions.push(format!("{} = ?{}", column, params.len()));
+        }
+    }
+    if conditions.is_empty() {
+        return Ok(None);
+    }
+
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let mut stmt = tx
+        .prepare(&format!(
+            "SELECT rowid FROM {} WHERE {}",
+            table.name,
+            conditions.join(" OR ")
+        ))
+        .map_err(|e| format!("Failed to prepare match query: {}", e))?;
+    let ids = stmt
+        .query_map(param_refs.as_slice(), |r| r.get::<_, i64>(0))
+        .map_err(|e| format!("Failed to match {} row: {}", table.name, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to match {} row: {}", table.name, e))?;
+    match ids.as_slice() {
+        [] => Ok(None),
+        [id] => Ok(Some(*id)),
+        _ => Err(format!(
+            "A {} row matches {} existing rows by {}: {}",
+            table.name,
+            ids.len(),
+            columns.join("/"),
+            columns
+                .iter()
+                .filter_map(|c| row.get(*c).map(|v| format!("{}={}", c, v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn import_from_json(
+    tx: &rusqlite::Transaction,
+    export: &DatabaseExport,
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    // An incremental export only carries changed rows: merge, don't replace
+    let insert = if export.changed_since.is_some() {
+        "INSERT OR REPLACE"
+    } else {
+        "INSERT"
+    };
+    let mut tables: Vec<&TableExport> = export.tables.iter().collect();
+    if mode != ImportMode::Replace {
+        // Users first, so rows pointing at them can be given the local ids
+        tables.sort_by_key(|table| table.name != "users");
+    }
+    // Exported users.id -> id of the same user here
+    let mut user_ids: HashMap<i64, i64> = HashMap::new();
+    let mut left_alone = Vec::new();
+
+    for table in tables {
+        progress.check_cancelled()?;
+        let total_rows = Some(table.data.len() as u64);
+        progress.report(Some(&table.name), 0, total_rows);
+
+        let columns = match mode {
+            ImportMode::Replace => None,
+            ImportMode::Merge | ImportMode::SkipExisting => match match_columns(&table.name) {
+                Some(columns) => Some(columns),
+                None => {
+                    left_alone.push(table.name.as_str());
+                    continue;
+                }
+            },
+        };
+
         // Clear existing data
-        tx.execute(&format!("DELETE FROM {}", table.name), [])
-            .map_err(|e| format!("Failed to clear table {}: {}", table.name, e))?;
+        if mode == ImportMode::Replace && export.changed_since.is_none() {
+            tx.execute(&format!("DELETE FROM {}", table.name), [])
+                .map_err(|e| format!("Failed to clear table {}: {}", table.name, e))?;
+        }
 
         // Insert new data
-        for row in &table.data {
-            if let Some(obj) = row.as_object() {
-                let mut columns = Vec::new();
-                let mut values = Vec::new();
-                let mut placeholders = Vec::new();
-
-                for (key, value) in obj {
-                    columns.push(key);
-                    values.push(value.clone());
-                    placeholders.push("?");
-                }
+        for (index, row) in table.data.iter().enumerate() {
+            let processed = index as u64 + 1;
+            if processed.is_multiple_of(ROW_REPORT_INTERVAL) {
+                progress.check_cancelled()?;
+                progress.report(Some(&table.name), processed, total_rows);
+            }
 
-                let query = format!(
-                    "INSERT INTO {} ({}) VALUES ({})",
-                    table.name,
-                    columns
-                        .iter()
-                        .map(|s| s.as_str())
-                        .collect::<Vec<_>>()
-                        .join(", "),
-                    placeholders.join(", ")
-                );
-
-                let mut stmt = tx
-                    .prepare(&query)
-                    .map_err(|e| format!("Failed to prepare insert statement: {}", e))?;
-
-                let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-                for value in values {
-                    match value {
-                        serde_json::Value::String(s) => params.push(Box::new(s)),
-                        serde_json::Value::Number(n) => {
-                            if let Some(i) = n.as_i64() {
-                                params.push(Box::new(i));
-                            } else if let Some(f) = n.as_f64() {
-                                params.push(Box::new(f));
-                            } else {
-                                params.push(Box::new(n.to_string()));
-                            }
-                        }
-                        serde_json::Value::Bool(b) => params.push(Box::new(b)),
-                        serde_json::Value::Null => params.push(Box::new(None::<String>)),
-                        _ => params.push(Box::new(value.to_string())),
+            let Some(obj) = row.as_object() else {
+                continue;
+            };
+            let Some(columns) = columns else {
+                insert_row(tx, table, obj, insert, true)?;
+                continue;
+            };
+
+            let mut obj = obj.clone();
+            if !remap_user_reference(&table.name, &mut obj, &user_ids) {
+                continue;
+            }
+            let id = match existing_row_id(tx, table, columns, &obj)? {
+                Some(id) => {
+                    if mode == ImportMode::Merge {
+                        update_row(tx, table, &obj, id)?;
                     }
+                    id
+                }
+                // Matched by natural keys, new rows get a fresh id here
+                None => {
+                    insert_row(tx, table, &obj, "INSERT", false)?;
+                    tx.last_insert_rowid()
+                }
+            };
+            if table.name == "users" {
+                if let Some(old_id) = obj.get("id").and_then(|v| v.as_i64()) {
+                    user_ids.insert(old_id, id);
                 }
-                let param_refs: Vec<&dyn rusqlite::ToSql> =
-                    params.iter().map(|p| p.as_ref()).collect();
-                stmt.execute(param_refs.as_slice())
-                    .map_err(|e| format!("Failed to execute insert: {}", e))?;
             }
         }
+
+        progress.report(Some(&table.name), table.data.len() as u64, total_rows);
     }
 
+    if !left_alone.is_empty() {
+        logger::info(format!(
+            "Import left tables without a natural key as they were: {}",
+            left_alone.join(", ")
+        ));
+    }
     Ok(())
 }
 
-fn import_from_csv(tx: &rusqlite::Transaction, csv_content: &str) -> Result<(), String> {
-    // Simple CSV import - can be enhanced for complex CSV files
-    let lines: Vec<&str> = csv_content.lines().collect();
-    let mut current_table = String::new();
-    let mut columns = Vec::new();
-
-    for line in lines {
-        if line.starts_with("# Table: ") {
-            current_table = line.replace("# Table: ", "").trim().to_string();
-        } else if line.starts_with("# Schema: ") {
-            // Skip schema line
-        } else if line.starts_with("# Rows: ") {
-            // Skip rows line
-        } else if !line.is_empty() && !line.starts_with("#") {
-            if columns.is_empty() {
-                columns = line.split(',').map(|s| s.trim().to_string()).collect();
-            } else {
-                let values: Vec<&str> = line.split(',').collect();
-                if values.len() == columns.len() {
-                    let placeholders = vec!["?"; columns.len()].join(", ");
-                    let query = format!(
-                        "INSERT INTO {} ({}) VALUES ({})",
-                        current_table,
-                        columns.join(", "),
-                        placeholders
-                    );
+/// Read the tables of a CSV export back and import them like a JSON export,
+/// so blobs, incremental merges and progress work the same way
+fn import_from_csv(
+    tx: &rusqlite::Transaction,
+    zip_content: &[u8],
+    mode: ImportMode,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip_content))
+        .map_err(|e| format!("Failed to read CSV export: {}", e))?;
+
+    let mut export: DatabaseExport = {
+        let manifest = archive
+            .by_name(CSV_MANIFEST_ENTRY)
+            .map_err(|e| format!("CSV export has no {}: {}", CSV_MANIFEST_ENTRY, e))?;
+        serde_json::from_reader(manifest)
+            .map_err(|e| format!("Failed to parse {}: {}", CSV_MANIFEST_ENTRY, e))?
+    };
+    for table in &mut export.tables {
+        progress.check_cancelled()?;
+        let mut content = Vec::new();
+        archive
+            .by_name(&csv_entry_name(&table.name))
+            .map_err(|e| format!("CSV export has no file for {}: {}", table.name, e))?
+            .read_to_end(&mut content)
+            .map_err(|e| format!("Failed to read CSV for {}: {}", table.name, e))?;
+        table.data = csv_to_rows(&table.name, &content)?;
+    }
 
-                    let mut stmt = tx
-                        .prepare(&query)
-                        .map_err(|e| format!("Failed to prepare insert statement: {}", e))?;
+    import_from_json(tx, &export, mode, progress)
+}
 
-                    let params: Vec<&dyn rusqlite::ToSql> =
-                        values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
-                    stmt.execute(params.as_slice())
-                        .map_err(|e| format!("Failed to execute insert: {}", e))?;
+/// Statements of an SQL script without comments; semicolons and dashes
+/// inside quoted literals and identifiers do not split or end anything
+fn split_sql_statements(sql_content: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = sql_content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                current.push(c);
+                // A doubled quote is an escaped one and keeps the literal open
+                if c == q && chars.peek() != Some(&q) {
+                    quote = None;
+                } else if c == q {
+                    current.push(chars.next().unwrap_or(q));
                 }
             }
+            None => match c {
+                '\'' | '"' | '`' => {
+                    quote = Some(c);
+                    current.push(c);
+                }
+                '-' if chars.peek() == Some(&'-') => {
+                    for skipped in chars.by_ref() {
+                        if skipped == '\n' {
+                            current.push('\n');
+                            break;
+                        }
+                    }
+                }
+                ';' => statements.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            },
         }
     }
+    statements.push(current);
 
-    Ok(())
+    statements
+        .into_iter()
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// First keyword of `statement`, upper-cased
+fn leading_keyword(statement: &str) -> String {
+    statement
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default()
+        .to_ascii_uppercase()
 }
 
-fn import_from_sql(tx: &rusqlite::Transaction, sql_content: &str) -> Result<(), String> {
-    // Simple SQL import - can be enhanced for complex SQL files
-    let statements: Vec<&str> = sql_content.split(';').collect();
+/// Imports run in a transaction of their own - the one a rehearsal rolls
+/// back - so the script's BEGIN/COMMIT wrapper (as written by
+/// `export_to_sql`) is left out and other transaction control is refused
+fn import_from_sql(
+    tx: &rusqlite::Transaction,
+    sql_content: &str,
+    progress: &ProgressReporter,
+) -> Result<(), String> {
+    let statements = split_sql_statements(sql_content);
+    let total = Some(statements.len() as u64);
+
+    for (index, statement) in statements.iter().enumerate() {
+        let processed = index as u64 + 1;
+        if processed.is_multiple_of(ROW_REPORT_INTERVAL) {
+            progress.check_cancelled()?;
+            progress.report(None, processed, total);
+        }
 
-    for statement in statements {
-        let statement = statement.trim();
-        if !statement.is_empty() && !statement.starts_with("--") {
-            tx.execute(statement, [])
-                .map_err(|e| format!("Failed to execute SQL statement: {}", e))?;
+        match leading_keyword(statement).as_str() {
+            "BEGIN" | "COMMIT" | "END" => continue,
+            "ROLLBACK" | "SAVEPOINT" | "RELEASE" => {
+                return Err(format!(
+                    "SQL imports may not control transactions: {}",
+                    statement
+                ))
+            }
+            _ => {}
         }
+        tx.execute(statement, [])
+            .map_err(|e| format!("Failed to execute SQL statement: {}", e))?;
     }
 
     Ok(())
@@ -640,6 +1353,7 @@ mod tests {
                     "note": null
                 })],
                 row_count: 1,
+                columns: Vec::new(),
             }],
             metadata: ExportMetadata {
                 created_at: "2026-03-09T00:00:00Z".to_string(),
@@ -648,6 +1362,7 @@ mod tests {
                 file_size: 0,
                 format: "Sql".to_string(),
             },
+            changed_since: None,
         }
     }
 
@@ -666,15 +1381,71 @@ mod tests {
 
     #[test]
     fn test_export_to_csv_contains_header_and_row() {
-        let mut export = sample_export();
-        export.format = ExportFormat::Csv;
+        let export = sample_export();
+
+        let csv = table_to_csv(&export.tables[0]).expect("CSV export should succeed");
+        let csv = String::from_utf8(csv).unwrap();
+
+        assert!(csv.starts_with("\u{feff}"));
+        assert!(csv.contains("active,id,name,note\n"));
+        assert!(csv.contains("1,1,O'Brien,\\N\n"));
+    }
+
+    #[test]
+    fn test_csv_zip_round_trips_quoting_nulls_and_blobs() {
+        let schema = "CREATE TABLE people (id INTEGER PRIMARY KEY, name TEXT NOT NULL, note TEXT, photo BLOB)";
+        let source = Connection::open_in_memory().expect("In-memory db should open");
+        source.execute(schema, []).expect("Table should be created");
+        source
+            .execute(
+                "INSERT INTO people VALUES (1, ?1, '', X'0001FF'), (2, ?2, NULL, NULL)",
+                ["สมชาย, ใจดี", "line one\nline \"two\""],
+            )
+            .expect("Rows should insert");
+        let export = DatabaseExport {
+            format: ExportFormat::Csv,
+            tables: vec![
+                export_table(&source, "people", None, &ProgressReporter::noop())
+                    .expect("export_table should succeed"),
+            ],
+            ..sample_export()
+        };
 
-        let csv = export_to_csv(&export).expect("CSV export should succeed");
+        let zip_content = export_to_csv_zip(&export).expect("CSV export should succeed");
+
+        let mut target = Connection::open_in_memory().expect("In-memory db should open");
+        target.execute(schema, []).expect("Table should be created");
+        let tx = target.transaction().expect("Transaction should start");
+        run_import(
+            &tx,
+            &ExportFormat::Csv,
+            &zip_content,
+            ImportMode::Replace,
+            &ProgressReporter::noop(),
+        )
+        .expect("CSV import should succeed");
+        tx.commit().expect("Commit should succeed");
 
-        assert!(csv.contains("# Table: users"));
-        assert!(csv.contains("id"));
-        assert!(csv.contains("name"));
-        assert!(csv.contains("\"O'Brien\""));
+        type Person = (i64, String, Option<String>, Option<Vec<u8>>);
+        let people: Vec<Person> = target
+            .prepare("SELECT id, name, note, photo FROM people ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            people,
+            vec![
+                (
+                    1,
+                    "สมชาย, ใจดี".to_string(),
+                    Some(String::new()),
+                    Some(vec![0, 1, 255])
+                ),
+                (2, "line one\nline \"two\"".to_string(), None, None),
+            ]
+        );
     }
 
     #[test]
@@ -691,7 +1462,8 @@ mod tests {
         )
         .expect("Insert row should succeed");
 
-        let table = export_table(&conn, "users").expect("export_table should succeed");
+        let table = export_table(&conn, "users", None, &ProgressReporter::noop())
+            .expect("export_table should succeed");
 
         assert_eq!(table.name, "users");
         assert_eq!(table.row_count, 1);
@@ -700,6 +1472,69 @@ mod tests {
         assert!(table.data[0].get("name").is_some());
     }
 
+    #[test]
+    fn test_export_keeps_column_order_and_types() {
+        let conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE files (name TEXT, size INTEGER, ratio REAL, data BLOB, note TEXT);
+             INSERT INTO files VALUES ('a.png', 3, 0.5, X'00FF10', NULL);",
+        )
+        .expect("Table should be created");
+
+        let table = export_table(&conn, "files", None, &ProgressReporter::noop())
+            .expect("export_table should succeed");
+
+        assert_eq!(
+            table.column_names(),
+            vec!["name", "size", "ratio", "data", "note"]
+        );
+        assert_eq!(table.columns[3].declared_type, "BLOB");
+        assert_eq!(
+            table.data[0],
+            json!({"name": "a.png", "size": 3, "ratio": 0.5, "data": "AP8Q", "note": null})
+        );
+
+        let export = DatabaseExport {
+            tables: vec![table],
+            ..sample_export()
+        };
+        let sql = export_to_sql(&export).expect("SQL export should succeed");
+        assert!(sql.contains(
+            "INSERT INTO files (name, size, ratio, data, note) VALUES ('a.png', 3, 0.5, X'00FF10', NULL);"
+        ));
+    }
+
+    #[test]
+    fn test_incremental_export_only_has_changed_rows() {
+        let conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, updated_at DATETIME);
+             INSERT INTO users VALUES (1, 'old', '2024-01-01 00:00:00');
+             INSERT INTO users VALUES (2, 'new', '2024-06-01 08:00:00');
+             CREATE TABLE ranks (id INTEGER PRIMARY KEY);
+             INSERT INTO ranks VALUES (1);",
+        )
+        .expect("Seed data should insert");
+
+        let since = parse_changed_since("2024-06-01T07:00:00+07:00").unwrap();
+        assert_eq!(since, "2024-06-01 00:00:00");
+        let table = export_table(&conn, "users", Some(&since), &ProgressReporter::noop())
+            .expect("export_table should succeed");
+        assert_eq!(table.row_count, 1);
+        assert_eq!(table.data[0]["name"], "new");
+
+        // No updated_at: nothing to filter on
+        let ranks = export_table(&conn, "ranks", Some(&since), &ProgressReporter::noop())
+            .expect("export_table should succeed");
+        assert_eq!(ranks.row_count, 1);
+
+        assert_eq!(
+            parse_changed_since("2024-06-01 08:00:00").unwrap(),
+            "2024-06-01 08:00:00"
+        );
+        assert!(parse_changed_since("yesterday").is_err());
+    }
+
     #[test]
     fn test_import_from_sql_executes_statements() {
         let mut conn = Connection::open_in_memory().expect("In-memory db should open");
@@ -710,7 +1545,7 @@ mod tests {
             INSERT INTO t (name) VALUES ('abc');
         ";
 
-        import_from_sql(&tx, sql).expect("SQL import should succeed");
+        import_from_sql(&tx, sql, &ProgressReporter::noop()).expect("SQL import should succeed");
         tx.commit().expect("Commit should succeed");
 
         let count: i64 = conn
@@ -719,6 +1554,100 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn test_sql_export_rehearses_without_committing() {
+        let mut conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, active INTEGER, note TEXT);
+             INSERT INTO users (name) VALUES ('existing');",
+        )
+        .expect("Setup should succeed");
+
+        let mut export = sample_export();
+        export.tables[0].data[0]["note"] = json!("a; b -- not a comment");
+        let sql = export_to_sql(&export).expect("SQL export should succeed");
+
+        let rehearsal = rehearse_import_with_conn(
+            &mut conn,
+            "export.sql",
+            &ExportFormat::Sql,
+            sql.as_bytes(),
+            ImportMode::Replace,
+            &ProgressReporter::noop(),
+        )
+        .expect("Rehearsal should run");
+        assert!(rehearsal.would_succeed, "{:?}", rehearsal.error);
+
+        // The script's COMMIT did not end the rehearsal's transaction
+        let name: String = conn
+            .query_row("SELECT name FROM users", [], |row| row.get(0))
+            .expect("Original row should remain");
+        assert_eq!(name, "existing");
+
+        let tx = conn.transaction().expect("Transaction should start");
+        import_from_sql(&tx, &sql, &ProgressReporter::noop()).expect("SQL import should succeed");
+        let note: String = tx
+            .query_row("SELECT note FROM users", [], |row| row.get(0))
+            .expect("Imported row should exist");
+        assert_eq!(note, "a; b -- not a comment");
+
+        assert!(import_from_sql(
+            &tx,
+            "SAVEPOINT s; ROLLBACK TO s;",
+            &ProgressReporter::noop()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rehearsal_reports_and_rolls_back() {
+        let mut conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT UNIQUE);
+             INSERT INTO t (name) VALUES ('existing');",
+        )
+        .expect("Setup should succeed");
+
+        let ok_sql = "INSERT INTO t (name) VALUES ('a'); INSERT INTO t (name) VALUES ('b');";
+        let rehearsal = rehearse_import_with_conn(
+            &mut conn,
+            "ok.sql",
+            &ExportFormat::Sql,
+            ok_sql.as_bytes(),
+            ImportMode::Replace,
+            &ProgressReporter::noop(),
+        )
+        .expect("Rehearsal should run");
+
+        assert!(rehearsal.would_succeed);
+        assert_eq!(
+            rehearsal.tables,
+            vec![TableImportStats {
+                table: "t".to_string(),
+                rows_before: 1,
+                rows_after: 3,
+            }]
+        );
+
+        let bad_sql = "INSERT INTO t (name) VALUES ('existing');";
+        let rehearsal = rehearse_import_with_conn(
+            &mut conn,
+            "bad.sql",
+            &ExportFormat::Sql,
+            bad_sql.as_bytes(),
+            ImportMode::Replace,
+            &ProgressReporter::noop(),
+        )
+        .expect("Rehearsal should run");
+
+        assert!(!rehearsal.would_succeed);
+        assert!(rehearsal.error.unwrap().contains("UNIQUE"));
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .expect("Count query should succeed");
+        assert_eq!(count, 1);
+    }
+
     #[test]
     fn test_import_from_json_inserts_data() {
         let mut conn = Connection::open_in_memory().expect("In-memory db should open");
@@ -740,6 +1669,7 @@ mod tests {
                     .to_string(),
                 data: vec![json!({"id": 10, "name": "bob", "active": true})],
                 row_count: 1,
+                columns: Vec::new(),
             }],
             metadata: ExportMetadata {
                 created_at: "now".to_string(),
@@ -748,9 +1678,11 @@ mod tests {
                 file_size: 0,
                 format: "Json".to_string(),
             },
+            changed_since: None,
         };
 
-        import_from_json(&tx, &export).expect("JSON import should succeed");
+        import_from_json(&tx, &export, ImportMode::Replace, &ProgressReporter::noop())
+            .expect("JSON import should succeed");
         tx.commit().expect("Commit should succeed");
 
         let name: String = conn
@@ -758,4 +1690,178 @@ mod tests {
             .expect("Inserted row should exist");
         assert_eq!(name, "bob");
     }
+
+    #[test]
+    fn test_merge_and_skip_existing_match_users_by_username_or_email() {
+        let mut conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT UNIQUE, email TEXT UNIQUE, full_name TEXT, row_version INTEGER NOT NULL DEFAULT 1);
+             INSERT INTO users VALUES (1, 'alice', 'alice@navy.mi.th', 'Alice', 1);
+             INSERT INTO users VALUES (2, 'bob', 'bob@navy.mi.th', 'Bob', 1);",
+        )
+        .expect("Setup should succeed");
+        // Ids come from another installation and collide with local ones
+        let export = DatabaseExport {
+            tables: vec![TableExport {
+                name: "users".to_string(),
+                schema: String::new(),
+                data: vec![
+                    json!({"id": 7, "username": "alice2", "email": "alice@navy.mi.th", "full_name": "Alice B", "row_version": 5}),
+                    json!({"id": 1, "username": "carol", "email": "carol@navy.mi.th", "full_name": "Carol", "row_version": 1}),
+                ],
+                row_count: 2,
+                columns: Vec::new(),
+            }],
+            ..sample_export()
+        };
+        let users = |conn: &Connection| -> Vec<(i64, String, String, i64)> {
+            conn.prepare("SELECT id, username, full_name, row_version FROM users ORDER BY id")
+                .unwrap()
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+
+        let tx = conn.transaction().expect("Transaction should start");
+        import_from_json(
+            &tx,
+            &export,
+            ImportMode::SkipExisting,
+            &ProgressReporter::noop(),
+        )
+        .expect("Skip-existing import should succeed");
+        let skipped = users(&tx);
+        tx.rollback().unwrap();
+        assert_eq!(skipped.len(), 3);
+        assert_eq!(skipped[0], (1, "alice".to_string(), "Alice".to_string(), 1));
+        assert_eq!(skipped[2].1, "carol");
+
+        let tx = conn.transaction().expect("Transaction should start");
+        import_from_json(&tx, &export, ImportMode::Merge, &ProgressReporter::noop())
+            .expect("Merge import should succeed");
+        tx.commit().unwrap();
+        assert_eq!(
+            users(&conn),
+            vec![
+                (1, "alice2".to_string(), "Alice B".to_string(), 2),
+                (2, "bob".to_string(), "Bob".to_string(), 1),
+                (3, "carol".to_string(), "Carol".to_string(), 1),
+            ]
+        );
+
+        // Username of one account and email of another
+        let ambiguous = DatabaseExport {
+            tables: vec![TableExport {
+                data: vec![
+                    json!({"username": "bob", "email": "carol@navy.mi.th", "full_name": "?"}),
+                ],
+                ..export.tables[0].clone()
+            }],
+            ..sample_export()
+        };
+        let tx = conn.transaction().expect("Transaction should start");
+        let error = import_from_json(
+            &tx,
+            &ambiguous,
+            ImportMode::Merge,
+            &ProgressReporter::noop(),
+        )
+        .unwrap_err();
+        assert!(error.contains("matches 2 existing rows"));
+    }
+
+    #[test]
+    fn test_merge_gives_child_rows_the_local_user_id() {
+        let mut conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT UNIQUE, email TEXT UNIQUE);
+             CREATE TABLE user_preferences (user_id INTEGER PRIMARY KEY, theme TEXT);
+             CREATE TABLE high_ranking_officers (id INTEGER PRIMARY KEY, full_name TEXT);
+             INSERT INTO users VALUES (5, 'alice', 'alice@navy.mi.th');
+             INSERT INTO users VALUES (1, 'bob', 'bob@navy.mi.th');
+             INSERT INTO user_preferences VALUES (1, 'light');
+             INSERT INTO high_ranking_officers VALUES (1, 'Local officer');",
+        )
+        .expect("Setup should succeed");
+        let table = |name: &str, data: Vec<serde_json::Value>| TableExport {
+            name: name.to_string(),
+            schema: String::new(),
+            row_count: data.len(),
+            data,
+            columns: Vec::new(),
+        };
+        // Exported alice had id 1, which is bob here; table order is by name
+        let export = DatabaseExport {
+            tables: vec![
+                table(
+                    "high_ranking_officers",
+                    vec![json!({"id": 1, "full_name": "Other officer"})],
+                ),
+                table(
+                    "user_preferences",
+                    vec![
+                        json!({"user_id": 1, "theme": "dark"}),
+                        json!({"user_id": 9, "theme": "dark"}),
+                    ],
+                ),
+                table(
+                    "users",
+                    vec![json!({"id": 1, "username": "alice", "email": "alice@navy.mi.th"})],
+                ),
+            ],
+            ..sample_export()
+        };
+
+        let tx = conn.transaction().expect("Transaction should start");
+        import_from_json(&tx, &export, ImportMode::Merge, &ProgressReporter::noop())
+            .expect("Merge import should succeed");
+        tx.commit().unwrap();
+
+        let themes: Vec<(i64, String)> = conn
+            .prepare("SELECT user_id, theme FROM user_preferences ORDER BY user_id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            themes,
+            vec![(1, "light".to_string()), (5, "dark".to_string())]
+        );
+        let officer: String = conn
+            .query_row(
+                "SELECT full_name FROM high_ranking_officers WHERE id = 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(officer, "Local officer");
+    }
+
+    #[test]
+    fn test_export_table_reports_progress_and_honours_cancel() {
+        use crate::progress::{cancel_operation, CANCELLED_MESSAGE};
+        use std::sync::{Arc, Mutex};
+
+        let conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)", [])
+            .expect("Create table should succeed");
+        conn.execute("INSERT INTO users (name) VALUES ('alice')", [])
+            .expect("Insert row should succeed");
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink_seen = Arc::clone(&seen);
+        let progress = ProgressReporter::new(
+            "export-progress-test",
+            Box::new(move |payload| sink_seen.lock().unwrap().push(payload.rows_processed)),
+        );
+
+        export_table(&conn, "users", None, &progress).expect("export_table should succeed");
+        assert_eq!(seen.lock().unwrap().last(), Some(&1));
+
+        assert!(cancel_operation("export-progress-test").expect("cancel should succeed"));
+        let result = export_table(&conn, "users", None, &progress);
+        assert_eq!(result.unwrap_err(), CANCELLED_MESSAGE);
+    }
 }
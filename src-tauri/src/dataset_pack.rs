@@ -0,0 +1,768 @@
+//! Signed starter dataset packs ("fleet starter pack")
+//!
+//! A pack is a zip with `pack.json` (name, version and the SHA-256 of every
+//! data file), `pack.sig` (Ed25519 signature of `pack.json`, hex) and any of
+//! `officers.json`, `ranks.json`, `units.json`, `positions.json` plus the
+//! officer photos they reference. Packs are built and signed at headquarters
+//! with `scripts/build_dataset_pack.py`, which holds the private key; the app
+//! only verifies, against the public keys in `PACK_PUBLIC_KEYS`, so no client
+//! can produce a pack the others would accept.
+//!
+//! Installing is additive: ranks, positions and units already present (by
+//! abbreviation, Thai title and unit id) and officers with the same name and
+//! position are left alone, so a pack can be installed again safely.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::content_database::get_content_connection;
+use crate::database::get_connection_safe;
+use crate::file_manager::{FileManager, HIGH_RANKS_SUBDIR};
+use crate::logger;
+use crate::validation;
+
+const MANIFEST_NAME: &str = "pack.json";
+const SIGNATURE_NAME: &str = "pack.sig";
+const OFFICERS_FILE: &str = "officers.json";
+const RANKS_FILE: &str = "ranks.json";
+const UNITS_FILE: &str = "units.json";
+const POSITIONS_FILE: &str = "positions.json";
+#[cfg(test)]
+const DATA_FILES: &[&str] = &[OFFICERS_FILE, RANKS_FILE, UNITS_FILE, POSITIONS_FILE];
+
+/// Headquarters' pack signing keys (Ed25519, raw). Add the new key here
+/// before packs are signed with it when the key is rotated.
+const PACK_PUBLIC_KEYS: &[[u8; 32]] = &[[
+    0xab, 0x78, 0xa1, 0x48, 0x15, 0x5b, 0x5d, 0x9b, 0x22, 0x0b, 0x0b, 0x43, 0xbf, 0x9b, 0x7c, 0xf3,
+    0x2b, 0x8f, 0x3e, 0x0b, 0x82, 0x84, 0x12, 0x71, 0xca, 0x26, 0x59, 0xfc, 0xc4, 0xb4, 0x15, 0xf7,
+]];
+
+/// Largest single file read out of a pack; a starter pack is small
+const MAX_ENTRY_BYTES: u64 = 20 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub created_at: String,
+    /// Entry name -> SHA-256 (hex) of its content
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackOfficer {
+    pub thai_name: String,
+    #[serde(default)]
+    pub full_name_en: Option<String>,
+    pub position_thai: String,
+    #[serde(default)]
+    pub position_english: String,
+    #[serde(default)]
+    pub order_index: i32,
+    /// Photo entry in the pack, e.g. `photos/cinc.jpg`
+    #[serde(default)]
+    pub photo: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackRank {
+    pub abbreviation: String,
+    pub name_thai: String,
+    #[serde(default)]
+    pub name_english: String,
+    #[serde(default)]
+    pub order_index: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackUnit {
+    pub unit_id: String,
+    pub unit_name: String,
+    #[serde(default)]
+    pub unit_abbr: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub unit_level: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PackPosition {
+    pub title_thai: String,
+    #[serde(default)]
+    pub title_english: String,
+    #[serde(default)]
+    pub order_index: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DatasetPackReport {
+    pub name: String,
+    pub version: String,
+    pub officers_added: usize,
+    pub ranks_added: usize,
+    pub units_added: usize,
+    pub positions_added: usize,
+    pub photos_added: usize,
+    /// Rows already present and left unchanged
+    pub skipped: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Verified pack content
+pub struct DatasetPack {
+    pub manifest: PackManifest,
+    pub officers: Vec<PackOfficer>,
+    pub ranks: Vec<PackRank>,
+    pub units: Vec<PackUnit>,
+    pub positions: Vec<PackPosition>,
+    /// Photo entry name -> bytes
+    pub photos: HashMap<String, Vec<u8>>,
+}
+
+pub fn init_dataset_pack_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ranks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            abbreviation TEXT NOT NULL UNIQUE,
+            name_thai TEXT NOT NULL,
+            name_english TEXT NOT NULL DEFAULT '',
+            order_index INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create ranks table: {}", e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS position_templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title_thai TEXT NOT NULL UNIQUE,
+            title_english TEXT NOT NULL DEFAULT '',
+            order_index INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create position_templates table: {}", e))?;
+
+    Ok(())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Keys packs are accepted from
+pub fn trusted_keys() -> Result<Vec<VerifyingKey>, String> {
+    PACK_PUBLIC_KEYS
+        .iter()
+        .map(|key| {
+            VerifyingKey::from_bytes(key).map_err(|e| format!("Invalid pack public key: {}", e))
+        })
+        .collect()
+}
+
+fn parse_signature(text: &str) -> Option<Signature> {
+    let text = text.trim();
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Signature::from_slice(&bytes).ok()
+}
+
+/// `manifest` was signed by one of `keys`
+fn verify_signature(keys: &[VerifyingKey], manifest: &[u8], signature: &str) -> bool {
+    parse_signature(signature).is_some_and(|signature| {
+        keys.iter()
+            .any(|key| key.verify_strict(manifest, &signature).is_ok())
+    })
+}
+
+fn validate_entry_name(name: &str) -> Result<(), String> {
+    let path = Path::new(name);
+    if name.is_empty()
+        || path.is_absolute()
+        || name.contains('\\')
+        || path
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(format!("Invalid file name in pack: {}", name));
+    }
+    Ok(())
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, String> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|e| format!("Pack is missing {}: {}", name, e))?;
+    let mut data = Vec::new();
+    entry
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {} from pack: {}", name, e))?;
+    if data.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(format!("{} in pack is too large", name));
+    }
+    Ok(data)
+}
+
+fn parse_list<T: DeserializeOwned>(
+    files: &HashMap<String, Vec<u8>>,
+    name: &str,
+) -> Result<Vec<T>, String> {
+    match files.get(name) {
+        Some(data) => {
+            serde_json::from_slice(data).map_err(|e| format!("Failed to parse {}: {}", name, e))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Open a pack, check its signature against `keys` and every file hash,
+/// and parse it
+pub fn read_pack(path: &Path, keys: &[VerifyingKey]) -> Result<DatasetPack, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open pack: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    let manifest_data = read_entry(&mut archive, MANIFEST_NAME)?;
+    let signature = String::from_utf8(read_entry(&mut archive, SIGNATURE_NAME)?)
+        .map_err(|_| "Pack signature is not text".to_string())?;
+    if !verify_signature(keys, &manifest_data, &signature) {
+        return Err(
+            "Pack signature does not match; it was not signed by headquarters or was modified"
+                .to_string(),
+        );
+    }
+
+    let manifest: PackManifest = serde_json::from_slice(&manifest_data)
+        .map_err(|e| format!("Failed to parse pack manifest: {}", e))?;
+
+    let mut files = HashMap::new();
+    for (name, expected) in &manifest.files {
+        validate_entry_name(name)?;
+        let data = read_entry(&mut archive, name)?;
+        if sha256_hex(&data) != expected.to_lowercase() {
+            return Err(format!("{} in pack does not match its checksum", name));
+        }
+        files.insert(name.clone(), data);
+    }
+
+    let officers: Vec<PackOfficer> = parse_list(&files, OFFICERS_FILE)?;
+    let mut photos = HashMap::new();
+    for photo in officers.iter().filter_map(|o| o.photo.as_ref()) {
+        let data = files
+            .get(photo)
+            .ok_or_else(|| format!("Photo {} is not listed in the pack manifest", photo))?;
+        photos.insert(photo.clone(), data.clone());
+    }
+
+    Ok(DatasetPack {
+        ranks: parse_list(&files, RANKS_FILE)?,
+        units: parse_list(&files, UNITS_FILE)?,
+        positions: parse_list(&files, POSITIONS_FILE)?,
+        officers,
+        photos,
+        manifest,
+    })
+}
+
+fn photo_mime(name: &str) -> &'static str {
+    match Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+fn write_officers(
+    tx: &rusqlite::Transaction,
+    media_dir: &Path,
+    pack: &DatasetPack,
+    report: &mut DatasetPackReport,
+    written: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    for officer in &pack.officers {
+        let exists: bool = tx
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM high_ranking_officers WHERE thai_name = ? AND position_thai = ?)",
+                params![officer.thai_name, officer.position_thai],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to check officer: {}", e))?;
+        if exists {
+            report.skipped += 1;
+            continue;
+        }
+
+        tx.execute(
+            "INSERT INTO high_ranking_officers (thai_name, full_name_en, position_thai, position_english, order_index) VALUES (?, ?, ?, ?, ?)",
+            params![
+                officer.thai_name,
+                officer.full_name_en,
+                officer.position_thai,
+                officer.position_english,
+                officer.order_index
+            ],
+        )
+        .map_err(|e| format!("Failed to insert officer {}: {}", officer.thai_name, e))?;
+        report.officers_added += 1;
+        let officer_id = tx.last_insert_rowid();
+
+        let Some(photo) = officer.photo.as_ref() else {
+            continue;
+        };
+        let data = &pack.photos[photo];
+        let extension = Path::new(photo)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("jpg")
+            .to_lowercase();
+        let relative_path = format!(
+            "{}/officer_{}_pack.{}",
+            HIGH_RANKS_SUBDIR, officer_id, extension
+        );
+        let file_path = media_dir.join(&relative_path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create media directory: {}", e))?;
+        }
+        fs::write(&file_path, data)
+            .map_err(|e| format!("Failed to write photo of {}: {}", officer.thai_name, e))?;
+        written.push(file_path);
+
+        tx.execute(
+            "UPDATE high_ranking_officers SET row_version = row_version + 1, avatar_path = ?, avatar_mime = ?, avatar_size = ?, avatar_updated_at = CURRENT_TIMESTAMP, avatar_status = NULL WHERE id = ?",
+            params![relative_path, photo_mime(photo), data.len() as i64, officer_id],
+        )
+        .map_err(|e| format!("Failed to set photo of {}: {}", officer.thai_name, e))?;
+        report.photos_added += 1;
+    }
+    Ok(())
+}
+
+/// Add the pack's rows; units go to the content database when one is given
+pub fn install_pack_with_conn(
+    conn: &mut Connection,
+    content_conn: Option<&Connection>,
+    media_dir: &Path,
+    pack: &DatasetPack,
+) -> Result<DatasetPackReport, String> {
+    let mut report = DatasetPackReport {
+        name: pack.manifest.name.clone(),
+        version: pack.manifest.version.clone(),
+        ..Default::default()
+    };
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    for rank in &pack.ranks {
+        let added = tx
+            .execute(
+                "INSERT OR IGNORE INTO ranks (abbreviation, name_thai, name_english, order_index) VALUES (?, ?, ?, ?)",
+                params![rank.abbreviation, rank.name_thai, rank.name_english, rank.order_index],
+            )
+            .map_err(|e| format!("Failed to insert rank {}: {}", rank.abbreviation, e))?;
+        report.ranks_added += added;
+        report.skipped += 1 - added;
+    }
+
+    for position in &pack.positions {
+        let added = tx
+            .execute(
+                "INSERT OR IGNORE INTO position_templates (title_thai, title_english, order_index) VALUES (?, ?, ?)",
+                params![position.title_thai, position.title_english, position.order_index],
+            )
+            .map_err(|e| format!("Failed to insert position {}: {}", position.title_thai, e))?;
+        report.positions_added += added;
+        report.skipped += 1 - added;
+    }
+
+    // Photo files are not part of the transaction; remove them if it fails
+    let mut written = Vec::new();
+    let result = write_officers(&tx, media_dir, pack, &mut report, &mut written).and_then(|_| {
+        tx.commit()
+            .map_err(|e| format!("Failed to commit pack: {}", e))
+    });
+    if let Err(e) = result {
+        for path in written {
+            let _ = fs::remove_file(path);
+        }
+        return Err(e);
+    }
+
+    match content_conn {
+        Some(content) => {
+            for unit in &pack.units {
+                match content.execute(
+                    "INSERT OR IGNORE INTO OwnerUnits (unit_id, unit_name, unit_abbr, parent_id, unit_level) VALUES (?, ?, ?, ?, ?)",
+                    params![unit.unit_id, unit.unit_name, unit.unit_abbr, unit.parent_id, unit.unit_level],
+                ) {
+                    Ok(added) => {
+                        report.units_added += added;
+                        report.skipped += 1 - added;
+                    }
+                    Err(e) => report
+                        .warnings
+                        .push(format!("Unit {} was not added: {}", unit.unit_id, e)),
+                }
+            }
+        }
+        None if !pack.units.is_empty() => report
+            .warnings
+            .push("Content database is not available; units were not added".to_string()),
+        None => {}
+    }
+
+    Ok(report)
+}
+
+pub fn install_dataset_pack(path: &str) -> Result<DatasetPackReport, String> {
+    let pack_path = validation::absolute_path("path", path)?;
+    let pack = read_pack(&pack_path, &trusted_keys()?)?;
+
+    let mut conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    let content_conn = match get_content_connection() {
+        Ok(content) => Some(content),
+        Err(e) => {
+            logger::warn(format!(
+                "Content database unavailable for pack units: {}",
+                e
+            ));
+            None
+        }
+    };
+
+    let report = install_pack_with_conn(
+        &mut conn,
+        content_conn.as_ref(),
+        file_manager.get_media_directory(),
+        &pack,
+    )?;
+    logger::info(format!(
+        "Dataset pack installed: {} {} ({} officers, {} ranks, {} units, {} positions)",
+        report.name,
+        report.version,
+        report.officers_added,
+        report.ranks_added,
+        report.units_added,
+        report.positions_added
+    ));
+    Ok(report)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rank {
+    pub id: i32,
+    pub abbreviation: String,
+    pub name_thai: String,
+    pub name_english: String,
+    pub order_index: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PositionTemplate {
+    pub id: i32,
+    pub title_thai: String,
+    pub title_english: String,
+    pub order_index: i32,
+}
+
+pub fn get_ranks_with_conn(conn: &Connection) -> Result<Vec<Rank>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, abbreviation, name_thai, name_english, order_index FROM ranks ORDER BY order_index, id")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let ranks = stmt
+        .query_map([], |row| {
+            Ok(Rank {
+                id: row.get(0)?,
+                abbreviation: row.get(1)?,
+                name_thai: row.get(2)?,
+                name_english: row.get(3)?,
+                order_index: row.get(4)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query ranks: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse rank: {}", e))?;
+    Ok(ranks)
+}
+
+pub fn get_position_templates_with_conn(
+    conn: &Connection,
+) -> Result<Vec<PositionTemplate>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, title_thai, title_english, order_index FROM position_templates ORDER BY order_index, id")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let positions = stmt
+        .query_map([], |row| {
+            Ok(PositionTemplate {
+                id: row.get(0)?,
+                title_thai: row.get(1)?,
+                title_english: row.get(2)?,
+                order_index: row.get(3)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query position templates: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse position template: {}", e))?;
+    Ok(positions)
+}
+
+pub fn get_ranks() -> Result<Vec<Rank>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_ranks_with_conn(&conn)
+}
+
+pub fn get_position_templates() -> Result<Vec<PositionTemplate>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_position_templates_with_conn(&conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::io::Write;
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    /// What `scripts/build_dataset_pack.py` writes, signed with `key`
+    fn build_pack_at(
+        source_dir: &Path,
+        destination: &Path,
+        key: &SigningKey,
+        name: &str,
+        version: &str,
+        description: Option<String>,
+    ) -> Result<PackManifest, String> {
+        let mut files = BTreeMap::new();
+        let mut contents = Vec::new();
+        let mut add_file =
+            |entry: String, files: &mut BTreeMap<String, String>| -> Result<(), String> {
+                validate_entry_name(&entry)?;
+                let data = fs::read(source_dir.join(&entry))
+                    .map_err(|e| format!("Failed to read {}: {}", entry, e))?;
+                files.insert(entry.clone(), sha256_hex(&data));
+                contents.push((entry, data));
+                Ok(())
+            };
+
+        for data_file in DATA_FILES {
+            if source_dir.join(data_file).is_file() {
+                add_file(data_file.to_string(), &mut files)?;
+            }
+        }
+        if files.is_empty() {
+            return Err(format!(
+                "No pack data files found; expected any of {}",
+                DATA_FILES.join(", ")
+            ));
+        }
+        if source_dir.join(OFFICERS_FILE).is_file() {
+            let data = fs::read(source_dir.join(OFFICERS_FILE))
+                .map_err(|e| format!("Failed to read {}: {}", OFFICERS_FILE, e))?;
+            let officers: Vec<PackOfficer> = serde_json::from_slice(&data)
+                .map_err(|e| format!("Failed to parse {}: {}", OFFICERS_FILE, e))?;
+            for photo in officers.into_iter().filter_map(|o| o.photo) {
+                if !files.contains_key(&photo) {
+                    add_file(photo, &mut files)?;
+                }
+            }
+        }
+
+        let manifest = PackManifest {
+            name: name.to_string(),
+            version: version.to_string(),
+            description,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            files,
+        };
+        let manifest_json = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize pack manifest: {}", e))?;
+
+        let file =
+            fs::File::create(destination).map_err(|e| format!("Failed to create pack: {}", e))?;
+        let mut zip = ZipWriter::new(file);
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let signature: String = key
+            .sign(manifest_json.as_bytes())
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        for (entry, data) in [
+            (MANIFEST_NAME.to_string(), manifest_json.into_bytes()),
+            (SIGNATURE_NAME.to_string(), signature.into_bytes()),
+        ]
+        .into_iter()
+        .chain(contents)
+        {
+            zip.start_file(entry.as_str(), options)
+                .map_err(|e| format!("Failed to add {} to pack: {}", entry, e))?;
+            zip.write_all(&data)
+                .map_err(|e| format!("Failed to write {} to pack: {}", entry, e))?;
+        }
+        zip.finish()
+            .map_err(|e| format!("Failed to finish pack: {}", e))?;
+
+        Ok(manifest)
+    }
+
+    #[test]
+    fn test_embedded_keys_are_valid() {
+        assert_eq!(trusted_keys().unwrap().len(), PACK_PUBLIC_KEYS.len());
+    }
+
+    fn write_source(dir: &Path) {
+        fs::create_dir_all(dir.join("photos")).unwrap();
+        fs::write(dir.join("photos").join("cinc.png"), b"png").unwrap();
+        fs::write(
+            dir.join(OFFICERS_FILE),
+            r#"[{"thai_name": "พล.ร.อ. หนึ่ง", "position_thai": "ผู้บัญชาการทหารเรือ", "position_english": "Commander-in-Chief", "photo": "photos/cinc.png"}]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join(RANKS_FILE),
+            r#"[{"abbreviation": "พล.ร.อ.", "name_thai": "พลเรือเอก", "name_english": "Admiral", "order_index": 1}]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join(UNITS_FILE),
+            r#"[{"unit_id": "1000000", "unit_name": "กองทัพเรือ", "unit_abbr": "ทร."}]"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_build_and_install_pack() {
+        let source = TempDir::new().expect("temp dir should be created");
+        write_source(source.path());
+        let pack_path = source.path().join("starter.zip");
+        let manifest = build_pack_at(
+            source.path(),
+            &pack_path,
+            &signing_key(),
+            "Fleet starter",
+            "1.0",
+            None,
+        )
+        .expect("pack should build");
+        assert_eq!(manifest.files.len(), 4);
+
+        let pack =
+            read_pack(&pack_path, &[signing_key().verifying_key()]).expect("pack should verify");
+        let mut conn = create_app_db();
+        let content = Connection::open_in_memory().expect("In-memory db should open");
+        content
+            .execute(
+                "CREATE TABLE OwnerUnits (unit_id VARCHAR(7) PRIMARY KEY, unit_name VARCHAR(255) NOT NULL, unit_abbr VARCHAR(100), parent_id VARCHAR(7), unit_level INT)",
+                [],
+            )
+            .unwrap();
+        let media = TempDir::new().expect("temp dir should be created");
+
+        let report = install_pack_with_conn(&mut conn, Some(&content), media.path(), &pack)
+            .expect("install should succeed");
+        assert_eq!(
+            (
+                report.officers_added,
+                report.ranks_added,
+                report.units_added,
+                report.photos_added
+            ),
+            (1, 1, 1, 1)
+        );
+        let avatar_path: String = conn
+            .query_row("SELECT avatar_path FROM high_ranking_officers", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(fs::read(media.path().join(avatar_path)).unwrap(), b"png");
+        assert_eq!(
+            get_ranks_with_conn(&conn).unwrap()[0].name_english,
+            "Admiral"
+        );
+
+        // Installing again changes nothing
+        let again = install_pack_with_conn(&mut conn, Some(&content), media.path(), &pack)
+            .expect("reinstall should succeed");
+        assert_eq!((again.officers_added, again.skipped), (0, 3));
+    }
+
+    #[test]
+    fn test_rejects_wrong_key_and_tampered_files() {
+        let source = TempDir::new().expect("temp dir should be created");
+        write_source(source.path());
+        let pack_path = source.path().join("starter.zip");
+        build_pack_at(
+            source.path(),
+            &pack_path,
+            &signing_key(),
+            "Fleet starter",
+            "1.0",
+            None,
+        )
+        .expect("pack should build");
+        assert!(read_pack(&pack_path, &trusted_keys().unwrap()).is_err());
+        let other = SigningKey::from_bytes(&[9u8; 32]);
+        assert!(read_pack(&pack_path, &[other.verifying_key()]).is_err());
+
+        // Same signed manifest, different ranks.json
+        let tampered = source.path().join("tampered.zip");
+        let mut archive = zip::ZipArchive::new(fs::File::open(&pack_path).unwrap()).unwrap();
+        let mut zip = ZipWriter::new(fs::File::create(&tampered).unwrap());
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).unwrap();
+            let name = entry.name().to_string();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            if name == RANKS_FILE {
+                data = br#"[{"abbreviation": "x", "name_thai": "x"}]"#.to_vec();
+            }
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let error = read_pack(&tampered, &[signing_key().verifying_key()])
+            .err()
+            .expect("tampered pack should be refused");
+        assert!(error.contains("checksum"));
+        assert!(validate_entry_name("../evil.json").is_err());
+    }
+}
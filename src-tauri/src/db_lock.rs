@@ -0,0 +1,284 @@
+//! Detection of locked databases and stale SQLite side files
+//!
+//! Each running instance records its pid in `app.lock` in the app root. When
+//! a command hits a lock, `diagnose_database_lock` tells apart "another copy
+//! is running" (live pid in the lock file or a write lock we cannot take)
+//! from "a crashed instance left `-wal`/`-shm`/`-journal` files behind".
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::error_codes::{self, DATABASE_LOCKED, DATABASE_STALE_FILES};
+use crate::logger;
+use crate::storage_paths;
+
+const INSTANCE_LOCK_FILENAME: &str = "app.lock";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InstanceLock {
+    pub pid: u32,
+    /// Unix seconds
+    pub started_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SideFileInfo {
+    pub path: String,
+    pub size: u64,
+    pub modified_secs_ago: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LockDiagnostics {
+    pub database_path: String,
+    pub side_files: Vec<SideFileInfo>,
+    /// A write lock could not be taken right now
+    pub write_locked: bool,
+    /// Another instance registered in app.lock; `other_instance_alive` is None
+    /// when liveness cannot be determined on this platform
+    pub other_instance: Option<InstanceLock>,
+    pub other_instance_alive: Option<bool>,
+    /// DB_LOCKED, DB_STALE_FILES or None when nothing is wrong
+    pub code: Option<String>,
+    pub message: String,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// `-wal`, `-shm` and `-journal` files next to the database that exist
+pub fn find_side_files(db_path: &Path) -> Vec<SideFileInfo> {
+    ["-wal", "-shm", "-journal"]
+        .iter()
+        .filter_map(|suffix| {
+            let mut name = db_path.as_os_str().to_os_string();
+            name.push(suffix);
+            let path = PathBuf::from(name);
+            let metadata = fs::metadata(&path).ok()?;
+            Some(SideFileInfo {
+                path: path.to_string_lossy().to_string(),
+                size: metadata.len(),
+                modified_secs_ago: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|m| SystemTime::now().duration_since(m).ok())
+                    .map(|d| d.as_secs()),
+            })
+        })
+        .collect()
+}
+
+/// Try to take (and immediately release) a write lock without waiting
+pub fn is_write_locked(db_path: &Path) -> Result<bool, String> {
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+    conn.busy_timeout(Duration::from_millis(0))
+        .map_err(|e| format!("Failed to set busy timeout: {}", e))?;
+
+    match conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;") {
+        Ok(()) => Ok(false),
+        Err(e) if error_codes::is_lock_error(&e) => Ok(true),
+        Err(e) => Err(format!("Failed to test database lock: {}", e)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> Option<bool> {
+    Some(Path::new("/proc").join(pid.to_string()).exists())
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> Option<bool> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(stdout.contains(&format!("\"{}\"", pid)))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+pub fn read_instance_lock(path: &Path) -> Option<InstanceLock> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Record this process in app.lock unless another live instance owns it
+/// Returns the other instance when one is running
+pub fn register_instance_in(app_root: &Path) -> Result<Option<InstanceLock>, String> {
+    let lock_path = app_root.join(INSTANCE_LOCK_FILENAME);
+    let own_pid = std::process::id();
+
+    if let Some(existing) = read_instance_lock(&lock_path) {
+        if existing.pid != own_pid && process_alive(existing.pid) != Some(false) {
+            return Ok(Some(existing));
+        }
+    }
+
+    let lock = InstanceLock {
+        pid: own_pid,
+        started_at: now_secs(),
+    };
+    let content = serde_json::to_string(&lock)
+        .map_err(|e| format!("Failed to serialize instance lock: {}", e))?;
+    fs::write(&lock_path, content).map_err(|e| format!("Failed to write instance lock: {}", e))?;
+    Ok(None)
+}
+
+pub fn register_instance() -> Result<Option<InstanceLock>, String> {
+    let other = register_instance_in(&storage_paths::get_app_root()?)?;
+    if let Some(ref other) = other {
+        logger::warn(format!(
+            "Another instance (pid {}) appears to be running - database access may fail",
+            other.pid
+        ));
+    }
+    Ok(other)
+}
+
+/// Decide code and message from the collected facts
+pub fn classify_lock_state(
+    write_locked: bool,
+    other_instance_alive: Option<bool>,
+    has_side_files: bool,
+) -> (Option<String>, String) {
+    if write_locked || other_instance_alive == Some(true) {
+        (
+            Some(DATABASE_LOCKED.to_string()),
+            "The database is in use by another copy of the application. Close other copies and try again."
+                .to_string(),
+        )
+    } else if has_side_files {
+        (
+            Some(DATABASE_STALE_FILES.to_string()),
+            "Leftover database journal files were found from an earlier crash. They are recovered automatically on the next write."
+                .to_string(),
+        )
+    } else {
+        (None, "The database is not locked".to_string())
+    }
+}
+
+pub fn diagnose_database_lock_at(
+    db_path: &Path,
+    app_root: &Path,
+) -> Result<LockDiagnostics, String> {
+    let side_files = find_side_files(db_path);
+    let write_locked = if db_path.exists() {
+        is_write_locked(db_path)?
+    } else {
+        false
+    };
+
+    let own_pid = std::process::id();
+    let other_instance = read_instance_lock(&app_root.join(INSTANCE_LOCK_FILENAME))
+        .filter(|lock| lock.pid != own_pid);
+    let other_instance_alive = other_instance.as_ref().and_then(|l| process_alive(l.pid));
+
+    // WAL mode keeps -wal/-shm while the app itself has connections open, so
+    // side files only count as stale when nobody holds the database
+    let (code, message) =
+        classify_lock_state(write_locked, other_instance_alive, !side_files.is_empty());
+
+    Ok(LockDiagnostics {
+        database_path: db_path.to_string_lossy().to_string(),
+        side_files,
+        write_locked,
+        other_instance,
+        other_instance_alive,
+        code,
+        message,
+    })
+}
+
+pub fn diagnose_database_lock() -> Result<LockDiagnostics, String> {
+    diagnose_database_lock_at(
+        &storage_paths::get_database_path()?,
+        &storage_paths::get_app_root()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_classify_lock_state() {
+        assert_eq!(
+            classify_lock_state(true, None, false).0.as_deref(),
+            Some(DATABASE_LOCKED)
+        );
+        assert_eq!(
+            classify_lock_state(false, Some(true), true).0.as_deref(),
+            Some(DATABASE_LOCKED)
+        );
+        assert_eq!(
+            classify_lock_state(false, Some(false), true).0.as_deref(),
+            Some(DATABASE_STALE_FILES)
+        );
+        assert_eq!(classify_lock_state(false, None, false).0, None);
+    }
+
+    #[test]
+    fn test_write_lock_detected() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let db_path = dir.path().join("database.db");
+        let holder = Connection::open(&db_path).expect("db should open");
+        holder
+            .execute_batch("CREATE TABLE t (x INTEGER);")
+            .expect("table should be created");
+
+        assert!(!is_write_locked(&db_path).unwrap());
+        holder.execute_batch("BEGIN IMMEDIATE;").unwrap();
+        assert!(is_write_locked(&db_path).unwrap());
+        holder.execute_batch("ROLLBACK;").unwrap();
+    }
+
+    #[test]
+    fn test_side_files_found() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let db_path = dir.path().join("database.db");
+        fs::write(&db_path, b"").unwrap();
+        fs::write(dir.path().join("database.db-wal"), b"wal").unwrap();
+
+        let files = find_side_files(&db_path);
+
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.ends_with("database.db-wal"));
+    }
+
+    #[test]
+    fn test_register_instance_rewrites_own_lock() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let own = InstanceLock {
+            pid: std::process::id(),
+            started_at: 1,
+        };
+        fs::write(
+            dir.path().join(INSTANCE_LOCK_FILENAME),
+            serde_json::to_string(&own).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(register_instance_in(dir.path()).unwrap(), None);
+        let lock = read_instance_lock(&dir.path().join(INSTANCE_LOCK_FILENAME)).unwrap();
+        assert_eq!(lock.pid, std::process::id());
+    }
+}
@@ -0,0 +1,367 @@
+//! Periodic database optimization run while the app is idle
+//!
+//! A background thread wakes up every few minutes and, once the maintenance
+//! interval has passed and no connection was opened recently, runs
+//! `PRAGMA optimize`, an incremental vacuum (when the database was created
+//! with auto_vacuum = INCREMENTAL) and a WAL checkpoint (in WAL mode).
+//! Each run is written to the activity log; the latest result is kept in
+//! memory for the diagnostics view.
+//!
+//! Heavy imports can grow the `-wal` file well past the database itself, so
+//! between full runs the scheduler also checkpoints on its own once the WAL
+//! passes `WAL_CHECKPOINT_THRESHOLD_BYTES` and the app is idle.
+
+use lazy_static::lazy_static;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::activity_log;
+use crate::database;
+use crate::logger;
+use crate::temp_space::{self, TempSpaceUsage};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAINTENANCE_INTERVAL_SECS: u64 = 6 * 60 * 60;
+/// No connection opened for this long counts as idle
+const IDLE_THRESHOLD_SECS: u64 = 2 * 60;
+/// PRAGMA auto_vacuum value for INCREMENTAL
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+/// WAL size that triggers a checkpoint outside the regular interval
+pub const WAL_CHECKPOINT_THRESHOLD_BYTES: u64 = 64 * 1024 * 1024;
+
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
+static LAST_ACTIVITY: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref LAST_RESULT: Mutex<Option<MaintenanceResult>> = Mutex::new(None);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceResult {
+    /// Unix seconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub journal_mode: String,
+    pub pages_freed: i64,
+    /// (busy, wal frames, frames checkpointed); None outside WAL mode
+    pub wal_checkpoint: Option<(i64, i64, i64)>,
+    #[serde(default)]
+    pub wal_bytes_before: u64,
+    #[serde(default)]
+    pub wal_bytes_after: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceStatus {
+    pub scheduler_running: bool,
+    pub interval_secs: u64,
+    pub last_result: Option<MaintenanceResult>,
+    pub database_bytes: u64,
+    /// Size of the `-wal` file; 0 outside WAL mode
+    pub wal_bytes: u64,
+    pub wal_checkpoint_threshold_bytes: u64,
+    /// Scratch space used by imports; stays high only if cleanup is failing
+    pub temp_space: TempSpaceUsage,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Called when a connection is opened so maintenance stays out of the way
+pub fn mark_activity() {
+    LAST_ACTIVITY.store(now_secs(), Ordering::Relaxed);
+}
+
+/// Seconds since a connection was last opened
+pub fn idle_secs() -> u64 {
+    now_secs().saturating_sub(LAST_ACTIVITY.load(Ordering::Relaxed))
+}
+
+/// Whether a run is due, given the last run, the last activity and now (all unix seconds)
+pub fn is_maintenance_due(last_run: Option<u64>, last_activity: u64, now: u64) -> bool {
+    let interval_elapsed = match last_run {
+        Some(last) => now.saturating_sub(last) >= MAINTENANCE_INTERVAL_SECS,
+        None => true,
+    };
+    interval_elapsed && now.saturating_sub(last_activity) >= IDLE_THRESHOLD_SECS
+}
+
+/// Whether the WAL has grown enough to checkpoint before the next full run
+pub fn is_wal_checkpoint_due(wal_bytes: u64, last_activity: u64, now: u64) -> bool {
+    wal_bytes >= WAL_CHECKPOINT_THRESHOLD_BYTES
+        && now.saturating_sub(last_activity) >= IDLE_THRESHOLD_SECS
+}
+
+/// `<database>-wal`, next to the database file
+pub fn wal_file_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_os_string();
+    name.push("-wal");
+    PathBuf::from(name)
+}
+
+pub fn wal_size_bytes(db_path: &Path) -> u64 {
+    std::fs::metadata(wal_file_path(db_path))
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+/// WAL size for the file behind `conn`; 0 for in-memory databases
+fn conn_wal_size_bytes(conn: &Connection) -> u64 {
+    conn.path()
+        .filter(|path| !path.is_empty())
+        .map(|path| wal_size_bytes(Path::new(path)))
+        .unwrap_or(0)
+}
+
+fn checkpoint_truncate(conn: &Connection) -> Result<(i64, i64, i64), String> {
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })
+    .map_err(|e| format!("Failed to checkpoint WAL: {}", e))
+}
+
+fn pragma_i64(conn: &Connection, pragma: &str) -> Result<i64, String> {
+    conn.query_row(&format!("PRAGMA {}", pragma), [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read PRAGMA {}: {}", pragma, e))
+}
+
+pub fn run_maintenance_with_conn(conn: &Connection) -> Result<MaintenanceResult, String> {
+    let started_at = now_secs();
+    let timer = Instant::now();
+
+    let free_before = pragma_i64(conn, "freelist_count")?;
+    let wal_bytes_before = conn_wal_size_bytes(conn);
+
+    conn.execute_batch("PRAGMA optimize")
+        .map_err(|e| format!("Failed to optimize database: {}", e))?;
+
+    // Only databases created with auto_vacuum = INCREMENTAL can shrink this way
+    if pragma_i64(conn, "auto_vacuum")? == AUTO_VACUUM_INCREMENTAL {
+        conn.execute_batch("PRAGMA incremental_vacuum")
+            .map_err(|e| format!("Failed to run incremental vacuum: {}", e))?;
+    }
+
+    let journal_mode: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read journal mode: {}", e))?;
+
+    let wal_checkpoint = if journal_mode.eq_ignore_ascii_case("wal") {
+        Some(checkpoint_truncate(conn)?)
+    } else {
+        None
+    };
+
+    let free_after = pragma_i64(conn, "freelist_count")?;
+
+    Ok(MaintenanceResult {
+        started_at,
+        duration_ms: timer.elapsed().as_millis() as u64,
+        journal_mode,
+        pages_freed: free_before - free_after,
+        wal_checkpoint,
+        wal_bytes_before,
+        wal_bytes_after: conn_wal_size_bytes(conn),
+        error: None,
+    })
+}
+
+/// Run maintenance now, record it in the activity log and remember the result
+pub fn run_maintenance() -> Result<MaintenanceResult, String> {
+    let outcome = database::get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))
+        .and_then(|conn| {
+            let result = run_maintenance_with_conn(&conn);
+            if let Ok(ref r) = result {
+                let details = serde_json::to_string(r).unwrap_or_default();
+                if let Err(e) = activity_log::record_event_with_conn(
+                    &conn,
+                    activity_log::EVENT_MAINTENANCE,
+                    None,
+                    None,
+                    Some(&details),
+                ) {
+                    logger::warn(format!("Failed to record maintenance run: {}", e));
+                }
+            }
+            result
+        });
+
+    let stored = match &outcome {
+        Ok(result) => result.clone(),
+        Err(e) => MaintenanceResult {
+            started_at: now_secs(),
+            duration_ms: 0,
+            journal_mode: String::new(),
+            pages_freed: 0,
+            wal_checkpoint: None,
+            wal_bytes_before: 0,
+            wal_bytes_after: 0,
+            error: Some(e.clone()),
+        },
+    };
+    if let Ok(mut last) = LAST_RESULT.lock() {
+        *last = Some(stored);
+    }
+
+    outcome
+}
+
+pub fn get_maintenance_status() -> Result<MaintenanceStatus, String> {
+    let last_result = LAST_RESULT
+        .lock()
+        .map_err(|e| format!("Failed to acquire maintenance lock: {}", e))?
+        .clone();
+
+    let db_path = database::get_database_path()?;
+    let database_bytes = std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+
+    Ok(MaintenanceStatus {
+        scheduler_running: SCHEDULER_STARTED.load(Ordering::Relaxed),
+        interval_secs: MAINTENANCE_INTERVAL_SECS,
+        last_result,
+        database_bytes,
+        wal_bytes: wal_size_bytes(&db_path),
+        wal_checkpoint_threshold_bytes: WAL_CHECKPOINT_THRESHOLD_BYTES,
+        temp_space: temp_space::temp_space_usage()?,
+    })
+}
+
+/// Checkpoint only, for a WAL that outgrew the threshold between full runs
+/// and for the idle task scheduler
+pub fn checkpoint_wal() -> Result<(i64, i64, i64), String> {
+    let conn = database::get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))?;
+    checkpoint_truncate(&conn)
+}
+
+/// Start the background scheduler; later calls are no-ops
+pub fn start_maintenance_scheduler() {
+    if SCHEDULER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    // Startup itself opens connections - count it as activity
+    mark_activity();
+
+    thread::spawn(|| loop {
+        thread::sleep(CHECK_INTERVAL);
+
+        let last_run = LAST_RESULT
+            .lock()
+            .ok()
+            .and_then(|last| last.as_ref().map(|r| r.started_at));
+        let last_activity = LAST_ACTIVITY.load(Ordering::Relaxed);
+        if !is_maintenance_due(last_run, last_activity, now_secs()) {
+            let wal_bytes = database::get_database_path()
+                .map(|path| wal_size_bytes(&path))
+                .unwrap_or(0);
+            if is_wal_checkpoint_due(wal_bytes, last_activity, now_secs()) {
+                match checkpoint_wal() {
+                    Ok((_, frames, checkpointed)) => logger::info(format!(
+                        "WAL checkpoint at {} bytes ({} of {} frames)",
+                        wal_bytes, checkpointed, frames
+                    )),
+                    Err(e) => logger::warn(format!("WAL checkpoint failed: {}", e)),
+                }
+            }
+            continue;
+        }
+        if !database::check_database_exists_and_valid().unwrap_or(false) {
+            continue;
+        }
+
+        match run_maintenance() {
+            Ok(result) => logger::info(format!(
+                "Database maintenance finished in {} ms ({} pages freed)",
+                result.duration_ms, result.pages_freed
+            )),
+            Err(e) => logger::warn(format!("Database maintenance failed: {}", e)),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_maintenance_due() {
+        let now = 1_000_000;
+        assert!(is_maintenance_due(None, now - IDLE_THRESHOLD_SECS, now));
+        assert!(!is_maintenance_due(None, now - 10, now));
+        assert!(!is_maintenance_due(
+            Some(now - 60),
+            now - IDLE_THRESHOLD_SECS,
+            now
+        ));
+        assert!(is_maintenance_due(
+            Some(now - MAINTENANCE_INTERVAL_SECS),
+            now - IDLE_THRESHOLD_SECS,
+            now
+        ));
+    }
+
+    #[test]
+    fn test_wal_checkpoint_due() {
+        let now = 1_000_000;
+        let idle = now - IDLE_THRESHOLD_SECS;
+        assert!(is_wal_checkpoint_due(
+            WAL_CHECKPOINT_THRESHOLD_BYTES,
+            idle,
+            now
+        ));
+        assert!(!is_wal_checkpoint_due(
+            WAL_CHECKPOINT_THRESHOLD_BYTES - 1,
+            idle,
+            now
+        ));
+        assert!(!is_wal_checkpoint_due(
+            WAL_CHECKPOINT_THRESHOLD_BYTES,
+            now - 10,
+            now
+        ));
+        assert_eq!(
+            wal_file_path(Path::new("/data/database.db")),
+            PathBuf::from("/data/database.db-wal")
+        );
+    }
+
+    #[test]
+    fn test_run_maintenance_in_wal_mode() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let conn = Connection::open(dir.path().join("database.db")).expect("db should open");
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; PRAGMA journal_mode = WAL;")
+            .expect("pragmas should apply");
+        apply_schema(&conn).expect("schema should apply");
+
+        let result = run_maintenance_with_conn(&conn).expect("maintenance should succeed");
+
+        assert_eq!(result.journal_mode, "wal");
+        assert!(result.wal_checkpoint.is_some());
+        assert!(result.wal_bytes_before > 0);
+        assert_eq!(result.wal_bytes_after, 0);
+        assert!(result.error.is_none());
+    }
+
+    #[test]
+    fn test_run_maintenance_without_wal() {
+        let conn = create_app_db();
+
+        let result = run_maintenance_with_conn(&conn).expect("maintenance should succeed");
+
+        assert!(result.wal_checkpoint.is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Free-space check run before writing backups, imports and media
+//!
+//! A full disk otherwise surfaces as an opaque IO error halfway through a
+//! write. Callers estimate how many bytes they need and call
+//! `ensure_free_space` on the destination first; a shortfall comes back with
+//! the INSUFFICIENT_DISK_SPACE code so the UI can say what is wrong.
+
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::error_codes::{self, INSUFFICIENT_DISK_SPACE};
+use crate::logger;
+
+/// Headroom kept free on top of the estimate (SQLite journals, temp files)
+pub const SAFETY_MARGIN_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Total size of the files under `path`; 0 when it does not exist
+pub fn directory_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+pub fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn format_megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Decision part of the check, separate from asking the OS
+pub fn check_free_space(available: u64, required: u64, destination: &Path) -> Result<(), String> {
+    let needed = required.saturating_add(SAFETY_MARGIN_BYTES);
+    if available >= needed {
+        return Ok(());
+    }
+    Err(error_codes::with_code(
+        INSUFFICIENT_DISK_SPACE,
+        &format!(
+            "Not enough disk space on the drive of {}: {} needed, {} free",
+            destination.display(),
+            format_megabytes(needed),
+            format_megabytes(available)
+        ),
+    ))
+}
+
+/// Check the drive holding `destination` (which may not exist yet) for
+/// `required` bytes plus the safety margin
+pub fn ensure_free_space(destination: &Path, required: u64) -> Result<(), String> {
+    // Query the closest existing ancestor; the target dir is often created later
+    let existing = destination.ancestors().find(|p| p.exists());
+    let Some(existing) = existing else {
+        return Ok(());
+    };
+
+    match fs2::available_space(existing) {
+        Ok(available) => check_free_space(available, required, destination),
+        Err(e) => {
+            // Not knowing is no reason to block the operation
+            logger::warn(format!(
+                "Could not determine free space for {}: {}",
+                existing.display(),
+                e
+            ));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_shortfall_is_coded() {
+        let error = check_free_space(10, 20, Path::new("/backups")).unwrap_err();
+        assert_eq!(
+            error_codes::find_code(&error),
+            Some(INSUFFICIENT_DISK_SPACE)
+        );
+
+        assert!(check_free_space(SAFETY_MARGIN_BYTES + 20, 20, Path::new("/backups")).is_ok());
+    }
+
+    #[test]
+    fn test_missing_destination_uses_existing_parent() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        assert!(ensure_free_space(&dir.path().join("not/yet/created"), 1).is_ok());
+    }
+
+    #[test]
+    fn test_directory_size() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.bin"), [0u8; 10]).unwrap();
+        std::fs::write(dir.path().join("sub/b.bin"), [0u8; 5]).unwrap();
+
+        assert_eq!(directory_size(dir.path()), 15);
+        assert_eq!(directory_size(&dir.path().join("missing")), 0);
+    }
+}
@@ -0,0 +1,103 @@
+//! Machine-readable codes embedded in command error strings
+//!
+//! Commands return `Result<T, String>`; errors the UI has to react to in a
+//! specific way carry a code prefix ("DB_LOCKED: ...") that survives being
+//! wrapped in further context by callers.
+
+pub const DATABASE_LOCKED: &str = "DB_LOCKED";
+pub const DATABASE_STALE_FILES: &str = "DB_STALE_FILES";
+pub const INSUFFICIENT_DISK_SPACE: &str = "INSUFFICIENT_DISK_SPACE";
+/// Followed by the administrator's maintenance message
+pub const MAINTENANCE_MODE: &str = "MAINTENANCE_MODE";
+/// Followed by a JSON list of field errors (see `validation`)
+pub const VALIDATION_FAILED: &str = "VALIDATION_FAILED";
+/// Followed by the current record as JSON (see `database::row_version_conflict`)
+pub const ROW_VERSION_CONFLICT: &str = "ROW_VERSION_CONFLICT";
+/// Followed by JSON naming the stalled job (see `watchdog`)
+pub const OPERATION_TIMED_OUT: &str = "OPERATION_TIMED_OUT";
+/// Followed by JSON with the command and the required role (see `permissions`)
+pub const FORBIDDEN: &str = "FORBIDDEN";
+/// Followed by JSON with the command and the size limit (see `ipc_guard`)
+pub const PAYLOAD_TOO_LARGE: &str = "PAYLOAD_TOO_LARGE";
+/// The backup is encrypted and the password is missing or wrong (see `backup_encryption`)
+pub const BACKUP_PASSWORD_REQUIRED: &str = "BACKUP_PASSWORD_REQUIRED";
+/// The session's user has a temporary password and may only change it (see `permissions`)
+pub const PASSWORD_CHANGE_REQUIRED: &str = "PASSWORD_CHANGE_REQUIRED";
+/// Followed by JSON naming the restore or import still running (see `watchdog`)
+pub const OPERATION_IN_PROGRESS: &str = "OPERATION_IN_PROGRESS";
+
+#[cfg(test)]
+const KNOWN_CODES: &[&str] = &[
+    DATABASE_LOCKED,
+    DATABASE_STALE_FILES,
+    INSUFFICIENT_DISK_SPACE,
+    MAINTENANCE_MODE,
+    VALIDATION_FAILED,
+    ROW_VERSION_CONFLICT,
+    OPERATION_TIMED_OUT,
+    FORBIDDEN,
+    PAYLOAD_TOO_LARGE,
+    BACKUP_PASSWORD_REQUIRED,
+    PASSWORD_CHANGE_REQUIRED,
+    OPERATION_IN_PROGRESS,
+];
+
+pub fn with_code(code: &str, message: &str) -> String {
+    format!("{}: {}", code, message)
+}
+
+/// First known code found anywhere in an error string
+#[cfg(test)]
+pub fn find_code(error: &str) -> Option<&'static str> {
+    KNOWN_CODES
+        .iter()
+        .find(|code| error.contains(&format!("{}:", code)))
+        .copied()
+}
+
+/// Whether SQLite reported that another connection holds a lock
+pub fn is_lock_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+/// "context: error", with lock errors replaced by a coded, user-facing message
+pub fn describe_sql_error(context: &str, e: &rusqlite::Error) -> String {
+    if is_lock_error(e) {
+        with_code(
+            DATABASE_LOCKED,
+            "The database is in use by another copy of the application. Close other copies and try again.",
+        )
+    } else {
+        format!("{}: {}", context, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_survives_wrapping() {
+        let error = format!(
+            "Failed to create user: {}",
+            with_code(DATABASE_LOCKED, "in use")
+        );
+        assert_eq!(find_code(&error), Some(DATABASE_LOCKED));
+        assert_eq!(find_code("Failed to create user: UNIQUE constraint"), None);
+    }
+
+    #[test]
+    fn test_busy_error_is_coded() {
+        let busy = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        );
+        assert!(describe_sql_error("Failed to update user", &busy).starts_with(DATABASE_LOCKED));
+
+        let other = rusqlite::Error::QueryReturnedNoRows;
+        assert!(describe_sql_error("Failed to update user", &other).starts_with("Failed"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Password-protected zip output for database exports
+//!
+//! Exports hold personnel data, so they can be written as an AES-256 zip
+//! instead of a plain file. The passphrase is either typed for one export or
+//! remembered in the OS keyring; it is never written to the settings file.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use zip_aes::write::SimpleFileOptions;
+use zip_aes::{AesMode, CompressionMethod, ZipWriter};
+
+use crate::settings;
+
+/// Keyring account of the remembered export passphrase
+const EXPORT_PASSPHRASE_ACCOUNT: &str = "export-passphrase";
+
+pub const MIN_PASSPHRASE_LENGTH: usize = 8;
+
+/// How the UI asks for an export to be encrypted
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportEncryption {
+    /// Passphrase for this export only
+    pub passphrase: Option<String>,
+    /// Use the passphrase stored with `store_export_passphrase`
+    #[serde(default)]
+    pub use_keyring: bool,
+}
+
+pub fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(format!(
+            "Export passphrase must be at least {} characters",
+            MIN_PASSPHRASE_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// The passphrase to encrypt with; a typed one wins over the keyring
+pub fn resolve_passphrase(encryption: &ExportEncryption) -> Result<String, String> {
+    let passphrase = match encryption.passphrase.as_deref().filter(|p| !p.is_empty()) {
+        Some(passphrase) => passphrase.to_string(),
+        None if encryption.use_keyring => settings::load_secret(EXPORT_PASSPHRASE_ACCOUNT)?
+            .ok_or_else(|| "No export passphrase is stored in the keyring".to_string())?,
+        None => return Err("An export passphrase is required".to_string()),
+    };
+    validate_passphrase(&passphrase)?;
+    Ok(passphrase)
+}
+
+pub fn store_export_passphrase(passphrase: &str) -> Result<(), String> {
+    validate_passphrase(passphrase)?;
+    settings::store_secret(EXPORT_PASSPHRASE_ACCOUNT, passphrase)
+}
+
+pub fn has_export_passphrase() -> Result<bool, String> {
+    Ok(settings::load_secret(EXPORT_PASSPHRASE_ACCOUNT)?.is_some())
+}
+
+/// Write `content` as the single entry `entry_name` of an AES-256 zip
+pub fn write_encrypted_zip(
+    zip_path: &Path,
+    entry_name: &str,
+    content: &[u8],
+    passphrase: &str,
+) -> Result<(), String> {
+    let write = || -> Result<(), String> {
+        let file =
+            fs::File::create(zip_path).map_err(|e| format!("Failed to create zip file: {}", e))?;
+        let mut zip = ZipWriter::new(file);
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .with_aes_encryption(AesMode::Aes256, passphrase);
+
+        zip.start_file(entry_name, options)
+            .map_err(|e| format!("Failed to add {} to zip: {}", entry_name, e))?;
+        zip.write_all(content)
+            .map_err(|e| format!("Failed to write {} to zip: {}", entry_name, e))?;
+        zip.finish()
+            .map_err(|e| format!("Failed to finalize zip file: {}", e))?;
+        Ok(())
+    };
+
+    // A half-written archive is useless and may hold readable fragments
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(zip_path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_encrypted_zip_needs_passphrase() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = dir.path().join("export.json.zip");
+
+        write_encrypted_zip(&zip_path, "export.json", b"{\"users\":[]}", "correct horse")
+            .expect("encrypted zip should be written");
+
+        let raw = fs::read(&zip_path).unwrap();
+        assert!(!raw.windows(7).any(|w| w == b"\"users\""));
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert!(archive
+            .by_name_decrypt("export.json", b"wrong passphrase")
+            .unwrap()
+            .is_err());
+        let mut content = String::new();
+        archive
+            .by_name_decrypt("export.json", b"correct horse")
+            .unwrap()
+            .expect("passphrase should be accepted")
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "{\"users\":[]}");
+    }
+
+    #[test]
+    fn test_typed_passphrase_is_validated() {
+        let typed = ExportEncryption {
+            passphrase: Some("short".to_string()),
+            use_keyring: true,
+        };
+        assert!(resolve_passphrase(&typed).is_err());
+        assert!(resolve_passphrase(&ExportEncryption::default()).is_err());
+
+        let typed = ExportEncryption {
+            passphrase: Some("long enough".to_string()),
+            use_keyring: false,
+        };
+        assert_eq!(resolve_passphrase(&typed).unwrap(), "long enough");
+    }
+}
@@ -0,0 +1,227 @@
+//! Native open/save dialogs for backup files
+//!
+//! The page asks for a path instead of building one: `choose_backup_destination`
+//! opens a save dialog for copying a backup elsewhere and
+//! `choose_backup_to_restore` an open dialog limited to the backup formats.
+//! Both return None when the dialog is cancelled. A chosen path is checked
+//! (absolute, no "..", a backup extension, an existing folder or file) and
+//! kept here; the page gets an opaque id plus the path for display only.
+//! `copy_backup_to_location` and `restore_backup_from_path` take that id, so
+//! what they touch is a path the user picked, not one the page made up. Ids
+//! are single-use. The dialogs block, so callers run them off the main thread.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::api::dialog::blocking::FileDialogBuilder;
+
+use crate::backup_manager;
+use crate::validation;
+
+/// Files a restore accepts, as written by the backup commands
+pub const BACKUP_EXTENSIONS: &[&str] = &["zip", "json", "db", "sql"];
+
+/// What a chosen path may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChoiceKind {
+    /// From `choose_backup_destination`: a file to write
+    Save,
+    /// From `choose_backup_to_restore`: a backup to read
+    Open,
+}
+
+/// Paths picked in a dialog, by the id handed to the page
+type Choices = HashMap<String, (ChoiceKind, PathBuf)>;
+
+lazy_static! {
+    static ref CHOICES: Mutex<Choices> = Mutex::new(HashMap::new());
+}
+
+static CHOICE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A dialog result as the page sees it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DialogChoice {
+    /// Pass this to the command that uses the path
+    pub id: String,
+    /// For display only
+    pub path: String,
+}
+
+fn lock_choices() -> Result<std::sync::MutexGuard<'static, Choices>, String> {
+    CHOICES
+        .lock()
+        .map_err(|e| format!("Failed to acquire dialog lock: {}", e))
+}
+
+/// Keep `path` server-side and return the id standing for it
+pub fn remember_choice(kind: ChoiceKind, path: PathBuf) -> Result<DialogChoice, String> {
+    let id = format!(
+        "dialog-{}-{}",
+        chrono::Utc::now().timestamp_millis(),
+        CHOICE_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    let display = path.to_string_lossy().to_string();
+    lock_choices()?.insert(id.clone(), (kind, path));
+    Ok(DialogChoice { id, path: display })
+}
+
+/// The path a dialog returned as `id`; each id works once
+pub fn take_choice(id: &str, kind: ChoiceKind) -> Result<PathBuf, String> {
+    let mut choices = lock_choices()?;
+    match choices.get(id) {
+        Some((chosen_kind, _)) if *chosen_kind == kind => {}
+        _ => return Err(format!("Unknown or already used file choice: {}", id)),
+    }
+    Ok(choices.remove(id).map(|(_, path)| path).unwrap_or_default())
+}
+
+fn filter_name(extension: &str) -> &'static str {
+    match extension {
+        "zip" => "Hybrid backup (.zip)",
+        "json" => "JSON backup (.json)",
+        "db" => "SQLite database (.db)",
+        "sql" => "SQL dump (.sql)",
+        _ => "Backup",
+    }
+}
+
+/// Lowercase extension of `path`, if it is one of `extensions`
+pub fn backup_extension(path: &Path, extensions: &[&str]) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .filter(|e| extensions.contains(&e.as_str()))
+}
+
+/// Where dialogs start when there is no better folder
+fn default_directory() -> Option<PathBuf> {
+    tauri::api::path::document_dir().or_else(tauri::api::path::home_dir)
+}
+
+/// `path` as a place to write a `.extension` file: absolute, in an existing
+/// folder and not a folder itself. Some Linux dialogs return the name
+/// without the extension of the filter, so it is added when missing.
+pub fn validated_save_path(path: &Path, extension: &str) -> Result<PathBuf, String> {
+    let mut path = validation::absolute_path("destination_path", &path.to_string_lossy())?;
+    if backup_extension(&path, &[extension]).is_none() {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", extension));
+        path.set_file_name(name);
+    }
+    if !path.parent().map(Path::is_dir).unwrap_or(false) {
+        return Err(format!("Folder does not exist: {}", path.display()));
+    }
+    if path.is_dir() {
+        return Err(format!("Destination is a folder: {}", path.display()));
+    }
+    Ok(path)
+}
+
+/// `path` as an existing file with one of `extensions`
+pub fn validated_open_path(path: &Path, extensions: &[&str]) -> Result<PathBuf, String> {
+    let path = validation::absolute_path("path", &path.to_string_lossy())?;
+    if !path.is_file() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+    if backup_extension(&path, extensions).is_none() {
+        return Err(format!(
+            "Not a backup file (expected .{}): {}",
+            extensions.join(", ."),
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+/// Save dialog for a copy of `backup_filename`, named like the backup and
+/// filtered to its format
+pub fn choose_backup_destination(backup_filename: &str) -> Result<Option<DialogChoice>, String> {
+    let backup_filename = validation::file_name("backup_filename", backup_filename)?;
+    let extension = backup_extension(Path::new(&backup_filename), BACKUP_EXTENSIONS)
+        .ok_or_else(|| format!("Not a backup file: {}", backup_filename))?;
+
+    let mut dialog = FileDialogBuilder::new()
+        .set_title("Save a copy of the backup")
+        .set_file_name(&backup_filename)
+        .add_filter(filter_name(&extension), &[extension.as_str()]);
+    if let Some(dir) = default_directory() {
+        dialog = dialog.set_directory(dir);
+    }
+
+    match dialog.save_file() {
+        Some(path) => {
+            remember_choice(ChoiceKind::Save, validated_save_path(&path, &extension)?).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Open dialog for a backup file to restore, starting in the backup directory
+pub fn choose_backup_to_restore() -> Result<Option<DialogChoice>, String> {
+    let mut dialog = FileDialogBuilder::new()
+        .set_title("Choose a backup to restore")
+        .add_filter("All backups", BACKUP_EXTENSIONS);
+    for extension in BACKUP_EXTENSIONS {
+        dialog = dialog.add_filter(filter_name(extension), &[*extension]);
+    }
+    if let Some(dir) = backup_manager::get_backup_directory()
+        .ok()
+        .or_else(default_directory)
+    {
+        dialog = dialog.set_directory(dir);
+    }
+
+    match dialog.pick_file() {
+        Some(path) => remember_choice(
+            ChoiceKind::Open,
+            validated_open_path(&path, BACKUP_EXTENSIONS)?,
+        )
+        .map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_open_paths_are_checked() {
+        let dir = TempDir::new().expect("temp dir should be created");
+
+        let saved = validated_save_path(&dir.path().join("copy"), "zip").unwrap();
+        assert_eq!(saved, dir.path().join("copy.zip"));
+        let kept = validated_save_path(&dir.path().join("copy.ZIP"), "zip").unwrap();
+        assert_eq!(kept, dir.path().join("copy.ZIP"));
+        assert!(validated_save_path(&dir.path().join("missing/copy.zip"), "zip").is_err());
+        assert!(validated_save_path(Path::new("relative/copy.zip"), "zip").is_err());
+
+        let backup = dir.path().join("database_backup_1.json");
+        fs::write(&backup, b"{}").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"notes").unwrap();
+        assert_eq!(
+            validated_open_path(&backup, BACKUP_EXTENSIONS).unwrap(),
+            backup
+        );
+        assert!(validated_open_path(&dir.path().join("notes.txt"), BACKUP_EXTENSIONS).is_err());
+        assert!(validated_open_path(&dir.path().join("gone.zip"), BACKUP_EXTENSIONS).is_err());
+    }
+
+    #[test]
+    fn test_choices_are_single_use_and_kept_apart() {
+        let path = PathBuf::from("/backups/copy.zip");
+        let choice = remember_choice(ChoiceKind::Save, path.clone()).unwrap();
+        assert_eq!(choice.path, path.to_string_lossy());
+
+        assert!(take_choice(&choice.id, ChoiceKind::Open).is_err());
+        assert_eq!(take_choice(&choice.id, ChoiceKind::Save).unwrap(), path);
+        assert!(take_choice(&choice.id, ChoiceKind::Save).is_err());
+        assert!(take_choice("/etc/passwd", ChoiceKind::Open).is_err());
+    }
+}
@@ -1,12 +1,12 @@
 use crate::logger;
+use crate::media_maintenance::normalize_media_path;
+use crate::safe_path::{MediaRoot, SafePath};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock}; // Phase 1.4: Arc + RwLock for better concurrency
-use tauri::api::path::app_data_dir;
-use tauri::Config;
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,8 +18,25 @@ pub struct FileInfo {
     pub created_at: String,
 }
 
+/// Media subdirectory of user avatars
+pub const AVATARS_SUBDIR: &str = "avatars";
+/// Media subdirectory of high ranking officer photos
+pub const HIGH_RANKS_SUBDIR: &str = "high_ranks";
+/// Media subdirectory of officer signature images (see `officer_signature`)
+pub const SIGNATURES_SUBDIR: &str = "signatures";
+
+/// Result of cleaning one media subdirectory
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DirectoryCleanup {
+    pub directory: String,
+    pub scanned: u32,
+    pub deleted: u32,
+    pub freed_bytes: u64,
+}
+
 pub struct FileManager {
     media_dir: PathBuf,
+    media_root: MediaRoot,
     avatars_dir: PathBuf,
     high_ranks_dir: PathBuf,
 }
@@ -33,21 +50,19 @@ lazy_static! {
 
 impl FileManager {
     pub fn new() -> Result<Self, String> {
-        // Get app data directory with better error handling
-        let app_data = match app_data_dir(&Config::default()) {
-            Some(dir) => dir,
-            None => {
-                logger::critical("Failed to get app data directory");
-                return Err(
-                    "Failed to get app data directory - app may not have proper permissions"
-                        .to_string(),
-                );
+        // Media lives inside the active workspace
+        let media_dir = match crate::storage_paths::get_media_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                logger::critical(format!("Failed to resolve media directory: {}", e));
+                return Err(format!(
+                    "Failed to resolve media directory - app may not have proper permissions: {}",
+                    e
+                ));
             }
         };
-
-        let media_dir = app_data.join("pqs-rtn-hybrid-storage").join("media");
-        let avatars_dir = media_dir.join("avatars");
-        let high_ranks_dir = media_dir.join("high_ranks");
+        let avatars_dir = media_dir.join(AVATARS_SUBDIR);
+        let high_ranks_dir = media_dir.join(HIGH_RANKS_SUBDIR);
 
         // Create directories if they don't exist - with enhanced error handling
         match fs::create_dir_all(&avatars_dir) {
@@ -78,8 +93,11 @@ impl FileManager {
             }
         }
 
+        let media_root = MediaRoot::new(&media_dir)?;
+
         Ok(FileManager {
             media_dir,
+            media_root,
             avatars_dir,
             high_ranks_dir,
         })
@@ -117,16 +135,26 @@ impl FileManager {
         Ok(new_instance)
     }
 
+    /// Drop the cached instance so the next get_instance() re-resolves directories
+    /// Needed after switching workspace, since the media root changes
+    pub fn reset_instance() -> Result<(), String> {
+        let mut instance = FILE_MANAGER_INSTANCE
+            .write()
+            .map_err(|e| format!("Failed to acquire write lock on FileManager: {}", e))?;
+        *instance = None;
+        Ok(())
+    }
+
     pub fn get_media_directory(&self) -> &PathBuf {
         &self.media_dir
     }
 
-    pub fn save_avatar_file(
+    /// Fresh (relative path, full path) for a user avatar, without writing it
+    pub fn new_avatar_file_path(
         &self,
         user_id: i32,
-        file_data: &[u8],
         mime_type: &str,
-    ) -> Result<String, String> {
+    ) -> Result<(String, PathBuf), String> {
         // Generate unique filename
         let extension = match mime_type {
             "image/jpeg" => "jpg",
@@ -144,6 +172,25 @@ impl FileManager {
         );
         let file_path = self.avatars_dir.join(&filename);
 
+        // Relative path from media directory
+        let relative_path = file_path
+            .strip_prefix(&self.media_dir)
+            .map_err(|e| format!("Failed to get relative path: {}", e))?
+            .to_string_lossy()
+            .to_string();
+
+        Ok((relative_path, file_path))
+    }
+
+    pub fn save_avatar_file(
+        &self,
+        user_id: i32,
+        file_data: &[u8],
+        mime_type: &str,
+    ) -> Result<String, String> {
+        let (relative_path, file_path) = self.new_avatar_file_path(user_id, mime_type)?;
+        crate::disk_space::ensure_free_space(&self.avatars_dir, file_data.len() as u64)?;
+
         // Write file to disk
         let mut file = fs::File::create(&file_path)
             .map_err(|e| format!("Failed to create avatar file: {}", e))?;
@@ -151,16 +198,16 @@ impl FileManager {
         file.write_all(file_data)
             .map_err(|e| format!("Failed to write avatar data: {}", e))?;
 
-        // Return relative path from media directory
-        let relative_path = file_path
-            .strip_prefix(&self.media_dir)
-            .map_err(|e| format!("Failed to get relative path: {}", e))?;
+        Ok(relative_path)
+    }
 
-        Ok(relative_path.to_string_lossy().to_string())
+    /// Resolve a media-relative path, refusing anything outside the media directory
+    pub fn resolve_media_path(&self, relative_path: &str) -> Result<SafePath, String> {
+        self.media_root.resolve(relative_path)
     }
 
     pub fn get_avatar_file_path(&self, avatar_path: &str) -> Result<PathBuf, String> {
-        let full_path = self.media_dir.join(avatar_path);
+        let full_path = self.resolve_media_path(avatar_path)?.into_path_buf();
 
         if !full_path.exists() {
             return Err(format!("Avatar file not found: {}", avatar_path));
@@ -170,30 +217,11 @@ impl FileManager {
     }
 
     pub fn delete_avatar_file(&self, avatar_path: &str) -> Result<(), String> {
-        // Validate input to prevent path traversal attacks
         if avatar_path.is_empty() {
             return Err("Avatar path is empty".to_string());
         }
 
-        if avatar_path.contains("..") {
-            return Err("Invalid avatar path: path traversal attempt detected".to_string());
-        }
-
-        let file_path = self.media_dir.join(avatar_path);
-
-        // Additional security: verify the resolved path is still within media_dir
-        let canonical_media = self
-            .media_dir
-            .canonicalize()
-            .map_err(|e| format!("Failed to canonicalize media directory: {}", e))?;
-
-        if let Ok(canonical_file) = file_path.canonicalize() {
-            if !canonical_file.starts_with(&canonical_media) {
-                return Err(
-                    "Security error: Attempted to delete file outside media directory".to_string(),
-                );
-            }
-        }
+        let file_path = self.resolve_media_path(avatar_path)?.into_path_buf();
 
         // Check if file exists before attempting deletion
         if !file_path.exists() {
@@ -210,6 +238,8 @@ impl FileManager {
         match fs::remove_file(&file_path) {
             Ok(_) => Ok(()),
             Err(e) => {
+                // Retried on the next startup in case it is only held open
+                crate::pending_deletions::queue(avatar_path);
                 // Provide detailed error information
                 Err(format!(
                     "Failed to delete avatar file '{}': {} ({})",
@@ -225,12 +255,12 @@ impl FileManager {
         }
     }
 
-    pub fn save_high_rank_avatar_file(
+    /// Fresh (relative path, full path) for an officer avatar, without writing it
+    pub fn new_high_rank_avatar_file_path(
         &self,
         officer_id: i32,
-        file_data: &[u8],
         mime_type: &str,
-    ) -> Result<String, String> {
+    ) -> Result<(String, PathBuf), String> {
         // Generate unique filename for high rank officer
         let extension = match mime_type {
             "image/jpeg" => "jpg",
@@ -248,46 +278,42 @@ impl FileManager {
         );
         let file_path = self.high_ranks_dir.join(&filename);
 
-        // Write file to disk
-        let mut file = fs::File::create(&file_path)
-            .map_err(|e| format!("Failed to create high rank avatar file: {}", e))?;
-
-        file.write_all(file_data)
-            .map_err(|e| format!("Failed to write high rank avatar data: {}", e))?;
-
-        // Return relative path from media directory
+        // Relative path from media directory
         let relative_path = file_path
             .strip_prefix(&self.media_dir)
-            .map_err(|e| format!("Failed to get relative path: {}", e))?;
+            .map_err(|e| format!("Failed to get relative path: {}", e))?
+            .to_string_lossy()
+            .to_string();
+
+        Ok((relative_path, file_path))
+    }
 
-        Ok(relative_path.to_string_lossy().to_string())
+    /// Fresh (relative path, full path) for an officer signature, without writing it
+    pub fn new_signature_file_path(
+        &self,
+        officer_id: i32,
+        extension: &str,
+    ) -> Result<(String, PathBuf), String> {
+        let signatures_dir = self.media_dir.join(SIGNATURES_SUBDIR);
+        fs::create_dir_all(&signatures_dir)
+            .map_err(|e| format!("Failed to create signatures directory: {}", e))?;
+
+        let filename = format!(
+            "signature_{}_{}.{}",
+            officer_id,
+            chrono::Utc::now().timestamp(),
+            extension
+        );
+        let relative_path = format!("{}/{}", SIGNATURES_SUBDIR, filename);
+        Ok((relative_path, signatures_dir.join(filename)))
     }
 
     pub fn delete_high_rank_avatar_file(&self, avatar_path: &str) -> Result<(), String> {
-        // Validate input to prevent path traversal attacks
         if avatar_path.is_empty() {
             return Err("Avatar path is empty".to_string());
         }
 
-        if avatar_path.contains("..") {
-            return Err("Invalid avatar path: path traversal attempt detected".to_string());
-        }
-
-        let file_path = self.media_dir.join(avatar_path);
-
-        // Additional security: verify the resolved path is still within media_dir
-        let canonical_media = self
-            .media_dir
-            .canonicalize()
-            .map_err(|e| format!("Failed to canonicalize media directory: {}", e))?;
-
-        if let Ok(canonical_file) = file_path.canonicalize() {
-            if !canonical_file.starts_with(&canonical_media) {
-                return Err(
-                    "Security error: Attempted to delete file outside media directory".to_string(),
-                );
-            }
-        }
+        let file_path = self.resolve_media_path(avatar_path)?.into_path_buf();
 
         // Check if file exists before attempting deletion
         if !file_path.exists() {
@@ -304,6 +330,8 @@ impl FileManager {
         match fs::remove_file(&file_path) {
             Ok(_) => Ok(()),
             Err(e) => {
+                // Retried on the next startup in case it is only held open
+                crate::pending_deletions::queue(avatar_path);
                 // Provide detailed error information
                 Err(format!(
                     "Failed to delete high rank avatar file '{}': {} ({})",
@@ -319,47 +347,20 @@ impl FileManager {
         }
     }
 
-    pub fn cleanup_orphaned_files(&self, valid_paths: &[String]) -> Result<u32, String> {
-        let mut deleted_count = 0;
-
-        let entries = fs::read_dir(&self.avatars_dir)
-            .map_err(|e| format!("Failed to read avatars directory: {}", e))?;
-
-        for entry in entries {
-            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
-            let path = entry.path();
-
-            if path.is_file() {
-                let relative_path = path
-                    .strip_prefix(&self.media_dir)
-                    .map_err(|e| format!("Failed to get relative path: {}", e))?;
-
-                let path_str = relative_path.to_string_lossy().to_string();
-
-                if !valid_paths.contains(&path_str) {
-                    fs::remove_file(&path)
-                        .map_err(|e| format!("Failed to delete orphaned file: {}", e))?;
-                    deleted_count += 1;
-                }
-            }
-        }
-
-        Ok(deleted_count)
+    /// Delete files in one media subdirectory that no valid path refers to
+    pub fn cleanup_orphaned_files(
+        &self,
+        subdir: &str,
+        valid_paths: &[String],
+    ) -> Result<DirectoryCleanup, String> {
+        cleanup_orphaned_files_in(&self.media_dir, subdir, valid_paths)
     }
 
     /// Check if media directory exists and has content (without creating directories)
     pub fn check_media_exists_and_valid_no_create() -> Result<bool, String> {
-        // Get app data directory
-        let app_data = match app_data_dir(&Config::default()) {
-            Some(dir) => dir,
-            None => {
-                return Err("Failed to get app data directory".to_string());
-            }
-        };
-
-        let media_dir = app_data.join("pqs-rtn-hybrid-storage").join("media");
-        let avatars_dir = media_dir.join("avatars");
-        let high_ranks_dir = media_dir.join("high_ranks");
+        let media_dir = crate::storage_paths::get_media_dir()?;
+        let avatars_dir = media_dir.join(AVATARS_SUBDIR);
+        let high_ranks_dir = media_dir.join(HIGH_RANKS_SUBDIR);
 
         // Check if media directory exists
         if !media_dir.exists() {
@@ -387,3 +388,54 @@ impl FileManager {
         }
     }
 }
+
+/// Delete files directly inside `media_dir/subdir` that are not in
+/// `valid_paths` (media-relative, either separator style)
+pub fn cleanup_orphaned_files_in(
+    media_dir: &Path,
+    subdir: &str,
+    valid_paths: &[String],
+) -> Result<DirectoryCleanup, String> {
+    let mut report = DirectoryCleanup {
+        directory: subdir.to_string(),
+        scanned: 0,
+        deleted: 0,
+        freed_bytes: 0,
+    };
+
+    let dir = media_dir.join(subdir);
+    if !dir.exists() {
+        return Ok(report);
+    }
+
+    let valid: std::collections::HashSet<String> = valid_paths
+        .iter()
+        .map(|p| normalize_media_path(p))
+        .collect();
+
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("Failed to read {} directory: {}", subdir, e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        report.scanned += 1;
+
+        let relative_path = path
+            .strip_prefix(media_dir)
+            .map_err(|e| format!("Failed to get relative path: {}", e))?;
+        if valid.contains(&normalize_media_path(&relative_path.to_string_lossy())) {
+            continue;
+        }
+
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete orphaned file: {}", e))?;
+        report.deleted += 1;
+        report.freed_bytes += size;
+    }
+
+    Ok(report)
+}
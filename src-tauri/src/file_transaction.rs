@@ -0,0 +1,215 @@
+//! Keep a file write and the database row that points at it in step
+//!
+//! `with_file_and_db` runs a closure with a staging area and an open
+//! transaction. Files written through `StagedFiles` land in a private staging
+//! directory; only after the transaction commits are they moved to their
+//! final paths. If the closure or the commit fails, the staged files are
+//! discarded and nothing outside the staging area has changed. The staging
+//! area sits inside the media directory so promotion is a same-volume rename.
+
+use rusqlite::{Connection, Transaction};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::disk_space;
+use crate::logger;
+use crate::long_path;
+use crate::storage_paths;
+
+/// Staging area inside the media directory; media scans skip it
+pub const STAGING_DIR_NAME: &str = ".staging";
+
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// File writes held back until the database transaction commits
+pub struct StagedFiles {
+    staging_dir: PathBuf,
+    /// (staged path, final path)
+    entries: Vec<(PathBuf, PathBuf)>,
+}
+
+impl StagedFiles {
+    fn new(staging_root: &Path) -> Result<Self, String> {
+        let staging_dir = staging_root.join(format!(
+            "{}_{}_{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_millis(),
+            STAGING_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&staging_dir)
+            .map_err(|e| format!("Failed to create staging directory: {}", e))?;
+        Ok(StagedFiles {
+            staging_dir,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Stage `data` to appear at `final_path` once the transaction commits
+    pub fn write(&mut self, final_path: &Path, data: &[u8]) -> Result<(), String> {
+        disk_space::ensure_free_space(&self.staging_dir, data.len() as u64)?;
+
+        let staged_path = self
+            .staging_dir
+            .join(format!("{}.staged", self.entries.len()));
+        fs::write(&staged_path, data).map_err(|e| format!("Failed to stage file: {}", e))?;
+        self.entries.push((staged_path, final_path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Move staged files into place; a file whose move fails is reported,
+    /// the rest are still promoted
+    fn promote(&mut self) -> Result<(), String> {
+        let mut failures = Vec::new();
+        for (staged, target) in self.entries.drain(..) {
+            if let Err(e) = move_file(&staged, &target) {
+                logger::error(format!(
+                    "Failed to promote staged file to {}: {}",
+                    target.display(),
+                    e
+                ));
+                failures.push(format!("{}: {}", target.display(), e));
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Database updated but files could not be moved into place: {}",
+                failures.join("; ")
+            ))
+        }
+    }
+}
+
+impl Drop for StagedFiles {
+    fn drop(&mut self) {
+        // Unpromoted files (rollback) go with the directory
+        let _ = fs::remove_dir_all(&self.staging_dir);
+    }
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    let (from, to) = (long_path::for_io(from), long_path::for_io(to));
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    if fs::rename(&from, &to).is_ok() {
+        return Ok(());
+    }
+    // Different volume: fall back to copy + delete
+    fs::copy(&from, &to).map_err(|e| format!("Failed to copy file: {}", e))?;
+    let _ = fs::remove_file(&from);
+    Ok(())
+}
+
+/// Run `f` with staged files and a transaction on `conn`; files are promoted
+/// after the commit, and discarded with the transaction on any error
+pub fn with_file_and_db_in<T, F>(
+    staging_root: &Path,
+    conn: &mut Connection,
+    f: F,
+) -> Result<T, String>
+where
+    F: FnOnce(&mut StagedFiles, &Transaction) -> Result<T, String>,
+{
+    let mut files = StagedFiles::new(staging_root)?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    // On error the transaction is dropped (rolled back) and `files` cleaned up
+    let value = f(&mut files, &tx)?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+    files.promote()?;
+    Ok(value)
+}
+
+/// `with_file_and_db_in` with the staging area in the media directory
+pub fn with_file_and_db<T, F>(conn: &mut Connection, f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut StagedFiles, &Transaction) -> Result<T, String>,
+{
+    let staging_root = storage_paths::get_media_dir()?.join(STAGING_DIR_NAME);
+    with_file_and_db_in(&staging_root, conn, f)
+}
+
+/// Remove staging directories left behind by a crash, in the media
+/// directory and in the content data directory (question images)
+pub fn cleanup_staging_area() {
+    let roots = [
+        storage_paths::get_media_dir(),
+        crate::content_database::get_portable_data_dir(),
+    ];
+    for root in roots.into_iter().flatten() {
+        let staging_root = root.join(STAGING_DIR_NAME);
+        if staging_root.exists() {
+            if let Err(e) = fs::remove_dir_all(&staging_root) {
+                logger::warn(format!("Failed to clean staging area: {}", e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, Connection) {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let conn = Connection::open_in_memory().expect("In-memory db should open");
+        conn.execute_batch("CREATE TABLE files (path TEXT NOT NULL)")
+            .expect("table should be created");
+        (dir, conn)
+    }
+
+    fn row_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_files_promoted_after_commit() {
+        let (dir, mut conn) = setup();
+        let target = dir.path().join("avatars/a.png");
+
+        with_file_and_db_in(&dir.path().join(".staging"), &mut conn, |files, tx| {
+            files.write(&target, b"image")?;
+            // Nothing visible before the commit
+            assert!(!target.exists());
+            tx.execute("INSERT INTO files (path) VALUES (?)", params!["a.png"])
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .expect("operation should succeed");
+
+        assert_eq!(fs::read(&target).unwrap(), b"image");
+        assert_eq!(row_count(&conn), 1);
+        assert_eq!(
+            fs::read_dir(dir.path().join(".staging")).unwrap().count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_error_discards_files_and_rows() {
+        let (dir, mut conn) = setup();
+        let target = dir.path().join("avatars/a.png");
+
+        let result: Result<(), String> =
+            with_file_and_db_in(&dir.path().join(".staging"), &mut conn, |files, tx| {
+                files.write(&target, b"image")?;
+                tx.execute("INSERT INTO files (path) VALUES (?)", params!["a.png"])
+                    .map_err(|e| e.to_string())?;
+                Err("validation failed".to_string())
+            });
+
+        assert!(result.is_err());
+        assert!(!target.exists());
+        assert_eq!(row_count(&conn), 0);
+    }
+}
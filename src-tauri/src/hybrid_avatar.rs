@@ -1,12 +1,37 @@
+use crate::avatar_approval;
+use crate::avatar_policy;
 use crate::database::get_connection_safe;
-use crate::file_manager::FileManager;
+use crate::file_manager::{FileManager, AVATARS_SUBDIR};
+use crate::file_transaction;
 use crate::logger;
-use rusqlite::params;
+use crate::media_hooks;
+use crate::media_maintenance::OWNER_USER;
+use crate::permissions::Caller;
+use crate::settings::AvatarPolicy;
+use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::sync::Arc; // Phase 1.4: Arc for shared FileManager
 
+/// (filename, full path, MIME type, validation report) of an avatar written to disk
+type StoredAvatarFile = (
+    String,
+    PathBuf,
+    String,
+    avatar_policy::AvatarValidationReport,
+);
+
+/// (path, updated at, MIME type, size, image metadata) columns of a user's avatar
+type AvatarColumns = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<i32>,
+    Option<avatar_policy::ImageMetadata>,
+);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HybridAvatarInfo {
     pub user_id: i32,
@@ -15,6 +40,47 @@ pub struct HybridAvatarInfo {
     pub avatar_mime: Option<String>,
     pub avatar_size: Option<i32>,
     pub file_exists: bool,
+    /// Format and dimensions recorded at save time, for layout before loading
+    pub metadata: Option<avatar_policy::ImageMetadata>,
+    /// Set by the save commands only
+    pub validation: Option<avatar_policy::AvatarValidationReport>,
+}
+
+/// Neutral head-and-shoulders silhouette shown when an avatar file is gone
+const PLACEHOLDER_AVATAR_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 128 128"><rect width="128" height="128" fill="#e5e7eb"/><circle cx="64" cy="48" r="24" fill="#9ca3af"/><path d="M20 120c0-26 20-42 44-42s44 16 44 42z" fill="#9ca3af"/></svg>"##;
+
+/// Avatar image with an explicit flag for the placeholder fallback
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AvatarImage {
+    pub avatar_path: String,
+    pub data_url: String,
+    /// True when the file is missing and `data_url` is the placeholder
+    pub missing: bool,
+    /// True when the file is missing but users still point at it; reads
+    /// never change the database, media reconciliation clears the reference
+    pub dangling_reference: bool,
+}
+
+pub fn placeholder_avatar_data_url() -> String {
+    use base64::{engine::general_purpose, Engine as _};
+    format!(
+        "data:image/svg+xml;base64,{}",
+        general_purpose::STANDARD.encode(PLACEHOLDER_AVATAR_SVG)
+    )
+}
+
+/// Number of users whose avatar points at `avatar_path`
+pub fn count_avatar_references_with_conn(
+    conn: &Connection,
+    avatar_path: &str,
+) -> Result<usize, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM users WHERE avatar_path = ?",
+        params![avatar_path],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count as usize)
+    .map_err(|e| format!("Failed to count avatar references: {}", e))
 }
 
 // Phase 1.4: Use Arc<FileManager> for zero-cost sharing
@@ -44,8 +110,20 @@ impl HybridAvatarManager {
         user_id: i32,
         file_data: &[u8],
         mime_type: &str,
+        uploaded_by: Option<Caller>,
     ) -> Result<HybridAvatarInfo, String> {
-        let conn =
+        // Reject or scale before anything is touched on disk
+        let prepared = media_hooks::prepare_avatar(
+            &avatar_policy::current_policy(),
+            OWNER_USER,
+            user_id,
+            file_data,
+            mime_type,
+        )?;
+        let file_data = prepared.data.as_slice();
+        let mime_type = prepared.mime_type.as_str();
+
+        let mut conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
         // First, verify user exists
@@ -61,24 +139,28 @@ impl HybridAvatarManager {
             return Err(format!("User with ID {} does not exist", user_id));
         }
 
-        // Delete old avatar file if exists
-        if let Ok(Some(path)) = self.get_user_avatar_path(user_id) {
-            let _ = self.file_manager.delete_avatar_file(&path);
-        }
-
-        // Save new avatar file
-        let avatar_path = self
-            .file_manager
-            .save_avatar_file(user_id, file_data, mime_type)?;
-
-        // Update user record with new avatar path
+        let old_path = self.get_user_avatar_path(user_id).ok().flatten();
+        let (avatar_path, file_path) =
+            self.file_manager.new_avatar_file_path(user_id, mime_type)?;
         let updated_at = chrono::Utc::now().to_rfc3339();
         let file_size = file_data.len() as i32;
+        let metadata = prepared.report.stored_metadata();
+
+        // The new file only appears once the user record points at it
+        file_transaction::with_file_and_db(&mut conn, |files, tx| {
+            files.write(&file_path, file_data)?;
+            tx.execute(
+                "UPDATE users SET row_version = row_version + 1, avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ?, avatar_format = ?, avatar_width = ?, avatar_height = ? WHERE id = ?",
+                params![avatar_path, updated_at, mime_type, file_size, metadata.format, metadata.width, metadata.height, user_id]
+            ).map_err(|e| format!("Failed to update user avatar: {}", e))?;
+            avatar_approval::mark_uploaded_with_conn(tx, OWNER_USER, user_id, uploaded_by)
+        })?;
 
-        conn.execute(
-            "UPDATE users SET avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ? WHERE id = ?",
-            params![avatar_path, updated_at, mime_type, file_size, user_id]
-        ).map_err(|e| format!("Failed to update user avatar: {}", e))?;
+        // Old file goes only after the switch succeeded
+        if let Some(old_path) = old_path.filter(|old| *old != avatar_path) {
+            let _ = self.file_manager.delete_avatar_file(&old_path);
+        }
+        media_hooks::notify_saved(OWNER_USER, user_id, &avatar_path);
 
         // Note: avatars table has been removed - no need to delete from it
         // File-based storage is now the only method
@@ -90,6 +172,8 @@ impl HybridAvatarManager {
             avatar_mime: Some(mime_type.to_string()),
             avatar_size: Some(file_size),
             file_exists: true,
+            metadata: Some(metadata),
+            validation: Some(prepared.report.clone()),
         })
     }
 
@@ -101,7 +185,16 @@ impl HybridAvatarManager {
         mut reader: impl Read,
         mime_type: &str,
         expected_size: Option<usize>,
+        uploaded_by: Option<Caller>,
     ) -> Result<HybridAvatarInfo, String> {
+        // ✅ Format and declared size are checked before reading any data
+        let policy = avatar_policy::current_policy();
+        avatar_policy::check_mime(&policy, mime_type)?;
+        if let Some(size) = expected_size {
+            avatar_policy::check_upload(&policy, size as u64, mime_type)?;
+        }
+        let mime_type = avatar_policy::normalize_mime(mime_type);
+
         // ✅ Verify user exists first
         let conn =
             get_connection_safe().map_err(|e| format!("Database connection error: {}", e))?;
@@ -123,29 +216,26 @@ impl HybridAvatarManager {
             let _ = self.file_manager.delete_avatar_file(&old_path);
         }
 
-        // ✅ Validate mime type and get extension
-        let extension = match mime_type {
-            "image/jpeg" | "image/jpg" => "jpg",
-            "image/png" => "png",
-            "image/webp" => "webp",
-            "image/gif" => "gif",
-            _ => return Err(format!("Unsupported image type: {}", mime_type)),
-        };
-
         // ✅ Create filename and file path
+        let extension = avatar_policy::extension_for_mime(&mime_type);
         let filename = format!("user_{}.{}", user_id, extension);
         let file_path = self
             .file_manager
             .get_avatar_file_path(&filename)
             .map_err(|e| format!("Failed to create file path: {}", e))?;
 
+        // ✅ Check free space up front when the size is known
+        if let Some(size) = expected_size {
+            crate::disk_space::ensure_free_space(&file_path, size as u64)?;
+        }
+
         // ✅ Open file for writing
         let mut file =
             File::create(&file_path).map_err(|e| format!("Failed to create file: {}", e))?;
 
         // ✅ Stream copy with 8KB buffer chunks
         const BUFFER_SIZE: usize = 8 * 1024; // 8KB chunks
-        const MAX_FILE_SIZE: usize = 10 * 1024 * 1024; // 10MB limit
+        let max_file_size = policy.max_upload_bytes as usize;
 
         let mut buffer = vec![0u8; BUFFER_SIZE];
         let mut total_written = 0usize;
@@ -171,13 +261,13 @@ impl HybridAvatarManager {
             total_written += bytes_read;
 
             // ✅ Safety check for size limits
-            if total_written > MAX_FILE_SIZE {
+            if total_written > max_file_size {
                 // Clean up partial file
                 drop(file);
                 let _ = std::fs::remove_file(&file_path);
                 return Err(format!(
-                    "File too large (max {}MB)",
-                    MAX_FILE_SIZE / 1024 / 1024
+                    "File too large (max {} bytes)",
+                    policy.max_upload_bytes
                 ));
             }
 
@@ -202,18 +292,26 @@ impl HybridAvatarManager {
             return Err("File too small to be a valid image".to_string());
         }
 
+        // ✅ Dimension limits need the decoded image; oversized ones are replaced
+        let (filename, file_path, mime_type, report) =
+            self.apply_dimension_policy(&policy, user_id, filename, file_path, mime_type)?;
+        let total_written = report.stored_size as usize;
+
         // ✅ Update database metadata
         let updated_at = chrono::Utc::now().to_rfc3339();
         let file_size = total_written as i32;
+        let metadata = report.stored_metadata();
 
         conn.execute(
-            "UPDATE users SET avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ? WHERE id = ?",
-            params![filename, updated_at, mime_type, file_size, user_id]
+            "UPDATE users SET row_version = row_version + 1, avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ?, avatar_format = ?, avatar_width = ?, avatar_height = ? WHERE id = ?",
+            params![filename, updated_at, mime_type, file_size, metadata.format, metadata.width, metadata.height, user_id]
         ).map_err(|e| {
             // Clean up file on database error
             let _ = std::fs::remove_file(&file_path);
             format!("Database update error: {}", e)
         })?;
+        avatar_approval::mark_uploaded_with_conn(&conn, OWNER_USER, user_id, uploaded_by)?;
+        media_hooks::notify_saved(OWNER_USER, user_id, &filename);
 
         logger::info(format!(
             "Avatar saved successfully for user {} ({} bytes)",
@@ -224,12 +322,58 @@ impl HybridAvatarManager {
             user_id,
             avatar_path: Some(filename),
             avatar_updated_at: Some(updated_at),
-            avatar_mime: Some(mime_type.to_string()),
+            avatar_mime: Some(mime_type),
             avatar_size: Some(file_size),
             file_exists: true,
+            metadata: Some(metadata),
+            validation: Some(report),
         })
     }
 
+    /// Re-check a streamed file against the policy and, when it had to be
+    /// scaled or was another format than declared, swap it for the stored
+    /// version (the extension may change)
+    fn apply_dimension_policy(
+        &self,
+        policy: &AvatarPolicy,
+        user_id: i32,
+        filename: String,
+        file_path: PathBuf,
+        mime_type: String,
+    ) -> Result<StoredAvatarFile, String> {
+        let data = std::fs::read(&file_path).map_err(|e| format!("Read error: {}", e))?;
+        let prepared =
+            match media_hooks::prepare_avatar(policy, OWNER_USER, user_id, &data, &mime_type) {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    let _ = std::fs::remove_file(&file_path);
+                    return Err(e);
+                }
+            };
+        // Rewritten when scaled or changed by a hook, or when the bytes turned
+        // out to be another format
+        if !prepared.report.downscaled
+            && !prepared.report.reencoded
+            && prepared.mime_type == mime_type
+        {
+            return Ok((filename, file_path, prepared.mime_type, prepared.report));
+        }
+
+        let _ = std::fs::remove_file(&file_path);
+        let filename = format!(
+            "user_{}.{}",
+            user_id,
+            avatar_policy::extension_for_mime(&prepared.mime_type)
+        );
+        let file_path = self
+            .file_manager
+            .get_avatar_file_path(&filename)
+            .map_err(|e| format!("Failed to create file path: {}", e))?;
+        std::fs::write(&file_path, &prepared.data).map_err(|e| format!("Write error: {}", e))?;
+
+        Ok((filename, file_path, prepared.mime_type, prepared.report))
+    }
+
     pub fn get_user_avatar_path(&self, user_id: i32) -> Result<Option<String>, String> {
         let conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
@@ -249,10 +393,11 @@ impl HybridAvatarManager {
         let conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-        let (avatar_path, avatar_updated_at, avatar_mime, avatar_size): (Option<String>, Option<String>, Option<String>, Option<i32>) =            conn.query_row(
-                "SELECT avatar_path, avatar_updated_at, avatar_mime, avatar_size FROM users WHERE id = ?",
+        let (avatar_path, avatar_updated_at, avatar_mime, avatar_size, metadata): AvatarColumns =
+            conn.query_row(
+                "SELECT avatar_path, avatar_updated_at, avatar_mime, avatar_size, avatar_format, avatar_width, avatar_height FROM users WHERE id = ?",
                 params![user_id],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, avatar_policy::ImageMetadata::from_columns(row.get(4)?, row.get(5)?, row.get(6)?)))
             ).map_err(|e| format!("Failed to get user avatar info: {}", e))?;
 
         let file_exists = if let Some(path) = &avatar_path {
@@ -268,6 +413,8 @@ impl HybridAvatarManager {
             avatar_mime,
             avatar_size,
             file_exists,
+            metadata,
+            validation: None,
         })
     }
 
@@ -352,11 +499,11 @@ impl HybridAvatarManager {
 
         // Update user record - clear all avatar fields
         match conn.execute(
-            "UPDATE users SET avatar_path = NULL, avatar_updated_at = NULL, avatar_mime = NULL, avatar_size = NULL WHERE id = ?",
+            "UPDATE users SET row_version = row_version + 1, avatar_path = NULL, avatar_updated_at = NULL, avatar_mime = NULL, avatar_size = NULL, avatar_format = NULL, avatar_width = NULL, avatar_height = NULL, avatar_status = NULL WHERE id = ?",
             params![user_id]
         ) {
             Ok(updated) if updated > 0 => {
-                // Successfully updated
+                media_hooks::notify_deleted(OWNER_USER, user_id);
                 Ok(true)
             },
             Ok(_) => {
@@ -374,11 +521,6 @@ impl HybridAvatarManager {
             return Err("Avatar path is empty".to_string());
         }
 
-        // Security: prevent path traversal
-        if avatar_path.contains("..") {
-            return Err("Invalid avatar path".to_string());
-        }
-
         let file_path = match self.file_manager.get_avatar_file_path(avatar_path) {
             Ok(path) => path,
             Err(e) => {
@@ -428,6 +570,54 @@ impl HybridAvatarManager {
         Ok(format!("data:{};base64,{}", mime_type, base64_data))
     }
 
+    /// Like get_avatar_base64, but a missing file yields the placeholder image
+    /// instead of an error, with the stale DB reference reported
+    pub fn get_avatar_image(&self, avatar_path: &str) -> Result<AvatarImage, String> {
+        // Invalid paths are left to get_avatar_base64 to report
+        let file_missing = !avatar_path.is_empty()
+            && self
+                .file_manager
+                .resolve_media_path(avatar_path)
+                .map(|path| !path.full_path().exists())
+                .unwrap_or(false);
+
+        if !file_missing {
+            return Ok(AvatarImage {
+                avatar_path: avatar_path.to_string(),
+                data_url: self.get_avatar_base64(avatar_path)?,
+                missing: false,
+                dangling_reference: false,
+            });
+        }
+
+        logger::warn(format!(
+            "Avatar file missing, using placeholder: {}",
+            avatar_path
+        ));
+
+        let conn =
+            get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+        let dangling_reference = count_avatar_references_with_conn(&conn, avatar_path)? > 0;
+
+        Ok(AvatarImage {
+            avatar_path: avatar_path.to_string(),
+            data_url: placeholder_avatar_data_url(),
+            missing: true,
+            dangling_reference,
+        })
+    }
+
+    /// Resolve the user's avatar path internally and return the image as a data URL
+    /// Returns None when the user has no avatar set
+    pub fn get_avatar_base64_by_user_id(&self, user_id: i32) -> Result<Option<String>, String> {
+        match self.get_user_avatar_path(user_id)? {
+            Some(path) if !path.is_empty() => self
+                .get_avatar_image(&path)
+                .map(|image| Some(image.data_url)),
+            _ => Ok(None),
+        }
+    }
+
     pub fn cleanup_orphaned_files(&self) -> Result<u32, String> {
         let conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
@@ -446,7 +636,9 @@ impl HybridAvatarManager {
             valid_paths.map_err(|e| format!("Failed to collect avatar paths: {}", e))?;
 
         // Clean up orphaned files
-        self.file_manager.cleanup_orphaned_files(&valid_paths)
+        self.file_manager
+            .cleanup_orphaned_files(AVATARS_SUBDIR, &valid_paths)
+            .map(|report| report.deleted)
     }
 
     pub fn migrate_blob_to_file(&self, user_id: i32) -> Result<bool, String> {
@@ -493,3 +685,68 @@ impl HybridAvatarManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_placeholder_is_svg_data_url() {
+        assert!(placeholder_avatar_data_url().starts_with("data:image/svg+xml;base64,"));
+    }
+
+    #[test]
+    fn test_dangling_avatar_reference_is_counted_not_cleared() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, avatar_path, avatar_mime, avatar_size) VALUES ('u', 'u@test.com', 'h', 'U', 'avatars/gone.png', 'image/png', 10)",
+            [],
+        )
+        .expect("user insert should succeed");
+
+        let references = count_avatar_references_with_conn(&conn, "avatars/gone.png")
+            .expect("count should succeed");
+
+        assert_eq!(references, 1);
+        let path: Option<String> = conn
+            .query_row("SELECT avatar_path FROM users", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(path.as_deref(), Some("avatars/gone.png"));
+    }
+    #[test]
+    fn test_avatar_base64_by_user_id_reads_the_saved_photo() {
+        let env = crate::test_support::TestEnvironment::in_memory();
+        let with_avatar = env.create_user("has_avatar").id.unwrap();
+        let without_avatar = env.create_user("no_avatar").id.unwrap();
+
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        let manager = HybridAvatarManager::new().expect("avatar manager should start");
+        let saved = manager
+            .save_avatar(with_avatar, &png, "image/png", None)
+            .expect("avatar should save");
+
+        let data_url = manager
+            .get_avatar_base64_by_user_id(with_avatar)
+            .expect("lookup should succeed")
+            .expect("user should have an avatar");
+        assert_eq!(
+            data_url,
+            manager
+                .get_avatar_base64(&saved.avatar_path.unwrap())
+                .unwrap()
+        );
+        assert_eq!(
+            manager
+                .get_avatar_base64_by_user_id(without_avatar)
+                .unwrap(),
+            None
+        );
+    }
+}
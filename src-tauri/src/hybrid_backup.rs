@@ -1,12 +1,18 @@
+use crate::backup_encryption::{self, BackupEncryption};
+use crate::backup_results::{BackupCreated, BackupDeleted, BackupKind, BackupRestored};
+use crate::backup_volumes::{self, VolumeReader};
+use crate::disk_space;
 use crate::logger;
+use crate::progress::ProgressReporter;
+use crate::restore_snapshot;
+use crate::temp_space::{self, TempSpace};
+use crate::validation;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{BufReader, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::api::path::app_data_dir;
-use tauri::Config;
 use walkdir::WalkDir;
 use zip::write::FileOptions;
 use zip::ZipWriter;
@@ -21,17 +27,61 @@ pub struct BackupManifest {
     pub total_files: u64,
     pub backup_type: String,
     pub checksum: String,
+    /// Main database schema version (PRAGMA user_version); absent in older backups
+    #[serde(default)]
+    pub schema_version: Option<i32>,
+    /// Admin annotation, e.g. "before annual officer rotation"
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Set on encrypted backups, whose data is sealed in `payload.enc`
+    #[serde(default)]
+    pub encryption: Option<BackupEncryption>,
+}
+
+const MAX_NOTE_LENGTH: usize = 500;
+
+/// Read buffer for files streamed into a backup zip; media can run to
+/// gigabytes, so no file is ever held in memory whole
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Stream `file` into the zip entry just started; returns the bytes copied
+fn copy_into_zip<W: Write + Seek>(file: fs::File, zip: &mut ZipWriter<W>) -> std::io::Result<u64> {
+    let mut reader = BufReader::with_capacity(COPY_CHUNK_BYTES, file);
+    std::io::copy(&mut reader, zip)
 }
 
 /// Hybrid backup that includes both database and media files in a compressed zip
-pub fn create_hybrid_backup() -> Result<String, String> {
+/// Progress counts files written; cancelling removes the partial zip
+/// With `max_volume_size` the finished zip is split into volumes of at most
+/// that many bytes (see backup_volumes)
+pub fn create_hybrid_backup_with_progress(
+    progress: &ProgressReporter,
+    max_volume_size: Option<u64>,
+) -> Result<BackupCreated, String> {
+    create_hybrid_backup_in(&get_backup_directory()?, progress, max_volume_size)
+}
+
+/// `create_hybrid_backup_with_progress` writing into `backup_dir`
+pub fn create_hybrid_backup_in(
+    backup_dir: &Path,
+    progress: &ProgressReporter,
+    max_volume_size: Option<u64>,
+) -> Result<BackupCreated, String> {
+    if let Some(max_volume_size) = max_volume_size {
+        backup_volumes::validate_volume_size(max_volume_size)?;
+    }
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
 
     let backup_filename = format!("hybrid_backup_{}.zip", timestamp);
-    let backup_path = get_backup_directory()?.join(&backup_filename);
+    let backup_path = backup_dir.join(&backup_filename);
+
+    // Uncompressed size is an upper bound for the zip
+    let estimated_size = disk_space::file_size(&get_database_path()?)
+        + disk_space::directory_size(&get_media_directory()?);
+    disk_space::ensure_free_space(&backup_path, estimated_size)?;
 
     logger::info(format!(
         "Starting hybrid backup creation: {}",
@@ -50,6 +100,7 @@ pub fn create_hybrid_backup() -> Result<String, String> {
     let mut total_files = 0u64;
     let mut media_size = 0u64;
     let mut database_size = 0u64;
+    let mut warnings = Vec::new();
 
     // 1. Add database file
     let db_path = get_database_path()?;
@@ -64,30 +115,43 @@ pub fn create_hybrid_backup() -> Result<String, String> {
         zip.start_file(&db_filename, options)
             .map_err(|e| format!("Failed to start database file in zip: {}", e))?;
 
-        let mut db_file =
+        let db_file =
             fs::File::open(&db_path).map_err(|e| format!("Failed to open database file: {}", e))?;
 
-        let mut buffer = Vec::new();
-        db_file
-            .read_to_end(&mut buffer)
-            .map_err(|e| format!("Failed to read database file: {}", e))?;
-
-        database_size = buffer.len() as u64;
-        zip.write_all(&buffer)
+        database_size = copy_into_zip(db_file, &mut zip)
             .map_err(|e| format!("Failed to write database to zip: {}", e))?;
 
         total_files += 1;
+        progress.report_bytes(
+            Some("database"),
+            total_files,
+            None,
+            database_size,
+            Some(estimated_size),
+        );
         logger::debug(format!("Database file added: {} bytes", database_size));
     } else {
         logger::warn("Database file not found, skipping database backup");
+        warnings.push("Database file not found; the backup has no database".to_string());
     }
 
     // 2. Add media directory
     let media_dir = get_media_directory()?;
     if media_dir.exists() {
         logger::debug("Adding media directory to backup");
+        let total_entries = 1 + WalkDir::new(&media_dir)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.file_type().is_file())
+            .count() as u64;
 
         for entry in WalkDir::new(&media_dir).into_iter() {
+            if let Err(e) = progress.check_cancelled() {
+                drop(zip);
+                let _ = fs::remove_file(&backup_path);
+                logger::info(format!("Hybrid backup cancelled: {}", backup_filename));
+                return Err(e);
+            }
             let entry =
                 entry.map_err(|e| format!("Failed to read media directory entry: {}", e))?;
 
@@ -102,18 +166,20 @@ pub fn create_hybrid_backup() -> Result<String, String> {
                 zip.start_file(&zip_path, options)
                     .map_err(|e| format!("Failed to start media file in zip: {}", e))?;
 
-                let mut file = fs::File::open(file_path)
+                let file = fs::File::open(file_path)
                     .map_err(|e| format!("Failed to open media file: {}", e))?;
 
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)
-                    .map_err(|e| format!("Failed to read media file: {}", e))?;
-
-                media_size += buffer.len() as u64;
-                zip.write_all(&buffer)
+                media_size += copy_into_zip(file, &mut zip)
                     .map_err(|e| format!("Failed to write media file to zip: {}", e))?;
 
                 total_files += 1;
+                progress.report_bytes(
+                    Some("media"),
+                    total_files,
+                    Some(total_entries),
+                    database_size + media_size,
+                    Some(estimated_size),
+                );
             }
         }
         logger::debug(format!(
@@ -123,6 +189,7 @@ pub fn create_hybrid_backup() -> Result<String, String> {
         ));
     } else {
         logger::warn("Media directory not found, skipping media backup");
+        warnings.push("Media directory not found; the backup has no media files".to_string());
     }
 
     // 3. Create and add manifest
@@ -134,6 +201,9 @@ pub fn create_hybrid_backup() -> Result<String, String> {
         total_files,
         backup_type: "hybrid".to_string(),
         checksum: "".to_string(), // Will be calculated after zip is complete
+        schema_version: Some(crate::database::SCHEMA_VERSION),
+        note: None,
+        encryption: None,
     };
 
     let manifest_json = serde_json::to_string_pretty(&manifest)
@@ -179,13 +249,30 @@ pub fn create_hybrid_backup() -> Result<String, String> {
         "Total files: {}, Database: {} bytes, Media: {} bytes",
         total_files, database_size, media_size
     ));
+    let volumes = match max_volume_size {
+        Some(max_volume_size) => backup_volumes::split_into_volumes(&backup_path, max_volume_size)?,
+        None => vec![backup_path.clone()],
+    };
+    progress.finish(total_files);
 
-    Ok(format!(
-        "Hybrid backup created: {} (Files: {}, Size: {} bytes)",
-        backup_filename,
-        total_files,
-        database_size + media_size
-    ))
+    let mut created = BackupCreated::for_file(BackupKind::Hybrid, &volumes[0])?;
+    if volumes.len() > 1 {
+        logger::info(format!(
+            "Hybrid backup split into {} volumes",
+            volumes.len()
+        ));
+        created.size_bytes = backup_volumes::set_size(&volumes);
+        created.volumes = volumes
+            .iter()
+            .filter_map(|v| v.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .collect();
+    }
+    created.database_bytes = Some(database_size);
+    created.media_bytes = Some(media_size);
+    created.file_count = Some(total_files);
+    created.warnings = warnings;
+    Ok(created)
 }
 
 /// Discover available backup files in the backup directory
@@ -204,17 +291,16 @@ pub fn discover_available_backups() -> Result<Vec<BackupInfo>, String> {
         let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
         let path = entry.path();
 
-        if path.extension().and_then(|s| s.to_str()) == Some("zip") {
-            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                if filename.starts_with("hybrid_backup_") {
-                    // Try to read manifest
-                    if let Ok(manifest) = read_backup_manifest(&path) {
-                        backups.push(BackupInfo {
-                            filename: filename.to_string(),
-                            path: path.to_string_lossy().to_string(),
-                            manifest,
-                        });
-                    }
+        if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
+            if is_hybrid_backup_filename(filename) {
+                // Try to read manifest; a set with missing volumes is skipped
+                if let Ok(manifest) = read_backup_manifest(&path) {
+                    backups.push(BackupInfo {
+                        filename: filename.to_string(),
+                        path: path.to_string_lossy().to_string(),
+                        volumes: backup_volumes::volume_set(&path).map_or(1, |v| v.len()),
+                        manifest,
+                    });
                 }
             }
         }
@@ -227,7 +313,13 @@ pub fn discover_available_backups() -> Result<Vec<BackupInfo>, String> {
 }
 
 /// Import backup from zip file
-pub fn import_backup(zip_path: &str) -> Result<String, String> {
+/// Progress counts extracted entries; cancelling is possible until the
+/// current database and media are replaced
+pub fn import_backup_with_progress(
+    zip_path: &str,
+    progress: &ProgressReporter,
+) -> Result<BackupRestored, String> {
+    let source = zip_path;
     let zip_path = Path::new(zip_path);
 
     if !zip_path.exists() {
@@ -241,23 +333,25 @@ pub fn import_backup(zip_path: &str) -> Result<String, String> {
 
     // Validate manifest first
     let manifest = read_backup_manifest(zip_path)?;
-
-    // Create temporary directory for extraction
-    let temp_dir = get_backup_directory()?.join("temp_import");
-    if temp_dir.exists() {
-        fs::remove_dir_all(&temp_dir)
-            .map_err(|e| format!("Failed to clean temp directory: {}", e))?;
-    }
-    fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp directory: {}", e))?;
+    backup_encryption::ensure_not_encrypted(&manifest)?;
+
+    // Extracted once to the temp dir, then copied into place
+    let restored_size = manifest.database_size + manifest.media_size;
+    disk_space::ensure_free_space(
+        &temp_space::get_temp_root()?,
+        restored_size.saturating_mul(2),
+    )?;
+    // Removed when this function returns, whether or not the import succeeded
+    let temp_space = TempSpace::create("import")?;
+    let temp_dir = temp_space.path();
 
     // Extract zip
-    let zip_file =
-        fs::File::open(zip_path).map_err(|e| format!("Failed to open zip file: {}", e))?;
-
-    let mut archive =
-        zip::ZipArchive::new(zip_file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+    let mut archive = open_backup_archive(zip_path)?;
 
+    let total_entries = archive.len() as u64;
+    let mut bytes_extracted = 0u64;
     for i in 0..archive.len() {
+        progress.check_cancelled()?;
         let mut file = archive
             .by_index(i)
             .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
@@ -278,10 +372,18 @@ pub fn import_backup(zip_path: &str) -> Result<String, String> {
             let mut outfile = fs::File::create(&outpath)
                 .map_err(|e| format!("Failed to create output file: {}", e))?;
 
-            std::io::copy(&mut file, &mut outfile)
+            bytes_extracted += std::io::copy(&mut file, &mut outfile)
                 .map_err(|e| format!("Failed to extract file: {}", e))?;
         }
+        progress.report_bytes(
+            Some("extract"),
+            i as u64 + 1,
+            Some(total_entries),
+            bytes_extracted,
+            Some(restored_size),
+        );
     }
+    progress.check_cancelled()?;
 
     // Validate extracted files
     let extracted_db = temp_dir.join("database.db");
@@ -296,41 +398,59 @@ pub fn import_backup(zip_path: &str) -> Result<String, String> {
     let current_media = get_media_directory()?;
 
     // Backup current files (if they exist) - simple approach
+    let mut previous_database = None;
     if current_db.exists() {
         let backup_current = current_db.with_extension("db.backup");
         fs::copy(&current_db, &backup_current)
             .map_err(|e| format!("Failed to backup current database: {}", e))?;
+        previous_database = Some(backup_current.to_string_lossy().to_string());
     }
 
-    // Copy new files
-    fs::copy(&extracted_db, &current_db)
-        .map_err(|e| format!("Failed to restore database: {}", e))?;
-
-    if extracted_media.exists() {
-        // Remove current media directory if exists
-        if current_media.exists() {
-            fs::remove_dir_all(&current_media)
-                .map_err(|e| format!("Failed to remove current media directory: {}", e))?;
-        }
-
-        // Copy new media directory
-        copy_dir_recursive(&extracted_media, &current_media)
-            .map_err(|e| format!("Failed to restore media files: {}", e))?;
-    }
+    // Copy new files; any failure, including a restored database that does
+    // not open, puts the current database and media back
+    let replace_media = extracted_media.exists();
+    let mut restored = restore_snapshot::with_restore_snapshot(
+        &current_db,
+        replace_media.then_some(current_media.as_path()),
+        || {
+            fs::copy(&extracted_db, &current_db)
+                .map_err(|e| format!("Failed to restore database: {}", e))?;
+            if replace_media {
+                copy_dir_recursive(&extracted_media, &current_media)
+                    .map_err(|e| format!("Failed to restore media files: {}", e))?;
+            }
 
-    // Clean up temp directory
-    fs::remove_dir_all(&temp_dir).map_err(|e| format!("Failed to clean temp directory: {}", e))?;
+            let conn = rusqlite::Connection::open(&current_db)
+                .map_err(|e| format!("Failed to open restored database: {}", e))?;
+            BackupRestored::from_database(BackupKind::Hybrid, source, &conn)
+        },
+    )?;
 
     logger::info("Backup import completed successfully");
+    progress.finish(total_entries);
 
-    Ok(format!(
-        "Backup imported successfully. Files restored: Database + {} media files",
+    restored.media_files = Some(if replace_media {
         manifest.total_files.saturating_sub(1)
-    ))
+    } else {
+        0
+    });
+    restored.previous_database = previous_database;
+    if !replace_media {
+        restored
+            .warnings
+            .push("The backup has no media files; current media was kept".to_string());
+    }
+    Ok(restored)
+}
+
+/// `hybrid_backup_*.zip`, or the first volume of a split one
+pub fn is_hybrid_backup_filename(filename: &str) -> bool {
+    filename.starts_with("hybrid_backup_")
+        && (filename.ends_with(".zip") || filename.ends_with(".zip.001"))
 }
 
-/// Delete a hybrid backup file
-pub fn delete_hybrid_backup(filename: &str) -> Result<String, String> {
+/// Delete a hybrid backup file; for a split backup every volume goes
+pub fn delete_hybrid_backup(filename: &str) -> Result<BackupDeleted, String> {
     let backup_dir = get_backup_directory()?;
     let backup_path = backup_dir.join(filename);
 
@@ -339,24 +459,123 @@ pub fn delete_hybrid_backup(filename: &str) -> Result<String, String> {
     }
 
     // Validate that it's actually a hybrid backup file
-    if !filename.starts_with("hybrid_backup_") || !filename.ends_with(".zip") {
+    if !is_hybrid_backup_filename(filename) {
         return Err("Invalid hybrid backup filename".to_string());
     }
 
-    fs::remove_file(&backup_path).map_err(|e| format!("Failed to delete backup file: {}", e))?;
+    let mut deleted = BackupDeleted::delete(&backup_path)?;
+    if backup_volumes::is_first_volume(&backup_path) {
+        let base = backup_path.with_extension("");
+        let mut index = 2;
+        while backup_volumes::volume_path(&base, index).is_file() {
+            let volume = BackupDeleted::delete(&backup_volumes::volume_path(&base, index))?;
+            deleted.freed_bytes += volume.freed_bytes;
+            index += 1;
+        }
+    }
 
     logger::info(format!("Hybrid backup deleted: {}", filename));
 
-    Ok(format!("Hybrid backup '{}' deleted successfully", filename))
+    Ok(deleted)
 }
 
-/// Helper function to read backup manifest from zip
-fn read_backup_manifest(zip_path: &Path) -> Result<BackupManifest, String> {
-    let zip_file =
-        fs::File::open(zip_path).map_err(|e| format!("Failed to open zip file: {}", e))?;
+/// Replace the note in a backup's manifest; an empty note removes it
+/// Zip entries cannot be edited in place, so the archive is rewritten with the
+/// other entries copied raw (no recompression) and then swapped in; a volume
+/// set is cut again to its volume size
+pub fn set_backup_note_at(zip_path: &Path, note: Option<&str>) -> Result<BackupManifest, String> {
+    let note = note.map(str::trim).filter(|n| !n.is_empty());
+    if let Some(note) = note {
+        if note.chars().count() > MAX_NOTE_LENGTH {
+            return Err(format!(
+                "Backup note must be at most {} characters",
+                MAX_NOTE_LENGTH
+            ));
+        }
+    }
 
-    let mut archive =
-        zip::ZipArchive::new(zip_file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+    let mut manifest = read_backup_manifest(zip_path)?;
+    manifest.note = note.map(str::to_string);
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+
+    let set_base = backup_volumes::set_base(zip_path);
+    let temp_path = set_base
+        .as_deref()
+        .unwrap_or(zip_path)
+        .with_extension("zip.tmp");
+    let result = rewrite_with_manifest(zip_path, &temp_path, &manifest_json);
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    if set_base.is_some() {
+        let volumes = backup_volumes::volume_set(zip_path)?;
+        backup_volumes::replace_volume_set(&temp_path, &volumes)?;
+    } else {
+        fs::rename(&temp_path, zip_path)
+            .map_err(|e| format!("Failed to replace backup file: {}", e))?;
+    }
+
+    Ok(manifest)
+}
+
+fn rewrite_with_manifest(src: &Path, dst: &Path, manifest_json: &str) -> Result<(), String> {
+    let mut archive = open_backup_archive(src)?;
+
+    let dst_file =
+        fs::File::create(dst).map_err(|e| format!("Failed to create backup file: {}", e))?;
+    let mut zip = ZipWriter::new(dst_file);
+
+    for i in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read zip entry: {}", e))?;
+        if entry.name() == "manifest.json" {
+            continue;
+        }
+        zip.raw_copy_file(entry)
+            .map_err(|e| format!("Failed to copy zip entry: {}", e))?;
+    }
+
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o755);
+    zip.start_file("manifest.json", options)
+        .map_err(|e| format!("Failed to start manifest file in zip: {}", e))?;
+    zip.write_all(manifest_json.as_bytes())
+        .map_err(|e| format!("Failed to write manifest to zip: {}", e))?;
+    zip.finish()
+        .map_err(|e| format!("Failed to finish zip file: {}", e))?;
+
+    Ok(())
+}
+
+pub fn set_backup_note(filename: &str, note: Option<&str>) -> Result<BackupManifest, String> {
+    validation::file_name("filename", filename)?;
+    if !is_hybrid_backup_filename(filename) {
+        return Err("Invalid hybrid backup filename".to_string());
+    }
+
+    let backup_path = get_backup_directory()?.join(filename);
+    if !backup_path.exists() {
+        return Err(format!("Backup file '{}' not found", filename));
+    }
+
+    let manifest = set_backup_note_at(&backup_path, note)?;
+    logger::info(format!("Backup note updated: {}", filename));
+    Ok(manifest)
+}
+
+/// A backup zip, or the volume set starting at `zip_path`, as one archive
+pub fn open_backup_archive(zip_path: &Path) -> Result<zip::ZipArchive<VolumeReader>, String> {
+    let reader = VolumeReader::open(zip_path)?;
+    zip::ZipArchive::new(reader).map_err(|e| format!("Failed to read zip archive: {}", e))
+}
+
+/// Helper function to read backup manifest from zip
+pub fn read_backup_manifest(zip_path: &Path) -> Result<BackupManifest, String> {
+    let mut archive = open_backup_archive(zip_path)?;
 
     let mut manifest_file = archive
         .by_name("manifest.json")
@@ -373,6 +592,27 @@ fn read_backup_manifest(zip_path: &Path) -> Result<BackupManifest, String> {
     Ok(manifest)
 }
 
+/// Extract the database file of a hybrid backup to `dest` without touching media
+pub fn extract_backup_database(zip_path: &Path, dest: &Path) -> Result<(), String> {
+    let mut archive = open_backup_archive(zip_path)?;
+
+    let mut entry =
+        archive
+            .by_name("database.db")
+            .map_err(|_| match read_backup_manifest(zip_path) {
+                Ok(manifest) if manifest.encryption.is_some() => {
+                    backup_encryption::ensure_not_encrypted(&manifest).unwrap_err()
+                }
+                _ => "Database file not found in backup".to_string(),
+            })?;
+    let mut out =
+        fs::File::create(dest).map_err(|e| format!("Failed to create output file: {}", e))?;
+    std::io::copy(&mut entry, &mut out)
+        .map_err(|e| format!("Failed to extract database file: {}", e))?;
+
+    Ok(())
+}
+
 /// Helper function to copy directory recursively
 fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), String> {
     fs::create_dir_all(dst)
@@ -402,39 +642,25 @@ fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), String> {
 
 /// Get backup directory path
 fn get_backup_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    let backup_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
-
-    if !backup_dir.exists() {
-        fs::create_dir_all(&backup_dir)
-            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
-    }
-
-    Ok(backup_dir)
+    crate::storage_paths::get_backup_dir()
 }
 
 /// Get database path
 fn get_database_path() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    Ok(app_data.join("pqs-rtn-hybrid-storage").join("database.db"))
+    crate::storage_paths::get_database_path()
 }
 
 /// Get media directory path
 fn get_media_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    Ok(app_data.join("pqs-rtn-hybrid-storage").join("media"))
+    crate::storage_paths::get_media_dir()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackupInfo {
     pub filename: String,
     pub path: String,
+    /// Files the backup is split into; 1 for an ordinary zip
+    pub volumes: usize,
     pub manifest: BackupManifest,
 }
 
@@ -512,6 +738,9 @@ mod tests {
             total_files: 2,
             backup_type: "hybrid".to_string(),
             checksum: "abc".to_string(),
+            schema_version: None,
+            note: None,
+            encryption: None,
         };
 
         let content = serde_json::to_string(&manifest).expect("Manifest should serialize");
@@ -527,6 +756,118 @@ mod tests {
         assert_eq!(parsed.total_files, 2);
     }
 
+    #[test]
+    fn test_set_backup_note_keeps_other_entries() {
+        let temp_dir = TempDir::new().expect("Temp dir should be created");
+        let zip_path = temp_dir.path().join("hybrid_backup_1.zip");
+
+        let file = fs::File::create(&zip_path).expect("Zip file should be created");
+        let mut zip = ZipWriter::new(file);
+        let manifest = BackupManifest {
+            version: "1.0".to_string(),
+            timestamp: 1,
+            database_size: 4,
+            media_size: 0,
+            total_files: 1,
+            backup_type: "hybrid".to_string(),
+            checksum: String::new(),
+            schema_version: None,
+            note: None,
+            encryption: None,
+        };
+        zip.start_file("database.db", FileOptions::default())
+            .expect("Start database entry should succeed");
+        zip.write_all(b"data")
+            .expect("Write database should succeed");
+        zip.start_file("manifest.json", FileOptions::default())
+            .expect("Start manifest entry should succeed");
+        zip.write_all(serde_json::to_string(&manifest).unwrap().as_bytes())
+            .expect("Write manifest should succeed");
+        zip.finish().expect("Finish zip should succeed");
+
+        set_backup_note_at(&zip_path, Some("  before annual officer rotation "))
+            .expect("Note should be set");
+
+        let parsed = read_backup_manifest(&zip_path).expect("Manifest should be read");
+        assert_eq!(
+            parsed.note.as_deref(),
+            Some("before annual officer rotation")
+        );
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut data = String::new();
+        archive
+            .by_name("database.db")
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "data");
+
+        set_backup_note_at(&zip_path, Some("")).expect("Note should be cleared");
+        assert!(read_backup_manifest(&zip_path).unwrap().note.is_none());
+    }
+
+    #[test]
+    fn test_split_backup_reads_from_first_volume() {
+        let temp_dir = TempDir::new().expect("Temp dir should be created");
+        let zip_path = temp_dir.path().join("hybrid_backup_2.zip");
+
+        let file = fs::File::create(&zip_path).expect("Zip file should be created");
+        let mut zip = ZipWriter::new(file);
+        let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let database: Vec<u8> = (0..3 * backup_volumes::MIN_VOLUME_SIZE)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        zip.start_file("database.db", stored)
+            .expect("Start database entry should succeed");
+        zip.write_all(&database)
+            .expect("Write database should succeed");
+        let manifest = BackupManifest {
+            version: "1.0".to_string(),
+            timestamp: 2,
+            database_size: database.len() as u64,
+            media_size: 0,
+            total_files: 1,
+            backup_type: "hybrid".to_string(),
+            checksum: String::new(),
+            schema_version: None,
+            note: None,
+            encryption: None,
+        };
+        zip.start_file("manifest.json", stored)
+            .expect("Start manifest entry should succeed");
+        zip.write_all(serde_json::to_string(&manifest).unwrap().as_bytes())
+            .expect("Write manifest should succeed");
+        zip.finish().expect("Finish zip should succeed");
+
+        let volumes =
+            backup_volumes::split_into_volumes(&zip_path, backup_volumes::MIN_VOLUME_SIZE)
+                .expect("Split should succeed");
+        assert_eq!(volumes.len(), 4);
+        assert!(is_hybrid_backup_filename(
+            &volumes[0].file_name().unwrap().to_string_lossy()
+        ));
+
+        let parsed = read_backup_manifest(&volumes[0]).expect("Manifest should be read");
+        assert_eq!(parsed.timestamp, 2);
+        let extracted = temp_dir.path().join("database.db");
+        extract_backup_database(&volumes[0], &extracted).expect("Database should extract");
+        assert_eq!(fs::read(&extracted).unwrap(), database);
+
+        set_backup_note_at(&volumes[0], Some("split")).expect("Note should be set on a set");
+        assert_eq!(
+            read_backup_manifest(&volumes[0]).unwrap().note.as_deref(),
+            Some("split")
+        );
+        assert_eq!(backup_volumes::volume_set(&volumes[0]).unwrap(), volumes);
+        extract_backup_database(&volumes[0], &extracted).expect("Database should extract");
+        assert_eq!(fs::read(&extracted).unwrap(), database);
+
+        // Without the last volume the central directory is gone
+        fs::remove_file(&volumes[3]).unwrap();
+        assert!(read_backup_manifest(&volumes[0]).is_err());
+    }
+
     #[test]
     fn test_read_backup_manifest_missing_manifest_returns_error() {
         let temp_dir = TempDir::new().expect("Temp dir should be created");
@@ -546,6 +887,34 @@ mod tests {
         assert!(result.is_err(), "Should fail when manifest.json is missing");
     }
 
+    #[test]
+    fn test_copy_into_zip_streams_files_larger_than_a_chunk() {
+        let temp_dir = TempDir::new().expect("Temp dir should be created");
+        let source = temp_dir.path().join("large.bin");
+        let data: Vec<u8> = (0..COPY_CHUNK_BYTES * 2 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&source, &data).expect("Write source should succeed");
+
+        let zip_path = temp_dir.path().join("streamed.zip");
+        let mut zip = ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        zip.start_file("media/large.bin", FileOptions::default())
+            .expect("Start file entry should succeed");
+        let copied = copy_into_zip(fs::File::open(&source).unwrap(), &mut zip)
+            .expect("Copy into zip should succeed");
+        zip.finish().expect("Finish zip should succeed");
+        assert_eq!(copied, data.len() as u64);
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        let mut stored = Vec::new();
+        archive
+            .by_name("media/large.bin")
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+        assert_eq!(stored, data);
+    }
+
     #[test]
     fn test_copy_dir_recursive_copies_nested_files() {
         let src_temp = TempDir::new().expect("Source temp dir should be created");
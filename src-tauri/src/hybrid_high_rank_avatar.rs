@@ -4,8 +4,14 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::sync::Arc; // Phase 1.4: Arc for shared FileManager
 
+use crate::avatar_approval;
+use crate::avatar_policy;
 use crate::database::get_connection_safe;
-use crate::file_manager::FileManager;
+use crate::file_manager::{FileManager, HIGH_RANKS_SUBDIR};
+use crate::file_transaction;
+use crate::media_hooks;
+use crate::media_maintenance::OWNER_OFFICER;
+use crate::permissions::Caller;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HybridHighRankAvatarInfo {
@@ -15,6 +21,10 @@ pub struct HybridHighRankAvatarInfo {
     pub avatar_mime: Option<String>,
     pub avatar_size: Option<i32>,
     pub file_exists: bool,
+    /// Format and dimensions recorded at save time
+    pub metadata: Option<avatar_policy::ImageMetadata>,
+    /// Set by the save command only
+    pub validation: Option<avatar_policy::AvatarValidationReport>,
 }
 
 // Phase 1.4: Use Arc<FileManager> for zero-cost sharing
@@ -34,8 +44,20 @@ impl HybridHighRankAvatarManager {
         officer_id: i32,
         file_data: &[u8],
         mime_type: &str,
+        uploaded_by: Option<Caller>,
     ) -> Result<HybridHighRankAvatarInfo, String> {
-        let conn =
+        // Same rules as user avatars; reject or scale before touching the old file
+        let prepared = media_hooks::prepare_avatar(
+            &avatar_policy::current_policy(),
+            OWNER_OFFICER,
+            officer_id,
+            file_data,
+            mime_type,
+        )?;
+        let file_data = prepared.data.as_slice();
+        let mime_type = prepared.mime_type.as_str();
+
+        let mut conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
         // First, verify officer exists
@@ -51,24 +73,29 @@ impl HybridHighRankAvatarManager {
             return Err(format!("Officer with ID {} does not exist", officer_id));
         }
 
-        // Delete old avatar file if exists
-        if let Ok(Some(path)) = self.get_officer_avatar_path(officer_id) {
-            let _ = self.file_manager.delete_high_rank_avatar_file(&path);
-        }
-
-        // Save new avatar file
-        let avatar_path = self
+        let old_path = self.get_officer_avatar_path(officer_id).ok().flatten();
+        let (avatar_path, file_path) = self
             .file_manager
-            .save_high_rank_avatar_file(officer_id, file_data, mime_type)?;
-
-        // Update officer record with new avatar path
+            .new_high_rank_avatar_file_path(officer_id, mime_type)?;
         let updated_at = chrono::Utc::now().to_rfc3339();
         let file_size = file_data.len() as i32;
-
-        conn.execute(
-            "UPDATE high_ranking_officers SET avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ? WHERE id = ?",
-            params![avatar_path, updated_at, mime_type, file_size, officer_id]
-        ).map_err(|e| format!("Failed to update officer avatar: {}", e))?;
+        let metadata = prepared.report.stored_metadata();
+
+        // The new file only appears once the officer record points at it
+        file_transaction::with_file_and_db(&mut conn, |files, tx| {
+            files.write(&file_path, file_data)?;
+            tx.execute(
+                "UPDATE high_ranking_officers SET row_version = row_version + 1, avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ?, avatar_format = ?, avatar_width = ?, avatar_height = ? WHERE id = ?",
+                params![avatar_path, updated_at, mime_type, file_size, metadata.format, metadata.width, metadata.height, officer_id]
+            ).map_err(|e| format!("Failed to update officer avatar: {}", e))?;
+            avatar_approval::mark_uploaded_with_conn(tx, OWNER_OFFICER, officer_id, uploaded_by)
+        })?;
+
+        // Old file goes only after the switch succeeded
+        if let Some(old_path) = old_path.filter(|old| *old != avatar_path) {
+            let _ = self.file_manager.delete_high_rank_avatar_file(&old_path);
+        }
+        media_hooks::notify_saved(OWNER_OFFICER, officer_id, &avatar_path);
 
         Ok(HybridHighRankAvatarInfo {
             officer_id,
@@ -77,6 +104,8 @@ impl HybridHighRankAvatarManager {
             avatar_mime: Some(mime_type.to_string()),
             avatar_size: Some(file_size),
             file_exists: true,
+            metadata: Some(metadata),
+            validation: Some(prepared.report.clone()),
         })
     }
 
@@ -84,15 +113,21 @@ impl HybridHighRankAvatarManager {
         let conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-        type AvatarRow = (Option<String>, Option<String>, Option<String>, Option<i32>);
+        type AvatarRow = (
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<i32>,
+            Option<avatar_policy::ImageMetadata>,
+        );
         let result: Result<AvatarRow, _> = conn.query_row(
-            "SELECT avatar_path, avatar_updated_at, avatar_mime, avatar_size FROM high_ranking_officers WHERE id = ?",
+            "SELECT avatar_path, avatar_updated_at, avatar_mime, avatar_size, avatar_format, avatar_width, avatar_height FROM high_ranking_officers WHERE id = ?",
             params![officer_id],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, avatar_policy::ImageMetadata::from_columns(row.get(4)?, row.get(5)?, row.get(6)?)))
         );
 
         match result {
-            Ok((avatar_path, avatar_updated_at, avatar_mime, avatar_size)) => {
+            Ok((avatar_path, avatar_updated_at, avatar_mime, avatar_size, metadata)) => {
                 let file_exists = if let Some(ref path) = avatar_path {
                     self.file_manager.get_avatar_file_path(path).is_ok()
                 } else {
@@ -106,6 +141,8 @@ impl HybridHighRankAvatarManager {
                     avatar_mime,
                     avatar_size,
                     file_exists,
+                    metadata,
+                    validation: None,
                 })
             }
             Err(_) => {
@@ -117,6 +154,8 @@ impl HybridHighRankAvatarManager {
                     avatar_mime: None,
                     avatar_size: None,
                     file_exists: false,
+                    metadata: None,
+                    validation: None,
                 })
             }
         }
@@ -209,11 +248,11 @@ impl HybridHighRankAvatarManager {
 
         // Update officer record - clear all avatar fields
         match conn.execute(
-            "UPDATE high_ranking_officers SET avatar_path = NULL, avatar_updated_at = NULL, avatar_mime = NULL, avatar_size = NULL WHERE id = ?",
+            "UPDATE high_ranking_officers SET row_version = row_version + 1, avatar_path = NULL, avatar_updated_at = NULL, avatar_mime = NULL, avatar_size = NULL, avatar_format = NULL, avatar_width = NULL, avatar_height = NULL, avatar_status = NULL WHERE id = ?",
             params![officer_id]
         ) {
             Ok(updated) if updated > 0 => {
-                // Successfully updated
+                media_hooks::notify_deleted(OWNER_OFFICER, officer_id);
                 Ok(true)
             },
             Ok(_) => {
@@ -244,6 +283,18 @@ impl HybridHighRankAvatarManager {
         Ok(format!("data:{};base64,{}", mime_type, base64_data))
     }
 
+    /// Resolve the officer's avatar path internally and return the image as a data URL
+    /// Returns None when the officer has no avatar set
+    pub fn get_avatar_base64_by_officer_id(
+        &self,
+        officer_id: i32,
+    ) -> Result<Option<String>, String> {
+        match self.get_officer_avatar_path(officer_id)? {
+            Some(path) if !path.is_empty() => self.get_avatar_base64(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
     fn get_officer_avatar_path(&self, officer_id: i32) -> Result<Option<String>, String> {
         let conn =
             get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
@@ -277,6 +328,46 @@ impl HybridHighRankAvatarManager {
             valid_paths.push(path);
         }
 
-        self.file_manager.cleanup_orphaned_files(&valid_paths)
+        self.file_manager
+            .cleanup_orphaned_files(HIGH_RANKS_SUBDIR, &valid_paths)
+            .map(|report| report.deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestEnvironment;
+
+    #[test]
+    fn test_avatar_base64_by_officer_id_reads_the_stored_photo() {
+        let _env = TestEnvironment::in_memory();
+        let conn = get_connection_safe().unwrap();
+        conn.execute(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, avatar_path) VALUES (901, 'a', 'b', 'c', 'high_ranks/officer_901_1.png')",
+            [],
+        )
+        .expect("officer insert should succeed");
+        conn.execute(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english) VALUES (902, 'd', 'e', 'f')",
+            [],
+        )
+        .expect("officer insert should succeed");
+        let manager = HybridHighRankAvatarManager::new().expect("avatar manager should start");
+        let photos = manager
+            .file_manager
+            .get_media_directory()
+            .join(HIGH_RANKS_SUBDIR);
+        fs::create_dir_all(&photos).unwrap();
+        fs::write(photos.join("officer_901_1.png"), b"png").unwrap();
+
+        assert_eq!(
+            manager.get_avatar_base64_by_officer_id(901).unwrap(),
+            Some(format!(
+                "data:image/png;base64,{}",
+                general_purpose::STANDARD.encode(b"png")
+            ))
+        );
+        assert_eq!(manager.get_avatar_base64_by_officer_id(902).unwrap(), None);
     }
 }
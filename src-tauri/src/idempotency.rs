@@ -0,0 +1,260 @@
+//! Idempotency keys for commands the webview may send twice
+//!
+//! A double-clicked save or a retried invoke after a dropped response would
+//! otherwise create a second user, avatar file or backup. Commands that
+//! create something accept an optional key; the first call with a key runs,
+//! and any repeat within `KEY_TTL` gets the first call's result back. A
+//! repeat that arrives while the first call is still running waits for it.
+//! Failures are not remembered, so a failed call can be retried with the
+//! same key; neither is a call that panicked. Keys belong to the session
+//! that sent them, so two windows signed in as different users never get
+//! each other's results.
+
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::sessions;
+
+/// How long a finished result is replayed for a repeated key
+pub const KEY_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Longest a repeat waits for the first call before giving up
+const IN_FLIGHT_WAIT: Duration = Duration::from_secs(60);
+
+enum Entry {
+    InFlight,
+    Done { at: Instant, result: Value },
+}
+
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    finished: Condvar,
+}
+
+lazy_static! {
+    static ref CACHE: IdempotencyCache = IdempotencyCache::default();
+}
+
+/// Settles an in-flight key; dropped without `finish`, because the call
+/// panicked, it frees the key for a retry
+struct InFlight<'a> {
+    cache: &'a IdempotencyCache,
+    cache_key: Option<String>,
+}
+
+impl InFlight<'_> {
+    /// Remember `result` for repeats, or free the key when there is none
+    fn finish(mut self, result: Option<Value>) {
+        let Some(cache_key) = self.cache_key.take() else {
+            return;
+        };
+        let mut entries = self
+            .cache
+            .entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match result {
+            Some(result) => {
+                entries.insert(
+                    cache_key,
+                    Entry::Done {
+                        at: Instant::now(),
+                        result,
+                    },
+                );
+            }
+            None => {
+                entries.remove(&cache_key);
+            }
+        }
+        drop(entries);
+        self.cache.finished.notify_all();
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Some(cache_key) = self.cache_key.take() {
+            self.cache
+                .entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&cache_key);
+            self.cache.finished.notify_all();
+        }
+    }
+}
+
+impl IdempotencyCache {
+    /// Run `f` once per (`scope`, `command`, `key`); without a key `f` always
+    /// runs. `scope` names whoever sent the key, None for calls without a
+    /// session.
+    pub fn run<T, F>(
+        &self,
+        scope: Option<&str>,
+        command: &str,
+        key: Option<&str>,
+        f: F,
+    ) -> Result<T, String>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T, String>,
+    {
+        let Some(key) = key.map(str::trim).filter(|k| !k.is_empty()) else {
+            return f();
+        };
+        let cache_key = format!("{}:{}:{}", scope.unwrap_or_default(), command, key);
+
+        {
+            let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+            let now = Instant::now();
+            entries.retain(|_, entry| match entry {
+                Entry::InFlight => true,
+                Entry::Done { at, .. } => now.duration_since(*at) < KEY_TTL,
+            });
+
+            let deadline = now + IN_FLIGHT_WAIT;
+            loop {
+                match entries.get(&cache_key) {
+                    Some(Entry::Done { result, .. }) => {
+                        return serde_json::from_value(result.clone())
+                            .map_err(|e| format!("Failed to replay command result: {}", e));
+                    }
+                    Some(Entry::InFlight) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            return Err(format!(
+                                "{} with this idempotency key is still running",
+                                command
+                            ));
+                        }
+                        entries = self
+                            .finished
+                            .wait_timeout(entries, remaining)
+                            .map_err(|e| e.to_string())?
+                            .0;
+                    }
+                    None => break,
+                }
+            }
+            entries.insert(cache_key.clone(), Entry::InFlight);
+        }
+
+        let in_flight = InFlight {
+            cache: self,
+            cache_key: Some(cache_key),
+        };
+        let outcome = f();
+        // Failed (or unserializable) calls may be retried with the same key
+        in_flight.finish(
+            outcome
+                .as_ref()
+                .ok()
+                .and_then(|result| serde_json::to_value(result).ok()),
+        );
+
+        outcome
+    }
+}
+
+/// `IdempotencyCache::run` on the process-wide cache, with keys scoped to
+/// the session of `session_token`
+pub fn run_once<T, F>(
+    command: &str,
+    session_token: Option<&str>,
+    key: Option<&str>,
+    f: F,
+) -> Result<T, String>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Result<T, String>,
+{
+    let scope = session_token
+        .filter(|token| !token.is_empty())
+        .map(sessions::session_id);
+    CACHE.run(scope.as_deref(), command, key, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_repeated_key_replays_first_result() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        let create = || -> Result<usize, String> { Ok(calls.fetch_add(1, Ordering::SeqCst) + 1) };
+
+        assert_eq!(cache.run(None, "create_user", Some("k1"), create), Ok(1));
+        assert_eq!(cache.run(None, "create_user", Some("k1"), create), Ok(1));
+        // Same key on another command, or no key at all, runs again
+        assert_eq!(
+            cache.run(None, "create_workspace", Some("k1"), create),
+            Ok(2)
+        );
+        assert_eq!(cache.run(None, "create_user", None, create), Ok(3));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_failures_are_not_remembered() {
+        let cache = IdempotencyCache::default();
+        let failed: Result<i32, String> =
+            cache.run(None, "save", Some("k"), || Err("disk full".to_string()));
+        assert!(failed.is_err());
+        assert_eq!(cache.run(None, "save", Some("k"), || Ok(7)), Ok(7));
+    }
+
+    #[test]
+    fn test_keys_are_scoped_to_their_session() {
+        let cache = IdempotencyCache::default();
+        assert_eq!(cache.run(Some("a"), "save", Some("k"), || Ok(1)), Ok(1));
+        assert_eq!(cache.run(Some("b"), "save", Some("k"), || Ok(2)), Ok(2));
+        assert_eq!(cache.run(None, "save", Some("k"), || Ok(3)), Ok(3));
+        assert_eq!(cache.run(Some("a"), "save", Some("k"), || Ok(4)), Ok(1));
+    }
+
+    #[test]
+    fn test_panicked_call_frees_its_key() {
+        let cache = IdempotencyCache::default();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.run(None, "save", Some("k"), || -> Result<i32, String> {
+                panic!("save crashed")
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(cache.run(None, "save", Some("k"), || Ok(7)), Ok(7));
+    }
+
+    #[test]
+    fn test_concurrent_repeat_waits_for_first_call() {
+        let cache = Arc::new(IdempotencyCache::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                let calls = Arc::clone(&calls);
+                std::thread::spawn(move || {
+                    cache.run(None, "save", Some("double-click"), || {
+                        std::thread::sleep(Duration::from_millis(50));
+                        Ok(calls.fetch_add(1, Ordering::SeqCst))
+                    })
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(0));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
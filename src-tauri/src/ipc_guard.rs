@@ -0,0 +1,112 @@
+//! Size limit for command payloads
+//!
+//! A `Vec<u8>` argument crosses the webview bridge as a JSON array of
+//! numbers, about four bytes per byte of data, so a careless send of a large
+//! file or of many avatars at once turns into hundreds of megabytes of JSON.
+//! The invoke handler refuses any payload whose JSON is larger than
+//! `MAX_IPC_PAYLOAD_BYTES` before the command deserialises its arguments.
+//! The refusal carries the PAYLOAD_TOO_LARGE code; for avatar uploads it
+//! also sets `chunked_upload`, telling the UI to resend the file through
+//! the `chunked_upload` commands instead.
+
+use serde_json::Value;
+
+use crate::error_codes::{self, PAYLOAD_TOO_LARGE};
+
+pub const MAX_IPC_PAYLOAD_BYTES: usize = 20 * 1024 * 1024;
+
+/// Commands with a `chunked_upload` counterpart for large data
+const CHUNKED_UPLOAD_COMMANDS: &[&str] = &[
+    "save_hybrid_avatar",
+    "save_hybrid_avatar_stream",
+    "save_hybrid_high_rank_avatar",
+];
+
+fn number_len(number: &serde_json::Number) -> usize {
+    match number.as_u64() {
+        Some(value) => value
+            .checked_ilog10()
+            .map_or(1, |digits| digits as usize + 1),
+        None => number.to_string().len(),
+    }
+}
+
+/// Add the compact JSON length of `value` to `total`, stopping once it
+/// passes `limit` so an oversized array is not walked to the end
+fn add_encoded_len(value: &Value, total: &mut usize, limit: usize) {
+    match value {
+        Value::Null => *total += 4,
+        Value::Bool(true) => *total += 4,
+        Value::Bool(false) => *total += 5,
+        Value::Number(number) => *total += number_len(number),
+        // Escapes are not counted; the estimate only has to be close
+        Value::String(text) => *total += text.len() + 2,
+        Value::Array(items) => {
+            *total += 2 + items.len().saturating_sub(1);
+            for item in items {
+                if *total > limit {
+                    return;
+                }
+                add_encoded_len(item, total, limit);
+            }
+        }
+        Value::Object(fields) => {
+            *total += 2 + fields.len().saturating_sub(1);
+            for (key, item) in fields {
+                if *total > limit {
+                    return;
+                }
+                *total += key.len() + 3;
+                add_encoded_len(item, total, limit);
+            }
+        }
+    }
+}
+
+pub fn check_payload_with_limit(
+    command: &str,
+    payload: &Value,
+    limit: usize,
+) -> Result<(), String> {
+    let mut size = 0;
+    add_encoded_len(payload, &mut size, limit);
+    if size <= limit {
+        return Ok(());
+    }
+    let details = serde_json::json!({
+        "command": command,
+        "limit_bytes": limit,
+        "chunked_upload": CHUNKED_UPLOAD_COMMANDS.contains(&command),
+    });
+    Err(error_codes::with_code(
+        PAYLOAD_TOO_LARGE,
+        &details.to_string(),
+    ))
+}
+
+/// Gate used by the invoke handler for every command
+pub fn check_payload(command: &str, payload: &Value) -> Result<(), String> {
+    check_payload_with_limit(command, payload, MAX_IPC_PAYLOAD_BYTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oversized_payloads_are_refused() {
+        let payload = serde_json::json!({ "userId": 7, "avatarData": [255, 0, 12, 1] });
+        let mut size = 0;
+        add_encoded_len(&payload, &mut size, usize::MAX);
+        assert_eq!(size, payload.to_string().len());
+
+        assert!(check_payload_with_limit("save_hybrid_avatar", &payload, size).is_ok());
+        let error = check_payload_with_limit("save_hybrid_avatar", &payload, size - 1)
+            .expect_err("payload should be over the limit");
+        assert_eq!(error_codes::find_code(&error), Some(PAYLOAD_TOO_LARGE));
+        assert!(error.contains("\"chunked_upload\":true"));
+
+        let error = check_payload_with_limit("import_database", &payload, 10).unwrap_err();
+        assert!(error.contains("\"chunked_upload\":false"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Registry of long-running commands that can be listed and cancelled
+//!
+//! A command wraps its work in `run_job`, which registers the job, hands the
+//! worker a `ProgressReporter` whose operation id is the job id, and records
+//! how the job ended. `cancel_job` sets that reporter's cancel flag, so the
+//! worker stops at its next `check_cancelled`. Users cancel the jobs they
+//! started; anyone else's, and jobs the app started itself, need an
+//! administrator. Finished jobs stay listed for a while so the UI can show
+//! what happened to them.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::permissions::{self, Caller, Role};
+use crate::progress::{self, ProgressPayload, ProgressReporter, ProgressSink};
+
+/// Every job's progress goes out on this event as well as its command's own
+pub const JOB_PROGRESS_EVENT: &str = "job://progress";
+
+/// Finished jobs kept for `list_jobs`; older ones are dropped
+const FINISHED_JOBS_KEPT: usize = 20;
+
+static JOB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref JOBS: Mutex<Vec<JobInfo>> = Mutex::new(Vec::new());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobInfo {
+    pub id: String,
+    /// "backup", "export", "import", ...
+    pub kind: String,
+    /// User whose call started the job; None for the app's own jobs
+    #[serde(default)]
+    pub started_by: Option<i32>,
+    pub status: JobStatus,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub cancel_requested: bool,
+    /// Stopped by the watchdog after reporting no progress for too long
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Latest progress event, if the job reported any
+    pub progress: Option<ProgressPayload>,
+    pub error: Option<String>,
+}
+
+/// Unique id for a job the frontend did not name itself
+pub fn new_job_id(kind: &str) -> String {
+    format!(
+        "{}-{}-{}",
+        kind,
+        chrono::Utc::now().timestamp_millis(),
+        JOB_COUNTER.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+fn register(job_id: &str, kind: &str, started_by: Option<i32>) -> Result<(), String> {
+    let mut jobs = JOBS
+        .lock()
+        .map_err(|e| format!("Failed to acquire job lock: {}", e))?;
+    if jobs
+        .iter()
+        .any(|job| job.id == job_id && job.status == JobStatus::Running)
+    {
+        return Err(format!("Job {} is already running", job_id));
+    }
+
+    jobs.retain(|job| job.id != job_id);
+    jobs.push(JobInfo {
+        id: job_id.to_string(),
+        kind: kind.to_string(),
+        started_by,
+        status: JobStatus::Running,
+        started_at: chrono::Utc::now().to_rfc3339(),
+        finished_at: None,
+        cancel_requested: false,
+        timed_out: false,
+        progress: None,
+        error: None,
+    });
+    Ok(())
+}
+
+fn record_progress(payload: &ProgressPayload) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == payload.operation_id) {
+            job.progress = Some(payload.clone());
+        }
+    }
+}
+
+fn record_finish(job_id: &str, error: Option<&str>) {
+    let Ok(mut jobs) = JOBS.lock() else {
+        return;
+    };
+    if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+        job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        job.status = match error {
+            None => JobStatus::Completed,
+            // Whatever the worker's error says, a requested cancel is what ended it
+            Some(_) if job.cancel_requested => JobStatus::Cancelled,
+            Some(_) => JobStatus::Failed,
+        };
+        job.error = error.map(str::to_string);
+    }
+
+    let finished = jobs
+        .iter()
+        .filter(|job| job.status != JobStatus::Running)
+        .count();
+    let mut excess = finished.saturating_sub(FINISHED_JOBS_KEPT);
+    // Oldest entries come first
+    jobs.retain(|job| {
+        if excess > 0 && job.status != JobStatus::Running {
+            excess -= 1;
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Run `work` as a registered job; `sink` receives its progress events
+pub fn run_job<T, F>(
+    job_id: &str,
+    kind: &str,
+    started_by: Option<i32>,
+    sink: ProgressSink,
+    work: F,
+) -> Result<T, String>
+where
+    F: FnOnce(&ProgressReporter) -> Result<T, String>,
+{
+    register(job_id, kind, started_by)?;
+
+    let reporter = ProgressReporter::new(
+        job_id,
+        Box::new(move |payload| {
+            record_progress(payload);
+            sink(payload);
+        }),
+    );
+    let result = work(&reporter);
+    drop(reporter);
+
+    record_finish(job_id, result.as_ref().err().map(String::as_str));
+    result
+}
+
+/// Running jobs first, then finished ones, newest first
+pub fn list_jobs() -> Result<Vec<JobInfo>, String> {
+    let mut jobs = JOBS
+        .lock()
+        .map_err(|e| format!("Failed to acquire job lock: {}", e))?
+        .clone();
+    jobs.reverse();
+    jobs.sort_by_key(|job| job.status != JobStatus::Running);
+    Ok(jobs)
+}
+
+/// Flag a job the watchdog gave up on
+pub fn mark_timed_out(job_id: &str) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+            job.timed_out = true;
+        }
+    }
+}
+
+/// Ask a running job to stop on the app's own behalf, e.g. the watchdog's;
+/// false when no such job is running
+pub fn stop_job(job_id: &str) -> Result<bool, String> {
+    if !progress::cancel_operation(job_id)? {
+        return Ok(false);
+    }
+
+    let mut jobs = JOBS
+        .lock()
+        .map_err(|e| format!("Failed to acquire job lock: {}", e))?;
+    if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+        job.cancel_requested = true;
+    }
+    Ok(true)
+}
+
+/// `stop_job` for `caller`, who needs to have started the job or be an
+/// administrator
+pub fn cancel_job_as(job_id: &str, caller: Option<Caller>) -> Result<bool, String> {
+    let started_by = {
+        let jobs = JOBS
+            .lock()
+            .map_err(|e| format!("Failed to acquire job lock: {}", e))?;
+        match jobs
+            .iter()
+            .find(|job| job.id == job_id && job.status == JobStatus::Running)
+        {
+            Some(job) => job.started_by,
+            None => return Ok(false),
+        }
+    };
+    let own_job = started_by.is_some() && caller.is_some_and(|caller| caller.user_id == started_by);
+    if !own_job {
+        permissions::check_role("cancel_job", Role::Admin, caller.map(|c| c.role))?;
+    }
+    stop_job(job_id)
+}
+
+/// `cancel_job_as` for the owner of `session_token`
+pub fn cancel_job(job_id: &str, session_token: Option<&str>) -> Result<bool, String> {
+    cancel_job_as(job_id, permissions::caller(session_token)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_codes::{self, FORBIDDEN};
+
+    fn caller(user_id: i32, role: Role) -> Caller {
+        Caller {
+            user_id: Some(user_id),
+            role,
+            must_change_password: false,
+        }
+    }
+
+    fn job(job_id: &str) -> JobInfo {
+        list_jobs()
+            .unwrap()
+            .into_iter()
+            .find(|job| job.id == job_id)
+            .expect("job should be listed")
+    }
+
+    #[test]
+    fn test_completed_job_keeps_last_progress() {
+        let job_id = new_job_id("test");
+
+        let value = run_job(&job_id, "test", None, Box::new(|_| {}), |progress| {
+            assert_eq!(job(&job_id).status, JobStatus::Running);
+            progress.report(Some("users"), 5, Some(10));
+            Ok(42)
+        })
+        .expect("job should succeed");
+
+        assert_eq!(value, 42);
+        let finished = job(&job_id);
+        assert_eq!(finished.status, JobStatus::Completed);
+        assert_eq!(finished.progress.map(|p| p.rows_processed), Some(5));
+    }
+
+    #[test]
+    fn test_file_job_reports_bytes() {
+        let job_id = new_job_id("backup");
+        let payloads = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = std::sync::Arc::clone(&payloads);
+
+        run_job(
+            &job_id,
+            "backup",
+            None,
+            Box::new(move |payload: &ProgressPayload| seen.lock().unwrap().push(payload.clone())),
+            |progress| {
+                progress.report_bytes(Some("media"), 2, Some(4), 2048, Some(8192));
+                Ok(())
+            },
+        )
+        .expect("job should succeed");
+
+        let reported = job(&job_id).progress.expect("progress should be kept");
+        assert_eq!(reported.bytes_processed, Some(2048));
+        assert_eq!(reported.total_bytes, Some(8192));
+        let payloads = payloads.lock().unwrap();
+        assert!(payloads.iter().any(|p| p.bytes_processed == Some(2048)));
+    }
+
+    #[test]
+    fn test_cancelled_job() {
+        let job_id = new_job_id("test");
+
+        let result: Result<(), String> =
+            run_job(&job_id, "test", Some(7), Box::new(|_| {}), |progress| {
+                assert!(cancel_job_as(&job_id, Some(caller(7, Role::Editor))).unwrap());
+                progress.check_cancelled()
+            });
+
+        assert!(result.is_err());
+        assert_eq!(job(&job_id).status, JobStatus::Cancelled);
+        // Finished jobs cannot be cancelled again
+        assert!(!cancel_job_as(&job_id, Some(caller(7, Role::Editor))).unwrap());
+    }
+
+    #[test]
+    fn test_only_admins_cancel_other_users_jobs() {
+        let job_id = new_job_id("restore");
+
+        let result: Result<(), String> =
+            run_job(&job_id, "restore", Some(1), Box::new(|_| {}), |progress| {
+                let refused = cancel_job_as(&job_id, Some(caller(2, Role::Editor))).unwrap_err();
+                assert_eq!(error_codes::find_code(&refused), Some(FORBIDDEN));
+                assert!(cancel_job_as(&job_id, None).is_err());
+                progress.check_cancelled()?;
+                assert!(cancel_job_as(&job_id, Some(caller(3, Role::Admin))).unwrap());
+                progress.check_cancelled()
+            });
+
+        assert!(result.is_err());
+        assert_eq!(job(&job_id).status, JobStatus::Cancelled);
+
+        // Nobody owns the app's own jobs
+        let job_id = new_job_id("warm-up");
+        run_job(&job_id, "warm-up", None, Box::new(|_| {}), |_| {
+            assert!(cancel_job_as(&job_id, Some(caller(2, Role::Editor))).is_err());
+            Ok(())
+        })
+        .expect("job should finish");
+    }
+
+    #[test]
+    fn test_failed_job_and_duplicate_id() {
+        let job_id = new_job_id("test");
+
+        let result: Result<(), String> = run_job(&job_id, "test", None, Box::new(|_| {}), |_| {
+            let nested: Result<(), String> =
+                run_job(&job_id, "test", None, Box::new(|_| {}), |_| Ok(()));
+            assert!(nested.is_err());
+            Err("disk full".to_string())
+        });
+
+        assert!(result.is_err());
+        let failed = job(&job_id);
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("disk full"));
+    }
+}
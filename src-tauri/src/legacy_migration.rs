@@ -0,0 +1,511 @@
+//! Migration assistant for installs that used the old `pqs-rtn-tauri` directory
+//!
+//! Finds the stranded database, media and backups, validates the database and
+//! brings everything into the active workspace. An empty workspace gets the
+//! legacy database file as-is; otherwise users and officers are merged in,
+//! skipping records that already exist. Files are copied, never overwritten,
+//! and the legacy directory is renamed afterwards so it is not offered again.
+//!
+//! On a fresh install (no database in the active workspace yet) the
+//! migration runs by itself at startup; merging into existing data is left
+//! to the assistant so an administrator decides.
+
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::activity_log;
+use crate::database;
+use crate::logger;
+use crate::storage_paths;
+
+const MIGRATED_SUFFIX: &str = ".migrated";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegacyDatabaseInfo {
+    pub path: String,
+    pub size: u64,
+    pub valid: bool,
+    pub user_count: i64,
+    pub officer_count: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegacyDataReport {
+    pub legacy_path: String,
+    pub found: bool,
+    pub database: Option<LegacyDatabaseInfo>,
+    pub media_file_count: usize,
+    pub backup_files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MergeCounts {
+    pub users_imported: usize,
+    pub users_skipped: usize,
+    pub officers_imported: usize,
+    pub officers_skipped: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegacyMigrationReport {
+    /// "copied" when the legacy database became the workspace database,
+    /// "merged" when its rows were added to an existing one, "skipped" otherwise
+    pub database_action: String,
+    pub counts: MergeCounts,
+    pub media_files_copied: usize,
+    pub backups_copied: usize,
+    pub warnings: Vec<String>,
+}
+
+fn count_files(dir: &Path) -> usize {
+    WalkDir::new(dir)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .count()
+}
+
+/// Open read-only, run an integrity check and count the rows we care about
+pub fn validate_legacy_database(path: &Path) -> Result<(i64, i64), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open legacy database: {}", e))?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to check legacy database: {}", e))?;
+    if integrity != "ok" {
+        return Err(format!("Legacy database is corrupt: {}", integrity));
+    }
+
+    let users: i64 = conn
+        .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+        .map_err(|e| format!("Legacy database has no users table: {}", e))?;
+    // Very old installs may predate the officers table
+    let officers: i64 = conn
+        .query_row("SELECT COUNT(*) FROM high_ranking_officers", [], |row| {
+            row.get(0)
+        })
+        .unwrap_or(0);
+
+    Ok((users, officers))
+}
+
+pub fn detect_legacy_data_in(legacy_root: &Path) -> LegacyDataReport {
+    let mut report = LegacyDataReport {
+        legacy_path: legacy_root.to_string_lossy().to_string(),
+        found: false,
+        database: None,
+        media_file_count: 0,
+        backup_files: Vec::new(),
+    };
+
+    if !legacy_root.is_dir() {
+        return report;
+    }
+
+    let db_path = legacy_root.join("database.db");
+    if db_path.is_file() {
+        let size = fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+        let (valid, user_count, officer_count, error) = match validate_legacy_database(&db_path) {
+            Ok((users, officers)) => (true, users, officers, None),
+            Err(e) => (false, 0, 0, Some(e)),
+        };
+        report.database = Some(LegacyDatabaseInfo {
+            path: db_path.to_string_lossy().to_string(),
+            size,
+            valid,
+            user_count,
+            officer_count,
+            error,
+        });
+    }
+
+    report.media_file_count = count_files(&legacy_root.join("media"));
+
+    if let Ok(entries) = fs::read_dir(legacy_root.join("backups")) {
+        report.backup_files = entries
+            .flatten()
+            .filter(|e| e.path().is_file())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        report.backup_files.sort();
+    }
+
+    report.found =
+        report.database.is_some() || report.media_file_count > 0 || !report.backup_files.is_empty();
+    report
+}
+
+pub fn detect_legacy_data() -> Result<LegacyDataReport, String> {
+    Ok(detect_legacy_data_in(&storage_paths::get_legacy_app_root()?))
+}
+
+fn table_columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA {}.table_info({})", schema, table))
+        .map_err(|e| format!("Failed to read {} columns: {}", table, e))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| format!("Failed to read {} columns: {}", table, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read {} columns: {}", table, e))?;
+    Ok(columns)
+}
+
+/// Copy rows the target lacks; ids are reassigned to avoid collisions
+/// Returns (imported, skipped)
+fn merge_table(
+    conn: &Connection,
+    table: &str,
+    duplicate_condition: &str,
+) -> Result<(usize, usize), String> {
+    let legacy_columns = table_columns(conn, "legacy", table)?;
+    if legacy_columns.is_empty() {
+        return Ok((0, 0));
+    }
+    let main_columns = table_columns(conn, "main", table)?;
+    let columns: Vec<&str> = legacy_columns
+        .iter()
+        .filter(|c| c.as_str() != "id" && main_columns.contains(c))
+        .map(|c| c.as_str())
+        .collect();
+
+    let total: i64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM legacy.{}", table),
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to count legacy {}: {}", table, e))?;
+
+    let column_list = columns.join(", ");
+    let select_list = columns
+        .iter()
+        .map(|c| format!("l.{}", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let imported = conn
+        .execute(
+            &format!(
+                "INSERT INTO main.{table} ({column_list})
+                 SELECT {select_list} FROM legacy.{table} l
+                 WHERE NOT EXISTS (SELECT 1 FROM main.{table} m WHERE {duplicate_condition})"
+            ),
+            [],
+        )
+        .map_err(|e| format!("Failed to merge legacy {}: {}", table, e))?;
+
+    Ok((imported, total as usize - imported))
+}
+
+/// Both merges against a connection with the legacy database attached as `legacy`
+fn merge_attached_legacy(conn: &Connection) -> Result<MergeCounts, String> {
+    let (users_imported, users_skipped) = merge_table(
+        conn,
+        "users",
+        "m.username = l.username OR m.email = l.email",
+    )?;
+    let (officers_imported, officers_skipped) = merge_table(
+        conn,
+        "high_ranking_officers",
+        "m.thai_name = l.thai_name AND m.position_thai = l.position_thai",
+    )?;
+    Ok(MergeCounts {
+        users_imported,
+        users_skipped,
+        officers_imported,
+        officers_skipped,
+    })
+}
+
+/// Merge users (matched on username or email) and officers (matched on name
+/// and position) from the legacy database into `conn`
+pub fn merge_legacy_database_with_conn(
+    conn: &Connection,
+    legacy_db: &Path,
+) -> Result<MergeCounts, String> {
+    conn.execute(
+        "ATTACH DATABASE ? AS legacy",
+        [legacy_db.to_string_lossy().to_string()],
+    )
+    .map_err(|e| format!("Failed to attach legacy database: {}", e))?;
+
+    let result = merge_attached_legacy(conn);
+
+    if let Err(e) = conn.execute("DETACH DATABASE legacy", []) {
+        logger::warn(format!("Failed to detach legacy database: {}", e));
+    }
+    result
+}
+
+/// Copy every file under `src` into `dst`, keeping files that already exist
+pub fn copy_missing_files(src: &Path, dst: &Path) -> Result<usize, String> {
+    let mut copied = 0;
+    for entry in WalkDir::new(src).into_iter().flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(src)
+            .map_err(|e| format!("Failed to resolve legacy file path: {}", e))?;
+        let target = dst.join(relative);
+        if target.exists() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+        fs::copy(entry.path(), &target)
+            .map_err(|e| format!("Failed to copy {}: {}", relative.display(), e))?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+pub fn migrate_legacy_data() -> Result<LegacyMigrationReport, String> {
+    migrate_legacy_data_in(&storage_paths::get_legacy_app_root()?)
+}
+
+fn migrate_legacy_data_in(legacy_root: &Path) -> Result<LegacyMigrationReport, String> {
+    let detected = detect_legacy_data_in(legacy_root);
+    if !detected.found {
+        return Err("No legacy data found".to_string());
+    }
+
+    logger::info(format!(
+        "Migrating legacy data from {}",
+        legacy_root.display()
+    ));
+
+    let mut report = LegacyMigrationReport {
+        database_action: "skipped".to_string(),
+        counts: MergeCounts::default(),
+        media_files_copied: 0,
+        backups_copied: 0,
+        warnings: Vec::new(),
+    };
+
+    if let Some(db) = &detected.database {
+        if !db.valid {
+            report.warnings.push(format!(
+                "Legacy database was not migrated: {}",
+                db.error.clone().unwrap_or_default()
+            ));
+        } else if database::check_database_exists_and_valid()? {
+            let conn = database::get_connection_safe()
+                .map_err(|e| format!("Failed to connect to database: {}", e))?;
+            report.counts = merge_legacy_database_with_conn(&conn, Path::new(&db.path))?;
+            report.database_action = "merged".to_string();
+        } else {
+            fs::copy(&db.path, storage_paths::get_database_path()?)
+                .map_err(|e| format!("Failed to copy legacy database: {}", e))?;
+            database::migrate_existing_database()?;
+            report.counts.users_imported = db.user_count as usize;
+            report.counts.officers_imported = db.officer_count as usize;
+            report.database_action = "copied".to_string();
+        }
+    }
+
+    report.media_files_copied =
+        copy_missing_files(&legacy_root.join("media"), &storage_paths::get_media_dir()?)?;
+    report.backups_copied = copy_missing_files(
+        &legacy_root.join("backups"),
+        &storage_paths::get_backup_dir()?,
+    )?;
+
+    // Keep the data around, but stop offering it for migration
+    let mut retired = legacy_root.as_os_str().to_os_string();
+    retired.push(MIGRATED_SUFFIX);
+    if let Err(e) = fs::rename(legacy_root, PathBuf::from(retired)) {
+        report
+            .warnings
+            .push(format!("Failed to rename legacy directory: {}", e));
+    }
+
+    let details = format!(
+        "database {}, {} users, {} officers, {} media files, {} backups",
+        report.database_action,
+        report.counts.users_imported,
+        report.counts.officers_imported,
+        report.media_files_copied,
+        report.backups_copied
+    );
+    logger::info(format!("Legacy migration finished: {}", details));
+    activity_log::record_event(
+        activity_log::EVENT_LEGACY_MIGRATION,
+        None,
+        None,
+        Some(&details),
+    );
+
+    Ok(report)
+}
+
+/// Startup hook: migrate legacy data when nothing would have to be merged
+/// Returns None when there was nothing to do
+pub fn migrate_legacy_data_on_startup() -> Result<Option<LegacyMigrationReport>, String> {
+    // A USB install must not pull in whatever this machine has in app data
+    if storage_paths::is_portable_mode()? {
+        return Ok(None);
+    }
+    migrate_legacy_data_on_startup_in(&storage_paths::get_legacy_app_root()?)
+}
+
+fn migrate_legacy_data_on_startup_in(
+    legacy_root: &Path,
+) -> Result<Option<LegacyMigrationReport>, String> {
+    let detected = detect_legacy_data_in(legacy_root);
+    if !detected.found {
+        return Ok(None);
+    }
+    // Any database file, even a damaged one, is left for a person to look at
+    if storage_paths::get_database_path()?.exists() {
+        logger::info(format!(
+            "Legacy data found in {}; use the migration assistant to merge it",
+            detected.legacy_path
+        ));
+        return Ok(None);
+    }
+    migrate_legacy_data_in(legacy_root).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use crate::test_support::TestEnvironment;
+    use rusqlite::params;
+    use tempfile::TempDir;
+
+    fn create_db(path: &Path, users: &[(&str, &str)]) {
+        let conn = Connection::open(path).expect("db should open");
+        apply_schema(&conn).expect("schema should apply");
+        for (username, email) in users {
+            conn.execute(
+                "INSERT INTO users (username, email, password_hash, full_name) VALUES (?, ?, 'h', ?)",
+                params![username, email, username],
+            )
+            .expect("user insert should succeed");
+        }
+    }
+
+    #[test]
+    fn test_detect_missing_legacy_dir() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let report = detect_legacy_data_in(&dir.path().join("pqs-rtn-tauri"));
+        assert!(!report.found);
+    }
+
+    #[test]
+    fn test_detect_legacy_data() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let root = dir.path();
+        create_db(&root.join("database.db"), &[("alice", "a@test.com")]);
+        fs::create_dir_all(root.join("media").join("avatars")).unwrap();
+        fs::write(root.join("media").join("avatars").join("a.png"), b"png").unwrap();
+        fs::create_dir_all(root.join("backups")).unwrap();
+        fs::write(root.join("backups").join("backup_1.json"), b"{}").unwrap();
+
+        let report = detect_legacy_data_in(root);
+
+        assert!(report.found);
+        let db = report.database.expect("database should be detected");
+        assert!(db.valid);
+        assert_eq!(db.user_count, 1);
+        assert_eq!(report.media_file_count, 1);
+        assert_eq!(report.backup_files, vec!["backup_1.json"]);
+    }
+
+    #[test]
+    fn test_merge_skips_existing_users() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let legacy = dir.path().join("legacy.db");
+        create_db(&legacy, &[("alice", "a@test.com"), ("bob", "b@test.com")]);
+        let target = dir.path().join("current.db");
+        create_db(&target, &[("alice", "alice@new.com")]);
+
+        let conn = Connection::open(&target).expect("db should open");
+        let counts = merge_legacy_database_with_conn(&conn, &legacy).expect("merge should succeed");
+
+        assert_eq!(counts.users_imported, 1);
+        assert_eq!(counts.users_skipped, 1);
+        let total: i64 = conn
+            .query_row("SELECT COUNT(*) FROM users", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn test_copy_missing_files_keeps_existing() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::create_dir_all(src.join("avatars")).unwrap();
+        fs::create_dir_all(dst.join("avatars")).unwrap();
+        fs::write(src.join("avatars").join("a.png"), b"old").unwrap();
+        fs::write(src.join("avatars").join("b.png"), b"new").unwrap();
+        fs::write(dst.join("avatars").join("a.png"), b"current").unwrap();
+
+        let copied = copy_missing_files(&src, &dst).expect("copy should succeed");
+
+        assert_eq!(copied, 1);
+        assert_eq!(
+            fs::read(dst.join("avatars").join("a.png")).unwrap(),
+            b"current"
+        );
+        assert!(dst.join("avatars").join("b.png").exists());
+    }
+
+    #[test]
+    fn test_startup_copies_legacy_data_into_a_fresh_install() {
+        let _env = TestEnvironment::without_database();
+        let dir = TempDir::new().expect("temp dir should be created");
+        let legacy = dir.path().join("pqs-rtn-tauri");
+        fs::create_dir_all(legacy.join("media").join("avatars")).unwrap();
+        create_db(&legacy.join("database.db"), &[("alice", "a@test.com")]);
+        fs::write(legacy.join("media").join("avatars").join("a.png"), b"png").unwrap();
+
+        let report = migrate_legacy_data_on_startup_in(&legacy)
+            .expect("migration should succeed")
+            .expect("fresh install should be migrated");
+
+        assert_eq!(report.database_action, "copied");
+        assert_eq!(report.counts.users_imported, 1);
+        assert_eq!(report.media_files_copied, 1);
+        assert!(storage_paths::get_database_path().unwrap().exists());
+        assert!(storage_paths::get_media_dir()
+            .unwrap()
+            .join("avatars")
+            .join("a.png")
+            .exists());
+        assert!(!legacy.exists());
+    }
+
+    #[test]
+    fn test_startup_leaves_legacy_data_when_a_database_exists() {
+        let _env = TestEnvironment::with_temp_dir();
+        let dir = TempDir::new().expect("temp dir should be created");
+        let legacy = dir.path().join("pqs-rtn-tauri");
+        fs::create_dir_all(&legacy).unwrap();
+        create_db(&legacy.join("database.db"), &[("alice", "a@test.com")]);
+
+        let report = migrate_legacy_data_on_startup_in(&legacy).expect("check should succeed");
+
+        assert!(report.is_none());
+        assert!(legacy.join("database.db").exists());
+        let conn = database::get_connection_safe().unwrap();
+        let alice: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM users WHERE username = 'alice'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(alice, 0);
+    }
+}
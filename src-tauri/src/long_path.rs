@@ -0,0 +1,146 @@
+//! Extended-length paths for Windows file operations
+//!
+//! Destinations picked in the file dialog can be deep inside shared drives
+//! and named in Thai, where each character is one UTF-16 unit but several
+//! bytes; such paths easily pass the 260-unit MAX_PATH limit of the plain
+//! Win32 calls. `for_io` turns an absolute path into its `\\?\` form, which
+//! has no such limit, before it reaches `fs`. The `\\?\` form is passed to
+//! the file system as is, so `extended_length` also does the clean-up
+//! Windows would otherwise do: `/` becomes `\`, empty and `.` components
+//! are dropped and `..` removes the component before it, stopping at the
+//! drive or at `\\server\share` as Windows does. Paths are only converted for I/O; messages and
+//! stored paths keep the form the user chose. Elsewhere `for_io` returns the
+//! path unchanged.
+
+// The conversion is only called on Windows; its tests run everywhere
+#![cfg_attr(not(windows), allow(dead_code))]
+
+use std::path::{Path, PathBuf};
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const DEVICE_PREFIX: &str = r"\\.\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+/// Components of `rest` with `.` and `..` resolved lexically; `..` never
+/// removes the first `root_len` components. None when `..` appears inside
+/// those root components themselves (`\\server\..\x`).
+fn join_components(rest: &str, root_len: usize) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in rest.split(['\\', '/']) {
+        match part {
+            "" | "." => {}
+            ".." if parts.len() < root_len => return None,
+            ".." => {
+                if parts.len() > root_len {
+                    parts.pop();
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("\\"))
+}
+
+/// The `\\?\` form of a Windows path such as `D:\สำรองข้อมูล\a.zip` or
+/// `\\server\share\a.zip`; None for relative paths and for paths that are
+/// already verbatim or device paths
+pub fn extended_length(path: &str) -> Option<String> {
+    if path.starts_with(VERBATIM_PREFIX) || path.starts_with(DEVICE_PREFIX) {
+        return None;
+    }
+
+    let bytes = path.as_bytes();
+    let is_separator = |b: u8| b == b'\\' || b == b'/';
+
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        if !is_separator(bytes[2]) {
+            // "C:name" is relative to the drive's current directory
+            return None;
+        }
+        let drive = path[..2].to_ascii_uppercase();
+        return Some(format!(
+            "{}{}\\{}",
+            VERBATIM_PREFIX,
+            drive,
+            join_components(&path[3..], 0)?
+        ));
+    }
+
+    if bytes.len() > 2 && is_separator(bytes[0]) && is_separator(bytes[1]) {
+        let share = join_components(&path[2..], 2)?;
+        // Needs at least server and share
+        if share.split('\\').count() < 2 {
+            return None;
+        }
+        return Some(format!("{}{}", VERBATIM_UNC_PREFIX, share));
+    }
+
+    None
+}
+
+/// `path` in the form to hand to `fs` calls
+#[cfg(windows)]
+pub fn for_io(path: &Path) -> PathBuf {
+    match path.to_str().and_then(extended_length) {
+        Some(extended) => PathBuf::from(extended),
+        None => path.to_path_buf(),
+    }
+}
+
+/// `path` in the form to hand to `fs` calls
+#[cfg(not(windows))]
+pub fn for_io(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extended_length_forms() {
+        assert_eq!(
+            extended_length(r"d:\สำรองข้อมูล/./ภาพ\\a.zip").as_deref(),
+            Some(r"\\?\D:\สำรองข้อมูล\ภาพ\a.zip")
+        );
+        assert_eq!(
+            extended_length(r"\\nas01\งานธุรการ\backup.zip").as_deref(),
+            Some(r"\\?\UNC\nas01\งานธุรการ\backup.zip")
+        );
+        assert_eq!(extended_length(r"C:\").as_deref(), Some(r"\\?\C:\"));
+        assert_eq!(
+            extended_length(r"C:\งาน\เก่า\..\ใหม่\.\a.zip").as_deref(),
+            Some(r"\\?\C:\งาน\ใหม่\a.zip")
+        );
+        // `..` stops at the drive and at the share, as in plain paths
+        assert_eq!(
+            extended_length(r"C:\..\..\a.zip").as_deref(),
+            Some(r"\\?\C:\a.zip")
+        );
+        assert_eq!(
+            extended_length(r"\\nas01\share\..\..\a.zip").as_deref(),
+            Some(r"\\?\UNC\nas01\share\a.zip")
+        );
+        assert_eq!(extended_length(r"\\nas01\..\share\a.zip"), None);
+
+        assert_eq!(extended_length(r"\\?\C:\already"), None);
+        assert_eq!(extended_length(r"\\.\pipe\x"), None);
+        assert_eq!(extended_length(r"C:relative"), None);
+        assert_eq!(extended_length(r"relative\dir"), None);
+        assert_eq!(extended_length(r"\\server"), None);
+        assert_eq!(extended_length("/home/user"), None);
+    }
+
+    #[test]
+    fn test_long_thai_path_keeps_every_component() {
+        let folder = "เอกสารสำรองข้อมูลกำลังพล";
+        let deep: Vec<&str> = std::iter::repeat_n(folder, 12).collect();
+        let path = format!(r"E:\{}\backup.zip", deep.join("\\"));
+        assert!(path.encode_utf16().count() > 260);
+
+        let extended = extended_length(&path).expect("drive path should convert");
+        assert!(extended.starts_with(r"\\?\E:\"));
+        assert_eq!(extended.matches(folder).count(), 12);
+        assert!(extended.ends_with(r"\backup.zip"));
+    }
+}
@@ -2,24 +2,98 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 // Removed unused imports
-use tauri::Manager;
+use tauri::{ClipboardManager, Manager};
 
 // Database module
+mod activity_log;
+mod admin_audit; // Audit trail of restores, imports, deletions and role changes
+mod admin_password; // Seeded admin password rotation + startup warning
+mod auth; // Argon2id/bcrypt password hashes with rehash on login
+mod avatar_approval; // Review queue for newly uploaded photos
+mod avatar_audit; // Optional audit trail of personnel photo reads
+mod avatar_export; // Bulk avatar zip for printing services
+mod avatar_policy; // Configurable avatar size/format/dimension limits
+mod avatar_protocol; // avatar:// scheme with ETag/Last-Modified caching
+mod backup_catalog; // Backup directory listing kept across manual file moves
+mod backup_compat; // Pre-restore format/schema compatibility check
+mod backup_encryption; // AES-256-GCM password-protected hybrid backups
 mod backup_manager;
+mod backup_notify; // Daily backup summary by e-mail or LINE
+mod backup_results; // Structured payloads of backup/restore/delete commands
+mod backup_sandbox; // Read-only inspection of a backup in a temp directory
+mod backup_volumes; // Hybrid backups split into FAT32-sized volumes
+mod change_log; // Row-level change events + NDJSON changeset export
+mod chunked_upload; // Avatar uploads too large for one command payload
+mod contact_sheet; // Printable avatar grid with names and ranks
 mod content_database; // Separate content database
+mod dashboard;
 mod database;
 mod database_backup;
 mod database_export;
+mod dataset_pack; // Signed starter packs of officers/ranks/units/positions
+mod db_lock; // Lock-holder / stale journal diagnostics
+mod db_maintenance; // Idle-time PRAGMA optimize / vacuum / WAL checkpoint
+mod disk_space; // Free-space pre-flight for backups/imports/media
+mod error_codes; // Coded error prefixes the UI can match on
+mod export_encryption; // Password-protected zip exports
+mod file_dialogs; // Native save/open dialogs returning checked backup paths
 mod file_manager;
+mod file_transaction; // Staged file writes promoted after DB commit
 mod hybrid_avatar;
 mod hybrid_backup; // New hybrid backup system
 mod hybrid_high_rank_avatar;
+mod idempotency; // Replay results of repeated create/save invocations
+mod ipc_guard; // Size limit for command payloads
+mod jobs; // Registry of cancellable long-running commands
+mod legacy_migration; // Import data left in the old pqs-rtn-tauri directory
 mod logger; // Logger system for conditional debug output
+mod long_path; // Extended-length (\\?\) paths for Windows file I/O
+mod maintenance_mode; // Read-only mode for manual fixes and scheduled backups
+mod media_budget; // Size budget warnings for avatars and attachments
+mod media_hooks; // Extension points in the avatar save/delete pipeline
+mod media_maintenance;
+mod media_watcher; // Detects media files changed outside the app
 mod migration_helper;
+mod officer_board; // Static officer page for the intranet web server
+mod officer_signature; // Signature blocks stamped onto generated reports
+mod operation_history; // Timed record of backups, restores, imports and cleanups
+mod password_hashing; // bcrypt with a configurable, calibrated cost
+mod password_reset; // Bulk temporary passwords for account refreshes
+mod pending_deletions; // Avatar files whose deletion failed, retried at startup
+mod permissions; // Role required per command, checked against the session
+mod photo_import; // Companion photo folder/zip for dataset imports
+mod photo_matching; // Bulk avatar assignment by photo filename
+mod progress; // Progress events and cancellation for long-running commands
+mod read_cache; // Short-lived cache for get_user_by_id and the officer list
+mod record_subscriptions; // record://<table>/<id> events for subscribed detail views
+mod restore_preview; // Dry-run comparison of a backup with the live data
+mod restore_snapshot; // Pre-restore snapshot rolled back when a restore fails
+mod safe_path; // Media paths confined to the media directory
+mod saved_views; // Named filter/sort views for the user list
+mod sessions; // Login session tokens with idle expiry
+mod settings; // JSON settings file + keyring secrets
+mod sftp_backup; // Remote backup destination over SFTP
+mod signature_pdf; // Signature blocks drawn onto existing PDF files
+mod sql_dump_import; // Filtered CREATE/INSERT import of .sql dumps
+mod startup_report; // app://startup-report summary of what setup did
+mod storage_paths; // Central resolver for database/media/backup locations
+mod task_scheduler; // Maintenance jobs run while the app is idle
+mod temp_space; // Per-operation temp dirs with startup cleanup
 mod universal_sqlite_backup; // Database migration utilities
+mod user_archive; // Inactive users moved to archive.db
+mod user_drafts; // Debounced auto-save of profile edits
+mod user_preferences; // Per-user zoom/theme/language
+mod user_query; // Filtered user lists + TSV for the clipboard
+mod user_restore; // Single-user restore from JSON/hybrid backups
+mod validation; // Typed input validators with field-level errors
+mod warm_up; // Background avatar/preview warm-up after restores
+mod watchdog; // Stall timeout for jobs stuck on unreachable drives
+mod workspaces; // Named data stores (one database + media per workspace)
 
 #[cfg(test)]
 mod test_helpers; // Test helper utilities
+#[cfg(test)]
+mod test_support; // Temp-dir / in-memory app environment for end-to-end tests
 
 // Re-export database structs
 pub use database::{HighRankingOfficer, User};
@@ -35,6 +109,29 @@ fn get_all_users() -> Result<Vec<User>, String> {
     database::get_all_users()
 }
 
+/// `sort_dir` is "asc" (default) or "desc"
+#[tauri::command]
+fn get_users_page(
+    offset: Option<u32>,
+    limit: Option<u32>,
+    sort_by: Option<String>,
+    sort_dir: Option<String>,
+    filters: Option<user_query::UserFilters>,
+) -> Result<user_query::UsersPage, String> {
+    let sort = user_query::UserSort::from_args(sort_by.as_deref(), sort_dir.as_deref())?;
+    user_query::query_users_page(
+        &filters.unwrap_or_default(),
+        &sort,
+        offset.unwrap_or(0),
+        limit.unwrap_or(user_query::DEFAULT_PAGE_SIZE),
+    )
+}
+
+#[tauri::command]
+fn count_users(filters: Option<user_query::UserFilters>) -> Result<i64, String> {
+    user_query::count_users(&filters.unwrap_or_default())
+}
+
 #[tauri::command]
 fn get_user_by_id(id: i32) -> Result<Option<User>, String> {
     database::get_user_by_id(id)
@@ -46,57 +143,486 @@ fn get_user_by_email(email: String) -> Result<Option<User>, String> {
 }
 
 #[tauri::command]
-fn create_user(
+#[allow(clippy::too_many_arguments)]
+async fn create_user(
     username: String,
     email: String,
     password: String,
     full_name: String,
+    full_name_en: Option<String>,
     rank: Option<String>,
     role: String,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
 ) -> Result<User, String> {
-    // Hash the password before storing
-    let password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST)
-        .map_err(|e| format!("Failed to hash password: {}", e))?;
+    let fields = validation::UserFields::parse(&username, &email, &full_name, Some(&password))?;
+    let role = validation::role("role", &role)?;
+    // Self-registration creates visitors; anything more is an administrator's call
+    if role > permissions::Role::Visitor {
+        permissions::require_role(
+            "create_user",
+            permissions::Role::Admin,
+            session_token.as_deref(),
+        )?;
+    }
 
-    database::create_user(
-        &username,
-        &email,
-        &password_hash,
-        &full_name,
-        rank.as_deref(),
-        &role,
-    )
+    // Hashing takes hundreds of milliseconds; keep it off the IPC thread
+    tauri::async_runtime::spawn_blocking(move || {
+        idempotency::run_once(
+            "create_user",
+            session_token.as_deref(),
+            idempotency_key.as_deref(),
+            || {
+                let password_hash = auth::hash_password(&password)?;
+
+                database::create_user(
+                    fields.username.as_str(),
+                    fields.email.as_str(),
+                    &password_hash,
+                    &fields.full_name,
+                    full_name_en.as_deref(),
+                    rank.as_deref(),
+                    role.as_str(),
+                )
+            },
+        )
+    })
+    .await
+    .map_err(|e| format!("Create user task failed: {}", e))?
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn update_user(
     id: i32,
     username: String,
     email: String,
     password_hash: String,
     full_name: String,
+    full_name_en: Option<String>,
     rank: Option<String>,
     role: String,
+    row_version: Option<i64>,
+    session_token: Option<String>,
 ) -> Result<User, String> {
-    database::update_user(
+    let fields = validation::UserFields::parse(&username, &email, &full_name, None)?;
+    let role = validation::role("role", &role)?.as_str();
+    let previous_role = database::get_user_by_id(id)?.map(|user| user.role);
+    let role_changed = previous_role
+        .as_deref()
+        .is_some_and(|previous| previous != role);
+    if role_changed {
+        permissions::require_role(
+            "update_user",
+            permissions::Role::Admin,
+            session_token.as_deref(),
+        )?;
+    } else {
+        permissions::require_self_or_role(
+            "update_user",
+            id,
+            permissions::Role::Admin,
+            session_token.as_deref(),
+        )?;
+    }
+
+    let result = database::update_user(
         id,
-        &username,
-        &email,
+        fields.username.as_str(),
+        fields.email.as_str(),
         &password_hash,
-        &full_name,
+        &fields.full_name,
+        full_name_en.as_deref(),
         rank.as_deref(),
-        &role,
+        role,
+        row_version,
+    );
+    if result.is_ok() {
+        if let Err(e) = user_drafts::discard_user_draft(id) {
+            logger::warn(format!("Failed to discard draft of user {}: {}", id, e));
+        }
+    }
+    // Only role changes are administrative; ordinary edits are not audited
+    match previous_role {
+        Some(previous_role) if previous_role != role => {
+            let session = admin_audit::acting_session(session_token.as_deref())?;
+            admin_audit::audited(
+                "update_user",
+                session.as_ref(),
+                serde_json::json!({ "id": id, "previous_role": previous_role, "role": role }),
+                result,
+            )
+        }
+        _ => result,
+    }
+}
+
+#[tauri::command]
+fn update_user_service_number(
+    id: i32,
+    service_number: Option<String>,
+    row_version: Option<i64>,
+    session_token: Option<String>,
+) -> Result<User, String> {
+    permissions::require_self_or_role(
+        "update_user_service_number",
+        id,
+        permissions::Role::Admin,
+        session_token.as_deref(),
+    )?;
+    database::update_user_service_number(id, service_number.as_deref(), row_version)
+}
+
+#[tauri::command]
+fn update_user_full_name_en(
+    id: i32,
+    full_name_en: Option<String>,
+    row_version: Option<i64>,
+    session_token: Option<String>,
+) -> Result<User, String> {
+    permissions::require_self_or_role(
+        "update_user_full_name_en",
+        id,
+        permissions::Role::Admin,
+        session_token.as_deref(),
+    )?;
+    database::update_user_full_name_en(id, full_name_en.as_deref(), row_version)
+}
+
+#[tauri::command]
+fn delete_user(id: i32, session_token: Option<String>) -> Result<bool, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    admin_audit::audited(
+        "delete_user",
+        session.as_ref(),
+        serde_json::json!({ "id": id }),
+        database::delete_user(id),
+    )
+}
+
+/// Verification runs off the main thread like every other hashing command
+#[tauri::command]
+async fn authenticate_user(
+    username_or_email: String,
+    password: String,
+) -> Result<Option<User>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        database::authenticate_user(&username_or_email, &password)
+    })
+    .await
+    .map_err(|e| format!("Sign-in task failed: {}", e))?
+}
+
+/// Sessions end with `logout`; `authenticate_user` keeps nothing to forget
+#[tauri::command]
+fn sign_out() {}
+
+/// Like `authenticate_user`, plus a session token for `validate_session`
+#[tauri::command]
+async fn login(
+    username_or_email: String,
+    password: String,
+) -> Result<Option<sessions::SessionLogin>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(user) = database::authenticate_user(&username_or_email, &password)? else {
+            return Ok(None);
+        };
+        sessions::create_session(user).map(Some)
+    })
+    .await
+    .map_err(|e| format!("Sign-in task failed: {}", e))?
+}
+
+/// The signed-in user, or None once the session expired or ended
+#[tauri::command]
+fn validate_session(token: String) -> Result<Option<User>, String> {
+    sessions::validate_session(&token)
+}
+
+/// The caller's own password; the only command a session with a temporary
+/// password may run
+#[tauri::command]
+async fn change_password(
+    current_password: String,
+    new_password: String,
+    session_token: Option<String>,
+) -> Result<(), String> {
+    let user_id = permissions::caller(session_token.as_deref())?
+        .and_then(|caller| caller.user_id)
+        .ok_or("Sign in to change your password")?;
+    // Argon2id (or bcrypt) verification and hashing take a while
+    tauri::async_runtime::spawn_blocking(move || {
+        password_reset::change_own_password(user_id, &current_password, &new_password)
+    })
+    .await
+    .map_err(|e| format!("Password change task failed: {}", e))?
+}
+
+#[tauri::command]
+fn logout(token: String) -> Result<bool, String> {
+    sessions::delete_session(&token)
+}
+
+#[tauri::command]
+fn get_admin_action_log(
+    filter: Option<admin_audit::AdminActionFilter>,
+) -> Result<Vec<admin_audit::AdminAction>, String> {
+    admin_audit::query_admin_actions(&filter.unwrap_or_default())
+}
+
+/// TSV of the filtered users on the OS clipboard, for pasting into spreadsheets
+#[tauri::command]
+fn copy_users_to_clipboard(
+    app: tauri::AppHandle,
+    filters: Option<user_query::UserFilters>,
+    columns: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let users = user_query::query_users(&filters.unwrap_or_default())?;
+    let tsv = user_query::users_to_tsv(&users, columns.as_deref())?;
+    app.clipboard_manager()
+        .write_text(tsv)
+        .map_err(|e| format!("Failed to write to clipboard: {}", e))?;
+    Ok(users.len())
+}
+
+// Saved user list views
+#[tauri::command]
+fn list_saved_views() -> Result<Vec<saved_views::SavedView>, String> {
+    saved_views::list_saved_views()
+}
+
+#[tauri::command]
+fn create_saved_view(
+    view: saved_views::SavedViewInput,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
+) -> Result<saved_views::SavedView, String> {
+    // The owner is whoever is signed in, never a client-supplied id
+    let created_by = permissions::caller_id(session_token.as_deref())?;
+    idempotency::run_once(
+        "create_saved_view",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || saved_views::create_saved_view(&view, created_by),
+    )
+}
+
+#[tauri::command]
+fn update_saved_view(
+    id: i32,
+    view: saved_views::SavedViewInput,
+) -> Result<saved_views::SavedView, String> {
+    saved_views::update_saved_view(id, &view)
+}
+
+#[tauri::command]
+fn delete_saved_view(id: i32) -> Result<bool, String> {
+    saved_views::delete_saved_view(id)
+}
+
+#[tauri::command]
+fn run_saved_view(id: i32) -> Result<Vec<User>, String> {
+    saved_views::run_saved_view(id)
+}
+
+/// Drafts of someone else's profile follow the rule of `update_user`
+fn require_draft_access(
+    command: &str,
+    user_id: i32,
+    session_token: Option<&str>,
+) -> Result<(), String> {
+    permissions::require_self_or_role(command, user_id, permissions::Role::Admin, session_token)
+}
+
+#[tauri::command]
+fn autosave_user_draft(
+    user_id: i32,
+    partial: serde_json::Value,
+    session_token: Option<String>,
+) -> Result<user_drafts::AutosaveStatus, String> {
+    require_draft_access("autosave_user_draft", user_id, session_token.as_deref())?;
+    user_drafts::autosave_user_draft(user_id, &partial)
+}
+
+#[tauri::command]
+fn get_user_draft(
+    user_id: i32,
+    session_token: Option<String>,
+) -> Result<Option<user_drafts::UserDraft>, String> {
+    require_draft_access("get_user_draft", user_id, session_token.as_deref())?;
+    user_drafts::get_user_draft(user_id)
+}
+
+#[tauri::command]
+fn discard_user_draft(user_id: i32, session_token: Option<String>) -> Result<bool, String> {
+    require_draft_access("discard_user_draft", user_id, session_token.as_deref())?;
+    user_drafts::discard_user_draft(user_id)
+}
+
+#[tauri::command]
+fn get_user_preferences(user_id: i32) -> Result<user_preferences::UserPreferences, String> {
+    user_preferences::get_user_preferences(user_id)
+}
+
+#[tauri::command]
+fn set_user_preferences(
+    user_id: i32,
+    preferences: user_preferences::UserPreferences,
+    session_token: Option<String>,
+) -> Result<(), String> {
+    permissions::require_self_or_role(
+        "set_user_preferences",
+        user_id,
+        permissions::Role::Admin,
+        session_token.as_deref(),
+    )?;
+    user_preferences::set_user_preferences(user_id, &preferences)
+}
+
+/// Called after login so each person gets their own zoom/theme/language back
+#[tauri::command]
+async fn apply_user_preferences(
+    window: tauri::Window,
+    user_id: i32,
+) -> Result<user_preferences::UserPreferences, String> {
+    let preferences = user_preferences::get_user_preferences(user_id)?;
+    window
+        .eval(&user_preferences::preferences_script(&preferences))
+        .map_err(|e| format!("Failed to apply user preferences: {}", e))?;
+    Ok(preferences)
+}
+
+#[tauri::command]
+async fn rotate_admin_password(
+    current_password: String,
+    new_password: String,
+) -> Result<(), String> {
+    // Argon2id (or bcrypt) verification and hashing take a while
+    tauri::async_runtime::spawn_blocking(move || {
+        invalidate_after(admin_password::rotate_admin_password(
+            &current_password,
+            &new_password,
+        ))
+    })
+    .await
+    .map_err(|e| format!("Password rotation task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn reset_passwords_bulk(
+    user_ids: Vec<i32>,
+    encryption: Option<export_encryption::ExportEncryption>,
+    session_token: Option<String>,
+) -> Result<password_reset::BulkPasswordReset, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let passphrase = encryption
+        .as_ref()
+        .map(export_encryption::resolve_passphrase)
+        .transpose()?;
+    // One password hash per user
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = admin_audit::audited(
+            "reset_passwords_bulk",
+            session.as_ref(),
+            serde_json::json!({ "user_ids": user_ids, "encrypted": passphrase.is_some() }),
+            password_reset::reset_passwords_bulk(&user_ids, passphrase.as_deref()),
+        );
+        invalidate_after(result)
+    })
+    .await
+    .map_err(|e| format!("Password reset task failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_dashboard_stats() -> Result<dashboard::DashboardStats, String> {
+    dashboard::get_dashboard_stats()
+}
+
+#[tauri::command]
+fn get_startup_report() -> Option<startup_report::StartupReport> {
+    startup_report::get_startup_report()
+}
+
+#[tauri::command]
+fn diagnose_database_lock() -> Result<db_lock::LockDiagnostics, String> {
+    db_lock::diagnose_database_lock()
+}
+
+#[tauri::command]
+fn run_database_maintenance() -> Result<db_maintenance::MaintenanceResult, String> {
+    db_maintenance::run_maintenance()
+}
+
+#[tauri::command]
+fn list_scheduled_tasks(
+    scheduler: tauri::State<'_, task_scheduler::TaskScheduler>,
+) -> Result<Vec<task_scheduler::ScheduledTaskInfo>, String> {
+    scheduler.list_tasks(std::time::Duration::from_secs(db_maintenance::idle_secs()))
+}
+
+#[tauri::command]
+async fn run_scheduled_task(
+    scheduler: tauri::State<'_, task_scheduler::TaskScheduler>,
+    name: String,
+) -> Result<task_scheduler::TaskRun, String> {
+    let scheduler = scheduler.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        invalidate_after(scheduler.run_task(&name, task_scheduler::TaskTrigger::Manual))
+    })
+    .await
+    .map_err(|e| format!("Scheduled task failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_maintenance_mode() -> Result<maintenance_mode::MaintenanceMode, String> {
+    maintenance_mode::get_maintenance_mode()
+}
+
+#[tauri::command]
+fn set_maintenance_mode(
+    enabled: bool,
+    message: Option<String>,
+) -> Result<maintenance_mode::MaintenanceMode, String> {
+    maintenance_mode::set_maintenance_mode(enabled, message)
+}
+
+#[tauri::command]
+fn get_maintenance_status() -> Result<db_maintenance::MaintenanceStatus, String> {
+    db_maintenance::get_maintenance_status()
+}
+
+#[tauri::command]
+fn restore_user_from_backup(
+    filename: String,
+    username: String,
+    session_token: Option<String>,
+) -> Result<user_restore::RestoredUser, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let _exclusive = watchdog::begin_exclusive("restore_user_from_backup")?;
+    admin_audit::audited(
+        "restore_user_from_backup",
+        session.as_ref(),
+        serde_json::json!({ "filename": filename, "username": username }),
+        operation_history::tracked(
+            operation_history::OPERATION_RESTORE,
+            "restore_user_from_backup",
+            Some(filename.as_str()),
+            || user_restore::restore_user_from_backup(&filename, &username),
+        ),
     )
 }
 
 #[tauri::command]
-fn delete_user(id: i32) -> Result<bool, String> {
-    database::delete_user(id)
+fn archive_users(
+    before_date: Option<String>,
+    ids: Option<Vec<i32>>,
+) -> Result<user_archive::ArchiveReport, String> {
+    user_archive::archive_users(before_date.as_deref(), ids.as_deref())
 }
 
 #[tauri::command]
-fn authenticate_user(username_or_email: String, password: String) -> Result<Option<User>, String> {
-    database::authenticate_user(&username_or_email, &password)
+fn search_archive(query: String) -> Result<Vec<User>, String> {
+    user_archive::search_archive(&query)
 }
 
 // Database initialization is handled by Tauri setup
@@ -172,34 +698,210 @@ fn get_all_high_ranking_officers() -> Result<Vec<HighRankingOfficer>, String> {
 fn update_high_ranking_officer(
     id: i32,
     thai_name: String,
+    full_name_en: Option<String>,
     position_thai: String,
     position_english: String,
     order_index: i32,
+    row_version: Option<i64>,
 ) -> Result<HighRankingOfficer, String> {
     database::update_high_ranking_officer(
         id,
         &thai_name,
+        full_name_en.as_deref(),
         &position_thai,
         &position_english,
         order_index,
+        row_version,
+    )
+}
+
+#[tauri::command]
+fn update_officer_full_name_en(
+    id: i32,
+    full_name_en: Option<String>,
+    row_version: Option<i64>,
+) -> Result<HighRankingOfficer, String> {
+    database::update_officer_full_name_en(id, full_name_en.as_deref(), row_version)
+}
+
+/// Drag-and-drop order of the officer list, first officer first
+#[tauri::command]
+fn reorder_high_ranking_officers(
+    ids_in_order: Vec<i32>,
+) -> Result<Vec<HighRankingOfficer>, String> {
+    database::reorder_high_ranking_officers(&ids_in_order)
+}
+
+#[tauri::command]
+fn set_officer_display_on_board(
+    officer_id: i32,
+    display_on_board: bool,
+) -> Result<HighRankingOfficer, String> {
+    database::set_officer_board_flag(
+        officer_id,
+        database::OfficerBoardFlag::DisplayOnBoard,
+        display_on_board,
     )
 }
 
 #[tauri::command]
-fn hash_password(password: String) -> Result<String, String> {
-    bcrypt::hash(&password, bcrypt::DEFAULT_COST)
-        .map_err(|e| format!("Failed to hash password: {}", e))
+fn set_officer_avatar_visible(
+    officer_id: i32,
+    avatar_visible: bool,
+) -> Result<HighRankingOfficer, String> {
+    database::set_officer_board_flag(
+        officer_id,
+        database::OfficerBoardFlag::AvatarVisible,
+        avatar_visible,
+    )
+}
+
+#[tauri::command]
+async fn hash_password(password: String) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || auth::hash_password(&password))
+        .await
+        .map_err(|e| format!("Hash task failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_password_hash_scheme() -> settings::HashScheme {
+    auth::configured_scheme()
+}
+
+/// Existing hashes move to the new scheme as their users sign in
+#[tauri::command]
+fn set_password_hash_scheme(scheme: settings::HashScheme) -> Result<(), String> {
+    auth::set_scheme(scheme)
+}
+
+/// Raise the hash target; weaker hashes are replaced as their users sign in
+#[tauri::command]
+async fn rehash_all_passwords(
+    window: tauri::Window,
+    target: auth::HashTarget,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<auth::RehashReport, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let started_by = session.as_ref().and_then(|session| session.user_id);
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("password-rehash"));
+    let details = serde_json::json!({ "target": target });
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window, None);
+        invalidate_after(jobs::run_job(
+            &job_id,
+            "password-rehash",
+            started_by,
+            sink,
+            |progress| auth::rehash_all_passwords(target, progress),
+        ))
+    })
+    .await
+    .map_err(|e| format!("Password rehash task failed: {}", e))?;
+    admin_audit::audited("rehash_all_passwords", session.as_ref(), details, result)
+}
+
+#[tauri::command]
+fn get_password_hash_cost() -> u32 {
+    password_hashing::configured_cost()
+}
+
+#[tauri::command]
+fn set_password_hash_cost(cost: u32) -> Result<(), String> {
+    password_hashing::set_cost(cost)
+}
+
+/// Time the configured scheme on this machine and pick the strongest cost
+/// under `target_ms`
+#[tauri::command]
+async fn calibrate_password_hash_cost(
+    target_ms: Option<u64>,
+    apply: Option<bool>,
+) -> Result<auth::HashCalibration, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        auth::calibrate_hash_target(
+            target_ms.unwrap_or(password_hashing::DEFAULT_TARGET_MS),
+            apply.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(|e| format!("Calibration task failed: {}", e))?
 }
 
 // Database backup/restore commands
 #[tauri::command]
-fn create_database_backup() -> Result<String, String> {
-    database_backup::create_backup()
+fn create_database_backup(
+    idempotency_key: Option<String>,
+    changed_since: Option<String>,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupCreated, String> {
+    idempotency::run_once(
+        "create_database_backup",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || {
+            backup_notify::record_backup(
+                backup_results::BackupKind::Json,
+                operation_history::tracked(
+                    operation_history::OPERATION_BACKUP,
+                    "create_database_backup",
+                    None,
+                    || database_backup::create_backup(changed_since.as_deref()),
+                ),
+            )
+        },
+    )
 }
 
 #[tauri::command]
-fn restore_database_backup(backup_filename: String) -> Result<String, String> {
-    database_backup::restore_backup(&backup_filename)
+fn restore_database_backup(
+    window: tauri::Window,
+    backup_filename: String,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupRestored, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let _exclusive = watchdog::begin_exclusive("restore_database_backup")?;
+    let result = admin_audit::audited(
+        "restore_database_backup",
+        session.as_ref(),
+        serde_json::json!({ "backup_filename": backup_filename }),
+        backup_notify::record_restore(
+            &backup_filename,
+            operation_history::tracked(
+                operation_history::OPERATION_RESTORE,
+                "restore_database_backup",
+                Some(backup_filename.as_str()),
+                || database_backup::restore_backup(&backup_filename, caller),
+            ),
+        ),
+    );
+    warm_up_after(window, result)
+}
+
+#[tauri::command]
+async fn import_sql_dump(
+    path: String,
+    skip_disallowed: bool,
+    session_token: Option<String>,
+) -> Result<sql_dump_import::SqlImportReport, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let _exclusive = watchdog::begin_exclusive("import_sql_dump")?;
+        invalidate_after(admin_audit::audited(
+            "import_sql_dump",
+            session.as_ref(),
+            serde_json::json!({ "path": path, "skip_disallowed": skip_disallowed }),
+            operation_history::tracked(
+                operation_history::OPERATION_IMPORT,
+                "import_sql_dump",
+                Some(path.as_str()),
+                || sql_dump_import::import_sql_dump(&path, skip_disallowed),
+            ),
+        ))
+    })
+    .await
+    .map_err(|e| format!("SQL import task failed: {}", e))?
 }
 
 #[tauri::command]
@@ -208,13 +910,55 @@ fn list_database_backups() -> Result<Vec<database_backup::BackupInfo>, String> {
 }
 
 #[tauri::command]
-fn delete_database_backup(backup_filename: String) -> Result<String, String> {
+fn delete_database_backup(
+    backup_filename: String,
+) -> Result<backup_results::BackupDeleted, String> {
     database_backup::delete_backup(&backup_filename)
 }
 
 // Database export/import commands
+/// Sink forwarding job progress as `job://progress` plus the command's own event
+fn window_job_sink(window: tauri::Window, event: Option<&'static str>) -> progress::ProgressSink {
+    Box::new(move |payload| {
+        for event in std::iter::once(jobs::JOB_PROGRESS_EVENT).chain(event) {
+            if let Err(e) = window.emit(event, payload) {
+                logger::warn(format!("Failed to emit {}: {}", event, e));
+            }
+        }
+    })
+}
+
+/// Drop cached reads once a write running off the IPC thread has finished;
+/// invalidating when the command is dispatched would let reads made during
+/// the write be cached again
+fn invalidate_after<T>(result: Result<T, String>) -> Result<T, String> {
+    read_cache::invalidate();
+    result
+}
+
+/// Queue the post-restore warm-up (avatars, read cache, previews) on success
+fn warm_up_after<T>(window: tauri::Window, result: Result<T, String>) -> Result<T, String> {
+    let result = invalidate_after(result);
+    if result.is_ok() {
+        warm_up::queue_warm_up(window_job_sink(window, None));
+    }
+    result
+}
+
 #[tauri::command]
-fn export_database(format: String) -> Result<String, String> {
+async fn export_database(
+    window: tauri::Window,
+    format: String,
+    encryption: Option<export_encryption::ExportEncryption>,
+    operation_id: Option<String>,
+    changed_since: Option<String>,
+    session_token: Option<String>,
+) -> Result<String, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    let passphrase = encryption
+        .as_ref()
+        .map(export_encryption::resolve_passphrase)
+        .transpose()?;
     let export_format = match format.to_lowercase().as_str() {
         "json" => database_export::ExportFormat::Json,
         "csv" => database_export::ExportFormat::Csv,
@@ -222,12 +966,170 @@ fn export_database(format: String) -> Result<String, String> {
         _ => return Err("Unsupported export format. Use: json, csv, or sql".to_string()),
     };
 
-    database_export::export_database(export_format)
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("export"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window, Some(progress::EXPORT_PROGRESS_EVENT));
+        // Export directories are often network shares
+        watchdog::run_watched_job(&job_id, "export", started_by, sink, move |progress| {
+            database_export::export_database_with_progress(
+                export_format,
+                passphrase.as_deref(),
+                changed_since.as_deref(),
+                progress,
+            )
+        })
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))?
+}
+
+/// Remember the passphrase for exports made with `use_keyring`
+#[tauri::command]
+fn store_export_passphrase(passphrase: String) -> Result<(), String> {
+    export_encryption::store_export_passphrase(&passphrase)
+}
+
+#[tauri::command]
+fn has_export_passphrase() -> Result<bool, String> {
+    export_encryption::has_export_passphrase()
+}
+
+#[tauri::command]
+async fn import_database(
+    window: tauri::Window,
+    import_filename: String,
+    photo_source: Option<String>,
+    operation_id: Option<String>,
+    import_mode: Option<database_export::ImportMode>,
+    session_token: Option<String>,
+) -> Result<String, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    let started_by = caller.and_then(|caller| caller.user_id);
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let import_mode = import_mode.unwrap_or_default();
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("import"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window.clone(), Some(progress::IMPORT_PROGRESS_EVENT));
+        let filename = import_filename.clone();
+        let source = photo_source.clone();
+        let result = operation_history::tracked(
+            operation_history::OPERATION_IMPORT,
+            "import_database",
+            Some(import_filename.as_str()),
+            || {
+                watchdog::run_exclusive_watched_job(
+                    &job_id,
+                    "import",
+                    started_by,
+                    sink,
+                    move |progress| {
+                        let message = database_export::import_database_with_progress(
+                            &filename,
+                            import_mode,
+                            progress,
+                        )?;
+                        // Photos only once the rows they belong to are committed
+                        let Some(source) = source else {
+                            return Ok(message);
+                        };
+                        let photos =
+                            photo_import::import_referenced_photos(&source, progress, caller)?;
+                        Ok(format!(
+                            "{} (photos linked: {}, already present: {}, missing: {}, refused: {})",
+                            message,
+                            photos.linked,
+                            photos.already_present,
+                            photos.missing.len(),
+                            photos.failed.len()
+                        ))
+                    },
+                )
+            },
+        );
+        let result = admin_audit::audited(
+            "import_database",
+            session.as_ref(),
+            serde_json::json!({
+                "import_filename": import_filename,
+                "photo_source": photo_source,
+                "import_mode": import_mode,
+            }),
+            result,
+        );
+        warm_up_after(window, result)
+    })
+    .await
+    .map_err(|e| format!("Import task failed: {}", e))?
+}
+
+/// NDJSON of the rows changed after `since` (unix milliseconds), for syncing another office
+#[tauri::command]
+fn export_changes_since(since: i64) -> Result<change_log::ChangesetInfo, String> {
+    change_log::export_changes_since(since)
+}
+
+#[tauri::command]
+fn apply_changeset(
+    path: String,
+    conflict_strategy: change_log::ConflictStrategy,
+    session_token: Option<String>,
+) -> Result<change_log::ChangesetApplyReport, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let params = serde_json::json!({ "path": path, "conflict_strategy": conflict_strategy });
+    admin_audit::audited(
+        "apply_changeset",
+        session.as_ref(),
+        params,
+        change_log::apply_changeset(std::path::Path::new(&path), conflict_strategy),
+    )
+}
+
+/// Live updates for one row; returns the event name to listen for
+#[tauri::command]
+fn subscribe_record(window: tauri::Window, table: String, id: i64) -> Result<String, String> {
+    record_subscriptions::subscribe(window.label(), &table, id)
+}
+
+#[tauri::command]
+fn unsubscribe_record(window: tauri::Window, table: String, id: i64) -> Result<bool, String> {
+    record_subscriptions::unsubscribe(window.label(), &table, id)
+}
+
+/// Full import inside a transaction that is always rolled back
+#[tauri::command]
+async fn rehearse_import_database(
+    window: tauri::Window,
+    import_filename: String,
+    operation_id: Option<String>,
+    import_mode: Option<database_export::ImportMode>,
+    session_token: Option<String>,
+) -> Result<database_export::ImportRehearsal, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    let import_mode = import_mode.unwrap_or_default();
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("import-rehearsal"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window, Some(progress::IMPORT_PROGRESS_EVENT));
+        jobs::run_job(&job_id, "import-rehearsal", started_by, sink, |progress| {
+            database_export::rehearse_import_with_progress(&import_filename, import_mode, progress)
+        })
+    })
+    .await
+    .map_err(|e| format!("Import task failed: {}", e))?
+}
+
+#[tauri::command]
+fn cancel_operation(operation_id: String, session_token: Option<String>) -> Result<bool, String> {
+    jobs::cancel_job(&operation_id, session_token.as_deref())
+}
+
+#[tauri::command]
+fn list_jobs() -> Result<Vec<jobs::JobInfo>, String> {
+    jobs::list_jobs()
 }
 
 #[tauri::command]
-fn import_database(import_filename: String) -> Result<String, String> {
-    database_export::import_database(&import_filename)
+fn cancel_job(id: String, session_token: Option<String>) -> Result<bool, String> {
+    jobs::cancel_job(&id, session_token.as_deref())
 }
 
 #[tauri::command]
@@ -242,26 +1144,254 @@ fn delete_database_export(export_filename: String) -> Result<String, String> {
     database_export::delete_export(&export_filename)
 }
 
+#[tauri::command]
+fn get_export_directory() -> Result<String, String> {
+    Ok(storage_paths::get_export_dir()?
+        .to_string_lossy()
+        .to_string())
+}
+
+#[tauri::command]
+fn get_storage_mode() -> Result<storage_paths::StorageInfo, String> {
+    storage_paths::get_storage_info()
+}
+
+/// None goes back to the default chain; applies after a restart
+#[tauri::command]
+fn set_storage_location(directory: Option<String>) -> Result<(), String> {
+    storage_paths::set_storage_location(directory.as_deref())
+}
+
+/// None resets to the default folder in Documents
+#[tauri::command]
+fn set_export_directory(directory: Option<String>) -> Result<String, String> {
+    Ok(storage_paths::set_export_directory(directory.as_deref())?
+        .to_string_lossy()
+        .to_string())
+}
+
+#[tauri::command]
+fn reveal_export_in_explorer(filename: String) -> Result<(), String> {
+    let path = database_export::export_file_path(&filename)?;
+    reveal_in_file_manager(&path)
+}
+
+/// Open the OS file manager with `path` selected
+fn reveal_in_file_manager(path: &std::path::Path) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    let result = std::process::Command::new("explorer")
+        .arg(format!("/select,{}", path.display()))
+        .spawn();
+    #[cfg(target_os = "macos")]
+    let result = std::process::Command::new("open")
+        .arg("-R")
+        .arg(path)
+        .spawn();
+    // Most Linux file managers cannot select a file; open its folder instead
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let result = std::process::Command::new("xdg-open")
+        .arg(path.parent().unwrap_or(path))
+        .spawn();
+
+    result
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open file manager: {}", e))
+}
+
 // Universal SQLite backup commands
 #[tauri::command]
-fn create_universal_sqlite_backup() -> Result<String, String> {
-    universal_sqlite_backup::create_universal_sqlite_backup()
+fn create_universal_sqlite_backup() -> Result<backup_results::BackupCreated, String> {
+    backup_notify::record_backup(
+        backup_results::BackupKind::Sqlite,
+        operation_history::tracked(
+            operation_history::OPERATION_BACKUP,
+            "create_universal_sqlite_backup",
+            None,
+            universal_sqlite_backup::create_universal_sqlite_backup,
+        ),
+    )
 }
 
 #[tauri::command]
-fn create_standard_sql_dump() -> Result<String, String> {
-    universal_sqlite_backup::create_standard_sql_dump()
+fn create_standard_sql_dump() -> Result<backup_results::BackupCreated, String> {
+    backup_notify::record_backup(
+        backup_results::BackupKind::SqlDump,
+        operation_history::tracked(
+            operation_history::OPERATION_BACKUP,
+            "create_standard_sql_dump",
+            None,
+            universal_sqlite_backup::create_standard_sql_dump,
+        ),
+    )
 }
 
 // Hybrid backup commands (Database + Media)
 #[tauri::command]
-fn create_hybrid_backup() -> Result<String, String> {
-    hybrid_backup::create_hybrid_backup()
+async fn create_hybrid_backup(
+    window: tauri::Window,
+    operation_id: Option<String>,
+    volume_size_mb: Option<u64>,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupCreated, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("backup"));
+    let max_volume_size = volume_size_mb.map(|mb| mb.saturating_mul(1024 * 1024));
+    tauri::async_runtime::spawn_blocking(move || {
+        backup_notify::record_backup(
+            backup_results::BackupKind::Hybrid,
+            operation_history::tracked(
+                operation_history::OPERATION_BACKUP,
+                "create_hybrid_backup",
+                None,
+                || {
+                    watchdog::run_watched_job(
+                        &job_id,
+                        "backup",
+                        started_by,
+                        window_job_sink(window, Some(progress::BACKUP_PROGRESS_EVENT)),
+                        move |progress| {
+                            hybrid_backup::create_hybrid_backup_with_progress(
+                                progress,
+                                max_volume_size,
+                            )
+                        },
+                    )
+                },
+            ),
+        )
+    })
+    .await
+    .map_err(|e| format!("Backup task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn import_hybrid_backup(
+    window: tauri::Window,
+    zip_path: String,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupRestored, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let started_by = session.as_ref().and_then(|session| session.user_id);
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("restore"));
+    // Extracting a large media library takes minutes; keep it off the IPC thread
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = zip_path.clone();
+        let result = operation_history::tracked(
+            operation_history::OPERATION_RESTORE,
+            "import_hybrid_backup",
+            Some(zip_path.as_str()),
+            || {
+                watchdog::run_exclusive_watched_job(
+                    &job_id,
+                    "restore",
+                    started_by,
+                    window_job_sink(window.clone(), Some(progress::BACKUP_PROGRESS_EVENT)),
+                    move |progress| hybrid_backup::import_backup_with_progress(&path, progress),
+                )
+            },
+        );
+        let result = admin_audit::audited(
+            "import_hybrid_backup",
+            session.as_ref(),
+            serde_json::json!({ "zip_path": zip_path }),
+            backup_notify::record_restore(&zip_path, result),
+        );
+        warm_up_after(window, result)
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))?
 }
 
 #[tauri::command]
-fn import_hybrid_backup(zip_path: String) -> Result<String, String> {
-    hybrid_backup::import_backup(&zip_path)
+async fn create_encrypted_backup(
+    window: tauri::Window,
+    password: String,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupCreated, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("backup"));
+    tauri::async_runtime::spawn_blocking(move || {
+        backup_notify::record_backup(
+            backup_results::BackupKind::Hybrid,
+            operation_history::tracked(
+                operation_history::OPERATION_BACKUP,
+                "create_encrypted_backup",
+                None,
+                || {
+                    watchdog::run_watched_job(
+                        &job_id,
+                        "backup",
+                        started_by,
+                        window_job_sink(window, Some(progress::BACKUP_PROGRESS_EVENT)),
+                        move |progress| {
+                            backup_encryption::create_encrypted_backup_with_progress(
+                                &password, progress,
+                            )
+                        },
+                    )
+                },
+            ),
+        )
+    })
+    .await
+    .map_err(|e| format!("Backup task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn restore_encrypted_backup(
+    window: tauri::Window,
+    zip_path: String,
+    password: String,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupRestored, String> {
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    let started_by = session.as_ref().and_then(|session| session.user_id);
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("restore"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = zip_path.clone();
+        let result = operation_history::tracked(
+            operation_history::OPERATION_RESTORE,
+            "restore_encrypted_backup",
+            Some(zip_path.as_str()),
+            || {
+                watchdog::run_exclusive_watched_job(
+                    &job_id,
+                    "restore",
+                    started_by,
+                    window_job_sink(window.clone(), Some(progress::BACKUP_PROGRESS_EVENT)),
+                    move |progress| {
+                        backup_encryption::restore_encrypted_backup_with_progress(
+                            &path, &password, progress,
+                        )
+                    },
+                )
+            },
+        );
+        // The password is deliberately left out of the audit entry
+        let result = admin_audit::audited(
+            "restore_encrypted_backup",
+            session.as_ref(),
+            serde_json::json!({ "zip_path": zip_path }),
+            backup_notify::record_restore(&zip_path, result),
+        );
+        warm_up_after(window, result)
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))?
+}
+
+/// Re-read the backup folder after files were copied in or moved away by hand
+#[tauri::command]
+fn rescan_backup_directory() -> Result<backup_catalog::CatalogRescan, String> {
+    backup_catalog::rescan_backup_directory()
+}
+
+#[tauri::command]
+fn list_backup_catalog() -> Result<Vec<backup_catalog::CatalogEntry>, String> {
+    backup_catalog::list_backup_catalog()
 }
 
 #[tauri::command]
@@ -273,10 +1403,89 @@ fn discover_hybrid_backups() -> Result<String, String> {
 }
 
 #[tauri::command]
-fn delete_hybrid_backup(filename: String) -> Result<String, String> {
+fn delete_hybrid_backup(filename: String) -> Result<backup_results::BackupDeleted, String> {
     hybrid_backup::delete_hybrid_backup(&filename)
 }
 
+#[tauri::command]
+fn set_backup_note(
+    filename: String,
+    note: Option<String>,
+) -> Result<hybrid_backup::BackupManifest, String> {
+    hybrid_backup::set_backup_note(&filename, note.as_deref())
+}
+
+#[tauri::command]
+fn detect_legacy_data() -> Result<legacy_migration::LegacyDataReport, String> {
+    legacy_migration::detect_legacy_data()
+}
+
+#[tauri::command]
+fn migrate_legacy_data() -> Result<legacy_migration::LegacyMigrationReport, String> {
+    operation_history::tracked(
+        operation_history::OPERATION_MIGRATION,
+        "migrate_legacy_data",
+        None,
+        legacy_migration::migrate_legacy_data,
+    )
+}
+
+// Backup sandbox commands (inspect a backup without restoring it)
+#[tauri::command]
+async fn open_backup_sandbox(filename: String) -> Result<backup_sandbox::SandboxInfo, String> {
+    // Extracting a large hybrid backup can take a while
+    tauri::async_runtime::spawn_blocking(move || backup_sandbox::open_backup_sandbox(&filename))
+        .await
+        .map_err(|e| format!("Sandbox task failed: {}", e))?
+}
+
+#[tauri::command]
+fn list_backup_sandboxes() -> Result<Vec<backup_sandbox::SandboxInfo>, String> {
+    backup_sandbox::list_backup_sandboxes()
+}
+
+#[tauri::command]
+fn sandbox_query_table(
+    sandbox_id: String,
+    table: String,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<backup_sandbox::SandboxRows, String> {
+    backup_sandbox::sandbox_query_table(
+        &sandbox_id,
+        &table,
+        limit.unwrap_or(100),
+        offset.unwrap_or(0),
+    )
+}
+
+#[tauri::command]
+fn sandbox_read_media(sandbox_id: String, path: String) -> Result<String, String> {
+    backup_sandbox::sandbox_read_media(&sandbox_id, &path)
+}
+
+#[tauri::command]
+fn close_backup_sandbox(sandbox_id: String) -> Result<bool, String> {
+    backup_sandbox::close_backup_sandbox(&sandbox_id)
+}
+
+#[tauri::command]
+fn check_backup_compatibility(
+    filename: String,
+) -> Result<backup_compat::BackupCompatibility, String> {
+    backup_compat::check_backup_compatibility(&filename)
+}
+
+/// What a restore of the backup would change, without restoring it
+#[tauri::command]
+async fn preview_restore(
+    backup_filename: String,
+) -> Result<restore_preview::RestorePreview, String> {
+    tauri::async_runtime::spawn_blocking(move || restore_preview::preview_restore(&backup_filename))
+        .await
+        .map_err(|e| format!("Restore preview task failed: {}", e))?
+}
+
 #[tauri::command]
 fn check_backup_for_initialization() -> Result<String, String> {
     let backup_info = hybrid_backup::check_backup_for_initialization()
@@ -303,12 +1512,9 @@ fn export_backup_to_location(
 ) -> Result<String, String> {
     use std::fs;
     use std::path::Path;
-    use tauri::api::path::app_data_dir;
-    use tauri::Config;
 
     // Get source file path from backups directory
-    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
-    let backups_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
+    let backups_dir = storage_paths::get_backup_dir()?;
     let source_path = backups_dir.join(&source_filename);
 
     // Verify source file exists
@@ -317,7 +1523,7 @@ fn export_backup_to_location(
     }
 
     // Copy file to destination
-    let dest = Path::new(&destination_path);
+    let dest = long_path::for_io(Path::new(&destination_path));
     fs::copy(&source_path, dest).map_err(|e| format!("Failed to copy file: {}", e))?;
 
     Ok(format!(
@@ -333,12 +1539,9 @@ fn export_hybrid_backup_to_location(
 ) -> Result<String, String> {
     use std::fs;
     use std::path::Path;
-    use tauri::api::path::app_data_dir;
-    use tauri::Config;
 
     // Get source file path from backups directory
-    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
-    let backups_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
+    let backups_dir = storage_paths::get_backup_dir()?;
     let source_path = backups_dir.join(&source_filename);
 
     // Verify source file exists
@@ -349,9 +1552,14 @@ fn export_hybrid_backup_to_location(
         ));
     }
 
-    // Copy file to destination
-    let dest = Path::new(&destination_path);
-    fs::copy(&source_path, dest).map_err(|e| format!("Failed to copy file: {}", e))?;
+    // Copy file to destination - a split backup as its whole volume set
+    let volumes = backup_volumes::volume_set(&source_path)?;
+    if volumes.len() > 1 {
+        backup_manager::copy_volume_set(&volumes, Path::new(&destination_path))?;
+    } else {
+        let dest = long_path::for_io(Path::new(&destination_path));
+        fs::copy(&source_path, dest).map_err(|e| format!("Failed to copy file: {}", e))?;
+    }
 
     Ok(format!(
         "✅ Hybrid backup exported successfully to: {}",
@@ -366,48 +1574,165 @@ fn export_sql_to_location(destination_path: String) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn copy_sql_export_to_location(
-    source_filename: String,
-    destination_path: String,
-) -> Result<String, String> {
-    use std::fs;
-    use tauri::api::path::app_data_dir;
-    use tauri::Config;
-
-    // Get source file from exports directory
-    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
-    let source_path = app_data
-        .join("pqs-rtn-hybrid-storage")
-        .join("exports")
-        .join(&source_filename);
-
-    if !source_path.exists() {
-        return Err(format!("Export file not found: {}", source_filename));
-    }
+fn copy_sql_export_to_location(
+    source_filename: String,
+    destination_path: String,
+) -> Result<String, String> {
+    use std::fs;
+
+    // Get source file from exports directory
+    let source_path = storage_paths::get_export_dir()?.join(&source_filename);
+
+    if !source_path.exists() {
+        return Err(format!("Export file not found: {}", source_filename));
+    }
+
+    // Copy to destination
+    fs::copy(
+        &source_path,
+        long_path::for_io(std::path::Path::new(&destination_path)),
+    )
+    .map_err(|e| format!("Failed to copy SQL export: {}", e))?;
+
+    Ok(format!(
+        "✅ SQL export copied successfully to: {}",
+        destination_path
+    ))
+}
+
+// Backup management commands
+/// Copy a backup to the place picked with `choose_backup_destination`
+#[tauri::command]
+fn copy_backup_to_location(
+    backup_filename: String,
+    destination_id: String,
+) -> Result<backup_results::BackupCopied, String> {
+    let destination = file_dialogs::take_choice(&destination_id, file_dialogs::ChoiceKind::Save)?;
+    backup_manager::copy_backup_to_location(&backup_filename, &destination.to_string_lossy())
+}
+
+#[tauri::command]
+async fn choose_backup_destination(
+    backup_filename: String,
+) -> Result<Option<file_dialogs::DialogChoice>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        file_dialogs::choose_backup_destination(&backup_filename)
+    })
+    .await
+    .map_err(|e| format!("File dialog task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn choose_backup_to_restore() -> Result<Option<file_dialogs::DialogChoice>, String> {
+    tauri::async_runtime::spawn_blocking(file_dialogs::choose_backup_to_restore)
+        .await
+        .map_err(|e| format!("File dialog task failed: {}", e))?
+}
+
+/// Restore the backup file picked with `choose_backup_to_restore`, wherever
+/// it is on disk; database backups are copied into the backup directory
+/// first so they show up in the list like any other
+#[tauri::command]
+async fn restore_backup_from_path(
+    window: tauri::Window,
+    choice_id: String,
+    session_token: Option<String>,
+) -> Result<backup_results::BackupRestored, String> {
+    let chosen = file_dialogs::take_choice(&choice_id, file_dialogs::ChoiceKind::Open)?;
+    // The file may have changed since it was picked
+    let path = file_dialogs::validated_open_path(&chosen, file_dialogs::BACKUP_EXTENSIONS)?;
+    if file_dialogs::backup_extension(&path, &["zip"]).is_some() {
+        return import_hybrid_backup(
+            window,
+            path.to_string_lossy().to_string(),
+            None,
+            session_token,
+        )
+        .await;
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let backup_filename = backup_manager::copy_into_backup_directory(&path)?;
+        restore_database_backup(window, backup_filename, session_token)
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_backup_directory_path() -> Result<String, String> {
+    let backup_dir = backup_manager::get_backup_directory()?;
+    Ok(backup_dir.to_string_lossy().to_string())
+}
+
+// SFTP backup destination commands (network calls run off the main thread)
+#[tauri::command]
+fn get_sftp_settings() -> Result<Option<settings::SftpSettings>, String> {
+    sftp_backup::get_sftp_settings()
+}
+
+#[tauri::command]
+fn save_sftp_settings(
+    settings: settings::SftpSettings,
+    secret: Option<String>,
+) -> Result<(), String> {
+    sftp_backup::save_sftp_settings(settings, secret)
+}
+
+#[tauri::command]
+async fn test_sftp_connection() -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(sftp_backup::test_sftp_connection)
+        .await
+        .map_err(|e| format!("SFTP task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn upload_backup_to_sftp(filename: String) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || sftp_backup::upload_backup_to_sftp(&filename))
+        .await
+        .map_err(|e| format!("SFTP task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn list_sftp_backups() -> Result<Vec<sftp_backup::RemoteBackupInfo>, String> {
+    tauri::async_runtime::spawn_blocking(sftp_backup::list_sftp_backups)
+        .await
+        .map_err(|e| format!("SFTP task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn download_sftp_backup(filename: String) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || sftp_backup::download_sftp_backup(&filename))
+        .await
+        .map_err(|e| format!("SFTP task failed: {}", e))?
+}
 
-    // Copy to destination
-    fs::copy(&source_path, &destination_path)
-        .map_err(|e| format!("Failed to copy SQL export: {}", e))?;
+// Backup notification commands
+#[tauri::command]
+fn get_notification_settings() -> Result<Option<settings::NotificationSettings>, String> {
+    backup_notify::get_notification_settings()
+}
 
-    Ok(format!(
-        "✅ SQL export copied successfully to: {}",
-        destination_path
-    ))
+#[tauri::command]
+fn save_notification_settings(
+    settings: Option<settings::NotificationSettings>,
+    secret: Option<String>,
+) -> Result<(), String> {
+    backup_notify::save_notification_settings(settings, secret)
 }
 
-// Backup management commands
 #[tauri::command]
-fn copy_backup_to_location(
-    backup_filename: String,
-    destination_path: String,
-) -> Result<String, String> {
-    backup_manager::copy_backup_to_location(&backup_filename, &destination_path)
+async fn send_test_notification() -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(backup_notify::send_test_notification)
+        .await
+        .map_err(|e| format!("Notification task failed: {}", e))?
 }
 
 #[tauri::command]
-fn get_backup_directory_path() -> Result<String, String> {
-    let backup_dir = backup_manager::get_backup_directory()?;
-    Ok(backup_dir.to_string_lossy().to_string())
+async fn send_backup_summary() -> Result<backup_notify::BackupSummary, String> {
+    tauri::async_runtime::spawn_blocking(backup_notify::send_backup_summary)
+        .await
+        .map_err(|e| format!("Notification task failed: {}", e))?
 }
 
 #[tauri::command]
@@ -426,29 +1751,33 @@ fn save_hybrid_avatar(
     user_id: i32,
     avatar_data: Vec<u8>,
     mime_type: String,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
 ) -> Result<hybrid_avatar::HybridAvatarInfo, String> {
-    // Validate avatar data
-    if avatar_data.is_empty() {
-        return Err("Avatar data is empty".to_string());
-    }
-
-    // Check maximum size (10MB)
-    const MAX_SIZE: usize = 10 * 1024 * 1024;
-    if avatar_data.len() > MAX_SIZE {
-        return Err(format!(
-            "Avatar data too large: {} bytes (max: {} bytes)",
-            avatar_data.len(),
-            MAX_SIZE
-        ));
-    }
-
-    // Validate MIME type
-    if !mime_type.starts_with("image/") {
-        return Err(format!("Invalid MIME type: {}", mime_type));
-    }
-
-    let manager = hybrid_avatar::HybridAvatarManager::new()?;
-    manager.save_avatar(user_id, &avatar_data, &mime_type)
+    // Users replace their own photo; anyone else's needs an editor
+    permissions::require_self_or_role(
+        "save_hybrid_avatar",
+        user_id,
+        permissions::Role::Editor,
+        session_token.as_deref(),
+    )?;
+    let caller = permissions::caller(session_token.as_deref())?;
+    // Dimension limits and re-encoding are applied by the manager (avatar_policy)
+    let image = validation::ImagePayload::parse(
+        "avatar_data",
+        &avatar_data,
+        &mime_type,
+        &avatar_policy::current_policy(),
+    )?;
+    idempotency::run_once(
+        "save_hybrid_avatar",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || {
+            let manager = hybrid_avatar::HybridAvatarManager::new()?;
+            manager.save_avatar(user_id, image.data, &image.mime_type, caller)
+        },
+    )
 }
 
 /// Phase 1.3: Streaming avatar upload to reduce memory usage
@@ -458,16 +1787,22 @@ fn save_hybrid_avatar_stream(
     user_id: i32,
     avatar_data: Vec<u8>,
     mime_type: String,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
 ) -> Result<hybrid_avatar::HybridAvatarInfo, String> {
-    // Validate avatar data
-    if avatar_data.is_empty() {
-        return Err("Avatar data is empty".to_string());
-    }
-
-    // Validate MIME type
-    if !mime_type.starts_with("image/") {
-        return Err(format!("Invalid MIME type: {}", mime_type));
-    }
+    permissions::require_self_or_role(
+        "save_hybrid_avatar_stream",
+        user_id,
+        permissions::Role::Editor,
+        session_token.as_deref(),
+    )?;
+    let caller = permissions::caller(session_token.as_deref())?;
+    validation::ImagePayload::parse(
+        "avatar_data",
+        &avatar_data,
+        &mime_type,
+        &avatar_policy::current_policy(),
+    )?;
 
     // Create a cursor from the data to act as a reader
     use std::io::Cursor;
@@ -475,8 +1810,114 @@ fn save_hybrid_avatar_stream(
     let data_len = reader.get_ref().len();
 
     // Use streaming method - memory efficient for large files
-    let manager = hybrid_avatar::HybridAvatarManager::new()?;
-    manager.save_avatar_stream(user_id, reader, &mime_type, Some(data_len))
+    idempotency::run_once(
+        "save_hybrid_avatar_stream",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || {
+            let manager = hybrid_avatar::HybridAvatarManager::new()?;
+            manager.save_avatar_stream(user_id, reader, &mime_type, Some(data_len), caller)
+        },
+    )
+}
+
+#[tauri::command]
+fn begin_avatar_upload(
+    owner_type: String,
+    owner_id: i32,
+    mime_type: String,
+    total_bytes: u64,
+    sha256: Option<String>,
+    session_token: Option<String>,
+) -> Result<chunked_upload::UploadStatus, String> {
+    // Users upload their own photos; other photos need an editor
+    if owner_type == media_maintenance::OWNER_USER {
+        permissions::require_self_or_role(
+            "begin_avatar_upload",
+            owner_id,
+            permissions::Role::Editor,
+            session_token.as_deref(),
+        )?;
+    } else {
+        permissions::require_role(
+            "begin_avatar_upload",
+            permissions::Role::Editor,
+            session_token.as_deref(),
+        )?;
+    }
+    chunked_upload::begin_upload(
+        &owner_type,
+        owner_id,
+        &mime_type,
+        total_bytes,
+        sha256.as_deref(),
+    )
+}
+
+#[tauri::command]
+fn get_avatar_upload_status(upload_id: String) -> Result<chunked_upload::UploadStatus, String> {
+    chunked_upload::get_upload_status(&upload_id)
+}
+
+#[tauri::command]
+fn append_avatar_upload_chunk(
+    upload_id: String,
+    offset: u64,
+    data: Vec<u8>,
+) -> Result<chunked_upload::UploadStatus, String> {
+    chunked_upload::append_chunk(&upload_id, offset, &data)
+}
+
+#[tauri::command]
+fn finish_avatar_upload(
+    upload_id: String,
+    session_token: Option<String>,
+) -> Result<chunked_upload::UploadedAvatar, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    chunked_upload::finish_upload(&upload_id, caller)
+}
+
+#[tauri::command]
+fn cancel_avatar_upload(upload_id: String) -> Result<bool, String> {
+    chunked_upload::cancel_upload(&upload_id)
+}
+
+#[tauri::command]
+fn get_avatar_policy() -> Result<settings::AvatarPolicy, String> {
+    Ok(avatar_policy::current_policy())
+}
+
+#[tauri::command]
+fn save_avatar_policy(policy: settings::AvatarPolicy) -> Result<(), String> {
+    avatar_policy::save_avatar_policy(policy)
+}
+
+#[tauri::command]
+fn get_media_budget_status() -> Result<media_budget::MediaUsage, String> {
+    media_budget::media_usage()
+}
+
+/// None stops watching media size
+#[tauri::command]
+fn set_media_budget(budget: Option<settings::MediaBudget>) -> Result<(), String> {
+    media_budget::set_media_budget(budget)
+}
+
+#[tauri::command]
+fn get_avatar_access_audit() -> bool {
+    avatar_audit::is_enabled()
+}
+
+#[tauri::command]
+fn set_avatar_access_audit(enabled: bool) -> Result<(), String> {
+    avatar_audit::set_enabled(enabled)
+}
+
+#[tauri::command]
+fn query_avatar_access_log(
+    filter: Option<avatar_audit::AvatarAccessFilter>,
+) -> Result<Vec<avatar_audit::AvatarAccessEvent>, String> {
+    avatar_audit::query_avatar_access(&filter.unwrap_or_default())
 }
 
 #[tauri::command]
@@ -489,7 +1930,13 @@ fn get_hybrid_avatar_info(user_id: i32) -> Result<hybrid_avatar::HybridAvatarInf
 }
 
 #[tauri::command]
-fn delete_hybrid_avatar(user_id: i32) -> Result<bool, String> {
+fn delete_hybrid_avatar(user_id: i32, session_token: Option<String>) -> Result<bool, String> {
+    permissions::require_self_or_role(
+        "delete_hybrid_avatar",
+        user_id,
+        permissions::Role::Editor,
+        session_token.as_deref(),
+    )?;
     let manager = hybrid_avatar::HybridAvatarManager::new()
         .map_err(|e| format!("Failed to initialize avatar manager: {}", e))?;
     manager
@@ -497,16 +1944,92 @@ fn delete_hybrid_avatar(user_id: i32) -> Result<bool, String> {
         .map_err(|e| format!("Failed to delete avatar for user {}: {}", user_id, e))
 }
 
+/// `avatar://` URLs for many owners at once; preferred over the base64
+/// commands, which copy every image through the IPC bridge
 #[tauri::command]
-fn get_hybrid_avatar_base64(avatar_path: String) -> Result<String, String> {
+fn get_avatar_urls(
+    owner_type: String,
+    ids: Vec<i32>,
+    session_token: Option<String>,
+) -> Result<Vec<avatar_protocol::AvatarUrl>, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    avatar_protocol::get_avatar_urls(&owner_type, &ids, viewer)
+}
+
+#[tauri::command]
+fn get_hybrid_avatar_base64(
+    avatar_path: String,
+    session_token: Option<String>,
+) -> Result<String, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    // Photos under review look like no photo to everyone but reviewers and owners
+    if !avatar_approval::path_visible_to(&avatar_path, viewer)? {
+        return Ok(hybrid_avatar::placeholder_avatar_data_url());
+    }
     let manager = hybrid_avatar::HybridAvatarManager::new()
         .map_err(|e| format!("Failed to initialize avatar manager: {}", e))?;
-    manager.get_avatar_base64(&avatar_path).map_err(|e| {
-        format!(
-            "Failed to get avatar base64 for path '{}': {}",
-            avatar_path, e
-        )
-    })
+    // A missing file yields the placeholder so the UI keeps rendering
+    let data_url = manager
+        .get_avatar_image(&avatar_path)
+        .map(|image| image.data_url)
+        .map_err(|e| {
+            format!(
+                "Failed to get avatar base64 for path '{}': {}",
+                avatar_path, e
+            )
+        })?;
+    avatar_audit::record_avatar_access(
+        viewer.and_then(|viewer| viewer.user_id),
+        avatar_audit::AvatarAccess::path(&avatar_path),
+    );
+    Ok(data_url)
+}
+
+#[tauri::command]
+fn get_hybrid_avatar_image(
+    avatar_path: String,
+    session_token: Option<String>,
+) -> Result<hybrid_avatar::AvatarImage, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    if !avatar_approval::path_visible_to(&avatar_path, viewer)? {
+        return Ok(hybrid_avatar::AvatarImage {
+            avatar_path,
+            data_url: hybrid_avatar::placeholder_avatar_data_url(),
+            missing: false,
+            dangling_reference: false,
+        });
+    }
+    let manager = hybrid_avatar::HybridAvatarManager::new()
+        .map_err(|e| format!("Failed to initialize avatar manager: {}", e))?;
+    let image = manager.get_avatar_image(&avatar_path)?;
+    avatar_audit::record_avatar_access(
+        viewer.and_then(|viewer| viewer.user_id),
+        avatar_audit::AvatarAccess::path(&avatar_path),
+    );
+    Ok(image)
+}
+
+#[tauri::command]
+fn get_avatar_base64_by_user_id(
+    user_id: i32,
+    session_token: Option<String>,
+) -> Result<Option<String>, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    if !avatar_approval::owner_visible_to(media_maintenance::OWNER_USER, user_id, viewer)? {
+        return Ok(None);
+    }
+    let manager = hybrid_avatar::HybridAvatarManager::new()
+        .map_err(|e| format!("Failed to initialize avatar manager: {}", e))?;
+    let data_url = manager
+        .get_avatar_base64_by_user_id(user_id)
+        .map_err(|e| format!("Failed to get avatar base64 for user {}: {}", user_id, e))?;
+    if data_url.is_some() {
+        avatar_audit::record_avatar_access(
+            viewer.and_then(|viewer| viewer.user_id),
+            avatar_audit::AvatarAccess::user(user_id),
+        );
+    }
+    Ok(data_url)
 }
 
 #[tauri::command]
@@ -533,29 +2056,26 @@ fn save_hybrid_high_rank_avatar(
     officer_id: i32,
     avatar_data: Vec<u8>,
     mime_type: String,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
 ) -> Result<hybrid_high_rank_avatar::HybridHighRankAvatarInfo, String> {
-    // Validate avatar data
-    if avatar_data.is_empty() {
-        return Err("Avatar data is empty".to_string());
-    }
-
-    // Check maximum size (10MB)
-    const MAX_SIZE: usize = 10 * 1024 * 1024;
-    if avatar_data.len() > MAX_SIZE {
-        return Err(format!(
-            "Avatar data too large: {} bytes (max: {} bytes)",
-            avatar_data.len(),
-            MAX_SIZE
-        ));
-    }
-
-    // Validate MIME type
-    if !mime_type.starts_with("image/") {
-        return Err(format!("Invalid MIME type: {}", mime_type));
-    }
-
-    let manager = hybrid_high_rank_avatar::HybridHighRankAvatarManager::new()?;
-    manager.save_avatar(officer_id, &avatar_data, &mime_type)
+    let caller = permissions::caller(session_token.as_deref())?;
+    // Dimension limits and re-encoding are applied by the manager (avatar_policy)
+    let image = validation::ImagePayload::parse(
+        "avatar_data",
+        &avatar_data,
+        &mime_type,
+        &avatar_policy::current_policy(),
+    )?;
+    idempotency::run_once(
+        "save_hybrid_high_rank_avatar",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || {
+            let manager = hybrid_high_rank_avatar::HybridHighRankAvatarManager::new()?;
+            manager.save_avatar(officer_id, image.data, &image.mime_type, caller)
+        },
+    )
 }
 
 #[tauri::command]
@@ -573,9 +2093,41 @@ fn delete_hybrid_high_rank_avatar(officer_id: i32) -> Result<bool, String> {
 }
 
 #[tauri::command]
-fn get_hybrid_high_rank_avatar_base64(avatar_path: String) -> Result<String, String> {
+fn get_hybrid_high_rank_avatar_base64(
+    avatar_path: String,
+    session_token: Option<String>,
+) -> Result<String, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    if !avatar_approval::path_visible_to(&avatar_path, viewer)? {
+        return Ok(hybrid_avatar::placeholder_avatar_data_url());
+    }
+    let manager = hybrid_high_rank_avatar::HybridHighRankAvatarManager::new()?;
+    let data_url = manager.get_avatar_base64(&avatar_path)?;
+    avatar_audit::record_avatar_access(
+        viewer.and_then(|viewer| viewer.user_id),
+        avatar_audit::AvatarAccess::path(&avatar_path),
+    );
+    Ok(data_url)
+}
+
+#[tauri::command]
+fn get_avatar_base64_by_officer_id(
+    officer_id: i32,
+    session_token: Option<String>,
+) -> Result<Option<String>, String> {
+    let viewer = permissions::caller(session_token.as_deref())?;
+    if !avatar_approval::owner_visible_to(media_maintenance::OWNER_OFFICER, officer_id, viewer)? {
+        return Ok(None);
+    }
     let manager = hybrid_high_rank_avatar::HybridHighRankAvatarManager::new()?;
-    manager.get_avatar_base64(&avatar_path)
+    let data_url = manager.get_avatar_base64_by_officer_id(officer_id)?;
+    if data_url.is_some() {
+        avatar_audit::record_avatar_access(
+            viewer.and_then(|viewer| viewer.user_id),
+            avatar_audit::AvatarAccess::officer(officer_id),
+        );
+    }
+    Ok(data_url)
 }
 
 #[tauri::command]
@@ -584,6 +2136,337 @@ fn cleanup_orphaned_high_rank_avatar_files() -> Result<u32, String> {
     manager.cleanup_orphaned_files()
 }
 
+// Workspace commands
+#[tauri::command]
+fn list_workspaces() -> Result<Vec<workspaces::WorkspaceInfo>, String> {
+    workspaces::list_workspaces()
+}
+
+#[tauri::command]
+fn create_workspace(
+    name: String,
+    idempotency_key: Option<String>,
+    session_token: Option<String>,
+) -> Result<workspaces::WorkspaceInfo, String> {
+    idempotency::run_once(
+        "create_workspace",
+        session_token.as_deref(),
+        idempotency_key.as_deref(),
+        || workspaces::create_workspace(&name),
+    )
+}
+
+#[tauri::command]
+fn switch_workspace(
+    app_handle: tauri::AppHandle,
+    name: String,
+) -> Result<workspaces::WorkspaceInfo, String> {
+    let info = workspaces::switch_workspace(&name)?;
+
+    // Point the media watcher at the new workspace
+    let manager = file_manager::FileManager::get_instance()?;
+    if let Err(e) =
+        media_watcher::start_media_watcher(app_handle, manager.get_media_directory().clone())
+    {
+        logger::warn(format!("Failed to restart media watcher: {}", e));
+    }
+
+    Ok(info)
+}
+
+#[tauri::command]
+fn export_avatars_zip(
+    user_ids: Vec<i32>,
+    destination: String,
+) -> Result<avatar_export::AvatarExportReport, String> {
+    avatar_export::export_avatars_zip(&user_ids, &destination)
+}
+
+/// Preview only; nothing is saved until `apply_photo_matches`
+#[tauri::command]
+fn match_photos_to_users(
+    folder: String,
+    pattern: photo_matching::PhotoNamePattern,
+) -> Result<Vec<photo_matching::PhotoMatch>, String> {
+    photo_matching::match_photos_to_users(&folder, pattern)
+}
+
+#[tauri::command]
+async fn apply_photo_matches(
+    window: tauri::Window,
+    folder: String,
+    assignments: Vec<photo_matching::PhotoAssignment>,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<Vec<photo_matching::PhotoApplyResult>, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    let started_by = caller.and_then(|caller| caller.user_id);
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("photo-matching"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window, None);
+        invalidate_after(jobs::run_job(
+            &job_id,
+            "photo-matching",
+            started_by,
+            sink,
+            |progress| photo_matching::apply_photo_matches(&folder, &assignments, progress, caller),
+        ))
+    })
+    .await
+    .map_err(|e| format!("Photo matching task failed: {}", e))?
+}
+
+/// Fill in missing avatar files from the folder or zip that came with an import
+#[tauri::command]
+async fn import_referenced_photos(
+    window: tauri::Window,
+    source: String,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<photo_import::PhotoImportReport, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    let started_by = caller.and_then(|caller| caller.user_id);
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("photo-import"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let sink = window_job_sink(window, None);
+        invalidate_after(jobs::run_job(
+            &job_id,
+            "photo-import",
+            started_by,
+            sink,
+            |progress| photo_import::import_referenced_photos(&source, progress, caller),
+        ))
+    })
+    .await
+    .map_err(|e| format!("Photo import task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn generate_contact_sheet(
+    owner_type: String,
+    ids: Vec<i32>,
+    columns: u32,
+    size: u32,
+    format: Option<contact_sheet::SheetFormat>,
+) -> Result<contact_sheet::ContactSheetReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        contact_sheet::generate_contact_sheet(
+            &owner_type,
+            &ids,
+            columns,
+            size,
+            format.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|e| format!("Contact sheet task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn install_dataset_pack(
+    window: tauri::Window,
+    path: String,
+) -> Result<dataset_pack::DatasetPackReport, String> {
+    // Packs can bring officer photos
+    tauri::async_runtime::spawn_blocking(move || {
+        warm_up_after(window, dataset_pack::install_dataset_pack(&path))
+    })
+    .await
+    .map_err(|e| format!("Pack install task failed: {}", e))?
+}
+
+#[tauri::command]
+fn get_ranks() -> Result<Vec<dataset_pack::Rank>, String> {
+    dataset_pack::get_ranks()
+}
+
+#[tauri::command]
+fn get_position_templates() -> Result<Vec<dataset_pack::PositionTemplate>, String> {
+    dataset_pack::get_position_templates()
+}
+
+#[tauri::command]
+fn publish_officer_board(destination: String) -> Result<officer_board::OfficerBoardReport, String> {
+    officer_board::publish_officer_board(&destination)
+}
+
+// Media reconciliation commands
+#[tauri::command]
+fn cleanup_all_media() -> Result<media_maintenance::MediaCleanupReport, String> {
+    operation_history::tracked(
+        operation_history::OPERATION_CLEANUP,
+        "cleanup_all_media",
+        None,
+        media_maintenance::cleanup_all_media,
+    )
+}
+
+/// Newest first, for the recent activity panel
+#[tauri::command]
+fn get_operation_history(
+    limit: Option<u32>,
+) -> Result<Vec<operation_history::OperationRecord>, String> {
+    operation_history::get_operation_history(limit.unwrap_or(50))
+}
+
+#[tauri::command]
+fn reconcile_media() -> Result<media_maintenance::MediaReconciliation, String> {
+    media_maintenance::reconcile_media()
+}
+
+/// Dangling references and unreferenced files, with proposed repairs
+#[tauri::command]
+fn reconcile_media_references() -> Result<media_maintenance::MediaReferenceReport, String> {
+    media_maintenance::reconcile_media_references()
+}
+
+#[tauri::command]
+fn apply_media_fixes(
+    fixes: Vec<media_maintenance::MediaFix>,
+    session_token: Option<String>,
+) -> Result<media_maintenance::MediaFixResult, String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    media_maintenance::apply_media_fixes(&fixes, caller)
+}
+
+#[tauri::command]
+fn adopt_media_file(
+    relative_path: String,
+    owner_type: String,
+    owner_id: i32,
+    session_token: Option<String>,
+) -> Result<(), String> {
+    let caller = permissions::caller(session_token.as_deref())?;
+    media_maintenance::adopt_media_file(&relative_path, &owner_type, owner_id, caller)
+}
+
+#[tauri::command]
+fn transfer_avatar(
+    from_user_id: i32,
+    to_user_id: i32,
+) -> Result<media_maintenance::AvatarTransfer, String> {
+    media_maintenance::transfer_avatar(media_maintenance::OWNER_USER, from_user_id, to_user_id)
+}
+
+#[tauri::command]
+fn transfer_high_rank_avatar(
+    from_officer_id: i32,
+    to_officer_id: i32,
+) -> Result<media_maintenance::AvatarTransfer, String> {
+    media_maintenance::transfer_avatar(
+        media_maintenance::OWNER_OFFICER,
+        from_officer_id,
+        to_officer_id,
+    )
+}
+
+#[tauri::command]
+fn get_officer_signature(
+    officer_id: i32,
+) -> Result<Option<officer_signature::OfficerSignature>, String> {
+    officer_signature::get_signature(officer_id)
+}
+
+#[tauri::command]
+fn save_officer_signature(
+    officer_id: i32,
+    image_data: Vec<u8>,
+    title_lines: Vec<String>,
+) -> Result<officer_signature::OfficerSignature, String> {
+    officer_signature::save_signature(officer_id, &image_data, &title_lines)
+}
+
+#[tauri::command]
+fn delete_officer_signature(officer_id: i32) -> Result<bool, String> {
+    officer_signature::delete_signature(officer_id)
+}
+
+/// Signed copy of a generated SVG/HTML report, written next to it
+#[tauri::command]
+fn stamp_officer_signature(
+    officer_id: i32,
+    document_path: String,
+) -> Result<officer_signature::StampedDocument, String> {
+    officer_signature::stamp_document(officer_id, &document_path)
+}
+
+/// Photos waiting for review by default
+#[tauri::command]
+fn get_avatar_review_queue(
+    status: Option<String>,
+) -> Result<Vec<avatar_approval::AvatarReviewItem>, String> {
+    let status = match status {
+        Some(status) => avatar_approval::AvatarStatus::parse(&status)?,
+        None => avatar_approval::AvatarStatus::Pending,
+    };
+    avatar_approval::list_review_queue(status)
+}
+
+#[tauri::command]
+fn review_avatar(
+    owner_type: String,
+    owner_id: i32,
+    status: String,
+    session_token: Option<String>,
+) -> Result<(), String> {
+    let status = avatar_approval::AvatarStatus::parse(&status)?;
+    let session = admin_audit::acting_session(session_token.as_deref())?;
+    admin_audit::audited(
+        "review_avatar",
+        session.as_ref(),
+        serde_json::json!({ "owner_type": owner_type, "owner_id": owner_id, "status": status }),
+        avatar_approval::set_avatar_status(&owner_type, owner_id, status),
+    )
+}
+
+#[tauri::command]
+fn remove_untracked_media_file(relative_path: String) -> Result<(), String> {
+    media_maintenance::remove_untracked_media_file(&relative_path)
+}
+
+#[tauri::command]
+async fn find_duplicate_media(
+    window: tauri::Window,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<media_maintenance::DuplicateMediaReport, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    // Hashing the whole media folder can take a while
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("media-scan"));
+    tauri::async_runtime::spawn_blocking(move || {
+        jobs::run_job(
+            &job_id,
+            "media-scan",
+            started_by,
+            window_job_sink(window, None),
+            media_maintenance::find_duplicate_media,
+        )
+    })
+    .await
+    .map_err(|e| format!("Duplicate scan task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn verify_avatar_images(
+    window: tauri::Window,
+    operation_id: Option<String>,
+    session_token: Option<String>,
+) -> Result<media_maintenance::ImageIntegrityReport, String> {
+    let started_by = permissions::caller_id(session_token.as_deref())?;
+    let job_id = operation_id.unwrap_or_else(|| jobs::new_job_id("image-scan"));
+    tauri::async_runtime::spawn_blocking(move || {
+        jobs::run_job(
+            &job_id,
+            "image-scan",
+            started_by,
+            window_job_sink(window, None),
+            media_maintenance::verify_avatar_images,
+        )
+    })
+    .await
+    .map_err(|e| format!("Image scan task failed: {}", e))?
+}
+
 // Test cleanup commands
 #[tauri::command]
 fn delete_test_users() -> Result<String, String> {
@@ -1341,183 +3224,388 @@ fn get_trainee_answers(
 }
 
 fn main() {
+    let commands: fn(tauri::Invoke) = tauri::generate_handler![
+        greet,
+        get_all_users,
+        get_users_page,
+        count_users,
+        get_user_by_id,
+        get_user_by_email,
+        create_user,
+        update_user,
+        update_user_service_number,
+        update_user_full_name_en,
+        delete_user,
+        authenticate_user,
+        sign_out,
+        login,
+        validate_session,
+        change_password,
+        logout,
+        get_admin_action_log,
+        copy_users_to_clipboard,
+        list_saved_views,
+        create_saved_view,
+        update_saved_view,
+        delete_saved_view,
+        run_saved_view,
+        autosave_user_draft,
+        get_user_draft,
+        discard_user_draft,
+        get_user_preferences,
+        set_user_preferences,
+        apply_user_preferences,
+        rotate_admin_password,
+        reset_passwords_bulk,
+        migrate_passwords,
+        get_dashboard_stats,
+        get_startup_report,
+        diagnose_database_lock,
+        run_database_maintenance,
+        list_scheduled_tasks,
+        run_scheduled_task,
+        get_maintenance_status,
+        get_maintenance_mode,
+        set_maintenance_mode,
+        restore_user_from_backup,
+        archive_users,
+        search_archive,
+        zoom_in,
+        zoom_out,
+        zoom_reset,
+        get_all_high_ranking_officers,
+        update_high_ranking_officer,
+        update_officer_full_name_en,
+        reorder_high_ranking_officers,
+        set_officer_display_on_board,
+        set_officer_avatar_visible,
+        hash_password,
+        get_password_hash_cost,
+        get_password_hash_scheme,
+        set_password_hash_scheme,
+        set_password_hash_cost,
+        calibrate_password_hash_cost,
+        rehash_all_passwords,
+        // Database backup/restore commands
+        create_database_backup,
+        restore_database_backup,
+        import_sql_dump,
+        list_database_backups,
+        delete_database_backup,
+        // Database export/import commands
+        export_database,
+        store_export_passphrase,
+        has_export_passphrase,
+        import_database,
+        rehearse_import_database,
+        export_changes_since,
+        apply_changeset,
+        subscribe_record,
+        unsubscribe_record,
+        cancel_operation,
+        list_jobs,
+        cancel_job,
+        list_database_exports,
+        delete_database_export,
+        get_export_directory,
+        set_export_directory,
+        get_storage_mode,
+        set_storage_location,
+        reveal_export_in_explorer,
+        // Universal SQLite backup commands
+        create_universal_sqlite_backup,
+        create_standard_sql_dump,
+        // Hybrid backup commands (Database + Media)
+        create_hybrid_backup,
+        import_hybrid_backup,
+        create_encrypted_backup,
+        restore_encrypted_backup,
+        discover_hybrid_backups,
+        rescan_backup_directory,
+        list_backup_catalog,
+        delete_hybrid_backup,
+        set_backup_note,
+        check_backup_compatibility,
+        preview_restore,
+        open_backup_sandbox,
+        list_backup_sandboxes,
+        sandbox_query_table,
+        sandbox_read_media,
+        close_backup_sandbox,
+        detect_legacy_data,
+        migrate_legacy_data,
+        check_backup_for_initialization,
+        check_system_state_for_initialization,
+        // File export commands
+        export_backup_to_location,
+        export_hybrid_backup_to_location,
+        export_sql_to_location,
+        copy_sql_export_to_location,
+        // Backup management commands
+        copy_backup_to_location,
+        choose_backup_destination,
+        choose_backup_to_restore,
+        restore_backup_from_path,
+        get_backup_directory_path,
+        list_backup_files_with_paths,
+        get_backup_file_info,
+        // SFTP backup destination commands
+        get_sftp_settings,
+        save_sftp_settings,
+        test_sftp_connection,
+        upload_backup_to_sftp,
+        list_sftp_backups,
+        download_sftp_backup,
+        // Backup notification commands
+        get_notification_settings,
+        save_notification_settings,
+        send_test_notification,
+        send_backup_summary,
+        // Hybrid Avatar commands
+        save_hybrid_avatar,
+        save_hybrid_avatar_stream, // Phase 1.3: Memory-efficient streaming
+        begin_avatar_upload,
+        append_avatar_upload_chunk,
+        finish_avatar_upload,
+        get_avatar_upload_status,
+        cancel_avatar_upload,
+        get_avatar_policy,
+        save_avatar_policy,
+        get_media_budget_status,
+        set_media_budget,
+        get_avatar_access_audit,
+        set_avatar_access_audit,
+        query_avatar_access_log,
+        get_hybrid_avatar_info,
+        delete_hybrid_avatar,
+        get_avatar_urls,
+        get_hybrid_avatar_base64,
+        get_hybrid_avatar_image,
+        get_avatar_base64_by_user_id,
+        migrate_user_avatar_to_file,
+        cleanup_orphaned_avatar_files,
+        get_media_directory_path,
+        export_avatars_zip,
+        match_photos_to_users,
+        apply_photo_matches,
+        import_referenced_photos,
+        publish_officer_board,
+        generate_contact_sheet,
+        install_dataset_pack,
+        get_ranks,
+        get_position_templates,
+        // Hybrid High Rank Avatar commands
+        save_hybrid_high_rank_avatar,
+        get_hybrid_high_rank_avatar_info,
+        delete_hybrid_high_rank_avatar,
+        get_hybrid_high_rank_avatar_base64,
+        get_avatar_base64_by_officer_id,
+        cleanup_orphaned_high_rank_avatar_files,
+        // Workspace commands
+        list_workspaces,
+        create_workspace,
+        switch_workspace,
+        // Media reconciliation commands
+        cleanup_all_media,
+        get_operation_history,
+        reconcile_media,
+        reconcile_media_references,
+        apply_media_fixes,
+        adopt_media_file,
+        remove_untracked_media_file,
+        transfer_avatar,
+        transfer_high_rank_avatar,
+        get_officer_signature,
+        save_officer_signature,
+        delete_officer_signature,
+        stamp_officer_signature,
+        get_avatar_review_queue,
+        review_avatar,
+        find_duplicate_media,
+        verify_avatar_images,
+        // Test cleanup commands
+        delete_test_users,
+        get_users_count,
+        // Database initialization command
+        initialize_database_if_needed,
+        initialize_content_database,
+        seed_content_database,
+        create_new_document,
+        generate_document_id_preview,
+        get_owner_units,
+        search_documents,
+        delete_document,
+        update_document,
+        get_document_questions,
+        get_document_questions_with_details, // New command
+        create_question,                     // Restored
+        update_question,                     // New command
+        delete_question,                     // New command
+        upload_question_image,               // New image upload command
+        delete_question_image,               // New image delete command
+        resolve_image_path,                  // New path resolver command
+        get_question_image_base64,           // New base64 image command
+        reorder_questions,                   // Reorder command
+        get_document_with_hierarchy,
+        // Section management
+        create_section,
+        get_sections_by_document,
+        delete_section,
+        update_section_order,
+        update_section,
+        migrate_section_101,
+        // Reference management
+        create_reference,
+        get_references,
+        update_reference,
+        delete_reference,
+        delete_all_references,
+        add_section_reference,
+        remove_section_reference,
+        get_section_references,
+        add_question_reference,
+        remove_question_reference,
+        update_question_reference_location,
+        // QuestionSectionLinks (3xx.1.4/1.5 → 100/200 Sections)
+        add_question_section_link,
+        batch_add_question_section_links,
+        remove_question_section_link,
+        remove_all_question_section_links,
+        get_question_section_links,
+        update_section_link_score,
+        recalculate_section_link_scores,
+        migrate_question_children_to_section_links,
+        get_document_stats,
+        open_path,
+        show_in_folder,
+        // Occupation Branch management
+        get_occupation_branches,
+        create_occupation_branch,
+        update_occupation_branch,
+        delete_occupation_branch,
+        get_occupation_sub_branches,
+        create_occupation_sub_branch,
+        update_occupation_sub_branch,
+        delete_occupation_sub_branch,
+        get_occupation_sub_questions,
+        get_all_sub_questions_for_branch,
+        create_occupation_sub_question,
+        update_occupation_sub_question,
+        delete_occupation_sub_question,
+        delete_occupation_sub_questions_by_sub_branch,
+        reorder_occupation_sub_questions,
+        batch_create_occupation_sub_questions,
+        get_standard_branch_sub_questions,
+        toggle_slot_completion,
+        get_slot_completion_map,
+        get_all_completed_branch_pairs,
+        // Section-Ref L3 Children (3xx.1.4/1.5 → real L3 Questions)
+        get_section_ref_children,
+        get_back_referencing_section_ids,
+        add_section_ref_child,
+        batch_add_section_ref_children,
+        remove_section_ref_child,
+        remove_all_section_ref_children,
+        update_section_ref_score,
+        migrate_section_links_to_ref_children,
+        // Required Count Children (3xx.2-3xx.6 L3 "ครั้งที่ X")
+        get_required_count_children,
+        sync_required_count_children,
+        check_has_children,
+        // Scoring & User Progress
+        calculate_section_total_score,
+        batch_recalculate_section_group_scores,
+        upsert_user_progress,
+        get_user_progress,
+        calculate_group_score,
+        update_question_score,
+        // Document Branch (Occupation Branch at document level)
+        get_document_branch,
+        update_document_branch,
+        check_career_branch_usage,
+        reset_and_update_career_branch,
+        check_branch_usage_global,
+        check_sub_branch_usage_global,
+        save_trainee_answer,
+        save_qualifier_assessment,
+        get_trainee_answers,
+        content_database::clear_all_trainee_answers,
+        content_database::get_sub_question_usage_counts,
+        content_database::get_section_progress,
+        content_database::get_attachment_preview,
+        content_database::get_section_dev_metrics,
+        content_database::get_question_answer_keys,
+        content_database::update_answer_key,
+        content_database::replace_question_answer_keys,
+    ];
+
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![
-            greet,
-            get_all_users,
-            get_user_by_id,
-            get_user_by_email,
-            create_user,
-            update_user,
-            delete_user,
-            authenticate_user,
-            migrate_passwords,
-            zoom_in,
-            zoom_out,
-            zoom_reset,
-            get_all_high_ranking_officers,
-            update_high_ranking_officer,
-            hash_password,
-            // Database backup/restore commands
-            create_database_backup,
-            restore_database_backup,
-            list_database_backups,
-            delete_database_backup,
-            // Database export/import commands
-            export_database,
-            import_database,
-            list_database_exports,
-            delete_database_export,
-            // Universal SQLite backup commands
-            create_universal_sqlite_backup,
-            create_standard_sql_dump,
-            // Hybrid backup commands (Database + Media)
-            create_hybrid_backup,
-            import_hybrid_backup,
-            discover_hybrid_backups,
-            delete_hybrid_backup,
-            check_backup_for_initialization,
-            check_system_state_for_initialization,
-            // File export commands
-            export_backup_to_location,
-            export_hybrid_backup_to_location,
-            export_sql_to_location,
-            copy_sql_export_to_location,
-            // Backup management commands
-            copy_backup_to_location,
-            get_backup_directory_path,
-            list_backup_files_with_paths,
-            get_backup_file_info,
-            // Hybrid Avatar commands
-            save_hybrid_avatar,
-            save_hybrid_avatar_stream, // Phase 1.3: Memory-efficient streaming
-            get_hybrid_avatar_info,
-            delete_hybrid_avatar,
-            get_hybrid_avatar_base64,
-            migrate_user_avatar_to_file,
-            cleanup_orphaned_avatar_files,
-            get_media_directory_path,
-            // Hybrid High Rank Avatar commands
-            save_hybrid_high_rank_avatar,
-            get_hybrid_high_rank_avatar_info,
-            delete_hybrid_high_rank_avatar,
-            get_hybrid_high_rank_avatar_base64,
-            cleanup_orphaned_high_rank_avatar_files,
-            // Test cleanup commands
-            delete_test_users,
-            get_users_count,
-            // Database initialization command
-            initialize_database_if_needed,
-            initialize_content_database,
-            seed_content_database,
-            create_new_document,
-            generate_document_id_preview,
-            get_owner_units,
-            search_documents,
-            delete_document,
-            update_document,
-            get_document_questions,
-            get_document_questions_with_details, // New command
-            create_question,                     // Restored
-            update_question,                     // New command
-            delete_question,                     // New command
-            upload_question_image,               // New image upload command
-            delete_question_image,               // New image delete command
-            resolve_image_path,                  // New path resolver command
-            get_question_image_base64,           // New base64 image command
-            reorder_questions,                   // Reorder command
-            get_document_with_hierarchy,
-            // Section management
-            create_section,
-            get_sections_by_document,
-            delete_section,
-            update_section_order,
-            update_section,
-            migrate_section_101,
-            // Reference management
-            create_reference,
-            get_references,
-            update_reference,
-            delete_reference,
-            delete_all_references,
-            add_section_reference,
-            remove_section_reference,
-            get_section_references,
-            add_question_reference,
-            remove_question_reference,
-            update_question_reference_location,
-            // QuestionSectionLinks (3xx.1.4/1.5 → 100/200 Sections)
-            add_question_section_link,
-            batch_add_question_section_links,
-            remove_question_section_link,
-            remove_all_question_section_links,
-            get_question_section_links,
-            update_section_link_score,
-            recalculate_section_link_scores,
-            migrate_question_children_to_section_links,
-            get_document_stats,
-            open_path,
-            show_in_folder,
-            // Occupation Branch management
-            get_occupation_branches,
-            create_occupation_branch,
-            update_occupation_branch,
-            delete_occupation_branch,
-            get_occupation_sub_branches,
-            create_occupation_sub_branch,
-            update_occupation_sub_branch,
-            delete_occupation_sub_branch,
-            get_occupation_sub_questions,
-            get_all_sub_questions_for_branch,
-            create_occupation_sub_question,
-            update_occupation_sub_question,
-            delete_occupation_sub_question,
-            delete_occupation_sub_questions_by_sub_branch,
-            reorder_occupation_sub_questions,
-            batch_create_occupation_sub_questions,
-            get_standard_branch_sub_questions,
-            toggle_slot_completion,
-            get_slot_completion_map,
-            get_all_completed_branch_pairs,
-            // Section-Ref L3 Children (3xx.1.4/1.5 → real L3 Questions)
-            get_section_ref_children,
-            get_back_referencing_section_ids,
-            add_section_ref_child,
-            batch_add_section_ref_children,
-            remove_section_ref_child,
-            remove_all_section_ref_children,
-            update_section_ref_score,
-            migrate_section_links_to_ref_children,
-            // Required Count Children (3xx.2-3xx.6 L3 "ครั้งที่ X")
-            get_required_count_children,
-            sync_required_count_children,
-            check_has_children,
-            // Scoring & User Progress
-            calculate_section_total_score,
-            batch_recalculate_section_group_scores,
-            upsert_user_progress,
-            get_user_progress,
-            calculate_group_score,
-            update_question_score,
-            // Document Branch (Occupation Branch at document level)
-            get_document_branch,
-            update_document_branch,
-            check_career_branch_usage,
-            reset_and_update_career_branch,
-            check_branch_usage_global,
-            check_sub_branch_usage_global,
-            save_trainee_answer,
-            save_qualifier_assessment,
-            get_trainee_answers,
-            content_database::clear_all_trainee_answers,
-            content_database::get_sub_question_usage_counts,
-            content_database::get_section_progress,
-            content_database::get_section_dev_metrics,
-            content_database::get_question_answer_keys,
-            content_database::update_answer_key,
-            content_database::replace_question_answer_keys,
-        ])
+        .register_uri_scheme_protocol(avatar_protocol::SCHEME, |_app, request| {
+            avatar_protocol::handle_request(request)
+        })
+        .on_window_event(|event| {
+            if let tauri::WindowEvent::Destroyed = event.event() {
+                record_subscriptions::unsubscribe_window(event.window().label());
+            }
+        })
+        .invoke_handler(move |invoke| {
+            if let Err(e) =
+                ipc_guard::check_payload(invoke.message.command(), invoke.message.payload())
+            {
+                invoke.resolver.reject(e);
+                return;
+            }
+            // Maintenance mode refuses everything that is not a read
+            if let Err(e) = maintenance_mode::check_command_allowed(invoke.message.command()) {
+                invoke.resolver.reject(e);
+                return;
+            }
+            if let Err(e) = permissions::check_command_allowed(
+                invoke.message.command(),
+                invoke.message.payload(),
+            ) {
+                invoke.resolver.reject(e);
+                return;
+            }
+            let read_only = maintenance_mode::is_read_only_command(invoke.message.command());
+            commands(invoke);
+            // Sync commands have finished by now; async writers invalidate
+            // themselves once their work is done (see `invalidate_after`)
+            if !read_only {
+                read_cache::invalidate();
+            }
+        })
         .setup(|app| {
+            // Record this instance so a second copy can be told apart from a crash
+            if let Err(e) = db_lock::register_instance() {
+                logger::warn(format!("Failed to register app instance: {}", e));
+            }
+
+            // Summary of the steps below, sent to the page once the window shows
+            let mut startup = startup_report::StartupReport::default();
+
+            // Fresh installs pick up data left under the old directory name
+            match legacy_migration::migrate_legacy_data_on_startup() {
+                Ok(Some(report)) => {
+                    logger::info(format!(
+                        "Legacy data migrated automatically: database {}",
+                        report.database_action
+                    ));
+                    startup.legacy_data_found = true;
+                    startup.legacy_data_migrated = true;
+                }
+                Ok(None) => startup.legacy_data_found = startup_report::legacy_data_left(),
+                Err(e) => startup.warn("Failed to migrate legacy data", &e),
+            }
+
+            // Bring an existing main database up to the current schema
+            startup.schema_version_before = startup_report::read_schema_version();
+            if let Err(e) = database::migrate_existing_database() {
+                startup.warn("Failed to migrate database schema", &e);
+            }
+            startup.schema_version = startup_report::read_schema_version();
+            startup.database_valid = startup.schema_version.is_some();
+
             // Initialize content database (OwnerUnits, Documents, etc.)
             if let Err(e) = content_database::initialize_content_database() {
                 logger::error(format!("Failed to initialize content database: {}", e));
@@ -1529,10 +3617,63 @@ fn main() {
             }
 
             // Initialize FileManager to ensure directories exist (singleton)
-            if let Err(e) = file_manager::FileManager::get_instance() {
-                logger::warn(format!("Failed to initialize file manager: {}", e));
-                logger::warn("Avatar operations may not work correctly");
+            match file_manager::FileManager::get_instance() {
+                Ok(manager) => {
+                    startup.media_ok = true;
+                    startup.media_directory =
+                        Some(manager.get_media_directory().to_string_lossy().to_string());
+
+                    // Watch for media files added or removed outside the app
+                    if let Err(e) = media_watcher::start_media_watcher(
+                        app.handle(),
+                        manager.get_media_directory().clone(),
+                    ) {
+                        logger::warn(format!("Failed to start media watcher: {}", e));
+                    }
+                }
+                Err(e) => {
+                    startup.warn("Failed to initialize file manager", &e);
+                    logger::warn("Avatar operations may not work correctly");
+                }
+            }
+
+            // Warn early when media already fills most of its budget
+            let handle = app.handle();
+            std::thread::spawn(move || media_budget::check_media_budget(&handle));
+
+            // Nag until the seeded admin password has been changed
+            admin_password::warn_if_default_admin_password(app.handle());
+
+            // Optimize the database periodically while the app is idle
+            db_maintenance::start_maintenance_scheduler();
+
+            // Targeted events for rows that detail views subscribed to
+            record_subscriptions::start_record_watcher(app.handle());
+
+            // Orphan cleanup, retention and previews once nobody is using the app
+            let scheduler = task_scheduler::TaskScheduler::with_default_tasks();
+            scheduler.start();
+            app.manage(scheduler);
+
+            // Send the daily backup summary when notifications are configured
+            backup_notify::start_notification_scheduler();
+
+            // Drop staged media files, backup sandboxes and temp dirs left behind by an earlier run
+            file_transaction::cleanup_staging_area();
+            backup_sandbox::cleanup_stale_sandboxes();
+            match temp_space::cleanup_temp_at_startup() {
+                Ok(removed) => startup.leftovers_removed = removed,
+                Err(e) => startup.warn("Failed to clean temp directories", &e),
+            }
+            match chunked_upload::cleanup_stale_uploads() {
+                Ok(removed) => startup.leftovers_removed += removed,
+                Err(e) => startup.warn("Failed to clean abandoned uploads", &e),
+            }
+            match pending_deletions::retry_pending_deletions() {
+                Ok(removed) => startup.pending_deletions_retried = removed,
+                Err(e) => startup.warn("Failed to retry pending deletions", &e),
             }
+            startup_report::check_backups(&mut startup);
 
             // Show window after it's ready (prevents flickering)
             if let Some(window) = app.get_window("main") {
@@ -1548,6 +3689,7 @@ fn main() {
             } else {
                 logger::warn("Main window not found");
             }
+            startup_report::publish(&app.handle(), startup);
 
             Ok(())
         })
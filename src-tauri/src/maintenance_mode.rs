@@ -0,0 +1,251 @@
+//! Soft maintenance mode: reads keep working, changes are refused
+//!
+//! Used while an administrator fixes data by hand or a scheduled backup runs
+//! on a shared terminal. The flag lives in `maintenance.json` in the
+//! workspace directory, next to the database, so every copy of the app
+//! working on the same data sees it. Commands are gated in the invoke
+//! handler: anything not known to be read-only is rejected with a
+//! MAINTENANCE_MODE coded error carrying the administrator's message.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error_codes::{self, MAINTENANCE_MODE};
+use crate::storage_paths;
+
+const MAINTENANCE_FILE_NAME: &str = "maintenance.json";
+
+const DEFAULT_MESSAGE: &str = "The system is under maintenance. Changes are disabled for now.";
+
+/// Commands starting with these only read data (or copy it out, like backups)
+const READ_ONLY_PREFIXES: &[&str] = &[
+    "get_",
+    "list_",
+    "search_",
+    "check_",
+    "detect_",
+    "discover_",
+    "has_",
+    "query_",
+    "diagnose_",
+    "find_",
+    "reconcile_",
+    "match_",
+    "calculate_",
+    "generate_",
+    "resolve_",
+    "sandbox_",
+    "cancel_",
+    "zoom_",
+    "export_",
+    "create_database_backup",
+    "create_hybrid_backup",
+    "create_encrypted_backup",
+    "create_universal_sqlite_backup",
+    "create_standard_sql_dump",
+];
+
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "greet",
+    // Signing in records a session and an activity entry, but must keep
+    // working; the password rehash on sign-in waits for maintenance to end
+    "authenticate_user",
+    "sign_out",
+    "login",
+    "validate_session",
+    "logout",
+    "apply_user_preferences",
+    "hash_password",
+    "count_users",
+    "copy_users_to_clipboard",
+    "run_saved_view",
+    "rehearse_import_database",
+    "open_backup_sandbox",
+    "preview_restore",
+    "close_backup_sandbox",
+    "copy_sql_export_to_location",
+    "copy_backup_to_location",
+    "choose_backup_destination",
+    "choose_backup_to_restore",
+    "subscribe_record",
+    "unsubscribe_record",
+    "test_sftp_connection",
+    "reveal_export_in_explorer",
+    "open_path",
+    "show_in_folder",
+    // Must stay reachable to switch the mode off again
+    "set_maintenance_mode",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MaintenanceMode {
+    pub enabled: bool,
+    /// Shown to users whose changes are refused
+    pub message: Option<String>,
+    pub enabled_at: Option<String>,
+}
+
+impl MaintenanceMode {
+    fn refusal(&self) -> String {
+        let message = self
+            .message
+            .as_deref()
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .unwrap_or(DEFAULT_MESSAGE);
+        error_codes::with_code(MAINTENANCE_MODE, message)
+    }
+}
+
+pub fn is_read_only_command(command: &str) -> bool {
+    READ_ONLY_COMMANDS.contains(&command)
+        || READ_ONLY_PREFIXES
+            .iter()
+            .any(|prefix| command.starts_with(prefix))
+}
+
+fn maintenance_file() -> Result<PathBuf, String> {
+    Ok(storage_paths::get_workspace_dir()?.join(MAINTENANCE_FILE_NAME))
+}
+
+/// Missing or unreadable file means maintenance mode is off
+pub fn load_mode_from(path: &Path) -> MaintenanceMode {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_mode_at(
+    path: &Path,
+    enabled: bool,
+    message: Option<String>,
+) -> Result<MaintenanceMode, String> {
+    if !enabled {
+        if path.exists() {
+            fs::remove_file(path)
+                .map_err(|e| format!("Failed to turn off maintenance mode: {}", e))?;
+        }
+        return Ok(MaintenanceMode::default());
+    }
+
+    let mode = MaintenanceMode {
+        enabled: true,
+        message,
+        enabled_at: Some(Utc::now().to_rfc3339()),
+    };
+    let content = serde_json::to_string_pretty(&mode)
+        .map_err(|e| format!("Failed to serialize maintenance mode: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create workspace directory: {}", e))?;
+    }
+    fs::write(path, content).map_err(|e| format!("Failed to turn on maintenance mode: {}", e))?;
+    Ok(mode)
+}
+
+/// Whether maintenance mode is on; unreadable means off, like `load_mode_from`
+pub fn is_enabled() -> bool {
+    maintenance_file()
+        .map(|path| load_mode_from(&path).enabled)
+        .unwrap_or(false)
+}
+
+/// Refuse `command` while maintenance mode is on, unless it only reads
+pub fn check_command_at(path: &Path, command: &str) -> Result<(), String> {
+    if is_read_only_command(command) {
+        return Ok(());
+    }
+    let mode = load_mode_from(path);
+    if mode.enabled {
+        return Err(mode.refusal());
+    }
+    Ok(())
+}
+
+pub fn get_maintenance_mode() -> Result<MaintenanceMode, String> {
+    Ok(load_mode_from(&maintenance_file()?))
+}
+
+pub fn set_maintenance_mode(
+    enabled: bool,
+    message: Option<String>,
+) -> Result<MaintenanceMode, String> {
+    set_mode_at(&maintenance_file()?, enabled, message)
+}
+
+/// Gate used by the invoke handler for every command
+pub fn check_command_allowed(command: &str) -> Result<(), String> {
+    if is_read_only_command(command) {
+        return Ok(());
+    }
+    check_command_at(&maintenance_file()?, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_classifies_commands() {
+        for command in [
+            "get_all_users",
+            "list_database_backups",
+            "create_hybrid_backup",
+            "export_database",
+            "set_maintenance_mode",
+            "apply_user_preferences",
+            "login",
+        ] {
+            assert!(
+                is_read_only_command(command),
+                "{} should be allowed",
+                command
+            );
+        }
+        for command in [
+            "create_user",
+            "delete_user",
+            "save_hybrid_avatar",
+            "import_hybrid_backup",
+            "restore_database_backup",
+            "set_user_preferences",
+            "publish_officer_board",
+            "stamp_officer_signature",
+            "upload_backup_to_sftp",
+            "send_test_notification",
+            "send_backup_summary",
+        ] {
+            assert!(
+                !is_read_only_command(command),
+                "{} should be blocked",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_blocks_changes_only_while_enabled() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join(MAINTENANCE_FILE_NAME);
+        assert!(check_command_at(&path, "create_user").is_ok());
+
+        set_mode_at(
+            &path,
+            true,
+            Some("Backup in progress until 14:00".to_string()),
+        )
+        .expect("mode should be set");
+        let error = check_command_at(&path, "create_user").unwrap_err();
+        assert_eq!(error_codes::find_code(&error), Some(MAINTENANCE_MODE));
+        assert!(error.contains("Backup in progress until 14:00"));
+        assert!(check_command_at(&path, "get_all_users").is_ok());
+
+        set_mode_at(&path, false, None).expect("mode should be cleared");
+        assert!(!load_mode_from(&path).enabled);
+        assert!(check_command_at(&path, "create_user").is_ok());
+    }
+}
@@ -0,0 +1,187 @@
+//! Size budget for avatars and attachments
+//!
+//! Terminals with small SSDs fill up with photos and reference files long
+//! before anyone notices. An administrator sets a budget in settings; after
+//! media changes (and once at startup) the media and attachment folders are
+//! measured, and crossing the warning or critical share of the budget emits
+//! `media://budget` and records a `media_budget` event in the activity log.
+//! Only rising levels are announced, once each, until usage drops again.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+use crate::activity_log::{self, EVENT_MEDIA_BUDGET};
+use crate::disk_space;
+use crate::logger;
+use crate::settings::{self, MediaBudget};
+use crate::storage_paths;
+
+pub const MEDIA_BUDGET_EVENT: &str = "media://budget";
+
+lazy_static! {
+    // Level last announced, so each crossing is reported once
+    static ref LAST_LEVEL: Mutex<BudgetLevel> = Mutex::new(BudgetLevel::Ok);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MediaUsage {
+    pub media_bytes: u64,
+    pub attachment_bytes: u64,
+    pub total_bytes: u64,
+    /// None when no budget is configured
+    pub budget_bytes: Option<u64>,
+    pub percent_used: Option<f64>,
+    pub level: BudgetLevel,
+}
+
+pub fn validate_budget(budget: &MediaBudget) -> Result<(), String> {
+    if budget.budget_bytes == 0 {
+        return Err("Media budget must be greater than zero".to_string());
+    }
+    if !(1..=100).contains(&budget.critical_percent)
+        || budget.warning_percent >= budget.critical_percent
+    {
+        return Err(
+            "Warning level must be below the critical level, which is at most 100%".to_string(),
+        );
+    }
+    Ok(())
+}
+
+pub fn budget_level(total_bytes: u64, budget: &MediaBudget) -> BudgetLevel {
+    let percent = total_bytes as f64 * 100.0 / budget.budget_bytes.max(1) as f64;
+    if percent >= f64::from(budget.critical_percent) {
+        BudgetLevel::Critical
+    } else if percent >= f64::from(budget.warning_percent) {
+        BudgetLevel::Warning
+    } else {
+        BudgetLevel::Ok
+    }
+}
+
+/// Measure the media and attachment folders against the configured budget
+pub fn media_usage() -> Result<MediaUsage, String> {
+    let media_dir = storage_paths::get_media_dir()?;
+    let media_bytes = disk_space::directory_size(&media_dir);
+    let attachment_bytes = match crate::content_database::get_portable_data_dir() {
+        // Portable mode keeps media inside the attachment folder; the whole
+        // folder is what fills the drive, so it counts once, media included
+        Ok(dir) if media_dir.starts_with(&dir) => {
+            disk_space::directory_size(&dir).saturating_sub(media_bytes)
+        }
+        Ok(dir) => disk_space::directory_size(&dir),
+        Err(_) => 0,
+    };
+    let total_bytes = media_bytes + attachment_bytes;
+    let budget = settings::load_settings()?.media_budget;
+
+    Ok(MediaUsage {
+        media_bytes,
+        attachment_bytes,
+        total_bytes,
+        budget_bytes: budget.as_ref().map(|b| b.budget_bytes),
+        percent_used: budget
+            .as_ref()
+            .map(|b| total_bytes as f64 * 100.0 / b.budget_bytes.max(1) as f64),
+        level: budget
+            .as_ref()
+            .map_or(BudgetLevel::Ok, |b| budget_level(total_bytes, b)),
+    })
+}
+
+/// Remember `level`; true when it is higher than the one announced last
+fn is_new_crossing(last: &mut BudgetLevel, level: BudgetLevel) -> bool {
+    let crossed = level > *last;
+    *last = level;
+    crossed
+}
+
+/// Measure usage and announce a newly crossed threshold
+pub fn check_media_budget(app: &AppHandle) {
+    let usage = match media_usage() {
+        Ok(usage) => usage,
+        Err(e) => {
+            logger::warn(format!("Failed to measure media usage: {}", e));
+            return;
+        }
+    };
+    let crossed = match LAST_LEVEL.lock() {
+        Ok(mut last) => is_new_crossing(&mut last, usage.level),
+        Err(_) => false,
+    };
+    if !crossed {
+        return;
+    }
+
+    logger::warn(format!(
+        "Media uses {:.0}% of its budget ({} of {} bytes)",
+        usage.percent_used.unwrap_or_default(),
+        usage.total_bytes,
+        usage.budget_bytes.unwrap_or_default()
+    ));
+    if let Ok(details) = serde_json::to_string(&usage) {
+        activity_log::record_event(EVENT_MEDIA_BUDGET, None, None, Some(&details));
+    }
+    if let Err(e) = app.emit_all(MEDIA_BUDGET_EVENT, &usage) {
+        logger::warn(format!("Failed to emit media budget event: {}", e));
+    }
+}
+
+/// None removes the budget
+pub fn set_media_budget(budget: Option<MediaBudget>) -> Result<(), String> {
+    if let Some(ref budget) = budget {
+        validate_budget(budget)?;
+    }
+    settings::update_settings(|settings| settings.media_budget = budget)?;
+    // Announce again against the new thresholds
+    if let Ok(mut last) = LAST_LEVEL.lock() {
+        *last = BudgetLevel::Ok;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_are_announced_once_per_crossing() {
+        let budget = MediaBudget {
+            budget_bytes: 1000,
+            warning_percent: 80,
+            critical_percent: 95,
+        };
+        assert_eq!(budget_level(799, &budget), BudgetLevel::Ok);
+        assert_eq!(budget_level(800, &budget), BudgetLevel::Warning);
+        assert_eq!(budget_level(2000, &budget), BudgetLevel::Critical);
+
+        let mut last = BudgetLevel::Ok;
+        assert!(is_new_crossing(&mut last, BudgetLevel::Warning));
+        assert!(!is_new_crossing(&mut last, BudgetLevel::Warning));
+        assert!(is_new_crossing(&mut last, BudgetLevel::Critical));
+        assert!(!is_new_crossing(&mut last, BudgetLevel::Warning));
+        assert!(is_new_crossing(&mut last, BudgetLevel::Critical));
+
+        assert!(validate_budget(&budget).is_ok());
+        assert!(validate_budget(&MediaBudget {
+            warning_percent: 95,
+            ..budget.clone()
+        })
+        .is_err());
+        assert!(validate_budget(&MediaBudget {
+            budget_bytes: 0,
+            ..budget
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Extension points in the avatar save and delete pipeline
+//!
+//! Both avatar managers run every registered `MediaHook`: `on_save` sees the
+//! image after the upload policy accepted it and before anything is written,
+//! and may change the bytes (face crop, unit insignia watermark) or refuse
+//! the save; `on_saved` and `on_delete` are told about the result once the
+//! database points at the new file or no longer points at any. Changed bytes
+//! go through the avatar policy again, so a hook cannot store an image the
+//! policy would refuse, and the stored format and dimensions stay right.
+//! Hooks are registered once at startup with `register_hook`.
+
+use lazy_static::lazy_static;
+use std::sync::{Arc, RwLock};
+
+use crate::avatar_policy::{self, PreparedAvatar};
+use crate::logger;
+use crate::settings::AvatarPolicy;
+
+/// Image about to be stored for `owner_type` (OWNER_USER, OWNER_OFFICER) `owner_id`
+#[allow(dead_code)] // Fields are read by hooks; none ships in the app yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingAvatar {
+    pub owner_type: String,
+    pub owner_id: i32,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An avatar that was stored or removed
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaEvent {
+    pub owner_type: String,
+    pub owner_id: i32,
+    /// Media-relative path of the new file; None after a delete
+    pub avatar_path: Option<String>,
+}
+
+pub trait MediaHook: Send + Sync {
+    /// Shown in logs and errors
+    fn name(&self) -> &str;
+
+    /// Change `avatar` in place, or refuse the save with an error
+    fn on_save(&self, _avatar: &mut PendingAvatar) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_saved(&self, _event: &MediaEvent) {}
+
+    fn on_delete(&self, _event: &MediaEvent) {}
+}
+
+lazy_static! {
+    static ref HOOKS: RwLock<Vec<Arc<dyn MediaHook>>> = RwLock::new(Vec::new());
+}
+
+#[allow(dead_code)]
+pub fn register_hook(hook: Arc<dyn MediaHook>) {
+    match HOOKS.write() {
+        Ok(mut hooks) => {
+            logger::info(format!("Media hook registered: {}", hook.name()));
+            hooks.push(hook);
+        }
+        Err(_) => logger::warn(format!("Failed to register media hook: {}", hook.name())),
+    }
+}
+
+fn registered_hooks() -> Vec<Arc<dyn MediaHook>> {
+    HOOKS.read().map(|hooks| hooks.clone()).unwrap_or_default()
+}
+
+/// `avatar_policy::prepare_avatar` followed by `hooks`' `on_save`
+pub fn prepare_with(
+    hooks: &[Arc<dyn MediaHook>],
+    policy: &AvatarPolicy,
+    owner_type: &str,
+    owner_id: i32,
+    data: &[u8],
+    mime_type: &str,
+) -> Result<PreparedAvatar, String> {
+    let prepared = avatar_policy::prepare_avatar(policy, data, mime_type)?;
+    if hooks.is_empty() {
+        return Ok(prepared);
+    }
+
+    let mut pending = PendingAvatar {
+        owner_type: owner_type.to_string(),
+        owner_id,
+        mime_type: prepared.mime_type.clone(),
+        data: prepared.data.clone(),
+    };
+    for hook in hooks {
+        hook.on_save(&mut pending)
+            .map_err(|e| format!("Avatar refused by {}: {}", hook.name(), e))?;
+    }
+    if pending.data == prepared.data && pending.mime_type == prepared.mime_type {
+        return Ok(prepared);
+    }
+
+    // The hooks' image has to pass the same rules as an upload
+    let mut changed = avatar_policy::prepare_avatar(policy, &pending.data, &pending.mime_type)?;
+    changed.report.original_width = prepared.report.original_width;
+    changed.report.original_height = prepared.report.original_height;
+    changed.report.original_size = prepared.report.original_size;
+    changed.report.detected_mime = prepared.report.detected_mime;
+    changed.report.reencoded = true;
+    Ok(changed)
+}
+
+/// `prepare_with` the registered hooks
+pub fn prepare_avatar(
+    policy: &AvatarPolicy,
+    owner_type: &str,
+    owner_id: i32,
+    data: &[u8],
+    mime_type: &str,
+) -> Result<PreparedAvatar, String> {
+    prepare_with(
+        &registered_hooks(),
+        policy,
+        owner_type,
+        owner_id,
+        data,
+        mime_type,
+    )
+}
+
+pub fn notify_saved(owner_type: &str, owner_id: i32, avatar_path: &str) {
+    let event = MediaEvent {
+        owner_type: owner_type.to_string(),
+        owner_id,
+        avatar_path: Some(avatar_path.to_string()),
+    };
+    for hook in registered_hooks() {
+        hook.on_saved(&event);
+    }
+}
+
+pub fn notify_deleted(owner_type: &str, owner_id: i32) {
+    let event = MediaEvent {
+        owner_type: owner_type.to_string(),
+        owner_id,
+        avatar_path: None,
+    };
+    for hook in registered_hooks() {
+        hook.on_delete(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media_maintenance::OWNER_USER;
+
+    fn png(size: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(size, size)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        png
+    }
+
+    /// Stands in for a face crop: always stores a 4x4 image
+    struct CropHook;
+
+    impl MediaHook for CropHook {
+        fn name(&self) -> &str {
+            "crop"
+        }
+
+        fn on_save(&self, avatar: &mut PendingAvatar) -> Result<(), String> {
+            avatar.data = png(4);
+            Ok(())
+        }
+    }
+
+    struct RefuseOfficers;
+
+    impl MediaHook for RefuseOfficers {
+        fn name(&self) -> &str {
+            "refuse-officers"
+        }
+
+        fn on_save(&self, avatar: &mut PendingAvatar) -> Result<(), String> {
+            if avatar.owner_type == OWNER_USER {
+                Ok(())
+            } else {
+                Err("officers are not allowed".to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_hooks_change_or_refuse_avatars() {
+        let policy = AvatarPolicy::default();
+        let upload = png(8);
+
+        let unchanged = prepare_with(&[], &policy, OWNER_USER, 1, &upload, "image/png").unwrap();
+        assert!(!unchanged.report.reencoded);
+
+        let hooks: Vec<Arc<dyn MediaHook>> = vec![Arc::new(RefuseOfficers), Arc::new(CropHook)];
+        let cropped = prepare_with(&hooks, &policy, OWNER_USER, 1, &upload, "image/png").unwrap();
+        assert_eq!(cropped.data, png(4));
+        assert_eq!(cropped.report.stored_width, 4);
+        assert_eq!(cropped.report.original_width, 8);
+        assert!(cropped.report.reencoded);
+
+        let refused =
+            prepare_with(&hooks, &policy, "officer", 1, &upload, "image/png").unwrap_err();
+        assert!(refused.contains("refuse-officers"));
+    }
+}
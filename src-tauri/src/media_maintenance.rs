@@ -0,0 +1,1225 @@
+//! Reconcile media files on disk against avatar references in the database
+//!
+//! Files can appear or disappear behind the app's back (manual copies,
+//! restores, antivirus quarantine). These helpers find untracked files and
+//! dangling references, and let the UI adopt or clean them.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::avatar_approval;
+use crate::avatar_policy::{self, ImageMetadata};
+use crate::database::get_connection_safe;
+use crate::file_manager::{
+    self, DirectoryCleanup, FileManager, AVATARS_SUBDIR, HIGH_RANKS_SUBDIR, SIGNATURES_SUBDIR,
+};
+use crate::file_transaction::STAGING_DIR_NAME;
+use crate::logger;
+use crate::permissions::Caller;
+use crate::progress::ProgressReporter;
+use crate::safe_path::{MediaRoot, SafePath};
+
+pub const OWNER_USER: &str = "user";
+pub const OWNER_OFFICER: &str = "officer";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MissingMedia {
+    pub owner_type: String,
+    pub owner_id: i32,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MediaReconciliation {
+    /// Files on disk that no user or officer references
+    pub untracked_files: Vec<String>,
+    /// Database references whose file no longer exists
+    pub missing_files: Vec<MissingMedia>,
+}
+
+/// Stored paths use the platform separator; compare them in one canonical form
+pub fn normalize_media_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Table holding the avatar reference for an owner type
+pub fn owner_table(owner_type: &str) -> Result<&'static str, String> {
+    match owner_type {
+        OWNER_USER => Ok("users"),
+        OWNER_OFFICER => Ok("high_ranking_officers"),
+        _ => Err(format!("Unknown media owner type: {}", owner_type)),
+    }
+}
+
+/// All avatar references as (owner_type, owner_id, normalized path)
+pub fn collect_media_references_with_conn(
+    conn: &Connection,
+) -> Result<Vec<(String, i32, String)>, String> {
+    let mut references = Vec::new();
+
+    for owner_type in [OWNER_USER, OWNER_OFFICER] {
+        let table = owner_table(owner_type)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, avatar_path FROM {} WHERE avatar_path IS NOT NULL AND avatar_path != ''",
+                table
+            ))
+            .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| format!("Failed to query avatar paths: {}", e))?;
+
+        for (id, path) in rows.flatten() {
+            references.push((owner_type.to_string(), id, normalize_media_path(&path)));
+        }
+    }
+
+    Ok(references)
+}
+
+/// Every file under the media directory as a normalized relative path
+/// Signatures are tracked by their own table and never count as avatars;
+/// files staged by an unfinished `file_transaction` are not media yet
+pub fn scan_media_files(media_dir: &Path) -> Vec<String> {
+    if !media_dir.exists() {
+        return Vec::new();
+    }
+
+    WalkDir::new(media_dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() != 1
+                || (entry.file_name() != SIGNATURES_SUBDIR && entry.file_name() != STAGING_DIR_NAME)
+        })
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(media_dir)
+                .ok()
+                .map(|rel| normalize_media_path(&rel.to_string_lossy()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaCleanupReport {
+    pub directories: Vec<DirectoryCleanup>,
+    pub total_deleted: u32,
+    pub total_freed_bytes: u64,
+}
+
+/// Remove unreferenced files from every avatar directory
+///
+/// Each directory is checked against the references of both tables, so a
+/// file is kept as long as any user or officer points at it.
+pub fn cleanup_all_media_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+) -> Result<MediaCleanupReport, String> {
+    let valid_paths: Vec<String> = collect_media_references_with_conn(conn)?
+        .into_iter()
+        .map(|(_, _, path)| path)
+        .collect();
+
+    let directories = [AVATARS_SUBDIR, HIGH_RANKS_SUBDIR]
+        .into_iter()
+        .map(|subdir| file_manager::cleanup_orphaned_files_in(media_dir, subdir, &valid_paths))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(MediaCleanupReport {
+        total_deleted: directories.iter().map(|d| d.deleted).sum(),
+        total_freed_bytes: directories.iter().map(|d| d.freed_bytes).sum(),
+        directories,
+    })
+}
+
+pub fn cleanup_all_media() -> Result<MediaCleanupReport, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+
+    let report = cleanup_all_media_with_conn(&conn, file_manager.get_media_directory())?;
+    logger::info(format!(
+        "Media cleanup removed {} orphaned file(s), {} bytes",
+        report.total_deleted, report.total_freed_bytes
+    ));
+    Ok(report)
+}
+
+pub fn reconcile_media_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+) -> Result<MediaReconciliation, String> {
+    let references = collect_media_references_with_conn(conn)?;
+    let files: HashSet<String> = scan_media_files(media_dir).into_iter().collect();
+    let referenced: HashSet<&str> = references.iter().map(|(_, _, p)| p.as_str()).collect();
+
+    let mut untracked_files: Vec<String> = files
+        .iter()
+        .filter(|f| !referenced.contains(f.as_str()))
+        .cloned()
+        .collect();
+    untracked_files.sort();
+
+    let missing_files = references
+        .into_iter()
+        .filter(|(_, _, path)| !files.contains(path))
+        .map(|(owner_type, owner_id, path)| MissingMedia {
+            owner_type,
+            owner_id,
+            path,
+        })
+        .collect();
+
+    Ok(MediaReconciliation {
+        untracked_files,
+        missing_files,
+    })
+}
+
+pub fn reconcile_media() -> Result<MediaReconciliation, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    reconcile_media_with_conn(&conn, file_manager.get_media_directory())
+}
+
+/// A repair offered by `reconcile_media_references`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MediaFix {
+    /// Forget a reference whose file is gone
+    ClearReference {
+        owner_type: String,
+        owner_id: i32,
+        path: String,
+    },
+    /// Link an unreferenced file to the owner its name points at
+    AdoptFile {
+        path: String,
+        owner_type: String,
+        owner_id: i32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MediaReferenceReport {
+    #[serde(flatten)]
+    pub reconciliation: MediaReconciliation,
+    pub proposed_fixes: Vec<MediaFix>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MediaFixResult {
+    pub applied: usize,
+    /// "path: error" for fixes that no longer applied
+    pub failed: Vec<String>,
+}
+
+/// Owner a stored file was saved for, from names like `avatars/avatar_12_1700000000.jpg`
+/// or `high_ranks/officer_3_1700000000.png`, with the timestamp for picking the newest
+fn owner_from_file_name(path: &str) -> Option<(&'static str, i32, i64)> {
+    let (dir, file_name) = path.rsplit_once('/')?;
+    let (owner_type, prefix) = match dir {
+        AVATARS_SUBDIR => (OWNER_USER, "avatar_"),
+        HIGH_RANKS_SUBDIR => (OWNER_OFFICER, "officer_"),
+        _ => return None,
+    };
+    let stem = file_name.strip_prefix(prefix)?.split('.').next()?;
+    let (id, timestamp) = stem.split_once('_')?;
+    Some((owner_type, id.parse().ok()?, timestamp.parse().ok()?))
+}
+
+fn owner_exists(conn: &Connection, owner_type: &str, owner_id: i32) -> Result<bool, String> {
+    conn.query_row(
+        &format!(
+            "SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?)",
+            owner_table(owner_type)?
+        ),
+        params![owner_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to look up {} {}: {}", owner_type, owner_id, e))
+}
+
+/// Cross-check database and media directory after a database-only or
+/// media-only restore. Missing files are offered for clearing, unless an
+/// unreferenced file named after the same owner can replace them; such
+/// files are offered for adoption by owners whose photo is missing or unset.
+/// Nothing is changed until the fixes are passed to `apply_media_fixes`.
+pub fn reconcile_media_references_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+) -> Result<MediaReferenceReport, String> {
+    let reconciliation = reconcile_media_with_conn(conn, media_dir)?;
+    let missing: HashSet<(&str, i32)> = reconciliation
+        .missing_files
+        .iter()
+        .map(|m| (m.owner_type.as_str(), m.owner_id))
+        .collect();
+    let with_working_avatar: HashSet<(String, i32)> = collect_media_references_with_conn(conn)?
+        .into_iter()
+        .filter(|(owner_type, owner_id, _)| !missing.contains(&(owner_type.as_str(), *owner_id)))
+        .map(|(owner_type, owner_id, _)| (owner_type, owner_id))
+        .collect();
+
+    // Newest untracked file per owner without a working photo
+    let mut adoptable: HashMap<(&'static str, i32), (i64, &String)> = HashMap::new();
+    for path in &reconciliation.untracked_files {
+        let Some((owner_type, owner_id, timestamp)) = owner_from_file_name(path) else {
+            continue;
+        };
+        if with_working_avatar.contains(&(owner_type.to_string(), owner_id))
+            || !owner_exists(conn, owner_type, owner_id)?
+        {
+            continue;
+        }
+        let entry = adoptable
+            .entry((owner_type, owner_id))
+            .or_insert((timestamp, path));
+        if timestamp > entry.0 {
+            *entry = (timestamp, path);
+        }
+    }
+
+    let mut proposed_fixes: Vec<MediaFix> = reconciliation
+        .missing_files
+        .iter()
+        .filter(|m| !adoptable.contains_key(&(m.owner_type.as_str(), m.owner_id)))
+        .map(|m| MediaFix::ClearReference {
+            owner_type: m.owner_type.clone(),
+            owner_id: m.owner_id,
+            path: m.path.clone(),
+        })
+        .collect();
+    let mut adoptions: Vec<_> = adoptable.into_iter().collect();
+    adoptions.sort_by(|a, b| (a.1).1.cmp((b.1).1));
+    proposed_fixes.extend(
+        adoptions
+            .into_iter()
+            .map(|((owner_type, owner_id), (_, path))| MediaFix::AdoptFile {
+                path: path.clone(),
+                owner_type: owner_type.to_string(),
+                owner_id,
+            }),
+    );
+
+    Ok(MediaReferenceReport {
+        reconciliation,
+        proposed_fixes,
+    })
+}
+
+pub fn reconcile_media_references() -> Result<MediaReferenceReport, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    reconcile_media_references_with_conn(&conn, file_manager.get_media_directory())
+}
+
+fn clear_missing_reference_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    owner_type: &str,
+    owner_id: i32,
+    path: &str,
+) -> Result<(), String> {
+    if resolve_media_path(media_dir, path)?.full_path().exists() {
+        return Err("The file exists again".to_string());
+    }
+    let assignments: Vec<String> = AVATAR_COLUMNS
+        .iter()
+        .map(|column| format!("{} = NULL", column))
+        .collect();
+    // Only while the owner still points at the same missing file
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE {} SET row_version = row_version + 1, {} WHERE id = ? AND REPLACE(avatar_path, '\\', '/') = ?",
+                owner_table(owner_type)?,
+                assignments.join(", ")
+            ),
+            params![owner_id, normalize_media_path(path)],
+        )
+        .map_err(|e| format!("Failed to clear avatar reference: {}", e))?;
+    if updated == 0 {
+        return Err(format!(
+            "{} {} no longer refers to this file",
+            owner_type, owner_id
+        ));
+    }
+    Ok(())
+}
+
+/// Apply fixes chosen from a `reconcile_media_references` report; each one
+/// is checked again, so a stale report cannot clear a working photo.
+/// Adopted files are reviewed as uploads by `applied_by`.
+pub fn apply_media_fixes_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    fixes: &[MediaFix],
+    applied_by: Option<Caller>,
+) -> Result<MediaFixResult, String> {
+    let mut result = MediaFixResult::default();
+    for fix in fixes {
+        let (path, applied) = match fix {
+            MediaFix::ClearReference {
+                owner_type,
+                owner_id,
+                path,
+            } => (
+                path,
+                clear_missing_reference_with_conn(conn, media_dir, owner_type, *owner_id, path),
+            ),
+            MediaFix::AdoptFile {
+                path,
+                owner_type,
+                owner_id,
+            } => (
+                path,
+                adopt_media_file_with_conn(
+                    conn, media_dir, path, owner_type, *owner_id, applied_by,
+                ),
+            ),
+        };
+        match applied {
+            Ok(()) => result.applied += 1,
+            Err(e) => result.failed.push(format!("{}: {}", path, e)),
+        }
+    }
+    Ok(result)
+}
+
+pub fn apply_media_fixes(
+    fixes: &[MediaFix],
+    applied_by: Option<Caller>,
+) -> Result<MediaFixResult, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    let result =
+        apply_media_fixes_with_conn(&conn, file_manager.get_media_directory(), fixes, applied_by)?;
+    crate::read_cache::invalidate();
+    Ok(result)
+}
+
+/// Reject paths leaving the media directory before touching the disk
+fn resolve_media_path(media_dir: &Path, relative_path: &str) -> Result<SafePath, String> {
+    MediaRoot::new(media_dir)?.resolve(relative_path)
+}
+
+pub fn mime_from_extension(path: &str) -> &'static str {
+    match Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Point an owner's avatar at an existing untracked file
+pub fn adopt_media_file_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    relative_path: &str,
+    owner_type: &str,
+    owner_id: i32,
+    adopted_by: Option<Caller>,
+) -> Result<(), String> {
+    let media_path = resolve_media_path(media_dir, relative_path)?;
+    let normalized = media_path.relative();
+    let table = owner_table(owner_type)?;
+
+    let full_path = media_path.full_path();
+    let metadata = fs::metadata(full_path)
+        .map_err(|e| format!("Media file not found: {} ({})", relative_path, e))?;
+    // Left empty for files that are not readable images; the image scan reports them
+    let image = avatar_policy::read_image_metadata(full_path).ok();
+
+    let updated_at = chrono::Utc::now().to_rfc3339();
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE {} SET row_version = row_version + 1, avatar_path = ?, avatar_updated_at = ?, avatar_mime = ?, avatar_size = ?, avatar_format = ?, avatar_width = ?, avatar_height = ? WHERE id = ?",
+                table
+            ),
+            params![
+                normalized,
+                updated_at,
+                mime_from_extension(normalized),
+                metadata.len() as i64,
+                image.as_ref().map(|i| i.format.clone()),
+                image.as_ref().map(|i| i.width),
+                image.as_ref().map(|i| i.height),
+                owner_id
+            ],
+        )
+        .map_err(|e| format!("Failed to adopt media file: {}", e))?;
+
+    if updated == 0 {
+        return Err(format!("No {} found with ID {}", owner_type, owner_id));
+    }
+
+    avatar_approval::mark_uploaded_with_conn(conn, owner_type, owner_id, adopted_by)
+}
+
+pub fn adopt_media_file(
+    relative_path: &str,
+    owner_type: &str,
+    owner_id: i32,
+    adopted_by: Option<Caller>,
+) -> Result<(), String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    adopt_media_file_with_conn(
+        &conn,
+        file_manager.get_media_directory(),
+        relative_path,
+        owner_type,
+        owner_id,
+        adopted_by,
+    )
+}
+
+/// Delete a file only if nothing in the database references it
+pub fn remove_untracked_media_file_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    relative_path: &str,
+) -> Result<(), String> {
+    let media_path = resolve_media_path(media_dir, relative_path)?;
+    // Only photo directories are maintained here; signatures have their own
+    // table and staged files belong to a running transaction
+    let in_photo_dir = matches!(
+        media_path.relative().split_once('/'),
+        Some((dir, _)) if dir == AVATARS_SUBDIR || dir == HIGH_RANKS_SUBDIR
+    );
+    if !in_photo_dir {
+        return Err(format!(
+            "Only avatar and high rank photos can be removed here: {}",
+            relative_path
+        ));
+    }
+
+    let referenced = collect_media_references_with_conn(conn)?
+        .iter()
+        .any(|(_, _, path)| path == media_path.relative());
+    if referenced {
+        return Err(format!(
+            "Media file is still referenced and cannot be removed: {}",
+            relative_path
+        ));
+    }
+
+    fs::remove_file(media_path.full_path())
+        .map_err(|e| format!("Failed to delete media file: {}", e))
+}
+
+pub fn remove_untracked_media_file(relative_path: &str) -> Result<(), String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    remove_untracked_media_file_with_conn(&conn, file_manager.get_media_directory(), relative_path)
+}
+
+/// Columns describing an owner's avatar, moved together on transfer
+const AVATAR_COLUMNS: &[&str] = &[
+    "avatar_path",
+    "avatar_updated_at",
+    "avatar_mime",
+    "avatar_size",
+    "avatar_format",
+    "avatar_width",
+    "avatar_height",
+    "avatar_status",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AvatarTransfer {
+    pub owner_type: String,
+    pub from_id: i32,
+    pub to_id: i32,
+    pub avatar_path: String,
+    /// The target's previous avatar, deleted once the transfer committed
+    pub replaced_path: Option<String>,
+}
+
+/// Move an avatar reference and its metadata from one owner to another of
+/// the same type; the file itself stays where it is
+pub fn transfer_avatar_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    owner_type: &str,
+    from_id: i32,
+    to_id: i32,
+) -> Result<AvatarTransfer, String> {
+    let table = owner_table(owner_type)?;
+    if from_id == to_id {
+        return Err("Cannot transfer an avatar to the same record".to_string());
+    }
+
+    let avatar_path_of = |id: i32| -> Result<Option<String>, String> {
+        conn.query_row(
+            &format!("SELECT avatar_path FROM {} WHERE id = ?", table),
+            params![id],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                format!("No {} found with ID {}", owner_type, id)
+            }
+            e => format!("Failed to read {} avatar: {}", owner_type, e),
+        })
+    };
+    let avatar_path = avatar_path_of(from_id)?
+        .ok_or_else(|| format!("{} {} has no avatar to transfer", owner_type, from_id))?;
+    let replaced_path = avatar_path_of(to_id)?;
+
+    let columns = AVATAR_COLUMNS.join(", ");
+    let cleared = AVATAR_COLUMNS
+        .iter()
+        .map(|column| format!("{} = NULL", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    tx.execute(
+        &format!(
+            "UPDATE {table} SET ({columns}) = (SELECT {columns} FROM {table} WHERE id = ?1), row_version = row_version + 1 WHERE id = ?2",
+            table = table,
+            columns = columns
+        ),
+        params![from_id, to_id],
+    )
+    .map_err(|e| format!("Failed to link avatar to {} {}: {}", owner_type, to_id, e))?;
+    tx.execute(
+        &format!(
+            "UPDATE {} SET {}, row_version = row_version + 1 WHERE id = ?",
+            table, cleared
+        ),
+        params![from_id],
+    )
+    .map_err(|e| {
+        format!(
+            "Failed to unlink avatar from {} {}: {}",
+            owner_type, from_id, e
+        )
+    })?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit avatar transfer: {}", e))?;
+
+    // The target's old photo is garbage now, unless someone else still uses it
+    if let Some(replaced) = replaced_path.as_deref() {
+        if normalize_media_path(replaced) != normalize_media_path(&avatar_path) {
+            if let Err(e) = remove_untracked_media_file_with_conn(conn, media_dir, replaced) {
+                logger::warn(format!("Kept replaced avatar {}: {}", replaced, e));
+            }
+        }
+    }
+
+    logger::info(format!(
+        "Transferred {} avatar {} from {} to {}",
+        owner_type, avatar_path, from_id, to_id
+    ));
+
+    Ok(AvatarTransfer {
+        owner_type: owner_type.to_string(),
+        from_id,
+        to_id,
+        avatar_path,
+        replaced_path,
+    })
+}
+
+pub fn transfer_avatar(
+    owner_type: &str,
+    from_id: i32,
+    to_id: i32,
+) -> Result<AvatarTransfer, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    transfer_avatar_with_conn(
+        &conn,
+        file_manager.get_media_directory(),
+        owner_type,
+        from_id,
+        to_id,
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageProblem {
+    /// The header cannot be parsed; the file is truncated or not an image
+    Unreadable,
+    /// The header disagrees with the format or size recorded at save time
+    MetadataMismatch,
+    /// The recorded path leads out of the media directory
+    InvalidPath,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageIntegrityIssue {
+    pub owner_type: String,
+    pub owner_id: i32,
+    pub path: String,
+    pub problem: ImageProblem,
+    pub recorded: Option<ImageMetadata>,
+    pub actual: Option<ImageMetadata>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImageIntegrityReport {
+    pub checked: usize,
+    /// Avatars saved before metadata was recorded, filled in from their header
+    pub backfilled: usize,
+    pub issues: Vec<ImageIntegrityIssue>,
+}
+
+/// Compare every referenced avatar's header with its recorded metadata
+///
+/// Only headers are read, so this is cheap enough to run over the whole
+/// library. Missing files are left to `reconcile_media_with_conn`.
+pub fn verify_avatar_images_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    progress: &ProgressReporter,
+) -> Result<ImageIntegrityReport, String> {
+    type ImageRow = (i32, String, Option<ImageMetadata>);
+
+    let mut rows: Vec<(&str, ImageRow)> = Vec::new();
+    for owner_type in [OWNER_USER, OWNER_OFFICER] {
+        let table = owner_table(owner_type)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, avatar_path, avatar_format, avatar_width, avatar_height FROM {} WHERE avatar_path IS NOT NULL",
+                table
+            ))
+            .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+        let owner_rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    ImageMetadata::from_columns(row.get(2)?, row.get(3)?, row.get(4)?),
+                ))
+            })
+            .map_err(|e| format!("Failed to query {} avatars: {}", owner_type, e))?
+            .collect::<Result<Vec<ImageRow>, _>>()
+            .map_err(|e| format!("Failed to read {} avatar: {}", owner_type, e))?;
+        rows.extend(owner_rows.into_iter().map(|row| (owner_type, row)));
+    }
+
+    let media_root = MediaRoot::new(media_dir)?;
+    let total = rows.len() as u64;
+    let mut report = ImageIntegrityReport::default();
+    for (index, (owner_type, (owner_id, path, recorded))) in rows.into_iter().enumerate() {
+        progress.check_cancelled()?;
+        progress.report(Some("images"), index as u64, Some(total));
+
+        let issue = |problem, actual, error| ImageIntegrityIssue {
+            owner_type: owner_type.to_string(),
+            owner_id,
+            path: normalize_media_path(&path),
+            problem,
+            recorded: recorded.clone(),
+            actual,
+            error,
+        };
+        let full_path = match media_root.resolve(&path) {
+            Ok(safe) => safe.into_path_buf(),
+            Err(e) => {
+                report
+                    .issues
+                    .push(issue(ImageProblem::InvalidPath, None, Some(e)));
+                continue;
+            }
+        };
+        if !full_path.exists() {
+            continue;
+        }
+        report.checked += 1;
+
+        match (avatar_policy::read_image_metadata(&full_path), &recorded) {
+            (Err(e), _) => report
+                .issues
+                .push(issue(ImageProblem::Unreadable, None, Some(e))),
+            (Ok(actual), Some(recorded)) if actual != *recorded => {
+                report
+                    .issues
+                    .push(issue(ImageProblem::MetadataMismatch, Some(actual), None))
+            }
+            (Ok(_), Some(_)) => {}
+            (Ok(actual), None) => {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET avatar_format = ?, avatar_width = ?, avatar_height = ? WHERE id = ?",
+                        owner_table(owner_type)?
+                    ),
+                    params![actual.format, actual.width, actual.height, owner_id],
+                )
+                .map_err(|e| format!("Failed to record image metadata: {}", e))?;
+                report.backfilled += 1;
+            }
+        }
+    }
+
+    progress.finish(total);
+    Ok(report)
+}
+
+pub fn verify_avatar_images(progress: &ProgressReporter) -> Result<ImageIntegrityReport, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    verify_avatar_images_with_conn(&conn, file_manager.get_media_directory(), progress)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MediaReference {
+    pub owner_type: String,
+    pub owner_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicateFile {
+    pub path: String,
+    /// Users/officers pointing at this file; empty for untracked copies
+    pub references: Vec<MediaReference>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicateGroup {
+    pub sha256: String,
+    pub size: u64,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DuplicateMediaReport {
+    pub files_scanned: usize,
+    pub groups: Vec<DuplicateGroup>,
+    /// Bytes that would be freed by keeping one file per group
+    pub wasted_bytes: u64,
+}
+
+pub fn hash_media_file(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to open media file: {}", e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let bytes_read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read media file: {}", e))?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Groups of files under `media_dir` with identical content
+/// Only files sharing a size are hashed, so unique files cost a stat call;
+/// progress counts hashed files and cancellation is checked between them
+pub fn find_duplicate_media_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+    progress: &ProgressReporter,
+) -> Result<DuplicateMediaReport, String> {
+    let files = scan_media_files(media_dir);
+
+    let mut by_size: HashMap<u64, Vec<String>> = HashMap::new();
+    for relative in &files {
+        if let Ok(metadata) = fs::metadata(media_dir.join(relative)) {
+            by_size
+                .entry(metadata.len())
+                .or_default()
+                .push(relative.clone());
+        }
+    }
+
+    let mut references: HashMap<String, Vec<MediaReference>> = HashMap::new();
+    for (owner_type, owner_id, path) in collect_media_references_with_conn(conn)? {
+        references.entry(path).or_default().push(MediaReference {
+            owner_type,
+            owner_id,
+        });
+    }
+
+    let to_hash: u64 = by_size
+        .values()
+        .filter(|c| c.len() > 1)
+        .map(|c| c.len() as u64)
+        .sum();
+    let mut hashed = 0u64;
+
+    let mut groups = Vec::new();
+    for (size, candidates) in by_size.into_iter().filter(|(_, c)| c.len() > 1) {
+        let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+        for relative in candidates {
+            progress.check_cancelled()?;
+            let hash = hash_media_file(&media_dir.join(&relative))?;
+            hashed += 1;
+            progress.report(None, hashed, Some(to_hash));
+            by_hash.entry(hash).or_default().push(relative);
+        }
+
+        for (sha256, mut paths) in by_hash.into_iter().filter(|(_, p)| p.len() > 1) {
+            paths.sort();
+            groups.push(DuplicateGroup {
+                sha256,
+                size,
+                files: paths
+                    .into_iter()
+                    .map(|path| DuplicateFile {
+                        references: references.get(&path).cloned().unwrap_or_default(),
+                        path,
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    // Biggest savings first
+    groups.sort_by(|a, b| {
+        let waste = |g: &DuplicateGroup| g.size * (g.files.len() as u64 - 1);
+        waste(b)
+            .cmp(&waste(a))
+            .then_with(|| a.sha256.cmp(&b.sha256))
+    });
+    let wasted_bytes = groups
+        .iter()
+        .map(|g| g.size * (g.files.len() as u64 - 1))
+        .sum();
+
+    progress.finish(hashed);
+
+    Ok(DuplicateMediaReport {
+        files_scanned: files.len(),
+        groups,
+        wasted_bytes,
+    })
+}
+
+pub fn find_duplicate_media(progress: &ProgressReporter) -> Result<DuplicateMediaReport, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+    find_duplicate_media_with_conn(&conn, file_manager.get_media_directory(), progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    fn setup() -> (Connection, TempDir) {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path) VALUES (1, 'u1', 'u1@test.com', 'h', 'U1', 'avatars\\avatar_1_1.png')",
+            [],
+        )
+        .expect("user insert should succeed");
+        conn.execute(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, avatar_path) VALUES (1, 'a', 'b', 'c', 'high_ranks/officer_1_1.png')",
+            [],
+        )
+        .expect("officer insert should succeed");
+
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("avatars")).unwrap();
+        fs::create_dir_all(media.path().join("high_ranks")).unwrap();
+        fs::write(media.path().join("avatars").join("avatar_1_1.png"), b"a").unwrap();
+        fs::write(media.path().join("avatars").join("manual.jpg"), b"abc").unwrap();
+        (conn, media)
+    }
+
+    #[test]
+    fn test_reconcile_finds_untracked_and_missing() {
+        let (conn, media) = setup();
+        let staged = media.path().join(STAGING_DIR_NAME).join("1_1_0");
+        fs::create_dir_all(&staged).unwrap();
+        fs::write(staged.join("0.staged"), b"s").unwrap();
+
+        let report =
+            reconcile_media_with_conn(&conn, media.path()).expect("reconcile should succeed");
+
+        assert_eq!(
+            report.untracked_files,
+            vec!["avatars/manual.jpg".to_string()]
+        );
+        assert_eq!(report.missing_files.len(), 1);
+        assert_eq!(report.missing_files[0].owner_type, OWNER_OFFICER);
+        assert_eq!(report.missing_files[0].path, "high_ranks/officer_1_1.png");
+    }
+
+    #[test]
+    fn test_reconcile_references_proposes_fixes() {
+        let (conn, media) = setup();
+        conn.execute(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, avatar_path) VALUES (2, 'd', 'e', 'f', 'high_ranks/officer_2_1.png')",
+            [],
+        )
+        .unwrap();
+        for name in ["officer_1_5.png", "officer_1_9.png", "officer_7_1.png"] {
+            fs::write(media.path().join("high_ranks").join(name), b"x").unwrap();
+        }
+
+        let report = reconcile_media_references_with_conn(&conn, media.path()).unwrap();
+        assert_eq!(
+            report.proposed_fixes,
+            vec![
+                MediaFix::ClearReference {
+                    owner_type: OWNER_OFFICER.to_string(),
+                    owner_id: 2,
+                    path: "high_ranks/officer_2_1.png".to_string(),
+                },
+                MediaFix::AdoptFile {
+                    path: "high_ranks/officer_1_9.png".to_string(),
+                    owner_type: OWNER_OFFICER.to_string(),
+                    owner_id: 1,
+                },
+            ]
+        );
+
+        let result =
+            apply_media_fixes_with_conn(&conn, media.path(), &report.proposed_fixes, None).unwrap();
+        assert_eq!(result.applied, 2);
+        let report = reconcile_media_references_with_conn(&conn, media.path()).unwrap();
+        assert!(report.reconciliation.missing_files.is_empty());
+        assert!(report.proposed_fixes.is_empty());
+
+        // A stale fix is refused rather than clearing the adopted photo
+        let stale = MediaFix::ClearReference {
+            owner_type: OWNER_OFFICER.to_string(),
+            owner_id: 1,
+            path: "high_ranks/officer_1_1.png".to_string(),
+        };
+        let result = apply_media_fixes_with_conn(&conn, media.path(), &[stale], None).unwrap();
+        assert_eq!(result.failed.len(), 1);
+    }
+
+    #[test]
+    fn test_adopt_and_remove_untracked_file() {
+        let (conn, media) = setup();
+
+        adopt_media_file_with_conn(
+            &conn,
+            media.path(),
+            "avatars/manual.jpg",
+            OWNER_USER,
+            1,
+            None,
+        )
+        .expect("adopt should succeed");
+        let (path, size): (String, i64) = conn
+            .query_row(
+                "SELECT avatar_path, avatar_size FROM users WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .expect("user should exist");
+        assert_eq!(path, "avatars/manual.jpg");
+        assert_eq!(size, 3);
+
+        // The previous avatar is now untracked and can be cleaned
+        assert!(
+            remove_untracked_media_file_with_conn(&conn, media.path(), "avatars/manual.jpg")
+                .is_err()
+        );
+        remove_untracked_media_file_with_conn(&conn, media.path(), "avatars/avatar_1_1.png")
+            .expect("remove should succeed");
+        assert!(!media.path().join("avatars").join("avatar_1_1.png").exists());
+    }
+
+    #[test]
+    fn test_cleanup_all_media_per_directory() {
+        let (conn, media) = setup();
+        fs::write(media.path().join("high_ranks").join("old.png"), b"old").unwrap();
+
+        let report =
+            cleanup_all_media_with_conn(&conn, media.path()).expect("cleanup should succeed");
+
+        assert_eq!(report.total_deleted, 2);
+        assert_eq!(report.total_freed_bytes, 6);
+        assert_eq!(report.directories[0].directory, "avatars");
+        assert_eq!(report.directories[0].scanned, 2);
+        assert_eq!(report.directories[1].deleted, 1);
+        // Referenced with a Windows separator, still kept
+        assert!(media.path().join("avatars").join("avatar_1_1.png").exists());
+        assert!(!media.path().join("avatars").join("manual.jpg").exists());
+        assert!(!media.path().join("high_ranks").join("old.png").exists());
+    }
+
+    #[test]
+    fn test_rejects_path_traversal() {
+        let (conn, media) = setup();
+        assert!(
+            remove_untracked_media_file_with_conn(&conn, media.path(), "../database.db").is_err()
+        );
+    }
+
+    #[test]
+    fn test_remove_refuses_files_outside_photo_directories() {
+        let (conn, media) = setup();
+        fs::create_dir_all(media.path().join(SIGNATURES_SUBDIR)).unwrap();
+        fs::create_dir_all(media.path().join(STAGING_DIR_NAME)).unwrap();
+        fs::write(media.path().join(SIGNATURES_SUBDIR).join("x.png"), b"s").unwrap();
+        fs::write(media.path().join(STAGING_DIR_NAME).join("tx_1.png"), b"t").unwrap();
+
+        for relative in [
+            format!("{}/x.png", SIGNATURES_SUBDIR),
+            format!("{}/tx_1.png", STAGING_DIR_NAME),
+        ] {
+            assert!(remove_untracked_media_file_with_conn(&conn, media.path(), &relative).is_err());
+            assert!(media.path().join(&relative).exists());
+        }
+    }
+
+    #[test]
+    fn test_find_duplicate_media() {
+        let (conn, media) = setup();
+        // Same bytes as the referenced avatar_1_1.png, plus a same-size different file
+        fs::write(media.path().join("high_ranks").join("copy.png"), b"a").unwrap();
+        fs::write(media.path().join("high_ranks").join("other.png"), b"b").unwrap();
+
+        let report = find_duplicate_media_with_conn(&conn, media.path(), &ProgressReporter::noop())
+            .expect("scan should succeed");
+
+        assert_eq!(report.files_scanned, 4);
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        let paths: Vec<&str> = group.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["avatars/avatar_1_1.png", "high_ranks/copy.png"]);
+        assert_eq!(group.files[0].references.len(), 1);
+        assert!(group.files[1].references.is_empty());
+        assert_eq!(report.wasted_bytes, 1);
+    }
+
+    #[test]
+    fn test_transfer_avatar() {
+        let (conn, media) = setup();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path, avatar_mime, avatar_width) VALUES (2, 'u2', 'u2@test.com', 'h', 'U2', 'avatars/manual.jpg', 'image/jpeg', 64)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (3, 'u3', 'u3@test.com', 'h', 'U3')",
+            [],
+        )
+        .unwrap();
+
+        // User 2's photo belongs to user 1; user 1's old file goes away
+        let transfer = transfer_avatar_with_conn(&conn, media.path(), OWNER_USER, 2, 1)
+            .expect("transfer should succeed");
+        assert_eq!(transfer.avatar_path, "avatars/manual.jpg");
+        assert_eq!(
+            transfer.replaced_path.as_deref(),
+            Some("avatars\\avatar_1_1.png")
+        );
+        let moved: (Option<String>, Option<String>, Option<u32>) = conn
+            .query_row(
+                "SELECT avatar_path, avatar_mime, avatar_width FROM users WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            moved,
+            (
+                Some("avatars/manual.jpg".to_string()),
+                Some("image/jpeg".to_string()),
+                Some(64)
+            )
+        );
+        let source: Option<String> = conn
+            .query_row("SELECT avatar_path FROM users WHERE id = 2", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(source, None);
+        assert!(!media.path().join("avatars").join("avatar_1_1.png").exists());
+        assert!(media.path().join("avatars").join("manual.jpg").exists());
+
+        assert!(transfer_avatar_with_conn(&conn, media.path(), OWNER_USER, 3, 1).is_err());
+        assert!(transfer_avatar_with_conn(&conn, media.path(), OWNER_USER, 1, 99).is_err());
+        assert!(transfer_avatar_with_conn(&conn, media.path(), OWNER_USER, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_verify_avatar_images() {
+        let (conn, media) = setup();
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(10, 20)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        fs::write(
+            media.path().join("high_ranks").join("officer_1_1.png"),
+            &png,
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE high_ranking_officers SET avatar_format = 'png', avatar_width = 10, avatar_height = 10 WHERE id = 1",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path) VALUES (2, 'u2', 'u2@test.com', 'h', 'U2', 'high_ranks/officer_1_1.png')",
+            [],
+        )
+        .unwrap();
+
+        let report = verify_avatar_images_with_conn(&conn, media.path(), &ProgressReporter::noop())
+            .expect("scan should succeed");
+
+        assert_eq!(report.checked, 3);
+        // User 2 had no metadata and the file is a valid image
+        assert_eq!(report.backfilled, 1);
+        let width: u32 = conn
+            .query_row("SELECT avatar_width FROM users WHERE id = 2", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(width, 10);
+
+        let problems: Vec<(&str, i32, ImageProblem)> = report
+            .issues
+            .iter()
+            .map(|i| (i.owner_type.as_str(), i.owner_id, i.problem.clone()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (OWNER_USER, 1, ImageProblem::Unreadable),
+                (OWNER_OFFICER, 1, ImageProblem::MetadataMismatch),
+            ]
+        );
+    }
+}
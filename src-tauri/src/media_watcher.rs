@@ -0,0 +1,140 @@
+//! Watch the media directory for changes made outside the app
+//!
+//! Filesystem events are debounced, then the media folder is reconciled
+//! against the database. When something is out of sync a `media://changed`
+//! event is emitted so the UI can offer to adopt or clean the files.
+
+use lazy_static::lazy_static;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::logger;
+use crate::media_budget;
+use crate::media_maintenance::{self, MediaReconciliation};
+
+pub const MEDIA_CHANGED_EVENT: &str = "media://changed";
+
+/// Quiet period before a burst of filesystem events is processed
+const DEBOUNCE: Duration = Duration::from_millis(1500);
+
+lazy_static! {
+    // Keeps the watcher alive for the lifetime of the app
+    static ref MEDIA_WATCHER: Mutex<Option<RecommendedWatcher>> = Mutex::new(None);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaChangedPayload {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub reconciliation: MediaReconciliation,
+}
+
+/// Start watching the media directory; calling again replaces the old watcher
+pub fn start_media_watcher(app: AppHandle, media_dir: PathBuf) -> Result<(), String> {
+    let (tx, rx) = channel::<notify::Event>();
+
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(e) => logger::warn(format!("Media watcher error: {}", e)),
+        })
+        .map_err(|e| format!("Failed to create media watcher: {}", e))?;
+
+    watcher
+        .watch(&media_dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch media directory: {}", e))?;
+
+    // Block until something happens; a closed channel means the watcher was dropped
+    thread::spawn(move || {
+        while let Ok(first) = rx.recv() {
+            let mut added = BTreeSet::new();
+            let mut removed = BTreeSet::new();
+            collect_event(&media_dir, first, &mut added, &mut removed);
+
+            loop {
+                match rx.recv_timeout(DEBOUNCE) {
+                    Ok(event) => collect_event(&media_dir, event, &mut added, &mut removed),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            if added.is_empty() && removed.is_empty() {
+                continue;
+            }
+            media_budget::check_media_budget(&app);
+
+            match media_maintenance::reconcile_media() {
+                Ok(reconciliation) => {
+                    // Files written by the app itself are referenced by now - stay quiet
+                    if reconciliation.untracked_files.is_empty()
+                        && reconciliation.missing_files.is_empty()
+                    {
+                        continue;
+                    }
+
+                    let payload = MediaChangedPayload {
+                        added: added.into_iter().collect(),
+                        removed: removed.into_iter().collect(),
+                        reconciliation,
+                    };
+                    if let Err(e) = app.emit_all(MEDIA_CHANGED_EVENT, payload) {
+                        logger::warn(format!("Failed to emit media change event: {}", e));
+                    }
+                }
+                Err(e) => logger::warn(format!("Failed to reconcile media directory: {}", e)),
+            }
+        }
+    });
+
+    let mut guard = MEDIA_WATCHER
+        .lock()
+        .map_err(|e| format!("Failed to acquire media watcher lock: {}", e))?;
+    *guard = Some(watcher);
+
+    logger::info("Media directory watcher started");
+    Ok(())
+}
+
+fn collect_event(
+    media_dir: &Path,
+    event: notify::Event,
+    added: &mut BTreeSet<String>,
+    removed: &mut BTreeSet<String>,
+) {
+    let is_create = matches!(event.kind, EventKind::Create(_));
+    let is_remove = matches!(event.kind, EventKind::Remove(_));
+    let is_rename = matches!(
+        event.kind,
+        EventKind::Modify(notify::event::ModifyKind::Name(_))
+    );
+
+    if !(is_create || is_remove || is_rename) {
+        return;
+    }
+
+    for path in event.paths {
+        let relative = match path.strip_prefix(media_dir) {
+            Ok(rel) => media_maintenance::normalize_media_path(&rel.to_string_lossy()),
+            Err(_) => continue,
+        };
+
+        // A rename reports both sides; whichever side still exists was added
+        if is_create || (is_rename && path.is_file()) {
+            removed.remove(&relative);
+            added.insert(relative);
+        } else if is_remove || is_rename {
+            added.remove(&relative);
+            removed.insert(relative);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! Static HTML/CSS page of the high ranking officers for the intranet server
+//!
+//! `publish_officer_board` writes `index.html`, `style.css` and a `photos/`
+//! folder into the destination directory. The bundle has no scripts and no
+//! references outside itself, so it can be copied to any web server as is.
+//! Publishing again replaces the previous bundle. Photos not approved yet
+//! (see `avatar_approval`) are left out as if the officer had none.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::avatar_approval::VISIBLE_AVATAR_CONDITION;
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+use crate::long_path;
+use crate::safe_path::MediaRoot;
+use crate::validation;
+
+const PHOTOS_DIR: &str = "photos";
+/// Photos the last publish wrote, one per line; only these are replaced
+const PHOTOS_MANIFEST: &str = ".board-photos";
+
+const STYLESHEET: &str = r#"body {
+  margin: 0;
+  font-family: "Sarabun", "Tahoma", sans-serif;
+  background: #f4f6f9;
+  color: #1f2937;
+}
+header {
+  background: #0b2a4a;
+  color: #fff;
+  padding: 24px 16px;
+  text-align: center;
+}
+header h1 { margin: 0; font-size: 1.6rem; }
+header p { margin: 8px 0 0; font-size: 0.85rem; opacity: 0.8; }
+.board {
+  display: grid;
+  grid-template-columns: repeat(auto-fill, minmax(200px, 1fr));
+  gap: 20px;
+  max-width: 1100px;
+  margin: 24px auto;
+  padding: 0 16px;
+}
+.officer {
+  background: #fff;
+  border-radius: 8px;
+  box-shadow: 0 1px 3px rgba(0, 0, 0, 0.12);
+  padding: 16px;
+  text-align: center;
+}
+.officer img, .officer .no-photo {
+  width: 140px;
+  height: 140px;
+  border-radius: 50%;
+  object-fit: cover;
+  background: #d1d5db;
+  display: inline-block;
+}
+.officer .name { font-weight: bold; margin: 12px 0 4px; }
+.officer .name-en { font-size: 0.85rem; margin-bottom: 4px; }
+.officer .position { font-size: 0.9rem; }
+.officer .position-en { font-size: 0.8rem; color: #6b7280; margin-top: 2px; }
+"#;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BoardEntry {
+    pub officer_id: i32,
+    pub thai_name: String,
+    pub full_name_en: Option<String>,
+    pub position_thai: String,
+    pub position_english: String,
+    /// Source photo in the media directory, when the officer has one on disk
+    #[serde(skip)]
+    pub photo_source: Option<PathBuf>,
+    /// Photo path inside the bundle
+    pub photo_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OfficerBoardReport {
+    pub destination: String,
+    pub officers: usize,
+    pub photos: usize,
+    /// Officers shown without a photo
+    pub missing_photos: Vec<String>,
+}
+
+/// Officers in board order with the photo each one should show
+pub fn collect_board_entries_with_conn(
+    conn: &Connection,
+    media_dir: &Path,
+) -> Result<Vec<BoardEntry>, String> {
+    let media_root = MediaRoot::new(media_dir)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT id, thai_name, position_thai, position_english,
+                        CASE WHEN avatar_visible = 1 AND {} THEN avatar_path END,
+                        full_name_en
+                 FROM high_ranking_officers
+                 WHERE display_on_board = 1
+                 ORDER BY order_index, id",
+            VISIBLE_AVATAR_CONDITION
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    type Row = (i32, String, String, String, Option<String>, Option<String>);
+    let rows = stmt
+        .query_map([], |r| {
+            Ok((
+                r.get(0)?,
+                r.get(1)?,
+                r.get(2)?,
+                r.get(3)?,
+                r.get(4)?,
+                r.get(5)?,
+            ))
+        })
+        .map_err(|e| format!("Failed to query officers: {}", e))?
+        .collect::<Result<Vec<Row>, _>>()
+        .map_err(|e| format!("Failed to parse officer: {}", e))?;
+
+    let entries = rows
+        .into_iter()
+        .map(
+            |(
+                officer_id,
+                thai_name,
+                position_thai,
+                position_english,
+                avatar_path,
+                full_name_en,
+            )| {
+                let photo_source = avatar_path
+                    .filter(|p| !p.is_empty())
+                    .and_then(|p| media_root.resolve(&p).ok())
+                    .map(|p| p.into_path_buf())
+                    .filter(|p| p.is_file());
+                // Named by id so the bundle never depends on the media layout
+                let photo_name = photo_source.as_ref().map(|source| {
+                    let extension = source
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .unwrap_or("jpg")
+                        .to_lowercase();
+                    format!("{}/officer_{}.{}", PHOTOS_DIR, officer_id, extension)
+                });
+
+                BoardEntry {
+                    officer_id,
+                    thai_name,
+                    full_name_en: full_name_en.filter(|n| !n.is_empty()),
+                    position_thai,
+                    position_english,
+                    photo_source,
+                    photo_name,
+                }
+            },
+        )
+        .collect();
+
+    Ok(entries)
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn render_board_html(entries: &[BoardEntry], generated_at: &str) -> String {
+    let mut cards = String::new();
+    for entry in entries {
+        let photo = match entry.photo_name {
+            Some(ref name) => format!(
+                r#"<img src="{}" alt="{}">"#,
+                escape_html(name),
+                escape_html(&entry.thai_name)
+            ),
+            None => r#"<span class="no-photo"></span>"#.to_string(),
+        };
+        let name_en = match entry.full_name_en {
+            Some(ref name) => format!("\n      <div class=\"name-en\">{}</div>", escape_html(name)),
+            None => String::new(),
+        };
+        cards.push_str(&format!(
+            r#"    <div class="officer">
+      {}
+      <div class="name">{}</div>{}
+      <div class="position">{}</div>
+      <div class="position-en">{}</div>
+    </div>
+"#,
+            photo,
+            escape_html(&entry.thai_name),
+            name_en,
+            escape_html(&entry.position_thai),
+            escape_html(&entry.position_english)
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="th">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>ทำเนียบผู้บังคับบัญชา</title>
+  <link rel="stylesheet" href="style.css">
+</head>
+<body>
+  <header>
+    <h1>ทำเนียบผู้บังคับบัญชา</h1>
+    <p>Updated {}</p>
+  </header>
+  <main class="board">
+{}  </main>
+</body>
+</html>
+"#,
+        escape_html(generated_at),
+        cards
+    )
+}
+
+/// Delete the photos a previous publish listed in its manifest. A photos
+/// folder with files but no manifest was not written here and is refused
+/// rather than emptied.
+fn remove_published_photos(photos_dir: &Path) -> Result<(), String> {
+    if !photos_dir.exists() {
+        return Ok(());
+    }
+    let manifest_path = photos_dir.join(PHOTOS_MANIFEST);
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(_) => {
+            let has_files = fs::read_dir(photos_dir)
+                .map_err(|e| format!("Failed to read photos directory: {}", e))?
+                .next()
+                .is_some();
+            if has_files {
+                return Err(format!(
+                    "{} already holds files that were not published by the officer board; \
+                     choose an empty destination",
+                    photos_dir.display()
+                ));
+            }
+            return Ok(());
+        }
+    };
+
+    for name in manifest.lines().map(str::trim).filter(|n| !n.is_empty()) {
+        // Only plain names, as written below
+        if validation::file_name("photo", name).is_err() {
+            continue;
+        }
+        let path = photos_dir.join(name);
+        if path.is_file() {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove old photo {}: {}", name, e))?;
+        }
+    }
+    fs::remove_file(&manifest_path).map_err(|e| format!("Failed to remove photo list: {}", e))
+}
+
+/// Write the bundle into `destination`, replacing a previously published one
+pub fn write_board_bundle(
+    entries: &[BoardEntry],
+    destination: &Path,
+    generated_at: &str,
+) -> Result<(), String> {
+    fs::create_dir_all(destination)
+        .map_err(|e| format!("Failed to create destination directory: {}", e))?;
+
+    // Photos of officers removed since the last publish must not linger
+    let photos_dir = destination.join(PHOTOS_DIR);
+    remove_published_photos(&photos_dir)?;
+    fs::create_dir_all(&photos_dir)
+        .map_err(|e| format!("Failed to create photos directory: {}", e))?;
+
+    let mut written = Vec::new();
+    for entry in entries {
+        if let (Some(source), Some(name)) = (&entry.photo_source, &entry.photo_name) {
+            fs::copy(
+                long_path::for_io(source),
+                long_path::for_io(&destination.join(name)),
+            )
+            .map_err(|e| format!("Failed to copy photo of {}: {}", entry.thai_name, e))?;
+            written.push(name.trim_start_matches(&format!("{}/", PHOTOS_DIR)));
+        }
+    }
+    let mut manifest = written.join("\n");
+    manifest.push('\n');
+    fs::write(photos_dir.join(PHOTOS_MANIFEST), manifest)
+        .map_err(|e| format!("Failed to write photo list: {}", e))?;
+
+    fs::write(destination.join("style.css"), STYLESHEET)
+        .map_err(|e| format!("Failed to write style.css: {}", e))?;
+    // Page last, so a half-finished publish never shows broken images
+    fs::write(
+        destination.join("index.html"),
+        render_board_html(entries, generated_at),
+    )
+    .map_err(|e| format!("Failed to write index.html: {}", e))?;
+
+    Ok(())
+}
+
+pub fn publish_officer_board(destination: &str) -> Result<OfficerBoardReport, String> {
+    let destination_dir = validation::absolute_path("destination", destination)?;
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let file_manager = FileManager::get_instance()?;
+
+    let entries = collect_board_entries_with_conn(&conn, file_manager.get_media_directory())?;
+    if entries.is_empty() {
+        return Err("No officers to publish".to_string());
+    }
+
+    let generated_at = chrono::Local::now().format("%Y-%m-%d %H:%M").to_string();
+    write_board_bundle(&entries, &destination_dir, &generated_at)?;
+
+    Ok(OfficerBoardReport {
+        destination: destination.to_string(),
+        officers: entries.len(),
+        photos: entries.iter().filter(|e| e.photo_name.is_some()).count(),
+        missing_photos: entries
+            .iter()
+            .filter(|e| e.photo_name.is_none())
+            .map(|e| e.thai_name.clone())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<b>"A" & 'B'</b>"#),
+            "&lt;b&gt;&quot;A&quot; &amp; &#39;B&#39;&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn test_publish_bundle_in_order() {
+        let conn = create_app_db();
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("high_ranks")).unwrap();
+        fs::write(media.path().join("high_ranks").join("o1.png"), b"png").unwrap();
+
+        for (id, name, order, avatar) in [
+            (1, "พล.ร.อ. หนึ่ง", 2, Some("high_ranks\\o1.png")),
+            (2, "พล.ร.อ. <สอง>", 1, None),
+        ] {
+            conn.execute(
+                "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, order_index, avatar_path) VALUES (?, ?, 'ตำแหน่ง', 'Position', ?, ?)",
+                rusqlite::params![id, name, order, avatar],
+            )
+            .expect("officer insert should succeed");
+        }
+
+        let entries =
+            collect_board_entries_with_conn(&conn, media.path()).expect("collect should succeed");
+        assert_eq!(entries[0].officer_id, 2);
+        assert_eq!(
+            entries[1].photo_name.as_deref(),
+            Some("photos/officer_1.png")
+        );
+
+        let out = media.path().join("board");
+        fs::create_dir_all(out.join(PHOTOS_DIR)).unwrap();
+        fs::write(out.join(PHOTOS_DIR).join("officer_9.png"), b"old").unwrap();
+        // Not written by a publish: refused, and left alone
+        assert!(write_board_bundle(&entries, &out, "2024-01-01 08:00").is_err());
+        assert!(out.join("photos/officer_9.png").exists());
+
+        fs::write(
+            out.join(PHOTOS_DIR).join(PHOTOS_MANIFEST),
+            "officer_9.png\n",
+        )
+        .unwrap();
+        fs::write(out.join(PHOTOS_DIR).join("notes.txt"), b"mine").unwrap();
+        write_board_bundle(&entries, &out, "2024-01-01 08:00").expect("bundle should be written");
+
+        let html = fs::read_to_string(out.join("index.html")).unwrap();
+        assert!(html.contains("พล.ร.อ. &lt;สอง&gt;"));
+        assert!(html.find("สอง").unwrap() < html.find("หนึ่ง").unwrap());
+        assert!(out.join("style.css").is_file());
+        assert_eq!(fs::read(out.join("photos/officer_1.png")).unwrap(), b"png");
+        assert!(!out.join("photos/officer_9.png").exists());
+        assert!(out.join("photos/notes.txt").exists());
+        assert_eq!(
+            fs::read_to_string(out.join(PHOTOS_DIR).join(PHOTOS_MANIFEST)).unwrap(),
+            "officer_1.png\n"
+        );
+    }
+
+    #[test]
+    fn test_board_toggles_hide_officers_and_photos() {
+        let conn = create_app_db();
+        let media = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(media.path().join("high_ranks")).unwrap();
+        fs::write(media.path().join("high_ranks").join("o1.png"), b"png").unwrap();
+        conn.execute_batch(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, order_index, avatar_path) VALUES
+                (1, 'หนึ่ง', 'ตำแหน่ง', 'Position', 1, 'high_ranks/o1.png'),
+                (2, 'ว่าง', 'ตำแหน่ง', 'Position', 2, NULL);",
+        )
+        .expect("officers should insert");
+
+        database::set_officer_board_flag_with_conn(
+            &conn,
+            2,
+            database::OfficerBoardFlag::DisplayOnBoard,
+            false,
+        )
+        .unwrap();
+        let officer = database::set_officer_board_flag_with_conn(
+            &conn,
+            1,
+            database::OfficerBoardFlag::AvatarVisible,
+            false,
+        )
+        .unwrap();
+        assert!(!officer.avatar_visible && officer.display_on_board);
+        assert!(database::set_officer_board_flag_with_conn(
+            &conn,
+            9,
+            database::OfficerBoardFlag::AvatarVisible,
+            true
+        )
+        .is_err());
+
+        let entries =
+            collect_board_entries_with_conn(&conn, media.path()).expect("collect should succeed");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].officer_id, 1);
+        assert_eq!(entries[0].photo_name, None);
+    }
+
+    #[test]
+    fn test_board_shows_english_name_when_set() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO high_ranking_officers (id, thai_name, position_thai, position_english, order_index) VALUES
+                (1, 'หนึ่ง', 'ตำแหน่ง', 'Position', 1),
+                (2, 'สอง', 'ตำแหน่ง', 'Position', 2);",
+        )
+        .expect("officers should insert");
+
+        let officer = database::update_officer_full_name_en_with_conn(
+            &conn,
+            1,
+            Some("  Adm. <One> "),
+            Some(1),
+        )
+        .expect("name should update");
+        assert_eq!(officer.full_name_en.as_deref(), Some("Adm. <One>"));
+        assert_eq!(officer.row_version, 2);
+        assert!(
+            database::update_officer_full_name_en_with_conn(&conn, 1, Some("Stale"), Some(1))
+                .is_err()
+        );
+        let cleared = database::update_officer_full_name_en_with_conn(&conn, 2, Some(" "), None)
+            .expect("blank name should clear");
+        assert_eq!(cleared.full_name_en, None);
+
+        let media = TempDir::new().expect("temp dir should be created");
+        let entries = collect_board_entries_with_conn(&conn, media.path()).expect("collect");
+        let html = render_board_html(&entries, "2024-01-01 08:00");
+        assert!(html.contains(r#"<div class="name-en">Adm. &lt;One&gt;</div>"#));
+        assert_eq!(html.matches("name-en\">").count(), 1);
+    }
+}
@@ -0,0 +1,423 @@
+//! Signature blocks of high ranking officers
+//!
+//! Each officer can have one signature image (PNG or JPEG, kept in the
+//! `signatures` media folder) and a title block of a few lines printed under
+//! it, typically rank and name, then position. `stamp_document` adds the
+//! block to the bottom right of a report the app generated, an SVG such as a
+//! contact sheet or an HTML page such as the officer board, and writes the
+//! result next to it as `<name>_signed.<ext>`. The image is embedded, so the
+//! signed copy prints on its own. Existing PDFs get the block on their last
+//! page (see `signature_pdf`).
+
+use base64::{engine::general_purpose, Engine as _};
+use image::ImageFormat;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+use crate::file_transaction;
+use crate::logger;
+use crate::officer_board::escape_html;
+use crate::signature_pdf;
+use crate::validation;
+
+pub const MAX_SIGNATURE_BYTES: usize = 1024 * 1024;
+pub const MAX_TITLE_LINES: usize = 4;
+pub const MAX_TITLE_LINE_CHARS: usize = 120;
+
+/// Size of the stamped block in SVG user units
+const BLOCK_WIDTH: f64 = 240.0;
+const SIGNATURE_HEIGHT: f64 = 80.0;
+const LINE_HEIGHT: f64 = 18.0;
+const BLOCK_MARGIN: f64 = 24.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OfficerSignature {
+    pub officer_id: i32,
+    /// Media-relative path of the signature image
+    pub signature_path: String,
+    pub signature_mime: String,
+    pub title_lines: Vec<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StampedDocument {
+    pub officer_id: i32,
+    pub path: String,
+}
+
+pub fn init_officer_signatures_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS officer_signatures (
+            officer_id INTEGER PRIMARY KEY REFERENCES high_ranking_officers(id) ON DELETE CASCADE,
+            signature_path TEXT NOT NULL,
+            signature_mime TEXT NOT NULL,
+            title_lines TEXT NOT NULL DEFAULT '[]',
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create officer_signatures table: {}", e))?;
+    Ok(())
+}
+
+/// Trimmed lines; blank lines are dropped
+pub fn validate_title_lines(lines: &[String]) -> Result<Vec<String>, String> {
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.len() > MAX_TITLE_LINES {
+        return Err(format!(
+            "A title block has at most {} lines",
+            MAX_TITLE_LINES
+        ));
+    }
+    if lines
+        .iter()
+        .any(|line| line.chars().count() > MAX_TITLE_LINE_CHARS)
+    {
+        return Err(format!(
+            "Title lines must be at most {} characters",
+            MAX_TITLE_LINE_CHARS
+        ));
+    }
+    Ok(lines)
+}
+
+/// (MIME type, extension) of a signature image, judged by its bytes
+pub fn check_signature_image(data: &[u8]) -> Result<(&'static str, &'static str), String> {
+    if data.is_empty() {
+        return Err("Signature image is empty".to_string());
+    }
+    if data.len() > MAX_SIGNATURE_BYTES {
+        return Err(format!(
+            "Signature image too large: {} bytes (max: {} bytes)",
+            data.len(),
+            MAX_SIGNATURE_BYTES
+        ));
+    }
+    match image::guess_format(data) {
+        Ok(ImageFormat::Png) => Ok(("image/png", "png")),
+        Ok(ImageFormat::Jpeg) => Ok(("image/jpeg", "jpg")),
+        _ => Err("Signature image must be a PNG or JPEG file".to_string()),
+    }
+}
+
+pub fn get_signature_with_conn(
+    conn: &Connection,
+    officer_id: i32,
+) -> Result<Option<OfficerSignature>, String> {
+    let row = conn
+        .query_row(
+            "SELECT signature_path, signature_mime, title_lines, updated_at
+             FROM officer_signatures WHERE officer_id = ?",
+            params![officer_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read signature: {}", e))?;
+
+    row.map(
+        |(signature_path, signature_mime, title_lines, updated_at)| {
+            Ok(OfficerSignature {
+                officer_id,
+                signature_path,
+                signature_mime,
+                title_lines: serde_json::from_str(&title_lines)
+                    .map_err(|e| format!("Failed to parse signature title block: {}", e))?,
+                updated_at,
+            })
+        },
+    )
+    .transpose()
+}
+
+pub fn get_signature(officer_id: i32) -> Result<Option<OfficerSignature>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_signature_with_conn(&conn, officer_id)
+}
+
+/// Store a new signature image and title block, replacing the previous ones
+pub fn save_signature(
+    officer_id: i32,
+    image_data: &[u8],
+    title_lines: &[String],
+) -> Result<OfficerSignature, String> {
+    let (mime_type, extension) = check_signature_image(image_data)?;
+    let title_lines = validate_title_lines(title_lines)?;
+    let title_json = serde_json::to_string(&title_lines)
+        .map_err(|e| format!("Failed to serialize title block: {}", e))?;
+
+    let mut conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let officer_exists: i32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM high_ranking_officers WHERE id = ?",
+            params![officer_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to check officer existence: {}", e))?;
+    if officer_exists == 0 {
+        return Err(format!("Officer with ID {} does not exist", officer_id));
+    }
+
+    let previous = get_signature_with_conn(&conn, officer_id)?;
+    let file_manager = FileManager::get_instance()?;
+    let (signature_path, file_path) =
+        file_manager.new_signature_file_path(officer_id, extension)?;
+
+    // The new file only appears once the row points at it
+    file_transaction::with_file_and_db(&mut conn, |files, tx| {
+        files.write(&file_path, image_data)?;
+        tx.execute(
+            "INSERT INTO officer_signatures (officer_id, signature_path, signature_mime, title_lines, updated_at)
+             VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+             ON CONFLICT(officer_id) DO UPDATE SET
+                 signature_path = excluded.signature_path,
+                 signature_mime = excluded.signature_mime,
+                 title_lines = excluded.title_lines,
+                 updated_at = excluded.updated_at",
+            params![officer_id, signature_path, mime_type, title_json],
+        )
+        .map_err(|e| format!("Failed to save signature: {}", e))?;
+        Ok(())
+    })?;
+
+    if let Some(previous) = previous.filter(|p| p.signature_path != signature_path) {
+        remove_signature_file(&file_manager, &previous.signature_path);
+    }
+
+    get_signature_with_conn(&conn, officer_id)?
+        .ok_or_else(|| format!("Signature of officer {} was not saved", officer_id))
+}
+
+fn remove_signature_file(file_manager: &FileManager, signature_path: &str) {
+    let removed = file_manager
+        .resolve_media_path(signature_path)
+        .and_then(|path| match fs::remove_file(path.full_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to delete signature file: {}", e))
+            }
+            _ => Ok(()),
+        });
+    if let Err(e) = removed {
+        logger::warn(format!("Kept signature file {}: {}", signature_path, e));
+    }
+}
+
+/// Whether a signature was removed
+pub fn delete_signature(officer_id: i32) -> Result<bool, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let Some(signature) = get_signature_with_conn(&conn, officer_id)? else {
+        return Ok(false);
+    };
+    conn.execute(
+        "DELETE FROM officer_signatures WHERE officer_id = ?",
+        params![officer_id],
+    )
+    .map_err(|e| format!("Failed to delete signature: {}", e))?;
+
+    remove_signature_file(&*FileManager::get_instance()?, &signature.signature_path);
+    Ok(true)
+}
+
+/// Numeric value of `name="..."` in a start tag; units other than px are not
+/// understood
+fn numeric_attribute(tag: &str, name: &str) -> Option<f64> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = start + tag[start..].find('"')?;
+    tag[start..end].trim().trim_end_matches("px").parse().ok()
+}
+
+/// `svg` with the signature block added below its content; the page grows
+/// by the height of the block
+pub fn stamp_svg(svg: &str, signature_uri: &str, title_lines: &[String]) -> Result<String, String> {
+    let start = svg.find("<svg").ok_or("Document is not an SVG image")?;
+    let tag_end = start
+        + svg[start..]
+            .find('>')
+            .ok_or("Document is not an SVG image")?;
+    let root_tag = &svg[start..tag_end];
+    let (Some(width), Some(height)) = (
+        numeric_attribute(root_tag, "width"),
+        numeric_attribute(root_tag, "height"),
+    ) else {
+        return Err("SVG document needs a width and height in pixels".to_string());
+    };
+
+    let block_height = SIGNATURE_HEIGHT + LINE_HEIGHT * title_lines.len() as f64 + BLOCK_MARGIN;
+    let total_height = height + block_height;
+    let x = (width - BLOCK_WIDTH - BLOCK_MARGIN).max(0.0);
+    let center = x + BLOCK_WIDTH / 2.0;
+
+    let mut block = format!(
+        r#"  <image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="xMidYMax meet" href="{}"/>
+"#,
+        x, height, BLOCK_WIDTH, SIGNATURE_HEIGHT, signature_uri
+    );
+    for (index, line) in title_lines.iter().enumerate() {
+        block.push_str(&format!(
+            r#"  <text x="{}" y="{}" class="signature-title">{}</text>
+"#,
+            center,
+            height + SIGNATURE_HEIGHT + LINE_HEIGHT * (index as f64 + 1.0),
+            escape_html(line)
+        ));
+    }
+
+    // The original page is nested unchanged, so its own styles and
+    // coordinates still apply
+    Ok(format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">
+  <style>
+    .signature-title {{ font-family: "Sarabun", "Tahoma", sans-serif; font-size: 14px; text-anchor: middle; fill: #1f2937; }}
+  </style>
+  <rect width="100%" height="100%" fill="#ffffff"/>
+{original}
+{block}</svg>
+"##,
+        w = width,
+        h = total_height,
+        original = svg[start..].trim_end(),
+        block = block
+    ))
+}
+
+/// `html` with the signature block added at the end of its body
+pub fn stamp_html(html: &str, signature_uri: &str, title_lines: &[String]) -> String {
+    let lines: String = title_lines
+        .iter()
+        .map(|line| format!("<div>{}</div>", escape_html(line)))
+        .collect();
+    let block = format!(
+        r#"<div class="signature-block" style="width: {}px; margin: 32px 24px 24px auto; text-align: center; font-family: 'Sarabun', 'Tahoma', sans-serif;"><img src="{}" alt="" style="max-width: 100%; height: {}px; object-fit: contain;">{}</div>
+"#,
+        BLOCK_WIDTH, signature_uri, SIGNATURE_HEIGHT, lines
+    );
+
+    // ASCII lowercasing keeps byte offsets valid for slicing `html`
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], block, &html[index..]),
+        None => format!("{}{}", html, block),
+    }
+}
+
+fn signed_copy_path(document: &Path) -> Result<PathBuf, String> {
+    let stem = document
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or("Document has no file name")?;
+    let extension = document
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    Ok(document.with_file_name(format!("{}_signed.{}", stem, extension)))
+}
+
+/// Write a signed copy of the SVG, HTML or PDF document at `document_path`
+pub fn stamp_document(officer_id: i32, document_path: &str) -> Result<StampedDocument, String> {
+    let document = validation::absolute_path("document_path", document_path)?;
+    let signature = get_signature(officer_id)?
+        .ok_or_else(|| format!("Officer {} has no signature on file", officer_id))?;
+
+    let file_manager = FileManager::get_instance()?;
+    let image_path = file_manager.resolve_media_path(&signature.signature_path)?;
+    let image_data = fs::read(image_path.full_path())
+        .map_err(|e| format!("Failed to read signature image: {}", e))?;
+    let signature_uri = format!(
+        "data:{};base64,{}",
+        signature.signature_mime,
+        general_purpose::STANDARD.encode(&image_data)
+    );
+
+    let extension = document
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let read_document =
+        || fs::read_to_string(&document).map_err(|e| format!("Failed to read document: {}", e));
+    let output = signed_copy_path(&document)?;
+    let stamped = match extension.as_str() {
+        "svg" => stamp_svg(&read_document()?, &signature_uri, &signature.title_lines)?,
+        "html" | "htm" => stamp_html(&read_document()?, &signature_uri, &signature.title_lines),
+        "pdf" => {
+            signature_pdf::stamp_pdf(&document, &output, &image_data, &signature.title_lines)?;
+            String::new()
+        }
+        _ => return Err(format!("Unsupported document type: .{}", extension)),
+    };
+    if extension != "pdf" {
+        fs::write(&output, stamped)
+            .map_err(|e| format!("Failed to write signed document: {}", e))?;
+    }
+    logger::info(format!(
+        "Stamped signature of officer {} onto {}",
+        officer_id,
+        output.display()
+    ));
+
+    Ok(StampedDocument {
+        officer_id,
+        path: output.to_string_lossy().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp_svg_and_html() {
+        let lines = vec!["พล.ร.อ. สมชาย <ใจดี>".to_string(), "ผู้บัญชาการ".to_string()];
+        let svg = r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="400" height="300" viewBox="0 0 400 300"><rect/></svg>
+"#;
+        let stamped = stamp_svg(svg, "data:image/png;base64,AA==", &lines).unwrap();
+        // 300 + 80 signature + 2 lines + margin
+        assert!(stamped.contains(r#"width="400" height="440""#));
+        assert!(
+            stamped.contains(r#"<svg xmlns="http://www.w3.org/2000/svg" width="400" height="300""#)
+        );
+        assert!(stamped.contains("สมชาย &lt;ใจดี&gt;"));
+        assert_eq!(stamped.matches("<?xml").count(), 1);
+        assert!(stamp_svg("<svg><rect/></svg>", "data:", &lines).is_err());
+
+        let html = stamp_html("<html><BODY><p>x</p></BODY></html>", "data:", &lines);
+        let block = html.find("signature-block").unwrap();
+        assert!(block < html.find("</BODY>").unwrap());
+    }
+
+    #[test]
+    fn test_signature_input_checks() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(4, 4)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        assert_eq!(check_signature_image(&png), Ok(("image/png", "png")));
+        assert!(check_signature_image(b"GIF89a....").is_err());
+
+        let lines = validate_title_lines(&[" a ".to_string(), "  ".to_string()]).unwrap();
+        assert_eq!(lines, vec!["a".to_string()]);
+        assert!(validate_title_lines(&vec!["x".to_string(); MAX_TITLE_LINES + 1]).is_err());
+    }
+}
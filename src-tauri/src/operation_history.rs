@@ -0,0 +1,239 @@
+//! History of major operations for the "Recent activity" panel
+//!
+//! Backups, restores, imports, migrations and cleanups are run through
+//! `tracked`, which times them and stores one row per run in `operations`:
+//! kind, start, duration, whether it succeeded and the error if not. Only
+//! the newest `OPERATIONS_KEPT` rows are kept. Recording is best-effort like
+//! the activity log and never changes the operation's result.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::database::get_connection_safe;
+use crate::logger;
+use crate::progress::CANCELLED_MESSAGE;
+
+pub const OPERATION_BACKUP: &str = "backup";
+pub const OPERATION_RESTORE: &str = "restore";
+pub const OPERATION_IMPORT: &str = "import";
+pub const OPERATION_MIGRATION: &str = "migration";
+pub const OPERATION_CLEANUP: &str = "cleanup";
+
+const OPERATIONS_KEPT: i64 = 500;
+/// Longer errors are cut; the full text is in the log
+const MAX_ERROR_CHARS: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl OperationStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            OperationStatus::Completed => "completed",
+            OperationStatus::Failed => "failed",
+            OperationStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(status: &str) -> OperationStatus {
+        match status {
+            "completed" => OperationStatus::Completed,
+            "cancelled" => OperationStatus::Cancelled,
+            _ => OperationStatus::Failed,
+        }
+    }
+
+    fn of<T>(result: &Result<T, String>) -> OperationStatus {
+        match result {
+            Ok(_) => OperationStatus::Completed,
+            Err(e) if e.contains(CANCELLED_MESSAGE) => OperationStatus::Cancelled,
+            Err(_) => OperationStatus::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperationRecord {
+    pub id: i64,
+    /// OPERATION_BACKUP, OPERATION_RESTORE, ...
+    pub kind: String,
+    /// Command that ran, e.g. "create_hybrid_backup"
+    pub name: String,
+    /// File or target the operation worked on, if any
+    pub target: Option<String>,
+    pub started_at: String,
+    pub duration_ms: i64,
+    pub status: OperationStatus,
+    pub error: Option<String>,
+}
+
+pub fn init_operations_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS operations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            target TEXT,
+            started_at TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            error TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_operations_started ON operations(started_at);",
+    )
+    .map_err(|e| format!("Failed to create operations table: {}", e))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn record_operation_with_conn(
+    conn: &Connection,
+    kind: &str,
+    name: &str,
+    target: Option<&str>,
+    started_at: &str,
+    duration_ms: i64,
+    status: OperationStatus,
+    error: Option<&str>,
+) -> Result<(), String> {
+    let error: Option<String> = error.map(|e| e.chars().take(MAX_ERROR_CHARS).collect());
+    conn.execute(
+        "INSERT INTO operations (kind, name, target, started_at, duration_ms, status, error) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![kind, name, target, started_at, duration_ms, status.as_str(), error],
+    )
+    .map_err(|e| format!("Failed to record operation: {}", e))?;
+    conn.execute(
+        "DELETE FROM operations WHERE id <= (SELECT MAX(id) FROM operations) - ?",
+        params![OPERATIONS_KEPT],
+    )
+    .map_err(|e| format!("Failed to prune operation history: {}", e))?;
+    Ok(())
+}
+
+/// Run `work` and record how it went; `name` is the command, `target` the
+/// file or location it worked on
+pub fn tracked<T, F>(kind: &str, name: &str, target: Option<&str>, work: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let started = Instant::now();
+    let result = work();
+    let duration_ms = started.elapsed().as_millis() as i64;
+
+    // After a restore this writes to the restored database, which is what
+    // the panel should show from then on
+    let recorded = get_connection_safe()
+        .map_err(|e| format!("Failed to connect to database: {}", e))
+        .and_then(|conn| {
+            // A restored older database may predate the table
+            init_operations_schema(&conn)?;
+            record_operation_with_conn(
+                &conn,
+                kind,
+                name,
+                target,
+                &started_at,
+                duration_ms,
+                OperationStatus::of(&result),
+                result.as_ref().err().map(String::as_str),
+            )
+        });
+    if let Err(e) = recorded {
+        logger::warn(format!("Failed to record operation '{}': {}", name, e));
+    }
+    result
+}
+
+/// Newest operations first
+pub fn get_operation_history_with_conn(
+    conn: &Connection,
+    limit: u32,
+) -> Result<Vec<OperationRecord>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, kind, name, target, started_at, duration_ms, status, error FROM operations
+             ORDER BY id DESC LIMIT ?",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let records = stmt
+        .query_map(params![limit], |row| {
+            Ok(OperationRecord {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                name: row.get(2)?,
+                target: row.get(3)?,
+                started_at: row.get(4)?,
+                duration_ms: row.get(5)?,
+                status: OperationStatus::parse(&row.get::<_, String>(6)?),
+                error: row.get(7)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query operation history: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read operation: {}", e))?;
+    Ok(records)
+}
+
+pub fn get_operation_history(limit: u32) -> Result<Vec<OperationRecord>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_operation_history_with_conn(&conn, limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_history_is_newest_first_and_pruned() {
+        let conn = create_app_db();
+
+        for i in 0..(OPERATIONS_KEPT + 3) {
+            record_operation_with_conn(
+                &conn,
+                OPERATION_BACKUP,
+                "create_hybrid_backup",
+                Some(&format!("backup_{}.zip", i)),
+                "2024-01-01T00:00:00Z",
+                i,
+                OperationStatus::Completed,
+                None,
+            )
+            .unwrap();
+        }
+        record_operation_with_conn(
+            &conn,
+            OPERATION_RESTORE,
+            "import_hybrid_backup",
+            None,
+            "2024-01-02T00:00:00Z",
+            5,
+            OperationStatus::of::<()>(&Err(CANCELLED_MESSAGE.to_string())),
+            Some(&"x".repeat(MAX_ERROR_CHARS * 2)),
+        )
+        .unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM operations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, OPERATIONS_KEPT);
+
+        let history = get_operation_history_with_conn(&conn, 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].kind, OPERATION_RESTORE);
+        assert_eq!(history[0].status, OperationStatus::Cancelled);
+        assert_eq!(history[0].error.as_ref().unwrap().len(), MAX_ERROR_CHARS);
+        assert_eq!(
+            history[1].target.as_deref(),
+            Some(format!("backup_{}.zip", OPERATIONS_KEPT + 2).as_str())
+        );
+    }
+}
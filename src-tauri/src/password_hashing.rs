@@ -0,0 +1,150 @@
+//! bcrypt hashing with a per-machine cost
+//!
+//! `bcrypt::DEFAULT_COST` takes a few hundred milliseconds on the office PCs
+//! this runs on and much longer on the oldest ones. The cost is a setting;
+//! `calibrate_cost` measures the machine and picks the highest cost that
+//! stays under a target time. Commands call these helpers from
+//! `spawn_blocking` so hashing never holds up the IPC thread.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::settings::{self, AppSettings};
+
+/// Below this bcrypt is too cheap to slow down offline guessing
+pub const MIN_COST: u32 = 10;
+/// Each step doubles the time; 16 is already seconds per login
+pub const MAX_COST: u32 = 16;
+
+pub const DEFAULT_TARGET_MS: u64 = 250;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostCalibration {
+    pub cost: u32,
+    /// Time one hash took at `cost` on this machine
+    pub measured_ms: u64,
+    pub target_ms: u64,
+    /// Whether the cost was saved to settings
+    pub applied: bool,
+}
+
+pub fn validate_cost(cost: u32) -> Result<(), String> {
+    if !(MIN_COST..=MAX_COST).contains(&cost) {
+        return Err(format!(
+            "Password hash cost must be between {} and {}",
+            MIN_COST, MAX_COST
+        ));
+    }
+    Ok(())
+}
+
+/// Configured cost, falling back to the bcrypt default for missing or invalid values
+pub fn cost_from_settings(settings: &AppSettings) -> u32 {
+    settings
+        .password_hash_cost
+        .filter(|cost| validate_cost(*cost).is_ok())
+        .unwrap_or(bcrypt::DEFAULT_COST)
+}
+
+#[cfg(not(test))]
+pub fn configured_cost() -> u32 {
+    settings::load_settings()
+        .map(|s| cost_from_settings(&s))
+        .unwrap_or(bcrypt::DEFAULT_COST)
+}
+
+// bcrypt at full cost makes the test suite crawl
+#[cfg(test)]
+pub fn configured_cost() -> u32 {
+    4
+}
+
+/// A bcrypt hash like earlier releases stored; new hashes come from
+/// `auth::hash_password`
+#[cfg(test)]
+pub fn hash_password(password: &str) -> Result<String, String> {
+    bcrypt::hash(password, configured_cost()).map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+pub fn set_cost(cost: u32) -> Result<(), String> {
+    validate_cost(cost)?;
+    settings::update_settings(|s| s.password_hash_cost = Some(cost))?;
+    Ok(())
+}
+
+/// Highest cost whose estimated time stays within `target`, given one hash at
+/// `base_cost` took `base_time`; never below `MIN_COST`
+pub fn pick_cost(base_cost: u32, base_time: Duration, target: Duration) -> u32 {
+    let mut cost = base_cost;
+    let mut estimate = base_time;
+    while cost < MAX_COST && estimate * 2 <= target {
+        cost += 1;
+        estimate *= 2;
+    }
+    cost.max(MIN_COST)
+}
+
+fn time_hash(cost: u32) -> Result<Duration, String> {
+    let started = Instant::now();
+    bcrypt::hash("calibration-password", cost)
+        .map_err(|e| format!("Failed to hash password: {}", e))?;
+    Ok(started.elapsed())
+}
+
+/// Measure this machine and pick a cost for `target_ms`; saved when `apply` is set
+pub fn calibrate_cost(target_ms: u64, apply: bool) -> Result<CostCalibration, String> {
+    if target_ms == 0 {
+        return Err("Calibration target must be above 0 ms".to_string());
+    }
+    let target = Duration::from_millis(target_ms);
+
+    let base_time = time_hash(MIN_COST)?;
+    let cost = pick_cost(MIN_COST, base_time, target);
+    // Confirm the extrapolation with a real hash at the chosen cost
+    let measured = if cost == MIN_COST {
+        base_time
+    } else {
+        time_hash(cost)?
+    };
+
+    if apply {
+        set_cost(cost)?;
+    }
+
+    Ok(CostCalibration {
+        cost,
+        measured_ms: measured.as_millis() as u64,
+        target_ms,
+        applied: apply,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_cost_doubles_until_target() {
+        let ms = Duration::from_millis;
+        assert_eq!(pick_cost(10, ms(60), ms(250)), 12);
+        assert_eq!(pick_cost(10, ms(300), ms(250)), MIN_COST);
+        assert_eq!(pick_cost(10, ms(1), ms(60_000)), MAX_COST);
+    }
+
+    #[test]
+    fn test_cost_from_settings() {
+        let mut settings = AppSettings::default();
+        assert_eq!(cost_from_settings(&settings), bcrypt::DEFAULT_COST);
+        settings.password_hash_cost = Some(11);
+        assert_eq!(cost_from_settings(&settings), 11);
+        settings.password_hash_cost = Some(4);
+        assert_eq!(cost_from_settings(&settings), bcrypt::DEFAULT_COST);
+        assert!(validate_cost(MAX_COST + 1).is_err());
+    }
+
+    #[test]
+    fn test_hash_verifies() {
+        let hash = hash_password("secret").expect("hash should succeed");
+        assert!(bcrypt::verify("secret", &hash).unwrap());
+    }
+}
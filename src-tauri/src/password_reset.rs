@@ -0,0 +1,362 @@
+//! Bulk password reset for the annual account refresh
+//!
+//! `reset_passwords_bulk` gives each selected user a fresh temporary password
+//! and sets `must_change_password`, so the next login asks for a new one.
+//! Until `change_own_password` clears the flag, the permission gate refuses
+//! every other command of that user's sessions.
+//! The username/password pairs come back once as CSV for handing out and,
+//! when requested, are also written to an AES-encrypted zip in the export
+//! directory. Only the password hashes are stored.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::activity_log;
+use crate::admin_password::check_password_policy;
+use crate::auth;
+use crate::database::get_connection_safe;
+use crate::export_encryption;
+use crate::logger;
+use crate::storage_paths;
+
+pub const TEMPORARY_PASSWORD_LENGTH: usize = 12;
+/// Users reset in one call; hashing makes each one take a noticeable moment
+pub const MAX_BULK_RESET: usize = 500;
+
+// No look-alikes (0/O, 1/l/I) - these passwords are read off paper
+const LOWERCASE: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"23456789";
+const SYMBOLS: &[u8] = b"!@#$%&*?";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TemporaryPassword {
+    pub user_id: i32,
+    pub username: String,
+    pub full_name: String,
+    pub temporary_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkPasswordReset {
+    pub reset: usize,
+    pub not_found: Vec<i32>,
+    /// username,full_name,temporary_password - shown once, never stored
+    pub csv: String,
+    /// Encrypted copy of the CSV, when one was requested
+    pub zip_path: Option<String>,
+}
+
+/// Uniform index below `bound` from OS randomness (rejection sampling)
+fn random_index(bound: usize) -> Result<usize, String> {
+    let limit = u32::MAX - u32::MAX % bound as u32;
+    loop {
+        let mut bytes = [0u8; 4];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| format!("Failed to read random bytes: {}", e))?;
+        let value = u32::from_le_bytes(bytes);
+        if value < limit {
+            return Ok((value % bound as u32) as usize);
+        }
+    }
+}
+
+/// Random password that passes the regular password policy for `username`
+pub fn generate_temporary_password(username: &str) -> Result<String, String> {
+    let alphabet: Vec<u8> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS].concat();
+    loop {
+        let mut password = String::with_capacity(TEMPORARY_PASSWORD_LENGTH);
+        for _ in 0..TEMPORARY_PASSWORD_LENGTH {
+            password.push(alphabet[random_index(alphabet.len())?] as char);
+        }
+        if check_password_policy(&password, username).is_empty() {
+            return Ok(password);
+        }
+    }
+}
+
+pub fn passwords_to_csv(passwords: &[TemporaryPassword]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["username", "full_name", "temporary_password"])
+        .map_err(|e| format!("Failed to write CSV: {}", e))?;
+    for entry in passwords {
+        writer
+            .write_record([
+                entry.username.as_str(),
+                entry.full_name.as_str(),
+                entry.temporary_password.as_str(),
+            ])
+            .map_err(|e| format!("Failed to write CSV: {}", e))?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| format!("Failed to write CSV: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("Failed to write CSV: {}", e))
+}
+
+/// Reset the given users; returns the new passwords and the ids not found
+pub fn reset_passwords_with_conn(
+    conn: &Connection,
+    user_ids: &[i32],
+) -> Result<(Vec<TemporaryPassword>, Vec<i32>), String> {
+    let mut seen = HashSet::new();
+    let user_ids: Vec<i32> = user_ids
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .collect();
+    if user_ids.is_empty() {
+        return Err("Select at least one user".to_string());
+    }
+    if user_ids.len() > MAX_BULK_RESET {
+        return Err(format!(
+            "At most {} users can be reset at once",
+            MAX_BULK_RESET
+        ));
+    }
+
+    let mut passwords = Vec::new();
+    let mut not_found = Vec::new();
+    let mut issued = HashSet::new();
+    let mut hashes = Vec::new();
+    for id in user_ids {
+        let user: Option<(String, String)> = conn
+            .query_row(
+                "SELECT username, full_name FROM users WHERE id = ?",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to query user {}: {}", id, e))?;
+        let Some((username, full_name)) = user else {
+            not_found.push(id);
+            continue;
+        };
+
+        // Two people comparing notes must not find they share a password
+        let temporary_password = loop {
+            let candidate = generate_temporary_password(&username)?;
+            if issued.insert(candidate.clone()) {
+                break candidate;
+            }
+        };
+        hashes.push((id, auth::hash_password(&temporary_password)?));
+        passwords.push(TemporaryPassword {
+            user_id: id,
+            username,
+            full_name,
+            temporary_password,
+        });
+    }
+
+    // Hashing is slow, so it happens before the write transaction
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    for (id, hash) in &hashes {
+        tx.execute(
+            "UPDATE users SET password_hash = ?, must_change_password = 1, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            params![hash, id],
+        )
+        .map_err(|e| format!("Failed to reset password of user {}: {}", id, e))?;
+    }
+    let details = serde_json::json!({
+        "user_ids": passwords.iter().map(|p| p.user_id).collect::<Vec<_>>(),
+    })
+    .to_string();
+    activity_log::record_event_with_conn(
+        &tx,
+        activity_log::EVENT_PASSWORDS_RESET,
+        None,
+        None,
+        Some(&details),
+    )?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit password reset: {}", e))?;
+
+    Ok((passwords, not_found))
+}
+
+/// Replace `user_id`'s password after checking the current one; clears
+/// `must_change_password`
+pub fn change_own_password_with_conn(
+    conn: &Connection,
+    user_id: i32,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), String> {
+    let (username, password_hash): (String, String) = conn
+        .query_row(
+            "SELECT username, password_hash FROM users WHERE id = ?",
+            params![user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to query user {}: {}", user_id, e))?
+        .ok_or_else(|| format!("User {} not found", user_id))?;
+
+    if !auth::verify_password(current_password, &password_hash)? {
+        return Err("Current password is incorrect".to_string());
+    }
+    if new_password == current_password {
+        return Err("New password must differ from the current password".to_string());
+    }
+    let problems = check_password_policy(new_password, &username);
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+
+    let new_hash = auth::hash_password(new_password)?;
+    conn.execute(
+        "UPDATE users SET password_hash = ?, must_change_password = 0, row_version = row_version + 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        params![new_hash, user_id],
+    )
+    .map_err(|e| format!("Failed to change password: {}", e))?;
+    activity_log::record_event_with_conn(
+        conn,
+        activity_log::EVENT_PASSWORD_CHANGED,
+        Some(user_id),
+        Some(&username),
+        None,
+    )?;
+    Ok(())
+}
+
+pub fn change_own_password(
+    user_id: i32,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    change_own_password_with_conn(&conn, user_id, current_password, new_password)?;
+    crate::read_cache::invalidate();
+    logger::info(format!("User {} changed their password", user_id));
+    Ok(())
+}
+
+/// `passphrase` set: the CSV is also written as an encrypted zip
+pub fn reset_passwords_bulk(
+    user_ids: &[i32],
+    passphrase: Option<&str>,
+) -> Result<BulkPasswordReset, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let (passwords, not_found) = reset_passwords_with_conn(&conn, user_ids)?;
+    crate::read_cache::invalidate();
+    let csv = passwords_to_csv(&passwords)?;
+
+    let zip_path = match passphrase {
+        Some(passphrase) => {
+            let export_dir = storage_paths::get_export_dir()?;
+            std::fs::create_dir_all(&export_dir)
+                .map_err(|e| format!("Failed to create export directory: {}", e))?;
+            let stem = format!(
+                "password_reset_{}",
+                chrono::Local::now().format("%Y%m%d_%H%M%S")
+            );
+            let path = export_dir.join(format!("{}.zip", stem));
+            export_encryption::write_encrypted_zip(
+                &path,
+                &format!("{}.csv", stem),
+                csv.as_bytes(),
+                passphrase,
+            )?;
+            Some(path.to_string_lossy().to_string())
+        }
+        None => None,
+    };
+
+    logger::info(format!("Reset passwords of {} users", passwords.len()));
+    Ok(BulkPasswordReset {
+        reset: passwords.len(),
+        not_found,
+        csv,
+        zip_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_generated_passwords_pass_policy() {
+        for _ in 0..20 {
+            let password = generate_temporary_password("somchai").unwrap();
+            assert_eq!(password.chars().count(), TEMPORARY_PASSWORD_LENGTH);
+            assert!(check_password_policy(&password, "somchai").is_empty());
+            assert!(!password.contains(['0', 'O', '1', 'l', 'I']));
+        }
+    }
+
+    #[test]
+    fn test_bulk_reset_flags_users_and_lists_passwords() {
+        let conn = create_app_db();
+        for (id, name) in [(1, "สมชาย, ใจดี"), (2, "สมหญิง")] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (?, ?, ?, 'old', ?)",
+                params![id, format!("u{}", id), format!("u{}@test.com", id), name],
+            )
+            .expect("user insert should succeed");
+        }
+
+        let (passwords, not_found) =
+            reset_passwords_with_conn(&conn, &[2, 1, 2, 99]).expect("reset should succeed");
+        assert_eq!(not_found, vec![99]);
+        assert_eq!(
+            passwords.iter().map(|p| p.user_id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_ne!(
+            passwords[0].temporary_password,
+            passwords[1].temporary_password
+        );
+
+        let (hash, must_change): (String, bool) = conn
+            .query_row(
+                "SELECT password_hash, must_change_password FROM users WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(must_change);
+        assert!(auth::verify_password(&passwords[1].temporary_password, &hash).unwrap());
+
+        let csv = passwords_to_csv(&passwords).unwrap();
+        assert!(csv.starts_with("username,full_name,temporary_password\n"));
+        assert!(csv.contains("\"สมชาย, ใจดี\""));
+
+        assert!(reset_passwords_with_conn(&conn, &[]).is_err());
+    }
+
+    #[test]
+    fn test_changing_own_password_clears_the_flag() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (1, 'somchai', 's@test.com', 'old', 'สมชาย')",
+            [],
+        )
+        .expect("user insert should succeed");
+        let (passwords, _) = reset_passwords_with_conn(&conn, &[1]).unwrap();
+        let temporary = &passwords[0].temporary_password;
+
+        assert!(change_own_password_with_conn(&conn, 1, "wrong", "N3w-Password!").is_err());
+        assert!(change_own_password_with_conn(&conn, 1, temporary, "short").is_err());
+        change_own_password_with_conn(&conn, 1, temporary, "N3w-Password!")
+            .expect("password change should succeed");
+
+        let (hash, must_change): (String, bool) = conn
+            .query_row(
+                "SELECT password_hash, must_change_password FROM users WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(!must_change);
+        assert!(auth::verify_password("N3w-Password!", &hash).unwrap());
+    }
+}
@@ -0,0 +1,168 @@
+//! Avatar files whose deletion failed, retried on the next startup
+//!
+//! On Windows a photo that a viewer or the virus scanner still holds open
+//! cannot be removed. The database already points elsewhere by then, so the
+//! media-relative path goes into `pending_deletions.json` in the workspace
+//! and `retry_pending_deletions` tries again when the app starts. Paths a
+//! user or officer references again (after a restore, say) are dropped from
+//! the list without touching the file.
+
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use crate::database::get_connection_safe;
+use crate::media_maintenance::normalize_media_path;
+use crate::{logger, safe_path, storage_paths};
+
+pub const PENDING_DELETIONS_FILE: &str = "pending_deletions.json";
+
+lazy_static! {
+    /// Serializes read-modify-write of the list file
+    static ref LIST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+fn list_path() -> Result<PathBuf, String> {
+    Ok(storage_paths::get_workspace_dir()?.join(PENDING_DELETIONS_FILE))
+}
+
+fn read_list(path: &Path) -> Result<Vec<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse pending deletions: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read pending deletions: {}", e)),
+    }
+}
+
+fn write_list(path: &Path, paths: &[String]) -> Result<(), String> {
+    if paths.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove pending deletions: {}", e))
+            }
+            _ => Ok(()),
+        };
+    }
+    let content = serde_json::to_string_pretty(paths)
+        .map_err(|e| format!("Failed to serialize pending deletions: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Failed to write pending deletions: {}", e))
+}
+
+/// Add `relative_path` to the list at `list_path`, once
+pub fn queue_in(list_path: &Path, relative_path: &str) -> Result<(), String> {
+    let _guard = LIST_LOCK
+        .lock()
+        .map_err(|e| format!("Failed to lock pending deletions: {}", e))?;
+    let relative_path = normalize_media_path(relative_path);
+    let mut paths = read_list(list_path)?;
+    if !paths.contains(&relative_path) {
+        paths.push(relative_path);
+        write_list(list_path, &paths)?;
+    }
+    Ok(())
+}
+
+/// Remember a media file that could not be deleted; failures are only logged
+pub fn queue(relative_path: &str) {
+    if let Err(e) = list_path().and_then(|path| queue_in(&path, relative_path)) {
+        logger::warn(format!(
+            "Failed to queue deletion of {}: {}",
+            relative_path, e
+        ));
+    }
+}
+
+fn is_referenced(conn: &Connection, relative_path: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE avatar_path = ?1)
+             OR EXISTS(SELECT 1 FROM high_ranking_officers WHERE avatar_path = ?1)",
+        params![relative_path],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to check avatar references: {}", e))
+}
+
+/// Try every queued deletion again; returns how many files are gone now.
+/// Entries that still fail stay in the list for the next run.
+pub fn retry_in(list_path: &Path, media_dir: &Path, conn: &Connection) -> Result<usize, String> {
+    let _guard = LIST_LOCK
+        .lock()
+        .map_err(|e| format!("Failed to lock pending deletions: {}", e))?;
+    let mut removed = 0;
+    let mut remaining = Vec::new();
+    for relative_path in read_list(list_path)? {
+        if is_referenced(conn, &relative_path)? {
+            continue;
+        }
+        let Ok(relative) = safe_path::normalize_relative(&relative_path) else {
+            logger::warn(format!(
+                "Dropping unsafe pending deletion: {}",
+                relative_path
+            ));
+            continue;
+        };
+        match fs::remove_file(media_dir.join(relative)) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => removed += 1,
+            Err(e) => {
+                logger::warn(format!("Still unable to delete {}: {}", relative_path, e));
+                remaining.push(relative_path);
+            }
+        }
+    }
+    write_list(list_path, &remaining)?;
+    Ok(removed)
+}
+
+/// Startup retry against the active workspace
+pub fn retry_pending_deletions() -> Result<usize, String> {
+    let path = list_path()?;
+    if !path.exists() {
+        return Ok(0);
+    }
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    retry_in(&path, &storage_paths::get_media_dir()?, &conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_retry_removes_queued_files_and_keeps_referenced_ones() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let list = dir.path().join(PENDING_DELETIONS_FILE);
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("avatars")).unwrap();
+        fs::write(media.join("avatars/old.png"), b"png").unwrap();
+        fs::write(media.join("avatars/back.png"), b"png").unwrap();
+
+        queue_in(&list, "avatars\\old.png").unwrap();
+        queue_in(&list, "avatars/old.png").unwrap();
+        queue_in(&list, "avatars/back.png").unwrap();
+        queue_in(&list, "avatars/gone.png").unwrap();
+        assert_eq!(read_list(&list).unwrap().len(), 3);
+
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name, avatar_path)
+             VALUES ('a', 'a@test.com', 'h', 'A', 'avatars/back.png')",
+            [],
+        )
+        .unwrap();
+
+        let removed = retry_in(&list, &media, &conn).expect("retry should work");
+        assert_eq!(removed, 2);
+        assert!(!media.join("avatars/old.png").exists());
+        // Referenced again, so the file stays and the entry is dropped
+        assert!(media.join("avatars/back.png").exists());
+        assert!(!list.exists());
+    }
+}
@@ -0,0 +1,562 @@
+//! Role required to run each command
+//!
+//! The invoke handler looks a command up in `COMMAND_ROLES` before running
+//! it and compares the role of the caller against the one listed: admin >
+//! editor > visitor. The caller is whoever the `sessionToken` argument sent
+//! with the call belongs to (see `sessions`), so each call is judged by its
+//! own session. `PUBLIC_COMMANDS` run without one: the login screen, first-run
+//! setup and window chrome. Commands in neither list are refused, so a new
+//! command has to be given a role before the page can call it. Refusals carry
+//! the FORBIDDEN code with the command and the roles involved as JSON. Checks
+//! that depend on arguments, such as who may change a role, are made in the
+//! command itself with `require_role` and `require_self_or_role`. A user
+//! whose password was reset (`must_change_password`) can only call
+//! `CHANGE_PASSWORD_COMMAND` until they pick a new one.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error_codes::{self, FORBIDDEN, PASSWORD_CHANGE_REQUIRED};
+use crate::sessions;
+
+/// Argument the page sends the token from `login` in, with every call
+pub const SESSION_TOKEN_ARG: &str = "sessionToken";
+
+/// The one command left to a user who has to change their password
+pub const CHANGE_PASSWORD_COMMAND: &str = "change_password";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Visitor,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    /// Name stored in `users.role`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Visitor => "visitor",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Unknown roles get the least access
+    pub fn parse(role: &str) -> Role {
+        match role.trim().to_lowercase().as_str() {
+            "admin" => Role::Admin,
+            "editor" => Role::Editor,
+            _ => Role::Visitor,
+        }
+    }
+}
+
+/// Run without a session
+const PUBLIC_COMMANDS: &[&str] = &[
+    "greet",
+    "authenticate_user",
+    "login",
+    "validate_session",
+    "logout",
+    "sign_out",
+    // Self-registration; anything above visitor is checked in the command
+    "create_user",
+    "check_system_state_for_initialization",
+    "check_backup_for_initialization",
+    "initialize_database_if_needed",
+    "get_startup_report",
+    "get_maintenance_mode",
+    "get_maintenance_status",
+    "zoom_in",
+    "zoom_out",
+    "zoom_reset",
+];
+
+const COMMAND_ROLES: &[(&str, Role)] = &[
+    // Any signed-in user
+    (CHANGE_PASSWORD_COMMAND, Role::Visitor),
+    ("update_user", Role::Visitor),
+    ("autosave_user_draft", Role::Visitor),
+    ("get_user_draft", Role::Visitor),
+    ("discard_user_draft", Role::Visitor),
+    ("update_user_service_number", Role::Visitor),
+    ("update_user_full_name_en", Role::Visitor),
+    // Reads, and what each user does to their own records
+    ("get_all_users", Role::Visitor),
+    ("get_users_page", Role::Visitor),
+    ("count_users", Role::Visitor),
+    ("get_users_count", Role::Visitor),
+    ("get_user_by_id", Role::Visitor),
+    ("get_user_by_email", Role::Visitor),
+    ("copy_users_to_clipboard", Role::Visitor),
+    ("list_saved_views", Role::Visitor),
+    ("run_saved_view", Role::Visitor),
+    ("get_user_preferences", Role::Visitor),
+    ("set_user_preferences", Role::Visitor),
+    ("apply_user_preferences", Role::Visitor),
+    ("get_all_high_ranking_officers", Role::Visitor),
+    ("hash_password", Role::Visitor),
+    ("get_ranks", Role::Visitor),
+    ("get_position_templates", Role::Visitor),
+    ("get_owner_units", Role::Visitor),
+    ("get_dashboard_stats", Role::Visitor),
+    ("get_storage_mode", Role::Visitor),
+    ("subscribe_record", Role::Visitor),
+    ("unsubscribe_record", Role::Visitor),
+    ("open_path", Role::Visitor),
+    ("show_in_folder", Role::Visitor),
+    ("resolve_image_path", Role::Visitor),
+    // Own photo; other owners are checked in the command
+    ("get_hybrid_avatar_base64", Role::Visitor),
+    ("get_hybrid_avatar_image", Role::Visitor),
+    ("get_hybrid_avatar_info", Role::Visitor),
+    ("get_hybrid_high_rank_avatar_base64", Role::Visitor),
+    ("get_hybrid_high_rank_avatar_info", Role::Visitor),
+    ("get_avatar_base64_by_user_id", Role::Visitor),
+    ("get_avatar_base64_by_officer_id", Role::Visitor),
+    ("get_avatar_urls", Role::Visitor),
+    ("get_avatar_policy", Role::Visitor),
+    ("save_hybrid_avatar", Role::Visitor),
+    ("save_hybrid_avatar_stream", Role::Visitor),
+    ("delete_hybrid_avatar", Role::Visitor),
+    ("begin_avatar_upload", Role::Visitor),
+    ("append_avatar_upload_chunk", Role::Visitor),
+    ("finish_avatar_upload", Role::Visitor),
+    ("cancel_avatar_upload", Role::Visitor),
+    ("get_avatar_upload_status", Role::Visitor),
+    // PQS documents, and trainees' own answers and progress
+    ("get_document_branch", Role::Visitor),
+    ("get_document_questions", Role::Visitor),
+    ("get_document_questions_with_details", Role::Visitor),
+    ("get_document_stats", Role::Visitor),
+    ("get_document_with_hierarchy", Role::Visitor),
+    ("get_occupation_branches", Role::Visitor),
+    ("get_occupation_sub_branches", Role::Visitor),
+    ("get_occupation_sub_questions", Role::Visitor),
+    ("get_all_sub_questions_for_branch", Role::Visitor),
+    ("get_standard_branch_sub_questions", Role::Visitor),
+    ("get_all_completed_branch_pairs", Role::Visitor),
+    ("get_back_referencing_section_ids", Role::Visitor),
+    ("get_question_image_base64", Role::Visitor),
+    ("get_question_section_links", Role::Visitor),
+    ("get_references", Role::Visitor),
+    ("get_required_count_children", Role::Visitor),
+    ("get_section_ref_children", Role::Visitor),
+    ("get_section_references", Role::Visitor),
+    ("get_sections_by_document", Role::Visitor),
+    ("get_slot_completion_map", Role::Visitor),
+    ("get_attachment_preview", Role::Visitor),
+    ("get_question_answer_keys", Role::Visitor),
+    ("get_section_dev_metrics", Role::Visitor),
+    ("get_section_progress", Role::Visitor),
+    ("get_sub_question_usage_counts", Role::Visitor),
+    ("search_documents", Role::Visitor),
+    ("calculate_group_score", Role::Visitor),
+    ("calculate_section_total_score", Role::Visitor),
+    ("check_has_children", Role::Visitor),
+    ("check_branch_usage_global", Role::Visitor),
+    ("check_career_branch_usage", Role::Visitor),
+    ("check_sub_branch_usage_global", Role::Visitor),
+    ("generate_document_id_preview", Role::Visitor),
+    ("get_trainee_answers", Role::Visitor),
+    ("get_user_progress", Role::Visitor),
+    ("save_trainee_answer", Role::Visitor),
+    ("upsert_user_progress", Role::Visitor),
+    ("toggle_slot_completion", Role::Visitor),
+    // PQS authoring
+    ("create_new_document", Role::Editor),
+    ("update_document", Role::Editor),
+    ("update_document_branch", Role::Editor),
+    ("delete_document", Role::Editor),
+    ("create_section", Role::Editor),
+    ("update_section", Role::Editor),
+    ("update_section_order", Role::Editor),
+    ("delete_section", Role::Editor),
+    ("create_question", Role::Editor),
+    ("update_question", Role::Editor),
+    ("update_question_score", Role::Editor),
+    ("reorder_questions", Role::Editor),
+    ("delete_question", Role::Editor),
+    ("upload_question_image", Role::Editor),
+    ("delete_question_image", Role::Editor),
+    ("replace_question_answer_keys", Role::Editor),
+    ("update_answer_key", Role::Editor),
+    ("save_qualifier_assessment", Role::Editor),
+    ("create_reference", Role::Editor),
+    ("update_reference", Role::Editor),
+    ("delete_reference", Role::Editor),
+    ("delete_all_references", Role::Editor),
+    ("add_question_reference", Role::Editor),
+    ("remove_question_reference", Role::Editor),
+    ("update_question_reference_location", Role::Editor),
+    ("add_section_reference", Role::Editor),
+    ("remove_section_reference", Role::Editor),
+    ("add_question_section_link", Role::Editor),
+    ("remove_question_section_link", Role::Editor),
+    ("remove_all_question_section_links", Role::Editor),
+    ("batch_add_question_section_links", Role::Editor),
+    ("update_section_link_score", Role::Editor),
+    ("recalculate_section_link_scores", Role::Editor),
+    ("add_section_ref_child", Role::Editor),
+    ("remove_section_ref_child", Role::Editor),
+    ("remove_all_section_ref_children", Role::Editor),
+    ("batch_add_section_ref_children", Role::Editor),
+    ("update_section_ref_score", Role::Editor),
+    ("sync_required_count_children", Role::Editor),
+    ("batch_recalculate_section_group_scores", Role::Editor),
+    ("create_occupation_branch", Role::Editor),
+    ("update_occupation_branch", Role::Editor),
+    ("delete_occupation_branch", Role::Editor),
+    ("reset_and_update_career_branch", Role::Editor),
+    ("create_occupation_sub_branch", Role::Editor),
+    ("update_occupation_sub_branch", Role::Editor),
+    ("delete_occupation_sub_branch", Role::Editor),
+    ("create_occupation_sub_question", Role::Editor),
+    ("batch_create_occupation_sub_questions", Role::Editor),
+    ("update_occupation_sub_question", Role::Editor),
+    ("reorder_occupation_sub_questions", Role::Editor),
+    ("delete_occupation_sub_question", Role::Editor),
+    (
+        "delete_occupation_sub_questions_by_sub_branch",
+        Role::Editor,
+    ),
+    // Officer board and media
+    ("update_high_ranking_officer", Role::Editor),
+    ("update_officer_full_name_en", Role::Editor),
+    ("reorder_high_ranking_officers", Role::Editor),
+    ("set_officer_display_on_board", Role::Editor),
+    ("set_officer_avatar_visible", Role::Editor),
+    ("save_hybrid_high_rank_avatar", Role::Editor),
+    ("delete_hybrid_high_rank_avatar", Role::Editor),
+    ("transfer_avatar", Role::Editor),
+    ("transfer_high_rank_avatar", Role::Editor),
+    ("adopt_media_file", Role::Editor),
+    ("apply_media_fixes", Role::Editor),
+    ("remove_untracked_media_file", Role::Editor),
+    ("apply_photo_matches", Role::Editor),
+    ("import_referenced_photos", Role::Editor),
+    ("publish_officer_board", Role::Editor),
+    ("stamp_officer_signature", Role::Editor),
+    ("export_avatars_zip", Role::Editor),
+    ("create_database_backup", Role::Editor),
+    ("create_hybrid_backup", Role::Editor),
+    ("create_encrypted_backup", Role::Editor),
+    ("create_universal_sqlite_backup", Role::Editor),
+    ("create_standard_sql_dump", Role::Editor),
+    ("export_database", Role::Editor),
+    // Backup files and exports
+    ("get_backup_directory_path", Role::Editor),
+    ("get_backup_file_info", Role::Editor),
+    ("list_backup_catalog", Role::Editor),
+    ("list_backup_files_with_paths", Role::Editor),
+    ("list_database_backups", Role::Editor),
+    ("discover_hybrid_backups", Role::Editor),
+    ("rescan_backup_directory", Role::Editor),
+    ("set_backup_note", Role::Editor),
+    ("choose_backup_destination", Role::Editor),
+    ("copy_backup_to_location", Role::Editor),
+    ("export_backup_to_location", Role::Editor),
+    ("export_hybrid_backup_to_location", Role::Editor),
+    ("upload_backup_to_sftp", Role::Editor),
+    ("list_sftp_backups", Role::Editor),
+    ("download_sftp_backup", Role::Editor),
+    ("export_sql_to_location", Role::Editor),
+    ("copy_sql_export_to_location", Role::Editor),
+    ("export_changes_since", Role::Editor),
+    ("get_export_directory", Role::Editor),
+    ("reveal_export_in_explorer", Role::Editor),
+    ("list_database_exports", Role::Editor),
+    ("has_export_passphrase", Role::Editor),
+    // Media tools, shared views and jobs
+    ("match_photos_to_users", Role::Editor),
+    ("find_duplicate_media", Role::Editor),
+    ("verify_avatar_images", Role::Editor),
+    ("generate_contact_sheet", Role::Editor),
+    ("get_media_budget_status", Role::Editor),
+    ("get_media_directory_path", Role::Editor),
+    ("get_officer_signature", Role::Editor),
+    ("create_saved_view", Role::Editor),
+    ("update_saved_view", Role::Editor),
+    ("delete_saved_view", Role::Editor),
+    ("list_jobs", Role::Editor),
+    // Other users' jobs are checked in `jobs::cancel_job`
+    ("cancel_job", Role::Editor),
+    ("cancel_operation", Role::Editor),
+    // Accounts
+    ("delete_user", Role::Admin),
+    ("reset_passwords_bulk", Role::Admin),
+    ("rotate_admin_password", Role::Admin),
+    ("migrate_passwords", Role::Admin),
+    ("archive_users", Role::Admin),
+    ("restore_user_from_backup", Role::Admin),
+    ("delete_test_users", Role::Admin),
+    ("get_admin_action_log", Role::Admin),
+    ("get_avatar_access_audit", Role::Admin),
+    ("set_avatar_access_audit", Role::Admin),
+    ("query_avatar_access_log", Role::Admin),
+    ("get_avatar_review_queue", Role::Admin),
+    ("review_avatar", Role::Admin),
+    // Restores and imports replace data
+    ("restore_database_backup", Role::Admin),
+    ("import_sql_dump", Role::Admin),
+    ("import_database", Role::Admin),
+    ("apply_changeset", Role::Admin),
+    ("import_hybrid_backup", Role::Admin),
+    ("restore_backup_from_path", Role::Admin),
+    ("restore_encrypted_backup", Role::Admin),
+    ("preview_restore", Role::Admin),
+    ("install_dataset_pack", Role::Admin),
+    ("migrate_legacy_data", Role::Admin),
+    ("delete_database_backup", Role::Admin),
+    ("delete_hybrid_backup", Role::Admin),
+    // Looking inside backups and archives
+    ("check_backup_compatibility", Role::Admin),
+    ("choose_backup_to_restore", Role::Admin),
+    ("rehearse_import_database", Role::Admin),
+    ("open_backup_sandbox", Role::Admin),
+    ("list_backup_sandboxes", Role::Admin),
+    ("sandbox_query_table", Role::Admin),
+    ("sandbox_read_media", Role::Admin),
+    ("close_backup_sandbox", Role::Admin),
+    ("delete_database_export", Role::Admin),
+    ("detect_legacy_data", Role::Admin),
+    ("search_archive", Role::Admin),
+    // System settings
+    ("set_maintenance_mode", Role::Admin),
+    ("run_database_maintenance", Role::Admin),
+    ("list_scheduled_tasks", Role::Admin),
+    ("run_scheduled_task", Role::Admin),
+    ("set_password_hash_scheme", Role::Admin),
+    ("set_password_hash_cost", Role::Admin),
+    ("calibrate_password_hash_cost", Role::Admin),
+    ("rehash_all_passwords", Role::Admin),
+    ("save_sftp_settings", Role::Admin),
+    ("save_notification_settings", Role::Admin),
+    ("set_storage_location", Role::Admin),
+    ("set_media_budget", Role::Admin),
+    ("save_officer_signature", Role::Admin),
+    ("delete_officer_signature", Role::Admin),
+    ("create_workspace", Role::Admin),
+    ("switch_workspace", Role::Admin),
+    ("cleanup_all_media", Role::Admin),
+    ("cleanup_orphaned_avatar_files", Role::Admin),
+    ("cleanup_orphaned_high_rank_avatar_files", Role::Admin),
+    // Settings, keys and one-off migrations
+    ("save_avatar_policy", Role::Admin),
+    ("migrate_user_avatar_to_file", Role::Admin),
+    ("reconcile_media", Role::Admin),
+    ("reconcile_media_references", Role::Admin),
+    ("get_operation_history", Role::Admin),
+    ("diagnose_database_lock", Role::Admin),
+    ("set_export_directory", Role::Admin),
+    ("store_export_passphrase", Role::Admin),
+    ("get_notification_settings", Role::Admin),
+    ("send_test_notification", Role::Admin),
+    ("send_backup_summary", Role::Admin),
+    ("get_sftp_settings", Role::Admin),
+    ("test_sftp_connection", Role::Admin),
+    ("get_password_hash_cost", Role::Admin),
+    ("get_password_hash_scheme", Role::Admin),
+    ("list_workspaces", Role::Admin),
+    ("initialize_content_database", Role::Admin),
+    ("seed_content_database", Role::Admin),
+    ("clear_all_trainee_answers", Role::Admin),
+    ("migrate_section_101", Role::Admin),
+    ("migrate_question_children_to_section_links", Role::Admin),
+    ("migrate_section_links_to_ref_children", Role::Admin),
+];
+
+pub fn required_role(command: &str) -> Option<Role> {
+    COMMAND_ROLES
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, role)| *role)
+}
+
+/// `role` is the signed-in user's, None without a session
+pub fn check_role(command: &str, required: Role, role: Option<Role>) -> Result<(), String> {
+    if role.is_some_and(|role| role >= required) {
+        return Ok(());
+    }
+    let details = serde_json::json!({
+        "command": command,
+        "required_role": required,
+        "role": role,
+    });
+    Err(error_codes::with_code(FORBIDDEN, &details.to_string()))
+}
+
+pub fn is_public_command(command: &str) -> bool {
+    PUBLIC_COMMANDS.contains(&command)
+}
+
+/// The signed-in user a call was made by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub user_id: Option<i32>,
+    pub role: Role,
+    pub must_change_password: bool,
+}
+
+/// Owner of `session_token`; None without a token or once its session
+/// expired, ended or its user was deactivated
+pub fn caller(session_token: Option<&str>) -> Result<Option<Caller>, String> {
+    let Some(token) = session_token.filter(|token| !token.is_empty()) else {
+        return Ok(None);
+    };
+    Ok(sessions::validate_session(token)?.map(|user| Caller {
+        user_id: user.id,
+        role: Role::parse(&user.role),
+        must_change_password: user.must_change_password,
+    }))
+}
+
+/// User id of `session_token`'s owner, e.g. to attribute what a call read
+pub fn caller_id(session_token: Option<&str>) -> Result<Option<i32>, String> {
+    Ok(caller(session_token)?.and_then(|caller| caller.user_id))
+}
+
+/// For checks that depend on a command's arguments
+pub fn require_role(
+    command: &str,
+    required: Role,
+    session_token: Option<&str>,
+) -> Result<(), String> {
+    let role = caller(session_token)?.map(|caller| caller.role);
+    check_role(command, required, role)
+}
+
+/// Users act on their own records; anyone else's need `required`
+pub fn require_self_or_role(
+    command: &str,
+    user_id: i32,
+    required: Role,
+    session_token: Option<&str>,
+) -> Result<(), String> {
+    let caller = caller(session_token)?;
+    if caller.is_some_and(|caller| caller.user_id == Some(user_id)) {
+        return Ok(());
+    }
+    check_role(command, required, caller.map(|caller| caller.role))
+}
+
+/// `SESSION_TOKEN_ARG` of an invoke payload
+pub fn session_token(payload: &Value) -> Option<&str> {
+    payload.get(SESSION_TOKEN_ARG).and_then(Value::as_str)
+}
+
+/// Gate used by the invoke handler for every command
+pub fn check_command_allowed(command: &str, payload: &Value) -> Result<(), String> {
+    if is_public_command(command) {
+        return Ok(());
+    }
+    let Some(required) = required_role(command) else {
+        let details = serde_json::json!({ "command": command, "unlisted": true });
+        return Err(error_codes::with_code(FORBIDDEN, &details.to_string()));
+    };
+    let caller = caller(session_token(payload))?;
+    check_role(command, required, caller.map(|caller| caller.role))?;
+    check_password_change(command, caller)
+}
+
+/// Refuses everything but `CHANGE_PASSWORD_COMMAND` while the caller's
+/// password has to be changed
+pub fn check_password_change(command: &str, caller: Option<Caller>) -> Result<(), String> {
+    if command == CHANGE_PASSWORD_COMMAND
+        || !caller.is_some_and(|caller| caller.must_change_password)
+    {
+        return Ok(());
+    }
+    let details = serde_json::json!({ "command": command });
+    Err(error_codes::with_code(
+        PASSWORD_CHANGE_REQUIRED,
+        &details.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_are_ordered() {
+        assert_eq!(required_role("delete_user"), Some(Role::Admin));
+        assert_eq!(required_role("get_all_users"), Some(Role::Visitor));
+        assert_eq!(required_role("login"), None);
+
+        assert!(check_role("delete_user", Role::Admin, Some(Role::Admin)).is_ok());
+        assert!(check_role("create_hybrid_backup", Role::Editor, Some(Role::Admin)).is_ok());
+        let error = check_role("delete_user", Role::Admin, Some(Role::Editor))
+            .expect_err("editor should be refused");
+        assert_eq!(error_codes::find_code(&error), Some(FORBIDDEN));
+        assert!(error.contains("\"required_role\":\"admin\""));
+        assert!(check_role("update_user", Role::Visitor, None).is_err());
+
+        assert_eq!(Role::parse(" Admin "), Role::Admin);
+        assert_eq!(Role::parse("superuser"), Role::Visitor);
+    }
+
+    #[test]
+    fn test_unlisted_commands_and_missing_sessions_are_refused() {
+        let payload = serde_json::json!({ "id": 1 });
+        assert!(check_command_allowed("login", &payload).is_ok());
+        let error = check_command_allowed("not_a_command", &payload)
+            .expect_err("unlisted command should be refused");
+        assert_eq!(error_codes::find_code(&error), Some(FORBIDDEN));
+        assert!(error.contains("\"unlisted\":true"));
+        assert_eq!(caller(None).unwrap(), None);
+        assert_eq!(caller(Some("")).unwrap(), None);
+        assert!(check_command_allowed("get_all_users", &payload).is_err());
+        assert_eq!(
+            session_token(&serde_json::json!({ "sessionToken": "abc" })),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn test_temporary_password_only_allows_changing_it() {
+        let caller = Caller {
+            user_id: Some(7),
+            role: Role::Admin,
+            must_change_password: true,
+        };
+        assert_eq!(required_role(CHANGE_PASSWORD_COMMAND), Some(Role::Visitor));
+        assert!(check_password_change(CHANGE_PASSWORD_COMMAND, Some(caller)).is_ok());
+        let error = check_password_change("get_all_users", Some(caller))
+            .expect_err("other commands should be refused");
+        assert_eq!(
+            error_codes::find_code(&error),
+            Some(PASSWORD_CHANGE_REQUIRED)
+        );
+
+        let changed = Caller {
+            must_change_password: false,
+            ..caller
+        };
+        assert!(check_password_change("get_all_users", Some(changed)).is_ok());
+        assert!(check_password_change("get_all_users", None).is_ok());
+    }
+
+    #[test]
+    fn test_every_registered_command_has_an_entry() {
+        let main = include_str!("main.rs");
+        let start = main
+            .find("generate_handler![")
+            .expect("main.rs should register commands");
+        let end = start + main[start..].find(']').expect("command list should end");
+        let missing: Vec<&str> = main[start + "generate_handler![".len()..end]
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .flat_map(|line| line.split(','))
+            .map(|name| name.trim().rsplit("::").next().unwrap_or_default())
+            .filter(|name| !name.is_empty())
+            .filter(|name| !is_public_command(name) && required_role(name).is_none())
+            .collect();
+        assert!(missing.is_empty(), "commands without a role: {:?}", missing);
+        for name in PUBLIC_COMMANDS {
+            assert_eq!(required_role(name), None, "{} is listed twice", name);
+        }
+    }
+}
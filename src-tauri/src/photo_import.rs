@@ -0,0 +1,279 @@
+//! Photos for an imported JSON/CSV dataset
+//!
+//! Exports carry each row's `avatar_path` but not the photo itself. After an
+//! import, a companion folder or zip can be given: every avatar reference
+//! with no file in the media directory is looked up there, first by its full
+//! media-relative path, then by file name alone, both case-insensitively.
+//! Found photos are saved through the avatar managers, so the avatar policy
+//! applies and the record is relinked to the stored file exactly as for an
+//! upload. References found nowhere are reported, not cleared.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::database::get_connection_safe;
+use crate::file_manager::FileManager;
+use crate::hybrid_avatar::HybridAvatarManager;
+use crate::hybrid_high_rank_avatar::HybridHighRankAvatarManager;
+use crate::media_maintenance::{
+    collect_media_references_with_conn, mime_from_extension, normalize_media_path, OWNER_USER,
+};
+use crate::permissions::Caller;
+use crate::progress::ProgressReporter;
+use crate::validation;
+
+/// Larger files are not photos the avatar policy would accept anyway
+const MAX_PHOTO_BYTES: u64 = 20 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PhotoImportReport {
+    /// Photos stored and linked to their record
+    pub linked: usize,
+    /// References whose file was already in the media directory
+    pub already_present: usize,
+    /// References found neither in the media directory nor in the source
+    pub missing: Vec<String>,
+    /// "owner id: error" for photos found but refused, e.g. by the avatar policy
+    pub failed: Vec<String>,
+}
+
+enum PhotoSource {
+    Folder(PathBuf),
+    Zip(zip::ZipArchive<fs::File>),
+}
+
+impl PhotoSource {
+    /// A directory, or a file read as a zip archive
+    fn open(path: &Path) -> Result<Self, String> {
+        if path.is_dir() {
+            return Ok(PhotoSource::Folder(path.to_path_buf()));
+        }
+        let file =
+            fs::File::open(path).map_err(|e| format!("Failed to open photo source: {}", e))?;
+        zip::ZipArchive::new(file)
+            .map(PhotoSource::Zip)
+            .map_err(|e| format!("Failed to read zip archive: {}", e))
+    }
+
+    /// Relative path of every file, forward slashes
+    fn file_names(&self) -> Vec<String> {
+        match self {
+            PhotoSource::Folder(root) => WalkDir::new(root)
+                .into_iter()
+                .flatten()
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| {
+                    entry
+                        .path()
+                        .strip_prefix(root)
+                        .ok()
+                        .map(|rel| normalize_media_path(&rel.to_string_lossy()))
+                })
+                .collect(),
+            PhotoSource::Zip(archive) => archive
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(normalize_media_path)
+                .collect(),
+        }
+    }
+
+    fn read(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let read = match self {
+            PhotoSource::Folder(root) => fs::File::open(root.join(name))
+                .and_then(|file| file.take(MAX_PHOTO_BYTES + 1).read_to_end(&mut data)),
+            PhotoSource::Zip(archive) => {
+                let entry = archive
+                    .by_name(name)
+                    .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+                entry.take(MAX_PHOTO_BYTES + 1).read_to_end(&mut data)
+            }
+        };
+        read.map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        if data.len() as u64 > MAX_PHOTO_BYTES {
+            return Err(format!("{} is too large for a photo", name));
+        }
+        Ok(data)
+    }
+}
+
+fn file_name_of(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Lookup from lower-cased full path and file name to the source entry; a
+/// file name shared by several entries only matches by full path
+fn index_source(names: Vec<String>) -> HashMap<String, String> {
+    let mut by_path = HashMap::new();
+    let mut by_file_name: HashMap<String, Option<String>> = HashMap::new();
+    for name in names {
+        by_file_name
+            .entry(file_name_of(&name).to_lowercase())
+            .and_modify(|entry| *entry = None)
+            .or_insert_with(|| Some(name.clone()));
+        by_path.insert(name.to_lowercase(), name);
+    }
+
+    let mut index: HashMap<String, String> = by_file_name
+        .into_iter()
+        .filter_map(|(file_name, name)| name.map(|name| (file_name, name)))
+        .collect();
+    // Full paths win over a bare file name that happens to look the same
+    index.extend(by_path);
+    index
+}
+
+fn find_in_index<'a>(index: &'a HashMap<String, String>, reference: &str) -> Option<&'a String> {
+    let reference = normalize_media_path(reference).to_lowercase();
+    index
+        .get(&reference)
+        .or_else(|| index.get(file_name_of(&reference)))
+}
+
+fn save_photo(
+    owner_type: &str,
+    owner_id: i32,
+    data: &[u8],
+    mime_type: &str,
+    imported_by: Option<Caller>,
+) -> Result<(), String> {
+    if owner_type == OWNER_USER {
+        HybridAvatarManager::new()?.save_avatar(owner_id, data, mime_type, imported_by)?;
+    } else {
+        HybridHighRankAvatarManager::new()?.save_avatar(owner_id, data, mime_type, imported_by)?;
+    }
+    Ok(())
+}
+
+/// Store the photos the database refers to but the media directory lacks,
+/// taking them from the folder or zip at `source`; they are reviewed as
+/// uploads by `imported_by`
+pub fn import_referenced_photos(
+    source: &str,
+    progress: &ProgressReporter,
+    imported_by: Option<Caller>,
+) -> Result<PhotoImportReport, String> {
+    let source = validation::absolute_path("source", source)?;
+    let mut source = PhotoSource::open(&source)?;
+    let index = index_source(source.file_names());
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let references = collect_media_references_with_conn(&conn)?;
+    drop(conn);
+    let file_manager = FileManager::get_instance()?;
+    let media_dir = file_manager.get_media_directory();
+    let total = references.len() as u64;
+
+    let mut report = PhotoImportReport::default();
+    for (index_in_list, (owner_type, owner_id, path)) in references.iter().enumerate() {
+        progress.check_cancelled()?;
+        progress.report(Some("photos"), index_in_list as u64, Some(total));
+
+        if media_dir.join(path).is_file() {
+            report.already_present += 1;
+            continue;
+        }
+        let Some(name) = find_in_index(&index, path) else {
+            report.missing.push(path.clone());
+            continue;
+        };
+        let saved = source.read(name).and_then(|data| {
+            save_photo(
+                owner_type,
+                *owner_id,
+                &data,
+                mime_from_extension(name),
+                imported_by,
+            )
+        });
+        match saved {
+            Ok(()) => report.linked += 1,
+            Err(e) => report
+                .failed
+                .push(format!("{} {}: {}", owner_type, owner_id, e)),
+        }
+    }
+
+    progress.finish(total);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestEnvironment;
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        png
+    }
+
+    #[test]
+    fn test_index_prefers_full_paths() {
+        let index = index_source(vec![
+            "avatars/12_a.png".to_string(),
+            "export/photos/B.JPG".to_string(),
+            "one/dup.png".to_string(),
+            "two/dup.png".to_string(),
+        ]);
+        assert_eq!(
+            find_in_index(&index, "avatars\\12_A.png").map(String::as_str),
+            Some("avatars/12_a.png")
+        );
+        assert_eq!(
+            find_in_index(&index, "avatars/b.jpg").map(String::as_str),
+            Some("export/photos/B.JPG")
+        );
+        assert_eq!(find_in_index(&index, "avatars/dup.png"), None);
+        assert_eq!(
+            find_in_index(&index, "two/dup.png").map(String::as_str),
+            Some("two/dup.png")
+        );
+    }
+
+    #[test]
+    fn test_photos_are_linked_from_folder() {
+        let env = TestEnvironment::with_temp_dir();
+        let linked = env.create_user("linked_user");
+        let missing = env.create_user("missing_user");
+        let conn = get_connection_safe().unwrap();
+        for (user, path) in [
+            (&linked, "avatars/linked.png"),
+            (&missing, "avatars/gone.png"),
+        ] {
+            conn.execute(
+                "UPDATE users SET avatar_path = ? WHERE id = ?",
+                rusqlite::params![path, user.id],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        let source = env.root().join("companion");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("LINKED.png"), png_bytes()).unwrap();
+
+        let source = source.to_string_lossy().to_string();
+        let report = import_referenced_photos(&source, &ProgressReporter::noop(), None)
+            .expect("import should succeed");
+        assert_eq!(report.linked, 1);
+        assert_eq!(report.missing, vec!["avatars/gone.png".to_string()]);
+        assert!(report.failed.is_empty());
+
+        let again = import_referenced_photos(&source, &ProgressReporter::noop(), None).unwrap();
+        assert_eq!(again.linked, 0);
+        assert_eq!(again.already_present, 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! Bulk avatar assignment from a folder of photos named after their owners
+//!
+//! Matching is purely by filename: the stem is compared against the service
+//! number, the username or "lastname_firstname" of every user. The UI shows
+//! the preview from `match_photos_with_conn`, lets the operator untick or fix
+//! rows, and sends the chosen pairs to `apply_photo_matches`.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::database::{get_connection_safe, map_user_row, User, USER_SELECT_COLUMNS};
+use crate::hybrid_avatar::HybridAvatarManager;
+use crate::media_maintenance::mime_from_extension;
+use crate::permissions::Caller;
+use crate::progress::ProgressReporter;
+use crate::validation;
+
+const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PhotoNamePattern {
+    ServiceNumber,
+    Username,
+    /// "lastname_firstname", taken from the first and last word of full_name
+    LastnameFirstname,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchStatus {
+    Matched,
+    /// Several users share the key; the operator has to pick one
+    Ambiguous,
+    Unmatched,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoMatch {
+    pub file_name: String,
+    pub status: MatchStatus,
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub full_name: Option<String>,
+    /// Whether applying would replace an existing avatar
+    pub has_avatar: bool,
+    /// Every candidate id when ambiguous
+    pub candidates: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoAssignment {
+    pub file_name: String,
+    pub user_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoApplyResult {
+    pub file_name: String,
+    pub user_id: i32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Case-insensitive key with separators and spacing folded to "_"
+fn normalize_key(value: &str) -> String {
+    value
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_' || c == '.')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Service numbers are written with or without dashes and spaces
+fn normalize_service_number(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn user_key(user: &User, pattern: PhotoNamePattern) -> Option<String> {
+    let key = match pattern {
+        PhotoNamePattern::ServiceNumber => {
+            normalize_service_number(user.service_number.as_deref()?)
+        }
+        PhotoNamePattern::Username => normalize_key(&user.username),
+        PhotoNamePattern::LastnameFirstname => {
+            let words: Vec<&str> = user.full_name.split_whitespace().collect();
+            if words.len() < 2 {
+                return None;
+            }
+            normalize_key(&format!("{}_{}", words[words.len() - 1], words[0]))
+        }
+    };
+    Some(key).filter(|k| !k.is_empty())
+}
+
+fn file_key(stem: &str, pattern: PhotoNamePattern) -> String {
+    match pattern {
+        PhotoNamePattern::ServiceNumber => normalize_service_number(stem),
+        _ => normalize_key(stem),
+    }
+}
+
+fn is_photo(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| PHOTO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            .unwrap_or(false)
+}
+
+/// Preview of which user each photo in `folder` would be assigned to
+pub fn match_photos_with_conn(
+    conn: &Connection,
+    folder: &Path,
+    pattern: PhotoNamePattern,
+) -> Result<Vec<PhotoMatch>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM users", USER_SELECT_COLUMNS))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let users = stmt
+        .query_map([], map_user_row)
+        .map_err(|e| format!("Failed to query users: {}", e))?
+        .collect::<Result<Vec<User>, _>>()
+        .map_err(|e| format!("Failed to parse user: {}", e))?;
+
+    let mut by_key: HashMap<String, Vec<&User>> = HashMap::new();
+    for user in &users {
+        if let Some(key) = user_key(user, pattern) {
+            by_key.entry(key).or_default().push(user);
+        }
+    }
+
+    let entries =
+        fs::read_dir(folder).map_err(|e| format!("Failed to read photo folder: {}", e))?;
+    let mut file_names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_photo(path))
+        .filter_map(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+        .collect();
+    file_names.sort();
+
+    let matches = file_names
+        .into_iter()
+        .map(|file_name| {
+            let stem = Path::new(&file_name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let candidates = by_key
+                .get(&file_key(&stem, pattern))
+                .cloned()
+                .unwrap_or_default();
+
+            match candidates.as_slice() {
+                [user] => PhotoMatch {
+                    file_name,
+                    status: MatchStatus::Matched,
+                    user_id: user.id,
+                    username: Some(user.username.clone()),
+                    full_name: Some(user.full_name.clone()),
+                    has_avatar: user.avatar_path.is_some(),
+                    candidates: user.id.into_iter().collect(),
+                },
+                users => PhotoMatch {
+                    file_name,
+                    status: if users.is_empty() {
+                        MatchStatus::Unmatched
+                    } else {
+                        MatchStatus::Ambiguous
+                    },
+                    user_id: None,
+                    username: None,
+                    full_name: None,
+                    has_avatar: false,
+                    candidates: users.iter().filter_map(|u| u.id).collect(),
+                },
+            }
+        })
+        .collect();
+
+    Ok(matches)
+}
+
+pub fn match_photos_to_users(
+    folder: &str,
+    pattern: PhotoNamePattern,
+) -> Result<Vec<PhotoMatch>, String> {
+    let folder = validation::absolute_path("folder", folder)?;
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    match_photos_with_conn(&conn, &folder, pattern)
+}
+
+/// Save each assigned photo as its user's avatar, reviewed as an upload by
+/// `applied_by`; one failure does not stop the rest
+pub fn apply_photo_matches(
+    folder: &str,
+    assignments: &[PhotoAssignment],
+    progress: &ProgressReporter,
+    applied_by: Option<Caller>,
+) -> Result<Vec<PhotoApplyResult>, String> {
+    let folder = validation::absolute_path("folder", folder)?;
+    let manager = HybridAvatarManager::new()?;
+    let total = assignments.len() as u64;
+
+    let mut results = Vec::with_capacity(assignments.len());
+    for (index, assignment) in assignments.iter().enumerate() {
+        progress.check_cancelled()?;
+
+        let outcome = validation::file_name("file_name", &assignment.file_name)
+            .map_err(String::from)
+            .and_then(|file_name| {
+                fs::read(folder.join(&file_name))
+                    .map_err(|e| format!("Failed to read {}: {}", file_name, e))
+            })
+            .and_then(|data| {
+                manager.save_avatar(
+                    assignment.user_id,
+                    &data,
+                    mime_from_extension(&assignment.file_name),
+                    applied_by,
+                )
+            });
+
+        results.push(PhotoApplyResult {
+            file_name: assignment.file_name.clone(),
+            user_id: assignment.user_id,
+            success: outcome.is_ok(),
+            error: outcome.err(),
+        });
+        progress.report(Some("photos"), index as u64 + 1, Some(total));
+    }
+
+    progress.finish(total);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use crate::test_support::TestEnvironment;
+    use tempfile::TempDir;
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        png
+    }
+
+    fn conn_with_users() -> Connection {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, role, service_number) VALUES
+                (1, 'somchai', 's@test.com', 'h', 'Somchai Jaidee', 'user', '12-3456'),
+                (2, 'somsri', 'r@test.com', 'h', 'Somsri Jaidee', 'user', NULL),
+                (3, 'somsak', 'k@test.com', 'h', 'Somsri Jaidee', 'user', NULL);",
+        )
+        .expect("users should insert");
+        conn
+    }
+
+    #[test]
+    fn test_match_by_each_pattern() {
+        let conn = conn_with_users();
+        let folder = TempDir::new().expect("temp dir should be created");
+        for name in [
+            "123456.jpg",
+            "SOMSRI.png",
+            "jaidee_somchai.JPG",
+            "jaidee-somsri.png",
+            "notes.txt",
+        ] {
+            fs::write(folder.path().join(name), b"x").unwrap();
+        }
+
+        let by_service =
+            match_photos_with_conn(&conn, folder.path(), PhotoNamePattern::ServiceNumber)
+                .expect("match should succeed");
+        assert_eq!(by_service.len(), 4);
+        assert_eq!(by_service[0].file_name, "123456.jpg");
+        assert_eq!(by_service[0].user_id, Some(1));
+
+        let by_username = match_photos_with_conn(&conn, folder.path(), PhotoNamePattern::Username)
+            .expect("match should succeed");
+        let somsri = by_username.iter().find(|m| m.file_name == "SOMSRI.png");
+        assert_eq!(somsri.and_then(|m| m.user_id), Some(2));
+
+        let by_name =
+            match_photos_with_conn(&conn, folder.path(), PhotoNamePattern::LastnameFirstname)
+                .expect("match should succeed");
+        let somchai = by_name
+            .iter()
+            .find(|m| m.file_name == "jaidee_somchai.JPG")
+            .unwrap();
+        assert_eq!(somchai.status, MatchStatus::Matched);
+        let shared = by_name
+            .iter()
+            .find(|m| m.file_name == "jaidee-somsri.png")
+            .unwrap();
+        assert_eq!(shared.status, MatchStatus::Ambiguous);
+        assert_eq!(shared.candidates, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_apply_reports_per_file() {
+        let env = TestEnvironment::with_temp_dir();
+        let user = env.create_user("photo_user");
+        let folder = env.root().join("photos");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("photo_user.png"), png_bytes()).unwrap();
+
+        let assignments = vec![
+            PhotoAssignment {
+                file_name: "photo_user.png".to_string(),
+                user_id: user.id.unwrap(),
+            },
+            PhotoAssignment {
+                file_name: "missing.png".to_string(),
+                user_id: user.id.unwrap(),
+            },
+            PhotoAssignment {
+                file_name: "../photo_user.png".to_string(),
+                user_id: user.id.unwrap(),
+            },
+        ];
+
+        let results = apply_photo_matches(
+            &folder.to_string_lossy(),
+            &assignments,
+            &ProgressReporter::noop(),
+            None,
+        )
+        .expect("apply should run");
+
+        let successes: Vec<bool> = results.iter().map(|r| r.success).collect();
+        assert_eq!(successes, vec![true, false, false]);
+        let saved = crate::database::get_user_by_id(user.id.unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(saved.avatar_path.is_some());
+    }
+}
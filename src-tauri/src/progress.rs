@@ -0,0 +1,160 @@
+//! Progress reporting and cancellation for long-running operations
+//!
+//! Commands create a `ProgressReporter` with an operation id supplied by the
+//! frontend. Workers call `report` as they go and `check_cancelled` between
+//! units of work; `cancel_operation` flips the flag from another IPC call.
+//! Commands listed as jobs get their reporter from `jobs::run_job`.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const EXPORT_PROGRESS_EVENT: &str = "export://progress";
+pub const IMPORT_PROGRESS_EVENT: &str = "import://progress";
+/// Hybrid backup creation and restore; carries byte counts as well
+pub const BACKUP_PROGRESS_EVENT: &str = "backup://progress";
+
+/// Error message returned when an operation stops because it was cancelled
+pub const CANCELLED_MESSAGE: &str = "Operation cancelled";
+
+/// Emit a progress event at most once per this many rows
+pub const ROW_REPORT_INTERVAL: u64 = 200;
+
+lazy_static! {
+    static ref CANCEL_FLAGS: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProgressPayload {
+    pub operation_id: String,
+    pub current_table: Option<String>,
+    pub rows_processed: u64,
+    pub total_rows: Option<u64>,
+    /// Bytes written or extracted so far, for operations on files
+    #[serde(default)]
+    pub bytes_processed: Option<u64>,
+    #[serde(default)]
+    pub total_bytes: Option<u64>,
+    pub done: bool,
+}
+
+pub type ProgressSink = Box<dyn Fn(&ProgressPayload) + Send + Sync>;
+
+pub struct ProgressReporter {
+    operation_id: String,
+    sink: Option<ProgressSink>,
+    cancel_flag: Arc<AtomicBool>,
+}
+
+impl ProgressReporter {
+    /// Register a cancellable operation; the flag is removed again on drop
+    pub fn new(operation_id: &str, sink: ProgressSink) -> Self {
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        if let Ok(mut flags) = CANCEL_FLAGS.lock() {
+            flags.insert(operation_id.to_string(), Arc::clone(&cancel_flag));
+        }
+
+        ProgressReporter {
+            operation_id: operation_id.to_string(),
+            sink: Some(sink),
+            cancel_flag,
+        }
+    }
+
+    /// Reporter that emits nothing and can never be cancelled
+    pub fn noop() -> Self {
+        ProgressReporter {
+            operation_id: String::new(),
+            sink: None,
+            cancel_flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn report(
+        &self,
+        current_table: Option<&str>,
+        rows_processed: u64,
+        total_rows: Option<u64>,
+    ) {
+        self.emit(current_table, rows_processed, total_rows, None, false);
+    }
+
+    /// `report` for file work, where rows are files
+    pub fn report_bytes(
+        &self,
+        current_table: Option<&str>,
+        files_processed: u64,
+        total_files: Option<u64>,
+        bytes_processed: u64,
+        total_bytes: Option<u64>,
+    ) {
+        self.emit(
+            current_table,
+            files_processed,
+            total_files,
+            Some((bytes_processed, total_bytes)),
+            false,
+        );
+    }
+
+    pub fn finish(&self, rows_processed: u64) {
+        self.emit(None, rows_processed, Some(rows_processed), None, true);
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancel_flag.load(Ordering::SeqCst) {
+            Err(CANCELLED_MESSAGE.to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn emit(
+        &self,
+        current_table: Option<&str>,
+        rows_processed: u64,
+        total_rows: Option<u64>,
+        bytes: Option<(u64, Option<u64>)>,
+        done: bool,
+    ) {
+        if let Some(ref sink) = self.sink {
+            sink(&ProgressPayload {
+                operation_id: self.operation_id.clone(),
+                current_table: current_table.map(|s| s.to_string()),
+                rows_processed,
+                total_rows,
+                bytes_processed: bytes.map(|(processed, _)| processed),
+                total_bytes: bytes.and_then(|(_, total)| total),
+                done,
+            });
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        if self.sink.is_some() {
+            if let Ok(mut flags) = CANCEL_FLAGS.lock() {
+                flags.remove(&self.operation_id);
+            }
+        }
+    }
+}
+
+/// Request cancellation of a running operation
+/// Returns false when no operation with this id is running
+pub fn cancel_operation(operation_id: &str) -> Result<bool, String> {
+    let flags = CANCEL_FLAGS
+        .lock()
+        .map_err(|e| format!("Failed to acquire cancel lock: {}", e))?;
+
+    match flags.get(operation_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
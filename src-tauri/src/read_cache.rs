@@ -0,0 +1,140 @@
+//! Short-lived in-memory cache for the hottest reads
+//!
+//! UI re-renders call `get_user_by_id` and the officer list over and over;
+//! each call opens a connection and queries SQLite. Results are kept for
+//! `CACHE_TTL` and dropped by `invalidate` whenever something may have
+//! changed: the user/officer write functions, workspace or database
+//! switches, every command the invoke handler does not know to be
+//! read-only (after it returns), and async restores, imports and other
+//! background writers once they finish. The TTL bounds staleness for writers outside those paths
+//! (sync, media watcher).
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::database::{HighRankingOfficer, User};
+
+pub const CACHE_TTL: Duration = Duration::from_secs(10);
+
+pub struct ReadCache<K, V> {
+    ttl: Duration,
+    /// Each value with when it was loaded and the generation it belongs to
+    entries: DashMap<K, (u64, Instant, V)>,
+    /// Bumped by `invalidate`; values from an older generation are never served
+    generation: AtomicU64,
+}
+
+impl<K: Eq + Hash, V: Clone> ReadCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        ReadCache {
+            ttl,
+            entries: DashMap::new(),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Cached value for `key`, or the result of `load` (stored when it succeeds)
+    pub fn get_or_load<F>(&self, key: K, load: F) -> Result<V, String>
+    where
+        F: FnOnce() -> Result<V, String>,
+    {
+        let generation = self.generation.load(Ordering::SeqCst);
+        if let Some(entry) = self.entries.get(&key) {
+            let (stored_in, at, value) = entry.value();
+            if *stored_in == generation && at.elapsed() < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+
+        let value = load()?;
+        // A load that overlapped `invalidate` may hold old data; leave it out
+        if self.generation.load(Ordering::SeqCst) == generation {
+            let ttl = self.ttl;
+            self.entries
+                .retain(|_, (stored_in, at, _)| *stored_in == generation && at.elapsed() < ttl);
+            self.entries
+                .insert(key, (generation, Instant::now(), value.clone()));
+        }
+        Ok(value)
+    }
+
+    pub fn invalidate(&self) {
+        // Bump first: anything stored from here on under the old generation
+        // is skipped by readers, even if it lands after the clear
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.clear();
+    }
+}
+
+lazy_static! {
+    static ref USERS: ReadCache<i32, Option<User>> = ReadCache::new(CACHE_TTL);
+    static ref OFFICERS: ReadCache<(), Vec<HighRankingOfficer>> = ReadCache::new(CACHE_TTL);
+}
+
+pub fn user_by_id<F>(id: i32, load: F) -> Result<Option<User>, String>
+where
+    F: FnOnce() -> Result<Option<User>, String>,
+{
+    USERS.get_or_load(id, load)
+}
+
+pub fn officers<F>(load: F) -> Result<Vec<HighRankingOfficer>, String>
+where
+    F: FnOnce() -> Result<Vec<HighRankingOfficer>, String>,
+{
+    OFFICERS.get_or_load((), load)
+}
+
+/// Drop everything cached; call after any write to users or officers
+pub fn invalidate() {
+    USERS.invalidate();
+    OFFICERS.invalidate();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_repeated_reads_hit_cache_until_invalidated() {
+        let cache: ReadCache<i32, String> = ReadCache::new(Duration::from_secs(60));
+        let loads = AtomicUsize::new(0);
+        let load = || -> Result<String, String> {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok("user".to_string())
+        };
+
+        assert_eq!(cache.get_or_load(1, load), Ok("user".to_string()));
+        assert_eq!(cache.get_or_load(1, load), Ok("user".to_string()));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        cache.invalidate();
+        cache.get_or_load(1, load).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        // Failures are not cached
+        assert!(cache.get_or_load(2, || Err("locked".to_string())).is_err());
+        cache.get_or_load(2, load).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_expired_and_overlapping_loads_are_not_reused() {
+        let cache: ReadCache<i32, u32> = ReadCache::new(Duration::from_millis(20));
+        cache.get_or_load(1, || Ok(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get_or_load(1, || Ok(2)), Ok(2));
+
+        // A write lands while the read is running: the old value is returned once, not kept
+        let overlapping = cache.get_or_load(3, || {
+            cache.invalidate();
+            Ok(10)
+        });
+        assert_eq!(overlapping, Ok(10));
+        assert_eq!(cache.get_or_load(3, || Ok(11)), Ok(11));
+    }
+}
@@ -0,0 +1,352 @@
+//! Live updates for single records
+//!
+//! A detail view calls `subscribe_record(table, id)` and listens for the
+//! event name it returns, `record://<table>/<id>`. A background thread follows
+//! `change_log` (filled by triggers, so edits from imports, restores and other
+//! installations on the same file count too) and emits that event with the
+//! current row - None once it is deleted - to the subscribed windows only.
+//! Subscriptions are counted per window and dropped when the window closes.
+//!
+//! A restore or workspace switch swaps in another database whose change_log
+//! says nothing about how rows differ from the old one (and may number its
+//! events lower). After `notify_database_replaced`, or when the newest event
+//! id goes backwards, every subscribed row is re-read and sent with op
+//! "refresh".
+
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::change_log;
+use crate::database::get_connection_safe;
+use crate::logger;
+
+pub const RECORD_EVENT_PREFIX: &str = "record://";
+
+/// How often change_log is read while anything is subscribed
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Never sent to the page, whatever the table
+const HIDDEN_COLUMNS: &[&str] = &["password_hash"];
+
+/// Op of the events sent after the database was replaced
+pub const OP_REFRESH: &str = "refresh";
+
+/// Set when the database was swapped; the next poll refreshes every row
+static DATABASE_REPLACED: AtomicBool = AtomicBool::new(false);
+
+/// (table, id) -> window label -> number of subscriptions from that window
+type Subscriptions = HashMap<(String, i64), HashMap<String, usize>>;
+
+lazy_static! {
+    static ref SUBSCRIPTIONS: Mutex<Subscriptions> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecordChanged {
+    pub table: String,
+    pub id: i64,
+    /// Last change in the batch: insert, update or delete; refresh after
+    /// the database was replaced
+    pub op: String,
+    /// Unix milliseconds
+    pub changed_at: i64,
+    /// The row as it is now; None when it no longer exists
+    pub row: Option<Value>,
+}
+
+pub fn record_event_name(table: &str, id: i64) -> String {
+    format!("{}{}/{}", RECORD_EVENT_PREFIX, table, id)
+}
+
+fn lock_subscriptions() -> Result<std::sync::MutexGuard<'static, Subscriptions>, String> {
+    SUBSCRIPTIONS
+        .lock()
+        .map_err(|e| format!("Failed to acquire subscription lock: {}", e))
+}
+
+/// Register `window` for changes to one row; returns the event to listen for
+pub fn subscribe(window: &str, table: &str, id: i64) -> Result<String, String> {
+    if !change_log::is_tracked_table(table) {
+        return Err(format!("Changes to {} are not tracked", table));
+    }
+    let mut subscriptions = lock_subscriptions()?;
+    *subscriptions
+        .entry((table.to_string(), id))
+        .or_default()
+        .entry(window.to_string())
+        .or_default() += 1;
+    Ok(record_event_name(table, id))
+}
+
+/// Undo one `subscribe`; false when `window` was not subscribed to the row
+pub fn unsubscribe(window: &str, table: &str, id: i64) -> Result<bool, String> {
+    let mut subscriptions = lock_subscriptions()?;
+    let key = (table.to_string(), id);
+    let Some(windows) = subscriptions.get_mut(&key) else {
+        return Ok(false);
+    };
+    let Some(count) = windows.get_mut(window) else {
+        return Ok(false);
+    };
+    *count -= 1;
+    if *count == 0 {
+        windows.remove(window);
+    }
+    if windows.is_empty() {
+        subscriptions.remove(&key);
+    }
+    Ok(true)
+}
+
+/// Forget every subscription of a closed window
+pub fn unsubscribe_window(window: &str) {
+    match lock_subscriptions() {
+        Ok(mut subscriptions) => subscriptions.retain(|_, windows| {
+            windows.remove(window);
+            !windows.is_empty()
+        }),
+        Err(e) => logger::warn(e),
+    }
+}
+
+/// Tell the watcher the database file was replaced (restore, workspace switch)
+pub fn notify_database_replaced() {
+    DATABASE_REPLACED.store(true, Ordering::SeqCst);
+}
+
+/// Current row without the columns the page must never see
+fn visible_row(conn: &Connection, table: &str, id: i64) -> Result<Option<Value>, String> {
+    let mut row = change_log::current_row(conn, table, id)?;
+    if let Some(Value::Object(row)) = row.as_mut() {
+        for column in HIDDEN_COLUMNS {
+            row.remove(*column);
+        }
+    }
+    Ok(row)
+}
+
+/// Id of the newest change_log event, 0 when there is none
+pub fn latest_event_id(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT COALESCE(MAX(id), 0) FROM change_log", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| format!("Failed to read change log: {}", e))
+}
+
+/// Changes logged after event `after_id` to the rows `is_subscribed` accepts,
+/// one per row, and the id of the last event read
+pub fn changes_after_with_conn(
+    conn: &Connection,
+    after_id: i64,
+    is_subscribed: impl Fn(&str, i64) -> bool,
+) -> Result<(i64, Vec<RecordChanged>), String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, op, table_name, pk, changed_at FROM change_log WHERE id > ? ORDER BY id",
+        )
+        .map_err(|e| format!("Failed to prepare change query: {}", e))?;
+    let events = stmt
+        .query_map(params![after_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })
+        .map_err(|e| format!("Failed to query changes: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read changes: {}", e))?;
+
+    let last_id = events.last().map(|event| event.0).unwrap_or(after_id);
+    let mut changes: Vec<RecordChanged> = Vec::new();
+    for (_, op, table, id, changed_at) in events {
+        if !is_subscribed(&table, id) {
+            continue;
+        }
+        // Only the last change of a row in this batch is reported
+        changes.retain(|c| !(c.table == table && c.id == id));
+        changes.push(RecordChanged {
+            table,
+            id,
+            op,
+            changed_at,
+            row: None,
+        });
+    }
+    for change in &mut changes {
+        change.row = visible_row(conn, &change.table, change.id)?;
+    }
+    Ok((last_id, changes))
+}
+
+/// A refresh event with the current row for every one of `rows`
+pub fn refresh_rows_with_conn<'a>(
+    conn: &Connection,
+    rows: impl IntoIterator<Item = &'a (String, i64)>,
+) -> Result<Vec<RecordChanged>, String> {
+    let changed_at = chrono::Utc::now().timestamp_millis();
+    rows.into_iter()
+        .map(|(table, id)| {
+            Ok(RecordChanged {
+                table: table.clone(),
+                id: *id,
+                op: OP_REFRESH.to_string(),
+                changed_at,
+                row: visible_row(conn, table, *id)?,
+            })
+        })
+        .collect()
+}
+
+fn emit_changes(app: &AppHandle, subscribed: &Subscriptions, changes: Vec<RecordChanged>) {
+    for change in changes {
+        let event = record_event_name(&change.table, change.id);
+        let Some(windows) = subscribed.get(&(change.table.clone(), change.id)) else {
+            continue;
+        };
+        for label in windows.keys() {
+            let Some(window) = app.get_window(label) else {
+                continue;
+            };
+            if let Err(e) = window.emit(&event, &change) {
+                logger::warn(format!("Failed to emit {}: {}", event, e));
+            }
+        }
+    }
+}
+
+/// Emit the changes since `last_id` to subscribed windows; `last_id` starts
+/// at the newest event whenever nothing was subscribed
+fn poll_once(app: &AppHandle, last_id: &mut Option<i64>) -> Result<(), String> {
+    let replaced = DATABASE_REPLACED.swap(false, Ordering::SeqCst);
+    let subscribed: Subscriptions = lock_subscriptions()?.clone();
+    if subscribed.is_empty() {
+        *last_id = None;
+        return Ok(());
+    }
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let latest = latest_event_id(&conn)?;
+    let after_id = match *last_id {
+        Some(id) if !replaced && latest >= id => id,
+        // Another change_log: its ids say nothing about the rows shown
+        Some(_) => {
+            *last_id = Some(latest);
+            emit_changes(
+                app,
+                &subscribed,
+                refresh_rows_with_conn(&conn, subscribed.keys())?,
+            );
+            return Ok(());
+        }
+        None => {
+            *last_id = Some(latest);
+            return Ok(());
+        }
+    };
+
+    let (newest, changes) = changes_after_with_conn(&conn, after_id, |table, id| {
+        subscribed.contains_key(&(table.to_string(), id))
+    })?;
+    *last_id = Some(newest);
+    emit_changes(app, &subscribed, changes);
+    Ok(())
+}
+
+/// Start the thread that turns change_log events into record events
+pub fn start_record_watcher(app: AppHandle) {
+    thread::spawn(move || {
+        let mut last_id = None;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if let Err(e) = poll_once(&app, &mut last_id) {
+                logger::warn(format!("Record watcher: {}", e));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_subscriptions_are_counted_per_window() {
+        assert_eq!(subscribe("main", "users", 41).unwrap(), "record://users/41");
+        subscribe("main", "users", 41).unwrap();
+        subscribe("detail", "users", 41).unwrap();
+        assert!(subscribe("main", "change_log", 1).is_err());
+
+        assert!(unsubscribe("main", "users", 41).unwrap());
+        assert!(lock_subscriptions().unwrap()[&("users".to_string(), 41)].contains_key("main"));
+        unsubscribe_window("main");
+        assert!(!unsubscribe("main", "users", 41).unwrap());
+        assert!(unsubscribe("detail", "users", 41).unwrap());
+        assert!(!lock_subscriptions()
+            .unwrap()
+            .contains_key(&("users".to_string(), 41)));
+    }
+
+    #[test]
+    fn test_changes_after_reports_last_change_of_subscribed_rows() {
+        let conn = create_app_db();
+        let start = latest_event_id(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES
+                (1, 'a', 'a@test.com', 'h', 'A'), (2, 'b', 'b@test.com', 'h', 'B');
+             UPDATE users SET full_name = 'A2' WHERE id = 1;
+             DELETE FROM users WHERE id = 2;",
+        )
+        .expect("changes should apply");
+
+        let (last_id, changes) =
+            changes_after_with_conn(&conn, start, |table, id| table == "users" && id != 3)
+                .expect("changes should be read");
+        assert_eq!(last_id, latest_event_id(&conn).unwrap());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].op, "update");
+        assert_eq!(changes[0].row.as_ref().unwrap()["full_name"], "A2");
+        assert!(changes[0]
+            .row
+            .as_ref()
+            .unwrap()
+            .get("password_hash")
+            .is_none());
+        assert_eq!((changes[1].id, changes[1].op.as_str()), (2, "delete"));
+        assert_eq!(changes[1].row, None);
+
+        let (again, changes) = changes_after_with_conn(&conn, last_id, |_, _| true).unwrap();
+        assert_eq!((again, changes.len()), (last_id, 0));
+    }
+
+    #[test]
+    fn test_refresh_reports_current_rows() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (1, 'a', 'a@test.com', 'h', 'A')",
+            [],
+        )
+        .expect("user insert should succeed");
+
+        let rows = [("users".to_string(), 1), ("users".to_string(), 2)];
+        let changes = refresh_rows_with_conn(&conn, &rows).expect("rows should be read");
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|change| change.op == OP_REFRESH));
+        let row = changes[0].row.as_ref().expect("user 1 exists");
+        assert_eq!(row["full_name"], "A");
+        assert!(row.get("password_hash").is_none());
+        assert_eq!(changes[1].row, None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Dry run of a restore: what a backup would change, without changing it
+//!
+//! `preview_restore` extracts the backup into a throwaway sandbox (see
+//! backup_sandbox), compares its tables and media files with the live
+//! database and media directory and removes the sandbox again. The live
+//! database is only read. Together with the compatibility check this is
+//! what the restore dialog shows before asking for confirmation.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::backup_compat::{self, BackupCompatibility};
+use crate::backup_manager;
+use crate::backup_sandbox;
+use crate::database::get_connection_safe;
+use crate::media_maintenance::normalize_media_path;
+use crate::storage_paths;
+use crate::validation;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TableDifference {
+    pub name: String,
+    /// None when the table does not exist on that side
+    pub backup_rows: Option<i64>,
+    pub live_rows: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RestorePreview {
+    pub filename: String,
+    pub compatibility: BackupCompatibility,
+    /// PRAGMA user_version of the backup and of the live database
+    pub schema_version: Option<i32>,
+    pub live_schema_version: Option<i32>,
+    /// Every table of either database, by name
+    pub tables: Vec<TableDifference>,
+    pub media_files: usize,
+    pub live_media_files: usize,
+    /// Restored files the live media directory does not have yet
+    pub media_added: usize,
+    /// Live files the backup does not have, gone after the restore; zero
+    /// when the backup carries no media, since the restore then leaves the
+    /// live media directory alone
+    pub media_removed: usize,
+}
+
+fn schema_version(conn: &Connection) -> Result<Option<i32>, String> {
+    let version: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+    Ok((version > 0).then_some(version))
+}
+
+/// Media-relative paths of the files under `media_dir`
+fn media_files_in(media_dir: &Path) -> HashSet<String> {
+    WalkDir::new(media_dir)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(media_dir)
+                .ok()
+                .map(|relative| normalize_media_path(&relative.to_string_lossy()))
+        })
+        .collect()
+}
+
+/// Row counts of both sides, one entry per table name
+pub fn compare_tables(
+    backup: &Connection,
+    live: Option<&Connection>,
+) -> Result<Vec<TableDifference>, String> {
+    let mut tables: BTreeMap<String, TableDifference> = BTreeMap::new();
+    for table in backup_sandbox::table_row_counts(backup)? {
+        tables.insert(
+            table.name.clone(),
+            TableDifference {
+                name: table.name,
+                backup_rows: Some(table.row_count),
+                live_rows: None,
+            },
+        );
+    }
+    if let Some(live) = live {
+        for table in backup_sandbox::table_row_counts(live)? {
+            tables
+                .entry(table.name.clone())
+                .or_insert_with(|| TableDifference {
+                    name: table.name.clone(),
+                    backup_rows: None,
+                    live_rows: None,
+                })
+                .live_rows = Some(table.row_count);
+        }
+    }
+    Ok(tables.into_values().collect())
+}
+
+/// Preview of restoring `backup_path` over `live` and `live_media_dir`;
+/// the sandbox goes under `sandbox_root` and is removed before returning
+pub fn preview_restore_in(
+    sandbox_root: &Path,
+    backup_path: &Path,
+    live: Option<&Connection>,
+    live_media_dir: &Path,
+) -> Result<RestorePreview, String> {
+    let compatibility = backup_compat::check_backup_compatibility_at(backup_path)?;
+    let sandbox = backup_sandbox::create_sandbox_in(sandbox_root, backup_path)?;
+    compare_sandbox(&sandbox, live, live_media_dir, compatibility)
+}
+
+fn compare_sandbox(
+    sandbox: &backup_sandbox::Sandbox,
+    live: Option<&Connection>,
+    live_media_dir: &Path,
+    compatibility: BackupCompatibility,
+) -> Result<RestorePreview, String> {
+    let backup = sandbox.connection()?;
+    let backup_media_dir = sandbox.media_dir();
+    let backup_media = media_files_in(&backup_media_dir);
+    let live_media = media_files_in(live_media_dir);
+    let media_removed = if backup_media_dir.is_dir() {
+        live_media.difference(&backup_media).count()
+    } else {
+        0
+    };
+
+    Ok(RestorePreview {
+        filename: sandbox.filename.clone(),
+        schema_version: schema_version(&backup)?,
+        live_schema_version: live.map(schema_version).transpose()?.flatten(),
+        tables: compare_tables(&backup, live)?,
+        media_files: backup_media.len(),
+        live_media_files: live_media.len(),
+        media_added: backup_media.difference(&live_media).count(),
+        media_removed,
+        compatibility,
+    })
+}
+
+pub fn preview_restore(backup_filename: &str) -> Result<RestorePreview, String> {
+    validation::file_name("backup_filename", backup_filename)?;
+
+    let backup_path = backup_manager::get_backup_directory()?.join(backup_filename);
+    if !backup_path.exists() {
+        return Err(format!("Backup file not found: {}", backup_filename));
+    }
+
+    // A fresh install has nothing to compare against
+    let live = if crate::database::check_database_exists_and_valid()? {
+        Some(get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?)
+    } else {
+        None
+    };
+    preview_restore_in(
+        &backup_sandbox::sandbox_root()?,
+        &backup_path,
+        live.as_ref(),
+        &storage_paths::get_media_dir()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+
+    fn write_backup_zip(dir: &Path, with_media: bool) -> std::path::PathBuf {
+        let db_path = dir.join("source.db");
+        let conn = Connection::open(&db_path).expect("db should open");
+        apply_schema(&conn).expect("schema should apply");
+        conn.execute_batch(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES
+                ('a', 'a@test.com', 'h', 'A'),
+                ('b', 'b@test.com', 'h', 'B');",
+        )
+        .expect("users should insert");
+        drop(conn);
+
+        let zip_path = dir.join("hybrid_backup_1.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = FileOptions::default();
+        zip.start_file("database.db", options).unwrap();
+        zip.write_all(&fs::read(&db_path).unwrap()).unwrap();
+        if with_media {
+            zip.start_file("media/avatars/a.png", options).unwrap();
+            zip.write_all(b"png").unwrap();
+            zip.start_file("media/avatars/b.png", options).unwrap();
+            zip.write_all(b"png").unwrap();
+        }
+        zip.finish().unwrap();
+        zip_path
+    }
+
+    #[test]
+    fn test_preview_compares_backup_with_live_data() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = write_backup_zip(dir.path(), true);
+
+        let live = Connection::open_in_memory().expect("In-memory db should open");
+        apply_schema(&live).expect("schema should apply");
+        live.execute(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('c', 'c@test.com', 'h', 'C')",
+            [],
+        )
+        .unwrap();
+        live.execute_batch("CREATE TABLE local_only (id INTEGER)")
+            .unwrap();
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("avatars")).unwrap();
+        fs::write(media.join("avatars/a.png"), b"png").unwrap();
+        fs::write(media.join("avatars/c.png"), b"png").unwrap();
+
+        let root = dir.path().join("sandboxes");
+        let preview =
+            preview_restore_in(&root, &zip_path, Some(&live), &media).expect("preview should work");
+
+        let users = preview.tables.iter().find(|t| t.name == "users").unwrap();
+        assert_eq!((users.backup_rows, users.live_rows), (Some(2), Some(1)));
+        let local = preview
+            .tables
+            .iter()
+            .find(|t| t.name == "local_only")
+            .unwrap();
+        assert_eq!((local.backup_rows, local.live_rows), (None, Some(0)));
+        assert_eq!(preview.schema_version, preview.live_schema_version);
+        assert_eq!((preview.media_files, preview.live_media_files), (2, 2));
+        assert_eq!((preview.media_added, preview.media_removed), (1, 1));
+
+        // Live data untouched and no sandbox left behind
+        let live_users: i64 = live
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(live_users, 1);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_preview_without_backup_media_removes_nothing() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let zip_path = write_backup_zip(dir.path(), false);
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("avatars")).unwrap();
+        fs::write(media.join("avatars/a.png"), b"png").unwrap();
+
+        let root = dir.path().join("sandboxes");
+        let preview =
+            preview_restore_in(&root, &zip_path, None, &media).expect("preview should work");
+
+        assert_eq!((preview.media_files, preview.live_media_files), (0, 1));
+        assert_eq!((preview.media_added, preview.media_removed), (0, 0));
+    }
+}
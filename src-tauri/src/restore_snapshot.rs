@@ -0,0 +1,274 @@
+//! Put the database and media back when a restore fails
+//!
+//! A restore overwrites the database file and replaces the media directory;
+//! a failure halfway (disk full, a damaged zip entry, a restored database
+//! that does not open) would leave a mix of old and new data. Before a
+//! restore touches anything, `with_restore_snapshot` copies the database
+//! file aside and moves the contents of the media directory into a sibling
+//! directory (renames, so gigabytes of photos are not copied; the directory
+//! itself stays, as the media watcher holds it open). If the restore fails,
+//! or panics, both are put back as they were; once it succeeds the snapshot
+//! is removed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::logger;
+
+const SNAPSHOT_SUFFIX: &str = "pre-restore";
+
+/// `<name>.pre-restore-<millis>` next to `path`
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}-{}",
+        SNAPSHOT_SUFFIX,
+        chrono::Utc::now().timestamp_millis()
+    ));
+    path.with_file_name(name)
+}
+
+/// Move every entry of `from` into `to`
+fn move_entries(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        fs::rename(entry.path(), to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Remove every entry of `dir`, keeping `dir`
+fn clear_dir(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// The database and media as they were before a restore
+pub struct RestoreSnapshot {
+    db_path: PathBuf,
+    /// None when there was no database to keep
+    saved_db: Option<PathBuf>,
+    /// Set when the restore replaces the media directory
+    media_dir: Option<PathBuf>,
+    saved_media: Option<PathBuf>,
+    finished: bool,
+}
+
+impl RestoreSnapshot {
+    /// Keep the database at `db_path` and, with `media_dir`, move the media
+    /// files out of the way for the restore to fill
+    pub fn take(db_path: &Path, media_dir: Option<&Path>) -> Result<Self, String> {
+        let mut snapshot = RestoreSnapshot {
+            db_path: db_path.to_path_buf(),
+            saved_db: None,
+            media_dir: None,
+            saved_media: None,
+            finished: false,
+        };
+
+        if db_path.exists() {
+            let saved = snapshot_path(db_path);
+            fs::copy(db_path, &saved)
+                .map_err(|e| format!("Failed to snapshot current database: {}", e))?;
+            snapshot.saved_db = Some(saved);
+        }
+        if let Some(media_dir) = media_dir {
+            if media_dir.exists() {
+                let saved = snapshot_path(media_dir);
+                fs::create_dir_all(&saved)
+                    .map_err(|e| format!("Failed to create media snapshot: {}", e))?;
+                if let Err(e) = move_entries(media_dir, &saved) {
+                    // Leave the media as it was; dropping `snapshot` puts the
+                    // database copy back
+                    let _ = move_entries(&saved, media_dir);
+                    let _ = fs::remove_dir(&saved);
+                    return Err(format!("Failed to snapshot current media files: {}", e));
+                }
+                snapshot.saved_media = Some(saved);
+            }
+            snapshot.media_dir = Some(media_dir.to_path_buf());
+        }
+        Ok(snapshot)
+    }
+
+    /// Put the snapshot back over whatever the restore left
+    pub fn roll_back(&mut self) -> Result<(), String> {
+        self.finished = true;
+        let mut failures = Vec::new();
+
+        match &self.saved_db {
+            // Copied rather than renamed: other connections may hold the file open
+            Some(saved) => match fs::copy(saved, &self.db_path) {
+                Ok(_) => {
+                    let _ = fs::remove_file(saved);
+                }
+                Err(e) => failures.push(format!("database: {}", e)),
+            },
+            None => {
+                if self.db_path.exists() {
+                    if let Err(e) = fs::remove_file(&self.db_path) {
+                        failures.push(format!("database: {}", e));
+                    }
+                }
+            }
+        }
+
+        if let Some(media_dir) = &self.media_dir {
+            let put_back = fs::create_dir_all(media_dir)
+                .and_then(|_| clear_dir(media_dir))
+                .and_then(|_| match &self.saved_media {
+                    Some(saved) => {
+                        move_entries(saved, media_dir).and_then(|_| fs::remove_dir(saved))
+                    }
+                    None => Ok(()),
+                });
+            if let Err(e) = put_back {
+                failures.push(format!("media: {}", e));
+            }
+        }
+
+        if failures.is_empty() {
+            logger::info("Restore rolled back to the pre-restore snapshot");
+            Ok(())
+        } else {
+            Err(format!(
+                "Failed to roll back restore ({}); the snapshot is kept next to the originals",
+                failures.join("; ")
+            ))
+        }
+    }
+
+    /// Keep the restored data and delete the snapshot
+    pub fn discard(mut self) {
+        self.finished = true;
+        if let Some(saved) = &self.saved_db {
+            if let Err(e) = fs::remove_file(saved) {
+                logger::warn(format!("Failed to remove database snapshot: {}", e));
+            }
+        }
+        if let Some(saved) = &self.saved_media {
+            if let Err(e) = fs::remove_dir_all(saved) {
+                logger::warn(format!("Failed to remove media snapshot: {}", e));
+            }
+        }
+    }
+}
+
+impl Drop for RestoreSnapshot {
+    fn drop(&mut self) {
+        // Neither rolled back nor discarded: the restore panicked or `take` failed
+        if !self.finished {
+            if let Err(e) = self.roll_back() {
+                logger::error(e);
+            }
+        }
+    }
+}
+
+/// Run `restore` with a snapshot of the database and, with `media_dir`, the
+/// media directory; any error rolls both back before it is returned
+pub fn with_restore_snapshot<T, F>(
+    db_path: &Path,
+    media_dir: Option<&Path>,
+    restore: F,
+) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    let mut snapshot = RestoreSnapshot::take(db_path, media_dir)?;
+    let result = restore();
+    // Restored or rolled back, the file under open views has changed
+    crate::record_subscriptions::notify_database_replaced();
+    match result {
+        Ok(value) => {
+            snapshot.discard();
+            Ok(value)
+        }
+        Err(e) => {
+            logger::warn(format!("Restore failed, rolling back: {}", e));
+            match snapshot.roll_back() {
+                Ok(()) => Err(e),
+                Err(rollback) => Err(format!("{}; {}", e, rollback)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let db_path = dir.path().join("database.db");
+        let media_dir = dir.path().join("media");
+        fs::write(&db_path, b"old database").unwrap();
+        fs::create_dir_all(media_dir.join("avatars")).unwrap();
+        fs::write(media_dir.join("avatars/old.png"), b"old").unwrap();
+        (dir, db_path, media_dir)
+    }
+
+    fn entries(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn test_failed_restore_puts_everything_back() {
+        let (dir, db_path, media_dir) = setup();
+
+        let result: Result<(), String> = with_restore_snapshot(&db_path, Some(&media_dir), || {
+            fs::write(&db_path, b"half restored").unwrap();
+            fs::create_dir_all(media_dir.join("avatars")).unwrap();
+            fs::write(media_dir.join("avatars/new.png"), b"new").unwrap();
+            Err("Failed to extract file: disk full".to_string())
+        });
+
+        assert_eq!(result.unwrap_err(), "Failed to extract file: disk full");
+        assert_eq!(fs::read(&db_path).unwrap(), b"old database");
+        assert!(media_dir.join("avatars/old.png").exists());
+        assert!(!media_dir.join("avatars/new.png").exists());
+        // database.db and media only, no snapshot left over
+        assert_eq!(entries(dir.path()), 2);
+    }
+
+    #[test]
+    fn test_successful_restore_drops_the_snapshot() {
+        let (dir, db_path, media_dir) = setup();
+
+        let restored = with_restore_snapshot(&db_path, Some(&media_dir), || {
+            // The old media is out of the way while restoring
+            assert_eq!(entries(&media_dir), 0);
+            fs::write(&db_path, b"new database").unwrap();
+            fs::create_dir_all(&media_dir).unwrap();
+            fs::write(media_dir.join("new.png"), b"new").unwrap();
+            Ok(7)
+        });
+
+        assert_eq!(restored, Ok(7));
+        assert_eq!(fs::read(&db_path).unwrap(), b"new database");
+        assert!(!media_dir.join("avatars").exists());
+        assert_eq!(entries(dir.path()), 2);
+    }
+
+    #[test]
+    fn test_rollback_without_previous_database_removes_the_new_one() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let db_path = dir.path().join("database.db");
+
+        let result: Result<(), String> = with_restore_snapshot(&db_path, None, || {
+            fs::write(&db_path, b"partial").unwrap();
+            Err("Restored database failed verification".to_string())
+        });
+
+        assert!(result.is_err());
+        assert!(!db_path.exists());
+    }
+}
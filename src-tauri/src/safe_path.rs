@@ -0,0 +1,172 @@
+//! Media paths confined to the media directory
+//!
+//! Avatar paths come from the database, from backups and from the UI as
+//! media-relative strings with either separator. `MediaRoot` canonicalizes
+//! the media directory once; `MediaRoot::resolve` turns a relative path into
+//! a `SafePath` only when it stays inside: no absolute paths or drive
+//! letters, no `..` components and no symlink leading out of the root.
+//! Media code resolves paths through here instead of checking for ".." on
+//! its own.
+
+use std::path::{Path, PathBuf};
+
+use crate::media_maintenance::normalize_media_path;
+
+/// A path known to lie inside the media directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafePath {
+    /// Media-relative, with forward slashes
+    relative: String,
+    full: PathBuf,
+}
+
+impl SafePath {
+    pub fn relative(&self) -> &str {
+        &self.relative
+    }
+
+    pub fn full_path(&self) -> &Path {
+        &self.full
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.full
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaRoot {
+    root: PathBuf,
+    canonical_root: PathBuf,
+}
+
+impl MediaRoot {
+    /// The directory must exist
+    pub fn new(root: &Path) -> Result<Self, String> {
+        let canonical_root = root
+            .canonicalize()
+            .map_err(|e| format!("Failed to canonicalize media directory: {}", e))?;
+        Ok(MediaRoot {
+            root: root.to_path_buf(),
+            canonical_root,
+        })
+    }
+
+    /// Resolve a media-relative path; the file itself need not exist
+    pub fn resolve(&self, relative: &str) -> Result<SafePath, String> {
+        let relative = normalize_relative(relative)?;
+        let full = self.root.join(&relative);
+
+        // The nearest part that exists decides where a link would lead
+        let mut existing = full.as_path();
+        while existing.symlink_metadata().is_err() {
+            existing = match existing.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        let canonical = existing
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve media path {}: {}", relative, e))?;
+        if !canonical.starts_with(&self.canonical_root) {
+            return Err(format!(
+                "Media path points outside the media directory: {}",
+                relative
+            ));
+        }
+
+        Ok(SafePath { relative, full })
+    }
+}
+
+/// `relative` with forward slashes and without empty or `.` parts; an error
+/// for anything that could leave the directory it is joined to
+pub fn normalize_relative(relative: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid media path: {}", relative);
+    let normalized = normalize_media_path(relative);
+    if normalized.starts_with('/') || Path::new(relative).is_absolute() {
+        return Err(invalid());
+    }
+
+    let mut parts = Vec::new();
+    for part in normalized.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(invalid()),
+            // Drive letters and NTFS alternate data streams
+            _ if part.contains(':') => return Err(invalid()),
+            _ => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(invalid());
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_normalize_relative() {
+        assert_eq!(
+            normalize_relative("avatars\\a.jpg"),
+            Ok("avatars/a.jpg".to_string())
+        );
+        assert_eq!(
+            normalize_relative("./avatars//a..b.jpg"),
+            Ok("avatars/a..b.jpg".to_string())
+        );
+        for bad in [
+            "",
+            "/etc/passwd",
+            "\\\\server\\share\\a.jpg",
+            "../database.db",
+            "avatars/../../x",
+            "avatars\\..\\..\\x",
+            "C:\\Windows\\a.jpg",
+            "avatars/a.jpg:stream",
+        ] {
+            assert!(
+                normalize_relative(bad).is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_stays_in_root() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("avatars")).unwrap();
+        let root = MediaRoot::new(&media).expect("root should resolve");
+
+        let path = root
+            .resolve("avatars\\new.jpg")
+            .expect("path should resolve");
+        assert_eq!(path.relative(), "avatars/new.jpg");
+        assert_eq!(path.full_path(), media.join("avatars/new.jpg"));
+        assert!(root.resolve("missing/dir/a.jpg").is_ok());
+        assert!(root.resolve("../secret.txt").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_rejects_links_out_of_root() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let media = dir.path().join("media");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(&media).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), b"x").unwrap();
+        std::os::unix::fs::symlink(&outside, media.join("avatars")).unwrap();
+
+        let root = MediaRoot::new(&media).expect("root should resolve");
+        assert!(root.resolve("avatars/secret.txt").is_err());
+        assert!(root.resolve("avatars/new.jpg").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Named filter/sort configurations for the user list ("Active instructors")
+//!
+//! Views live in the main database rather than the settings file so they
+//! travel with backups and restores, and they are executed here so every
+//! machine gets the same result for the same view.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{get_connection_safe, User};
+use crate::user_query::{self, UserFilters, UserSort};
+
+pub const MAX_VIEW_NAME_LENGTH: usize = 100;
+
+const VIEW_SELECT_COLUMNS: &str =
+    "id, name, filters, sort_column, sort_descending, created_by, created_at, updated_at";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedView {
+    pub id: i32,
+    pub name: String,
+    pub filters: UserFilters,
+    pub sort: UserSort,
+    pub created_by: Option<i32>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Fields the UI sends when creating or editing a view
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedViewInput {
+    pub name: String,
+    #[serde(default)]
+    pub filters: UserFilters,
+    #[serde(default)]
+    pub sort: UserSort,
+}
+
+pub fn init_saved_views_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_views (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            filters TEXT NOT NULL DEFAULT '{}',
+            sort_column TEXT NOT NULL DEFAULT 'username',
+            sort_descending INTEGER NOT NULL DEFAULT 0,
+            created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create saved_views table: {}", e))?;
+
+    Ok(())
+}
+
+fn map_view_row(row: &Row) -> rusqlite::Result<SavedView> {
+    let filters_json: String = row.get(2)?;
+    // A row edited by hand with broken JSON shows everything rather than failing the list
+    let filters = serde_json::from_str(&filters_json).unwrap_or_default();
+
+    Ok(SavedView {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        filters,
+        sort: UserSort {
+            column: row.get(3)?,
+            descending: row.get(4)?,
+        },
+        created_by: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn validate_input(input: &SavedViewInput) -> Result<String, String> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err("View name is required".to_string());
+    }
+    if name.chars().count() > MAX_VIEW_NAME_LENGTH {
+        return Err(format!(
+            "View name must be at most {} characters",
+            MAX_VIEW_NAME_LENGTH
+        ));
+    }
+    user_query::validate_sort(&input.sort)?;
+    Ok(name.to_string())
+}
+
+fn map_write_error(name: &str, e: rusqlite::Error) -> String {
+    match e {
+        rusqlite::Error::SqliteFailure(ref err, _)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            format!("A view named '{}' already exists", name)
+        }
+        e => format!("Failed to save view: {}", e),
+    }
+}
+
+pub fn list_saved_views_with_conn(conn: &Connection) -> Result<Vec<SavedView>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM saved_views ORDER BY name",
+            VIEW_SELECT_COLUMNS
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let rows = stmt
+        .query_map([], map_view_row)
+        .map_err(|e| format!("Failed to query saved views: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse saved view: {}", e))
+}
+
+pub fn get_saved_view_with_conn(conn: &Connection, id: i32) -> Result<Option<SavedView>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM saved_views WHERE id = ?",
+            VIEW_SELECT_COLUMNS
+        ),
+        params![id],
+        map_view_row,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load saved view: {}", e))
+}
+
+pub fn create_saved_view_with_conn(
+    conn: &Connection,
+    input: &SavedViewInput,
+    created_by: Option<i32>,
+) -> Result<SavedView, String> {
+    let name = validate_input(input)?;
+    let filters = serde_json::to_string(&input.filters)
+        .map_err(|e| format!("Failed to serialize filters: {}", e))?;
+
+    conn.execute(
+        "INSERT INTO saved_views (name, filters, sort_column, sort_descending, created_by)
+         VALUES (?, ?, ?, ?, ?)",
+        params![
+            name,
+            filters,
+            input.sort.column,
+            input.sort.descending,
+            created_by
+        ],
+    )
+    .map_err(|e| map_write_error(&name, e))?;
+
+    get_saved_view_with_conn(conn, conn.last_insert_rowid() as i32)?
+        .ok_or_else(|| "Saved view disappeared after insert".to_string())
+}
+
+pub fn update_saved_view_with_conn(
+    conn: &Connection,
+    id: i32,
+    input: &SavedViewInput,
+) -> Result<SavedView, String> {
+    let name = validate_input(input)?;
+    let filters = serde_json::to_string(&input.filters)
+        .map_err(|e| format!("Failed to serialize filters: {}", e))?;
+
+    let updated = conn
+        .execute(
+            "UPDATE saved_views
+             SET name = ?, filters = ?, sort_column = ?, sort_descending = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?",
+            params![name, filters, input.sort.column, input.sort.descending, id],
+        )
+        .map_err(|e| map_write_error(&name, e))?;
+    if updated == 0 {
+        return Err(format!("Saved view {} not found", id));
+    }
+
+    get_saved_view_with_conn(conn, id)?.ok_or_else(|| format!("Saved view {} not found", id))
+}
+
+pub fn delete_saved_view_with_conn(conn: &Connection, id: i32) -> Result<bool, String> {
+    let deleted = conn
+        .execute("DELETE FROM saved_views WHERE id = ?", params![id])
+        .map_err(|e| format!("Failed to delete saved view: {}", e))?;
+    Ok(deleted > 0)
+}
+
+/// Users matching the view, in the view's order
+pub fn run_saved_view_with_conn(conn: &Connection, id: i32) -> Result<Vec<User>, String> {
+    let view = get_saved_view_with_conn(conn, id)?
+        .ok_or_else(|| format!("Saved view {} not found", id))?;
+    user_query::query_users_sorted_with_conn(conn, &view.filters, &view.sort)
+}
+
+fn connection() -> Result<Connection, String> {
+    get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))
+}
+
+pub fn list_saved_views() -> Result<Vec<SavedView>, String> {
+    list_saved_views_with_conn(&connection()?)
+}
+
+pub fn create_saved_view(
+    input: &SavedViewInput,
+    created_by: Option<i32>,
+) -> Result<SavedView, String> {
+    create_saved_view_with_conn(&connection()?, input, created_by)
+}
+
+pub fn update_saved_view(id: i32, input: &SavedViewInput) -> Result<SavedView, String> {
+    update_saved_view_with_conn(&connection()?, id, input)
+}
+
+pub fn delete_saved_view(id: i32) -> Result<bool, String> {
+    delete_saved_view_with_conn(&connection()?, id)
+}
+
+pub fn run_saved_view(id: i32) -> Result<Vec<User>, String> {
+    run_saved_view_with_conn(&connection()?, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn conn_with_users() -> Connection {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (username, email, password_hash, full_name, role, is_active) VALUES
+                ('alpha', 'alpha@test.com', 'h', 'Alpha', 'editor', 1),
+                ('bravo', 'bravo@test.com', 'h', 'Bravo', 'editor', 1),
+                ('charlie', 'charlie@test.com', 'h', 'Charlie', 'editor', 0);",
+        )
+        .expect("users should insert");
+        conn
+    }
+
+    fn active_editors() -> SavedViewInput {
+        SavedViewInput {
+            name: "Active editors".to_string(),
+            filters: UserFilters {
+                role: Some("editor".to_string()),
+                is_active: Some(true),
+                ..UserFilters::default()
+            },
+            sort: UserSort {
+                column: "username".to_string(),
+                descending: true,
+            },
+        }
+    }
+
+    #[test]
+    fn test_view_runs_with_filters_and_sort() {
+        let conn = conn_with_users();
+        let view = create_saved_view_with_conn(&conn, &active_editors(), None)
+            .expect("create should work");
+
+        let users = run_saved_view_with_conn(&conn, view.id).expect("run should work");
+
+        let names: Vec<&str> = users.iter().map(|u| u.username.as_str()).collect();
+        assert_eq!(names, vec!["bravo", "alpha"]);
+    }
+
+    #[test]
+    fn test_crud_and_duplicate_names() {
+        let conn = conn_with_users();
+        let view = create_saved_view_with_conn(&conn, &active_editors(), None)
+            .expect("create should work");
+
+        let mut duplicate = active_editors();
+        duplicate.name = "ACTIVE EDITORS".to_string();
+        assert!(create_saved_view_with_conn(&conn, &duplicate, None)
+            .unwrap_err()
+            .contains("already exists"));
+
+        let mut renamed = active_editors();
+        renamed.name = "All editors".to_string();
+        renamed.filters.is_active = None;
+        let updated = update_saved_view_with_conn(&conn, view.id, &renamed).expect("update");
+        assert_eq!(updated.name, "All editors");
+        assert_eq!(run_saved_view_with_conn(&conn, view.id).unwrap().len(), 3);
+
+        assert!(delete_saved_view_with_conn(&conn, view.id).unwrap());
+        assert!(list_saved_views_with_conn(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_sort_rejected() {
+        let conn = conn_with_users();
+        let mut input = active_editors();
+        input.sort.column = "password_hash".to_string();
+
+        assert!(create_saved_view_with_conn(&conn, &input, None).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Login sessions with opaque tokens
+//!
+//! `login` issues a random token for the authenticated user and stores only
+//! its SHA-256 in the `sessions` table, so a copied database or backup holds
+//! no usable tokens. A session lasts `SESSION_IDLE_HOURS` after its last
+//! successful `validate_session`; deactivating or deleting the user ends it
+//! as well. `logout` removes it.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::database::{get_connection_safe, map_user_row, User, USER_SELECT_COLUMNS};
+
+pub const SESSIONS_TABLE: &str = "sessions";
+/// Idle time after which a session has to sign in again
+pub const SESSION_IDLE_HOURS: i64 = 8;
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionLogin {
+    pub user: User,
+    /// Shown once; only its hash is stored
+    pub token: String,
+    pub expires_at: String,
+    /// The session can only call `change_password` until this is cleared
+    pub must_change_password: bool,
+}
+
+pub fn init_sessions_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            token_hash TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expires_at DATETIME NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create sessions table: {}", e))?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id)",
+        [],
+    )
+    .map_err(|e| format!("Failed to create sessions index: {}", e))?;
+
+    Ok(())
+}
+
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to read random bytes: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Short name of the session behind `token` for logs; the token itself
+/// cannot be recovered from it
+pub fn session_id(token: &str) -> String {
+    token_hash(token)[..16].to_string()
+}
+
+fn idle_expiry_modifier() -> String {
+    format!("+{} hours", SESSION_IDLE_HOURS)
+}
+
+/// Drop sessions past their expiry; returns how many
+pub fn remove_expired_sessions_with_conn(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM sessions WHERE expires_at <= CURRENT_TIMESTAMP",
+        [],
+    )
+    .map_err(|e| format!("Failed to remove expired sessions: {}", e))
+}
+
+/// Start a session for `user`; expired sessions of everyone are dropped
+pub fn create_session_with_conn(conn: &Connection, user: User) -> Result<SessionLogin, String> {
+    let user_id = user
+        .id
+        .ok_or("Cannot start a session for an unsaved user")?;
+    let token = new_token()?;
+
+    remove_expired_sessions_with_conn(conn)?;
+    let expires_at: String = conn
+        .query_row(
+            "INSERT INTO sessions (token_hash, user_id, expires_at)
+             VALUES (?1, ?2, datetime('now', ?3))
+             RETURNING expires_at",
+            params![token_hash(&token), user_id, idle_expiry_modifier()],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to create session: {}", e))?;
+
+    Ok(SessionLogin {
+        must_change_password: user.must_change_password,
+        user,
+        token,
+        expires_at,
+    })
+}
+
+/// The signed-in user when `token` is a live session of an active user; the
+/// session's idle timer restarts
+pub fn validate_session_with_conn(conn: &Connection, token: &str) -> Result<Option<User>, String> {
+    let hash = token_hash(token);
+    let user = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM users
+                 WHERE is_active = 1 AND id = (
+                     SELECT user_id FROM sessions
+                     WHERE token_hash = ?1 AND expires_at > CURRENT_TIMESTAMP
+                 )",
+                USER_SELECT_COLUMNS
+            ),
+            params![hash],
+            map_user_row,
+        )
+        .optional()
+        .map_err(|e| format!("Failed to validate session: {}", e))?;
+
+    if user.is_some() {
+        conn.execute(
+            "UPDATE sessions SET expires_at = datetime('now', ?2) WHERE token_hash = ?1",
+            params![hash, idle_expiry_modifier()],
+        )
+        .map_err(|e| format!("Failed to extend session: {}", e))?;
+    }
+    Ok(user)
+}
+
+/// Whether a session was ended
+pub fn delete_session_with_conn(conn: &Connection, token: &str) -> Result<bool, String> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM sessions WHERE token_hash = ?",
+            params![token_hash(token)],
+        )
+        .map_err(|e| format!("Failed to end session: {}", e))?;
+    Ok(deleted > 0)
+}
+
+pub fn create_session(user: User) -> Result<SessionLogin, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    create_session_with_conn(&conn, user)
+}
+
+pub fn validate_session(token: &str) -> Result<Option<User>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    validate_session_with_conn(&conn, token)
+}
+
+pub fn delete_session(token: &str) -> Result<bool, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    delete_session_with_conn(&conn, token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    #[test]
+    fn test_session_lifecycle() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name) VALUES (1, 'a', 'a@test.com', 'h', 'A')",
+            [],
+        )
+        .expect("user insert should succeed");
+        let user = conn
+            .query_row(
+                &format!("SELECT {} FROM users WHERE id = 1", USER_SELECT_COLUMNS),
+                [],
+                map_user_row,
+            )
+            .expect("user should exist");
+
+        let login = create_session_with_conn(&conn, user).expect("session should start");
+        assert_eq!(login.token.len(), TOKEN_BYTES * 2);
+        let stored: String = conn
+            .query_row("SELECT token_hash FROM sessions", [], |row| row.get(0))
+            .unwrap();
+        assert_ne!(stored, login.token);
+        assert!(stored.starts_with(&session_id(&login.token)));
+
+        let validated = validate_session_with_conn(&conn, &login.token).expect("should validate");
+        assert_eq!(validated.map(|u| u.username), Some("a".to_string()));
+        assert!(validate_session_with_conn(&conn, "not-a-token")
+            .unwrap()
+            .is_none());
+
+        conn.execute("UPDATE users SET is_active = 0 WHERE id = 1", [])
+            .unwrap();
+        assert!(validate_session_with_conn(&conn, &login.token)
+            .unwrap()
+            .is_none());
+        conn.execute("UPDATE users SET is_active = 1 WHERE id = 1", [])
+            .unwrap();
+
+        conn.execute(
+            "UPDATE sessions SET expires_at = datetime('now', '-1 minute')",
+            [],
+        )
+        .unwrap();
+        assert!(validate_session_with_conn(&conn, &login.token)
+            .unwrap()
+            .is_none());
+
+        assert!(delete_session_with_conn(&conn, &login.token).unwrap());
+        assert!(!delete_session_with_conn(&conn, &login.token).unwrap());
+    }
+}
@@ -0,0 +1,335 @@
+//! Application settings persisted as JSON in the app root
+//!
+//! Every section uses `#[serde(default)]` so settings files written by older
+//! versions keep loading after new options are added. Secrets never go in
+//! this file - they live in the OS keyring (see `store_secret`).
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILENAME: &str = "settings.json";
+const KEYRING_SERVICE: &str = "pqs-rtn-hybrid-storage";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SftpAuthMethod {
+    Password,
+    Key,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SftpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth_method: SftpAuthMethod,
+    /// Private key file, used when `auth_method` is `Key`
+    pub private_key_path: Option<String>,
+    pub remote_directory: String,
+    /// SHA-256 fingerprint of the server's host key as OpenSSH prints it
+    /// (`SHA256:...`); pinned on the first connection when not set here
+    #[serde(default)]
+    pub host_key_sha256: Option<String>,
+}
+
+impl SftpSettings {
+    /// Keyring account holding the password or key passphrase
+    pub fn secret_key(&self) -> String {
+        format!("sftp:{}@{}:{}", self.username, self.host, self.port)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationChannel {
+    Smtp,
+    Line,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SmtpSettings {
+    pub host: String,
+    /// 465 uses implicit TLS, any other port STARTTLS
+    pub port: u16,
+    /// Empty for relays that accept mail without logging in
+    pub username: String,
+    pub from: String,
+    pub recipients: Vec<String>,
+}
+
+impl SmtpSettings {
+    /// Keyring account holding the SMTP password
+    pub fn secret_key(&self) -> String {
+        format!("smtp:{}@{}:{}", self.username, self.host, self.port)
+    }
+}
+
+/// LINE Messaging API destination; LINE Notify, used before, shut down on
+/// 2025-03-31
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LineSettings {
+    /// User, group or room id the official account pushes the summary to
+    pub to: String,
+}
+
+/// Keyring account holding the LINE channel access token
+pub const LINE_CHANNEL_TOKEN_SECRET_KEY: &str = "notify:line-messaging";
+
+/// Daily backup summary sent by `backup_notify`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NotificationSettings {
+    pub channel: NotificationChannel,
+    /// Used when `channel` is `Smtp`
+    pub smtp: Option<SmtpSettings>,
+    /// Used when `channel` is `Line`
+    #[serde(default)]
+    pub line: Option<LineSettings>,
+    /// Local hour (0-23) after which the day's summary is sent
+    pub send_hour: u8,
+    /// Stay quiet on days where every backup succeeded
+    #[serde(default)]
+    pub only_on_failure: bool,
+}
+
+/// Limits applied to avatar uploads by both avatar managers (see `avatar_policy`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AvatarPolicy {
+    pub max_upload_bytes: u64,
+    pub accepted_mime_types: Vec<String>,
+    /// Longest side in pixels; larger images are scaled down and re-encoded
+    pub max_dimension: u32,
+    /// JPEG quality (1-100) used when an image is re-encoded
+    pub reencode_quality: u8,
+}
+
+impl Default for AvatarPolicy {
+    fn default() -> Self {
+        AvatarPolicy {
+            max_upload_bytes: 10 * 1024 * 1024,
+            accepted_mime_types: vec![
+                "image/jpeg".to_string(),
+                "image/png".to_string(),
+                "image/webp".to_string(),
+                "image/gif".to_string(),
+            ],
+            max_dimension: 1024,
+            reencode_quality: 85,
+        }
+    }
+}
+
+/// Size allowed for avatars and attachments together (see `media_budget`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MediaBudget {
+    pub budget_bytes: u64,
+    /// Percentages of `budget_bytes` that trigger a warning
+    #[serde(default = "default_warning_percent")]
+    pub warning_percent: u8,
+    #[serde(default = "default_critical_percent")]
+    pub critical_percent: u8,
+}
+
+fn default_warning_percent() -> u8 {
+    80
+}
+
+fn default_critical_percent() -> u8 {
+    95
+}
+
+/// Algorithm for new password hashes (see `auth`)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HashScheme {
+    Bcrypt,
+    #[default]
+    Argon2id,
+}
+
+/// Argon2id cost for new password hashes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Settings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+/// OWASP's recommended minimum: 19 MiB, 2 passes, 1 lane
+impl Default for Argon2Settings {
+    fn default() -> Self {
+        Argon2Settings {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AppSettings {
+    pub sftp: Option<SftpSettings>,
+    /// Name of the workspace opened at startup; None means the default workspace
+    pub active_workspace: Option<String>,
+    pub avatar_policy: AvatarPolicy,
+    /// Where exports are written; None means the Documents folder
+    pub export_directory: Option<String>,
+    /// Record every read of a personnel photo in the activity log
+    pub audit_avatar_access: bool,
+    /// bcrypt cost for new password hashes; None means bcrypt's default
+    pub password_hash_cost: Option<u32>,
+    pub password_hash_scheme: HashScheme,
+    /// None means the Argon2Settings defaults
+    pub argon2_params: Option<Argon2Settings>,
+    /// None means no backup notifications are sent
+    pub notifications: Option<NotificationSettings>,
+    /// None means media size is not watched
+    pub media_budget: Option<MediaBudget>,
+}
+
+/// Settings are shared by all workspaces, so they sit in the app root
+fn get_settings_path() -> Result<PathBuf, String> {
+    Ok(crate::storage_paths::get_app_root()?.join(SETTINGS_FILENAME))
+}
+
+/// Missing file means defaults; a corrupt file is an error rather than silently reset
+pub fn load_settings_from(path: &Path) -> Result<AppSettings, String> {
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read settings file: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse settings file: {}", e))
+}
+
+/// Write through a temp file so a crash never leaves half-written settings
+pub fn save_settings_to(path: &Path, settings: &AppSettings) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content).map_err(|e| format!("Failed to write settings file: {}", e))?;
+    fs::rename(&temp_path, path).map_err(|e| format!("Failed to replace settings file: {}", e))
+}
+
+pub fn load_settings() -> Result<AppSettings, String> {
+    load_settings_from(&get_settings_path()?)
+}
+
+pub fn save_settings(settings: &AppSettings) -> Result<(), String> {
+    save_settings_to(&get_settings_path()?, settings)
+}
+
+/// Load, modify and save settings in one step
+pub fn update_settings<F>(update: F) -> Result<AppSettings, String>
+where
+    F: FnOnce(&mut AppSettings),
+{
+    let mut settings = load_settings()?;
+    update(&mut settings);
+    save_settings(&settings)?;
+    Ok(settings)
+}
+
+pub fn store_secret(account: &str, secret: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, account)
+        .and_then(|entry| entry.set_password(secret))
+        .map_err(|e| format!("Failed to store secret in keyring: {}", e))
+}
+
+/// Returns None when no secret has been stored for this account
+pub fn load_secret(account: &str) -> Result<Option<String>, String> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, account)
+        .map_err(|e| format!("Failed to open keyring entry: {}", e))?;
+
+    match entry.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read secret from keyring: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_settings_file_gives_defaults() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let settings =
+            load_settings_from(&dir.path().join(SETTINGS_FILENAME)).expect("load should succeed");
+        assert_eq!(settings, AppSettings::default());
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join(SETTINGS_FILENAME);
+        let settings = AppSettings {
+            sftp: Some(SftpSettings {
+                host: "files.local".to_string(),
+                port: 22,
+                username: "backup".to_string(),
+                auth_method: SftpAuthMethod::Key,
+                private_key_path: Some("C:/keys/id_ed25519".to_string()),
+                remote_directory: "/backups/pqs".to_string(),
+                host_key_sha256: Some("SHA256:abc".to_string()),
+            }),
+            active_workspace: Some("squadron-1".to_string()),
+            avatar_policy: AvatarPolicy {
+                max_upload_bytes: 2 * 1024 * 1024,
+                accepted_mime_types: vec!["image/png".to_string()],
+                max_dimension: 512,
+                reencode_quality: 70,
+            },
+            export_directory: Some("D:/Exports".to_string()),
+            audit_avatar_access: true,
+            password_hash_cost: Some(11),
+            password_hash_scheme: HashScheme::Bcrypt,
+            argon2_params: Some(Argon2Settings {
+                memory_kib: 64 * 1024,
+                iterations: 3,
+                parallelism: 1,
+            }),
+            notifications: Some(NotificationSettings {
+                channel: NotificationChannel::Smtp,
+                smtp: Some(SmtpSettings {
+                    host: "mail.navy.local".to_string(),
+                    port: 587,
+                    username: "pqs".to_string(),
+                    from: "pqs@navy.local".to_string(),
+                    recipients: vec!["admin@navy.local".to_string()],
+                }),
+                line: None,
+                send_hour: 7,
+                only_on_failure: false,
+            }),
+            media_budget: Some(MediaBudget {
+                budget_bytes: 10 * 1024 * 1024 * 1024,
+                warning_percent: 75,
+                critical_percent: 90,
+            }),
+        };
+
+        save_settings_to(&path, &settings).expect("save should succeed");
+        let loaded = load_settings_from(&path).expect("load should succeed");
+
+        assert_eq!(loaded, settings);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_ignored() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join(SETTINGS_FILENAME);
+        fs::write(&path, r#"{"future_option": true}"#).unwrap();
+
+        let settings = load_settings_from(&path).expect("load should succeed");
+        assert!(settings.sftp.is_none());
+        assert_eq!(settings.avatar_policy, AvatarPolicy::default());
+    }
+}
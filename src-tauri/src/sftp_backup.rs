@@ -0,0 +1,406 @@
+//! SFTP as a remote backup destination
+//!
+//! Uploads local backup files to an SSH file server, lists what is stored
+//! there and downloads a file back into the local backup directory so the
+//! regular restore commands can use it. A split hybrid backup travels as
+//! its whole volume set and is listed under its first volume.
+//!
+//! The server's host key is checked on every connection against the
+//! fingerprint in the settings. When none is configured, the key seen on the
+//! first successful connection is pinned; a different key afterwards is
+//! refused until an administrator saves the new fingerprint (or an empty one
+//! to pin again).
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use ssh2::{HashType, Session, Sftp};
+use std::fs;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backup_manager;
+use crate::backup_volumes;
+use crate::logger;
+use crate::settings::{self, SftpAuthMethod, SftpSettings};
+use crate::validation;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const BACKUP_EXTENSIONS: [&str; 4] = ["zip", "json", "db", "sql"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteBackupInfo {
+    pub filename: String,
+    pub size: u64,
+    pub modified: Option<String>,
+}
+
+/// Backup filenames must be a single path component
+fn validate_backup_filename(filename: &str) -> Result<(), String> {
+    validation::file_name("filename", filename)?;
+    Ok(())
+}
+
+fn is_backup_file(path: &Path) -> bool {
+    if backup_volumes::is_first_volume(path) {
+        return backup_volumes::set_base(path).is_some_and(|base| is_backup_file(&base));
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| BACKUP_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// SFTP paths always use forward slashes, whatever the local platform
+fn remote_path(settings: &SftpSettings, filename: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}/{}",
+        settings.remote_directory.trim_end_matches('/'),
+        filename
+    ))
+}
+
+fn load_sftp_settings() -> Result<SftpSettings, String> {
+    settings::load_settings()?
+        .sftp
+        .ok_or_else(|| "SFTP backup destination is not configured".to_string())
+}
+
+/// Host key hash in OpenSSH's notation
+fn fingerprint(sha256: &[u8]) -> String {
+    format!("SHA256:{}", general_purpose::STANDARD_NO_PAD.encode(sha256))
+}
+
+/// Fingerprints match whether or not they carry the `SHA256:` prefix or padding
+fn same_fingerprint(pinned: &str, actual: &str) -> bool {
+    let normalize = |f: &str| {
+        f.trim()
+            .trim_start_matches("SHA256:")
+            .trim_end_matches('=')
+            .to_string()
+    };
+    normalize(pinned) == normalize(actual)
+}
+
+/// Refuse a server whose key differs from the pinned one; the first key
+/// seen is pinned
+fn verify_host_key(settings: &SftpSettings, session: &Session) -> Result<(), String> {
+    let actual = session
+        .host_key_hash(HashType::Sha256)
+        .map(fingerprint)
+        .ok_or("SFTP server sent no host key")?;
+    match settings.host_key_sha256.as_deref() {
+        Some(pinned) if same_fingerprint(pinned, &actual) => Ok(()),
+        Some(pinned) => Err(format!(
+            "SFTP host key of {} changed: expected {}, got {}. If the server was \
+             reinstalled, save the new fingerprint in the SFTP settings",
+            settings.host, pinned, actual
+        )),
+        None => {
+            logger::info(format!(
+                "Pinning SFTP host key of {}:{}: {}",
+                settings.host, settings.port, actual
+            ));
+            settings::update_settings(|s| {
+                if let Some(sftp) = s
+                    .sftp
+                    .as_mut()
+                    .filter(|sftp| sftp.host == settings.host && sftp.port == settings.port)
+                {
+                    sftp.host_key_sha256 = Some(actual.clone());
+                }
+            })?;
+            Ok(())
+        }
+    }
+}
+
+/// Fingerprint to keep when settings are saved: an explicit one wins, empty
+/// clears the pin, and none keeps the pin while host and port stay the same
+fn pinned_host_key(existing: Option<&SftpSettings>, incoming: &SftpSettings) -> Option<String> {
+    match incoming.host_key_sha256.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(fingerprint) => Some(fingerprint.to_string()),
+        None => existing
+            .filter(|existing| existing.host == incoming.host && existing.port == incoming.port)
+            .and_then(|existing| existing.host_key_sha256.clone()),
+    }
+}
+
+fn connect(settings: &SftpSettings) -> Result<(Session, Sftp), String> {
+    let address = (settings.host.as_str(), settings.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve SFTP host: {}", e))?
+        .next()
+        .ok_or_else(|| format!("No address found for SFTP host {}", settings.host))?;
+
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .map_err(|e| format!("Failed to connect to SFTP server: {}", e))?;
+
+    let mut session = Session::new().map_err(|e| format!("Failed to create SSH session: {}", e))?;
+    session.set_tcp_stream(tcp);
+    session
+        .handshake()
+        .map_err(|e| format!("SSH handshake failed: {}", e))?;
+    // Before any credential is sent
+    verify_host_key(settings, &session)?;
+
+    let secret = settings::load_secret(&settings.secret_key())?;
+    match settings.auth_method {
+        SftpAuthMethod::Password => {
+            let password = secret.ok_or("No SFTP password stored in keyring")?;
+            session
+                .userauth_password(&settings.username, &password)
+                .map_err(|e| format!("SFTP password authentication failed: {}", e))?;
+        }
+        SftpAuthMethod::Key => {
+            let key_path = settings
+                .private_key_path
+                .as_ref()
+                .ok_or("No private key configured for SFTP")?;
+            session
+                .userauth_pubkey_file(
+                    &settings.username,
+                    None,
+                    Path::new(key_path),
+                    secret.as_deref(),
+                )
+                .map_err(|e| format!("SFTP key authentication failed: {}", e))?;
+        }
+    }
+
+    if !session.authenticated() {
+        return Err("SFTP authentication failed".to_string());
+    }
+
+    let sftp = session
+        .sftp()
+        .map_err(|e| format!("Failed to open SFTP channel: {}", e))?;
+    Ok((session, sftp))
+}
+
+pub fn get_sftp_settings() -> Result<Option<SftpSettings>, String> {
+    Ok(settings::load_settings()?.sftp)
+}
+
+/// Save connection settings; the secret (password or key passphrase) goes to the keyring
+pub fn save_sftp_settings(mut sftp: SftpSettings, secret: Option<String>) -> Result<(), String> {
+    if sftp.host.trim().is_empty() || sftp.username.trim().is_empty() {
+        return Err("SFTP host and username are required".to_string());
+    }
+    sftp.host_key_sha256 = pinned_host_key(get_sftp_settings()?.as_ref(), &sftp);
+
+    if let Some(secret) = secret.filter(|s| !s.is_empty()) {
+        settings::store_secret(&sftp.secret_key(), &secret)?;
+    }
+
+    settings::update_settings(|s| s.sftp = Some(sftp))?;
+    Ok(())
+}
+
+pub fn test_sftp_connection() -> Result<String, String> {
+    let settings = load_sftp_settings()?;
+    let (_session, sftp) = connect(&settings)?;
+    sftp.stat(Path::new(&settings.remote_directory))
+        .map_err(|e| format!("Remote directory is not accessible: {}", e))?;
+    Ok(format!(
+        "Connected to {}:{} as {}",
+        settings.host, settings.port, settings.username
+    ))
+}
+
+pub fn upload_backup_to_sftp(filename: &str) -> Result<String, String> {
+    validate_backup_filename(filename)?;
+    let local_path = backup_manager::get_backup_directory()?.join(filename);
+    if !local_path.exists() {
+        return Err(format!("Backup file not found: {}", filename));
+    }
+
+    let volumes = backup_volumes::volume_set(&local_path)?;
+
+    let settings = load_sftp_settings()?;
+    let (_session, sftp) = connect(&settings)?;
+
+    // Best effort - the directory usually exists already
+    let _ = sftp.mkdir(Path::new(&settings.remote_directory), 0o755);
+
+    let mut bytes = 0;
+    for volume in &volumes {
+        let name = volume
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or("Invalid backup volume name")?;
+        let mut local_file =
+            fs::File::open(volume).map_err(|e| format!("Failed to open backup file: {}", e))?;
+        let mut remote_file = sftp
+            .create(&remote_path(&settings, &name))
+            .map_err(|e| format!("Failed to create remote file: {}", e))?;
+        bytes += io::copy(&mut local_file, &mut remote_file)
+            .map_err(|e| format!("Failed to upload backup: {}", e))?;
+    }
+
+    logger::info(format!(
+        "Uploaded backup {} ({} bytes) to SFTP {}",
+        filename, bytes, settings.host
+    ));
+    Ok(format!(
+        "Backup uploaded to {}:{}",
+        settings.host, settings.remote_directory
+    ))
+}
+
+pub fn list_sftp_backups() -> Result<Vec<RemoteBackupInfo>, String> {
+    let settings = load_sftp_settings()?;
+    let (_session, sftp) = connect(&settings)?;
+
+    let entries = sftp
+        .readdir(Path::new(&settings.remote_directory))
+        .map_err(|e| format!("Failed to list remote directory: {}", e))?;
+
+    let mut backups: Vec<RemoteBackupInfo> = entries
+        .into_iter()
+        .filter(|(path, stat)| stat.is_file() && is_backup_file(path))
+        .filter_map(|(path, stat)| {
+            let filename = path.file_name()?.to_string_lossy().to_string();
+            let modified = stat
+                .mtime
+                .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+                .map(|dt| dt.to_rfc3339());
+            Some(RemoteBackupInfo {
+                filename,
+                size: stat.size.unwrap_or(0),
+                modified,
+            })
+        })
+        .collect();
+
+    // Newest first
+    backups.sort_by(|a, b| b.modified.cmp(&a.modified));
+    Ok(backups)
+}
+
+/// Remote files of the backup `filename`: every volume when it is the first
+/// of a split backup
+fn remote_volume_names(sftp: &Sftp, settings: &SftpSettings, filename: &str) -> Vec<String> {
+    let Some(base) = backup_volumes::set_base(Path::new(filename)) else {
+        return vec![filename.to_string()];
+    };
+    let mut names = vec![filename.to_string()];
+    for index in 2.. {
+        let name = backup_volumes::volume_path(&base, index)
+            .to_string_lossy()
+            .to_string();
+        if sftp.stat(&remote_path(settings, &name)).is_err() {
+            break;
+        }
+        names.push(name);
+    }
+    names
+}
+
+fn download_file(sftp: &Sftp, remote: &Path, local_path: &Path) -> Result<(), String> {
+    let temp_path = local_path.with_extension("download");
+    let mut remote_file = sftp
+        .open(remote)
+        .map_err(|e| format!("Failed to open remote backup: {}", e))?;
+    let mut local_file =
+        fs::File::create(&temp_path).map_err(|e| format!("Failed to create local file: {}", e))?;
+
+    if let Err(e) = io::copy(&mut remote_file, &mut local_file) {
+        drop(local_file);
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Failed to download backup: {}", e));
+    }
+    drop(local_file);
+
+    fs::rename(&temp_path, local_path)
+        .map_err(|e| format!("Failed to move downloaded backup into place: {}", e))
+}
+
+/// Download into the local backup directory and return the local path
+pub fn download_sftp_backup(filename: &str) -> Result<String, String> {
+    validate_backup_filename(filename)?;
+    let settings = load_sftp_settings()?;
+    let (_session, sftp) = connect(&settings)?;
+
+    let backup_dir = backup_manager::get_backup_directory()?;
+    let names = remote_volume_names(&sftp, &settings, filename);
+    let mut downloaded = Vec::new();
+    for name in &names {
+        let local_path = backup_dir.join(name);
+        if let Err(e) = download_file(&sftp, &remote_path(&settings, name), &local_path) {
+            for done in &downloaded {
+                let _ = fs::remove_file(done);
+            }
+            return Err(e);
+        }
+        downloaded.push(local_path);
+    }
+
+    let local_path = backup_dir.join(filename);
+    // Refuses a set that is incomplete on the server too
+    backup_volumes::volume_set(&local_path)?;
+    Ok(local_path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_backup_filename() {
+        assert!(validate_backup_filename("hybrid_backup_1700000000.zip").is_ok());
+        assert!(validate_backup_filename("../database.db").is_err());
+        assert!(validate_backup_filename("sub/backup.zip").is_err());
+        assert!(validate_backup_filename("sub\\backup.zip").is_err());
+        assert!(validate_backup_filename("").is_err());
+    }
+
+    #[test]
+    fn test_only_backup_extensions_are_listed() {
+        assert!(is_backup_file(Path::new("/remote/hybrid_backup_1.zip")));
+        assert!(is_backup_file(Path::new("/remote/database_backup_1.JSON")));
+        assert!(!is_backup_file(Path::new("/remote/notes.txt")));
+        assert!(!is_backup_file(Path::new("/remote/no_extension")));
+        assert!(is_backup_file(Path::new("/remote/hybrid_backup_1.zip.001")));
+        assert!(!is_backup_file(Path::new(
+            "/remote/hybrid_backup_1.zip.002"
+        )));
+        assert!(!is_backup_file(Path::new("/remote/notes.txt.001")));
+    }
+
+    #[test]
+    fn test_host_key_pin_survives_saves_to_the_same_server() {
+        let pinned = fingerprint(&[7u8; 32]);
+        assert!(pinned.starts_with("SHA256:"));
+        assert!(same_fingerprint(&format!("{}=", pinned), &pinned));
+        assert!(!same_fingerprint(&fingerprint(&[8u8; 32]), &pinned));
+
+        let existing = SftpSettings {
+            host: "files.local".to_string(),
+            port: 22,
+            username: "backup".to_string(),
+            auth_method: SftpAuthMethod::Password,
+            private_key_path: None,
+            remote_directory: "/backups".to_string(),
+            host_key_sha256: Some(pinned.clone()),
+        };
+        let resaved = SftpSettings {
+            host_key_sha256: None,
+            remote_directory: "/other".to_string(),
+            ..existing.clone()
+        };
+        assert_eq!(pinned_host_key(Some(&existing), &resaved), Some(pinned));
+        let moved = SftpSettings {
+            host: "new.local".to_string(),
+            ..resaved.clone()
+        };
+        assert_eq!(pinned_host_key(Some(&existing), &moved), None);
+        let cleared = SftpSettings {
+            host_key_sha256: Some(" ".to_string()),
+            ..resaved
+        };
+        assert_eq!(pinned_host_key(Some(&existing), &cleared), None);
+    }
+}
@@ -0,0 +1,450 @@
+//! Signature blocks on existing PDF files
+//!
+//! `stamp_pdf` draws an officer's signature image and title block at the
+//! bottom right of the last page and saves the result as a new file. The
+//! image goes in as an image XObject with its transparency kept as a soft
+//! mask. Title lines are mostly Thai, which the standard PDF fonts cannot
+//! show, so they are written with a TrueType font installed on the machine
+//! (the first of `TITLE_FONT_CANDIDATES` that has every character), embedded
+//! whole. The original page content is wrapped in q/Q so whatever graphics
+//! state it leaves behind does not move the block.
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fonts with Thai glyphs shipped with Windows, macOS and common Linux
+/// distributions; collections (.ttc) cannot be embedded and are not listed
+pub const TITLE_FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\tahoma.ttf",
+    "C:\\Windows\\Fonts\\LeelawUI.ttf",
+    "C:\\Windows\\Fonts\\leelawad.ttf",
+    "/System/Library/Fonts/Supplemental/Tahoma.ttf",
+    "/Library/Fonts/Tahoma.ttf",
+    "/usr/share/fonts/truetype/tlwg/Garuda.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansThai-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansThai-Regular.ttf",
+];
+
+/// Block size in points, matching the SVG and HTML stamps
+const BLOCK_WIDTH: f32 = 240.0;
+const SIGNATURE_HEIGHT: f32 = 80.0;
+const LINE_HEIGHT: f32 = 18.0;
+const BLOCK_MARGIN: f32 = 24.0;
+const FONT_SIZE: f32 = 12.0;
+
+/// Resource names; unusual enough not to clash with the page's own
+const IMAGE_NAME: &str = "PqsSignature";
+const FONT_NAME: &str = "PqsSignatureFont";
+
+/// A4 portrait, for pages that carry no MediaBox anywhere
+const DEFAULT_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 595.0, 842.0];
+
+/// A TrueType font read from disk
+pub struct TitleFont {
+    pub path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl TitleFont {
+    pub(crate) fn face(&self) -> Result<ttf_parser::Face<'_>, String> {
+        ttf_parser::Face::parse(&self.data, 0)
+            .map_err(|e| format!("Failed to read font {}: {}", self.path.display(), e))
+    }
+}
+
+/// First of `candidates` that exists and has a glyph for every character of
+/// `lines`
+pub fn find_title_font(candidates: &[&str], lines: &[String]) -> Option<TitleFont> {
+    candidates.iter().find_map(|candidate| {
+        let path = PathBuf::from(candidate);
+        let data = fs::read(&path).ok()?;
+        let face = ttf_parser::Face::parse(&data, 0).ok()?;
+        let covered = lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .all(|c| face.glyph_index(c).is_some());
+        covered.then_some(TitleFont { path, data })
+    })
+}
+
+pub(crate) fn pdf_error(e: lopdf::Error) -> String {
+    format!("Failed to process PDF: {}", e)
+}
+
+/// Value of `key` on the page or inherited from its page tree parents
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    loop {
+        if let Ok(value) = node.get(key) {
+            return match value {
+                Object::Reference(id) => doc.get_object(*id).ok(),
+                value => Some(value),
+            };
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+}
+
+fn media_box(doc: &Document, page_id: ObjectId) -> [f32; 4] {
+    let values: Option<Vec<f32>> = inherited(doc, page_id, b"MediaBox")
+        .and_then(|value| value.as_array().ok())
+        .map(|array| array.iter().filter_map(|v| v.as_float().ok()).collect());
+    match values.as_deref() {
+        Some([x0, y0, x1, y1]) => [*x0, *y0, *x1, *y1],
+        _ => DEFAULT_MEDIA_BOX,
+    }
+}
+
+/// `object` itself, or the dictionary it refers to, as an owned copy
+fn resolved_dictionary(doc: &Document, object: Option<&Object>) -> Dictionary {
+    match object {
+        Some(Object::Dictionary(dict)) => dict.clone(),
+        Some(Object::Reference(id)) => doc.get_dictionary(*id).cloned().unwrap_or_default(),
+        _ => Dictionary::new(),
+    }
+}
+
+/// Image XObject (plus soft mask for transparency) for a PNG or JPEG
+pub(crate) fn add_image(
+    doc: &mut Document,
+    image_data: &[u8],
+) -> Result<(ObjectId, f32, f32), String> {
+    let image = image::load_from_memory(image_data)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let (width, height) = (image.width(), image.height());
+    let rgba = image.to_rgba8();
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    let mut alpha = Vec::with_capacity((width * height) as usize);
+    for pixel in rgba.pixels() {
+        rgb.extend_from_slice(&pixel.0[..3]);
+        alpha.push(pixel.0[3]);
+    }
+
+    let mut image_dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width as i64,
+        "Height" => height as i64,
+        "ColorSpace" => "DeviceRGB",
+        "BitsPerComponent" => 8,
+    };
+    if alpha.iter().any(|&a| a != u8::MAX) {
+        let mut mask = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width as i64,
+                "Height" => height as i64,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            alpha,
+        );
+        mask.compress().map_err(pdf_error)?;
+        image_dict.set("SMask", doc.add_object(mask));
+    }
+    let mut stream = Stream::new(image_dict, rgb);
+    stream.compress().map_err(pdf_error)?;
+    Ok((doc.add_object(stream), width as f32, height as f32))
+}
+
+/// Type0 font embedding `font` whole, addressed by glyph id (Identity-H)
+pub(crate) fn add_font(
+    doc: &mut Document,
+    font: &TitleFont,
+    lines: &[String],
+) -> Result<ObjectId, String> {
+    let face = font.face()?;
+    let scale = 1000.0 / f32::from(face.units_per_em());
+    let base_font = font
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(' ', ""))
+        .unwrap_or_else(|| "TitleFont".to_string());
+
+    // Widths of the glyphs used, so the viewer spaces them like the font does
+    let mut glyphs: Vec<u16> = lines
+        .iter()
+        .flat_map(|line| line.chars())
+        .filter_map(|c| face.glyph_index(c).map(|glyph| glyph.0))
+        .collect();
+    glyphs.sort_unstable();
+    glyphs.dedup();
+    let mut widths = Vec::new();
+    for glyph in glyphs {
+        let advance = face
+            .glyph_hor_advance(ttf_parser::GlyphId(glyph))
+            .unwrap_or(0);
+        widths.push(Object::Integer(i64::from(glyph)));
+        widths.push(Object::Array(vec![Object::Real(
+            f32::from(advance) * scale,
+        )]));
+    }
+
+    let bbox = face.global_bounding_box();
+    let mut font_file = Stream::new(
+        dictionary! { "Length1" => font.data.len() as i64 },
+        font.data.clone(),
+    );
+    font_file.compress().map_err(pdf_error)?;
+    let font_file_id = doc.add_object(font_file);
+
+    let descriptor_id = doc.add_object(dictionary! {
+        "Type" => "FontDescriptor",
+        "FontName" => Object::Name(base_font.clone().into_bytes()),
+        "Flags" => 32,
+        "FontBBox" => vec![
+            Object::Real(f32::from(bbox.x_min) * scale),
+            Object::Real(f32::from(bbox.y_min) * scale),
+            Object::Real(f32::from(bbox.x_max) * scale),
+            Object::Real(f32::from(bbox.y_max) * scale),
+        ],
+        "ItalicAngle" => 0,
+        "Ascent" => Object::Real(f32::from(face.ascender()) * scale),
+        "Descent" => Object::Real(f32::from(face.descender()) * scale),
+        "CapHeight" => Object::Real(f32::from(face.capital_height().unwrap_or(face.ascender())) * scale),
+        "StemV" => 80,
+        "FontFile2" => font_file_id,
+    });
+    let cid_font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "CIDFontType2",
+        "BaseFont" => Object::Name(base_font.clone().into_bytes()),
+        "CIDSystemInfo" => dictionary! {
+            "Registry" => Object::string_literal("Adobe"),
+            "Ordering" => Object::string_literal("Identity"),
+            "Supplement" => 0,
+        },
+        "FontDescriptor" => descriptor_id,
+        "CIDToGIDMap" => "Identity",
+        "W" => widths,
+    });
+    Ok(doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type0",
+        "BaseFont" => Object::Name(base_font.into_bytes()),
+        "Encoding" => "Identity-H",
+        "DescendantFonts" => vec![Object::Reference(cid_font_id)],
+    }))
+}
+
+/// (glyph ids as a hex string, width in points at `font_size`) of `line`
+pub(crate) fn encode_line(face: &ttf_parser::Face, line: &str, font_size: f32) -> (String, f32) {
+    let scale = font_size / f32::from(face.units_per_em());
+    let mut hex = String::new();
+    let mut width = 0.0;
+    for c in line.chars() {
+        let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+        hex.push_str(&format!("{:04X}", glyph.0));
+        width += f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale;
+    }
+    (hex, width)
+}
+
+/// Content stream drawing the block into `media_box`
+fn block_content(media_box: [f32; 4], image_size: (f32, f32), lines: &[(String, f32)]) -> String {
+    let [_, y0, x1, _] = media_box;
+    let left = x1 - BLOCK_MARGIN - BLOCK_WIDTH;
+    let center = left + BLOCK_WIDTH / 2.0;
+    let lines_bottom = y0 + BLOCK_MARGIN;
+
+    let mut content = String::from("Q\n");
+    // Title lines from the last one up, the image above them
+    for (index, (hex, width)) in lines.iter().rev().enumerate() {
+        content.push_str(&format!(
+            "BT /{} {} Tf 0.12 0.16 0.22 rg {:.2} {:.2} Td <{}> Tj ET\n",
+            FONT_NAME,
+            FONT_SIZE,
+            center - width / 2.0,
+            lines_bottom + LINE_HEIGHT * index as f32,
+            hex
+        ));
+    }
+
+    // Fit the image into the signature area, keeping its aspect ratio
+    let (image_width, image_height) = image_size;
+    let fit = (BLOCK_WIDTH / image_width).min(SIGNATURE_HEIGHT / image_height);
+    let (width, height) = (image_width * fit, image_height * fit);
+    content.push_str(&format!(
+        "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /{} Do Q\n",
+        width,
+        height,
+        center - width / 2.0,
+        lines_bottom + LINE_HEIGHT * lines.len() as f32,
+        IMAGE_NAME
+    ));
+    content
+}
+
+/// Write `document` with the signature block on its last page to `output`
+pub fn stamp_pdf(
+    document: &Path,
+    output: &Path,
+    image_data: &[u8],
+    title_lines: &[String],
+) -> Result<(), String> {
+    let font = if title_lines.is_empty() {
+        None
+    } else {
+        Some(find_title_font(TITLE_FONT_CANDIDATES, title_lines).ok_or(
+            "No installed font can print the title block; install Tahoma or a Thai font such as Noto Sans Thai",
+        )?)
+    };
+    stamp_pdf_with_font(document, output, image_data, title_lines, font.as_ref())
+}
+
+pub fn stamp_pdf_with_font(
+    document: &Path,
+    output: &Path,
+    image_data: &[u8],
+    title_lines: &[String],
+    font: Option<&TitleFont>,
+) -> Result<(), String> {
+    let mut doc = Document::load(document).map_err(pdf_error)?;
+    if doc.is_encrypted() {
+        return Err("Encrypted PDF files cannot be stamped".to_string());
+    }
+    let page_id = *doc.get_pages().values().last().ok_or("PDF has no pages")?;
+    let media_box = media_box(&doc, page_id);
+
+    let (image_id, image_width, image_height) = add_image(&mut doc, image_data)?;
+    let mut resources = resolved_dictionary(&doc, inherited(&doc, page_id, b"Resources"));
+    let mut xobjects = resolved_dictionary(&doc, resources.get(b"XObject").ok());
+    xobjects.set(IMAGE_NAME, image_id);
+    resources.set("XObject", xobjects);
+
+    let mut encoded = Vec::new();
+    if let Some(font) = font {
+        let font_id = add_font(&mut doc, font, title_lines)?;
+        let mut fonts = resolved_dictionary(&doc, resources.get(b"Font").ok());
+        fonts.set(FONT_NAME, font_id);
+        resources.set("Font", fonts);
+        let face = font.face()?;
+        encoded = title_lines
+            .iter()
+            .map(|line| encode_line(&face, line, FONT_SIZE))
+            .collect();
+    }
+
+    let existing: Vec<Object> = match doc
+        .get_dictionary(page_id)
+        .map_err(pdf_error)?
+        .get(b"Contents")
+    {
+        Ok(Object::Array(contents)) => contents.clone(),
+        Ok(contents) => vec![contents.clone()],
+        Err(_) => Vec::new(),
+    };
+    let open = doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    let block = block_content(media_box, (image_width, image_height), &encoded);
+    let close = doc.add_object(Stream::new(Dictionary::new(), block.into_bytes()));
+    let mut contents = vec![Object::Reference(open)];
+    contents.extend(existing);
+    contents.push(Object::Reference(close));
+
+    let page = doc
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(pdf_error)?;
+    page.set("Resources", resources);
+    page.set("Contents", contents);
+
+    doc.compress();
+    doc.save(output)
+        .map_err(|e| format!("Failed to write signed PDF: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_pdf(path: &Path) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(
+            Dictionary::new(),
+            b"0 0 1 rg 10 10 50 50 re f".to_vec(),
+        ));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![Object::Reference(page_id)],
+                "Count" => 1,
+                "MediaBox" => vec![
+                    Object::Integer(0),
+                    Object::Integer(0),
+                    Object::Integer(612),
+                    Object::Integer(792),
+                ],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path).unwrap();
+    }
+
+    fn transparent_png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(40, 20)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_stamp_pdf_adds_image_to_last_page() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let source = dir.path().join("report.pdf");
+        let output = dir.path().join("report_signed.pdf");
+        write_pdf(&source);
+
+        stamp_pdf_with_font(&source, &output, &transparent_png(), &[], None)
+            .expect("PDF should be stamped");
+
+        let doc = Document::load(&output).expect("signed PDF should load");
+        let page_id = *doc.get_pages().values().last().unwrap();
+        assert_eq!(media_box(&doc, page_id), [0.0, 0.0, 612.0, 792.0]);
+        let content = String::from_utf8(doc.get_page_content(page_id).unwrap()).unwrap();
+        assert!(content.starts_with("q\n"));
+        assert!(content.contains("50 50 re f"));
+        assert!(content.contains(&format!("/{} Do", IMAGE_NAME)));
+
+        let resources = resolved_dictionary(&doc, inherited(&doc, page_id, b"Resources"));
+        let xobjects = resolved_dictionary(&doc, resources.get(b"XObject").ok());
+        let image_id = xobjects
+            .get(IMAGE_NAME.as_bytes())
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert!(image.dict.get(b"SMask").is_ok());
+    }
+
+    #[test]
+    fn test_block_sits_bottom_right_and_centers_lines() {
+        let content = block_content(
+            [0.0, 0.0, 612.0, 792.0],
+            (400.0, 100.0),
+            &[("0001".to_string(), 40.0)],
+        );
+        // Centered under a 240pt block that ends 24pt from the right edge
+        let center = 612.0 - 24.0 - 120.0;
+        assert!(content.contains(&format!("{:.2} {:.2} Td <0001> Tj", center - 20.0, 24.0)));
+        // 400x100 scaled to fit 240x80 keeps its 4:1 ratio
+        assert!(content.contains("240.00 0 0 60.00"));
+        assert!(find_title_font(&["/nonexistent/font.ttf"], &["ก".to_string()]).is_none());
+    }
+}
@@ -0,0 +1,605 @@
+//! Import of `.sql` dumps with statement filtering
+//!
+//! A dump is split into statements and each one is checked before anything
+//! runs: only `CREATE TABLE` and `INSERT ... VALUES` for tables this app
+//! knows are allowed. Everything else (DROP, UPDATE, ATTACH, PRAGMA, triggers,
+//! INSERT ... SELECT, other tables) is either rejected up front or skipped and
+//! listed in the report, depending on `skip_disallowed`.
+//!
+//! Statements run against a fresh database next to the live one, which is
+//! brought up to the current schema and only then swapped in, keeping the
+//! replaced file as `.backup` like the SQLite restore does.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::backup_results::{BackupKind, BackupRestored};
+use crate::database::apply_schema;
+use crate::logger;
+use crate::storage_paths;
+use crate::validation;
+
+/// Length of the statement excerpt shown for skipped statements
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SkippedStatement {
+    /// Line in the dump where the statement starts
+    pub line: usize,
+    pub statement: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SqlImportReport {
+    pub source: String,
+    pub statements: usize,
+    pub executed: usize,
+    pub tables_created: usize,
+    pub rows_inserted: usize,
+    pub skipped: Vec<SkippedStatement>,
+    pub restored: BackupRestored,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlStatement {
+    pub line: usize,
+    pub sql: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AllowedStatement {
+    CreateTable,
+    Insert,
+}
+
+/// Split a dump into statements, dropping comments
+///
+/// Statements end at `;`, or at a blank line outside parentheses: dumps
+/// written before the schema lines got a `;` rely on that.
+pub fn split_statements(sql: &str) -> Vec<SqlStatement> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut start_line = 0;
+    let mut line = 1;
+    let mut line_blank = true;
+    let mut depth = 0usize;
+    let mut chars = sql.chars().peekable();
+
+    let mut flush = |current: &mut String, start_line: usize| {
+        let statement = current.trim();
+        if !statement.is_empty() {
+            statements.push(SqlStatement {
+                line: start_line,
+                sql: statement.to_string(),
+            });
+        }
+        current.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                line_blank = false;
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                line_blank = false;
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            '\'' | '"' | '`' | '[' => {
+                if current.trim().is_empty() {
+                    start_line = line;
+                }
+                line_blank = false;
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                while let Some(next) = chars.next() {
+                    current.push(next);
+                    if next == '\n' {
+                        line += 1;
+                    }
+                    if next == close {
+                        // Doubled quote is an escaped quote
+                        if close != ']' && chars.peek() == Some(&close) {
+                            current.push(chars.next().unwrap_or(close));
+                            continue;
+                        }
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                flush(&mut current, start_line);
+                depth = 0;
+                line_blank = false;
+            }
+            '\n' => {
+                if line_blank && depth == 0 {
+                    flush(&mut current, start_line);
+                } else {
+                    current.push(c);
+                }
+                line += 1;
+                line_blank = true;
+            }
+            c if c.is_whitespace() => current.push(c),
+            c => {
+                if current.trim().is_empty() {
+                    start_line = line;
+                }
+                line_blank = false;
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                current.push(c);
+            }
+        }
+    }
+    flush(&mut current, start_line);
+
+    statements
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keyword or identifier, lowercased and unquoted
+    Word(String),
+    Literal,
+    Punct(char),
+}
+
+fn tokenize(sql: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '\'' => {
+                while let Some(next) = chars.next() {
+                    if next == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                            continue;
+                        }
+                        break;
+                    }
+                }
+                tokens.push(Token::Literal);
+            }
+            '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                let mut word = String::new();
+                for next in chars.by_ref() {
+                    if next == close {
+                        break;
+                    }
+                    word.push(next);
+                }
+                tokens.push(Token::Word(word.to_lowercase()));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    // Keeps decimals like 1.5 whole; `main.users` splits at the dot
+                    let decimal = next == '.' && word.chars().all(|w| w.is_ascii_digit());
+                    if !(next.is_alphanumeric() || next == '_' || decimal) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word.to_lowercase()));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+fn is_word(token: Option<&Token>, expected: &str) -> bool {
+    matches!(token, Some(Token::Word(word)) if word == expected)
+}
+
+/// Table named at `tokens[index]`, refusing schema-qualified names
+fn table_at(tokens: &[Token], index: usize, known: &HashSet<String>) -> Result<(), String> {
+    let Some(Token::Word(table)) = tokens.get(index) else {
+        return Err("Missing table name".to_string());
+    };
+    if tokens.get(index + 1) == Some(&Token::Punct('.')) {
+        return Err("Schema-qualified table names are not allowed".to_string());
+    }
+    if !known.contains(table) {
+        return Err(format!("Unknown table: {}", table));
+    }
+    Ok(())
+}
+
+fn classify(sql: &str, known: &HashSet<String>) -> Result<AllowedStatement, String> {
+    let tokens = tokenize(sql);
+    let first = match tokens.first() {
+        Some(Token::Word(word)) => word.clone(),
+        _ => return Err("Not an SQL statement".to_string()),
+    };
+
+    match first.as_str() {
+        "create" => {
+            if !is_word(tokens.get(1), "table") {
+                return Err("Only CREATE TABLE is allowed".to_string());
+            }
+            let mut index = 2;
+            if is_word(tokens.get(2), "if")
+                && is_word(tokens.get(3), "not")
+                && is_word(tokens.get(4), "exists")
+            {
+                index = 5;
+            }
+            table_at(&tokens, index, known)?;
+            if tokens.get(index + 1) != Some(&Token::Punct('(')) {
+                return Err("CREATE TABLE must list its columns".to_string());
+            }
+            Ok(AllowedStatement::CreateTable)
+        }
+        "insert" => {
+            let mut index = 1;
+            if is_word(tokens.get(1), "or") {
+                index = 3;
+            }
+            if !is_word(tokens.get(index), "into") {
+                return Err("Malformed INSERT".to_string());
+            }
+            index += 1;
+            table_at(&tokens, index, known)?;
+            index += 1;
+            if tokens.get(index) == Some(&Token::Punct('(')) {
+                while index < tokens.len() && tokens[index] != Token::Punct(')') {
+                    index += 1;
+                }
+                index += 1;
+            }
+            if !is_word(tokens.get(index), "values") {
+                return Err("Only INSERT ... VALUES is allowed".to_string());
+            }
+            if tokens[index..]
+                .iter()
+                .any(|t| matches!(t, Token::Word(word) if word == "select" || word == "returning"))
+            {
+                return Err("Subqueries are not allowed in INSERT".to_string());
+            }
+            Ok(AllowedStatement::Insert)
+        }
+        other => Err(format!(
+            "{} statements are not allowed",
+            other.to_uppercase()
+        )),
+    }
+}
+
+/// Tables created by the current schema
+pub fn known_tables() -> Result<HashSet<String>, String> {
+    let conn = Connection::open_in_memory()
+        .map_err(|e| format!("Failed to open in-memory database: {}", e))?;
+    apply_schema(&conn)?;
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%'")
+        .map_err(|e| format!("Failed to prepare table list query: {}", e))?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to query table list: {}", e))?
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| format!("Failed to get table name: {}", e))?;
+    Ok(tables.into_iter().map(|t| t.to_lowercase()).collect())
+}
+
+fn preview(sql: &str) -> String {
+    let flat = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= PREVIEW_CHARS {
+        return flat;
+    }
+    let cut: String = flat.chars().take(PREVIEW_CHARS).collect();
+    format!("{}…", cut)
+}
+
+pub struct ImportOutcome {
+    pub statements: usize,
+    pub executed: usize,
+    pub tables_created: usize,
+    pub rows_inserted: usize,
+    pub skipped: Vec<SkippedStatement>,
+}
+
+/// Run the allowed statements of `sql` against `conn` in one transaction
+///
+/// With `skip_disallowed` unset, any disallowed statement or failing
+/// statement aborts the import before the transaction commits.
+pub fn import_statements_with_conn(
+    conn: &mut Connection,
+    sql: &str,
+    known: &HashSet<String>,
+    skip_disallowed: bool,
+) -> Result<ImportOutcome, String> {
+    let statements = split_statements(sql);
+    let mut skipped = Vec::new();
+    let mut allowed = Vec::new();
+    for statement in &statements {
+        match classify(&statement.sql, known) {
+            Ok(kind) => allowed.push((statement, kind)),
+            Err(reason) => skipped.push(SkippedStatement {
+                line: statement.line,
+                statement: preview(&statement.sql),
+                reason,
+            }),
+        }
+    }
+
+    if !skip_disallowed {
+        if let Some(first) = skipped.first() {
+            return Err(format!(
+                "SQL dump contains {} disallowed statement(s); line {}: {} ({})",
+                skipped.len(),
+                first.line,
+                first.reason,
+                first.statement
+            ));
+        }
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let mut executed = 0;
+    let mut tables_created = 0;
+    let mut rows_inserted = 0;
+    for (statement, kind) in allowed {
+        match tx.execute(&statement.sql, []) {
+            Ok(changes) => {
+                executed += 1;
+                match kind {
+                    AllowedStatement::CreateTable => tables_created += 1,
+                    AllowedStatement::Insert => rows_inserted += changes,
+                }
+            }
+            Err(e) if skip_disallowed => skipped.push(SkippedStatement {
+                line: statement.line,
+                statement: preview(&statement.sql),
+                reason: format!("Failed: {}", e),
+            }),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to import statement at line {}: {}",
+                    statement.line, e
+                ))
+            }
+        }
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to commit import: {}", e))?;
+
+    skipped.sort_by_key(|s| s.line);
+    Ok(ImportOutcome {
+        statements: statements.len(),
+        executed,
+        tables_created,
+        rows_inserted,
+        skipped,
+    })
+}
+
+fn build_staging_database(
+    staging_path: &Path,
+    sql: &str,
+    known: &HashSet<String>,
+    skip_disallowed: bool,
+) -> Result<ImportOutcome, String> {
+    let mut conn = Connection::open(staging_path)
+        .map_err(|e| format!("Failed to create import database: {}", e))?;
+    let outcome = import_statements_with_conn(&mut conn, sql, known, skip_disallowed)?;
+    // Older dumps lack newer columns and tables; triggers come last so
+    // imported rows are not recorded as changes
+    apply_schema(&conn)?;
+    Ok(outcome)
+}
+
+/// Import the dump at `source` into a new database and swap it in for `db_path`
+pub fn import_sql_dump_at(
+    source: &Path,
+    db_path: &Path,
+    skip_disallowed: bool,
+) -> Result<SqlImportReport, String> {
+    let sql = fs::read_to_string(source).map_err(|e| format!("Failed to read SQL dump: {}", e))?;
+    let known = known_tables()?;
+
+    let staging_path = db_path.with_extension("sql-import");
+    if staging_path.exists() {
+        fs::remove_file(&staging_path)
+            .map_err(|e| format!("Failed to remove old import database: {}", e))?;
+    }
+
+    let outcome = match build_staging_database(&staging_path, &sql, &known, skip_disallowed) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            let _ = fs::remove_file(&staging_path);
+            return Err(e);
+        }
+    };
+
+    let mut previous_database = None;
+    if db_path.exists() && fs::metadata(db_path).map(|m| m.len() > 0).unwrap_or(false) {
+        let current_backup_path = db_path.with_extension("backup");
+        fs::copy(db_path, &current_backup_path)
+            .map_err(|e| format!("Failed to backup current database: {}", e))?;
+        previous_database = Some(current_backup_path.to_string_lossy().to_string());
+    }
+    fs::copy(&staging_path, db_path).map_err(|e| format!("Failed to restore database: {}", e))?;
+    let _ = fs::remove_file(&staging_path);
+
+    let source_name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let conn = Connection::open(db_path)
+        .map_err(|e| format!("Failed to open restored database: {}", e))?;
+    let mut restored = BackupRestored::from_database(BackupKind::SqlDump, &source_name, &conn)?;
+    restored.previous_database = previous_database;
+    restored.warnings = outcome
+        .skipped
+        .iter()
+        .map(|s| format!("Line {}: {}", s.line, s.reason))
+        .collect();
+
+    logger::info(format!(
+        "SQL dump imported: {} ({} statements run, {} skipped)",
+        source_name,
+        outcome.executed,
+        outcome.skipped.len()
+    ));
+
+    Ok(SqlImportReport {
+        source: source.to_string_lossy().to_string(),
+        statements: outcome.statements,
+        executed: outcome.executed,
+        tables_created: outcome.tables_created,
+        rows_inserted: outcome.rows_inserted,
+        skipped: outcome.skipped,
+        restored,
+    })
+}
+
+/// Import an external `.sql` file chosen by the user
+pub fn import_sql_dump(path: &str, skip_disallowed: bool) -> Result<SqlImportReport, String> {
+    let source = validation::absolute_path("path", path)?;
+    if !source.is_file() {
+        return Err(format!("SQL dump not found: {}", path));
+    }
+    import_sql_dump_at(
+        &source,
+        &storage_paths::get_database_path()?,
+        skip_disallowed,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn known() -> HashSet<String> {
+        known_tables().expect("known tables should load")
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let sql = "-- header; not a statement\n\
+                   CREATE TABLE users (id INTEGER,\n\n name TEXT)\n\
+                   \n\
+                   INSERT INTO users VALUES (1, 'a;b\n\nc'); /* note */ INSERT INTO users VALUES (2, 'it''s');";
+        let statements = split_statements(sql);
+        let lines: Vec<usize> = statements.iter().map(|s| s.line).collect();
+        assert_eq!(lines, vec![2, 6, 8]);
+        assert!(statements[0].sql.ends_with("name TEXT)"));
+        assert!(statements[1].sql.contains("'a;b\n\nc'"));
+        assert!(statements[2].sql.contains("'it''s'"));
+    }
+
+    #[test]
+    fn test_classify_allows_only_create_and_insert() {
+        let known = known();
+        for sql in [
+            "CREATE TABLE users (id INTEGER)",
+            "CREATE TABLE IF NOT EXISTS \"users\" (id INTEGER)",
+            "INSERT INTO users VALUES (1, 'select')",
+            "INSERT OR REPLACE INTO [high_ranking_officers] (id, thai_name) VALUES (1, 'x')",
+        ] {
+            assert!(classify(sql, &known).is_ok(), "{} should be allowed", sql);
+        }
+        for sql in [
+            "DROP TABLE users",
+            "UPDATE users SET role = 'admin'",
+            "ATTACH DATABASE 'x.db' AS x",
+            "PRAGMA writable_schema = 1",
+            "INSERT INTO secrets VALUES (1)",
+            "INSERT INTO users SELECT * FROM users",
+            "INSERT INTO users VALUES ((SELECT 1))",
+            "INSERT INTO main.users VALUES (1)",
+            "CREATE TABLE users AS SELECT 1",
+            "CREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM users",
+        ] {
+            assert!(classify(sql, &known).is_err(), "{} should be refused", sql);
+        }
+    }
+
+    #[test]
+    fn test_import_skips_or_rejects_disallowed() {
+        let sql = "CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT, email TEXT, password_hash TEXT, full_name TEXT);\n\
+                   INSERT INTO users VALUES (1, 'u', 'u@test.com', 'h', 'U');\n\
+                   DROP TABLE users;\n";
+        let known = known();
+
+        let mut conn = Connection::open_in_memory().expect("In-memory db should open");
+        let error = import_statements_with_conn(&mut conn, sql, &known, false)
+            .err()
+            .expect("strict import should be refused");
+        assert!(error.contains("line 3"));
+        let tables: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(tables, 0);
+
+        let outcome = import_statements_with_conn(&mut conn, sql, &known, true)
+            .expect("lenient import should succeed");
+        assert_eq!((outcome.statements, outcome.executed), (3, 2));
+        assert_eq!((outcome.tables_created, outcome.rows_inserted), (1, 1));
+        assert_eq!(outcome.skipped.len(), 1);
+        assert_eq!(outcome.skipped[0].line, 3);
+    }
+
+    #[test]
+    fn test_import_dump_replaces_database() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let db_path = dir.path().join("database.db");
+        let live = Connection::open(&db_path).expect("db should open");
+        apply_schema(&live).expect("schema should apply");
+        drop(live);
+
+        let dump = dir.path().join("dump.sql");
+        fs::write(
+            &dump,
+            "-- Table: users\n\
+             CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT UNIQUE NOT NULL, email TEXT UNIQUE NOT NULL, password_hash TEXT NOT NULL, full_name TEXT NOT NULL, rank TEXT, role TEXT NOT NULL DEFAULT 'visitor', is_active BOOLEAN NOT NULL DEFAULT 1, avatar_path TEXT, avatar_updated_at DATETIME, avatar_mime TEXT, avatar_size INTEGER, created_at DATETIME, updated_at DATETIME)\n\
+             \n\
+             INSERT INTO users VALUES (1, 'u', 'u@test.com', 'h', 'U', NULL, 'admin', 1, NULL, NULL, NULL, NULL, NULL, NULL);\n",
+        )
+        .unwrap();
+
+        let report = import_sql_dump_at(&dump, &db_path, false).expect("import should succeed");
+        assert_eq!(report.restored.user_count, 1);
+        assert!(report.restored.previous_database.is_some());
+        assert!(!db_path.with_extension("sql-import").exists());
+
+        // Columns added since the dump was written are present again
+        let conn = Connection::open(&db_path).unwrap();
+        let service_number: Option<String> = conn
+            .query_row("SELECT service_number FROM users WHERE id = 1", [], |r| {
+                r.get(0)
+            })
+            .expect("migrated column should exist");
+        assert_eq!(service_number, None);
+    }
+}
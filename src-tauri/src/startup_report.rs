@@ -0,0 +1,163 @@
+//! One status report of what happened during startup
+//!
+//! `setup` fills a `StartupReport` as it goes (database check and schema
+//! migration, media directory, leftovers of earlier runs removed, avatar
+//! deletions retried, legacy data) and `publish` sends it once as `app://startup-report`. The report
+//! is also kept for `get_startup_report`, since the page may start listening
+//! only after the event went out. Steps that failed add a line to
+//! `warnings` instead of being left for the UI to guess from later errors.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+use crate::database::{self, get_connection_safe};
+use crate::{legacy_migration, logger, storage_paths};
+
+pub const STARTUP_REPORT_EVENT: &str = "app://startup-report";
+
+/// A backup is due when the newest one is older than this
+pub const BACKUP_DUE_AFTER_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct StartupReport {
+    /// The main database exists and has its tables
+    pub database_valid: bool,
+    /// PRAGMA user_version before startup; None without a database
+    pub schema_version_before: Option<i32>,
+    /// PRAGMA user_version after the startup migration
+    pub schema_version: Option<i32>,
+    /// The media directory could be opened
+    pub media_ok: bool,
+    pub media_directory: Option<String>,
+    /// Temp directories and abandoned uploads of earlier runs removed again
+    pub leftovers_removed: usize,
+    /// Avatar files whose deletion failed earlier and that are gone now
+    pub pending_deletions_retried: usize,
+    /// Newest file in the backup directory (RFC 3339)
+    pub last_backup_at: Option<String>,
+    pub backup_due: bool,
+    /// Data from the old pqs-rtn-tauri directory was found
+    pub legacy_data_found: bool,
+    /// ... and moved over automatically
+    pub legacy_data_migrated: bool,
+    /// One line per startup step that failed
+    pub warnings: Vec<String>,
+}
+
+impl StartupReport {
+    pub fn migrations_applied(&self) -> bool {
+        self.schema_version_before.is_some() && self.schema_version != self.schema_version_before
+    }
+
+    /// Log `error` from `step` and keep it for the banner
+    pub fn warn(&mut self, step: &str, error: &str) {
+        let line = format!("{}: {}", step, error);
+        logger::warn(&line);
+        self.warnings.push(line);
+    }
+}
+
+lazy_static! {
+    static ref LAST_REPORT: Mutex<Option<StartupReport>> = Mutex::new(None);
+}
+
+/// PRAGMA user_version of the main database; None while there is none
+pub fn read_schema_version() -> Option<i32> {
+    if !database::check_database_exists_and_valid().unwrap_or(false) {
+        return None;
+    }
+    let conn = get_connection_safe().ok()?;
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+        .ok()
+}
+
+/// Legacy data is still there after the startup migration left it alone
+pub fn legacy_data_left() -> bool {
+    !storage_paths::is_portable_mode().unwrap_or(false)
+        && legacy_migration::detect_legacy_data()
+            .map(|detected| detected.found)
+            .unwrap_or(false)
+}
+
+/// No backup at all, or the newest is more than BACKUP_DUE_AFTER_DAYS old
+pub fn is_backup_due(last_backup_at: Option<&str>, now: chrono::DateTime<chrono::Utc>) -> bool {
+    match last_backup_at.and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok()) {
+        Some(at) => now.signed_duration_since(at).num_days() >= BACKUP_DUE_AFTER_DAYS,
+        None => true,
+    }
+}
+
+/// Fill in the backup fields from the backup directory
+pub fn check_backups(report: &mut StartupReport) {
+    match storage_paths::get_backup_dir() {
+        Ok(dir) => {
+            report.last_backup_at = crate::dashboard::find_latest_backup(&dir).map(|(_, at)| at);
+            report.backup_due = is_backup_due(report.last_backup_at.as_deref(), chrono::Utc::now());
+        }
+        Err(e) => report.warn("Backups", &e),
+    }
+}
+
+/// Keep the report and send it to the page
+pub fn publish(app: &AppHandle, report: StartupReport) {
+    logger::info(format!(
+        "Startup report: database valid {}, schema {:?}{}, {} warnings",
+        report.database_valid,
+        report.schema_version,
+        if report.migrations_applied() {
+            " (migrated)"
+        } else {
+            ""
+        },
+        report.warnings.len()
+    ));
+    if let Err(e) = app.emit_all(STARTUP_REPORT_EVENT, &report) {
+        logger::warn(format!("Failed to emit startup report: {}", e));
+    }
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        *last = Some(report);
+    }
+}
+
+/// The report of this run; None while startup is still going
+pub fn get_startup_report() -> Option<StartupReport> {
+    LAST_REPORT.lock().ok().and_then(|last| last.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_backup_due_after_a_week() {
+        let now = chrono::Utc.with_ymd_and_hms(2024, 3, 10, 8, 0, 0).unwrap();
+        assert!(is_backup_due(None, now));
+        assert!(!is_backup_due(Some("2024-03-05T08:00:00+00:00"), now));
+        assert!(is_backup_due(Some("2024-03-03T08:00:00+00:00"), now));
+        assert!(is_backup_due(Some("not a date"), now));
+    }
+
+    #[test]
+    fn test_migrations_applied() {
+        let mut report = StartupReport {
+            schema_version_before: Some(13),
+            schema_version: Some(15),
+            ..StartupReport::default()
+        };
+        assert!(report.migrations_applied());
+        report.schema_version_before = Some(15);
+        assert!(!report.migrations_applied());
+        // A fresh install has nothing to migrate
+        report.schema_version_before = None;
+        assert!(!report.migrations_applied());
+
+        report.warn("Media", "directory missing");
+        assert_eq!(
+            report.warnings,
+            vec!["Media: directory missing".to_string()]
+        );
+    }
+}
@@ -0,0 +1,601 @@
+//! Central resolver for on-disk locations
+//!
+//! Every module asks this module where the database, media, backups and
+//! exports live instead of rebuilding paths from `app_data_dir`. Paths depend
+//! on the active workspace: the "default" workspace is the app root itself
+//! (so existing installs keep their data in place), named workspaces live in
+//! `workspaces/<name>/` with the same layout.
+//!
+//! Exports are the exception: users look for them, so they go to the
+//! directory chosen in settings (Documents by default) instead of app data.
+//! Exports written to the old `<workspace>/exports` folder are moved there
+//! the first time the export directory is resolved.
+//!
+//! The app root is the first usable location of: a user-chosen directory
+//! (`PQS_RTN_DATA_DIR` or `storage_location.txt` next to the executable),
+//! the OS app data directory, and a `data` folder next to the executable.
+//! Locked-down machines where app data is missing or read-only thus still
+//! start; `get_storage_mode` tells which location won and why. Falling back
+//! never hides existing data: a configured directory that is unusable, or a
+//! location that already holds a database but cannot be used, stops startup
+//! with an error, as does an empty location while another one holds data.
+//!
+//! A `portable.txt` marker next to the executable switches to portable mode
+//! for running from a USB drive: the database, media, backups, settings and
+//! exports all live in the `data` folder beside it and nothing is read from
+//! or written to the machine's own directories.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::api::path::{app_data_dir, document_dir};
+use tauri::Config;
+
+use crate::settings;
+use crate::validation;
+
+pub const APP_DIR_NAME: &str = "pqs-rtn-hybrid-storage";
+/// Directory used by installs that predate the hybrid storage layout
+pub const LEGACY_APP_DIR_NAME: &str = "pqs-rtn-tauri";
+pub const DEFAULT_WORKSPACE: &str = "default";
+pub const WORKSPACES_DIR_NAME: &str = "workspaces";
+/// Folder created under Documents when no export directory is configured
+pub const DEFAULT_EXPORTS_DIR_NAME: &str = "PQS-RTN Exports";
+/// Data directory chosen by the user or IT, e.g. on a network share
+pub const DATA_DIR_ENV: &str = "PQS_RTN_DATA_DIR";
+/// Next to the executable; holds the path of a user-chosen data directory
+pub const STORAGE_LOCATION_FILE: &str = "storage_location.txt";
+/// Next to the executable; last resort when app data is unusable
+pub const PORTABLE_DATA_DIR_NAME: &str = "data";
+/// Next to the executable; its presence forces portable mode
+pub const PORTABLE_MARKER_FILE: &str = "portable.txt";
+
+lazy_static! {
+    // Cached active workspace name; None until first read from settings
+    static ref ACTIVE_WORKSPACE: RwLock<Option<String>> = RwLock::new(None);
+    // Replaces the app data location, e.g. with a temp dir in tests
+    static ref APP_ROOT_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+    // Resolved once per run; the probing writes a file
+    static ref RESOLVED_STORAGE: RwLock<Option<StorageInfo>> = RwLock::new(None);
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    /// `PQS_RTN_DATA_DIR` or `storage_location.txt`
+    Custom,
+    AppData,
+    /// `data` folder next to the executable
+    Portable,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StorageInfo {
+    pub mode: StorageMode,
+    /// Portable mode was asked for with the marker file rather than reached
+    /// as a fallback
+    #[serde(default)]
+    pub portable_marker: bool,
+    pub root: String,
+    /// Locations tried before `root`, with the reason each was passed over
+    pub skipped: Vec<String>,
+}
+
+fn executable_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+/// The user-chosen directory, if any
+fn custom_data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let location = fs::read_to_string(executable_dir()?.join(STORAGE_LOCATION_FILE)).ok()?;
+    let location = location.trim();
+    (!location.is_empty()).then(|| PathBuf::from(location))
+}
+
+/// Locations in order of preference; None when one cannot even be named.
+/// With the portable marker only the portable location is allowed: falling
+/// back to this machine's app data would split the data carried around.
+fn storage_candidates(exe_dir: Option<&Path>) -> Vec<(StorageMode, Option<PathBuf>)> {
+    let portable = exe_dir.map(|dir| dir.join(PORTABLE_DATA_DIR_NAME));
+    if exe_dir.is_some_and(is_portable_install) {
+        return vec![(StorageMode::Portable, portable)];
+    }
+    vec![
+        (StorageMode::Custom, custom_data_dir()),
+        (
+            StorageMode::AppData,
+            app_data_dir(&Config::default()).map(|dir| dir.join(APP_DIR_NAME)),
+        ),
+        (StorageMode::Portable, portable),
+    ]
+}
+
+/// `exe_dir` holds the portable marker
+pub fn is_portable_install(exe_dir: &Path) -> bool {
+    exe_dir.join(PORTABLE_MARKER_FILE).is_file()
+}
+
+/// Running with the portable marker, so nothing may be kept outside `data`
+pub fn is_portable_mode() -> Result<bool, String> {
+    if APP_ROOT_OVERRIDE
+        .read()
+        .map_err(|e| format!("Failed to acquire app root lock: {}", e))?
+        .is_some()
+    {
+        return Ok(false);
+    }
+    Ok(get_storage_info()?.portable_marker)
+}
+
+/// Create `dir` and prove a file can be written in it
+fn ensure_writable(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot be created: {}", e))?;
+    let probe = dir.join(".write_test");
+    fs::write(&probe, b"").map_err(|e| format!("is not writable: {}", e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// A database was kept here before
+fn holds_data(dir: &Path) -> bool {
+    dir.join("database.db").is_file()
+}
+
+/// First candidate that is usable, unless using it would silently swap the
+/// data in use for another (empty) location
+fn resolve_storage(candidates: Vec<(StorageMode, Option<PathBuf>)>) -> Result<StorageInfo, String> {
+    let mut skipped = Vec::new();
+    for (index, (mode, dir)) in candidates.iter().enumerate() {
+        let mode = *mode;
+        let Some(dir) = dir else {
+            // No user-chosen directory is the normal case, not worth reporting
+            if mode != StorageMode::Custom {
+                skipped.push(format!("{:?}: location unavailable", mode));
+            }
+            continue;
+        };
+        match ensure_writable(dir) {
+            Ok(()) => {
+                // A chosen directory may start empty; a fallback may not
+                // while the data lives elsewhere
+                let data_elsewhere = candidates[index + 1..]
+                    .iter()
+                    .filter_map(|(_, other)| other.as_deref())
+                    .find(|other| holds_data(other));
+                if let Some(other) =
+                    data_elsewhere.filter(|_| mode != StorageMode::Custom && !holds_data(dir))
+                {
+                    return Err(format!(
+                        "Data directory {} is empty but existing data was found in {}; set {} to the directory to use",
+                        dir.display(),
+                        other.display(),
+                        DATA_DIR_ENV
+                    ));
+                }
+                return Ok(StorageInfo {
+                    mode,
+                    portable_marker: false,
+                    root: dir.to_string_lossy().to_string(),
+                    skipped,
+                });
+            }
+            Err(e) if mode == StorageMode::Custom => {
+                return Err(format!("Configured data directory {} {}", dir.display(), e))
+            }
+            Err(e) if holds_data(dir) => {
+                return Err(format!(
+                    "Data directory {} holds the existing database but {}",
+                    dir.display(),
+                    e
+                ))
+            }
+            Err(e) => skipped.push(format!("{:?}: {} {}", mode, dir.display(), e)),
+        }
+    }
+    Err(format!("No usable data directory: {}", skipped.join("; ")))
+}
+
+/// Where data is kept in this run, resolving it on first use
+pub fn get_storage_info() -> Result<StorageInfo, String> {
+    if let Some(info) = RESOLVED_STORAGE
+        .read()
+        .map_err(|e| format!("Failed to acquire storage lock: {}", e))?
+        .clone()
+    {
+        return Ok(info);
+    }
+
+    let exe_dir = executable_dir();
+    let mut info = resolve_storage(storage_candidates(exe_dir.as_deref()))?;
+    info.portable_marker = exe_dir.as_deref().is_some_and(is_portable_install);
+    if !info.skipped.is_empty() {
+        crate::logger::warn(format!(
+            "Using {:?} data directory {} ({})",
+            info.mode,
+            info.root,
+            info.skipped.join("; ")
+        ));
+    }
+    let mut resolved = RESOLVED_STORAGE
+        .write()
+        .map_err(|e| format!("Failed to acquire storage lock: {}", e))?;
+    Ok(resolved.get_or_insert(info).clone())
+}
+
+/// Save (or with None, remove) the user-chosen data directory next to the
+/// executable. Data is not moved; the new location is used after a restart.
+pub fn set_storage_location(directory: Option<&str>) -> Result<(), String> {
+    let exe_dir = executable_dir().ok_or("Failed to locate the executable")?;
+    if is_portable_install(&exe_dir) {
+        return Err(format!(
+            "Portable mode keeps data in the {} folder; remove {} to choose another location",
+            PORTABLE_DATA_DIR_NAME, PORTABLE_MARKER_FILE
+        ));
+    }
+    let location_file = exe_dir.join(STORAGE_LOCATION_FILE);
+    let directory = directory.map(str::trim).filter(|dir| !dir.is_empty());
+    let result = match directory {
+        Some(dir) => {
+            let path = validation::absolute_path("directory", dir)?;
+            ensure_writable(&path).map_err(|e| format!("{} {}", path.display(), e))?;
+            fs::write(&location_file, path.to_string_lossy().as_bytes())
+        }
+        None if location_file.exists() => fs::remove_file(&location_file),
+        None => Ok(()),
+    };
+    result.map_err(|e| {
+        format!(
+            "Failed to save storage location (set {} instead): {}",
+            DATA_DIR_ENV, e
+        )
+    })
+}
+
+/// Point every path at `root` instead of the app data directory (None restores it)
+#[cfg(test)]
+pub fn set_app_root_override(root: Option<PathBuf>) -> Result<(), String> {
+    let mut current = APP_ROOT_OVERRIDE
+        .write()
+        .map_err(|e| format!("Failed to acquire app root lock: {}", e))?;
+    *current = root;
+    crate::read_cache::invalidate();
+    Ok(())
+}
+
+/// Root directory shared by all workspaces (settings live here)
+pub fn get_app_root() -> Result<PathBuf, String> {
+    let overridden = APP_ROOT_OVERRIDE
+        .read()
+        .map_err(|e| format!("Failed to acquire app root lock: {}", e))?
+        .clone();
+    let root = match overridden {
+        Some(root) => root,
+        None => PathBuf::from(get_storage_info()?.root),
+    };
+    fs::create_dir_all(&root).map_err(|e| format!("Failed to create app directory: {}", e))?;
+    Ok(root)
+}
+
+/// Root of the pre-hybrid installation (not created here)
+pub fn get_legacy_app_root() -> Result<PathBuf, String> {
+    let app_data = app_data_dir(&Config::default()).ok_or("Failed to get app data directory")?;
+    Ok(app_data.join(LEGACY_APP_DIR_NAME))
+}
+
+/// Data directory for a workspace under the given app root
+pub fn workspace_dir_for(app_root: &Path, workspace: &str) -> PathBuf {
+    if workspace == DEFAULT_WORKSPACE {
+        app_root.to_path_buf()
+    } else {
+        app_root.join(WORKSPACES_DIR_NAME).join(workspace)
+    }
+}
+
+pub fn get_active_workspace() -> Result<String, String> {
+    {
+        let cached = ACTIVE_WORKSPACE
+            .read()
+            .map_err(|e| format!("Failed to acquire workspace lock: {}", e))?;
+        if let Some(ref name) = *cached {
+            return Ok(name.clone());
+        }
+    }
+
+    let name = settings::load_settings()?
+        .active_workspace
+        .unwrap_or_else(|| DEFAULT_WORKSPACE.to_string());
+    set_cached_workspace(&name)?;
+    Ok(name)
+}
+
+/// Update the in-memory workspace; persisting it is the caller's job
+pub fn set_cached_workspace(name: &str) -> Result<(), String> {
+    let mut cached = ACTIVE_WORKSPACE
+        .write()
+        .map_err(|e| format!("Failed to acquire workspace lock: {}", e))?;
+    *cached = Some(name.to_string());
+    // Another workspace is another database
+    crate::read_cache::invalidate();
+    crate::record_subscriptions::notify_database_replaced();
+    Ok(())
+}
+
+/// Data directory of the active workspace (not created here)
+pub fn get_workspace_dir() -> Result<PathBuf, String> {
+    Ok(workspace_dir_for(
+        &get_app_root()?,
+        &get_active_workspace()?,
+    ))
+}
+
+/// Main database file; the containing directory is created if needed
+pub fn get_database_path() -> Result<PathBuf, String> {
+    let dir = get_workspace_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create database directory: {}", e))?;
+    Ok(dir.join("database.db"))
+}
+
+/// Media root (avatars, high_ranks); callers decide whether to create it
+pub fn get_media_dir() -> Result<PathBuf, String> {
+    Ok(get_workspace_dir()?.join("media"))
+}
+
+pub fn get_backup_dir() -> Result<PathBuf, String> {
+    let dir = get_workspace_dir()?.join("backups");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Archive database and the media of archived users
+pub fn get_archive_dir() -> Result<PathBuf, String> {
+    let dir = get_workspace_dir()?.join("archive");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create archive directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Exports of named workspaces get their own subfolder so lists don't mix
+pub fn export_dir_for(export_root: &Path, workspace: &str) -> PathBuf {
+    if workspace == DEFAULT_WORKSPACE {
+        export_root.to_path_buf()
+    } else {
+        export_root.join(workspace)
+    }
+}
+
+fn default_export_root() -> Result<Option<PathBuf>, String> {
+    // With the app root overridden (tests) nothing may leak into Documents,
+    // and in portable mode exports travel with the rest of the data
+    let overridden = APP_ROOT_OVERRIDE
+        .read()
+        .map_err(|e| format!("Failed to acquire app root lock: {}", e))?
+        .is_some();
+    if overridden || is_portable_mode()? {
+        return Ok(None);
+    }
+    Ok(document_dir().map(|dir| dir.join(DEFAULT_EXPORTS_DIR_NAME)))
+}
+
+pub fn get_export_dir() -> Result<PathBuf, String> {
+    let configured = settings::load_settings()?
+        .export_directory
+        .filter(|dir| !dir.trim().is_empty())
+        .map(PathBuf::from);
+    let export_root = match configured {
+        Some(dir) => Some(dir),
+        None => default_export_root()?,
+    };
+    let legacy_dir = get_workspace_dir()?.join("exports");
+    let dir = match export_root {
+        Some(root) => export_dir_for(&root, &get_active_workspace()?),
+        // No Documents folder (service accounts): keep exports in the workspace
+        None => legacy_dir.clone(),
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create export directory: {}", e))?;
+    if dir != legacy_dir && legacy_dir.is_dir() {
+        migrate_legacy_exports(&legacy_dir, &dir);
+    }
+    Ok(dir)
+}
+
+/// Move exports from the pre-Documents `legacy_dir` into `dir` so they stay
+/// listed and importable; a name already taken in `dir` is left where it is,
+/// and `legacy_dir` goes away once empty. Returns the number of files moved
+fn migrate_legacy_exports(legacy_dir: &Path, dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(legacy_dir) else {
+        return 0;
+    };
+    let mut moved = 0;
+    for entry in entries.flatten() {
+        let from = entry.path();
+        let to = dir.join(entry.file_name());
+        if !from.is_file() || to.exists() {
+            continue;
+        }
+        // Documents is often on another volume than app data
+        let result = fs::rename(&from, &to).or_else(|_| {
+            fs::copy(&from, &to)?;
+            fs::remove_file(&from)
+        });
+        match result {
+            Ok(()) => moved += 1,
+            Err(e) => crate::logger::warn(format!(
+                "Failed to move export {} to {}: {}",
+                from.display(),
+                dir.display(),
+                e
+            )),
+        }
+    }
+    if moved > 0 {
+        crate::logger::info(format!(
+            "Moved {} export(s) from {} to {}",
+            moved,
+            legacy_dir.display(),
+            dir.display()
+        ));
+    }
+    // Only succeeds when nothing was left behind
+    let _ = fs::remove_dir(legacy_dir);
+    moved
+}
+
+/// Save the preferred export directory (None goes back to Documents) and
+/// return the directory exports will now be written to
+pub fn set_export_directory(directory: Option<&str>) -> Result<PathBuf, String> {
+    let directory = directory.map(str::trim).filter(|dir| !dir.is_empty());
+    if let Some(dir) = directory {
+        let path = validation::absolute_path("directory", dir)?;
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create export directory: {}", e))?;
+    }
+
+    settings::update_settings(|settings| {
+        settings.export_directory = directory.map(str::to_string);
+    })?;
+    get_export_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_workspace_is_app_root() {
+        let root = Path::new("/data/pqs");
+        assert_eq!(workspace_dir_for(root, DEFAULT_WORKSPACE), root);
+    }
+
+    #[test]
+    fn test_export_dir_per_workspace() {
+        let root = Path::new("/docs/exports");
+        assert_eq!(export_dir_for(root, DEFAULT_WORKSPACE), root);
+        assert_eq!(export_dir_for(root, "squadron-1"), root.join("squadron-1"));
+    }
+
+    #[test]
+    fn test_legacy_exports_are_moved() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let legacy = dir.path().join("exports");
+        let target = dir.path().join("PQS-RTN Exports");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(legacy.join("export_1.json"), b"old").unwrap();
+        fs::write(legacy.join("export_2.json"), b"old").unwrap();
+        fs::write(target.join("export_2.json"), b"new").unwrap();
+
+        assert_eq!(migrate_legacy_exports(&legacy, &target), 1);
+        assert_eq!(fs::read(target.join("export_1.json")).unwrap(), b"old");
+        assert_eq!(fs::read(target.join("export_2.json")).unwrap(), b"new");
+        // The conflicting file keeps the old folder alive
+        assert!(legacy.join("export_2.json").exists());
+
+        fs::remove_file(legacy.join("export_2.json")).unwrap();
+        assert_eq!(migrate_legacy_exports(&legacy, &target), 0);
+        assert!(!legacy.exists());
+    }
+
+    #[test]
+    fn test_storage_falls_back_to_first_usable_location() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let blocker = dir.path().join("not-a-dir");
+        fs::write(&blocker, b"x").unwrap();
+        let portable = dir.path().join("usb").join("data");
+
+        let info = resolve_storage(vec![
+            (StorageMode::Custom, None),
+            (StorageMode::AppData, Some(blocker.join("app"))),
+            (StorageMode::Portable, Some(portable.clone())),
+        ])
+        .expect("portable location should be used");
+        assert_eq!(info.mode, StorageMode::Portable);
+        assert_eq!(PathBuf::from(&info.root), portable);
+        assert_eq!(info.skipped.len(), 1);
+        assert!(info.skipped[0].starts_with("AppData"));
+        assert!(!portable.join(".write_test").exists());
+
+        assert!(resolve_storage(vec![(StorageMode::AppData, None)]).is_err());
+    }
+
+    #[test]
+    fn test_storage_never_falls_back_away_from_data() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let blocker = dir.path().join("not-a-dir");
+        fs::write(&blocker, b"x").unwrap();
+        let app_data = dir.path().join("app");
+        let portable = dir.path().join("usb").join("data");
+
+        // A configured directory that cannot be used is an error, not a hint
+        let error = resolve_storage(vec![
+            (StorageMode::Custom, Some(blocker.join("share"))),
+            (StorageMode::AppData, Some(app_data.clone())),
+        ])
+        .expect_err("unusable configured directory should fail");
+        assert!(error.starts_with("Configured data directory"));
+
+        // An empty fallback must not stand in for data kept further down
+        fs::create_dir_all(&portable).unwrap();
+        fs::write(portable.join("database.db"), b"db").unwrap();
+        let error = resolve_storage(vec![
+            (StorageMode::AppData, Some(app_data.clone())),
+            (StorageMode::Portable, Some(portable.clone())),
+        ])
+        .expect_err("empty app data should not hide the portable data");
+        assert!(error.contains(&portable.display().to_string()));
+
+        // Once it holds data itself, app data wins as before
+        fs::write(app_data.join("database.db"), b"db").unwrap();
+        let info = resolve_storage(vec![
+            (StorageMode::AppData, Some(app_data.clone())),
+            (StorageMode::Portable, Some(portable)),
+        ])
+        .expect("app data should be used");
+        assert_eq!(info.mode, StorageMode::AppData);
+
+        // A newly chosen directory may start empty
+        let chosen = dir.path().join("chosen");
+        let info = resolve_storage(vec![
+            (StorageMode::Custom, Some(chosen)),
+            (StorageMode::AppData, Some(app_data)),
+        ])
+        .expect("chosen directory should be used");
+        assert_eq!(info.mode, StorageMode::Custom);
+    }
+
+    #[test]
+    fn test_portable_marker_allows_only_portable_location() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        assert_eq!(storage_candidates(Some(dir.path())).len(), 3);
+
+        fs::write(dir.path().join(PORTABLE_MARKER_FILE), b"").unwrap();
+        assert!(is_portable_install(dir.path()));
+        let candidates = storage_candidates(Some(dir.path()));
+        assert_eq!(
+            candidates,
+            vec![(
+                StorageMode::Portable,
+                Some(dir.path().join(PORTABLE_DATA_DIR_NAME))
+            )]
+        );
+        let info = resolve_storage(candidates).expect("data folder should be created");
+        assert_eq!(
+            PathBuf::from(info.root),
+            dir.path().join(PORTABLE_DATA_DIR_NAME)
+        );
+    }
+
+    #[test]
+    fn test_named_workspace_is_nested() {
+        let root = Path::new("/data/pqs");
+        assert_eq!(
+            workspace_dir_for(root, "squadron-1"),
+            root.join("workspaces").join("squadron-1")
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! Maintenance jobs run while the app is idle
+//!
+//! `TaskScheduler` lives in Tauri's managed state and holds the registered
+//! jobs, each with the interval it should run at. A background thread looks
+//! every minute; once no connection has been opened for the scheduler's idle
+//! time (`db_maintenance::idle_secs`) it runs every job whose interval has
+//! passed, one after the other. Nothing runs in maintenance mode. Admins can
+//! list the jobs with their last result and run one at once from the
+//! diagnostics view; a job is never run twice at the same time.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chunked_upload;
+use crate::content_database::previews;
+use crate::database::get_connection_safe;
+use crate::db_maintenance;
+use crate::hybrid_avatar::HybridAvatarManager;
+use crate::hybrid_high_rank_avatar::HybridHighRankAvatarManager;
+use crate::logger;
+use crate::maintenance_mode;
+use crate::sessions;
+use crate::temp_space;
+
+/// Minutes without database activity before jobs run
+pub const DEFAULT_IDLE_MINUTES: u64 = 10;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// A job's work; Ok holds a one-line summary for the job list
+pub type TaskFn = fn() -> Result<String, String>;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskTrigger {
+    Idle,
+    Manual,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TaskRun {
+    /// RFC 3339
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub trigger: TaskTrigger,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScheduledTaskInfo {
+    pub name: String,
+    pub description: String,
+    pub interval_secs: u64,
+    pub running: bool,
+    /// Would run at the next idle check
+    pub due: bool,
+    pub last_run: Option<TaskRun>,
+}
+
+struct ScheduledTask {
+    name: &'static str,
+    description: &'static str,
+    interval: Duration,
+    run: TaskFn,
+    running: bool,
+    last_started: Option<Instant>,
+    last_run: Option<TaskRun>,
+}
+
+/// Whether a job runs now: the app has been idle long enough and the job's
+/// interval has passed since it last started
+pub fn is_task_due(
+    since_last_run: Option<Duration>,
+    interval: Duration,
+    idle: Duration,
+    idle_after: Duration,
+) -> bool {
+    if idle < idle_after {
+        return false;
+    }
+    match since_last_run {
+        Some(elapsed) => elapsed >= interval,
+        None => true,
+    }
+}
+
+#[derive(Clone)]
+pub struct TaskScheduler {
+    tasks: Arc<Mutex<Vec<ScheduledTask>>>,
+    idle_after: Duration,
+}
+
+impl TaskScheduler {
+    pub fn new(idle_after: Duration) -> Self {
+        Self {
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            idle_after,
+        }
+    }
+
+    /// The scheduler with the app's maintenance jobs
+    pub fn with_default_tasks() -> Self {
+        let scheduler = Self::new(Duration::from_secs(DEFAULT_IDLE_MINUTES * 60));
+        scheduler.register(
+            "orphan_cleanup",
+            "Delete avatar files no user or officer refers to",
+            24 * HOUR,
+            cleanup_orphaned_media,
+        );
+        scheduler.register(
+            "wal_checkpoint",
+            "Write the WAL back into the database file",
+            HOUR,
+            checkpoint_wal,
+        );
+        scheduler.register(
+            "retention",
+            "Remove expired sessions, stale temp directories and abandoned uploads",
+            6 * HOUR,
+            apply_retention,
+        );
+        scheduler.register(
+            "thumbnails",
+            "Generate missing attachment previews",
+            6 * HOUR,
+            generate_missing_previews,
+        );
+        scheduler
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Vec<ScheduledTask>>, String> {
+        self.tasks
+            .lock()
+            .map_err(|_| "Task scheduler is unavailable".to_string())
+    }
+
+    pub fn register(
+        &self,
+        name: &'static str,
+        description: &'static str,
+        interval: Duration,
+        run: TaskFn,
+    ) {
+        if let Ok(mut tasks) = self.lock() {
+            tasks.push(ScheduledTask {
+                name,
+                description,
+                interval,
+                run,
+                running: false,
+                last_started: None,
+                last_run: None,
+            });
+        }
+    }
+
+    pub fn list_tasks(&self, idle: Duration) -> Result<Vec<ScheduledTaskInfo>, String> {
+        let tasks = self.lock()?;
+        Ok(tasks
+            .iter()
+            .map(|task| ScheduledTaskInfo {
+                name: task.name.to_string(),
+                description: task.description.to_string(),
+                interval_secs: task.interval.as_secs(),
+                running: task.running,
+                due: !task.running
+                    && is_task_due(
+                        task.last_started.map(|at| at.elapsed()),
+                        task.interval,
+                        idle,
+                        self.idle_after,
+                    ),
+                last_run: task.last_run.clone(),
+            })
+            .collect())
+    }
+
+    /// Names of the jobs to run after `idle` without activity
+    pub fn due_tasks(&self, idle: Duration) -> Vec<&'static str> {
+        self.lock()
+            .map(|tasks| {
+                tasks
+                    .iter()
+                    .filter(|task| {
+                        !task.running
+                            && is_task_due(
+                                task.last_started.map(|at| at.elapsed()),
+                                task.interval,
+                                idle,
+                                self.idle_after,
+                            )
+                    })
+                    .map(|task| task.name)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Run the job called `name` now; its failure is kept in the returned run
+    pub fn run_task(&self, name: &str, trigger: TaskTrigger) -> Result<TaskRun, String> {
+        let run = {
+            let mut tasks = self.lock()?;
+            let task = tasks
+                .iter_mut()
+                .find(|task| task.name == name)
+                .ok_or_else(|| format!("Unknown scheduled task: {}", name))?;
+            if task.running {
+                return Err(format!("Scheduled task {} is already running", name));
+            }
+            task.running = true;
+            task.last_started = Some(Instant::now());
+            task.run
+        };
+
+        let started_at = chrono::Utc::now().to_rfc3339();
+        let timer = Instant::now();
+        let outcome = run();
+        let record = TaskRun {
+            started_at,
+            duration_ms: timer.elapsed().as_millis() as u64,
+            trigger,
+            summary: outcome.as_ref().ok().cloned(),
+            error: outcome.err(),
+        };
+        match &record.error {
+            None => logger::info(format!(
+                "Scheduled task {} finished in {} ms: {}",
+                name,
+                record.duration_ms,
+                record.summary.as_deref().unwrap_or_default()
+            )),
+            Some(e) => logger::warn(format!("Scheduled task {} failed: {}", name, e)),
+        }
+
+        let mut tasks = self.lock()?;
+        if let Some(task) = tasks.iter_mut().find(|task| task.name == name) {
+            task.running = false;
+            task.last_run = Some(record.clone());
+        }
+        Ok(record)
+    }
+
+    /// Start the idle check on its own thread
+    pub fn start(&self) {
+        let scheduler = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+
+            // The jobs open connections themselves, so take idleness once
+            let idle = Duration::from_secs(db_maintenance::idle_secs());
+            let due = scheduler.due_tasks(idle);
+            if due.is_empty() {
+                continue;
+            }
+            if maintenance_mode::get_maintenance_mode()
+                .map(|mode| mode.enabled)
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            for name in due {
+                if let Err(e) = scheduler.run_task(name, TaskTrigger::Idle) {
+                    logger::warn(format!("Failed to run scheduled task {}: {}", name, e));
+                }
+            }
+        });
+    }
+}
+
+fn cleanup_orphaned_media() -> Result<String, String> {
+    let avatars = HybridAvatarManager::new()?.cleanup_orphaned_files()?;
+    let officers = HybridHighRankAvatarManager::new()?.cleanup_orphaned_files()?;
+    Ok(format!("{} orphaned files deleted", avatars + officers))
+}
+
+fn checkpoint_wal() -> Result<String, String> {
+    let (_, frames, checkpointed) = db_maintenance::checkpoint_wal()?;
+    if frames < 0 {
+        return Ok("Database is not in WAL mode".to_string());
+    }
+    Ok(format!(
+        "{} of {} WAL frames checkpointed",
+        checkpointed, frames
+    ))
+}
+
+fn apply_retention() -> Result<String, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let sessions = sessions::remove_expired_sessions_with_conn(&conn)?;
+    drop(conn);
+    let temp_dirs = temp_space::cleanup_stale_temp_dirs()?;
+    let uploads = chunked_upload::cleanup_stale_uploads()?;
+    Ok(format!(
+        "{} expired sessions, {} temp directories and {} uploads removed",
+        sessions, temp_dirs, uploads
+    ))
+}
+
+fn generate_missing_previews() -> Result<String, String> {
+    let files = previews::files_missing_preview()?;
+    let mut generated = 0;
+    let mut failed = 0;
+    for file in &files {
+        match previews::generate_preview(file) {
+            Ok(Some(_)) => generated += 1,
+            Ok(None) => {}
+            Err(e) => {
+                failed += 1;
+                logger::warn(format!(
+                    "Failed to generate preview for {}: {}",
+                    file.display(),
+                    e
+                ));
+            }
+        }
+    }
+    Ok(format!(
+        "{} previews generated, {} failed",
+        generated, failed
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_due_only_when_idle_and_interval_passed() {
+        let idle_after = Duration::from_secs(600);
+        let interval = HOUR;
+        let idle = Duration::from_secs(601);
+
+        assert!(is_task_due(None, interval, idle, idle_after));
+        assert!(!is_task_due(
+            None,
+            interval,
+            Duration::from_secs(30),
+            idle_after
+        ));
+        assert!(!is_task_due(
+            Some(Duration::from_secs(60)),
+            interval,
+            idle,
+            idle_after
+        ));
+        assert!(is_task_due(Some(interval), interval, idle, idle_after));
+    }
+
+    #[test]
+    fn test_run_task_records_result() {
+        let scheduler = TaskScheduler::new(Duration::from_secs(600));
+        scheduler.register("ok", "Succeeds", HOUR, || Ok("done".to_string()));
+        scheduler.register("broken", "Fails", HOUR, || Err("disk full".to_string()));
+        let idle = Duration::from_secs(3600);
+        assert_eq!(scheduler.due_tasks(idle), vec!["ok", "broken"]);
+        assert!(scheduler.due_tasks(Duration::from_secs(5)).is_empty());
+
+        let run = scheduler.run_task("ok", TaskTrigger::Manual).unwrap();
+        assert_eq!(run.summary.as_deref(), Some("done"));
+        assert_eq!(run.error, None);
+        let failed = scheduler.run_task("broken", TaskTrigger::Idle).unwrap();
+        assert_eq!(failed.error.as_deref(), Some("disk full"));
+        assert!(scheduler.run_task("missing", TaskTrigger::Manual).is_err());
+
+        // Both just ran, so neither is due again within the hour
+        assert!(scheduler.due_tasks(idle).is_empty());
+        let listed = scheduler.list_tasks(idle).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].last_run, Some(run));
+        assert!(!listed[1].due);
+    }
+}
@@ -0,0 +1,259 @@
+//! Scratch directories for backup extraction and other multi-step file work
+//!
+//! Every operation gets its own directory under `<workspace>/temp`, named
+//! after the operation kind, so two imports never share files. A `TempSpace`
+//! removes its directory when dropped, including on early `?` returns;
+//! whatever a crash leaves behind is removed by `cleanup_stale_temp_dirs`
+//! once it is older than `STALE_TEMP_AGE`. Directories of live `TempSpace`s
+//! are registered and never cleaned up, however long the operation runs.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use crate::logger;
+use crate::storage_paths;
+
+pub const TEMP_DIR_NAME: &str = "temp";
+
+/// Old enough that no running operation can still own the directory
+pub const STALE_TEMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Shared extraction directory used before per-operation dirs existed
+const LEGACY_IMPORT_DIR: &str = "temp_import";
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /// Directories owned by a `TempSpace` that has not been dropped yet
+    static ref LIVE_TEMP_DIRS: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+fn is_live(path: &Path) -> bool {
+    LIVE_TEMP_DIRS
+        .lock()
+        .map(|dirs| dirs.contains(path))
+        .unwrap_or(true)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TempSpaceUsage {
+    pub directories: u64,
+    pub bytes: u64,
+}
+
+/// A per-operation directory deleted on drop
+#[derive(Debug)]
+pub struct TempSpace {
+    path: PathBuf,
+}
+
+impl TempSpace {
+    /// New empty directory under `root`; `kind` ("import", ...) only names it
+    pub fn create_in(root: &Path, kind: &str) -> Result<Self, String> {
+        let name = format!(
+            "{}-{}-{}-{}",
+            kind,
+            std::process::id(),
+            chrono::Utc::now().timestamp_millis(),
+            TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let path = root.join(name);
+        fs::create_dir_all(&path).map_err(|e| format!("Failed to create temp directory: {}", e))?;
+        if let Ok(mut dirs) = LIVE_TEMP_DIRS.lock() {
+            dirs.insert(path.clone());
+        }
+        Ok(TempSpace { path })
+    }
+
+    pub fn create(kind: &str) -> Result<Self, String> {
+        Self::create_in(&get_temp_root()?, kind)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempSpace {
+    fn drop(&mut self) {
+        if let Ok(mut dirs) = LIVE_TEMP_DIRS.lock() {
+            dirs.remove(&self.path);
+        }
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                logger::warn(format!(
+                    "Failed to remove temp directory {}: {}",
+                    self.path.display(),
+                    e
+                ));
+            }
+        }
+    }
+}
+
+/// `<workspace>/temp`, next to the data it is restored into so copies stay on one volume
+pub fn get_temp_root() -> Result<PathBuf, String> {
+    let dir = storage_paths::get_workspace_dir()?.join(TEMP_DIR_NAME);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create temp directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Remove entries of `root` last modified more than `max_age` before `now`,
+/// except the directories of live `TempSpace`s
+pub fn cleanup_stale_temp_dirs_in(
+    root: &Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> Result<usize, String> {
+    if !root.exists() {
+        return Ok(0);
+    }
+
+    let entries =
+        fs::read_dir(root).map_err(|e| format!("Failed to read temp directory: {}", e))?;
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(now);
+        if now.duration_since(modified).unwrap_or_default() < max_age {
+            continue;
+        }
+
+        let path = entry.path();
+        if is_live(&path) {
+            continue;
+        }
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => removed += 1,
+            Err(e) => logger::warn(format!(
+                "Failed to remove stale temp entry {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove temp dirs left by crashed or killed operations; safe to run at any time
+pub fn cleanup_stale_temp_dirs() -> Result<usize, String> {
+    let removed = cleanup_stale_temp_dirs_in(&get_temp_root()?, STALE_TEMP_AGE, SystemTime::now())?;
+    if removed > 0 {
+        logger::info(format!("Removed {} stale temp directories", removed));
+    }
+    Ok(removed)
+}
+
+/// Startup cleanup: stale temp dirs plus the legacy `backups/temp_import`,
+/// which only an import of this process could be using
+pub fn cleanup_temp_at_startup() -> Result<usize, String> {
+    let mut removed = cleanup_stale_temp_dirs()?;
+
+    let legacy = storage_paths::get_backup_dir()?.join(LEGACY_IMPORT_DIR);
+    if legacy.exists() {
+        fs::remove_dir_all(&legacy)
+            .map_err(|e| format!("Failed to remove old temp_import directory: {}", e))?;
+        removed += 1;
+        logger::info("Removed old temp_import directory");
+    }
+    Ok(removed)
+}
+
+pub fn temp_space_usage_in(root: &Path) -> TempSpaceUsage {
+    let mut usage = TempSpaceUsage::default();
+    if !root.exists() {
+        return usage;
+    }
+
+    if let Ok(entries) = fs::read_dir(root) {
+        usage.directories = entries.flatten().filter(|e| e.path().is_dir()).count() as u64;
+    }
+    usage.bytes = WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    usage
+}
+
+pub fn temp_space_usage() -> Result<TempSpaceUsage, String> {
+    Ok(temp_space_usage_in(&get_temp_root()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_temp_space_is_unique_and_removed_on_drop() {
+        let root = TempDir::new().expect("temp dir should be created");
+
+        let first = TempSpace::create_in(root.path(), "import").expect("temp space should open");
+        let second = TempSpace::create_in(root.path(), "import").expect("temp space should open");
+        assert_ne!(first.path(), second.path());
+
+        fs::write(first.path().join("database.db"), b"12345").unwrap();
+        let usage = temp_space_usage_in(root.path());
+        assert_eq!(usage.directories, 2);
+        assert_eq!(usage.bytes, 5);
+
+        let first_path = first.path().to_path_buf();
+        drop(first);
+        assert!(!first_path.exists());
+        assert!(second.path().exists());
+    }
+
+    #[test]
+    fn test_cleanup_only_removes_stale_entries() {
+        let root = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(root.path().join("import-1")).unwrap();
+        fs::write(root.path().join("import-1").join("database.db"), b"db").unwrap();
+
+        let now = SystemTime::now();
+        assert_eq!(
+            cleanup_stale_temp_dirs_in(root.path(), STALE_TEMP_AGE, now).unwrap(),
+            0
+        );
+        let later = now + STALE_TEMP_AGE + Duration::from_secs(1);
+        assert_eq!(
+            cleanup_stale_temp_dirs_in(root.path(), STALE_TEMP_AGE, later).unwrap(),
+            1
+        );
+        assert!(!root.path().join("import-1").exists());
+    }
+
+    #[test]
+    fn test_cleanup_skips_live_temp_spaces() {
+        let root = TempDir::new().expect("temp dir should be created");
+        let live = TempSpace::create_in(root.path(), "import").expect("temp space should open");
+
+        let later = SystemTime::now() + STALE_TEMP_AGE + Duration::from_secs(1);
+        assert_eq!(
+            cleanup_stale_temp_dirs_in(root.path(), STALE_TEMP_AGE, later).unwrap(),
+            0
+        );
+        assert!(live.path().exists());
+
+        let path = live.path().to_path_buf();
+        drop(live);
+        fs::create_dir_all(&path).unwrap();
+        assert_eq!(
+            cleanup_stale_temp_dirs_in(root.path(), STALE_TEMP_AGE, later).unwrap(),
+            1
+        );
+    }
+}
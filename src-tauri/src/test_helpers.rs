@@ -28,6 +28,21 @@ pub mod helpers {
         Connection::open_in_memory().expect("Failed to create in-memory database for testing")
     }
 
+    /// Creates an in-memory database with the application schema applied
+    ///
+    /// # Example
+    /// ```
+    /// use crate::test_helpers::helpers::create_app_db;
+    ///
+    /// let conn = create_app_db();
+    /// // users, officers and the other app tables are ready
+    /// ```
+    pub fn create_app_db() -> Connection {
+        let conn = create_test_db();
+        crate::database::apply_schema(&conn).expect("Failed to apply app schema for testing");
+        conn
+    }
+
     /// Creates a temporary database file in a temporary directory
     ///
     /// # Returns
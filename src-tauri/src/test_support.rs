@@ -0,0 +1,266 @@
+//! Isolated app environment for end-to-end tests of backup/restore/avatar flows
+//!
+//! `TestEnvironment` points the app root at a temp dir and, in in-memory
+//! mode, installs a shared in-memory database provider, so the real command
+//! paths run without touching the user's app data directory. The overrides
+//! are process-wide, so environments are serialized by a global lock and
+//! undone on drop.
+
+use lazy_static::lazy_static;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tempfile::TempDir;
+
+use crate::database::{self, InMemoryProvider, User};
+use crate::file_manager::FileManager;
+use crate::storage_paths::{self, DEFAULT_WORKSPACE};
+
+lazy_static! {
+    static ref ENVIRONMENT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+static MEMORY_DB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestEnvironment {
+    root: TempDir,
+    // Released last, after the overrides are removed in drop()
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestEnvironment {
+    /// Database file inside the temp dir, initialized like a first run
+    pub fn with_temp_dir() -> Self {
+        let env = Self::isolate();
+        database::initialize_database().expect("database should initialize");
+        env
+    }
+
+    /// Main database in shared in-memory SQLite; media and backups use the temp dir
+    /// File-level operations (hybrid backups) need `with_temp_dir` instead
+    pub fn in_memory() -> Self {
+        let env = Self::isolate();
+        let name = format!(
+            "pqs_test_{}",
+            MEMORY_DB_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let provider = InMemoryProvider::new(&name).expect("in-memory provider should open");
+        database::set_connection_provider(Some(Arc::new(provider)));
+        database::initialize_database().expect("database should initialize");
+        env
+    }
+
+    /// Fresh app root before first-run initialization: no database file yet
+    pub fn without_database() -> Self {
+        Self::isolate()
+    }
+
+    fn isolate() -> Self {
+        // A panicking test poisons the lock; the next environment is still usable
+        let lock = ENVIRONMENT_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let root = TempDir::new().expect("temp dir should be created");
+
+        storage_paths::set_app_root_override(Some(root.path().to_path_buf()))
+            .expect("app root override should be set");
+        storage_paths::set_cached_workspace(DEFAULT_WORKSPACE).expect("workspace should be set");
+        FileManager::reset_instance().expect("file manager should reset");
+
+        TestEnvironment { root, _lock: lock }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.path()
+    }
+
+    /// Regular user with a throwaway password hash
+    pub fn create_user(&self, username: &str) -> User {
+        database::create_user(
+            username,
+            &format!("{}@test.local", username),
+            "not-a-real-hash",
+            &format!("Test {}", username),
+            None,
+            None,
+            "user",
+        )
+        .expect("user should be created")
+    }
+}
+
+impl Drop for TestEnvironment {
+    fn drop(&mut self) {
+        database::set_connection_provider(None);
+        let _ = storage_paths::set_app_root_override(None);
+        let _ = FileManager::reset_instance();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hybrid_avatar::HybridAvatarManager;
+    use crate::hybrid_high_rank_avatar::HybridHighRankAvatarManager;
+    use crate::progress::ProgressReporter;
+    use crate::{error_codes, hybrid_backup, user_restore};
+
+    #[test]
+    fn test_in_memory_environment_keeps_data_between_connections() {
+        let env = TestEnvironment::in_memory();
+        let user = env.create_user("memory_user");
+
+        let found = database::get_user_by_id(user.id.unwrap()).expect("lookup should succeed");
+
+        assert_eq!(found.map(|u| u.username), Some("memory_user".to_string()));
+        assert!(!env.root().join("database.db").exists());
+        assert!(database::check_database_exists_and_valid().expect("check should succeed"));
+    }
+
+    #[test]
+    fn test_stale_update_is_refused_with_current_record() {
+        let env = TestEnvironment::in_memory();
+        let mut user = env.create_user("first_window");
+        let id = user.id.unwrap();
+        let version = user.row_version;
+
+        user = database::update_user_service_number(id, Some("12345"), Some(version))
+            .expect("update with current version should succeed");
+        assert_eq!(user.row_version, version + 1);
+
+        // The second window still holds the original version
+        let error = database::update_user(
+            id,
+            "second_window",
+            &user.email,
+            &user.password_hash,
+            &user.full_name,
+            None,
+            None,
+            "user",
+            Some(version),
+        )
+        .expect_err("stale update should conflict");
+        assert_eq!(
+            error_codes::find_code(&error),
+            Some(error_codes::ROW_VERSION_CONFLICT)
+        );
+        assert!(error.contains("12345"));
+        let current = database::get_user_by_id(id).unwrap().unwrap();
+        assert_eq!(current.username, "first_window");
+
+        // Callers that do not send a version keep last-write-wins
+        database::update_user_service_number(id, None, None).expect("unversioned update works");
+    }
+
+    #[test]
+    fn test_english_name_goes_through_create_and_update() {
+        let _env = TestEnvironment::in_memory();
+        let user = database::create_user(
+            "english_name",
+            "english_name@test.local",
+            "not-a-real-hash",
+            "ทดสอบ",
+            Some("  Test Person "),
+            None,
+            "user",
+        )
+        .expect("user should be created");
+        assert_eq!(user.full_name_en.as_deref(), Some("Test Person"));
+        let id = user.id.unwrap();
+
+        let update = |full_name_en: Option<&str>| {
+            database::update_user(
+                id,
+                &user.username,
+                &user.email,
+                &user.password_hash,
+                &user.full_name,
+                full_name_en,
+                None,
+                "user",
+                None,
+            )
+            .expect("update should succeed")
+            .full_name_en
+        };
+        // None leaves the English name alone, blank clears it
+        assert_eq!(update(None).as_deref(), Some("Test Person"));
+        assert_eq!(update(Some("Renamed")).as_deref(), Some("Renamed"));
+        assert_eq!(update(Some(" ")), None);
+    }
+
+    #[test]
+    fn test_missing_database_is_not_created_by_app_code() {
+        let env = TestEnvironment::without_database();
+
+        assert!(database::get_connection_safe().is_err());
+        assert!(database::get_all_users().is_err());
+        assert!(database::get_user_by_id(1).is_err());
+        let avatars = HybridAvatarManager::new().expect("avatar manager should start");
+        assert!(avatars.get_user_avatar_info(1).is_err());
+        assert!(avatars.cleanup_orphaned_files().is_err());
+        let officers =
+            HybridHighRankAvatarManager::new().expect("high rank avatar manager should start");
+        assert!(officers.cleanup_orphaned_files().is_err());
+
+        // Only the initializer may create it
+        assert!(!env.root().join("database.db").exists());
+        database::initialize_database().expect("database should initialize");
+        assert!(env.root().join("database.db").exists());
+    }
+
+    #[test]
+    fn test_paths_resolve_inside_temp_root() {
+        let env = TestEnvironment::with_temp_dir();
+
+        assert!(env.root().join("database.db").exists());
+        assert_eq!(
+            storage_paths::get_backup_dir().expect("backup dir should resolve"),
+            env.root().join("backups")
+        );
+    }
+
+    #[test]
+    fn test_avatar_survives_hybrid_backup_and_user_restore() {
+        let env = TestEnvironment::with_temp_dir();
+        let user = env.create_user("restore_me");
+        let user_id = user.id.unwrap();
+
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("test image should encode");
+        let avatar = HybridAvatarManager::new()
+            .expect("avatar manager should start")
+            .save_avatar(user_id, &png, "image/png", None)
+            .expect("avatar should save");
+        let avatar_path = avatar.avatar_path.expect("avatar path should be set");
+
+        hybrid_backup::create_hybrid_backup_with_progress(&ProgressReporter::noop(), None)
+            .expect("backup should be created");
+        let backups = hybrid_backup::discover_available_backups().expect("discover should work");
+        assert_eq!(backups.len(), 1);
+
+        // Lose the account and its photo
+        database::delete_user(user_id).expect("delete should succeed");
+        let media_file = env.root().join("media").join(&avatar_path);
+        let _ = std::fs::remove_file(&media_file);
+
+        let restored = user_restore::restore_user_from_backup(&backups[0].filename, "restore_me")
+            .expect("restore should succeed");
+
+        assert_eq!(restored.user_id, user_id);
+        assert!(restored.avatar_restored);
+        assert!(media_file.exists());
+        // The backed-up database was staged outside the backup directory
+        let backup_dir = crate::backup_manager::get_backup_directory().unwrap();
+        assert!(!std::fs::read_dir(backup_dir)
+            .unwrap()
+            .flatten()
+            .any(|entry| entry.path().extension().is_some_and(|ext| ext == "db")));
+    }
+}
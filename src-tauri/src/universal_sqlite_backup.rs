@@ -1,12 +1,13 @@
+use rusqlite::types::ValueRef;
 use rusqlite::Connection;
 use std::fs;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::api::path::app_data_dir;
-use tauri::Config;
+
+use crate::backup_results::{BackupCreated, BackupKind};
 
 // Universal SQLite backup that creates standard .db files
-pub fn create_universal_sqlite_backup() -> Result<String, String> {
+pub fn create_universal_sqlite_backup() -> Result<BackupCreated, String> {
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -22,14 +23,13 @@ pub fn create_universal_sqlite_backup() -> Result<String, String> {
     fs::copy(&source_db_path, &backup_path)
         .map_err(|e| format!("Failed to copy database file: {}", e))?;
 
-    Ok(format!(
-        "Universal SQLite backup created: {}",
-        backup_filename
-    ))
+    let mut created = BackupCreated::for_file(BackupKind::Sqlite, &backup_path)?;
+    created.database_bytes = Some(created.size_bytes);
+    Ok(created)
 }
 
 // Create standard SQL dump that works with any SQLite
-pub fn create_standard_sql_dump() -> Result<String, String> {
+pub fn create_standard_sql_dump() -> Result<BackupCreated, String> {
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -57,7 +57,7 @@ pub fn create_standard_sql_dump() -> Result<String, String> {
         // Get table schema
         let schema = get_table_schema(&conn, &table)?;
         sql_content.push_str(&format!("-- Table: {}\n", table));
-        sql_content.push_str(&format!("{}\n\n", schema));
+        sql_content.push_str(&format!("{};\n\n", schema));
 
         // Get table data
         let data = get_table_data(&conn, &table)?;
@@ -70,29 +70,16 @@ pub fn create_standard_sql_dump() -> Result<String, String> {
     // Write to file
     fs::write(&dump_path, sql_content).map_err(|e| format!("Failed to write SQL dump: {}", e))?;
 
-    Ok(format!("Standard SQL dump created: {}", dump_filename))
+    BackupCreated::for_file(BackupKind::SqlDump, &dump_path)
 }
 
 // Helper functions
 fn get_backup_directory() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    let backup_dir = app_data.join("pqs-rtn-hybrid-storage").join("backups");
-
-    if !backup_dir.exists() {
-        fs::create_dir_all(&backup_dir)
-            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
-    }
-
-    Ok(backup_dir)
+    crate::storage_paths::get_backup_dir()
 }
 
 fn get_database_path() -> Result<PathBuf, String> {
-    let config = Config::default();
-    let app_data = app_data_dir(&config).ok_or("Failed to get app data directory")?;
-
-    Ok(app_data.join("pqs-rtn-hybrid-storage").join("database.db"))
+    crate::storage_paths::get_database_path()
 }
 
 #[allow(dead_code)]
@@ -257,26 +244,31 @@ fn get_table_data(conn: &Connection, table: &str) -> Result<Vec<String>, String>
         .prepare(&format!("SELECT * FROM {}", table))
         .map_err(|e| format!("Failed to prepare data query for {}: {}", table, e))?;
 
+    let column_count = stmt.column_count();
     let rows = stmt
         .query_map([], |row| {
-            let mut values = Vec::new();
-            let mut i = 0;
-            while let Ok(value) = row.get::<_, String>(i) {
-                values.push(value);
-                i += 1;
-            }
-
             // Create INSERT statement
             let mut insert = format!("INSERT INTO {} VALUES (", table);
 
-            for (i, value) in values.iter().enumerate() {
+            for i in 0..column_count {
                 if i > 0 {
                     insert.push_str(", ");
                 }
-                if value == "NULL" {
-                    insert.push_str("NULL");
-                } else {
-                    insert.push_str(&format!("'{}'", value.replace("'", "''")));
+                match row.get_ref(i)? {
+                    ValueRef::Null => insert.push_str("NULL"),
+                    ValueRef::Integer(n) => insert.push_str(&n.to_string()),
+                    ValueRef::Real(f) => insert.push_str(&format!("{:?}", f)),
+                    ValueRef::Text(text) => insert.push_str(&format!(
+                        "'{}'",
+                        String::from_utf8_lossy(text).replace("'", "''")
+                    )),
+                    ValueRef::Blob(blob) => {
+                        insert.push_str("X'");
+                        for byte in blob {
+                            insert.push_str(&format!("{:02X}", byte));
+                        }
+                        insert.push('\'');
+                    }
                 }
             }
             insert.push_str(");");
@@ -0,0 +1,404 @@
+//! Move inactive users out of the live database into `archive/archive.db`
+//!
+//! Archived rows keep their original id and columns, and their avatar files
+//! move to `archive/media/` under the same relative path, so `avatar_path` of
+//! an archived user resolves against the archive media directory.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::activity_log;
+use crate::database::{
+    add_column_if_missing, get_connection_safe, map_user_row, User, USER_SELECT_COLUMNS,
+};
+use crate::file_manager::FileManager;
+use crate::logger;
+use crate::safe_path;
+use crate::storage_paths;
+
+const ARCHIVE_DB_FILENAME: &str = "archive.db";
+const SEARCH_LIMIT: i64 = 200;
+
+/// User id and avatar path of a user selected for archiving
+type ArchiveCandidate = (i32, Option<String>);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArchiveSkip {
+    pub user_id: i32,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArchiveReport {
+    pub archived_user_ids: Vec<i32>,
+    pub skipped: Vec<ArchiveSkip>,
+    pub avatars_moved: usize,
+    pub warnings: Vec<String>,
+}
+
+pub fn init_archive_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS archived_users (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            email TEXT NOT NULL,
+            password_hash TEXT NOT NULL,
+            full_name TEXT NOT NULL,
+            rank TEXT,
+            role TEXT NOT NULL,
+            is_active BOOLEAN NOT NULL,
+            avatar_path TEXT,
+            avatar_updated_at DATETIME,
+            avatar_mime TEXT,
+            avatar_size INTEGER,
+            created_at DATETIME,
+            updated_at DATETIME,
+            service_number TEXT,
+            archived_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create archived_users table: {}", e))?;
+
+    // Keep up with columns added to users, since rows are copied by USER_SELECT_COLUMNS
+    add_column_if_missing(
+        conn,
+        "archived_users",
+        "row_version",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+    add_column_if_missing(
+        conn,
+        "archived_users",
+        "must_change_password",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(conn, "archived_users", "full_name_en", "TEXT")?;
+    Ok(())
+}
+
+/// Users to archive with their avatar paths, plus the ids that were refused
+/// Only inactive users are archived; `ids` takes precedence over `before_date`
+pub fn select_archive_candidates_with_conn(
+    conn: &Connection,
+    before_date: Option<&str>,
+    ids: Option<&[i32]>,
+) -> Result<(Vec<ArchiveCandidate>, Vec<ArchiveSkip>), String> {
+    let mut candidates = Vec::new();
+    let mut skipped = Vec::new();
+
+    if let Some(ids) = ids {
+        let mut stmt = conn
+            .prepare("SELECT is_active, avatar_path FROM users WHERE id = ?")
+            .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+        for &id in ids {
+            let row: Option<(bool, Option<String>)> =
+                match stmt.query_row([id], |r| Ok((r.get(0)?, r.get(1)?))) {
+                    Ok(row) => Some(row),
+                    Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                    Err(e) => return Err(format!("Failed to query user {}: {}", id, e)),
+                };
+            let reason = match row {
+                None => "User not found",
+                Some((true, _)) => "User is still active",
+                Some((false, avatar_path)) => {
+                    candidates.push((id, avatar_path));
+                    continue;
+                }
+            };
+            skipped.push(ArchiveSkip {
+                user_id: id,
+                reason: reason.to_string(),
+            });
+        }
+    } else if let Some(before_date) = before_date {
+        chrono::NaiveDate::parse_from_str(before_date, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", before_date))?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, avatar_path FROM users
+                 WHERE is_active = 0 AND COALESCE(updated_at, created_at) < ?
+                 ORDER BY id",
+            )
+            .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+        candidates = stmt
+            .query_map([before_date], |r| Ok((r.get(0)?, r.get(1)?)))
+            .map_err(|e| format!("Failed to query users: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read user: {}", e))?;
+    } else {
+        return Err("Specify a date or a list of users to archive".to_string());
+    }
+
+    Ok((candidates, skipped))
+}
+
+/// Copy the rows into the archive and delete them from `conn` in one transaction
+pub fn move_users_to_archive_with_conn(
+    conn: &Connection,
+    archive_db: &Path,
+    ids: &[i32],
+) -> Result<(), String> {
+    {
+        let archive_conn = Connection::open(archive_db)
+            .map_err(|e| format!("Failed to open archive database: {}", e))?;
+        init_archive_schema(&archive_conn)?;
+    }
+
+    conn.execute(
+        "ATTACH DATABASE ? AS archive",
+        [archive_db.to_string_lossy().to_string()],
+    )
+    .map_err(|e| format!("Failed to attach archive database: {}", e))?;
+
+    let result = copy_and_delete_users(conn, ids);
+
+    if let Err(e) = conn.execute("DETACH DATABASE archive", []) {
+        logger::warn(format!("Failed to detach archive database: {}", e));
+    }
+    result
+}
+
+fn copy_and_delete_users(conn: &Connection, ids: &[i32]) -> Result<(), String> {
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    for &id in ids {
+        tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO archive.archived_users ({cols}) SELECT {cols} FROM main.users WHERE id = ?",
+                cols = USER_SELECT_COLUMNS
+            ),
+            params![id],
+        )
+        .map_err(|e| format!("Failed to archive user {}: {}", id, e))?;
+        tx.execute("DELETE FROM main.users WHERE id = ?", params![id])
+            .map_err(|e| format!("Failed to remove archived user {}: {}", id, e))?;
+    }
+
+    tx.commit()
+        .map_err(|e| format!("Failed to commit archive: {}", e))
+}
+
+/// Move one media file, falling back to copy + delete across filesystems
+pub fn move_media_file(
+    media_dir: &Path,
+    archive_media_dir: &Path,
+    relative: &str,
+) -> Result<(), String> {
+    let source = safe_path::MediaRoot::new(media_dir)?
+        .resolve(relative)?
+        .into_path_buf();
+    let relative = safe_path::normalize_relative(relative)?;
+    let target = archive_media_dir.join(&relative);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create archive media directory: {}", e))?;
+    }
+
+    if fs::rename(&source, &target).is_err() {
+        fs::copy(&source, &target)
+            .map_err(|e| format!("Failed to copy {} to archive: {}", relative, e))?;
+        fs::remove_file(&source)
+            .map_err(|e| format!("Failed to remove {} after archiving: {}", relative, e))?;
+    }
+    Ok(())
+}
+
+pub fn archive_users(
+    before_date: Option<&str>,
+    ids: Option<&[i32]>,
+) -> Result<ArchiveReport, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let (candidates, skipped) = select_archive_candidates_with_conn(&conn, before_date, ids)?;
+
+    let mut report = ArchiveReport {
+        archived_user_ids: candidates.iter().map(|(id, _)| *id).collect(),
+        skipped,
+        avatars_moved: 0,
+        warnings: Vec::new(),
+    };
+    if candidates.is_empty() {
+        return Ok(report);
+    }
+
+    let archive_dir = storage_paths::get_archive_dir()?;
+    move_users_to_archive_with_conn(
+        &conn,
+        &archive_dir.join(ARCHIVE_DB_FILENAME),
+        &report.archived_user_ids,
+    )?;
+
+    // Rows are committed; a file that fails to move only produces a warning
+    let file_manager = FileManager::get_instance()?;
+    let media_dir = file_manager.get_media_directory();
+    let archive_media_dir = archive_dir.join("media");
+    for (id, avatar_path) in &candidates {
+        let avatar_path = match avatar_path.as_deref().filter(|p| !p.is_empty()) {
+            Some(p) => p,
+            None => continue,
+        };
+        let exists = safe_path::MediaRoot::new(media_dir)
+            .and_then(|root| root.resolve(avatar_path))
+            .is_ok_and(|source| source.full_path().exists());
+        if !exists {
+            continue;
+        }
+        match move_media_file(media_dir, &archive_media_dir, avatar_path) {
+            Ok(()) => report.avatars_moved += 1,
+            Err(e) => report.warnings.push(format!("User {}: {}", id, e)),
+        }
+    }
+
+    let details = format!(
+        "{} users archived, {} avatars moved",
+        report.archived_user_ids.len(),
+        report.avatars_moved
+    );
+    logger::info(format!("Archive finished: {}", details));
+    activity_log::record_event(
+        activity_log::EVENT_USERS_ARCHIVED,
+        None,
+        None,
+        Some(&details),
+    );
+
+    Ok(report)
+}
+
+/// Case-insensitive match on username, name, email or service number
+pub fn search_archive_with_conn(conn: &Connection, query: &str) -> Result<Vec<User>, String> {
+    let pattern = format!("%{}%", query.trim());
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM archived_users
+             WHERE username LIKE ?1 OR full_name LIKE ?1 OR email LIKE ?1 OR service_number LIKE ?1
+             ORDER BY archived_at DESC LIMIT ?2",
+            USER_SELECT_COLUMNS
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+
+    let users = stmt
+        .query_map(params![pattern, SEARCH_LIMIT], map_user_row)
+        .map_err(|e| format!("Failed to search archive: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read archived user: {}", e))?;
+    Ok(users)
+}
+
+pub fn search_archive(query: &str) -> Result<Vec<User>, String> {
+    let archive_db = storage_paths::get_archive_dir()?.join(ARCHIVE_DB_FILENAME);
+    if !archive_db.exists() {
+        return Ok(Vec::new());
+    }
+
+    let conn = Connection::open(&archive_db)
+        .map_err(|e| format!("Failed to open archive database: {}", e))?;
+    search_archive_with_conn(&conn, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::apply_schema;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    fn seed_users(conn: &Connection) {
+        for (id, username, active, updated_at) in [
+            (1, "active", true, "2020-01-01 00:00:00"),
+            (2, "old_inactive", false, "2020-01-01 00:00:00"),
+            (3, "new_inactive", false, "2024-06-01 00:00:00"),
+        ] {
+            conn.execute(
+                "INSERT INTO users (id, username, email, password_hash, full_name, is_active, avatar_path, updated_at)
+                 VALUES (?, ?, ?, 'h', ?, ?, ?, ?)",
+                params![
+                    id,
+                    username,
+                    format!("{}@test.com", username),
+                    username,
+                    active,
+                    format!("avatars/{}.png", username),
+                    updated_at
+                ],
+            )
+            .expect("user insert should succeed");
+        }
+    }
+
+    #[test]
+    fn test_select_by_date_only_takes_old_inactive_users() {
+        let conn = create_app_db();
+        seed_users(&conn);
+
+        let (candidates, skipped) =
+            select_archive_candidates_with_conn(&conn, Some("2023-01-01"), None)
+                .expect("select should succeed");
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].0, 2);
+        assert!(skipped.is_empty());
+        assert!(select_archive_candidates_with_conn(&conn, Some("01/01/2023"), None).is_err());
+    }
+
+    #[test]
+    fn test_select_by_ids_skips_active_and_missing() {
+        let conn = create_app_db();
+        seed_users(&conn);
+
+        let (candidates, skipped) =
+            select_archive_candidates_with_conn(&conn, None, Some(&[1, 3, 9]))
+                .expect("select should succeed");
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].0, 3);
+        assert_eq!(skipped.len(), 2);
+    }
+
+    #[test]
+    fn test_move_and_search_archive() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let conn = Connection::open(dir.path().join("database.db")).expect("db should open");
+        apply_schema(&conn).expect("schema should apply");
+        seed_users(&conn);
+        let archive_db = dir.path().join(ARCHIVE_DB_FILENAME);
+
+        move_users_to_archive_with_conn(&conn, &archive_db, &[2, 3])
+            .expect("archive should succeed");
+
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM users", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(remaining, 1);
+
+        let archive_conn = Connection::open(&archive_db).expect("archive should open");
+        let found =
+            search_archive_with_conn(&archive_conn, "OLD_inactive").expect("search should succeed");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, Some(2));
+        assert_eq!(
+            search_archive_with_conn(&archive_conn, "").unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_move_media_file() {
+        let dir = TempDir::new().expect("temp dir should be created");
+        let media = dir.path().join("media");
+        let archive_media = dir.path().join("archive").join("media");
+        fs::create_dir_all(media.join("avatars")).unwrap();
+        fs::write(media.join("avatars").join("a.png"), b"png").unwrap();
+
+        move_media_file(&media, &archive_media, "avatars\\a.png").expect("move should succeed");
+
+        assert!(!media.join("avatars").join("a.png").exists());
+        assert!(archive_media.join("avatars").join("a.png").exists());
+    }
+}
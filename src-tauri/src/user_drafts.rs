@@ -0,0 +1,303 @@
+//! Auto-saved drafts of profile edits
+//!
+//! The profile form sends its fields to `autosave_user_draft` as they are
+//! typed. Each call is merged into the user's draft in memory; the draft is
+//! written to `user_drafts` at most once per `AUTOSAVE_INTERVAL`, and a call
+//! that arrives sooner schedules one write for when the interval is over, so
+//! the last keystrokes are never lost. A form reopened after a crash loads
+//! the draft with `get_user_draft`. The real update still goes through
+//! `update_user`, which discards the draft once it succeeds. Passwords are
+//! never part of a draft.
+
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::database::get_connection_safe;
+use crate::logger;
+
+/// Shortest time between two writes of one user's draft
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Profile fields a draft may hold
+const DRAFT_FIELDS: &[&str] = &[
+    "username",
+    "email",
+    "full_name",
+    "full_name_en",
+    "rank",
+    "service_number",
+    "role",
+];
+const MAX_FIELD_CHARS: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserDraft {
+    pub user_id: i32,
+    /// Field name to value, only the fields the user touched
+    pub fields: Map<String, Value>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AutosaveStatus {
+    /// Written now; otherwise a write is scheduled within AUTOSAVE_INTERVAL
+    pub saved: bool,
+    pub fields: Map<String, Value>,
+}
+
+#[derive(Default)]
+struct PendingDraft {
+    fields: Map<String, Value>,
+    last_written: Option<Instant>,
+    /// Changed since the last write
+    dirty: bool,
+    flush_scheduled: bool,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<i32, PendingDraft>> = Mutex::new(HashMap::new());
+}
+
+pub fn init_user_drafts_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS user_drafts (
+            user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            fields TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create user_drafts table: {}", e))?;
+
+    Ok(())
+}
+
+/// Check `partial` is an object of known profile fields with string or null values
+pub fn validate_partial(partial: &Value) -> Result<&Map<String, Value>, String> {
+    let fields = partial
+        .as_object()
+        .ok_or_else(|| "Draft must be an object of profile fields".to_string())?;
+    for (name, value) in fields {
+        if !DRAFT_FIELDS.contains(&name.as_str()) {
+            return Err(format!("Unknown draft field: {}", name));
+        }
+        match value {
+            Value::Null => {}
+            Value::String(text) if text.chars().count() <= MAX_FIELD_CHARS => {}
+            Value::String(_) => {
+                return Err(format!(
+                    "Draft field {} must be at most {} characters",
+                    name, MAX_FIELD_CHARS
+                ))
+            }
+            _ => return Err(format!("Draft field {} must be text", name)),
+        }
+    }
+    Ok(fields)
+}
+
+pub fn save_user_draft_with_conn(
+    conn: &Connection,
+    user_id: i32,
+    fields: &Map<String, Value>,
+) -> Result<(), String> {
+    let json =
+        serde_json::to_string(fields).map_err(|e| format!("Failed to serialize draft: {}", e))?;
+    conn.execute(
+        "INSERT INTO user_drafts (user_id, fields, updated_at)
+         VALUES (?1, ?2, CURRENT_TIMESTAMP)
+         ON CONFLICT(user_id) DO UPDATE SET
+            fields = excluded.fields,
+            updated_at = excluded.updated_at",
+        params![user_id, json],
+    )
+    .map_err(|e| format!("Failed to save draft: {}", e))?;
+    Ok(())
+}
+
+pub fn get_user_draft_with_conn(
+    conn: &Connection,
+    user_id: i32,
+) -> Result<Option<UserDraft>, String> {
+    let stored = conn
+        .query_row(
+            "SELECT fields, updated_at FROM user_drafts WHERE user_id = ?",
+            params![user_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to load draft: {}", e))?;
+
+    stored
+        .map(|(fields, updated_at)| -> Result<UserDraft, String> {
+            Ok(UserDraft {
+                user_id,
+                fields: serde_json::from_str(&fields)
+                    .map_err(|e| format!("Failed to parse draft: {}", e))?,
+                updated_at,
+            })
+        })
+        .transpose()
+}
+
+pub fn delete_user_draft_with_conn(conn: &Connection, user_id: i32) -> Result<bool, String> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM user_drafts WHERE user_id = ?",
+            params![user_id],
+        )
+        .map_err(|e| format!("Failed to delete draft: {}", e))?;
+    Ok(deleted > 0)
+}
+
+fn connect() -> Result<Connection, String> {
+    get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))
+}
+
+fn lock_pending() -> Result<std::sync::MutexGuard<'static, HashMap<i32, PendingDraft>>, String> {
+    PENDING
+        .lock()
+        .map_err(|_| "Draft registry is unavailable".to_string())
+}
+
+/// Write the user's pending changes, if any
+fn flush(user_id: i32) -> Result<(), String> {
+    let mut pending = lock_pending()?;
+    let Some(draft) = pending.get_mut(&user_id) else {
+        return Ok(());
+    };
+    draft.flush_scheduled = false;
+    if !draft.dirty {
+        return Ok(());
+    }
+    save_user_draft_with_conn(&connect()?, user_id, &draft.fields)?;
+    draft.dirty = false;
+    draft.last_written = Some(Instant::now());
+    Ok(())
+}
+
+fn schedule_flush(user_id: i32, delay: Duration) {
+    thread::spawn(move || {
+        thread::sleep(delay);
+        if let Err(e) = flush(user_id) {
+            logger::warn(format!(
+                "Failed to auto-save draft of user {}: {}",
+                user_id, e
+            ));
+        }
+    });
+}
+
+/// Merge `partial` into the user's draft; written now or within
+/// AUTOSAVE_INTERVAL of the previous write
+pub fn autosave_user_draft(user_id: i32, partial: &Value) -> Result<AutosaveStatus, String> {
+    let partial = validate_partial(partial)?;
+    let mut pending = lock_pending()?;
+
+    let draft = match pending.entry(user_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            // Continue the draft left by an earlier session
+            let stored = get_user_draft_with_conn(&connect()?, user_id)?;
+            entry.insert(PendingDraft {
+                fields: stored.map(|d| d.fields).unwrap_or_default(),
+                ..PendingDraft::default()
+            })
+        }
+    };
+    for (name, value) in partial {
+        draft.fields.insert(name.clone(), value.clone());
+    }
+    draft.dirty = true;
+
+    let wait = draft
+        .last_written
+        .map(|at| AUTOSAVE_INTERVAL.saturating_sub(at.elapsed()))
+        .unwrap_or_default();
+    let saved = wait.is_zero();
+    if saved {
+        save_user_draft_with_conn(&connect()?, user_id, &draft.fields)?;
+        draft.dirty = false;
+        draft.last_written = Some(Instant::now());
+    } else if !draft.flush_scheduled {
+        draft.flush_scheduled = true;
+        schedule_flush(user_id, wait);
+    }
+
+    Ok(AutosaveStatus {
+        saved,
+        fields: draft.fields.clone(),
+    })
+}
+
+/// The user's draft including changes not written yet
+pub fn get_user_draft(user_id: i32) -> Result<Option<UserDraft>, String> {
+    flush(user_id)?;
+    get_user_draft_with_conn(&connect()?, user_id)
+}
+
+/// Forget the user's draft; false if there was none
+pub fn discard_user_draft(user_id: i32) -> Result<bool, String> {
+    let had_pending = lock_pending()?.remove(&user_id).is_some();
+    let deleted = delete_user_draft_with_conn(&connect()?, user_id)?;
+    Ok(had_pending || deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestEnvironment;
+
+    #[test]
+    fn test_partial_fields_are_validated() {
+        assert!(
+            validate_partial(&serde_json::json!({ "full_name": "สมชาย", "rank": null })).is_ok()
+        );
+        assert!(validate_partial(&serde_json::json!({ "password_hash": "x" })).is_err());
+        assert!(validate_partial(&serde_json::json!({ "email": 5 })).is_err());
+        assert!(validate_partial(&serde_json::json!(["full_name"])).is_err());
+        assert!(
+            validate_partial(&serde_json::json!({ "email": "a".repeat(MAX_FIELD_CHARS + 1) }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_autosave_is_debounced_and_merged() {
+        let env = TestEnvironment::with_temp_dir();
+        let user_id = env
+            .create_user("draft_user")
+            .id
+            .expect("user should have an id");
+
+        let first =
+            autosave_user_draft(user_id, &serde_json::json!({ "full_name": "สมชาย" })).unwrap();
+        assert!(first.saved);
+        let second = autosave_user_draft(user_id, &serde_json::json!({ "rank": "น.ท." })).unwrap();
+        assert!(!second.saved);
+
+        // Only the first write reached the table so far
+        let stored = get_user_draft_with_conn(&connect().unwrap(), user_id)
+            .unwrap()
+            .expect("draft should be stored");
+        assert!(!stored.fields.contains_key("rank"));
+
+        // Reading flushes what is pending
+        let draft = get_user_draft(user_id)
+            .unwrap()
+            .expect("draft should exist");
+        assert_eq!(draft.fields["full_name"], "สมชาย");
+        assert_eq!(draft.fields["rank"], "น.ท.");
+
+        assert!(discard_user_draft(user_id).unwrap());
+        assert_eq!(get_user_draft(user_id).unwrap(), None);
+        assert!(!discard_user_draft(user_id).unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+//! Per-user view settings (zoom, theme, language) in the main database
+//!
+//! Shared machines are used by several people, so these live next to the
+//! user account instead of in the machine-wide settings file. The frontend
+//! calls `apply_user_preferences` right after login.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::database::get_connection_safe;
+
+/// Zoom is a factor of the 16px root font size; matches the zoom commands' 8-32px range
+pub const MIN_ZOOM_LEVEL: f64 = 0.5;
+pub const MAX_ZOOM_LEVEL: f64 = 2.0;
+const BASE_FONT_SIZE_PX: f64 = 16.0;
+
+const THEMES: &[&str] = &["light", "dark"];
+const LANGUAGES: &[&str] = &["th", "en"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserPreferences {
+    pub zoom_level: f64,
+    pub theme: String,
+    pub language: String,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        UserPreferences {
+            zoom_level: 1.0,
+            theme: "light".to_string(),
+            language: "th".to_string(),
+        }
+    }
+}
+
+pub fn init_user_preferences_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS user_preferences (
+            user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            zoom_level REAL NOT NULL DEFAULT 1.0,
+            theme TEXT NOT NULL DEFAULT 'light',
+            language TEXT NOT NULL DEFAULT 'th',
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create user_preferences table: {}", e))?;
+
+    Ok(())
+}
+
+pub fn validate_preferences(preferences: &UserPreferences) -> Result<(), String> {
+    if !(MIN_ZOOM_LEVEL..=MAX_ZOOM_LEVEL).contains(&preferences.zoom_level) {
+        return Err(format!(
+            "Zoom level must be between {} and {}",
+            MIN_ZOOM_LEVEL, MAX_ZOOM_LEVEL
+        ));
+    }
+    if !THEMES.contains(&preferences.theme.as_str()) {
+        return Err(format!("Unknown theme: {}", preferences.theme));
+    }
+    if !LANGUAGES.contains(&preferences.language.as_str()) {
+        return Err(format!("Unknown language: {}", preferences.language));
+    }
+    Ok(())
+}
+
+/// Stored preferences, or defaults for users who never changed anything
+pub fn get_user_preferences_with_conn(
+    conn: &Connection,
+    user_id: i32,
+) -> Result<UserPreferences, String> {
+    let stored = conn
+        .query_row(
+            "SELECT zoom_level, theme, language FROM user_preferences WHERE user_id = ?",
+            params![user_id],
+            |row| {
+                Ok(UserPreferences {
+                    zoom_level: row.get(0)?,
+                    theme: row.get(1)?,
+                    language: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to load user preferences: {}", e))?;
+
+    Ok(stored.unwrap_or_default())
+}
+
+pub fn set_user_preferences_with_conn(
+    conn: &Connection,
+    user_id: i32,
+    preferences: &UserPreferences,
+) -> Result<(), String> {
+    validate_preferences(preferences)?;
+
+    conn.execute(
+        "INSERT INTO user_preferences (user_id, zoom_level, theme, language, updated_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+         ON CONFLICT(user_id) DO UPDATE SET
+            zoom_level = excluded.zoom_level,
+            theme = excluded.theme,
+            language = excluded.language,
+            updated_at = excluded.updated_at",
+        params![
+            user_id,
+            preferences.zoom_level,
+            preferences.theme,
+            preferences.language
+        ],
+    )
+    .map_err(|e| format!("Failed to save user preferences: {}", e))?;
+
+    Ok(())
+}
+
+pub fn get_user_preferences(user_id: i32) -> Result<UserPreferences, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    get_user_preferences_with_conn(&conn, user_id)
+}
+
+pub fn set_user_preferences(user_id: i32, preferences: &UserPreferences) -> Result<(), String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    set_user_preferences_with_conn(&conn, user_id, preferences)
+}
+
+/// Script applying zoom, theme and language to the current page
+pub fn preferences_script(preferences: &UserPreferences) -> String {
+    let font_size =
+        BASE_FONT_SIZE_PX * preferences.zoom_level.clamp(MIN_ZOOM_LEVEL, MAX_ZOOM_LEVEL);
+    let dark = preferences.theme == "dark";
+    // Values are validated on save, but never splice raw strings into the page
+    let language = serde_json::to_string(&preferences.language).unwrap_or_default();
+
+    format!(
+        r#"
+        (function() {{
+            const root = document.documentElement;
+            root.style.fontSize = '{}px';
+            root.classList.toggle('dark', {});
+            root.lang = {};
+        }})()
+    "#,
+        font_size, dark, language
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn conn_with_user() -> (Connection, i32) {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (username, email, password_hash, full_name) VALUES ('u', 'u@test.com', 'h', 'U')",
+            [],
+        )
+        .expect("user insert should succeed");
+        let id = conn.last_insert_rowid() as i32;
+        (conn, id)
+    }
+
+    #[test]
+    fn test_defaults_until_saved() {
+        let (conn, user_id) = conn_with_user();
+
+        let preferences = get_user_preferences_with_conn(&conn, user_id).expect("load should work");
+
+        assert_eq!(preferences, UserPreferences::default());
+    }
+
+    #[test]
+    fn test_preferences_round_trip_per_user() {
+        let (conn, user_id) = conn_with_user();
+        let preferences = UserPreferences {
+            zoom_level: 1.25,
+            theme: "dark".to_string(),
+            language: "en".to_string(),
+        };
+
+        set_user_preferences_with_conn(&conn, user_id, &preferences).expect("save should work");
+        set_user_preferences_with_conn(&conn, user_id, &preferences).expect("update should work");
+
+        assert_eq!(
+            get_user_preferences_with_conn(&conn, user_id).unwrap(),
+            preferences
+        );
+        assert_eq!(
+            get_user_preferences_with_conn(&conn, user_id + 1).unwrap(),
+            UserPreferences::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_preferences_rejected() {
+        let (conn, user_id) = conn_with_user();
+        let too_far = UserPreferences {
+            zoom_level: 5.0,
+            ..UserPreferences::default()
+        };
+        let bad_theme = UserPreferences {
+            theme: "neon".to_string(),
+            ..UserPreferences::default()
+        };
+
+        assert!(set_user_preferences_with_conn(&conn, user_id, &too_far).is_err());
+        assert!(set_user_preferences_with_conn(&conn, user_id, &bad_theme).is_err());
+    }
+
+    #[test]
+    fn test_preferences_script() {
+        let script = preferences_script(&UserPreferences {
+            zoom_level: 1.5,
+            theme: "dark".to_string(),
+            language: "en".to_string(),
+        });
+
+        assert!(script.contains("'24px'"));
+        assert!(script.contains("toggle('dark', true)"));
+        assert!(script.contains("root.lang = \"en\""));
+    }
+}
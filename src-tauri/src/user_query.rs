@@ -0,0 +1,393 @@
+//! Filtered user lists and their TSV rendering for pasting into spreadsheets
+//!
+//! Large personnel lists are fetched a page at a time with
+//! `query_users_page_with_conn` and `count_users_with_conn` instead of in one
+//! IPC payload.
+
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{get_connection_safe, map_user_row, User, USER_SELECT_COLUMNS};
+
+/// Columns that may be exported; password hashes and avatar metadata never leave the app
+pub const EXPORTABLE_COLUMNS: &[&str] = &[
+    "id",
+    "username",
+    "email",
+    "full_name",
+    "full_name_en",
+    "rank",
+    "role",
+    "is_active",
+    "service_number",
+    "created_at",
+    "updated_at",
+];
+
+const DEFAULT_COLUMNS: &[&str] = &["username", "full_name", "rank", "role", "email"];
+
+/// Rows per page when none is given, and the most one call returns
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+/// Columns a list may be ordered by; the name is spliced into SQL, so only these
+pub const SORTABLE_COLUMNS: &[&str] = &[
+    "username",
+    "full_name",
+    "full_name_en",
+    "email",
+    "rank",
+    "role",
+    "service_number",
+    "created_at",
+    "updated_at",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct UserFilters {
+    /// Matched against username, full name, email and service number
+    pub search: Option<String>,
+    pub role: Option<String>,
+    pub rank: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct UserSort {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl Default for UserSort {
+    fn default() -> Self {
+        UserSort {
+            column: "username".to_string(),
+            descending: false,
+        }
+    }
+}
+
+impl UserSort {
+    /// From the `sort_by`/`sort_dir` command arguments; `sort_dir` is "asc"
+    /// or "desc"
+    pub fn from_args(sort_by: Option<&str>, sort_dir: Option<&str>) -> Result<Self, String> {
+        let descending = match sort_dir.map(str::to_lowercase).as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => return Err(format!("Invalid sort direction: {}", other)),
+        };
+        let sort = UserSort {
+            column: sort_by.unwrap_or("username").to_string(),
+            descending,
+        };
+        validate_sort(&sort)?;
+        Ok(sort)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsersPage {
+    pub users: Vec<User>,
+    pub offset: u32,
+    pub limit: u32,
+    /// Users matching the filters across all pages
+    pub total: i64,
+}
+
+pub fn validate_sort(sort: &UserSort) -> Result<(), String> {
+    if !SORTABLE_COLUMNS.contains(&sort.column.as_str()) {
+        return Err(format!("Cannot sort by column: {}", sort.column));
+    }
+    Ok(())
+}
+
+/// ` WHERE ...` (empty without filters) and its `?N` parameters
+fn where_clause(filters: &UserFilters) -> (String, Vec<String>) {
+    let mut conditions = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+
+    if let Some(search) = filters
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        values.push(format!("%{}%", search));
+        let n = values.len();
+        conditions.push(format!(
+            "(username LIKE ?{n} OR full_name LIKE ?{n} OR full_name_en LIKE ?{n} OR email LIKE ?{n} OR service_number LIKE ?{n})"
+        ));
+    }
+    if let Some(ref role) = filters.role {
+        values.push(role.clone());
+        conditions.push(format!("role = ?{}", values.len()));
+    }
+    if let Some(ref rank) = filters.rank {
+        values.push(rank.clone());
+        conditions.push(format!("rank = ?{}", values.len()));
+    }
+    if let Some(is_active) = filters.is_active {
+        conditions.push(format!("is_active = {}", i32::from(is_active)));
+    }
+
+    if conditions.is_empty() {
+        (String::new(), values)
+    } else {
+        (format!(" WHERE {}", conditions.join(" AND ")), values)
+    }
+}
+
+pub fn query_users_with_conn(
+    conn: &Connection,
+    filters: &UserFilters,
+) -> Result<Vec<User>, String> {
+    query_users_sorted_with_conn(conn, filters, &UserSort::default())
+}
+
+pub fn query_users_sorted_with_conn(
+    conn: &Connection,
+    filters: &UserFilters,
+    sort: &UserSort,
+) -> Result<Vec<User>, String> {
+    select_users(conn, filters, sort, None)
+}
+
+/// `page` is (limit, offset)
+fn select_users(
+    conn: &Connection,
+    filters: &UserFilters,
+    sort: &UserSort,
+    page: Option<(u32, u32)>,
+) -> Result<Vec<User>, String> {
+    validate_sort(sort)?;
+    let (where_clause, values) = where_clause(filters);
+    let limit_clause = match page {
+        Some((limit, offset)) => format!(" LIMIT {} OFFSET {}", limit, offset),
+        None => String::new(),
+    };
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM users{} ORDER BY {} {}, id{}",
+            USER_SELECT_COLUMNS,
+            where_clause,
+            sort.column,
+            if sort.descending { "DESC" } else { "ASC" },
+            limit_clause
+        ))
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let rows = stmt
+        .query_map(params_from_iter(values.iter()), map_user_row)
+        .map_err(|e| format!("Failed to query users: {}", e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse user: {}", e))
+}
+
+pub fn query_users(filters: &UserFilters) -> Result<Vec<User>, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    query_users_with_conn(&conn, filters)
+}
+
+pub fn count_users_with_conn(conn: &Connection, filters: &UserFilters) -> Result<i64, String> {
+    let (where_clause, values) = where_clause(filters);
+    conn.query_row(
+        &format!("SELECT COUNT(*) FROM users{}", where_clause),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to count users: {}", e))
+}
+
+/// `limit` is capped at `MAX_PAGE_SIZE`
+pub fn query_users_page_with_conn(
+    conn: &Connection,
+    filters: &UserFilters,
+    sort: &UserSort,
+    offset: u32,
+    limit: u32,
+) -> Result<UsersPage, String> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    Ok(UsersPage {
+        users: select_users(conn, filters, sort, Some((limit, offset)))?,
+        offset,
+        limit,
+        total: count_users_with_conn(conn, filters)?,
+    })
+}
+
+pub fn query_users_page(
+    filters: &UserFilters,
+    sort: &UserSort,
+    offset: u32,
+    limit: u32,
+) -> Result<UsersPage, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    query_users_page_with_conn(&conn, filters, sort, offset, limit)
+}
+
+pub fn count_users(filters: &UserFilters) -> Result<i64, String> {
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    count_users_with_conn(&conn, filters)
+}
+
+fn column_value(user: &User, column: &str) -> String {
+    match column {
+        "id" => user.id.map(|id| id.to_string()).unwrap_or_default(),
+        "username" => user.username.clone(),
+        "email" => user.email.clone(),
+        "full_name" => user.full_name.clone(),
+        "full_name_en" => user.full_name_en.clone().unwrap_or_default(),
+        "rank" => user.rank.clone().unwrap_or_default(),
+        "role" => user.role.clone(),
+        "is_active" => user.is_active.to_string(),
+        "service_number" => user.service_number.clone().unwrap_or_default(),
+        "created_at" => user.created_at.clone().unwrap_or_default(),
+        "updated_at" => user.updated_at.clone().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Tabs and line breaks would split cells; a leading formula character would
+/// make the spreadsheet evaluate the cell
+fn tsv_cell(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| {
+            if matches!(c, '\t' | '\r' | '\n') {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    if cleaned.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", cleaned)
+    } else {
+        cleaned
+    }
+}
+
+/// Header row plus one row per user; `None` selects the default columns
+pub fn users_to_tsv(users: &[User], columns: Option<&[String]>) -> Result<String, String> {
+    let columns: Vec<&str> = match columns {
+        Some(columns) if !columns.is_empty() => columns.iter().map(String::as_str).collect(),
+        _ => DEFAULT_COLUMNS.to_vec(),
+    };
+    if let Some(unknown) = columns.iter().find(|c| !EXPORTABLE_COLUMNS.contains(c)) {
+        return Err(format!("Column cannot be exported: {}", unknown));
+    }
+
+    let mut lines = Vec::with_capacity(users.len() + 1);
+    lines.push(columns.join("\t"));
+    for user in users {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|column| tsv_cell(&column_value(user, column)))
+            .collect();
+        lines.push(cells.join("\t"));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+
+    fn conn_with_users() -> Connection {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (username, email, password_hash, full_name, rank, role, is_active) VALUES
+                ('alpha', 'alpha@test.com', 'h', 'Alpha One', 'LT', 'editor', 1),
+                ('bravo', 'bravo@test.com', 'h', 'Bravo\tTwo', 'CDR', 'visitor', 1),
+                ('charlie', 'charlie@test.com', 'h', '=Charlie', 'LT', 'visitor', 0);",
+        )
+        .expect("users should insert");
+        conn
+    }
+
+    #[test]
+    fn test_filters_combine() {
+        let conn = conn_with_users();
+
+        let lieutenants = query_users_with_conn(
+            &conn,
+            &UserFilters {
+                rank: Some("LT".to_string()),
+                is_active: Some(true),
+                ..UserFilters::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(lieutenants.len(), 1);
+        assert_eq!(lieutenants[0].username, "alpha");
+
+        let searched = query_users_with_conn(
+            &conn,
+            &UserFilters {
+                search: Some("bravo".to_string()),
+                role: Some("visitor".to_string()),
+                ..UserFilters::default()
+            },
+        )
+        .expect("query should succeed");
+        assert_eq!(searched.len(), 1);
+    }
+
+    #[test]
+    fn test_pages_follow_sort_and_filters() {
+        let conn = conn_with_users();
+        let sort = UserSort::from_args(Some("full_name"), Some("DESC")).expect("sort is valid");
+
+        let first = query_users_page_with_conn(&conn, &UserFilters::default(), &sort, 0, 2)
+            .expect("page should load");
+        assert_eq!(first.total, 3);
+        assert_eq!(
+            first
+                .users
+                .iter()
+                .map(|u| u.username.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bravo", "alpha"]
+        );
+        let second = query_users_page_with_conn(&conn, &UserFilters::default(), &sort, 2, 2)
+            .expect("page should load");
+        assert_eq!(second.users.len(), 1);
+        assert_eq!(second.users[0].username, "charlie");
+
+        let visitors = UserFilters {
+            role: Some("visitor".to_string()),
+            ..UserFilters::default()
+        };
+        assert_eq!(count_users_with_conn(&conn, &visitors), Ok(2));
+        assert!(UserSort::from_args(Some("password_hash"), None).is_err());
+        assert!(UserSort::from_args(None, Some("sideways")).is_err());
+    }
+
+    #[test]
+    fn test_tsv_escapes_cells() {
+        let conn = conn_with_users();
+        let users = query_users_with_conn(&conn, &UserFilters::default()).unwrap();
+        let columns = vec!["username".to_string(), "full_name".to_string()];
+
+        let tsv = users_to_tsv(&users, Some(&columns)).expect("tsv should render");
+        let lines: Vec<&str> = tsv.lines().collect();
+
+        assert_eq!(lines[0], "username\tfull_name");
+        assert_eq!(lines[2], "bravo\tBravo Two");
+        assert_eq!(lines[3], "charlie\t'=Charlie");
+    }
+
+    #[test]
+    fn test_password_hash_cannot_be_exported() {
+        let columns = vec!["password_hash".to_string()];
+        assert!(users_to_tsv(&[], Some(&columns)).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+//! Restore a single user account from a backup
+//!
+//! Recovers one accidentally deleted or damaged account without rolling the
+//! whole database back. Works with JSON backups (row only) and hybrid zip
+//! backups (row plus avatar file).
+
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::backup_manager;
+use crate::backup_volumes;
+use crate::database::get_connection_safe;
+use crate::database_backup::DatabaseBackup;
+use crate::file_manager::FileManager;
+use crate::hybrid_backup;
+use crate::logger;
+use crate::media_maintenance::normalize_media_path;
+use crate::safe_path::MediaRoot;
+use crate::temp_space::TempSpace;
+use crate::validation;
+
+type UserRow = Map<String, Value>;
+
+/// Avatar file read from a backup: where it goes and its bytes
+type PendingAvatar = (PathBuf, Vec<u8>);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RestoredUser {
+    pub user_id: i32,
+    pub username: String,
+    /// "inserted" when the account was recreated, "updated" when it was overwritten
+    pub action: String,
+    pub avatar_restored: bool,
+}
+
+pub fn restore_user_from_backup(filename: &str, username: &str) -> Result<RestoredUser, String> {
+    validation::file_name("filename", filename)?;
+
+    let backup_path = backup_manager::get_backup_directory()?.join(filename);
+    if !backup_path.exists() {
+        return Err(format!("Backup file not found: {}", filename));
+    }
+
+    let file_manager = FileManager::get_instance()?;
+    let media_dir = file_manager.get_media_directory();
+
+    let extension = if backup_volumes::is_first_volume(&backup_path) {
+        Some("zip")
+    } else {
+        backup_path.extension().and_then(|s| s.to_str())
+    };
+    let (mut row, avatar) = match extension {
+        Some("json") => (read_user_from_json_backup(&backup_path, username)?, None),
+        Some("zip") => read_user_from_hybrid_backup(&backup_path, username, media_dir)?,
+        _ => return Err("Only JSON and hybrid (.zip) backups are supported".to_string()),
+    };
+
+    // A JSON backup carries no avatar file - keep the reference only if the file is still on disk
+    if avatar.is_none() {
+        let avatar_missing = row
+            .get("avatar_path")
+            .and_then(|v| v.as_str())
+            .map(|p| !media_dir.join(normalize_media_path(p)).exists())
+            .unwrap_or(false);
+        if avatar_missing {
+            for column in [
+                "avatar_path",
+                "avatar_updated_at",
+                "avatar_mime",
+                "avatar_size",
+                "avatar_format",
+                "avatar_width",
+                "avatar_height",
+            ] {
+                row.insert(column.to_string(), Value::Null);
+            }
+        }
+    }
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let (user_id, inserted) = upsert_user_row_with_conn(&conn, &row)?;
+
+    // Only now: a failed upsert must not have replaced a live user's photo
+    let avatar_restored = match avatar {
+        Some((target, data)) => match write_avatar(&target, &data) {
+            Ok(()) => true,
+            Err(e) => {
+                logger::warn(format!("Restored '{}' without the avatar: {}", username, e));
+                false
+            }
+        },
+        None => false,
+    };
+
+    logger::info(format!(
+        "Restored user '{}' from backup {}",
+        username, filename
+    ));
+
+    Ok(RestoredUser {
+        user_id,
+        username: username.to_string(),
+        action: if inserted { "inserted" } else { "updated" }.to_string(),
+        avatar_restored,
+    })
+}
+
+fn read_user_from_json_backup(path: &Path, username: &str) -> Result<UserRow, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read backup file: {}", e))?;
+    let backup: DatabaseBackup = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse backup file: {}", e))?;
+
+    find_user_in_backup(&backup, username)
+        .ok_or_else(|| format!("User '{}' not found in backup", username))
+}
+
+pub fn find_user_in_backup(backup: &DatabaseBackup, username: &str) -> Option<UserRow> {
+    backup
+        .tables
+        .iter()
+        .find(|t| t.name == "users")?
+        .data
+        .iter()
+        .filter_map(|row| row.as_object())
+        .find(|row| row.get("username").and_then(|v| v.as_str()) == Some(username))
+        .cloned()
+}
+
+/// Returns the user row and, when the backup has it, the avatar file to
+/// write into `media_dir` once the row is restored
+fn read_user_from_hybrid_backup(
+    path: &Path,
+    username: &str,
+    media_dir: &Path,
+) -> Result<(UserRow, Option<PendingAvatar>), String> {
+    let mut archive = hybrid_backup::open_backup_archive(path)?;
+
+    // SQLite needs a real file; stage it in temp space, which the next start
+    // clears if this process dies first, not among the backups
+    let temp = TempSpace::create("user-restore")?;
+    let staged_db = temp.path().join("database.db");
+    hybrid_backup::extract_backup_database(path, &staged_db)?;
+
+    let row = Connection::open_with_flags(&staged_db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open backup database: {}", e))
+        .and_then(|conn| read_user_row_with_conn(&conn, username));
+    drop(temp);
+    let row = row?.ok_or_else(|| format!("User '{}' not found in backup", username))?;
+
+    let avatar_path = match row.get("avatar_path").and_then(|v| v.as_str()) {
+        Some(p) if !p.is_empty() => normalize_media_path(p),
+        _ => return Ok((row, None)),
+    };
+    let target = match MediaRoot::new(media_dir)?.resolve(&avatar_path) {
+        Ok(target) => target.into_path_buf(),
+        Err(e) => {
+            logger::warn(format!("Not restoring avatar of '{}': {}", username, e));
+            return Ok((row, None));
+        }
+    };
+
+    // Entry names were written with the platform separator - compare normalized
+    let wanted = format!("media/{}", avatar_path);
+    let entry_name = archive
+        .file_names()
+        .find(|name| normalize_media_path(name) == wanted)
+        .map(|name| name.to_string());
+
+    let entry_name = match entry_name {
+        Some(name) => name,
+        None => {
+            logger::warn(format!(
+                "Avatar file {} not found in backup, restoring user without it",
+                avatar_path
+            ));
+            return Ok((row, None));
+        }
+    };
+
+    let mut data = Vec::new();
+    archive
+        .by_name(&entry_name)
+        .map_err(|e| format!("Failed to read avatar from backup: {}", e))?
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to extract avatar: {}", e))?;
+
+    Ok((row, Some((target, data))))
+}
+
+fn write_avatar(target: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create avatar directory: {}", e))?;
+    }
+    fs::write(target, data).map_err(|e| format!("Failed to write avatar file: {}", e))
+}
+
+/// Read one user as a column -> JSON value map
+pub fn read_user_row_with_conn(
+    conn: &Connection,
+    username: &str,
+) -> Result<Option<UserRow>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM users WHERE username = ?")
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|n| n.to_string()).collect();
+
+    let result = stmt.query_row(params![username], |row| {
+        let mut map = Map::new();
+        for (i, name) in column_names.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+                ValueRef::Integer(v) => Value::from(v),
+                ValueRef::Real(v) => Value::from(v),
+                ValueRef::Text(v) => Value::from(String::from_utf8_lossy(v).to_string()),
+            };
+            map.insert(name.clone(), value);
+        }
+        Ok(map)
+    });
+
+    match result {
+        Ok(map) => Ok(Some(map)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(format!("Failed to read user: {}", e)),
+    }
+}
+
+fn json_to_sql(value: &Value) -> Box<dyn rusqlite::ToSql> {
+    match value {
+        Value::String(s) => Box::new(s.clone()),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Box::new(i),
+            None => Box::new(n.as_f64()),
+        },
+        Value::Bool(b) => Box::new(*b),
+        Value::Null => Box::new(None::<String>),
+        other => Box::new(other.to_string()),
+    }
+}
+
+/// Insert or update the user by username, writing only columns the live schema has
+/// Returns the live user id and whether a new row was inserted
+pub fn upsert_user_row_with_conn(conn: &Connection, row: &UserRow) -> Result<(i32, bool), String> {
+    let username = row
+        .get("username")
+        .and_then(|v| v.as_str())
+        .ok_or("Backup row has no username")?;
+
+    let mut stmt = conn
+        .prepare("PRAGMA table_info(users)")
+        .map_err(|e| format!("Failed to prepare pragma statement: {}", e))?;
+    let live_columns: HashSet<String> = stmt
+        .query_map([], |r| r.get::<_, String>(1))
+        .map_err(|e| format!("Failed to query table info: {}", e))?
+        .flatten()
+        .collect();
+
+    let existing_id: Option<i32> = conn
+        .query_row(
+            "SELECT id FROM users WHERE username = ?",
+            params![username],
+            |r| r.get(0),
+        )
+        .ok();
+
+    // Keep the original id only when it is free, so references elsewhere line up again
+    let backup_id = row.get("id").and_then(|v| v.as_i64());
+    let id_free = match backup_id {
+        Some(id) => conn
+            .query_row(
+                "SELECT COUNT(*) FROM users WHERE id = ?",
+                params![id],
+                |r| r.get::<_, i64>(0),
+            )
+            .map(|count| count == 0)
+            .unwrap_or(false),
+        None => false,
+    };
+
+    let columns: Vec<&String> = row
+        .keys()
+        .filter(|k| live_columns.contains(*k))
+        // A restored row counts as a new edit, not as the backed-up version
+        .filter(|k| k.as_str() != "row_version")
+        .filter(|k| k.as_str() != "id" || (existing_id.is_none() && id_free))
+        .collect();
+    let values: Vec<Box<dyn rusqlite::ToSql>> =
+        columns.iter().map(|c| json_to_sql(&row[*c])).collect();
+
+    match existing_id {
+        Some(id) => {
+            let assignments = columns
+                .iter()
+                .map(|c| format!("{} = ?", c))
+                .chain(std::iter::once("row_version = row_version + 1".to_string()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut value_refs: Vec<&dyn rusqlite::ToSql> =
+                values.iter().map(|v| v.as_ref()).collect();
+            value_refs.push(&id);
+            conn.execute(
+                &format!("UPDATE users SET {} WHERE id = ?", assignments),
+                value_refs.as_slice(),
+            )
+            .map_err(|e| format!("Failed to update user: {}", e))?;
+            Ok((id, false))
+        }
+        None => {
+            let column_list = columns
+                .iter()
+                .map(|c| c.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let placeholders = vec!["?"; columns.len()].join(", ");
+            let value_refs: Vec<&dyn rusqlite::ToSql> = values.iter().map(|v| v.as_ref()).collect();
+            conn.execute(
+                &format!(
+                    "INSERT INTO users ({}) VALUES ({})",
+                    column_list, placeholders
+                ),
+                value_refs.as_slice(),
+            )
+            .map_err(|e| format!("Failed to insert user: {}", e))?;
+            Ok((conn.last_insert_rowid() as i32, true))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_backup::{BackupMetadata, TableBackup};
+    use crate::test_helpers::helpers::create_app_db;
+    use serde_json::json;
+
+    fn sample_row() -> UserRow {
+        json!({
+            "id": 7,
+            "username": "jdoe",
+            "email": "jdoe@test.com",
+            "password_hash": "hash",
+            "full_name": "John Doe",
+            "rank": "ร.ท.",
+            "role": "editor",
+            "is_active": 1,
+            "avatar_path": null,
+            "legacy_column": "ignored"
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    #[test]
+    fn test_restore_deleted_user_keeps_original_id() {
+        let conn = create_app_db();
+
+        let (id, inserted) =
+            upsert_user_row_with_conn(&conn, &sample_row()).expect("upsert should succeed");
+
+        assert!(inserted);
+        assert_eq!(id, 7);
+        let restored = read_user_row_with_conn(&conn, "jdoe")
+            .expect("read should succeed")
+            .expect("user should exist");
+        assert_eq!(restored["full_name"], json!("John Doe"));
+    }
+
+    #[test]
+    fn test_restore_existing_user_updates_in_place() {
+        let conn = create_app_db();
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, full_name, role) VALUES (3, 'jdoe', 'old@test.com', 'h', 'Old Name', 'visitor')",
+            [],
+        )
+        .expect("insert should succeed");
+
+        let (id, inserted) =
+            upsert_user_row_with_conn(&conn, &sample_row()).expect("upsert should succeed");
+
+        assert!(!inserted);
+        assert_eq!(id, 3);
+        let (email, role): (String, String) = conn
+            .query_row("SELECT email, role FROM users WHERE id = 3", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .expect("user should exist");
+        assert_eq!(email, "jdoe@test.com");
+        assert_eq!(role, "editor");
+    }
+
+    #[test]
+    fn test_find_user_in_json_backup() {
+        let backup = DatabaseBackup {
+            timestamp: 0,
+            version: "1.0".to_string(),
+            tables: vec![TableBackup {
+                name: "users".to_string(),
+                schema: String::new(),
+                data: vec![Value::Object(sample_row())],
+                row_count: 1,
+            }],
+            metadata: BackupMetadata {
+                created_at: String::new(),
+                total_tables: 1,
+                total_rows: 1,
+                user_count: 1,
+                avatar_count: 0,
+                high_ranking_count: 0,
+                file_size: 0,
+            },
+            changed_since: None,
+        };
+
+        assert!(find_user_in_backup(&backup, "jdoe").is_some());
+        assert!(find_user_in_backup(&backup, "missing").is_none());
+    }
+}
@@ -0,0 +1,372 @@
+//! Typed validation of command input with field-level error details
+//!
+//! Each validator turns a raw value into a checked one or a `FieldError`
+//! naming the offending field. Commands with several inputs collect them in a
+//! `Validator` so the UI gets every problem at once. Failures leave the
+//! command as a VALIDATION_FAILED coded error whose body is the JSON list of
+//! field errors, e.g.
+//! `VALIDATION_FAILED: [{"field":"email","message":"Email address is invalid"}]`.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::avatar_policy;
+use crate::error_codes::{self, VALIDATION_FAILED};
+use crate::permissions::Role;
+use crate::settings::AvatarPolicy;
+
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 50;
+pub const MAX_EMAIL_LENGTH: usize = 254;
+pub const MAX_TEXT_LENGTH: usize = 200;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Coded error string carrying the field errors as JSON
+pub fn to_error_string(errors: &[FieldError]) -> String {
+    let details = serde_json::to_string(errors).unwrap_or_else(|_| "[]".to_string());
+    error_codes::with_code(VALIDATION_FAILED, &details)
+}
+
+/// Lets single-field checks use `?` in functions returning `Result<_, String>`
+impl From<FieldError> for String {
+    fn from(error: FieldError) -> String {
+        to_error_string(&[error])
+    }
+}
+
+/// Collects the results of several field checks
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    /// Keep the value of a passing check, remember the error of a failing one
+    pub fn check<T>(&mut self, result: Result<T, FieldError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(to_error_string(&self.errors))
+        }
+    }
+}
+
+/// Trimmed, non-empty text of bounded length
+pub fn required_text(field: &str, value: &str, max_length: usize) -> Result<String, FieldError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(FieldError::new(field, "Value is required"));
+    }
+    if value.chars().count() > max_length {
+        return Err(FieldError::new(
+            field,
+            format!("Must be at most {} characters", max_length),
+        ));
+    }
+    Ok(value.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Username(String);
+
+impl Username {
+    /// Thai usernames are allowed; whitespace and control characters are not
+    pub fn parse(field: &str, value: &str) -> Result<Self, FieldError> {
+        let value = value.trim();
+        let length = value.chars().count();
+        if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+            return Err(FieldError::new(
+                field,
+                format!(
+                    "Username must be {} to {} characters",
+                    MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+                ),
+            ));
+        }
+        if value
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '@')
+        {
+            return Err(FieldError::new(
+                field,
+                "Username cannot contain spaces or '@'",
+            ));
+        }
+        Ok(Username(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email(String);
+
+impl Email {
+    /// Shape check only (one '@', a dotted domain); stored lowercase
+    pub fn parse(field: &str, value: &str) -> Result<Self, FieldError> {
+        let value = value.trim().to_lowercase();
+        let invalid = || FieldError::new(field, "Email address is invalid");
+
+        if value.is_empty() || value.len() > MAX_EMAIL_LENGTH {
+            return Err(invalid());
+        }
+        if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(invalid());
+        }
+        let (local, domain) = value.split_once('@').ok_or_else(invalid)?;
+        let domain_ok = !domain.contains('@')
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.');
+        if local.is_empty() || !domain_ok {
+            return Err(invalid());
+        }
+        Ok(Email(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Account fields shared by the create and update user commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFields {
+    pub username: Username,
+    pub email: Email,
+    pub full_name: String,
+}
+
+impl UserFields {
+    /// Every invalid field is reported, not just the first; a password is
+    /// only checked when given (updates carry an existing hash instead)
+    pub fn parse(
+        username: &str,
+        email: &str,
+        full_name: &str,
+        password: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut validator = Validator::new();
+        let username = validator.check(Username::parse("username", username));
+        let email = validator.check(Email::parse("email", email));
+        let full_name = validator.check(required_text("full_name", full_name, MAX_TEXT_LENGTH));
+        if let Some(password) = password {
+            if password.is_empty() {
+                validator.check::<()>(Err(FieldError::new("password", "Value is required")));
+            }
+        }
+        validator.finish()?;
+
+        match (username, email, full_name) {
+            (Some(username), Some(email), Some(full_name)) => Ok(UserFields {
+                username,
+                email,
+                full_name,
+            }),
+            _ => unreachable!("validator reported no errors"),
+        }
+    }
+}
+
+/// Uploaded image bytes that passed the avatar policy's size and format checks
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePayload<'a> {
+    pub data: &'a [u8],
+    pub mime_type: String,
+}
+
+impl<'a> ImagePayload<'a> {
+    pub fn parse(
+        field: &str,
+        data: &'a [u8],
+        mime_type: &str,
+        policy: &AvatarPolicy,
+    ) -> Result<Self, FieldError> {
+        let mime_type = avatar_policy::normalize_mime(mime_type);
+        if !mime_type.starts_with("image/") {
+            return Err(FieldError::new(
+                field,
+                format!("Not an image type: {}", mime_type),
+            ));
+        }
+        avatar_policy::check_upload(policy, data.len() as u64, &mime_type)
+            .map_err(|message| FieldError::new(field, message))?;
+        Ok(ImagePayload { data, mime_type })
+    }
+}
+
+/// One of the roles `permissions` knows; unlike `Role::parse` nothing else
+/// is mapped to a visitor, so no unknown name gets stored
+pub fn role(field: &str, value: &str) -> Result<Role, FieldError> {
+    [Role::Visitor, Role::Editor, Role::Admin]
+        .into_iter()
+        .find(|role| role.as_str() == value.trim().to_lowercase())
+        .ok_or_else(|| FieldError::new(field, format!("Unknown role: {}", value)))
+}
+
+/// A single path component: no separators, no "..", not empty
+pub fn file_name(field: &str, value: &str) -> Result<String, FieldError> {
+    if value.is_empty() || value.contains("..") || value.contains(['/', '\\']) {
+        return Err(FieldError::new(
+            field,
+            format!("Invalid file name: {}", value),
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// An absolute path without ".." components
+pub fn absolute_path(field: &str, value: &str) -> Result<PathBuf, FieldError> {
+    let value = value.trim();
+    let path = Path::new(value);
+    if value.is_empty() || !path.is_absolute() {
+        return Err(FieldError::new(
+            field,
+            format!("Path must be absolute: {}", value),
+        ));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(FieldError::new(
+            field,
+            format!("Path cannot contain '..': {}", value),
+        ));
+    }
+    Ok(path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_field_errors(error: &str) -> Option<Vec<FieldError>> {
+        let prefix = format!("{}: ", VALIDATION_FAILED);
+        let start = error.find(&prefix)? + prefix.len();
+        serde_json::from_str(&error[start..]).ok()
+    }
+
+    #[test]
+    fn test_username() {
+        assert_eq!(
+            Username::parse("username", " admin ").unwrap().as_str(),
+            "admin"
+        );
+        assert!(Username::parse("username", "สมชาย").is_ok());
+        assert!(Username::parse("username", "ab").is_err());
+        assert!(Username::parse("username", "two words").is_err());
+    }
+
+    #[test]
+    fn test_email() {
+        assert_eq!(
+            Email::parse("email", " Admin@Navy.mi.th ")
+                .unwrap()
+                .as_str(),
+            "admin@navy.mi.th"
+        );
+        for bad in [
+            "",
+            "admin",
+            "admin@",
+            "@navy.mi.th",
+            "a@b@c.th",
+            "a@localhost",
+            "a b@c.th",
+        ] {
+            assert!(Email::parse("email", bad).is_err(), "{} should fail", bad);
+        }
+    }
+
+    #[test]
+    fn test_image_payload() {
+        let policy = AvatarPolicy::default();
+        assert!(ImagePayload::parse("avatar", b"data", "image/jpg", &policy).is_ok());
+        assert!(ImagePayload::parse("avatar", b"", "image/png", &policy).is_err());
+        assert!(ImagePayload::parse("avatar", b"data", "text/html", &policy).is_err());
+    }
+
+    #[test]
+    fn test_paths() {
+        assert!(file_name("filename", "hybrid_backup_1.zip").is_ok());
+        for bad in ["", "../db", "a/b", "a\\b"] {
+            assert!(file_name("filename", bad).is_err());
+        }
+        assert!(absolute_path("destination", "relative/dir").is_err());
+        let root = std::env::temp_dir();
+        assert!(absolute_path("destination", &root.to_string_lossy()).is_ok());
+        assert!(
+            absolute_path("destination", &root.join("..").join("x").to_string_lossy()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_validator_collects_all_fields() {
+        let mut validator = Validator::new();
+        let username = validator.check(Username::parse("username", "ok_user"));
+        validator.check(Email::parse("email", "broken"));
+        validator.check(required_text("full_name", "  ", MAX_TEXT_LENGTH));
+
+        assert!(username.is_some());
+        let error = validator.finish().unwrap_err();
+        assert_eq!(error_codes::find_code(&error), Some(VALIDATION_FAILED));
+        let fields: Vec<String> = parse_field_errors(&error)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["email", "full_name"]);
+    }
+
+    #[test]
+    fn test_role() {
+        assert_eq!(role("role", " Editor "), Ok(Role::Editor));
+        assert_eq!(role("role", "visitor"), Ok(Role::Visitor));
+        assert!(role("role", "superuser").is_err());
+        assert!(role("role", "").is_err());
+    }
+
+    #[test]
+    fn test_user_fields() {
+        let fields = UserFields::parse("admin", "Admin@navy.mi.th", " Admin ", Some("secret"))
+            .expect("valid fields should parse");
+        assert_eq!(fields.email.as_str(), "admin@navy.mi.th");
+        assert_eq!(fields.full_name, "Admin");
+
+        let error = UserFields::parse("a", "admin@navy.mi.th", "Admin", Some("")).unwrap_err();
+        let fields: Vec<String> = parse_field_errors(&error)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["username", "password"]);
+    }
+}
@@ -0,0 +1,170 @@
+//! Background warm-up after a restore or import
+//!
+//! Right after recovery every photo is cold on disk (often a USB stick or a
+//! network share), the read cache is empty and reference files restored
+//! without their `.preview.png` would render one on first view. Restore
+//! commands queue `queue_warm_up` on success: it reads each avatar file once,
+//! reloads the officer list into the read cache and builds missing
+//! attachment previews, as a cancellable `warm-up` job.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::content_database::previews;
+use crate::database::{self, get_connection_safe};
+use crate::jobs;
+use crate::logger;
+use crate::progress::{ProgressReporter, ProgressSink};
+use crate::safe_path::MediaRoot;
+use crate::storage_paths;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct WarmUpReport {
+    pub avatars_read: u64,
+    pub avatar_bytes: u64,
+    /// Referenced by a user or officer but not on disk
+    pub avatars_missing: u64,
+    pub previews_generated: u64,
+    pub preview_failures: u64,
+}
+
+/// Avatar paths of users and officers, each once
+fn avatar_paths_with_conn(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT avatar_path FROM users WHERE avatar_path IS NOT NULL AND avatar_path != ''
+             UNION
+             SELECT avatar_path FROM high_ranking_officers
+             WHERE avatar_path IS NOT NULL AND avatar_path != ''",
+        )
+        .map_err(|e| format!("Failed to prepare statement: {}", e))?;
+    let paths = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| format!("Failed to query avatar paths: {}", e))?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| format!("Failed to read avatar path: {}", e))?;
+    Ok(paths)
+}
+
+/// Read every avatar once so the OS has it cached
+fn warm_avatars(
+    paths: &[String],
+    media_root: &MediaRoot,
+    progress: &ProgressReporter,
+    report: &mut WarmUpReport,
+) -> Result<(), String> {
+    for (index, path) in paths.iter().enumerate() {
+        progress.check_cancelled()?;
+        match media_root.resolve(path).map(|p| fs::read(p.full_path())) {
+            Ok(Ok(data)) => {
+                report.avatars_read += 1;
+                report.avatar_bytes += data.len() as u64;
+            }
+            _ => report.avatars_missing += 1,
+        }
+        progress.report(Some("avatars"), index as u64 + 1, Some(paths.len() as u64));
+    }
+    Ok(())
+}
+
+fn generate_previews(
+    files: &[PathBuf],
+    progress: &ProgressReporter,
+    report: &mut WarmUpReport,
+) -> Result<(), String> {
+    for (index, file) in files.iter().enumerate() {
+        progress.check_cancelled()?;
+        match previews::generate_preview(file) {
+            Ok(Some(_)) => report.previews_generated += 1,
+            Ok(None) => {}
+            Err(e) => {
+                report.preview_failures += 1;
+                logger::warn(format!(
+                    "Failed to generate preview for {}: {}",
+                    file.display(),
+                    e
+                ));
+            }
+        }
+        progress.report(Some("previews"), index as u64 + 1, Some(files.len() as u64));
+    }
+    Ok(())
+}
+
+pub fn warm_up_with_progress(progress: &ProgressReporter) -> Result<WarmUpReport, String> {
+    let mut report = WarmUpReport::default();
+
+    let conn =
+        get_connection_safe().map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let paths = avatar_paths_with_conn(&conn)?;
+    drop(conn);
+    let media_dir = storage_paths::get_media_dir()?;
+    if media_dir.is_dir() {
+        let media_root = MediaRoot::new(&media_dir)?;
+        warm_avatars(&paths, &media_root, progress, &mut report)?;
+    } else {
+        report.avatars_missing = paths.len() as u64;
+    }
+
+    database::get_all_high_ranking_officers()?;
+
+    // The content database is optional; without it there is nothing to preview
+    match previews::files_missing_preview() {
+        Ok(files) => generate_previews(&files, progress, &mut report)?,
+        Err(e) => logger::warn(format!("Skipping attachment previews: {}", e)),
+    }
+
+    progress.finish(report.avatars_read + report.previews_generated);
+    Ok(report)
+}
+
+/// Start the warm-up on its own thread; the restore that queued it has
+/// already returned
+pub fn queue_warm_up(sink: ProgressSink) {
+    std::thread::spawn(move || {
+        let job_id = jobs::new_job_id("warm-up");
+        match jobs::run_job(&job_id, "warm-up", None, sink, warm_up_with_progress) {
+            Ok(report) => logger::info(format!(
+                "Warm-up finished: {} avatars read ({} missing), {} previews generated",
+                report.avatars_read, report.avatars_missing, report.previews_generated
+            )),
+            Err(e) => logger::warn(format!("Warm-up stopped: {}", e)),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::helpers::create_app_db;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_warm_avatars_reads_each_referenced_file() {
+        let conn = create_app_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, email, password_hash, full_name, avatar_path) VALUES
+                (1, 'a', 'a@test.com', 'h', 'A', 'avatars/a.png'),
+                (2, 'b', 'b@test.com', 'h', 'B', 'avatars/a.png'),
+                (3, 'c', 'c@test.com', 'h', 'C', 'avatars/gone.png'),
+                (4, 'd', 'd@test.com', 'h', 'D', NULL);",
+        )
+        .expect("seed data should insert");
+
+        let dir = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(dir.path().join("avatars")).unwrap();
+        fs::write(dir.path().join("avatars/a.png"), b"12345").unwrap();
+        let media_root = MediaRoot::new(dir.path()).expect("root should resolve");
+
+        let paths = avatar_paths_with_conn(&conn).expect("paths should load");
+        assert_eq!(paths.len(), 2);
+        let mut report = WarmUpReport::default();
+        warm_avatars(&paths, &media_root, &ProgressReporter::noop(), &mut report)
+            .expect("warm-up should succeed");
+        assert_eq!(report.avatars_read, 1);
+        assert_eq!(report.avatar_bytes, 5);
+        assert_eq!(report.avatars_missing, 1);
+    }
+}
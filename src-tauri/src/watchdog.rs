@@ -0,0 +1,291 @@
+//! Stall watchdog for long-running jobs
+//!
+//! File IO on a network drive that disappears can block forever, and with it
+//! the invoke the UI is awaiting. `run_watched_job` runs a job on its own
+//! thread and treats every progress event as a heartbeat. When no heartbeat
+//! arrives for `STALL_TIMEOUT` the job is asked to cancel; if it has not
+//! stopped after `CANCEL_GRACE` it is abandoned: the command returns an
+//! OPERATION_TIMED_OUT error while the stuck thread is left to finish (or not)
+//! on its own, and the job stays listed as timed out.
+//!
+//! An abandoned restore can still swap the database whenever its IO comes
+//! back. Restores and imports therefore run under the exclusive operation
+//! slot (`begin_exclusive`), which the worker thread holds until it actually
+//! exits; a retry meanwhile fails with OPERATION_IN_PROGRESS.
+
+use lazy_static::lazy_static;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error_codes::{self, OPERATION_IN_PROGRESS, OPERATION_TIMED_OUT};
+use crate::jobs;
+use crate::logger;
+use crate::progress::{ProgressReporter, ProgressSink};
+
+/// Time without a progress event after which a job counts as stuck
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Time a stuck job gets to notice its cancel flag before it is abandoned
+pub const CANCEL_GRACE: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    /// Operation holding the exclusive slot
+    static ref EXCLUSIVE_OPERATION: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Hold on the exclusive operation slot; released when dropped
+pub struct ExclusiveOperation {
+    operation: String,
+}
+
+impl Drop for ExclusiveOperation {
+    fn drop(&mut self) {
+        if let Ok(mut current) = EXCLUSIVE_OPERATION.lock() {
+            if current.as_deref() == Some(self.operation.as_str()) {
+                *current = None;
+            }
+        }
+    }
+}
+
+/// Claim the exclusive slot for `operation`, failing with
+/// OPERATION_IN_PROGRESS while another restore or import (possibly an
+/// abandoned one) still runs
+pub fn begin_exclusive(operation: &str) -> Result<ExclusiveOperation, String> {
+    let mut current = EXCLUSIVE_OPERATION
+        .lock()
+        .map_err(|_| "Exclusive operation lock poisoned".to_string())?;
+    if let Some(running) = current.as_ref() {
+        let details = serde_json::json!({ "requested": operation, "running": running });
+        return Err(error_codes::with_code(
+            OPERATION_IN_PROGRESS,
+            &details.to_string(),
+        ));
+    }
+    *current = Some(operation.to_string());
+    Ok(ExclusiveOperation {
+        operation: operation.to_string(),
+    })
+}
+
+/// Run `work` as job `job_id` like `jobs::run_job`, giving up on it once it
+/// stops reporting progress
+pub fn run_watched_job<T, F>(
+    job_id: &str,
+    kind: &'static str,
+    started_by: Option<i32>,
+    sink: ProgressSink,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&ProgressReporter) -> Result<T, String> + Send + 'static,
+{
+    run_watched(job_id, kind, started_by, sink, None, work)
+}
+
+/// `run_watched_job` under the exclusive operation slot, which stays taken
+/// until the worker thread exits even if the job is abandoned
+pub fn run_exclusive_watched_job<T, F>(
+    job_id: &str,
+    kind: &'static str,
+    started_by: Option<i32>,
+    sink: ProgressSink,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&ProgressReporter) -> Result<T, String> + Send + 'static,
+{
+    let exclusive = begin_exclusive(job_id)?;
+    run_watched(job_id, kind, started_by, sink, Some(exclusive), work)
+}
+
+fn run_watched<T, F>(
+    job_id: &str,
+    kind: &'static str,
+    started_by: Option<i32>,
+    sink: ProgressSink,
+    exclusive: Option<ExclusiveOperation>,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&ProgressReporter) -> Result<T, String> + Send + 'static,
+{
+    let last_beat = Arc::new(Mutex::new(Instant::now()));
+    let beat = Arc::clone(&last_beat);
+    let sink: ProgressSink = Box::new(move |payload| {
+        if let Ok(mut last) = beat.lock() {
+            *last = Instant::now();
+        }
+        sink(payload);
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let worker_job_id = job_id.to_string();
+    std::thread::spawn(move || {
+        let result = jobs::run_job(&worker_job_id, kind, started_by, sink, work);
+        // Released only now, so an abandoned job keeps others out until it is done
+        drop(exclusive);
+        // Nobody is waiting once the job was abandoned
+        if sender.send(result).is_err() {
+            logger::warn(format!(
+                "Job {} finished after it was abandoned",
+                worker_job_id
+            ));
+        }
+    });
+
+    supervise(
+        job_id,
+        kind,
+        &receiver,
+        &last_beat,
+        STALL_TIMEOUT,
+        CANCEL_GRACE,
+    )
+}
+
+fn supervise<T>(
+    job_id: &str,
+    kind: &str,
+    receiver: &Receiver<Result<T, String>>,
+    last_beat: &Mutex<Instant>,
+    stall_timeout: Duration,
+    cancel_grace: Duration,
+) -> Result<T, String> {
+    loop {
+        let last = last_beat
+            .lock()
+            .map(|last| *last)
+            .unwrap_or_else(|_| Instant::now());
+        let Some(remaining) = (last + stall_timeout).checked_duration_since(Instant::now()) else {
+            break;
+        };
+        match receiver.recv_timeout(remaining) {
+            Ok(result) => return result,
+            // A heartbeat may have moved the deadline; the loop re-checks
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(format!("{} job {} stopped unexpectedly", kind, job_id))
+            }
+        }
+    }
+
+    logger::warn(format!(
+        "Job {} made no progress for {} seconds; cancelling it",
+        job_id,
+        stall_timeout.as_secs()
+    ));
+    jobs::mark_timed_out(job_id);
+    if let Err(e) = jobs::stop_job(job_id) {
+        logger::warn(format!("Failed to cancel stalled job {}: {}", job_id, e));
+    }
+    let abandoned = match receiver.recv_timeout(cancel_grace) {
+        // It finished anyway; a success is still a success
+        Ok(Ok(value)) => return Ok(value),
+        Ok(Err(_)) => false,
+        Err(_) => {
+            logger::error(format!(
+                "Job {} did not stop after cancellation; abandoning it",
+                job_id
+            ));
+            true
+        }
+    };
+
+    let details = serde_json::json!({
+        "job_id": job_id,
+        "kind": kind,
+        "stalled_secs": stall_timeout.as_secs(),
+        "abandoned": abandoned,
+    });
+    Err(error_codes::with_code(
+        OPERATION_TIMED_OUT,
+        &details.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_job<F>(job_id: &str, work: F) -> (Receiver<Result<u32, String>>, Arc<Mutex<Instant>>)
+    where
+        F: FnOnce(&ProgressReporter) -> Result<u32, String> + Send + 'static,
+    {
+        let last_beat = Arc::new(Mutex::new(Instant::now()));
+        let beat = Arc::clone(&last_beat);
+        let (sender, receiver) = mpsc::channel();
+        let job_id = job_id.to_string();
+        std::thread::spawn(move || {
+            let sink: ProgressSink = Box::new(move |_| *beat.lock().unwrap() = Instant::now());
+            let _ = sender.send(jobs::run_job(&job_id, "test", None, sink, work));
+        });
+        (receiver, last_beat)
+    }
+
+    #[test]
+    fn test_job_with_heartbeats_outlives_stall_timeout() {
+        let job_id = jobs::new_job_id("watchdog-ok");
+        let (receiver, last_beat) = spawn_job(&job_id, |progress| {
+            for step in 0..6 {
+                std::thread::sleep(Duration::from_millis(30));
+                progress.report(None, step, Some(6));
+            }
+            Ok(7)
+        });
+
+        let result = supervise(
+            &job_id,
+            "test",
+            &receiver,
+            &last_beat,
+            Duration::from_millis(100),
+            Duration::from_millis(50),
+        );
+        assert_eq!(result, Ok(7));
+    }
+
+    #[test]
+    fn test_stalled_job_is_cancelled_with_timeout_error() {
+        let job_id = jobs::new_job_id("watchdog-stall");
+        let (receiver, last_beat) = spawn_job(&job_id, |progress| loop {
+            std::thread::sleep(Duration::from_millis(10));
+            progress.check_cancelled()?;
+        });
+
+        let error = supervise(
+            &job_id,
+            "test",
+            &receiver,
+            &last_beat,
+            Duration::from_millis(50),
+            Duration::from_secs(2),
+        )
+        .expect_err("stalled job should time out");
+        assert_eq!(error_codes::find_code(&error), Some(OPERATION_TIMED_OUT));
+        assert!(error.contains("\"abandoned\":false"));
+
+        let job = jobs::list_jobs()
+            .unwrap()
+            .into_iter()
+            .find(|job| job.id == job_id)
+            .expect("job should be listed");
+        assert!(job.timed_out);
+    }
+
+    #[test]
+    fn test_exclusive_slot_is_held_until_released() {
+        let first = begin_exclusive("watchdog-restore").expect("slot should be free");
+        let error = begin_exclusive("watchdog-retry")
+            .err()
+            .expect("a second operation should be refused");
+        assert_eq!(error_codes::find_code(&error), Some(OPERATION_IN_PROGRESS));
+        assert!(error.contains("watchdog-restore"));
+
+        drop(first);
+        assert!(begin_exclusive("watchdog-retry").is_ok());
+    }
+}
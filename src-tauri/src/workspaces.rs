@@ -0,0 +1,184 @@
+//! Named workspaces - independent data stores (e.g. one per squadron)
+//!
+//! Each workspace has its own database, media, backups and exports, laid out
+//! by `storage_paths`. The content database (documents, questions) stays
+//! shared between workspaces.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::database;
+use crate::file_manager::FileManager;
+use crate::logger;
+use crate::settings;
+use crate::storage_paths::{self, DEFAULT_WORKSPACE, WORKSPACES_DIR_NAME};
+
+const MAX_WORKSPACE_NAME_LEN: usize = 40;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkspaceInfo {
+    pub name: String,
+    pub path: String,
+    pub is_active: bool,
+    pub has_database: bool,
+}
+
+/// Names become directory names, so keep them to a safe character set
+pub fn validate_workspace_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_WORKSPACE_NAME_LEN {
+        return Err(format!(
+            "Workspace name must be 1-{} characters",
+            MAX_WORKSPACE_NAME_LEN
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("Workspace name may only contain letters, digits, '-' and '_'".to_string());
+    }
+    Ok(())
+}
+
+/// Workspace names found under the app root, default first
+pub fn list_workspace_names_in(app_root: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(app_root.join(WORKSPACES_DIR_NAME))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| validate_workspace_name(name).is_ok() && name != DEFAULT_WORKSPACE)
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.insert(0, DEFAULT_WORKSPACE.to_string());
+    names
+}
+
+pub fn list_workspaces() -> Result<Vec<WorkspaceInfo>, String> {
+    let app_root = storage_paths::get_app_root()?;
+    let active = storage_paths::get_active_workspace()?;
+
+    Ok(list_workspace_names_in(&app_root)
+        .into_iter()
+        .map(|name| {
+            let dir = storage_paths::workspace_dir_for(&app_root, &name);
+            WorkspaceInfo {
+                is_active: name == active,
+                has_database: dir.join("database.db").exists(),
+                path: dir.to_string_lossy().to_string(),
+                name,
+            }
+        })
+        .collect())
+}
+
+pub fn create_workspace(name: &str) -> Result<WorkspaceInfo, String> {
+    validate_workspace_name(name)?;
+    if name == DEFAULT_WORKSPACE {
+        return Err("The default workspace always exists".to_string());
+    }
+
+    let app_root = storage_paths::get_app_root()?;
+    let dir = storage_paths::workspace_dir_for(&app_root, name);
+    if dir.exists() {
+        return Err(format!("Workspace '{}' already exists", name));
+    }
+
+    for sub in ["media/avatars", "media/high_ranks", "backups", "exports"] {
+        fs::create_dir_all(dir.join(sub))
+            .map_err(|e| format!("Failed to create workspace directory: {}", e))?;
+    }
+
+    logger::info(format!("Created workspace '{}'", name));
+    Ok(WorkspaceInfo {
+        name: name.to_string(),
+        path: dir.to_string_lossy().to_string(),
+        is_active: false,
+        has_database: false,
+    })
+}
+
+/// Make another workspace active and persist the choice
+/// A workspace without a usable database gets the schema and seed data
+/// (default admin, high ranking officers) so it can be logged into at once
+pub fn switch_workspace(name: &str) -> Result<WorkspaceInfo, String> {
+    validate_workspace_name(name)?;
+
+    let app_root = storage_paths::get_app_root()?;
+    let dir = storage_paths::workspace_dir_for(&app_root, name);
+    if !dir.exists() {
+        return Err(format!("Workspace '{}' does not exist", name));
+    }
+
+    settings::update_settings(|s| {
+        s.active_workspace = if name == DEFAULT_WORKSPACE {
+            None
+        } else {
+            Some(name.to_string())
+        };
+    })?;
+    storage_paths::set_cached_workspace(name)?;
+
+    // Media root moved - rebuild the file manager on next use
+    FileManager::reset_instance()?;
+
+    let prepared = if database::check_database_exists_and_valid()? {
+        database::migrate_existing_database()
+    } else {
+        logger::info(format!("Initializing database for workspace '{}'", name));
+        database::initialize_database().map(|_| ())
+    };
+    prepared.map_err(|e| {
+        format!(
+            "Switched to workspace '{}' but its database could not be prepared: {}",
+            name, e
+        )
+    })?;
+
+    logger::info(format!("Switched to workspace '{}'", name));
+    Ok(WorkspaceInfo {
+        name: name.to_string(),
+        path: dir.to_string_lossy().to_string(),
+        is_active: true,
+        has_database: dir.join("database.db").exists(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate_workspace_name() {
+        assert!(validate_workspace_name("squadron-1").is_ok());
+        assert!(validate_workspace_name("Wing_2").is_ok());
+        assert!(validate_workspace_name("").is_err());
+        assert!(validate_workspace_name("../etc").is_err());
+        assert!(validate_workspace_name("has space").is_err());
+        assert!(validate_workspace_name(&"a".repeat(41)).is_err());
+    }
+
+    #[test]
+    fn test_list_workspace_names_default_first() {
+        let root = TempDir::new().expect("temp dir should be created");
+        fs::create_dir_all(root.path().join("workspaces").join("zulu")).unwrap();
+        fs::create_dir_all(root.path().join("workspaces").join("alpha")).unwrap();
+        fs::create_dir_all(root.path().join("workspaces").join("bad name")).unwrap();
+        fs::write(root.path().join("workspaces").join("file.txt"), b"x").unwrap();
+
+        let names = list_workspace_names_in(root.path());
+
+        assert_eq!(names, vec!["default", "alpha", "zulu"]);
+    }
+
+    #[test]
+    fn test_list_without_workspaces_dir_has_only_default() {
+        let root = TempDir::new().expect("temp dir should be created");
+        assert_eq!(list_workspace_names_in(root.path()), vec!["default"]);
+    }
+}
@@ -41,7 +41,7 @@ const HeaderMenuBar: React.FC = () => {
 
   const handleZoomIn = async () => {
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri')
+      const { invoke } = await import('../services/sessionInvoke')
       await invoke('zoom_in')
     } catch (error) {
       console.error('Zoom in failed:', error)
@@ -50,7 +50,7 @@ const HeaderMenuBar: React.FC = () => {
 
   const handleZoomOut = async () => {
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri')
+      const { invoke } = await import('../services/sessionInvoke')
       await invoke('zoom_out')
     } catch (error) {
       console.error('Zoom out failed:', error)
@@ -59,7 +59,7 @@ const HeaderMenuBar: React.FC = () => {
 
   const handleZoomReset = async () => {
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri')
+      const { invoke } = await import('../services/sessionInvoke')
       await invoke('zoom_reset')
     } catch (error) {
       console.error('Zoom reset failed:', error)
@@ -76,7 +76,7 @@ const HeaderMenuBar: React.FC = () => {
 
   const handleToggleDevTools = async () => {
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri')
+      const { invoke } = await import('../services/sessionInvoke')
       try {
         await invoke('toggle_devtools')
       } catch (error) {
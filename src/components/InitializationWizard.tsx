@@ -1,8 +1,10 @@
 import { open } from '@tauri-apps/api/dialog';
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../services/sessionInvoke';
 import { AlertTriangle, Clock, Database, FileText, FolderOpen, HardDrive } from 'lucide-react';
 import React, { useEffect, useState } from 'react';
 import { useToast } from '../contexts/ToastContext';
+import type { BackupRestored } from '../types/backup';
+import { describeBackupRestored } from '../types/backup';
 import ConfirmModal from './modals/ConfirmModal';
 import Button from './ui/Button';
 import Card from './ui/Card';
@@ -88,10 +90,10 @@ const InitializationWizard: React.FC<InitializationWizardProps> = ({ onComplete,
 
     try {
       setIsImporting(true);
-      const result = await invoke<string>('import_hybrid_backup', {
+      const result = await invoke<BackupRestored>('import_hybrid_backup', {
         zipPath: backupPath
       });
-      showSuccess(`กู้คืนข้อมูลสำเร็จ!\n${result}`);
+      showSuccess(`กู้คืนข้อมูลสำเร็จ!\n${describeBackupRestored(result)}`);
       onComplete();
     } catch (err) {
       console.error('Failed to import backup:', err);
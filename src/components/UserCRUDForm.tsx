@@ -111,7 +111,7 @@ const UserCRUDForm: React.FC = () => {
         const mimeType = dataUrl.split(';')[0].split(':')[1] || 'image/jpeg'
 
         // Save avatar using Hybrid Avatar System
-        const { invoke } = await import('@tauri-apps/api/tauri')
+        const { invoke } = await import('../services/sessionInvoke')
         const result = await invoke('save_hybrid_avatar', {
           userId: user.id,
           avatarData: Array.from(fileData),
@@ -173,7 +173,7 @@ const UserCRUDForm: React.FC = () => {
 
       // Delete avatar using Hybrid Avatar System with enhanced error handling
       try {
-        const { invoke } = await import('@tauri-apps/api/tauri')
+        const { invoke } = await import('../services/sessionInvoke')
 
         // Call Tauri backend with proper error handling
         const result = await invoke<boolean>('delete_hybrid_avatar', {
@@ -93,7 +93,7 @@ const UserProfileContent: React.FC = () => {
 
     try {
       // Delete avatar using Hybrid Avatar System
-      const { invoke } = await import('@tauri-apps/api/tauri')
+      const { invoke } = await import('../services/sessionInvoke')
       await invoke('delete_hybrid_avatar', { userId: parseInt(user.id, 10) })
 
       // Update local state
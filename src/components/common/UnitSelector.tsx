@@ -1,6 +1,6 @@
 import React, { useState, useEffect } from 'react'
 import { FormSelect, FormGroup } from '../ui/Form'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 
 export interface OwnerUnit {
   unit_id: string
@@ -1,4 +1,5 @@
-import { convertFileSrc, invoke } from "@tauri-apps/api/tauri";
+import { convertFileSrc } from "@tauri-apps/api/tauri";
+import { invoke } from "../../services/sessionInvoke";
 import React, { useEffect, useState } from "react";
 import Tooltip from "../ui/Tooltip";
 
@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { BarChart3, CheckCircle2, Database, RefreshCw } from 'lucide-react';
 import React, { useEffect, useState } from 'react';
 
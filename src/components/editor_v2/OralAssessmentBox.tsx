@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/tauri";
+import { invoke } from "../../services/sessionInvoke";
 import { CheckCircle2, Edit3, FileText, Save, X } from "lucide-react";
 import React, { useEffect, useMemo, useRef, useState } from "react";
 import ConfirmModal from "../modals/ConfirmModal";
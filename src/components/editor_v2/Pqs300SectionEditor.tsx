@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { Clock, Trophy } from 'lucide-react';
 import React, { useCallback, useEffect, useState } from 'react';
 import ConfirmModal from '../modals/ConfirmModal';
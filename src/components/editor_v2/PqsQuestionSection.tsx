@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/tauri";
+import { invoke } from "../../services/sessionInvoke";
 import {
     ChevronDown,
     FileQuestion,
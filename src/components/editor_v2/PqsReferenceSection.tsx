@@ -1,6 +1,7 @@
 import { open as openDialog } from '@tauri-apps/api/dialog';
 import { join } from '@tauri-apps/api/path';
-import { convertFileSrc, invoke } from '@tauri-apps/api/tauri';
+import { convertFileSrc } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { Book, CheckCircle, Edit, FileDigit, FileText, FolderOpen, Globe, Image, Lock, Mic, Plus, Save, Search, Shield, Trash2, Video, X } from 'lucide-react';
 import React, { useCallback, useEffect, useMemo, useState } from 'react';
 import ConfirmModal from '../modals/ConfirmModal';
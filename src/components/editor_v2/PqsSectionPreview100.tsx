@@ -1,4 +1,4 @@
-﻿import { invoke } from '@tauri-apps/api/tauri';
+﻿import { invoke } from '../../services/sessionInvoke';
 import React, { useEffect, useMemo, useState } from 'react';
 import ReactMarkdown from 'react-markdown';
 import rehypeRaw from 'rehype-raw';
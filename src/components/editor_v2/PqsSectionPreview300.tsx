@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import React, { useEffect, useMemo, useState } from 'react';
 import { QuestionDetail } from '../../types/content';
 import Tooltip from '../ui/Tooltip';
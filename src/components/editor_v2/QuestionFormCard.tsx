@@ -1,5 +1,5 @@
 import { open as openDialog } from "@tauri-apps/api/dialog";
-import { invoke } from "@tauri-apps/api/tauri";
+import { invoke } from "../../services/sessionInvoke";
 import {
     CheckCircle,
     ChevronDown,
@@ -1,4 +1,4 @@
-import { invoke } from "@tauri-apps/api/tauri";
+import { invoke } from "../../services/sessionInvoke";
 import { Save, X } from "lucide-react";
 import React, { useEffect, useMemo, useState } from "react";
 import { QuestionDetail, QuestionReferenceDetail } from "../../types/content";
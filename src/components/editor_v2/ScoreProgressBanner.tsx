@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { Award, CheckCircle2, Clock } from 'lucide-react';
 import React, { useEffect, useState } from 'react';
 import { formatNumberByMode } from '../../utils/thaiNumbering';
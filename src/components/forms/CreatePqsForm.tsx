@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 import { FormInput, FormTextarea, FormSelect, FormGroup, FormRow, FormActions } from '../ui/Form'
 import Button from '../ui/Button'
 import UnitSelector from '../common/UnitSelector'
@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { AlertCircle, BookOpen, HelpCircle, Save, Table, X } from 'lucide-react'; // Added HelpCircle icon
 import React, { useEffect, useState } from 'react';
 import { QuestionDetail } from '../../types/content'; // Ensure this import exists
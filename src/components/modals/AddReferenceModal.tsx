@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { BookOpen, Edit, FileText, Lock, Plus, Search, Shield, X } from 'lucide-react';
 import React, { useEffect, useMemo, useState } from 'react';
 import { useToast } from '../../contexts/ToastContext';
@@ -115,7 +115,7 @@ const AddSectionModal: React.FC<AddSectionModalProps> = ({
     setIsSubmitting(true);
 
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri');
+      const { invoke } = await import('../../services/sessionInvoke');
 
       await invoke('create_section', {
         request: {
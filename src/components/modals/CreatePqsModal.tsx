@@ -1,6 +1,6 @@
 import React, { useState, useEffect } from 'react'
 import Modal from '../ui/Modal'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 import Button from '../ui/Button' // Assuming default export based on name
 // import Form inputs or use generic HTML/Tailwind for speed/customization?
 // Checking Form.tsx might be useful but standard inputs are fine for this specificity.
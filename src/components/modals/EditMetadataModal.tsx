@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { Settings, X } from 'lucide-react';
 import React, { useEffect, useState } from 'react';
 import { normalizePolicyGuardError } from '../../utils/policyGuards';
@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { ArrowLeft, BookOpen, ChevronDown, ChevronRight, Edit3, Eye, EyeOff, FileText, Menu, Plus, Printer, Trash2, UserCircle, Users, X } from 'lucide-react';
 import React, { useCallback, useEffect, useState } from 'react';
 import { useNavigate, useParams } from 'react-router-dom';
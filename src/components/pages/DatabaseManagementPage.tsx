@@ -1,8 +1,10 @@
 import React, { useState, useEffect } from 'react';
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import { open, save } from '@tauri-apps/api/dialog';
 import { Container, Title, Card, Button, Alert } from '../ui';
 import { Database, Download, Trash2, RefreshCw, FileText, Archive, Package, RotateCcw, FileInput, Shield } from 'lucide-react';
+import type { BackupCreated, BackupDeleted, BackupRestored } from '../../types/backup';
+import { describeBackupCreated, describeBackupDeleted, describeBackupRestored } from '../../types/backup';
 
 interface BackupFile {
   filename: string;
@@ -87,8 +89,8 @@ const DatabaseManagementPage: React.FC = () => {
   const createUniversalBackup = async () => {
     setIsLoading(true);
     try {
-      const result = await invoke<string>('create_universal_sqlite_backup');
-      showMessage('success', result);
+      const result = await invoke<BackupCreated>('create_universal_sqlite_backup');
+      showMessage('success', describeBackupCreated(result));
       loadBackups();
     } catch (error) {
       showMessage('error', `Failed to create universal backup: ${error}`);
@@ -100,8 +102,8 @@ const DatabaseManagementPage: React.FC = () => {
   const createHybridBackup = async () => {
     setIsLoading(true);
     try {
-      const result = await invoke<string>('create_hybrid_backup');
-      showMessage('success', result);
+      const result = await invoke<BackupCreated>('create_hybrid_backup');
+      showMessage('success', describeBackupCreated(result));
       loadHybridBackups(); // Reload hybrid backup list
     } catch (error) {
       showMessage('error', `Failed to create hybrid backup: ${error}`);
@@ -119,8 +121,8 @@ const DatabaseManagementPage: React.FC = () => {
 
     setIsLoading(true);
     try {
-      const result = await invoke<string>('import_hybrid_backup', { zipPath: backupPath });
-      showMessage('success', result);
+      const result = await invoke<BackupRestored>('import_hybrid_backup', { zipPath: backupPath });
+      showMessage('success', describeBackupRestored(result));
       // Reload data after import
       loadBackups();
       loadHybridBackups();
@@ -140,8 +142,8 @@ const DatabaseManagementPage: React.FC = () => {
 
     setIsLoading(true);
     try {
-      const result = await invoke<string>('delete_hybrid_backup', { filename });
-      showMessage('success', result);
+      const result = await invoke<BackupDeleted>('delete_hybrid_backup', { filename });
+      showMessage('success', describeBackupDeleted(result));
       loadHybridBackups(); // Reload list after delete
     } catch (error) {
       showMessage('error', `Failed to delete hybrid backup: ${error}`);
@@ -165,8 +167,8 @@ const DatabaseManagementPage: React.FC = () => {
     showMessage('info', `🔄 Restoring database from ${filename}... Please wait.`);
     
     try {
-      const result = await invoke<string>('restore_database_backup', { backupFilename: filename });
-      showMessage('success', `✅ ${result}`);
+      const result = await invoke<BackupRestored>('restore_database_backup', { backupFilename: filename });
+      showMessage('success', `✅ ${describeBackupRestored(result)}`);
       
       // Show countdown notification
       let countdown = 3;
@@ -204,8 +206,8 @@ const DatabaseManagementPage: React.FC = () => {
     showMessage('info', `🗑️ Deleting backup ${filename}...`);
     
     try {
-      const result = await invoke<string>('delete_database_backup', { backupFilename: filename });
-      showMessage('success', `✅ ${result}`);
+      const result = await invoke<BackupDeleted>('delete_database_backup', { backupFilename: filename });
+      showMessage('success', `✅ ${describeBackupDeleted(result)}`);
       loadBackups();
     } catch (error) {
       showMessage('error', `❌ Failed to delete backup: ${error}`);
@@ -272,7 +274,7 @@ const DatabaseManagementPage: React.FC = () => {
     setIsLoading(true);
     try {
       // Create hybrid backup first
-      await invoke<string>('create_hybrid_backup');
+      await invoke<BackupCreated>('create_hybrid_backup');
       
       // Get the latest backup file
       const hybridBackupListJson = await invoke<string>('discover_hybrid_backups');
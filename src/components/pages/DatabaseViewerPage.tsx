@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 import { Container, Card, Button, Title } from '../ui'
 import { RefreshCw, Database, Users, Image, Eye, EyeOff, X, CheckCircle, XCircle } from 'lucide-react'
 
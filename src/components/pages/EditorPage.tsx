@@ -6,7 +6,7 @@ import CreatePqsForm from '../forms/CreatePqsForm'
 import SearchPqsForm from '../forms/SearchPqsForm'
 import Button from '../ui/Button'
 import { FilePlus, Search, LayoutDashboard, Clock } from 'lucide-react'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 
 interface DocumentStats {
   total_count: number;
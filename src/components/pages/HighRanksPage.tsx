@@ -3,7 +3,7 @@ import { Container, Title, Card, Button, Alert } from '../ui';
 import Avatar from '../ui/Avatar';
 import EditOfficerModal from '../ui/EditOfficerModal';
 import { validateAvatarFile, fileToDataUrl, maybeDownscaleImage } from '../../services/avatarService';
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from '../../services/sessionInvoke';
 import navyLogo from '../../assets/images/navy_logo.webp';
 
 interface HighRankingOfficer {
@@ -4,6 +4,7 @@ import { LogIn, Mail, Lock, AlertCircle, CheckCircle } from 'lucide-react'
 import Container from '../ui/Container'
 import { FormInput, FormGroup, FormActions, Button } from '../ui'
 import { useAuth } from '../../hooks/useAuth'
+import { tauriUserService } from '../../services/tauriService'
 import navyLogo from '../../assets/images/navy_logo.webp'
 
 const SignInPage: React.FC = () => {
@@ -20,6 +21,10 @@ const SignInPage: React.FC = () => {
   const [isLoading, setIsLoading] = useState(false)
   const [errors, setErrors] = useState<{ [key: string]: string }>({})
   const [isSuccess, setIsSuccess] = useState(false)
+  // Set after signing in with a temporary password
+  const [mustChangePassword, setMustChangePassword] = useState(false)
+  const [newPassword, setNewPassword] = useState('')
+  const [confirmPassword, setConfirmPassword] = useState('')
 
   // Reset form state when component mounts or user signs out
   useEffect(() => {
@@ -105,7 +110,9 @@ const SignInPage: React.FC = () => {
         password: formData.password
       })
 
-      if (result.success) {
+      if (result.mustChangePassword) {
+        setMustChangePassword(true)
+      } else if (result.success) {
         setIsSuccess(true)
 
         // Redirect based on user role
@@ -130,6 +137,93 @@ const SignInPage: React.FC = () => {
     }
   }
 
+  const handleChangePassword = async (e: React.FormEvent) => {
+    e.preventDefault()
+
+    if (newPassword !== confirmPassword) {
+      setErrors({ confirmPassword: 'รหัสผ่านใหม่ไม่ตรงกัน' })
+      return
+    }
+
+    setIsLoading(true)
+    setErrors({})
+
+    try {
+      await tauriUserService.changePassword(formData.password, newPassword)
+      const result = await signIn({
+        username_or_email: formData.usernameOrEmail,
+        password: newPassword
+      })
+      if (result.success) {
+        setMustChangePassword(false)
+        setIsSuccess(true)
+        setTimeout(() => {
+          navigate('/home', { replace: true })
+        }, 1000)
+      }
+    } catch (error) {
+      // The backend lists every password policy problem at once
+      setErrors({ newPassword: String(error) })
+    } finally {
+      setIsLoading(false)
+    }
+  }
+
+  if (mustChangePassword) {
+    return (
+      <Container size="medium" padding="large" className="py-12 sm:py-20">
+        <div className="max-w-md mx-auto">
+          <div className="text-center mb-8">
+            <h1 className="text-2xl font-bold text-github-text-primary mb-2">
+              เปลี่ยนรหัสผ่าน
+            </h1>
+            <p className="text-github-text-secondary">
+              รหัสผ่านของคุณเป็นรหัสผ่านชั่วคราว กรุณาตั้งรหัสผ่านใหม่ก่อนใช้งาน
+            </p>
+          </div>
+
+          <form onSubmit={handleChangePassword}>
+            <FormGroup>
+              <FormInput
+                name="newPassword"
+                value={newPassword}
+                onChange={(e) => setNewPassword(e.target.value)}
+                label="รหัสผ่านใหม่"
+                placeholder="กรอกรหัสผ่านใหม่"
+                type="password"
+                icon={Lock}
+                disabled={isLoading}
+                error={errors.newPassword}
+              />
+              <FormInput
+                name="confirmPassword"
+                value={confirmPassword}
+                onChange={(e) => setConfirmPassword(e.target.value)}
+                label="ยืนยันรหัสผ่านใหม่"
+                placeholder="กรอกรหัสผ่านใหม่อีกครั้ง"
+                type="password"
+                icon={Lock}
+                disabled={isLoading}
+                error={errors.confirmPassword}
+              />
+            </FormGroup>
+
+            <FormActions>
+              <Button
+                type="submit"
+                variant="primary"
+                size="medium"
+                loading={isLoading}
+                className="w-full"
+              >
+                {isLoading ? 'กำลังบันทึก...' : 'บันทึกรหัสผ่านใหม่'}
+              </Button>
+            </FormActions>
+          </form>
+        </div>
+      </Container>
+    )
+  }
 
   return (
     <Container size="medium" padding="large" className="py-12 sm:py-20">
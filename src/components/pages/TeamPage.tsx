@@ -5,7 +5,7 @@ import kittisak from '../../assets/images/kittisak.webp';
 import React, { useState, useEffect } from 'react'
 import { Star } from 'lucide-react'
 import { Container, Card, Header, Title } from '../ui'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../../services/sessionInvoke'
 
 
 interface HighRankingOfficer {
@@ -17,14 +17,20 @@ export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
     setIsLoading(true)
     
     try {
-      // Just check for existing session in localStorage
+      // Restore the saved user only while its session is still valid
       const savedUser = localStorage.getItem('pqs_user')
       const savedToken = localStorage.getItem('pqs_token')
       
       if (savedUser && savedToken) {
         try {
           const user = JSON.parse(savedUser)
-          setUser(user)
+          const sessionUser = await tauriUserService.validateSession(savedToken)
+          // A temporary password has to be changed on the sign-in page first
+          if (sessionUser && !sessionUser.must_change_password) {
+            setUser(user)
+          } else {
+            clearAuthData()
+          }
         } catch (error) {
           console.warn('Failed to restore user session:', error)
           clearAuthData()
@@ -56,14 +62,21 @@ export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
     setUser(null)
   }
 
-  const signIn = async (credentials: { username_or_email: string; password: string }): Promise<{ success: boolean; user?: User; token?: string }> => {
+  const signIn = async (credentials: { username_or_email: string; password: string }): Promise<{ success: boolean; user?: User; token?: string; mustChangePassword?: boolean }> => {
     setIsLoading(true)
     
     try {
       // Use Tauri authentication service
-      const tauriUser = await tauriUserService.authenticateUser(credentials.username_or_email, credentials.password)
+      const session = await tauriUserService.login(credentials.username_or_email, credentials.password)
+      const tauriUser = session?.user
       
-      if (tauriUser) {
+      if (session && session.must_change_password) {
+        // Every other command is refused until the password is changed
+        localStorage.setItem('pqs_token', session.token)
+        return { success: false, token: session.token, mustChangePassword: true }
+      }
+
+      if (session && tauriUser) {
         // Convert Tauri user to context user format
         const contextUser: User = {
           id: tauriUser.id?.toString() || '1',
@@ -100,10 +113,10 @@ export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
         
         // Save to localStorage
         localStorage.setItem('pqs_user', JSON.stringify(contextUser))
-        localStorage.setItem('pqs_token', 'tauri_token_' + Date.now())
+        localStorage.setItem('pqs_token', session.token)
         
         setUser(contextUser)
-        return { success: true, user: contextUser, token: 'tauri_token_' + Date.now() }
+        return { success: true, user: contextUser, token: session.token }
       }
       
       return { success: false }
@@ -119,6 +132,10 @@ export const AuthProvider: React.FC<AuthProviderProps> = ({ children }) => {
 
 
   const signOut = () => {
+    const token = localStorage.getItem('pqs_token')
+    if (token) {
+      tauriUserService.logout(token).catch(error => console.warn('Failed to end session:', error))
+    }
     clearAuthData()
     setIsLoading(false) // Reset loading state
     // Navigate to home page after sign out
@@ -33,7 +33,7 @@ export const InitializationProvider: React.FC<InitializationProviderProps> = ({
       try {
         logger.info('🔄 Checking system state for initialization...');
         // ALWAYS check system state first (ignore localStorage until database is verified)
-        const { invoke } = await import('@tauri-apps/api/tauri');
+        const { invoke } = await import('../services/sessionInvoke');
         const result = await invoke<string>('check_system_state_for_initialization');
         const state = JSON.parse(result);
         setSystemState(state);
@@ -83,7 +83,7 @@ export const InitializationProvider: React.FC<InitializationProviderProps> = ({
 
   const initializeDatabaseIfNeeded = useCallback(async () => {
     try {
-      const { invoke } = await import('@tauri-apps/api/tauri');
+      const { invoke } = await import('../services/sessionInvoke');
       await invoke<string>('initialize_database_if_needed');
     } catch (error) {
       console.error('Failed to initialize database:', error);
@@ -23,7 +23,7 @@ export interface AuthContextType {
   user: User | null
   isAuthenticated: boolean
   isLoading: boolean
-  signIn: (credentials: { username_or_email: string; password: string }) => Promise<{ success: boolean; user?: User; token?: string }>
+  signIn: (credentials: { username_or_email: string; password: string }) => Promise<{ success: boolean; user?: User; token?: string; mustChangePassword?: boolean }>
   signOut: () => void
   checkAuthStatus: () => void
   updateAvatar: (avatar: string | null) => Promise<void> | void
@@ -1,5 +1,5 @@
 import { useEffect } from 'react'
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from '../services/sessionInvoke'
 
 export const useZoomShortcuts = () => {
   useEffect(() => {
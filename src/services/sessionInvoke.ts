@@ -0,0 +1,8 @@
+import { invoke as tauriInvoke, InvokeArgs } from '@tauri-apps/api/tauri';
+
+// The backend checks every command against the session of the caller, so the
+// token from login goes along with each call
+export const invoke = <T>(cmd: string, args?: InvokeArgs): Promise<T> => {
+  const sessionToken = localStorage.getItem('pqs_token');
+  return tauriInvoke<T>(cmd, sessionToken ? { ...args, sessionToken } : args);
+};
@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri';
+import { invoke } from './sessionInvoke';
 
 // Desktop App only — invoke() directly, no web fallback
 export const safeInvoke = async (command: string, args?: any) => {
@@ -26,6 +26,22 @@ export interface TauriUser {
   avatar_size?: number;
   created_at?: string;
   updated_at?: string;
+  must_change_password?: boolean;
+}
+
+export interface TauriSessionLogin {
+  user: TauriUser;
+  token: string;
+  expires_at: string;
+  // Only change_password is allowed until the temporary password is replaced
+  must_change_password: boolean;
+}
+
+export interface TauriUsersPage {
+  users: TauriUser[];
+  offset: number;
+  limit: number;
+  total: number;
 }
 
 export interface TauriAvatar {
@@ -50,6 +66,31 @@ export const tauriUserService = {
     }
   },
 
+  // Get one page of users, sorted by sortBy
+  async getUsersPage(
+    offset: number,
+    limit: number,
+    sortBy = 'username',
+    sortDir: 'asc' | 'desc' = 'asc'
+  ): Promise<TauriUsersPage> {
+    try {
+      return await safeInvoke('get_users_page', { offset, limit, sortBy, sortDir }) as TauriUsersPage;
+    } catch (error) {
+      console.error('Error getting users page:', error);
+      throw error;
+    }
+  },
+
+  // Count all users
+  async countUsers(): Promise<number> {
+    try {
+      return await safeInvoke('count_users') as number;
+    } catch (error) {
+      console.error('Error counting users:', error);
+      throw error;
+    }
+  },
+
   // Get user by ID
   async getUserById(id: number): Promise<TauriUser | null> {
     try {
@@ -151,6 +192,46 @@ export const tauriUserService = {
     }
   },
 
+  // Authenticate and start a session; the token is checked with validateSession
+  async login(username_or_email: string, password: string): Promise<TauriSessionLogin | null> {
+    try {
+      return await safeInvoke('login', { usernameOrEmail: username_or_email, password }) as TauriSessionLogin | null;
+    } catch (error) {
+      console.error('Error logging in:', error);
+      throw error;
+    }
+  },
+
+  // User of a live session, null once it expired or ended
+  async validateSession(token: string): Promise<TauriUser | null> {
+    try {
+      return await safeInvoke('validate_session', { token }) as TauriUser | null;
+    } catch (error) {
+      console.error('Error validating session:', error);
+      throw error;
+    }
+  },
+
+  // Replace the signed-in user's password; clears must_change_password
+  async changePassword(currentPassword: string, newPassword: string): Promise<void> {
+    try {
+      await safeInvoke('change_password', { currentPassword, newPassword });
+    } catch (error) {
+      console.error('Error changing password:', error);
+      throw error;
+    }
+  },
+
+  // End a session
+  async logout(token: string): Promise<boolean> {
+    try {
+      return await safeInvoke('logout', { token }) as boolean;
+    } catch (error) {
+      console.error('Error logging out:', error);
+      throw error;
+    }
+  },
+
   // Hash password
   async hashPassword(password: string): Promise<string> {
     try {
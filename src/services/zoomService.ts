@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/tauri'
+import { invoke } from './sessionInvoke'
 
 export interface ZoomService {
   zoomIn: () => Promise<void>
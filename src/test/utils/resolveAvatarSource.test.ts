@@ -1,5 +1,5 @@
 import { describe, expect, it } from "vitest";
-import { resolveAvatarSource } from "../../utils/resolveAvatarSource";
+import { avatarProtocolUrl, resolveAvatarSource } from "../../utils/resolveAvatarSource";
 
 describe("resolveAvatarSource", () => {
   it("returns null when raw is missing", () => {
@@ -34,3 +34,11 @@ describe("resolveAvatarSource", () => {
     expect(resolveAvatarSource({ raw: "/avatars/1.png" })).toBe("/avatars/1.png");
   });
 });
+
+describe("avatarProtocolUrl", () => {
+  it("encodes each path segment for the avatar protocol", () => {
+    const url = avatarProtocolUrl("avatars\\ก b.png");
+    expect(url.endsWith("/avatars/%E0%B8%81%20b.png")).toBe(true);
+    expect(url.startsWith("avatar://localhost/") || url.startsWith("https://avatar.localhost/")).toBe(true);
+  });
+});
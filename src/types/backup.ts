@@ -0,0 +1,58 @@
+// Payloads of the backup / restore / delete commands (see backup_results.rs)
+export type BackupKind = 'json' | 'sqlite' | 'sql_dump' | 'hybrid'
+
+export interface BackupCreated {
+  kind: BackupKind
+  filename: string
+  path: string
+  size_bytes: number
+  database_bytes?: number | null
+  media_bytes?: number | null
+  file_count?: number | null
+  user_count?: number | null
+  warnings: string[]
+  // Volume filenames of a split hybrid backup; empty when not split
+  volumes: string[]
+}
+
+export interface BackupRestored {
+  kind: BackupKind
+  source: string
+  user_count: number
+  officer_count: number
+  media_files?: number | null
+  previous_database?: string | null
+  requires_reload: boolean
+  warnings: string[]
+}
+
+export interface BackupDeleted {
+  filename: string
+  freed_bytes: number
+}
+
+const formatBytes = (bytes: number) =>
+  bytes >= 1024 * 1024 ? `${(bytes / 1024 / 1024).toFixed(2)} MB` : `${(bytes / 1024).toFixed(1)} KB`
+
+const withWarnings = (text: string, warnings: string[]) =>
+  warnings.length > 0 ? `${text}\n⚠️ ${warnings.join('\n⚠️ ')}` : text
+
+export const describeBackupCreated = (result: BackupCreated) =>
+  withWarnings(
+    `Backup created: ${result.filename} (${formatBytes(result.size_bytes)}` +
+      (result.file_count != null ? `, ${result.file_count} files` : '') +
+      (result.volumes.length > 1 ? `, ${result.volumes.length} volumes` : '') +
+      ')',
+    result.warnings
+  )
+
+export const describeBackupRestored = (result: BackupRestored) =>
+  withWarnings(
+    `Restored ${result.user_count} users and ${result.officer_count} officers` +
+      (result.media_files != null ? ` with ${result.media_files} media files` : '') +
+      ` from ${result.source}`,
+    result.warnings
+  )
+
+export const describeBackupDeleted = (result: BackupDeleted) =>
+  `Backup deleted: ${result.filename} (${formatBytes(result.freed_bytes)} freed)`
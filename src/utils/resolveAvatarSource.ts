@@ -22,3 +22,17 @@ export const resolveAvatarSource = (options: AvatarSourceOptions): string | null
   
   return raw
 }
+
+// URL of a media-relative avatar path on the avatar:// protocol; the webview
+// caches it and revalidates with ETag/Last-Modified
+export const avatarProtocolUrl = (relativePath: string): string => {
+  const encoded = relativePath
+    .replace(/\\/g, '/')
+    .split('/')
+    .map(encodeURIComponent)
+    .join('/')
+  // WebView2 only loads custom schemes through https://<scheme>.localhost
+  return navigator.userAgent.includes('Windows')
+    ? `https://avatar.localhost/${encoded}`
+    : `avatar://localhost/${encoded}`
+}